@@ -0,0 +1,64 @@
+#ifndef SYSMON_CLI_H
+#define SYSMON_CLI_H
+
+/* Generated by cbindgen from src/sysmon_json_ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Converts a Sysmon XML document to JSON.
+ *
+ * # Safety
+ *
+ * `xml` must be NULL or point to a NUL-terminated string.
+ */
+char *sysmon_xml_to_json(const char *xml);
+
+/**
+ * Converts JSON produced by [`sysmon_xml_to_json`] back to Sysmon XML.
+ *
+ * # Safety
+ *
+ * `json` must be NULL or point to a NUL-terminated string.
+ */
+char *sysmon_json_to_xml(const char *json);
+
+/**
+ * Validates a Sysmon XML document and returns a JSON array of
+ * `{"location", "message"}` issues; an empty array means no problems.
+ *
+ * # Safety
+ *
+ * `xml` must be NULL or point to a NUL-terminated string.
+ */
+char *sysmon_validate(const char *xml);
+
+/**
+ * Releases a string returned by this library. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `s` must be NULL or a pointer returned by one of the functions above
+ * that has not already been freed.
+ */
+void sysmon_string_free(char *s);
+
+/**
+ * Returns the message for the last failure on this thread, or NULL. The
+ * pointer is owned by the library and valid until the next call on the
+ * same thread; do not free it.
+ */
+const char *sysmon_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SYSMON_CLI_H */
@@ -0,0 +1,299 @@
+//! `analyze --perf`: estimate the endpoint overhead of a configuration.
+//!
+//! Sysmon's cost comes from two places: evaluating conditions against every
+//! event of a type, and writing the events that get through. The score
+//! models both with a relative volume per event type (a busy host raises
+//! far more `ProcessAccess`, `RegistryEvent` and `ImageLoad` events than
+//! `ProcessCreate`):
+//!
+//! - every condition costs the event type's volume times the work of its
+//!   operator, so a `contains` is dearer than an `is`, and `contains any`
+//!   pays once per listed value;
+//! - an include filter costs its rules, since each one can let events
+//!   through;
+//! - an event type filtered only by `exclude` logs everything the
+//!   exclusions miss, which costs far more than any rule. `DnsQuery` is the
+//!   usual case.
+//!
+//! Sysmon matches literal text only, so there are no pattern conditions to
+//! account for. The numbers are relative: they rank rules within a config
+//! and compare versions of one config, not hosts.
+
+use crate::{io_guard, validate};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::{Condition, FieldCondition, Filter, OnMatch, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+/// Relative event volume on a typical workstation; event types not listed
+/// count as 1.
+const VOLUME: &[(&str, u32)] = &[
+    ("ProcessAccess", 10),
+    ("RegistryEvent", 10),
+    ("ImageLoad", 8),
+    ("FileCreate", 6),
+    ("NetworkConnect", 6),
+    ("DnsQuery", 6),
+    ("FileDelete", 4),
+    ("PipeEvent", 3),
+    ("ProcessCreate", 2),
+    ("ProcessTerminate", 2),
+    ("FileCreateTime", 2),
+    ("FileCreateStreamHash", 2),
+];
+
+/// Multiplier for event types logged wholesale, behind `exclude` filters.
+const LOG_ALL_FACTOR: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnalyzeFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    /// Configuration to analyze (XML or JSON)
+    pub config: PathBuf,
+
+    /// Estimate endpoint overhead and rank the most expensive rules (the only
+    /// analysis so far)
+    #[arg(long, required = true)]
+    pub perf: bool,
+
+    /// Number of rules to list
+    #[arg(long, default_value = "10")]
+    pub top: usize,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: AnalyzeFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Cost of one event filter.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EventCost {
+    /// `ProcessCreate/include`.
+    pub scope: String,
+    pub rules: usize,
+    pub score: u32,
+    /// True when the filter lets every event not excluded through.
+    pub logs_all: bool,
+}
+
+/// Cost of one rule: a field condition or a `<Rule>`.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RuleCost {
+    pub scope: String,
+    pub rule: String,
+    pub score: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PerfReport {
+    pub score: u32,
+    pub events: Vec<EventCost>,
+    /// Most expensive first.
+    pub rules: Vec<RuleCost>,
+}
+
+pub fn run(args: &AnalyzeArgs) -> Result<(), ConversionError> {
+    let config = SysmonConfig::from_xml_str(&validate::load(&args.config)?.0)?;
+    let mut report = perf(&config);
+    report.rules.truncate(args.top);
+
+    let text = match args.format {
+        AnalyzeFormat::Text => render(&report),
+        AnalyzeFormat::Json => serde_json::to_string_pretty(&report)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+pub fn perf(config: &SysmonConfig) -> PerfReport {
+    let mut events: Vec<EventCost> = Vec::new();
+    let mut rules = Vec::new();
+
+    for event in config.events() {
+        let volume = volume(&event.event);
+        let scope = format!("{}/{}", event.event, event.onmatch.as_str());
+        let mut score = 0;
+        for filter in &event.filters {
+            let (rule, cost) = match filter {
+                Filter::Field(field) => (describe(field), volume * work(field)),
+                Filter::Rule(rule) => (
+                    format!(
+                        "Rule {}: {}",
+                        rule.group_relation.as_str(),
+                        rule.fields
+                            .iter()
+                            .map(describe)
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    ),
+                    volume * rule.fields.iter().map(work).sum::<u32>(),
+                ),
+            };
+            score += cost;
+            rules.push(RuleCost {
+                scope: scope.clone(),
+                rule,
+                score: cost,
+            });
+        }
+
+        match events.iter_mut().find(|e| e.scope == scope) {
+            Some(existing) => {
+                existing.rules += event.filters.len();
+                existing.score += score;
+            }
+            None => events.push(EventCost {
+                scope,
+                rules: event.filters.len(),
+                score,
+                logs_all: false,
+            }),
+        }
+    }
+
+    // Only exclusions for an event type: everything else is logged.
+    for cost in &mut events {
+        let (event, onmatch) = cost.scope.split_once('/').unwrap_or_default();
+        let included = config
+            .events()
+            .any(|e| e.event == event && e.onmatch == OnMatch::Include);
+        if onmatch == OnMatch::Exclude.as_str() && !included {
+            cost.logs_all = true;
+            cost.score += volume(event) * LOG_ALL_FACTOR;
+        }
+    }
+
+    events.sort_by_key(|e| std::cmp::Reverse(e.score));
+    rules.sort_by_key(|r| std::cmp::Reverse(r.score));
+    PerfReport {
+        score: events.iter().map(|e| e.score).sum(),
+        events,
+        rules,
+    }
+}
+
+fn volume(event: &str) -> u32 {
+    VOLUME
+        .iter()
+        .find(|(name, _)| *name == event)
+        .map_or(1, |(_, volume)| *volume)
+}
+
+/// Relative work of testing one condition against one event.
+fn work(field: &FieldCondition) -> u32 {
+    let values = field.value.split(';').filter(|v| !v.is_empty()).count() as u32;
+    match field.condition {
+        Condition::Is | Condition::IsNot | Condition::LessThan | Condition::MoreThan => 1,
+        Condition::Image => 1,
+        Condition::IsAny => values.max(1),
+        Condition::BeginWith
+        | Condition::NotBeginWith
+        | Condition::EndWith
+        | Condition::NotEndWith => 2,
+        Condition::Contains | Condition::Excludes => 4,
+        Condition::ContainsAny
+        | Condition::ContainsAll
+        | Condition::ExcludesAny
+        | Condition::ExcludesAll => 4 * values.max(1),
+    }
+}
+
+fn describe(field: &FieldCondition) -> String {
+    format!(
+        "{} {} {:?}",
+        field.field,
+        field.condition.as_str(),
+        field.value
+    )
+}
+
+fn render(report: &PerfReport) -> String {
+    let mut out = format!(
+        "Estimated overhead score: {}\n\nBy event filter:\n",
+        report.score
+    );
+    for event in &report.events {
+        let _ = writeln!(
+            out,
+            "  {:>6}  {} ({} rule(s){})",
+            event.score,
+            event.scope,
+            event.rules,
+            if event.logs_all {
+                ", logs every event not excluded"
+            } else {
+                ""
+            }
+        );
+    }
+    if !report.rules.is_empty() {
+        out.push_str("\nMost expensive rules:\n");
+    }
+    for rule in &report.rules {
+        let _ = writeln!(out, "  {:>6}  {}: {}", rule.score, rule.scope, rule.rule);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_ranks_rules_by_volume_and_operator() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+            <RuleGroup groupRelation="or"><ProcessCreate onmatch="include">
+                <Image condition="is">C:\Windows\System32\cmd.exe</Image>
+            </ProcessCreate></RuleGroup>
+            <RuleGroup groupRelation="or"><ProcessAccess onmatch="include">
+                <CallTrace condition="contains any">UNKNOWN;dbghelp;dbgcore</CallTrace>
+                <TargetImage condition="end with">\lsass.exe</TargetImage>
+            </ProcessAccess></RuleGroup>
+            <RuleGroup groupRelation="or"><DnsQuery onmatch="exclude">
+                <QueryName condition="end with">.microsoft.com</QueryName>
+            </DnsQuery></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let report = perf(&config);
+        let rules: Vec<(u32, &str)> = report
+            .rules
+            .iter()
+            .map(|r| (r.score, r.scope.as_str()))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                (120, "ProcessAccess/include"),
+                (20, "ProcessAccess/include"),
+                (12, "DnsQuery/exclude"),
+                (2, "ProcessCreate/include"),
+            ]
+        );
+
+        assert_eq!(report.events[0].scope, "DnsQuery/exclude");
+        assert!(report.events[0].logs_all);
+        assert_eq!(report.events[0].score, 12 + 6 * LOG_ALL_FACTOR);
+        assert_eq!(report.score, 12 + 300 + 140 + 2);
+    }
+}
@@ -0,0 +1,279 @@
+//! Zip and tar.gz bundles as batch inputs and outputs.
+//!
+//! Archives are staged through a temporary directory: inputs are unpacked,
+//! converted like any other directory, and the results are packed into the
+//! requested output archive. The user never has to extract anything by hand.
+
+use crate::io_guard;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::TarGz => "tar.gz",
+        }
+    }
+}
+
+/// File name of `path` with its archive extension removed.
+pub fn archive_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("output");
+    let lower = name.to_ascii_lowercase();
+    for suffix in [".tar.gz", ".tgz", ".zip"] {
+        if lower.ends_with(suffix) {
+            return name[..name.len() - suffix.len()].to_string();
+        }
+    }
+    name.to_string()
+}
+
+/// Default output for a converted archive: `configs.zip` becomes
+/// `configs_converted.zip` next to it.
+pub fn default_output(input: &Path, kind: ArchiveKind) -> PathBuf {
+    input.with_file_name(format!(
+        "{}_converted.{}",
+        archive_stem(input),
+        kind.extension()
+    ))
+}
+
+/// Unpacks `archive` into `dest`, refusing entries that would escape it.
+pub fn extract(kind: ArchiveKind, archive: &Path, dest: &Path) -> Result<(), ConversionError> {
+    let file = File::open(archive).map_err(|e| ConversionError::io_error(archive, e))?;
+    io_guard::create_dir_all(dest)?;
+
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(file))
+                .map_err(|e| archive_error(archive, e))?;
+            for index in 0..zip.len() {
+                let mut entry = zip.by_index(index).map_err(|e| archive_error(archive, e))?;
+                let Some(relative) = entry.enclosed_name() else {
+                    return Err(ConversionError::InvalidFile(format!(
+                        "{}: entry escapes the archive root: {}",
+                        archive.display(),
+                        entry.name()
+                    )));
+                };
+                let target = dest.join(relative);
+                if entry.is_dir() {
+                    io_guard::create_dir_all(&target)?;
+                    continue;
+                }
+                if let Some(parent) = target.parent() {
+                    io_guard::create_dir_all(parent)?;
+                }
+                let mut out = io_guard::create(&target)?;
+                std::io::copy(&mut entry, &mut out)
+                    .map_err(|e| ConversionError::io_error(&target, e))?;
+            }
+        }
+        ArchiveKind::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+            tar.unpack(dest)
+                .map_err(|e| ConversionError::io_error(archive, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the file entries of `archive` without extracting anything.
+pub fn list(kind: ArchiveKind, archive: &Path) -> Result<Vec<String>, ConversionError> {
+    let file = File::open(archive).map_err(|e| ConversionError::io_error(archive, e))?;
+    let mut names = Vec::new();
+
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(file))
+                .map_err(|e| archive_error(archive, e))?;
+            for index in 0..zip.len() {
+                let entry = zip.by_index(index).map_err(|e| archive_error(archive, e))?;
+                if !entry.is_dir() {
+                    names.push(entry.name().to_string());
+                }
+            }
+        }
+        ArchiveKind::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+            let entries = tar
+                .entries()
+                .map_err(|e| ConversionError::io_error(archive, e))?;
+            for entry in entries {
+                let entry = entry.map_err(|e| ConversionError::io_error(archive, e))?;
+                if entry.header().entry_type().is_file() {
+                    let path = entry
+                        .path()
+                        .map_err(|e| ConversionError::io_error(archive, e))?;
+                    names.push(path.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+/// Packs every file under `source` into a new archive at `archive`, using
+/// paths relative to `source` in sorted order. Entries carry a fixed
+/// timestamp, owner and mode rather than the files' own, so packing the
+/// same files again gives a byte-identical archive.
+pub fn pack(kind: ArchiveKind, source: &Path, archive: &Path) -> Result<(), ConversionError> {
+    let files: Vec<PathBuf> = WalkDir::new(source)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+
+    if let Some(parent) = archive.parent().filter(|p| !p.as_os_str().is_empty()) {
+        io_guard::create_dir_all(parent)?;
+    }
+    let file = io_guard::create(archive)?;
+
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipWriter::new(BufWriter::new(file));
+            for path in &files {
+                let name = entry_name(source, path);
+                let options = SimpleFileOptions::default()
+                    .last_modified_time(zip::DateTime::default())
+                    .unix_permissions(0o644);
+                zip.start_file(name, options)
+                    .map_err(|e| archive_error(archive, e))?;
+                let content =
+                    std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+                zip.write_all(&content)
+                    .map_err(|e| ConversionError::io_error(archive, e))?;
+            }
+            zip.finish().map_err(|e| archive_error(archive, e))?;
+        }
+        ArchiveKind::TarGz => {
+            let mut tar =
+                tar::Builder::new(GzEncoder::new(BufWriter::new(file), Compression::default()));
+            tar.mode(tar::HeaderMode::Deterministic);
+            for path in &files {
+                tar.append_path_with_name(path, entry_name(source, path))
+                    .map_err(|e| ConversionError::io_error(path, e))?;
+            }
+            tar.into_inner()
+                .and_then(|gz| gz.finish())
+                .and_then(|mut out| out.flush())
+                .map_err(|e| ConversionError::io_error(archive, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn entry_name(source: &Path, path: &Path) -> String {
+    path.strip_prefix(source)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+fn archive_error(path: &Path, e: zip::result::ZipError) -> ConversionError {
+    ConversionError::InvalidFile(format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn round_trip(kind: ArchiveKind, name: &str) {
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("source");
+        fs::create_dir_all(source.join("1_process_creation")).unwrap();
+        fs::write(source.join("base.xml"), "<Sysmon/>").unwrap();
+        fs::write(
+            source.join("1_process_creation").join("include.xml"),
+            "<Sysmon><EventFiltering/></Sysmon>",
+        )
+        .unwrap();
+
+        let archive = temp_dir.path().join(name);
+        assert_eq!(ArchiveKind::from_path(&archive), Some(kind));
+        pack(kind, &source, &archive).unwrap();
+
+        let extracted = temp_dir.path().join("extracted");
+        extract(kind, &archive, &extracted).unwrap();
+        assert_eq!(
+            fs::read_to_string(extracted.join("1_process_creation").join("include.xml")).unwrap(),
+            "<Sysmon><EventFiltering/></Sysmon>"
+        );
+        assert!(extracted.join("base.xml").exists());
+    }
+
+    #[test]
+    fn test_zip_round_trip() {
+        round_trip(ArchiveKind::Zip, "bundle.zip");
+    }
+
+    #[test]
+    fn test_tar_gz_round_trip() {
+        round_trip(ArchiveKind::TarGz, "bundle.tar.gz");
+    }
+
+    #[test]
+    fn test_pack_ignores_file_metadata() {
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("source");
+        fs::create_dir_all(&source).unwrap();
+        let file = source.join("base.xml");
+        fs::write(&file, "<Sysmon/>").unwrap();
+
+        for kind in [ArchiveKind::Zip, ArchiveKind::TarGz] {
+            let first = temp_dir.path().join(format!("first.{}", kind.extension()));
+            pack(kind, &source, &first).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&file)
+                .and_then(|f| f.set_modified(std::time::UNIX_EPOCH))
+                .unwrap();
+            let second = temp_dir.path().join(format!("second.{}", kind.extension()));
+            pack(kind, &source, &second).unwrap();
+            assert_eq!(fs::read(first).unwrap(), fs::read(second).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_default_output_keeps_archive_kind() {
+        assert_eq!(
+            default_output(Path::new("dist/configs.tar.gz"), ArchiveKind::TarGz),
+            PathBuf::from("dist/configs_converted.tar.gz")
+        );
+        assert_eq!(ArchiveKind::from_path(Path::new("configs.xml")), None);
+    }
+}
@@ -0,0 +1,72 @@
+//! Async wrappers for services running on tokio.
+//!
+//! Enabled with the `async` feature. `sysmon_json` converts synchronously,
+//! so each call runs on tokio's blocking thread pool; callers get a future
+//! and never have to manage blocking threads themselves.
+
+use std::path::{Path, PathBuf};
+use sysmon_json::batch::BatchProcessingStats;
+use sysmon_json::{convert_file, error::ConversionError, BatchProcessor, ProcessingOptions};
+use tokio::task;
+
+/// Async counterpart of `sysmon_json::convert_file`.
+pub async fn convert_file_async(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<(), ConversionError> {
+    let input = input.as_ref().to_path_buf();
+    let output = output.as_ref().to_path_buf();
+    run_blocking(move || convert_file(input.as_path(), output.as_path())).await
+}
+
+/// Async counterpart of `BatchProcessor::process_directory`.
+pub async fn process_directory_async(
+    input_dir: impl AsRef<Path>,
+    output_dir: impl AsRef<Path>,
+    recursive: bool,
+    options: ProcessingOptions,
+) -> Result<BatchProcessingStats, ConversionError> {
+    let input_dir: PathBuf = input_dir.as_ref().to_path_buf();
+    let output_dir: PathBuf = output_dir.as_ref().to_path_buf();
+    run_blocking(move || {
+        BatchProcessor::new().process_directory(&input_dir, &output_dir, recursive, &options)
+    })
+    .await
+}
+
+async fn run_blocking<T, F>(work: F) -> Result<T, ConversionError>
+where
+    F: FnOnce() -> Result<T, ConversionError> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(work)
+        .await
+        .map_err(|e| ConversionError::InvalidFile(format!("Conversion task failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_convert_file_async() {
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("config.xml");
+        let output = temp_dir.path().join("config.json");
+        fs::write(
+            &input,
+            r#"<Sysmon schemaversion="4.90"><EventFiltering/></Sysmon>"#,
+        )
+        .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime
+            .block_on(convert_file_async(&input, &output))
+            .unwrap();
+        assert!(output.exists());
+    }
+}
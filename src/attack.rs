@@ -0,0 +1,1102 @@
+//! The MITRE ATT&CK (Enterprise) techniques rule names cite.
+//!
+//! Every technique of ATT&CK v15 is bundled by ID, name and tactics, with
+//! the sub-techniques Sysmon configs commonly cite. IDs that ATT&CK has
+//! since revoked or deprecated, many of them still found in older
+//! community configs, are listed with the technique that replaced them.
+//! Since the bundled sub-techniques are a selection, an unlisted one is
+//! only known not to exist once the full data has been fetched.
+//!
+//! `update-attack-data` refreshes this from MITRE's STIX feed into the
+//! cache directory, and [`Database::load`] prefers that copy, so `fix`,
+//! `explain` and `coverage-diff` keep up with new ATT&CK releases without
+//! a new release of this tool.
+
+use crate::{compare, io_guard};
+use clap::Args;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_json::error::ConversionError;
+
+/// Technique and sub-technique IDs with their names.
+pub const TECHNIQUES: &[(&str, &str)] = &[
+    ("T1001", "Data Obfuscation"),
+    ("T1003", "OS Credential Dumping"),
+    ("T1003.001", "LSASS Memory"),
+    ("T1003.002", "Security Account Manager"),
+    ("T1003.003", "NTDS"),
+    ("T1003.004", "LSA Secrets"),
+    ("T1003.005", "Cached Domain Credentials"),
+    ("T1003.006", "DCSync"),
+    ("T1003.008", "/etc/passwd and /etc/shadow"),
+    ("T1005", "Data from Local System"),
+    ("T1006", "Direct Volume Access"),
+    ("T1007", "System Service Discovery"),
+    ("T1008", "Fallback Channels"),
+    ("T1010", "Application Window Discovery"),
+    ("T1011", "Exfiltration Over Other Network Medium"),
+    ("T1012", "Query Registry"),
+    ("T1014", "Rootkit"),
+    ("T1016", "System Network Configuration Discovery"),
+    ("T1018", "Remote System Discovery"),
+    ("T1020", "Automated Exfiltration"),
+    ("T1021", "Remote Services"),
+    ("T1021.001", "Remote Desktop Protocol"),
+    ("T1021.002", "SMB/Windows Admin Shares"),
+    ("T1021.003", "Distributed Component Object Model"),
+    ("T1021.004", "SSH"),
+    ("T1021.005", "VNC"),
+    ("T1021.006", "Windows Remote Management"),
+    ("T1025", "Data from Removable Media"),
+    ("T1027", "Obfuscated Files or Information"),
+    ("T1027.001", "Binary Padding"),
+    ("T1027.002", "Software Packing"),
+    ("T1027.004", "Compile After Delivery"),
+    ("T1027.010", "Command Obfuscation"),
+    ("T1029", "Scheduled Transfer"),
+    ("T1030", "Data Transfer Size Limits"),
+    ("T1033", "System Owner/User Discovery"),
+    ("T1036", "Masquerading"),
+    ("T1036.003", "Rename System Utilities"),
+    ("T1036.004", "Masquerade Task or Service"),
+    ("T1036.005", "Match Legitimate Name or Location"),
+    ("T1037", "Boot or Logon Initialization Scripts"),
+    ("T1037.001", "Logon Script (Windows)"),
+    ("T1039", "Data from Network Shared Drive"),
+    ("T1040", "Network Sniffing"),
+    ("T1041", "Exfiltration Over C2 Channel"),
+    ("T1046", "Network Service Discovery"),
+    ("T1047", "Windows Management Instrumentation"),
+    ("T1048", "Exfiltration Over Alternative Protocol"),
+    ("T1049", "System Network Connections Discovery"),
+    ("T1052", "Exfiltration Over Physical Medium"),
+    ("T1053", "Scheduled Task/Job"),
+    ("T1053.002", "At"),
+    ("T1053.003", "Cron"),
+    ("T1053.005", "Scheduled Task"),
+    ("T1055", "Process Injection"),
+    ("T1055.001", "Dynamic-link Library Injection"),
+    ("T1055.002", "Portable Executable Injection"),
+    ("T1055.003", "Thread Execution Hijacking"),
+    ("T1055.004", "Asynchronous Procedure Call"),
+    ("T1055.005", "Thread Local Storage"),
+    ("T1055.012", "Process Hollowing"),
+    ("T1055.013", "Process Doppelgänging"),
+    ("T1056", "Input Capture"),
+    ("T1057", "Process Discovery"),
+    ("T1059", "Command and Scripting Interpreter"),
+    ("T1059.001", "PowerShell"),
+    ("T1059.003", "Windows Command Shell"),
+    ("T1059.004", "Unix Shell"),
+    ("T1059.005", "Visual Basic"),
+    ("T1059.006", "Python"),
+    ("T1059.007", "JavaScript"),
+    ("T1068", "Exploitation for Privilege Escalation"),
+    ("T1069", "Permission Groups Discovery"),
+    ("T1070", "Indicator Removal"),
+    ("T1070.001", "Clear Windows Event Logs"),
+    ("T1070.004", "File Deletion"),
+    ("T1070.006", "Timestomp"),
+    ("T1071", "Application Layer Protocol"),
+    ("T1071.001", "Web Protocols"),
+    ("T1071.004", "DNS"),
+    ("T1072", "Software Deployment Tools"),
+    ("T1074", "Data Staged"),
+    ("T1078", "Valid Accounts"),
+    ("T1078.003", "Local Accounts"),
+    ("T1080", "Taint Shared Content"),
+    ("T1082", "System Information Discovery"),
+    ("T1083", "File and Directory Discovery"),
+    ("T1087", "Account Discovery"),
+    ("T1090", "Proxy"),
+    ("T1090.003", "Multi-hop Proxy"),
+    ("T1091", "Replication Through Removable Media"),
+    ("T1092", "Communication Through Removable Media"),
+    ("T1095", "Non-Application Layer Protocol"),
+    ("T1098", "Account Manipulation"),
+    ("T1102", "Web Service"),
+    ("T1104", "Multi-Stage Channels"),
+    ("T1105", "Ingress Tool Transfer"),
+    ("T1106", "Native API"),
+    ("T1110", "Brute Force"),
+    ("T1111", "Multi-Factor Authentication Interception"),
+    ("T1112", "Modify Registry"),
+    ("T1113", "Screen Capture"),
+    ("T1114", "Email Collection"),
+    ("T1115", "Clipboard Data"),
+    ("T1119", "Automated Collection"),
+    ("T1120", "Peripheral Device Discovery"),
+    ("T1123", "Audio Capture"),
+    ("T1124", "System Time Discovery"),
+    ("T1125", "Video Capture"),
+    ("T1127", "Trusted Developer Utilities Proxy Execution"),
+    ("T1127.001", "MSBuild"),
+    ("T1129", "Shared Modules"),
+    ("T1132", "Data Encoding"),
+    ("T1133", "External Remote Services"),
+    ("T1134", "Access Token Manipulation"),
+    ("T1134.001", "Token Impersonation/Theft"),
+    ("T1134.002", "Create Process with Token"),
+    ("T1135", "Network Share Discovery"),
+    ("T1136", "Create Account"),
+    ("T1136.001", "Local Account"),
+    ("T1137", "Office Application Startup"),
+    ("T1137.001", "Office Template Macros"),
+    ("T1137.002", "Office Test"),
+    ("T1140", "Deobfuscate/Decode Files or Information"),
+    ("T1176", "Browser Extensions"),
+    ("T1185", "Browser Session Hijacking"),
+    ("T1187", "Forced Authentication"),
+    ("T1189", "Drive-by Compromise"),
+    ("T1190", "Exploit Public-Facing Application"),
+    ("T1195", "Supply Chain Compromise"),
+    ("T1197", "BITS Jobs"),
+    ("T1199", "Trusted Relationship"),
+    ("T1200", "Hardware Additions"),
+    ("T1201", "Password Policy Discovery"),
+    ("T1202", "Indirect Command Execution"),
+    ("T1203", "Exploitation for Client Execution"),
+    ("T1204", "User Execution"),
+    ("T1204.002", "Malicious File"),
+    ("T1205", "Traffic Signaling"),
+    ("T1207", "Rogue Domain Controller"),
+    ("T1210", "Exploitation of Remote Services"),
+    ("T1211", "Exploitation for Defense Evasion"),
+    ("T1212", "Exploitation for Credential Access"),
+    ("T1213", "Data from Information Repositories"),
+    ("T1216", "System Script Proxy Execution"),
+    ("T1216.001", "PubPrn"),
+    ("T1217", "Browser Information Discovery"),
+    ("T1218", "System Binary Proxy Execution"),
+    ("T1218.001", "Compiled HTML File"),
+    ("T1218.002", "Control Panel"),
+    ("T1218.003", "CMSTP"),
+    ("T1218.004", "InstallUtil"),
+    ("T1218.005", "Mshta"),
+    ("T1218.007", "Msiexec"),
+    ("T1218.008", "Odbcconf"),
+    ("T1218.009", "Regsvcs/Regasm"),
+    ("T1218.010", "Regsvr32"),
+    ("T1218.011", "Rundll32"),
+    ("T1218.012", "Verclsid"),
+    ("T1218.013", "Mavinject"),
+    ("T1218.014", "MMC"),
+    ("T1219", "Remote Access Software"),
+    ("T1220", "XSL Script Processing"),
+    ("T1221", "Template Injection"),
+    ("T1222", "File and Directory Permissions Modification"),
+    (
+        "T1222.001",
+        "Windows File and Directory Permissions Modification",
+    ),
+    ("T1480", "Execution Guardrails"),
+    ("T1482", "Domain Trust Discovery"),
+    ("T1484", "Domain or Tenant Policy Modification"),
+    ("T1485", "Data Destruction"),
+    ("T1486", "Data Encrypted for Impact"),
+    ("T1489", "Service Stop"),
+    ("T1490", "Inhibit System Recovery"),
+    ("T1491", "Defacement"),
+    ("T1495", "Firmware Corruption"),
+    ("T1496", "Resource Hijacking"),
+    ("T1497", "Virtualization/Sandbox Evasion"),
+    ("T1498", "Network Denial of Service"),
+    ("T1499", "Endpoint Denial of Service"),
+    ("T1505", "Server Software Component"),
+    ("T1505.003", "Web Shell"),
+    ("T1518", "Software Discovery"),
+    ("T1525", "Implant Internal Image"),
+    ("T1526", "Cloud Service Discovery"),
+    ("T1528", "Steal Application Access Token"),
+    ("T1529", "System Shutdown/Reboot"),
+    ("T1530", "Data from Cloud Storage"),
+    ("T1531", "Account Access Removal"),
+    ("T1534", "Internal Spearphishing"),
+    ("T1535", "Unused/Unsupported Cloud Regions"),
+    ("T1537", "Transfer Data to Cloud Account"),
+    ("T1538", "Cloud Service Dashboard"),
+    ("T1539", "Steal Web Session Cookie"),
+    ("T1542", "Pre-OS Boot"),
+    ("T1542.002", "Component Firmware"),
+    ("T1542.003", "Bootkit"),
+    ("T1543", "Create or Modify System Process"),
+    ("T1543.002", "Systemd Service"),
+    ("T1543.003", "Windows Service"),
+    ("T1546", "Event Triggered Execution"),
+    ("T1546.001", "Change Default File Association"),
+    ("T1546.002", "Screensaver"),
+    (
+        "T1546.003",
+        "Windows Management Instrumentation Event Subscription",
+    ),
+    ("T1546.007", "Netsh Helper DLL"),
+    ("T1546.008", "Accessibility Features"),
+    ("T1546.009", "AppCert DLLs"),
+    ("T1546.010", "AppInit DLLs"),
+    ("T1546.011", "Application Shimming"),
+    ("T1546.012", "Image File Execution Options Injection"),
+    ("T1546.013", "PowerShell Profile"),
+    ("T1546.015", "Component Object Model Hijacking"),
+    ("T1547", "Boot or Logon Autostart Execution"),
+    ("T1547.001", "Registry Run Keys / Startup Folder"),
+    ("T1547.002", "Authentication Package"),
+    ("T1547.004", "Winlogon Helper DLL"),
+    ("T1547.005", "Security Support Provider"),
+    ("T1547.009", "Shortcut Modification"),
+    ("T1547.010", "Port Monitors"),
+    ("T1547.012", "Print Processors"),
+    ("T1548", "Abuse Elevation Control Mechanism"),
+    ("T1548.002", "Bypass User Account Control"),
+    ("T1550", "Use Alternate Authentication Material"),
+    ("T1550.002", "Pass the Hash"),
+    ("T1550.003", "Pass the Ticket"),
+    ("T1552", "Unsecured Credentials"),
+    ("T1552.001", "Credentials In Files"),
+    ("T1552.002", "Credentials in Registry"),
+    ("T1553", "Subvert Trust Controls"),
+    ("T1553.004", "Install Root Certificate"),
+    ("T1553.005", "Mark-of-the-Web Bypass"),
+    ("T1554", "Compromise Host Software Binary"),
+    ("T1555", "Credentials from Password Stores"),
+    ("T1555.003", "Credentials from Web Browsers"),
+    ("T1555.004", "Windows Credential Manager"),
+    ("T1556", "Modify Authentication Process"),
+    ("T1557", "Adversary-in-the-Middle"),
+    ("T1558", "Steal or Forge Kerberos Tickets"),
+    ("T1558.003", "Kerberoasting"),
+    ("T1559", "Inter-Process Communication"),
+    ("T1559.001", "Component Object Model"),
+    ("T1559.002", "Dynamic Data Exchange"),
+    ("T1560", "Archive Collected Data"),
+    ("T1560.001", "Archive via Utility"),
+    ("T1561", "Disk Wipe"),
+    ("T1562", "Impair Defenses"),
+    ("T1562.001", "Disable or Modify Tools"),
+    ("T1562.002", "Disable Windows Event Logging"),
+    ("T1562.004", "Disable or Modify System Firewall"),
+    ("T1562.006", "Indicator Blocking"),
+    ("T1563", "Remote Service Session Hijacking"),
+    ("T1564", "Hide Artifacts"),
+    ("T1564.001", "Hidden Files and Directories"),
+    ("T1564.004", "NTFS File Attributes"),
+    ("T1565", "Data Manipulation"),
+    ("T1566", "Phishing"),
+    ("T1566.001", "Spearphishing Attachment"),
+    ("T1566.002", "Spearphishing Link"),
+    ("T1567", "Exfiltration Over Web Service"),
+    ("T1567.002", "Exfiltration to Cloud Storage"),
+    ("T1568", "Dynamic Resolution"),
+    ("T1569", "System Services"),
+    ("T1569.002", "Service Execution"),
+    ("T1570", "Lateral Tool Transfer"),
+    ("T1571", "Non-Standard Port"),
+    ("T1572", "Protocol Tunneling"),
+    ("T1573", "Encrypted Channel"),
+    ("T1574", "Hijack Execution Flow"),
+    ("T1574.001", "DLL Search Order Hijacking"),
+    ("T1574.002", "DLL Side-Loading"),
+    ("T1574.010", "Services File Permissions Weakness"),
+    ("T1574.011", "Services Registry Permissions Weakness"),
+    ("T1574.012", "COR_PROFILER"),
+    ("T1578", "Modify Cloud Compute Infrastructure"),
+    ("T1580", "Cloud Infrastructure Discovery"),
+    ("T1583", "Acquire Infrastructure"),
+    ("T1584", "Compromise Infrastructure"),
+    ("T1585", "Establish Accounts"),
+    ("T1586", "Compromise Accounts"),
+    ("T1587", "Develop Capabilities"),
+    ("T1588", "Obtain Capabilities"),
+    ("T1589", "Gather Victim Identity Information"),
+    ("T1590", "Gather Victim Network Information"),
+    ("T1591", "Gather Victim Org Information"),
+    ("T1592", "Gather Victim Host Information"),
+    ("T1593", "Search Open Websites/Domains"),
+    ("T1594", "Search Victim-Owned Websites"),
+    ("T1595", "Active Scanning"),
+    ("T1596", "Search Open Technical Databases"),
+    ("T1597", "Search Closed Sources"),
+    ("T1598", "Phishing for Information"),
+    ("T1599", "Network Boundary Bridging"),
+    ("T1600", "Weaken Encryption"),
+    ("T1601", "Modify System Image"),
+    ("T1602", "Data from Configuration Repository"),
+    ("T1606", "Forge Web Credentials"),
+    ("T1608", "Stage Capabilities"),
+    ("T1609", "Container Administration Command"),
+    ("T1610", "Deploy Container"),
+    ("T1611", "Escape to Host"),
+    ("T1612", "Build Image on Host"),
+    ("T1613", "Container and Resource Discovery"),
+    ("T1614", "System Location Discovery"),
+    ("T1615", "Group Policy Discovery"),
+    ("T1619", "Cloud Storage Object Discovery"),
+    ("T1620", "Reflective Code Loading"),
+    ("T1621", "Multi-Factor Authentication Request Generation"),
+    ("T1622", "Debugger Evasion"),
+    ("T1647", "Plist File Modification"),
+    ("T1648", "Serverless Execution"),
+    ("T1649", "Steal or Forge Authentication Certificates"),
+    ("T1650", "Acquire Access"),
+    ("T1651", "Cloud Administration Command"),
+    ("T1652", "Device Driver Discovery"),
+    ("T1653", "Power Settings"),
+    ("T1654", "Log Enumeration"),
+    ("T1656", "Impersonation"),
+    ("T1657", "Financial Theft"),
+    ("T1659", "Content Injection"),
+    ("T1665", "Hide Infrastructure"),
+];
+
+/// Revoked or deprecated IDs, with the technique that replaced them.
+pub const REVOKED: &[(&str, &str)] = &[
+    ("T1004", "T1547.004"),
+    ("T1009", "T1027.001"),
+    ("T1013", "T1547.010"),
+    ("T1015", "T1546.008"),
+    ("T1023", "T1547.009"),
+    ("T1031", "T1543.003"),
+    ("T1035", "T1569.002"),
+    ("T1038", "T1574.001"),
+    ("T1042", "T1546.001"),
+    ("T1044", "T1574.010"),
+    ("T1050", "T1543.003"),
+    ("T1058", "T1574.011"),
+    ("T1060", "T1547.001"),
+    ("T1064", "T1059"),
+    ("T1067", "T1542.003"),
+    ("T1073", "T1574.002"),
+    ("T1076", "T1021.001"),
+    ("T1077", "T1021.002"),
+    ("T1084", "T1546.003"),
+    ("T1085", "T1218.011"),
+    ("T1086", "T1059.001"),
+    ("T1088", "T1548.002"),
+    ("T1089", "T1562.001"),
+    ("T1093", "T1055.012"),
+    ("T1100", "T1505.003"),
+    ("T1101", "T1547.005"),
+    ("T1103", "T1546.010"),
+    ("T1107", "T1070.004"),
+    ("T1109", "T1542.002"),
+    ("T1117", "T1218.010"),
+    ("T1118", "T1218.004"),
+    ("T1121", "T1218.009"),
+    ("T1122", "T1546.015"),
+    ("T1128", "T1546.007"),
+    ("T1130", "T1553.004"),
+    ("T1131", "T1547.002"),
+    ("T1138", "T1546.011"),
+    ("T1170", "T1218.005"),
+    ("T1175", "T1559.001"),
+    ("T1180", "T1546.002"),
+    ("T1183", "T1546.012"),
+    ("T1191", "T1218.003"),
+    ("T1196", "T1218.002"),
+];
+
+/// ATT&CK tactics in kill-chain order, by the short name STIX uses.
+pub const TACTIC_NAMES: &[(&str, &str)] = &[
+    ("reconnaissance", "Reconnaissance"),
+    ("resource-development", "Resource Development"),
+    ("initial-access", "Initial Access"),
+    ("execution", "Execution"),
+    ("persistence", "Persistence"),
+    ("privilege-escalation", "Privilege Escalation"),
+    ("defense-evasion", "Defense Evasion"),
+    ("credential-access", "Credential Access"),
+    ("discovery", "Discovery"),
+    ("lateral-movement", "Lateral Movement"),
+    ("collection", "Collection"),
+    ("command-and-control", "Command and Control"),
+    ("exfiltration", "Exfiltration"),
+    ("impact", "Impact"),
+];
+
+/// The tactics of each technique in [`TECHNIQUES`]; sub-techniques have
+/// their parent's.
+const TACTICS: &[(&str, &[&str])] = &[
+    ("T1001", &["command-and-control"]),
+    ("T1003", &["credential-access"]),
+    ("T1005", &["collection"]),
+    ("T1006", &["defense-evasion"]),
+    ("T1007", &["discovery"]),
+    ("T1008", &["command-and-control"]),
+    ("T1010", &["discovery"]),
+    ("T1011", &["exfiltration"]),
+    ("T1012", &["discovery"]),
+    ("T1014", &["defense-evasion"]),
+    ("T1016", &["discovery"]),
+    ("T1018", &["discovery"]),
+    ("T1020", &["exfiltration"]),
+    ("T1021", &["lateral-movement"]),
+    ("T1025", &["collection"]),
+    ("T1027", &["defense-evasion"]),
+    ("T1029", &["exfiltration"]),
+    ("T1030", &["exfiltration"]),
+    ("T1033", &["discovery"]),
+    ("T1036", &["defense-evasion"]),
+    ("T1037", &["persistence", "privilege-escalation"]),
+    ("T1039", &["collection"]),
+    ("T1040", &["credential-access", "discovery"]),
+    ("T1041", &["exfiltration"]),
+    ("T1046", &["discovery"]),
+    ("T1047", &["execution"]),
+    ("T1048", &["exfiltration"]),
+    ("T1049", &["discovery"]),
+    ("T1052", &["exfiltration"]),
+    (
+        "T1053",
+        &["execution", "persistence", "privilege-escalation"],
+    ),
+    ("T1055", &["privilege-escalation", "defense-evasion"]),
+    ("T1056", &["credential-access", "collection"]),
+    ("T1057", &["discovery"]),
+    ("T1059", &["execution"]),
+    ("T1068", &["privilege-escalation"]),
+    ("T1069", &["discovery"]),
+    ("T1070", &["defense-evasion"]),
+    ("T1071", &["command-and-control"]),
+    ("T1072", &["execution", "lateral-movement"]),
+    ("T1074", &["collection"]),
+    (
+        "T1078",
+        &[
+            "initial-access",
+            "persistence",
+            "privilege-escalation",
+            "defense-evasion",
+        ],
+    ),
+    ("T1080", &["lateral-movement"]),
+    ("T1082", &["discovery"]),
+    ("T1083", &["discovery"]),
+    ("T1087", &["discovery"]),
+    ("T1090", &["command-and-control"]),
+    ("T1091", &["initial-access", "lateral-movement"]),
+    ("T1092", &["command-and-control"]),
+    ("T1095", &["command-and-control"]),
+    ("T1098", &["persistence", "privilege-escalation"]),
+    ("T1102", &["command-and-control"]),
+    ("T1104", &["command-and-control"]),
+    ("T1105", &["command-and-control"]),
+    ("T1106", &["execution"]),
+    ("T1110", &["credential-access"]),
+    ("T1111", &["credential-access"]),
+    ("T1112", &["defense-evasion"]),
+    ("T1113", &["collection"]),
+    ("T1114", &["collection"]),
+    ("T1115", &["collection"]),
+    ("T1119", &["collection"]),
+    ("T1120", &["discovery"]),
+    ("T1123", &["collection"]),
+    ("T1124", &["discovery"]),
+    ("T1125", &["collection"]),
+    ("T1127", &["defense-evasion"]),
+    ("T1129", &["execution"]),
+    ("T1132", &["command-and-control"]),
+    ("T1133", &["initial-access", "persistence"]),
+    ("T1134", &["privilege-escalation", "defense-evasion"]),
+    ("T1135", &["discovery"]),
+    ("T1136", &["persistence"]),
+    ("T1137", &["persistence"]),
+    ("T1140", &["defense-evasion"]),
+    ("T1176", &["persistence"]),
+    ("T1185", &["collection"]),
+    ("T1187", &["credential-access"]),
+    ("T1189", &["initial-access"]),
+    ("T1190", &["initial-access"]),
+    ("T1195", &["initial-access"]),
+    ("T1197", &["persistence", "defense-evasion"]),
+    ("T1199", &["initial-access"]),
+    ("T1200", &["initial-access"]),
+    ("T1201", &["discovery"]),
+    ("T1202", &["defense-evasion"]),
+    ("T1203", &["execution"]),
+    ("T1204", &["execution"]),
+    (
+        "T1205",
+        &["persistence", "defense-evasion", "command-and-control"],
+    ),
+    ("T1207", &["defense-evasion"]),
+    ("T1210", &["lateral-movement"]),
+    ("T1211", &["defense-evasion"]),
+    ("T1212", &["credential-access"]),
+    ("T1213", &["collection"]),
+    ("T1216", &["defense-evasion"]),
+    ("T1217", &["discovery"]),
+    ("T1218", &["defense-evasion"]),
+    ("T1219", &["command-and-control"]),
+    ("T1220", &["defense-evasion"]),
+    ("T1221", &["defense-evasion"]),
+    ("T1222", &["defense-evasion"]),
+    ("T1480", &["defense-evasion"]),
+    ("T1482", &["discovery"]),
+    ("T1484", &["privilege-escalation", "defense-evasion"]),
+    ("T1485", &["impact"]),
+    ("T1486", &["impact"]),
+    ("T1489", &["impact"]),
+    ("T1490", &["impact"]),
+    ("T1491", &["impact"]),
+    ("T1495", &["impact"]),
+    ("T1496", &["impact"]),
+    ("T1497", &["defense-evasion", "discovery"]),
+    ("T1498", &["impact"]),
+    ("T1499", &["impact"]),
+    ("T1505", &["persistence"]),
+    ("T1518", &["discovery"]),
+    ("T1525", &["persistence"]),
+    ("T1526", &["discovery"]),
+    ("T1528", &["credential-access"]),
+    ("T1529", &["impact"]),
+    ("T1530", &["collection"]),
+    ("T1531", &["impact"]),
+    ("T1534", &["lateral-movement"]),
+    ("T1535", &["defense-evasion"]),
+    ("T1537", &["exfiltration"]),
+    ("T1538", &["discovery"]),
+    ("T1539", &["credential-access"]),
+    ("T1542", &["persistence", "defense-evasion"]),
+    ("T1543", &["persistence", "privilege-escalation"]),
+    ("T1546", &["persistence", "privilege-escalation"]),
+    ("T1547", &["persistence", "privilege-escalation"]),
+    ("T1548", &["privilege-escalation", "defense-evasion"]),
+    ("T1550", &["defense-evasion", "lateral-movement"]),
+    ("T1552", &["credential-access"]),
+    ("T1553", &["defense-evasion"]),
+    ("T1554", &["persistence"]),
+    ("T1555", &["credential-access"]),
+    (
+        "T1556",
+        &["persistence", "defense-evasion", "credential-access"],
+    ),
+    ("T1557", &["credential-access", "collection"]),
+    ("T1558", &["credential-access"]),
+    ("T1559", &["execution"]),
+    ("T1560", &["collection"]),
+    ("T1561", &["impact"]),
+    ("T1562", &["defense-evasion"]),
+    ("T1563", &["lateral-movement"]),
+    ("T1564", &["defense-evasion"]),
+    ("T1565", &["impact"]),
+    ("T1566", &["initial-access"]),
+    ("T1567", &["exfiltration"]),
+    ("T1568", &["command-and-control"]),
+    ("T1569", &["execution"]),
+    ("T1570", &["lateral-movement"]),
+    ("T1571", &["command-and-control"]),
+    ("T1572", &["command-and-control"]),
+    ("T1573", &["command-and-control"]),
+    (
+        "T1574",
+        &["persistence", "privilege-escalation", "defense-evasion"],
+    ),
+    ("T1578", &["defense-evasion"]),
+    ("T1580", &["discovery"]),
+    ("T1583", &["resource-development"]),
+    ("T1584", &["resource-development"]),
+    ("T1585", &["resource-development"]),
+    ("T1586", &["resource-development"]),
+    ("T1587", &["resource-development"]),
+    ("T1588", &["resource-development"]),
+    ("T1589", &["reconnaissance"]),
+    ("T1590", &["reconnaissance"]),
+    ("T1591", &["reconnaissance"]),
+    ("T1592", &["reconnaissance"]),
+    ("T1593", &["reconnaissance"]),
+    ("T1594", &["reconnaissance"]),
+    ("T1595", &["reconnaissance"]),
+    ("T1596", &["reconnaissance"]),
+    ("T1597", &["reconnaissance"]),
+    ("T1598", &["reconnaissance"]),
+    ("T1599", &["defense-evasion"]),
+    ("T1600", &["defense-evasion"]),
+    ("T1601", &["defense-evasion"]),
+    ("T1602", &["collection"]),
+    ("T1606", &["credential-access"]),
+    ("T1608", &["resource-development"]),
+    ("T1609", &["execution"]),
+    ("T1610", &["execution", "defense-evasion"]),
+    ("T1611", &["privilege-escalation"]),
+    ("T1612", &["defense-evasion"]),
+    ("T1613", &["discovery"]),
+    ("T1614", &["discovery"]),
+    ("T1615", &["discovery"]),
+    ("T1619", &["discovery"]),
+    ("T1620", &["defense-evasion"]),
+    ("T1621", &["credential-access"]),
+    ("T1622", &["defense-evasion", "discovery"]),
+    ("T1647", &["defense-evasion"]),
+    ("T1648", &["execution"]),
+    ("T1649", &["credential-access"]),
+    ("T1650", &["resource-development"]),
+    ("T1651", &["execution"]),
+    ("T1652", &["discovery"]),
+    ("T1653", &["persistence"]),
+    ("T1654", &["discovery"]),
+    ("T1656", &["defense-evasion"]),
+    ("T1657", &["impact"]),
+    ("T1659", &["initial-access", "command-and-control"]),
+    ("T1665", &["command-and-control"]),
+];
+
+/// A current technique.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Technique {
+    pub name: String,
+    /// Tactic short names, such as `defense-evasion`.
+    pub tactics: Vec<String>,
+}
+
+/// What the ATT&CK data says about a technique ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup<'a> {
+    Current(&'a Technique),
+    /// Revoked or deprecated, with the ID that replaced it if there is one.
+    Retired(Option<&'a str>),
+    /// A sub-technique of a current technique that the bundled table does
+    /// not list, so whether ATT&CK has it is not known.
+    UnlistedSubTechnique,
+    /// Not a technique the data knows.
+    Unknown,
+}
+
+/// The techniques, tactics and retired IDs rule names are checked against:
+/// the tables above, or the copy `update-attack-data` last saved.
+#[derive(Debug)]
+pub struct Database {
+    /// `ATT&CK v15 (bundled)`.
+    pub source: String,
+    techniques: BTreeMap<String, Technique>,
+    /// Retired ID to the ID that replaced it, if any.
+    retired: BTreeMap<String, Option<String>>,
+    /// Whether every sub-technique is listed, as in data from the STIX feed.
+    complete: bool,
+}
+
+impl Database {
+    pub fn bundled() -> Database {
+        let tactics = |id: &str| -> Vec<String> {
+            let parent = id.split('.').next().unwrap_or(id);
+            TACTICS
+                .iter()
+                .find(|(known, _)| *known == parent)
+                .map(|(_, tactics)| tactics.iter().map(|t| t.to_string()).collect())
+                .unwrap_or_default()
+        };
+        Database {
+            source: "ATT&CK v15 (bundled)".to_string(),
+            techniques: TECHNIQUES
+                .iter()
+                .map(|(id, name)| {
+                    let technique = Technique {
+                        name: name.to_string(),
+                        tactics: tactics(id),
+                    };
+                    (id.to_string(), technique)
+                })
+                .collect(),
+            retired: REVOKED
+                .iter()
+                .map(|(old, new)| (old.to_string(), Some(new.to_string())))
+                .collect(),
+            complete: false,
+        }
+    }
+
+    /// The data `update-attack-data` saved, or the bundled tables when
+    /// there is none or it cannot be read.
+    pub fn load() -> Database {
+        let Some(path) = cached_path() else {
+            return Database::bundled();
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Database::bundled(),
+            Err(e) => {
+                warn!("{}: {}; using the bundled ATT&CK data", path.display(), e);
+                return Database::bundled();
+            }
+        };
+        match Database::parse(&text) {
+            Ok(database) => {
+                debug!("Using {} from {}", database.source, path.display());
+                database
+            }
+            Err(e) => {
+                warn!(
+                    "{}: {}; using the bundled ATT&CK data, run update-attack-data to replace it",
+                    path.display(),
+                    e
+                );
+                Database::bundled()
+            }
+        }
+    }
+
+    pub fn lookup(&self, id: &str) -> Lookup<'_> {
+        if let Some(technique) = self.techniques.get(id) {
+            return Lookup::Current(technique);
+        }
+        if let Some(replacement) = self.retired.get(id) {
+            return Lookup::Retired(replacement.as_deref());
+        }
+        match id.split_once('.') {
+            Some((parent, _)) if !self.complete && self.techniques.contains_key(parent) => {
+                Lookup::UnlistedSubTechnique
+            }
+            _ => Lookup::Unknown,
+        }
+    }
+
+    /// The name of a current technique.
+    pub fn name(&self, id: &str) -> Option<&str> {
+        self.techniques.get(id).map(|t| t.name.as_str())
+    }
+
+    /// The tactics of a current technique, or of the parent of a sub-technique
+    /// the data does not list.
+    pub fn tactics(&self, id: &str) -> &[String] {
+        let parent = id.split('.').next().unwrap_or(id);
+        self.techniques
+            .get(id)
+            .or_else(|| self.techniques.get(parent))
+            .map_or(&[], |t| t.tactics.as_slice())
+    }
+
+    /// The cache file: a `# source` line, then one tab-separated line per
+    /// ID with its name, comma-separated tactics, and for a retired ID the
+    /// ID that replaced it or `-`.
+    fn to_tsv(&self) -> String {
+        let mut out = format!("# {}\n", self.source);
+        for (id, technique) in &self.techniques {
+            let _ = writeln!(
+                out,
+                "{}\t{}\t{}\t",
+                id,
+                technique.name,
+                technique.tactics.join(",")
+            );
+        }
+        for (id, replacement) in &self.retired {
+            let _ = writeln!(out, "{}\t\t\t{}", id, replacement.as_deref().unwrap_or("-"));
+        }
+        out
+    }
+
+    fn parse(text: &str) -> Result<Database, String> {
+        let mut lines = text.lines().enumerate();
+        let source = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix("# "))
+            .ok_or("missing the source line")?
+            .to_string();
+        let mut techniques = BTreeMap::new();
+        let mut retired = BTreeMap::new();
+        for (index, line) in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [id, name, tactics, replaced_by] = fields[..] else {
+                return Err(format!(
+                    "line {}: expected 4 tab-separated fields",
+                    index + 1
+                ));
+            };
+            match replaced_by {
+                "" => {
+                    let technique = Technique {
+                        name: name.to_string(),
+                        tactics: tactics
+                            .split(',')
+                            .filter(|t| !t.is_empty())
+                            .map(str::to_string)
+                            .collect(),
+                    };
+                    techniques.insert(id.to_string(), technique);
+                }
+                "-" => {
+                    retired.insert(id.to_string(), None);
+                }
+                new => {
+                    retired.insert(id.to_string(), Some(new.to_string()));
+                }
+            }
+        }
+        if techniques.is_empty() {
+            return Err("no techniques".to_string());
+        }
+        Ok(Database {
+            source,
+            techniques,
+            retired,
+            complete: true,
+        })
+    }
+
+    /// The Enterprise techniques in a STIX 2.1 bundle such as MITRE's
+    /// `enterprise-attack.json`.
+    fn from_stix(objects: &[StixObject]) -> Result<Database, String> {
+        let attack_id = |object: &StixObject| {
+            object
+                .external_references
+                .iter()
+                .find(|r| r.source_name == "mitre-attack")
+                .map(|r| r.external_id.clone())
+                .filter(|id| id.starts_with('T'))
+        };
+        let by_stix_id: HashMap<&str, String> = objects
+            .iter()
+            .filter(|o| o.kind == "attack-pattern")
+            .filter_map(|o| Some((o.id.as_str(), attack_id(o)?)))
+            .collect();
+        let replaced_by: HashMap<&str, &String> = objects
+            .iter()
+            .filter(|o| o.kind == "relationship" && o.relationship_type == "revoked-by")
+            .filter_map(|o| {
+                Some((
+                    o.source_ref.as_str(),
+                    by_stix_id.get(o.target_ref.as_str())?,
+                ))
+            })
+            .collect();
+
+        let mut techniques = BTreeMap::new();
+        let mut retired = BTreeMap::new();
+        for object in objects.iter().filter(|o| o.kind == "attack-pattern") {
+            let Some(id) = attack_id(object) else {
+                continue;
+            };
+            if object.revoked || object.x_mitre_deprecated {
+                let replacement = replaced_by.get(object.id.as_str()).map(|s| s.to_string());
+                retired.insert(id, replacement);
+                continue;
+            }
+            let tactics = object
+                .kill_chain_phases
+                .iter()
+                .filter(|p| p.kill_chain_name == "mitre-attack")
+                .map(|p| p.phase_name.clone())
+                .collect();
+            let technique = Technique {
+                name: object.name.clone(),
+                tactics,
+            };
+            techniques.insert(id, technique);
+        }
+        if techniques.is_empty() {
+            return Err("no ATT&CK techniques in the bundle".to_string());
+        }
+        let version = objects
+            .iter()
+            .find(|o| o.kind == "x-mitre-collection")
+            .map(|o| o.x_mitre_version.as_str())
+            .filter(|v| !v.is_empty());
+        Ok(Database {
+            source: match version {
+                Some(version) => format!("ATT&CK v{}", version),
+                None => "ATT&CK".to_string(),
+            },
+            techniques,
+            retired,
+            complete: true,
+        })
+    }
+}
+
+/// The display name of a tactic short name.
+pub fn tactic_name(tactic: &str) -> &str {
+    TACTIC_NAMES
+        .iter()
+        .find(|(short, _)| *short == tactic)
+        .map_or(tactic, |(_, name)| name)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct StixBundle {
+    objects: Vec<StixObject>,
+}
+
+/// The parts of a STIX object `update-attack-data` reads.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct StixObject {
+    #[serde(rename = "type")]
+    kind: String,
+    id: String,
+    name: String,
+    external_references: Vec<ExternalReference>,
+    kill_chain_phases: Vec<KillChainPhase>,
+    revoked: bool,
+    x_mitre_deprecated: bool,
+    x_mitre_version: String,
+    relationship_type: String,
+    source_ref: String,
+    target_ref: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ExternalReference {
+    source_name: String,
+    external_id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct KillChainPhase {
+    kill_chain_name: String,
+    phase_name: String,
+}
+
+#[derive(Args)]
+pub struct UpdateAttackDataArgs {
+    /// Read a STIX bundle already on disk instead of downloading one
+    #[arg(long, value_name = "FILE", conflicts_with = "url")]
+    pub from: Option<PathBuf>,
+
+    /// Where to download the Enterprise ATT&CK STIX bundle from
+    #[arg(
+        long,
+        default_value = "https://raw.githubusercontent.com/mitre-attack/attack-stix-data/master/enterprise-attack/enterprise-attack.json"
+    )]
+    pub url: String,
+}
+
+fn cached_path() -> Option<PathBuf> {
+    compare::cache_dir("attack").map(|dir| dir.join("techniques.tsv"))
+}
+
+pub fn run(args: &UpdateAttackDataArgs) -> Result<(), ConversionError> {
+    let path = cached_path().ok_or_else(|| {
+        ConversionError::ValidationError(
+            "no cache directory for the ATT&CK data; set XDG_CACHE_HOME".to_string(),
+        )
+    })?;
+    // Refuse before downloading anything when the cache cannot be written.
+    io_guard::check_write(&path)?;
+    let dir = path.parent().expect("the cache file is in a directory");
+    io_guard::create_dir_all(dir)?;
+
+    let bundle = match &args.from {
+        Some(from) => from.clone(),
+        None => {
+            let bundle = dir.join("enterprise-attack.json");
+            info!("Downloading {}", args.url);
+            compare::download(&args.url, &bundle, "pass its path to --from")?;
+            bundle
+        }
+    };
+    let text =
+        std::fs::read_to_string(&bundle).map_err(|e| ConversionError::io_error(&bundle, e))?;
+    let parsed: StixBundle = serde_json::from_str(&text)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", bundle.display(), e)))?;
+    if args.from.is_none() {
+        let _ = io_guard::remove(&bundle);
+    }
+    let database = Database::from_stix(&parsed.objects)
+        .map_err(|e| ConversionError::ValidationError(format!("{}: {}", bundle.display(), e)))?;
+
+    io_guard::write(&path, database.to_tsv())?;
+    info!(
+        "Saved {} with {} technique(s) and {} retired ID(s) to {}",
+        database.source,
+        database.techniques.len(),
+        database.retired.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_knows_current_retired_and_unlisted_ids() {
+        let attack = Database::bundled();
+        assert_eq!(attack.name("T1059.001"), Some("PowerShell"));
+        assert_eq!(attack.tactics("T1059.001"), ["execution"]);
+        assert_eq!(attack.tactics("T1059.010"), ["execution"]);
+        assert_eq!(attack.lookup("T1086"), Lookup::Retired(Some("T1059.001")));
+        assert_eq!(attack.lookup("T1059.010"), Lookup::UnlistedSubTechnique);
+        assert_eq!(attack.lookup("T9999"), Lookup::Unknown);
+        let fetched = Database {
+            complete: true,
+            ..Database::bundled()
+        };
+        assert_eq!(fetched.lookup("T1059.999"), Lookup::Unknown);
+        assert!(TECHNIQUES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(REVOKED.iter().all(|(_, new)| attack.name(new).is_some()));
+        assert!(TECHNIQUES
+            .iter()
+            .all(|(id, _)| !attack.tactics(id).is_empty()));
+        assert_eq!(tactic_name("command-and-control"), "Command and Control");
+    }
+
+    #[test]
+    fn test_stix_objects_round_trip_through_the_cache_file() {
+        let pattern = |stix: &str, id: &str, name: &str, tactic: &str| StixObject {
+            kind: "attack-pattern".into(),
+            id: stix.into(),
+            name: name.into(),
+            external_references: vec![ExternalReference {
+                source_name: "mitre-attack".into(),
+                external_id: id.into(),
+            }],
+            kill_chain_phases: vec![KillChainPhase {
+                kill_chain_name: "mitre-attack".into(),
+                phase_name: tactic.into(),
+            }],
+            ..StixObject::default()
+        };
+        let objects = vec![
+            StixObject {
+                kind: "x-mitre-collection".into(),
+                x_mitre_version: "16.1".into(),
+                ..StixObject::default()
+            },
+            pattern("attack-pattern--1", "T1059.001", "PowerShell", "execution"),
+            StixObject {
+                revoked: true,
+                ..pattern("attack-pattern--2", "T1086", "PowerShell", "execution")
+            },
+            StixObject {
+                x_mitre_deprecated: true,
+                ..pattern("attack-pattern--3", "T1153", "Source", "execution")
+            },
+            StixObject {
+                kind: "relationship".into(),
+                relationship_type: "revoked-by".into(),
+                source_ref: "attack-pattern--2".into(),
+                target_ref: "attack-pattern--1".into(),
+                ..StixObject::default()
+            },
+        ];
+        let database = Database::from_stix(&objects).unwrap();
+        let tsv = database.to_tsv();
+        assert_eq!(
+            tsv,
+            "# ATT&CK v16.1\nT1059.001\tPowerShell\texecution\t\nT1086\t\t\tT1059.001\nT1153\t\t\t-\n"
+        );
+
+        let cached = Database::parse(&tsv).unwrap();
+        assert_eq!(cached.source, "ATT&CK v16.1");
+        assert_eq!(cached.lookup("T1086"), Lookup::Retired(Some("T1059.001")));
+        assert_eq!(cached.lookup("T1153"), Lookup::Retired(None));
+        assert_eq!(cached.tactics("T1059.001"), ["execution"]);
+        assert!(Database::parse("# x\nT1\tbroken\n").is_err());
+    }
+
+    #[test]
+    fn test_update_refused_under_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = UpdateAttackDataArgs {
+            from: Some(dir.path().join("enterprise-attack.json")),
+            url: String::new(),
+        };
+        let error = io_guard::with_read_only(|| run(&args)).unwrap_err();
+        assert!(error.to_string().contains("--read-only"), "{}", error);
+    }
+}
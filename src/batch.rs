@@ -0,0 +1,1580 @@
+//! Planned batch conversion.
+//!
+//! Every directory conversion runs here rather than through `BatchProcessor`,
+//! which always mirrors the input tree and cannot run the CLI's own
+//! preprocessing. The CLI builds an explicit plan of input/output pairs,
+//! resolves any output name collisions according to the chosen policy, and
+//! then converts each job, reporting every file to a
+//! `sysmon_cli::progress::Progress`. Every job goes through the same
+//! preprocessing pipeline as a single-file conversion.
+//!
+//! A cancelled [`CancellationToken`] (Ctrl-C) stops the run early: jobs not
+//! yet started are left alone, and jobs in progress stop before writing
+//! their output.
+//!
+//! With `--dedupe-outputs`, an output identical to one already written is
+//! hard-linked to it, or left unwritten and listed in
+//! `duplicate-outputs.csv`, instead of being written again.
+
+use crate::cancel::CancellationToken;
+use crate::encoding::Encoding;
+use crate::since::ModifiedSince;
+use crate::template::OutputTemplate;
+use crate::{convert, encoding, include, io_guard, minify, pretty, walk};
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use sysmon_cli::format::Format;
+use sysmon_cli::preprocess::{self, Pipeline};
+use sysmon_cli::progress::{FileEvent, FileStatus, Progress, Summary};
+use sysmon_cli::repair;
+use sysmon_json::{error::ConversionError, preprocessor::preprocess_config};
+
+/// What to do when two inputs would be written to the same output path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CollisionPolicy {
+    /// Abort before converting anything
+    Error,
+    /// Append a short hash of the input's relative path to the file stem
+    SuffixHash,
+    /// Append the input's relative parent directories to the file stem
+    SuffixDir,
+    /// Convert the first input and skip the others
+    Skip,
+}
+
+/// How `--dedupe-outputs` stores an output identical to one already written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DedupeMode {
+    /// Hard-link it to the first copy, or write a copy where links are not supported
+    Link,
+    /// Do not write it; list it in duplicate-outputs.csv in the output directory
+    Record,
+}
+
+/// The order batch jobs are started in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Schedule {
+    /// Input path order
+    InputOrder,
+    /// Largest inputs first, so a big file does not start last and hold up the end of the run
+    LargestFirst,
+    /// Inputs that took longest in an earlier --incremental run first; the rest by size
+    SlowestFirst,
+}
+
+/// Lists the outputs `DedupeMode::Record` left unwritten.
+pub const DUPLICATES_FILE: &str = "duplicate-outputs.csv";
+
+/// A size in bytes, written as a number with an optional K, M or G suffix
+/// (powers of 1024).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let upper = text.trim().to_ascii_uppercase();
+        let digits = upper.trim_end_matches('B');
+        let (number, unit) = match digits.char_indices().last() {
+            Some((at, 'K')) => (&digits[..at], 1 << 10),
+            Some((at, 'M')) => (&digits[..at], 1 << 20),
+            Some((at, 'G')) => (&digits[..at], 1 << 30),
+            _ => (digits, 1),
+        };
+        number
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .map(ByteSize)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid size {:?}: expected bytes, e.g. 0, 512K or 5M",
+                    text
+                )
+            })
+    }
+}
+
+/// Settings for a planned batch run.
+pub struct BatchSettings {
+    pub recursive: bool,
+    pub max_depth: u32,
+    /// Let `<?include?>` directives leave the input's directory, as
+    /// `--allow-outside-includes`.
+    pub allow_outside_includes: bool,
+    pub max_file_size: u64,
+    /// Inputs smaller than this are skipped rather than converted. The
+    /// size range lives here only: `ProcessingOptionsBuilder` is
+    /// `sysmon_json`'s, and `BatchProcessor` has no notion of skipping.
+    pub min_size: Option<u64>,
+    /// Inputs larger than this are skipped; unlike `max_file_size`, which
+    /// fails them.
+    pub max_size_skip: Option<u64>,
+    pub workers: Option<usize>,
+    pub create_backup: bool,
+    pub ignore_patterns: Vec<String>,
+    pub flatten: bool,
+    pub on_collision: CollisionPolicy,
+    pub pipeline: Pipeline,
+    /// Repair recoverable XML problems before converting, as `--lenient`.
+    pub lenient: bool,
+    /// Text encoding of the outputs [default: UTF-8].
+    pub output_encoding: Option<Encoding>,
+    /// Re-read each output after writing it and check that it parses.
+    pub verify: bool,
+    /// Upper bound, in bytes, on the memory held by conversions in flight.
+    pub max_memory: Option<u64>,
+    /// Time allowed for each file before it is recorded as failed.
+    pub timeout: Option<Duration>,
+    pub follow_symlinks: bool,
+    /// Where outputs go instead of mirroring the input tree.
+    pub output_template: Option<OutputTemplate>,
+    /// Stop starting new files once this many have failed; files already
+    /// being converted still finish.
+    pub max_errors: Option<usize>,
+    /// Only inputs modified at or after this time are converted.
+    pub modified_since: Option<ModifiedSince>,
+    /// Minify outputs (see [`minify`]).
+    pub minify: bool,
+    /// Lay outputs out in this style (see [`pretty`]).
+    pub pretty: Option<pretty::Style>,
+    /// Store outputs identical to an earlier one this way instead of
+    /// writing them again.
+    pub dedupe_outputs: Option<DedupeMode>,
+    pub schedule: Schedule,
+    /// Stops the run when cancelled.
+    pub cancel: CancellationToken,
+}
+
+impl BatchSettings {
+    /// How far `<?include?>` directives may reach.
+    pub fn includes(&self) -> include::Limits {
+        include::Limits {
+            max_depth: self.max_depth as usize,
+            outside_root: self.allow_outside_includes,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+/// Several inputs that mapped to one output path, and how that was resolved.
+#[derive(Debug)]
+pub struct Collision {
+    pub output: PathBuf,
+    pub inputs: Vec<PathBuf>,
+    pub resolution: String,
+}
+
+/// An output whose content is identical to one written before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    pub output: PathBuf,
+    pub original: PathBuf,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub jobs: Vec<Job>,
+    pub collisions: Vec<Collision>,
+    pub skipped: Vec<PathBuf>,
+    /// How long inputs took to convert in an earlier run, for
+    /// `Schedule::SlowestFirst`.
+    pub history: HashMap<PathBuf, Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub processed: usize,
+    pub errors: usize,
+    pub skipped: usize,
+    pub collisions: Vec<Collision>,
+    /// Inputs that failed to convert, sorted.
+    pub failures: Vec<PathBuf>,
+    /// Inputs never started because `max_errors` was reached.
+    pub not_started: Vec<PathBuf>,
+    /// Outputs linked or left unwritten by `dedupe_outputs`, sorted.
+    pub duplicates: Vec<Duplicate>,
+    /// How long each converted input took.
+    pub durations: Vec<(PathBuf, Duration)>,
+    /// Inputs not converted because the run was cancelled, sorted.
+    pub cancelled: Vec<PathBuf>,
+}
+
+/// Runs `work` on every item of `items`, with its index, on `workers`
+/// threads (default: one per CPU). Each thread takes the next item nobody
+/// has claimed yet, so slow items do not hold up the rest.
+pub fn in_parallel<T: Sync>(items: &[T], workers: Option<usize>, work: impl Fn(usize, &T) + Sync) {
+    let workers = workers
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+        .clamp(1, items.len().max(1));
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                work(index, item);
+            });
+        }
+    });
+}
+
+/// Lists convertible files under `input_dir` in a stable order.
+pub fn collect_inputs(input_dir: &Path, settings: &BatchSettings) -> Vec<PathBuf> {
+    let max_depth = if settings.recursive {
+        settings.max_depth as usize
+    } else {
+        1
+    };
+
+    walk::files(input_dir, max_depth, settings.follow_symlinks)
+        .into_iter()
+        .filter(|path| is_selected(input_dir, path, settings))
+        .collect()
+}
+
+/// Narrows a `--files-from` list to the convertible files under
+/// `input_dir`, in a stable order. Listed files that no longer exist are
+/// skipped, and so are files outside `input_dir`, with a warning, since
+/// their outputs would land outside the output directory.
+pub fn listed_inputs(
+    input_dir: &Path,
+    listed: Vec<PathBuf>,
+    settings: &BatchSettings,
+) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in listed {
+        let path = if input_dir == Path::new(".") && path.is_relative() {
+            input_dir.join(path)
+        } else {
+            path
+        };
+        if path.strip_prefix(input_dir).is_err() {
+            warn!(
+                "Skipping {}: not inside the input directory {}",
+                path.display(),
+                input_dir.display()
+            );
+            continue;
+        }
+        if !path.is_file() {
+            info!("Skipping {}: no such file", path.display());
+            continue;
+        }
+        if is_selected(input_dir, &path, settings) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn is_selected(input_dir: &Path, path: &Path, settings: &BatchSettings) -> bool {
+    let relative = path.strip_prefix(input_dir).unwrap_or(path);
+    output_extension(path).is_some()
+        && !settings
+            .ignore_patterns
+            .iter()
+            .any(|pattern| is_ignored(pattern, relative))
+        && settings.modified_since.as_ref().is_none_or(|since| {
+            let modified = since.includes(path);
+            if !modified {
+                debug!("Skipping {}: not modified since {}", path.display(), since);
+            }
+            modified
+        })
+}
+
+/// Maps each input to its output path and applies the collision policy.
+/// Inputs outside the `min_size`/`max_size_skip` range are skipped first.
+pub fn plan(
+    input_dir: &Path,
+    output_dir: &Path,
+    files: Vec<PathBuf>,
+    settings: &BatchSettings,
+) -> Result<Plan, ConversionError> {
+    let (files, out_of_range): (Vec<PathBuf>, Vec<PathBuf>) = files
+        .into_iter()
+        .partition(|input| in_size_range(input, settings));
+
+    let mut by_output: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for input in files {
+        let output = match &settings.output_template {
+            Some(template) => {
+                let format = Format::from_extension(&input)
+                    .unwrap_or(Format::Xml)
+                    .target();
+                output_dir.join(template.render(input_dir, &input, format)?)
+            }
+            None => default_output(input_dir, output_dir, &input, settings.flatten),
+        };
+        by_output.entry(output).or_default().push(input);
+    }
+
+    let colliding: Vec<(&PathBuf, &Vec<PathBuf>)> = by_output
+        .iter()
+        .filter(|(_, inputs)| inputs.len() > 1)
+        .collect();
+
+    if settings.on_collision == CollisionPolicy::Error && !colliding.is_empty() {
+        let details: Vec<String> = colliding
+            .iter()
+            .map(|(output, inputs)| describe_collision(output, inputs))
+            .collect();
+        return Err(ConversionError::InvalidFile(format!(
+            "{} output collision(s):\n{}",
+            details.len(),
+            details.join("\n")
+        )));
+    }
+
+    let mut plan = Plan {
+        skipped: out_of_range,
+        ..Plan::default()
+    };
+    for (output, inputs) in by_output {
+        if inputs.len() == 1 {
+            plan.jobs.push(Job {
+                input: inputs.into_iter().next().unwrap(),
+                output,
+            });
+            continue;
+        }
+
+        let resolution = match settings.on_collision {
+            CollisionPolicy::Error => unreachable!("collisions rejected above"),
+            CollisionPolicy::Skip => {
+                let mut inputs_iter = inputs.iter().cloned();
+                plan.jobs.push(Job {
+                    input: inputs_iter.next().unwrap(),
+                    output: output.clone(),
+                });
+                plan.skipped.extend(inputs_iter);
+                format!("kept {}, skipped the rest", inputs[0].display())
+            }
+            CollisionPolicy::SuffixHash | CollisionPolicy::SuffixDir => {
+                for input in &inputs {
+                    let relative = input.strip_prefix(input_dir).unwrap_or(input);
+                    let suffix = if settings.on_collision == CollisionPolicy::SuffixHash {
+                        hash_suffix(relative)
+                    } else {
+                        dir_suffix(relative)
+                    };
+                    plan.jobs.push(Job {
+                        input: input.clone(),
+                        output: with_stem_suffix(&output, &suffix),
+                    });
+                }
+                "renamed with suffixes".to_string()
+            }
+        };
+
+        plan.collisions.push(Collision {
+            output,
+            inputs,
+            resolution,
+        });
+    }
+
+    // Suffixes are derived from unique relative paths, but `a_b/x` and
+    // `a/b/x` still share a directory suffix, so check once more.
+    let mut seen = BTreeMap::new();
+    for job in &plan.jobs {
+        if let Some(previous) = seen.insert(job.output.clone(), job.input.clone()) {
+            return Err(ConversionError::InvalidFile(format!(
+                "Unresolvable output collision: {} and {} both map to {}",
+                previous.display(),
+                job.input.display(),
+                job.output.display()
+            )));
+        }
+    }
+
+    plan.jobs.sort_by(|a, b| a.input.cmp(&b.input));
+    Ok(plan)
+}
+
+/// Converts every job in `plan`, continuing past individual failures, and
+/// reports each file to `progress`.
+pub fn run(plan: Plan, settings: &BatchSettings, progress: &dyn Progress) -> BatchReport {
+    for collision in &plan.collisions {
+        warn!(
+            "{} ({})",
+            describe_collision(&collision.output, &collision.inputs),
+            collision.resolution
+        );
+    }
+
+    let sizes: Vec<u64> = plan.jobs.iter().map(|job| input_size(&job.input)).collect();
+    progress.on_start(plan.jobs.len(), sizes.iter().sum());
+
+    let started = Instant::now();
+    let summary = Mutex::new(Summary::default());
+    for skipped in &plan.skipped {
+        let event = FileEvent {
+            path: skipped,
+            bytes: input_size(skipped),
+            status: FileStatus::Skipped,
+            elapsed: Duration::ZERO,
+        };
+        summary.lock().unwrap().record(&event);
+        progress.on_file_done(&event);
+    }
+
+    let budget = Arc::new(MemoryBudget::new(settings.max_memory));
+    let written = Written::default();
+    let duplicates = Mutex::new(Vec::new());
+    let durations = Mutex::new(Vec::new());
+    let failed = AtomicUsize::new(0);
+    let not_started = Mutex::new(Vec::new());
+    let cancelled = Mutex::new(Vec::new());
+    let order = start_order(&plan, &sizes, settings.schedule);
+    in_parallel(&order, settings.workers, |_, &index| {
+        let job = &plan.jobs[index];
+        if settings
+            .max_errors
+            .is_some_and(|max| failed.load(Ordering::SeqCst) >= max)
+        {
+            not_started.lock().unwrap().push(job.input.clone());
+            return;
+        }
+        if settings.cancel.is_cancelled() {
+            cancelled.lock().unwrap().push(job.input.clone());
+            return;
+        }
+        progress.on_file_start(&job.input);
+        let job_started = Instant::now();
+        let result = {
+            let _in_flight = settings.cancel.track(&job.output);
+            budget
+                .reserve(sizes[index] * MEMORY_PER_INPUT_BYTE)
+                .and_then(|reservation| convert_job(job, settings, &written, reservation))
+        };
+        let status = match result {
+            Ok(duplicate) => {
+                duplicates.lock().unwrap().extend(duplicate);
+                durations
+                    .lock()
+                    .unwrap()
+                    .push((job.input.clone(), job_started.elapsed()));
+                FileStatus::Converted
+            }
+            Err(e) if settings.cancel.is_cancelled() => {
+                debug!("Cancelled {}: {}", job.input.display(), e);
+                // Outputs are renamed into place when complete, so only an
+                // unfinished partial file can be left.
+                if let Err(e) = io_guard::remove(&io_guard::partial_path(&job.output)) {
+                    warn!("{}", e);
+                }
+                cancelled.lock().unwrap().push(job.input.clone());
+                return;
+            }
+            Err(e) => {
+                failed.fetch_add(1, Ordering::SeqCst);
+                error!(
+                    kind = crate::error_report::kind(&e),
+                    input:% = job.input.display();
+                    "Failed to convert {}: {}", job.input.display(), e
+                );
+                progress.on_error(&job.input, &e);
+                FileStatus::Failed
+            }
+        };
+        let event = FileEvent {
+            path: &job.input,
+            bytes: sizes[index],
+            status,
+            elapsed: job_started.elapsed(),
+        };
+        summary.lock().unwrap().record(&event);
+        progress.on_file_done(&event);
+    });
+
+    // Failures are recorded as workers finish; sort them so reports and
+    // the pending list do not depend on thread timing.
+    let mut summary = summary.into_inner().unwrap();
+    summary.failures.sort();
+    summary.elapsed = started.elapsed();
+    progress.on_finish(&summary);
+
+    let report = BatchReport {
+        processed: summary.converted,
+        errors: summary.failed,
+        skipped: summary.skipped,
+        collisions: plan.collisions,
+        failures: summary.failures,
+        not_started: {
+            let mut not_started = not_started.into_inner().unwrap();
+            not_started.sort();
+            not_started
+        },
+        duplicates: {
+            let mut duplicates = duplicates.into_inner().unwrap();
+            duplicates.sort_by(|a, b| a.output.cmp(&b.output));
+            duplicates
+        },
+        durations: durations.into_inner().unwrap(),
+        cancelled: {
+            let mut cancelled = cancelled.into_inner().unwrap();
+            cancelled.sort();
+            cancelled
+        },
+    };
+    if !report.not_started.is_empty() {
+        warn!(
+            "Stopped after {} failed file(s); {} file(s) were not converted",
+            report.errors,
+            report.not_started.len()
+        );
+    }
+    if settings.cancel.is_cancelled() {
+        warn!(
+            "Interrupted; {} file(s) were not converted",
+            report.cancelled.len()
+        );
+    }
+
+    info!(
+        "Processed {} file(s), {} failed, {} skipped, {} collision(s)",
+        report.processed,
+        report.errors,
+        report.skipped,
+        report.collisions.len()
+    );
+    if let Some(mode) = settings.dedupe_outputs {
+        let bytes: u64 = report
+            .duplicates
+            .iter()
+            .map(|d| input_size(&d.original))
+            .sum();
+        info!(
+            "{} {} duplicate output(s), saving {} byte(s)",
+            match mode {
+                DedupeMode::Link => "Linked",
+                DedupeMode::Record => "Recorded",
+            },
+            report.duplicates.len(),
+            bytes
+        );
+    }
+    report
+}
+
+/// The indices of `plan.jobs` in the order `schedule` starts them. For
+/// `Schedule::SlowestFirst`, inputs without a recorded duration are
+/// expected to take as long per byte as the recorded ones did on average,
+/// which orders them by size when nothing was recorded.
+fn start_order(plan: &Plan, sizes: &[u64], schedule: Schedule) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..plan.jobs.len()).collect();
+    match schedule {
+        Schedule::InputOrder => {}
+        Schedule::LargestFirst => order.sort_by_key(|&i| std::cmp::Reverse(sizes[i])),
+        Schedule::SlowestFirst => {
+            let recorded = |i: usize| plan.history.get(&plan.jobs[i].input);
+            let (bytes, nanos) = order
+                .iter()
+                .filter_map(|&i| recorded(i).map(|d| (sizes[i], d.as_nanos())))
+                .fold((0u64, 0u128), |(b, n), (size, d)| (b + size, n + d));
+            let per_byte = if bytes > 0 && nanos > 0 {
+                nanos as f64 / bytes as f64
+            } else {
+                1.0
+            };
+            let expected =
+                |i: usize| recorded(i).map_or(sizes[i] as f64 * per_byte, |d| d.as_nanos() as f64);
+            order.sort_by(|&a, &b| expected(b).total_cmp(&expected(a)));
+        }
+    }
+    order
+}
+
+/// Rough peak memory per byte of input: the raw bytes, the decoded text,
+/// the parsed document and the converted output are alive at the same time.
+const MEMORY_PER_INPUT_BYTE: u64 = 4;
+
+/// Shared cap on the estimated memory of the jobs being converted. Workers
+/// wait for room instead of all loading large files at once.
+struct MemoryBudget {
+    limit: Option<u64>,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    fn new(limit: Option<u64>) -> Self {
+        MemoryBudget {
+            limit,
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Blocks until `bytes` fit under the limit. Fails straight away for a
+    /// job that could never fit.
+    fn reserve(self: &Arc<Self>, bytes: u64) -> Result<Reservation, ConversionError> {
+        let Some(limit) = self.limit else {
+            return Ok(Reservation {
+                budget: self.clone(),
+                bytes: 0,
+            });
+        };
+        if bytes > limit {
+            return Err(ConversionError::InvalidFile(format!(
+                "Converting would need about {} MB, over the --max-memory-mb limit of {} MB",
+                bytes.div_ceil(1024 * 1024),
+                limit / (1024 * 1024)
+            )));
+        }
+        let mut used = self.used.lock().unwrap();
+        while *used + bytes > limit {
+            used = self.freed.wait(used).unwrap();
+        }
+        *used += bytes;
+        Ok(Reservation {
+            budget: self.clone(),
+            bytes,
+        })
+    }
+}
+
+/// Memory held for one job until dropped. Shared with the thread of a
+/// timed-out conversion, which keeps using the memory after the job gives up.
+struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            *self.budget.used.lock().unwrap() -= self.bytes;
+            self.budget.freed.notify_all();
+        }
+    }
+}
+
+/// Whether `input` is within the size range of `settings`, logging why not.
+fn in_size_range(input: &Path, settings: &BatchSettings) -> bool {
+    let size = input_size(input);
+    let reason = match (settings.min_size, settings.max_size_skip) {
+        (Some(min), _) if size < min => format!("smaller than {} bytes", min),
+        (_, Some(max)) if size > max => format!("larger than {} bytes", max),
+        _ => return true,
+    };
+    info!("Skipping {}: {} bytes, {}", input.display(), size, reason);
+    false
+}
+
+fn input_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Prints what `run` would do, without touching the filesystem.
+pub fn print_plan(plan: &Plan) {
+    for job in &plan.jobs {
+        println!("{} -> {}", job.input.display(), job.output.display());
+    }
+    for skipped in &plan.skipped {
+        println!("{} -> skipped", skipped.display());
+    }
+    for collision in &plan.collisions {
+        println!(
+            "{} ({})",
+            describe_collision(&collision.output, &collision.inputs),
+            collision.resolution
+        );
+    }
+    println!(
+        "{} file(s) would be converted, {} skipped",
+        plan.jobs.len(),
+        plan.skipped.len()
+    );
+}
+
+/// Converts one job, returning the earlier output it duplicates when
+/// `dedupe_outputs` stored it as a duplicate.
+fn convert_job(
+    job: &Job,
+    settings: &BatchSettings,
+    written: &Written,
+    reservation: Reservation,
+) -> Result<Option<Duplicate>, ConversionError> {
+    check_size(job, settings)?;
+
+    if let Some(parent) = job.output.parent() {
+        io_guard::create_dir_all(parent)?;
+    }
+
+    if settings.create_backup && job.output.exists() {
+        io_guard::copy(&job.output, &job.output.with_extension("bak"))?;
+    }
+
+    io_guard::check_write(&job.output)?;
+    settings.cancel.check()?;
+    let reservation = Arc::new(reservation);
+    let text = if let Some(timeout) = settings.timeout {
+        convert_text_within(job, settings, timeout, reservation.clone())?
+    } else {
+        convert_text(
+            job,
+            &settings.pipeline,
+            settings.includes(),
+            settings.lenient,
+        )?
+    };
+    let text = finish(job, text, settings)?;
+    settings.cancel.check()?;
+
+    let duplicate = match settings.dedupe_outputs {
+        Some(mode) => written.store(&text, &job.output, mode)?,
+        None => {
+            io_guard::replace(&job.output, text)?;
+            None
+        }
+    };
+    if !matches!(
+        (&duplicate, settings.dedupe_outputs),
+        (Some(_), Some(DedupeMode::Record))
+    ) {
+        verify_job(job, settings)?;
+    }
+    Ok(duplicate)
+}
+
+fn verify_job(job: &Job, settings: &BatchSettings) -> Result<(), ConversionError> {
+    if settings.verify {
+        verify_output(&job.output)?;
+    }
+    Ok(())
+}
+
+/// The outputs written so far, by the SHA-256 of their content.
+#[derive(Default)]
+struct Written(Mutex<HashMap<String, PathBuf>>);
+
+impl Written {
+    /// Writes `content` to `output`, or, when an earlier output has the same
+    /// content, links or skips it as `mode` says and returns the duplicate.
+    /// Two identical outputs finishing at once may both be written.
+    fn store(
+        &self,
+        content: &[u8],
+        output: &Path,
+        mode: DedupeMode,
+    ) -> Result<Option<Duplicate>, ConversionError> {
+        let digest: String = Sha256::digest(content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let original = self.0.lock().unwrap().get(&digest).cloned();
+        let Some(original) = original.filter(|o| o != output) else {
+            io_guard::replace(output, content)?;
+            self.0
+                .lock()
+                .unwrap()
+                .entry(digest)
+                .or_insert_with(|| output.to_path_buf());
+            return Ok(None);
+        };
+        match mode {
+            DedupeMode::Link => {
+                if let Err(e) = io_guard::hard_link(&original, output) {
+                    debug!("{}; writing a copy instead", e);
+                    io_guard::replace(output, content)?;
+                    return Ok(None);
+                }
+            }
+            // A stale output from an earlier run would read as current.
+            DedupeMode::Record => io_guard::remove(output)?,
+        }
+        Ok(Some(Duplicate {
+            output: output.to_path_buf(),
+            original,
+        }))
+    }
+}
+
+/// Writes `duplicate-outputs.csv` to `output_dir`, listing each duplicate
+/// and the output it is identical to, relative to `output_dir`.
+pub fn write_duplicates(
+    output_dir: &Path,
+    duplicates: &[Duplicate],
+) -> Result<(), ConversionError> {
+    let relative = |path: &Path| {
+        path.strip_prefix(output_dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    };
+    let mut csv = String::from("output,original\n");
+    for duplicate in duplicates {
+        csv.push_str(&format!(
+            "{},{}\n",
+            relative(&duplicate.output),
+            relative(&duplicate.original)
+        ));
+    }
+    let path = output_dir.join(DUPLICATES_FILE);
+    io_guard::write(&path, csv)?;
+    info!(
+        "Listed {} duplicate output(s) in {}",
+        duplicates.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Checks that a written output converts back, i.e. that it is well-formed.
+fn verify_output(output: &Path) -> Result<(), ConversionError> {
+    let bytes = std::fs::read(output).map_err(|e| ConversionError::io_error(output, e))?;
+    // Written in the --output-encoding, which may not be UTF-8.
+    let text = encoding::decode(&bytes)
+        .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", output.display(), e)))?;
+    let format = Format::from_extension(output).unwrap_or(Format::Json);
+    convert::convert_str(&text, format)
+        .map(|_| ())
+        .map_err(|e| {
+            ConversionError::ValidationError(format!(
+                "Verification of {} failed: {}",
+                output.display(),
+                e
+            ))
+        })
+}
+
+/// Converts a job in memory, producing what `run` would write to
+/// `job.output`.
+pub fn render(job: &Job, settings: &BatchSettings) -> Result<Vec<u8>, ConversionError> {
+    check_size(job, settings)?;
+    let text = convert_text(
+        job,
+        &settings.pipeline,
+        settings.includes(),
+        settings.lenient,
+    )?;
+    finish(job, text, settings)
+}
+
+/// Applies the output options of `settings` to the converted `text`.
+fn finish(job: &Job, text: String, settings: &BatchSettings) -> Result<Vec<u8>, ConversionError> {
+    let format = Format::from_extension(&job.input)
+        .unwrap_or(Format::Xml)
+        .target();
+    let text = match &settings.pretty {
+        _ if settings.minify => minify::minify(&text, format)?,
+        Some(style) => pretty::format(&text, format, style)?,
+        None => text,
+    };
+    Ok(match settings.output_encoding {
+        Some(encoding) => encoding::encode(&text, encoding),
+        None => text.into_bytes(),
+    })
+}
+
+fn check_size(job: &Job, settings: &BatchSettings) -> Result<(), ConversionError> {
+    let size = std::fs::metadata(&job.input)
+        .map_err(|e| ConversionError::io_error(&job.input, e))?
+        .len();
+    if size > settings.max_file_size {
+        return Err(ConversionError::InvalidFile(format!(
+            "File exceeds maximum size of {} bytes: {}",
+            settings.max_file_size,
+            job.input.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Runs `convert_text` on its own thread and gives up after `timeout`.
+///
+/// A thread cannot be stopped from outside, so a conversion that times out
+/// keeps running in the background until it finishes, but its result is
+/// dropped: only the caller writes the output. The thread holds the job's
+/// `reservation` until then, so abandoned conversions still count against
+/// `--max-memory-mb`.
+fn convert_text_within(
+    job: &Job,
+    settings: &BatchSettings,
+    timeout: Duration,
+    reservation: Arc<Reservation>,
+) -> Result<String, ConversionError> {
+    let (sender, receiver) = mpsc::channel();
+    let (job, pipeline) = (job.clone(), settings.pipeline.clone());
+    let (includes, lenient) = (settings.includes(), settings.lenient);
+    std::thread::spawn(move || {
+        let _reservation = reservation;
+        let _ = sender.send(convert_text(&job, &pipeline, includes, lenient));
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(ConversionError::Other(format!(
+            "Conversion did not finish within {} second(s)",
+            timeout.as_secs()
+        ))),
+        Err(RecvTimeoutError::Disconnected) => Err(ConversionError::Other(
+            "Conversion thread stopped without a result".to_string(),
+        )),
+    }
+}
+
+/// Reads, preprocesses and converts one input; `<?include?>` directives
+/// are expanded within `includes`, and with `lenient` XML is repaired
+/// first.
+fn convert_text(
+    job: &Job,
+    pipeline: &Pipeline,
+    includes: include::Limits,
+    lenient: bool,
+) -> Result<String, ConversionError> {
+    let format = Format::from_extension(&job.input).unwrap_or(Format::Xml);
+    let bytes = std::fs::read(&job.input).map_err(|e| ConversionError::io_error(&job.input, e))?;
+    let text = encoding::decode(&bytes)
+        .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", job.input.display(), e)))?;
+    let repaired = lenient && format == Format::Xml;
+    let text = if repaired {
+        repair::repair_file(&job.input, &text)
+    } else {
+        text
+    };
+    let text = if format == Format::Xml && (repaired || include::has_includes(&text)) {
+        // The library step reads from disk, which would miss the includes
+        // and repairs.
+        let expanded = include::expand(&job.input, &text, includes)?;
+        if pipeline.runs_library() {
+            convert::preprocess_str(&expanded, format)?
+        } else {
+            expanded
+        }
+    } else if pipeline.runs_library() {
+        preprocess_config(&job.input).map_err(|e| preprocess::library_error(&job.input, e))?
+    } else {
+        text
+    };
+    let text = pipeline.apply(&text, format)?;
+    convert::convert_str(&text, format)
+}
+
+fn describe_collision(output: &Path, inputs: &[PathBuf]) -> String {
+    let names: Vec<String> = inputs.iter().map(|p| p.display().to_string()).collect();
+    format!(
+        "Output collision at {}: {}",
+        output.display(),
+        names.join(", ")
+    )
+}
+
+pub fn is_convertible(path: &Path) -> bool {
+    output_extension(path).is_some()
+}
+
+/// Returns the extension a converted file gets, or `None` if the file is not
+/// a format this tool converts.
+fn output_extension(path: &Path) -> Option<&'static str> {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("xml") => Some("json"),
+        Some("json") => Some("xml"),
+        _ => None,
+    }
+}
+
+fn default_output(input_dir: &Path, output_dir: &Path, input: &Path, flatten: bool) -> PathBuf {
+    let relative = if flatten {
+        PathBuf::from(input.file_name().unwrap_or_default())
+    } else {
+        input.strip_prefix(input_dir).unwrap_or(input).to_path_buf()
+    };
+    let mut output = output_dir.join(relative);
+    if let Some(ext) = output_extension(input) {
+        output.set_extension(ext);
+    }
+    output
+}
+
+fn hash_suffix(relative: &Path) -> String {
+    let digest = Sha256::digest(relative.to_string_lossy().replace('\\', "/").as_bytes());
+    digest[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn dir_suffix(relative: &Path) -> String {
+    let parents: Vec<String> = relative
+        .parent()
+        .map(|p| {
+            p.components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    if parents.is_empty() {
+        "root".to_string()
+    } else {
+        parents.join("_")
+    }
+}
+
+/// `dir/name.ext` -> `dir/name-suffix.ext`; only the last extension moves,
+/// so `sysmon.v2.json` becomes `sysmon.v2-suffix.json`.
+fn with_stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// Matches `--ignore` patterns against a relative path. `*` matches any run
+/// of characters and `?` a single one; patterns without a separator are
+/// matched against the file name alone.
+pub fn is_ignored(pattern: &str, relative: &Path) -> bool {
+    let text = relative.to_string_lossy().replace('\\', "/");
+    if pattern.contains('/') {
+        return wildcard_match(pattern, &text);
+    }
+    relative
+        .file_name()
+        .map(|name| wildcard_match(pattern, &name.to_string_lossy()))
+        .unwrap_or(false)
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(flatten: bool, on_collision: CollisionPolicy) -> BatchSettings {
+        BatchSettings {
+            recursive: true,
+            max_depth: 10,
+            allow_outside_includes: false,
+            max_file_size: 10 * 1024 * 1024,
+            min_size: None,
+            max_size_skip: None,
+            workers: Some(1),
+            create_backup: false,
+            ignore_patterns: Vec::new(),
+            flatten,
+            on_collision,
+            pipeline: Pipeline::default(),
+            lenient: false,
+            output_encoding: None,
+            verify: false,
+            max_memory: None,
+            timeout: None,
+            follow_symlinks: false,
+            output_template: None,
+            max_errors: None,
+            modified_since: None,
+            minify: false,
+            pretty: None,
+            dedupe_outputs: None,
+            schedule: Schedule::InputOrder,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    #[test]
+    fn test_memory_budget_rejects_oversized_jobs() {
+        let budget = Arc::new(MemoryBudget::new(Some(100)));
+        assert!(budget.reserve(101).is_err());
+
+        let first = budget.reserve(60).unwrap();
+        drop(first);
+        let second = budget.reserve(100).unwrap();
+        assert_eq!(*budget.used.lock().unwrap(), 100);
+        drop(second);
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_timeout_abandons_slow_conversions() {
+        struct Slow;
+        impl preprocess::Preprocessor for Slow {
+            fn name(&self) -> &str {
+                "slow"
+            }
+            fn description(&self) -> &str {
+                "sleeps"
+            }
+            fn process(&self, text: &str, _format: Format) -> Result<String, ConversionError> {
+                std::thread::sleep(Duration::from_millis(500));
+                Ok(text.to_string())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("slow.xml");
+        std::fs::write(&input, "<Sysmon/>").unwrap();
+        let job = Job {
+            output: dir.path().join("slow.json"),
+            input,
+        };
+        let mut settings = settings(false, CollisionPolicy::Error);
+        settings.pipeline.register(Arc::new(Slow));
+        let budget = Arc::new(MemoryBudget::new(Some(100)));
+        let reservation = Arc::new(budget.reserve(60).unwrap());
+
+        let started = Instant::now();
+        let error = convert_text_within(&job, &settings, Duration::from_millis(50), reservation)
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert!(error.to_string().contains("did not finish"));
+
+        // The abandoned conversion keeps its memory until it ends.
+        assert_eq!(*budget.used.lock().unwrap(), 60);
+        drop(budget.reserve(100).unwrap());
+        assert!(started.elapsed() >= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_plan_skips_inputs_outside_the_size_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = [("empty.xml", 0), ("rules.xml", 100), ("huge.xml", 5000)]
+            .iter()
+            .map(|(name, size)| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, " ".repeat(*size)).unwrap();
+                path
+            })
+            .collect();
+        let mut settings = settings(false, CollisionPolicy::Error);
+        settings.min_size = Some("1".parse::<ByteSize>().unwrap().0);
+        settings.max_size_skip = Some("4k".parse::<ByteSize>().unwrap().0);
+
+        let plan = plan(
+            dir.path(),
+            &dir.path().join("out"),
+            files.clone(),
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(plan.jobs.len(), 1);
+        assert_eq!(plan.jobs[0].input, files[1]);
+        assert_eq!(plan.skipped, [files[0].clone(), files[2].clone()]);
+
+        assert_eq!("5 MB".parse(), Ok(ByteSize(5 << 20)));
+        assert!("5X".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn test_max_errors_stops_starting_files() {
+        struct Fail;
+        impl preprocess::Preprocessor for Fail {
+            fn name(&self) -> &str {
+                "fail"
+            }
+            fn description(&self) -> &str {
+                "always fails"
+            }
+            fn process(&self, _text: &str, _format: Format) -> Result<String, ConversionError> {
+                Err(ConversionError::Other("broken".to_string()))
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let jobs: Vec<Job> = (1..=5)
+            .map(|n| {
+                let input = dir.path().join(format!("{}.xml", n));
+                std::fs::write(&input, "<Sysmon/>").unwrap();
+                Job {
+                    output: input.with_extension("json"),
+                    input,
+                }
+            })
+            .collect();
+        let mut settings = settings(false, CollisionPolicy::Error);
+        settings.pipeline.register(std::sync::Arc::new(Fail));
+        settings.max_errors = Some(2);
+
+        let plan = Plan {
+            jobs: jobs.clone(),
+            ..Plan::default()
+        };
+        let report = run(plan, &settings, &sysmon_cli::progress::Silent);
+        assert_eq!(report.errors, 2);
+        assert_eq!(
+            report.not_started,
+            jobs[2..]
+                .iter()
+                .map(|j| j.input.clone())
+                .collect::<Vec<_>>()
+        );
+
+        settings.max_errors = None;
+        settings.workers = Some(4);
+        let plan = Plan {
+            jobs: jobs.clone(),
+            ..Plan::default()
+        };
+        let report = run(plan, &settings, &sysmon_cli::progress::Silent);
+        assert_eq!((report.errors, report.not_started.len()), (5, 0));
+        // Sorted, whichever worker finished first.
+        assert_eq!(
+            report.failures,
+            jobs.iter().map(|j| j.input.clone()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_resume_after_max_errors_converts_the_rest() {
+        struct Reject;
+        impl preprocess::Preprocessor for Reject {
+            fn name(&self) -> &str {
+                "reject"
+            }
+            fn description(&self) -> &str {
+                "fails on broken inputs"
+            }
+            fn process(&self, text: &str, _format: Format) -> Result<String, ConversionError> {
+                if text.contains("broken") {
+                    return Err(ConversionError::Other("broken".to_string()));
+                }
+                Ok(text.to_string())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let (input_dir, output_dir) = (dir.path().join("in"), dir.path().join("out"));
+        std::fs::create_dir_all(&input_dir).unwrap();
+        let jobs: Vec<Job> = (1..=4)
+            .map(|n| {
+                let input = input_dir.join(format!("{}.xml", n));
+                std::fs::write(&input, "<Sysmon/>").unwrap();
+                Job {
+                    output: output_dir.join(format!("{}.json", n)),
+                    input,
+                }
+            })
+            .collect();
+        std::fs::write(&jobs[1].input, "<!-- broken --><Sysmon/>").unwrap();
+        let mut settings = settings(false, CollisionPolicy::Error);
+        settings.pipeline.register(std::sync::Arc::new(Reject));
+        settings.max_errors = Some(1);
+        let checkpoint_path = output_dir.join(crate::checkpoint::CHECKPOINT_FILE);
+        let run_with_checkpoint = |plan: Plan, resume: bool| {
+            let silent = sysmon_cli::progress::Silent;
+            let checkpoint = crate::checkpoint::Checkpoint::open(
+                &output_dir,
+                &input_dir,
+                resume,
+                &settings.cancel,
+                &silent,
+            )
+            .unwrap();
+            run(plan, &settings, &checkpoint)
+        };
+
+        let plan = Plan {
+            jobs: jobs.clone(),
+            ..Plan::default()
+        };
+        let report = run_with_checkpoint(plan, false);
+        assert_eq!((report.processed, report.errors), (1, 1));
+        assert_eq!(report.not_started.len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(&checkpoint_path).unwrap(),
+            "1.xml\n"
+        );
+
+        std::fs::write(&jobs[1].input, "<Sysmon/>").unwrap();
+        let mut plan = Plan {
+            jobs: jobs.clone(),
+            ..Plan::default()
+        };
+        crate::checkpoint::skip_completed(&mut plan, &input_dir, &output_dir);
+        assert_eq!(plan.skipped, [jobs[0].input.clone()]);
+        let report = run_with_checkpoint(plan, true);
+        assert_eq!((report.processed, report.errors, report.skipped), (3, 0, 1));
+        assert!(!checkpoint_path.exists());
+    }
+
+    #[test]
+    fn test_cancel_stops_the_run() {
+        // Stands in for a Ctrl-C arriving while the first file converts.
+        struct Interrupt(CancellationToken);
+        impl preprocess::Preprocessor for Interrupt {
+            fn name(&self) -> &str {
+                "interrupt"
+            }
+            fn description(&self) -> &str {
+                "cancels the run"
+            }
+            fn process(&self, _text: &str, _format: Format) -> Result<String, ConversionError> {
+                self.0.cancel();
+                self.0.check().map(|_| String::new())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let jobs: Vec<Job> = (1..=3)
+            .map(|n| {
+                let input = dir.path().join(format!("{}.xml", n));
+                std::fs::write(&input, "<Sysmon/>").unwrap();
+                Job {
+                    output: input.with_extension("json"),
+                    input,
+                }
+            })
+            .collect();
+        // An output from an earlier run is left as it was.
+        std::fs::write(&jobs[0].output, "{}").unwrap();
+        let mut settings = settings(false, CollisionPolicy::Error);
+        settings.workers = Some(1);
+        let interrupt = Interrupt(settings.cancel.clone());
+        settings.pipeline.register(std::sync::Arc::new(interrupt));
+
+        let plan = Plan {
+            jobs: jobs.clone(),
+            ..Plan::default()
+        };
+        let report = run(plan, &settings, &sysmon_cli::progress::Silent);
+        assert_eq!((report.processed, report.errors), (0, 0));
+        assert_eq!(
+            report.cancelled,
+            jobs.iter().map(|j| j.input.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(std::fs::read_to_string(&jobs[0].output).unwrap(), "{}");
+        assert!(!jobs[1].output.exists());
+    }
+
+    #[test]
+    fn test_listed_inputs_stay_inside_input_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_dir = dir.path().join("configs");
+        std::fs::create_dir_all(&input_dir).unwrap();
+        std::fs::write(input_dir.join("a.xml"), "<Sysmon/>").unwrap();
+        std::fs::write(input_dir.join("notes.md"), "").unwrap();
+        let settings = settings(false, CollisionPolicy::Error);
+
+        std::fs::write(dir.path().join("b.xml"), "<Sysmon/>").unwrap();
+        let listed = vec![
+            input_dir.join("notes.md"),
+            input_dir.join("deleted.xml"),
+            dir.path().join("b.xml"),
+            input_dir.join("a.xml"),
+        ];
+        assert_eq!(
+            listed_inputs(&input_dir, listed, &settings),
+            vec![input_dir.join("a.xml")]
+        );
+    }
+
+    fn inputs() -> Vec<PathBuf> {
+        vec![
+            PathBuf::from("in/1_process/include.xml"),
+            PathBuf::from("in/3_network/include.xml"),
+            PathBuf::from("in/unique.xml"),
+        ]
+    }
+
+    #[test]
+    fn test_with_stem_suffix_keeps_dotted_stems() {
+        assert_eq!(
+            with_stem_suffix(Path::new("out/sysmon.v2.xml"), "1_process"),
+            Path::new("out/sysmon.v2-1_process.xml")
+        );
+        assert_eq!(
+            with_stem_suffix(Path::new("out/rules"), "a.b"),
+            Path::new("out/rules-a.b")
+        );
+    }
+
+    #[test]
+    fn test_flatten_collision_error_lists_all_inputs() {
+        let result = plan(
+            Path::new("in"),
+            Path::new("out"),
+            inputs(),
+            &settings(true, CollisionPolicy::Error),
+        );
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("in/1_process/include.xml"));
+        assert!(message.contains("in/3_network/include.xml"));
+    }
+
+    #[test]
+    fn test_flatten_suffix_dir() {
+        let plan = plan(
+            Path::new("in"),
+            Path::new("out"),
+            inputs(),
+            &settings(true, CollisionPolicy::SuffixDir),
+        )
+        .unwrap();
+        let outputs: Vec<PathBuf> = plan.jobs.iter().map(|j| j.output.clone()).collect();
+        assert!(outputs.contains(&PathBuf::from("out/include-1_process.json")));
+        assert!(outputs.contains(&PathBuf::from("out/include-3_network.json")));
+        assert!(outputs.contains(&PathBuf::from("out/unique.json")));
+        assert_eq!(plan.collisions.len(), 1);
+    }
+
+    #[test]
+    fn test_flatten_skip_keeps_first() {
+        let plan = plan(
+            Path::new("in"),
+            Path::new("out"),
+            inputs(),
+            &settings(true, CollisionPolicy::Skip),
+        )
+        .unwrap();
+        assert_eq!(plan.jobs.len(), 2);
+        assert_eq!(
+            plan.skipped,
+            vec![PathBuf::from("in/3_network/include.xml")]
+        );
+    }
+
+    #[test]
+    fn test_flatten_suffix_hash_is_unique() {
+        let plan = plan(
+            Path::new("in"),
+            Path::new("out"),
+            inputs(),
+            &settings(true, CollisionPolicy::SuffixHash),
+        )
+        .unwrap();
+        assert_eq!(plan.jobs.len(), 3);
+        assert_ne!(plan.jobs[0].output, plan.jobs[1].output);
+    }
+
+    #[test]
+    fn test_dedupe_links_or_records_identical_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let written = Written::default();
+        assert_eq!(
+            written
+                .store(b"{}", &path("a.json"), DedupeMode::Link)
+                .unwrap(),
+            None
+        );
+        let linked = written
+            .store(b"{}", &path("b.json"), DedupeMode::Link)
+            .unwrap();
+        assert_eq!(
+            linked,
+            Some(Duplicate {
+                output: path("b.json"),
+                original: path("a.json"),
+            })
+        );
+        assert_eq!(std::fs::read_to_string(path("b.json")).unwrap(), "{}");
+        assert_eq!(
+            written
+                .store(b"[]", &path("c.json"), DedupeMode::Record)
+                .unwrap(),
+            None
+        );
+
+        std::fs::write(path("d.json"), "stale").unwrap();
+        let recorded = written
+            .store(b"{}", &path("d.json"), DedupeMode::Record)
+            .unwrap()
+            .unwrap();
+        assert!(!path("d.json").exists());
+        write_duplicates(dir.path(), &[recorded]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path(DUPLICATES_FILE)).unwrap(),
+            "output,original\nd.json,a.json\n"
+        );
+    }
+
+    #[test]
+    fn test_start_order_follows_schedule() {
+        let jobs: Vec<Job> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| Job {
+                input: PathBuf::from(format!("{}.xml", name)),
+                output: PathBuf::from(format!("{}.json", name)),
+            })
+            .collect();
+        let sizes = [10, 400, 100, 400];
+        let mut plan = Plan {
+            jobs,
+            ..Plan::default()
+        };
+        assert_eq!(
+            start_order(&plan, &sizes, Schedule::InputOrder),
+            [0, 1, 2, 3]
+        );
+        assert_eq!(
+            start_order(&plan, &sizes, Schedule::LargestFirst),
+            [1, 3, 2, 0]
+        );
+        // Nothing recorded: by size.
+        assert_eq!(
+            start_order(&plan, &sizes, Schedule::SlowestFirst),
+            [1, 3, 2, 0]
+        );
+        // a was timed slower than c despite being smaller; b and d are
+        // expected to take as long per byte as a and c did together.
+        plan.history
+            .insert(PathBuf::from("a.xml"), Duration::from_secs(50));
+        plan.history
+            .insert(PathBuf::from("c.xml"), Duration::from_secs(1));
+        assert_eq!(
+            start_order(&plan, &sizes, Schedule::SlowestFirst),
+            [1, 3, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.bak.xml", "config.bak.xml"));
+        assert!(wildcard_match("include_?.xml", "include_a.xml"));
+        assert!(!wildcard_match("*.json", "config.xml"));
+        assert!(is_ignored("templates/*", Path::new("templates/base.xml")));
+    }
+}
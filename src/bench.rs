@@ -0,0 +1,280 @@
+//! `bench`: time conversion or merging over a fixture set.
+//!
+//! Every file under the fixture directory is converted in memory (or the
+//! whole set merged) `--iterations` times, so results are not skewed by
+//! disk reads or writes: fixtures are read once up front, and for a merge
+//! parsed once too, so only folding them together and serializing the
+//! result is timed. The report gives throughput over all iterations, the 50th
+//! and 95th percentile latency of one file's conversion (or one merge), and
+//! the process's peak resident memory where the platform reports it (Linux
+//! only). Run the same fixtures with two releases to compare them.
+
+use crate::{convert, encoding, include, io_guard, merge, walk};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use sysmon_cli::format::{self, Format};
+use sysmon_cli::model::SysmonConfig;
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BenchMode {
+    /// Convert each file to the other format
+    Convert,
+    /// Merge the XML files into one config
+    Merge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BenchFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Directory of fixture configs
+    pub fixtures: PathBuf,
+
+    /// What to time
+    #[arg(long, value_enum, default_value = "convert")]
+    pub mode: BenchMode,
+
+    /// Number of passes over the fixtures
+    #[arg(short = 'n', long, default_value = "5")]
+    pub iterations: usize,
+
+    /// Include fixtures in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: BenchFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub mode: BenchMode,
+    pub iterations: usize,
+    /// Fixture files per iteration.
+    pub files: usize,
+    /// Fixture bytes per iteration.
+    pub bytes: u64,
+    pub elapsed_secs: f64,
+    pub files_per_sec: f64,
+    pub mb_per_sec: f64,
+    /// Latency of one file's conversion, or of one merge.
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    /// Peak resident set size, where the platform reports it.
+    pub peak_rss_bytes: Option<u64>,
+}
+
+pub fn run(args: &BenchArgs) -> Result<(), ConversionError> {
+    if args.iterations == 0 {
+        return Err(ConversionError::ValidationError(
+            "--iterations must be at least 1".to_string(),
+        ));
+    }
+    let report = bench(&args.fixtures, args.mode, args.iterations, args.recursive)?;
+
+    let text = match args.format {
+        BenchFormat::Text => render(&report),
+        BenchFormat::Json => serde_json::to_string_pretty(&report)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+/// Runs `mode` over the fixtures in `dir` `iterations` times.
+pub fn bench(
+    dir: &Path,
+    mode: BenchMode,
+    iterations: usize,
+    recursive: bool,
+) -> Result<BenchReport, ConversionError> {
+    let max_depth = if recursive { usize::MAX } else { 1 };
+    let fixtures: Vec<PathBuf> = match mode {
+        BenchMode::Convert => walk::files(dir, max_depth, false)
+            .into_iter()
+            .filter(|p| Format::from_extension(p).is_some())
+            .collect(),
+        BenchMode::Merge => merge::sources(dir, recursive, false, Path::new("")),
+    };
+    if fixtures.is_empty() {
+        return Err(ConversionError::InvalidFile(format!(
+            "No fixtures found in {}",
+            dir.display()
+        )));
+    }
+
+    // Read everything up front so only conversion or merging is timed.
+    let mut inputs = Vec::with_capacity(fixtures.len());
+    for path in &fixtures {
+        let bytes = std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+        let from = format::detect(path, &bytes)?;
+        let text = encoding::decode(&bytes)
+            .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", path.display(), e)))?;
+        inputs.push((bytes.len() as u64, from, text));
+    }
+    let bytes: u64 = inputs.iter().map(|(size, _, _)| size).sum();
+    let mut parsed = Vec::new();
+    if mode == BenchMode::Merge {
+        for (path, (_, _, text)) in fixtures.iter().zip(&inputs) {
+            let text = include::expand(path, text, include::Limits::new(10))?;
+            let config = SysmonConfig::from_xml_str(&text)
+                .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))?;
+            parsed.push((path.display().to_string(), config));
+        }
+    }
+
+    let mut latencies = Vec::new();
+    let started = Instant::now();
+    for _ in 0..iterations {
+        match mode {
+            BenchMode::Convert => {
+                for (_, from, text) in &inputs {
+                    let timer = Instant::now();
+                    convert::convert_str(text, *from)?;
+                    latencies.push(timer.elapsed());
+                }
+            }
+            BenchMode::Merge => {
+                let configs = parsed.clone();
+                let timer = Instant::now();
+                let mut resolver = merge::Resolver::new(None);
+                merge::merge(configs, &mut resolver)?.to_xml_string()?;
+                latencies.push(timer.elapsed());
+            }
+        }
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    latencies.sort();
+
+    let per_sec = |amount: f64| if elapsed > 0.0 { amount / elapsed } else { 0.0 };
+    Ok(BenchReport {
+        mode,
+        iterations,
+        files: fixtures.len(),
+        bytes,
+        elapsed_secs: elapsed,
+        files_per_sec: per_sec((fixtures.len() * iterations) as f64),
+        mb_per_sec: per_sec((bytes * iterations as u64) as f64 / 1_000_000.0),
+        p50_ms: millis(percentile(&latencies, 50)),
+        p95_ms: millis(percentile(&latencies, 95)),
+        peak_rss_bytes: peak_rss(),
+    })
+}
+
+/// The nearest-rank `p`th percentile of sorted `values`.
+fn percentile(values: &[Duration], p: usize) -> Duration {
+    if values.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * values.len()).div_ceil(100).max(1);
+    values[rank - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Peak resident set size of this process: `VmHWM` in `/proc/self/status`.
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+fn render(report: &BenchReport) -> String {
+    let mut out = String::new();
+    let mode = match report.mode {
+        BenchMode::Convert => "convert",
+        BenchMode::Merge => "merge",
+    };
+    let _ = writeln!(
+        out,
+        "{}: {} file(s), {:.2} MB, {} iteration(s) in {:.2}s",
+        mode,
+        report.files,
+        report.bytes as f64 / 1_000_000.0,
+        report.iterations,
+        report.elapsed_secs
+    );
+    let _ = writeln!(
+        out,
+        "throughput: {:.1} files/s, {:.2} MB/s",
+        report.files_per_sec, report.mb_per_sec
+    );
+    let unit = match report.mode {
+        BenchMode::Convert => "file",
+        BenchMode::Merge => "merge",
+    };
+    let _ = writeln!(
+        out,
+        "latency per {}: p50 {:.2} ms, p95 {:.2} ms",
+        unit, report.p50_ms, report.p95_ms
+    );
+    let _ = match report.peak_rss_bytes {
+        Some(bytes) => writeln!(out, "peak RSS: {:.1} MB", bytes as f64 / 1_000_000.0),
+        None => writeln!(out, "peak RSS: not available on this platform"),
+    };
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_uses_nearest_rank() {
+        let values: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&values, 50), Duration::from_millis(10));
+        assert_eq!(percentile(&values, 95), Duration::from_millis(19));
+        assert_eq!(percentile(&values[..1], 95), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_bench_merge_reports_each_iteration() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, event) in [("1.xml", "ProcessCreate"), ("2.xml", "NetworkConnect")] {
+            std::fs::write(
+                dir.path().join(name),
+                format!(
+                    r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or"><{0} onmatch="include"><Image condition="is">a.exe</Image></{0}></RuleGroup></EventFiltering></Sysmon>"#,
+                    event
+                ),
+            )
+            .unwrap();
+        }
+
+        let report = bench(dir.path(), BenchMode::Merge, 3, false).unwrap();
+        assert_eq!((report.files, report.iterations), (2, 3));
+        assert!(report.p50_ms <= report.p95_ms);
+        assert!(render(&report).starts_with("merge: 2 file(s)"));
+        assert!(bench(&dir.path().join("missing"), BenchMode::Merge, 1, false).is_err());
+    }
+}
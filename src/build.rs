@@ -0,0 +1,949 @@
+//! `build`: every config a build matrix declares, in one invocation.
+//!
+//! The matrix is a YAML file naming the profiles to merge, the platforms
+//! to build them for and the sites whose `${VAR}` values (see
+//! [`crate::vars`]) they are built with:
+//!
+//! ```yaml
+//! output: dist
+//! template: baseconfig.xml
+//! profiles:
+//!   workstation:
+//!     inputs: [rules/common, rules/workstation]
+//!   server:
+//!     inputs: [rules/common, rules/server]
+//!     suppress: suppress/server.txt
+//! platforms: [windows, linux]
+//! sites:
+//!   emea:
+//!   amer:
+//!     CORP_DOMAIN: amer.example.com
+//! exclude:
+//!   - profile: workstation
+//!     platform: linux
+//! ```
+//!
+//! Every combination not excluded is merged the way the top-level merge
+//! does it: each input a layer, then the template (a profile's own or the
+//! shared one), the platform's event types and the suppressions. Paths are
+//! relative to the matrix file. Outputs are named `{profile}-{platform}.xml`,
+//! with `-{site}` when sites are declared, unless `name` gives another
+//! pattern. `strategy` settles merge conflicts as `--strategy` does, and
+//! `recursive: false` merges only the files directly in each input.
+//!
+//! Each output is validated, and nothing is written unless all of them
+//! build without errors. Then the outputs and `build-manifest.json` are
+//! written to the output directory. The manifest records the provenance
+//! of each output (see [`crate::provenance`]): its SHA-256, the hashes of
+//! the sources, template and suppressions it was built from, and the
+//! site's variables.
+//!
+//! `verify-build` re-runs the build a manifest records, from the matrix it
+//! was written for, and checks every output hashes as recorded, naming the
+//! sources that changed when one does not.
+
+use crate::merge::{self, Resolution};
+use crate::provenance::{self, FileHash, Provenance};
+use crate::yaml::{self, Yaml};
+use crate::{include, io_guard, suppress, user_config, vars};
+use clap::Args;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use sysmon_cli::schema::Platform;
+use sysmon_cli::validation::{self, Severity};
+use sysmon_json::error::ConversionError;
+
+/// The manifest's file name, in the output directory.
+pub const MANIFEST: &str = "build-manifest.json";
+
+#[derive(Args)]
+pub struct BuildArgs {
+    /// Build matrix (YAML)
+    pub matrix: PathBuf,
+
+    /// Directory to write the outputs to [default: the matrix's `output`]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Maximum recursion depth, for directories and for nested <?include?> directives
+    #[arg(long, default_value = "10")]
+    pub max_depth: u32,
+
+    /// Let <?include?> directives name files outside the including source's directory
+    #[arg(long)]
+    pub allow_outside_includes: bool,
+
+    /// Worker threads for parsing sources [default: one per CPU]
+    #[arg(long)]
+    pub workers: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct VerifyBuildArgs {
+    /// Build manifest written by `build`
+    #[arg(long)]
+    pub manifest: PathBuf,
+
+    /// Build matrix to re-run [default: the path the manifest records]
+    #[arg(long)]
+    pub matrix: Option<PathBuf>,
+
+    /// Deployed config that must be one of the verified outputs
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Worker threads for parsing sources [default: one per CPU]
+    #[arg(long)]
+    pub workers: Option<usize>,
+}
+
+/// A build matrix, with paths resolved against the matrix file.
+#[derive(Debug, PartialEq)]
+pub struct Matrix {
+    pub output: PathBuf,
+    pub name: String,
+    pub template: Option<PathBuf>,
+    pub strategy: Option<Resolution>,
+    pub recursive: bool,
+    pub profiles: Vec<Profile>,
+    pub platforms: Vec<Platform>,
+    /// Site names and their variables; empty when no sites are declared.
+    pub sites: Vec<(String, Vec<String>)>,
+    /// Partial combinations to leave out, as `(key, value)` pairs.
+    pub exclude: Vec<Vec<(String, String)>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub inputs: Vec<PathBuf>,
+    pub template: Option<PathBuf>,
+    pub suppress: Option<PathBuf>,
+}
+
+/// One output of the matrix.
+#[derive(Debug, PartialEq)]
+pub struct Target<'a> {
+    pub profile: &'a Profile,
+    pub platform: Platform,
+    pub site: Option<&'a (String, Vec<String>)>,
+    pub file: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    #[serde(flatten)]
+    pub provenance: Provenance,
+    pub matrix: FileHash,
+    pub max_depth: u32,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_outside_includes: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<Resolution>,
+    pub outputs: Vec<Artifact>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Artifact {
+    pub file: String,
+    pub profile: String,
+    pub platform: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+    pub schema_version: String,
+    pub rules: usize,
+    pub bytes: usize,
+    pub sha256: String,
+    pub warnings: usize,
+    pub vars: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<FileHash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suppress: Option<FileHash>,
+    pub inputs: Vec<FileHash>,
+}
+
+pub fn run(args: &BuildArgs) -> Result<(), ConversionError> {
+    let text = std::fs::read_to_string(&args.matrix)
+        .map_err(|e| ConversionError::io_error(&args.matrix, e))?;
+    let base = args.matrix.parent().unwrap_or(Path::new(""));
+    let matrix = parse_matrix(&text, base)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", args.matrix.display(), e)))?;
+    let output = args.output.clone().unwrap_or_else(|| matrix.output.clone());
+    let targets = matrix.targets()?;
+    if targets.is_empty() {
+        return Err(ConversionError::ValidationError(format!(
+            "{} excludes every combination",
+            args.matrix.display()
+        )));
+    }
+
+    let mut built = Vec::new();
+    let mut failed = Vec::new();
+    for target in &targets {
+        let includes = include::Limits {
+            max_depth: args.max_depth as usize,
+            outside_root: args.allow_outside_includes,
+        };
+        match build(&matrix, target, &output, includes, args.workers) {
+            Ok(artifact) => built.push(artifact),
+            Err(e) => {
+                warn!("{}: {}", target.file, e);
+                failed.push(target.file.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        return Err(ConversionError::ValidationError(format!(
+            "{} of {} output(s) failed to build, so none were written: {}",
+            failed.len(),
+            targets.len(),
+            failed.join(", ")
+        )));
+    }
+
+    io_guard::create_dir_all(&output)?;
+    let mut outputs = Vec::new();
+    for (artifact, xml) in built {
+        io_guard::write(&output.join(&artifact.file), xml)?;
+        info!(
+            "Built {} ({} rule(s), sha256 {})",
+            artifact.file, artifact.rules, artifact.sha256
+        );
+        outputs.push(artifact);
+    }
+    let mut sources: Vec<&Path> = vec![args.matrix.as_path()];
+    sources.extend(matrix.template.as_deref());
+    for profile in &matrix.profiles {
+        sources.extend(profile.inputs.iter().map(PathBuf::as_path));
+        sources.extend(
+            profile
+                .template
+                .iter()
+                .chain(&profile.suppress)
+                .map(PathBuf::as_path),
+        );
+    }
+    let manifest = Manifest {
+        provenance: Provenance::new(&sources),
+        matrix: provenance::hash(&args.matrix, text.as_bytes()),
+        max_depth: args.max_depth,
+        allow_outside_includes: args.allow_outside_includes,
+        strategy: matrix.strategy,
+        outputs,
+    };
+    let path = output.join(MANIFEST);
+    provenance::write(&path, &manifest)?;
+    info!(
+        "Built {} output(s) into {}; manifest at {}",
+        manifest.outputs.len(),
+        output.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Merges and validates one output, returning its manifest entry and XML.
+fn build(
+    matrix: &Matrix,
+    target: &Target,
+    output: &Path,
+    includes: include::Limits,
+    workers: Option<usize>,
+) -> Result<(Artifact, String), ConversionError> {
+    let pairs = target.site.map_or(&[][..], |(_, pairs)| pairs);
+    let vars = vars::Vars::load(pairs, None)?;
+    let template = target
+        .profile
+        .template
+        .as_ref()
+        .or(matrix.template.as_ref());
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let excluded = template.map(|t| canonical(t));
+    let layers: Vec<Vec<PathBuf>> = target
+        .profile
+        .inputs
+        .iter()
+        .map(|input| {
+            let mut sources = merge::sources(input, matrix.recursive, false, output);
+            sources.retain(|s| excluded.as_ref().is_none_or(|t| canonical(s) != *t));
+            sources
+        })
+        .collect();
+    if layers.iter().all(Vec::is_empty) {
+        return Err(ConversionError::ValidationError(format!(
+            "profile {} has no XML sources",
+            target.profile.name
+        )));
+    }
+
+    let mut resolver = matrix
+        .strategy
+        .map_or(merge::Resolver::KeepBoth, merge::Resolver::Fixed);
+    let mut config = merge::merge_layers(
+        &layers,
+        workers,
+        includes,
+        &vars,
+        false,
+        false,
+        &mut resolver,
+    )?;
+    if let Some(template) = template {
+        config = merge::apply_template(merge::template(template, includes, &vars, false)?, config);
+    }
+    merge::retain_platform(&mut config, target.platform);
+    if let Some(path) = &target.profile.suppress {
+        let report = suppress::apply(&mut config, &suppress::load(path)?);
+        info!("{}: suppressed {} rule(s)", target.file, report.total());
+    }
+
+    let issues = validation::validate(&config);
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(ConversionError::ValidationError(format!(
+            "{} validation error(s); run `validate` on the merged config for details",
+            errors
+        )));
+    }
+
+    let xml = config.to_xml_string()?;
+    let optional = |path: Option<&PathBuf>| path.map(|p| provenance::hash_file(p)).transpose();
+    let artifact = Artifact {
+        file: target.file.clone(),
+        profile: target.profile.name.clone(),
+        platform: target.platform.to_string(),
+        site: target.site.map(|(name, _)| name.clone()),
+        schema_version: config.schema_version.clone(),
+        rules: config
+            .rule_groups
+            .iter()
+            .flat_map(|g| &g.events)
+            .map(|e| e.filters.len())
+            .sum(),
+        bytes: xml.len(),
+        sha256: provenance::sha256(xml.as_bytes()),
+        warnings: issues.len() - errors,
+        vars: vars.values(),
+        template: optional(template)?,
+        suppress: optional(target.profile.suppress.as_ref())?,
+        inputs: provenance::hash_inputs(&layers.concat(), includes)?,
+    };
+    Ok((artifact, xml))
+}
+
+/// Re-runs the build a manifest records and checks every output hashes
+/// the same, and, with `--config`, that the deployed config is one of them.
+pub fn run_verify(args: &VerifyBuildArgs) -> Result<(), ConversionError> {
+    let text = std::fs::read_to_string(&args.manifest)
+        .map_err(|e| ConversionError::io_error(&args.manifest, e))?;
+    let manifest: Manifest = serde_json::from_str(&text).map_err(|e| {
+        let merge = serde_json::from_str::<serde_json::Value>(&text)
+            .is_ok_and(|json| json.get("matrix").is_none() && json.get("inputs").is_some());
+        if merge {
+            return ConversionError::ValidationError(format!(
+                "{} is a merge manifest; only manifests written by `build` can be verified",
+                args.manifest.display()
+            ));
+        }
+        ConversionError::ParserError(format!(
+            "{}: not a build manifest: {}",
+            args.manifest.display(),
+            e
+        ))
+    })?;
+    let version = env!("CARGO_PKG_VERSION");
+    if manifest.provenance.tool_version != version {
+        warn!(
+            "The manifest was written by {} {} and is verified with {}; outputs can differ for that alone",
+            manifest.provenance.tool, manifest.provenance.tool_version, version
+        );
+    }
+
+    let matrix_path = args
+        .matrix
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(&manifest.matrix.path));
+    let matrix_text = std::fs::read_to_string(&matrix_path)
+        .map_err(|e| ConversionError::io_error(&matrix_path, e))?;
+    if provenance::sha256(matrix_text.as_bytes()) != manifest.matrix.sha256 {
+        return Err(ConversionError::ValidationError(format!(
+            "{} is not the build matrix the manifest was written for",
+            matrix_path.display()
+        )));
+    }
+    let base = matrix_path.parent().unwrap_or(Path::new(""));
+    let matrix = parse_matrix(&matrix_text, base)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", matrix_path.display(), e)))?;
+    let targets = matrix.targets()?;
+    let output = args.manifest.parent().unwrap_or(Path::new(""));
+
+    let mut failed = 0;
+    for recorded in &manifest.outputs {
+        let Some(target) = targets.iter().find(|t| t.file == recorded.file) else {
+            println!(
+                "missing: {} (the matrix no longer builds it)",
+                recorded.file
+            );
+            failed += 1;
+            continue;
+        };
+        let stale = stale_sources(recorded);
+        if !stale.is_empty() {
+            println!("differs: {}", recorded.file);
+            for change in stale {
+                println!("           {}", change);
+            }
+            failed += 1;
+            continue;
+        }
+        let includes = include::Limits {
+            max_depth: manifest.max_depth as usize,
+            outside_root: manifest.allow_outside_includes,
+        };
+        match build(&matrix, target, output, includes, args.workers) {
+            Ok((rebuilt, _)) if rebuilt.sha256 == recorded.sha256 => {
+                println!("ok:      {}", recorded.file)
+            }
+            Ok((rebuilt, _)) => {
+                println!("differs: {}", recorded.file);
+                for change in changed_sources(recorded, &rebuilt) {
+                    println!("           {}", change);
+                }
+                failed += 1;
+            }
+            Err(e) => {
+                println!("failed:  {}: {}", recorded.file, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(ConversionError::ValidationError(format!(
+            "{} of {} output(s) do not reproduce from their recorded sources",
+            failed,
+            manifest.outputs.len()
+        )));
+    }
+    info!(
+        "All {} output(s) reproduce from their recorded sources",
+        manifest.outputs.len()
+    );
+
+    if let Some(path) = &args.config {
+        let deployed = provenance::hash_file(path)?;
+        let Some(artifact) = manifest
+            .outputs
+            .iter()
+            .find(|a| a.sha256 == deployed.sha256)
+        else {
+            return Err(ConversionError::ValidationError(format!(
+                "{} matches none of the outputs in {}",
+                path.display(),
+                args.manifest.display()
+            )));
+        };
+        println!("{} is {}", path.display(), artifact.file);
+    }
+    Ok(())
+}
+
+/// The sources, template and suppressions an output was built from.
+fn sources(artifact: &Artifact) -> Vec<FileHash> {
+    let mut files = artifact.inputs.clone();
+    files.extend(artifact.template.iter().chain(&artifact.suppress).cloned());
+    files
+}
+
+/// The recorded sources of `artifact` that no longer hash as recorded, as
+/// `changed:` and `removed:` lines: no rebuild can reproduce it then.
+fn stale_sources(artifact: &Artifact) -> Vec<String> {
+    sources(artifact)
+        .iter()
+        .filter_map(
+            |recorded| match provenance::hash_file(Path::new(&recorded.path)) {
+                Ok(now) if now.sha256 == recorded.sha256 => None,
+                Ok(_) => Some(format!("changed: {}", recorded.path)),
+                Err(_) => Some(format!("removed: {}", recorded.path)),
+            },
+        )
+        .collect()
+}
+
+/// The sources, template and suppressions whose hashes differ between a
+/// recorded output and its rebuild, as `changed:`, `added:` and `removed:`
+/// lines, then the placeholder values that differ.
+fn changed_sources(recorded: &Artifact, rebuilt: &Artifact) -> Vec<String> {
+    let (before, after) = (sources(recorded), sources(rebuilt));
+    let find = |files: &[FileHash], path: &str| files.iter().find(|f| f.path == path).cloned();
+    let mut changes = Vec::new();
+    for old in &before {
+        match find(&after, &old.path) {
+            Some(new) if new.sha256 != old.sha256 => changes.push(format!("changed: {}", old.path)),
+            Some(_) => {}
+            None => changes.push(format!("removed: {}", old.path)),
+        }
+    }
+    for new in after.iter().filter(|f| find(&before, &f.path).is_none()) {
+        changes.push(format!("added:   {}", new.path));
+    }
+    for (name, old) in &recorded.vars {
+        match rebuilt.vars.get(name) {
+            Some(new) if new != old => {
+                changes.push(format!("changed: ${{{}}} {:?} -> {:?}", name, old, new))
+            }
+            Some(_) => {}
+            None => changes.push(format!("removed: ${{{}}} {:?}", name, old)),
+        }
+    }
+    for (name, new) in &rebuilt.vars {
+        if !recorded.vars.contains_key(name) {
+            changes.push(format!("added:   ${{{}}} {:?}", name, new));
+        }
+    }
+    if changes.is_empty() {
+        // Every input and value the build read is recorded, so only the
+        // tool itself can differ.
+        changes.push("no recorded source or value changed: check the tool version".to_string());
+    }
+    changes
+}
+
+impl Matrix {
+    /// Every combination of profile, platform and site not excluded, in
+    /// that nesting order.
+    pub fn targets(&self) -> Result<Vec<Target<'_>>, ConversionError> {
+        let sites: Vec<Option<&(String, Vec<String>)>> = if self.sites.is_empty() {
+            vec![None]
+        } else {
+            self.sites.iter().map(Some).collect()
+        };
+        let mut targets: Vec<Target> = Vec::new();
+        for profile in &self.profiles {
+            for &platform in &self.platforms {
+                for &site in &sites {
+                    let platform_name = platform.to_string();
+                    let site_name = site.map_or("", |(name, _)| name.as_str());
+                    let value = |key: &str| match key {
+                        "profile" => Some(profile.name.as_str()),
+                        "platform" => Some(platform_name.as_str()),
+                        "site" => Some(site_name),
+                        _ => None,
+                    };
+                    if self
+                        .exclude
+                        .iter()
+                        .any(|e| e.iter().all(|(k, v)| value(k) == Some(v)))
+                    {
+                        continue;
+                    }
+                    let file = self
+                        .name
+                        .replace("{profile}", &profile.name)
+                        .replace("{platform}", &platform_name)
+                        .replace("{site}", site_name);
+                    if let Some(other) = targets.iter().find(|t| t.file == file) {
+                        return Err(ConversionError::ValidationError(format!(
+                            "Outputs for profile {} and {} would both be named {}; add the \
+                             missing placeholders to `name`",
+                            other.profile.name, profile.name, file
+                        )));
+                    }
+                    targets.push(Target {
+                        profile,
+                        platform,
+                        site,
+                        file,
+                    });
+                }
+            }
+        }
+        Ok(targets)
+    }
+}
+
+/// Reads a build matrix, resolving its paths against `base`.
+pub fn parse_matrix(text: &str, base: &Path) -> Result<Matrix, String> {
+    let yaml = yaml::parse(text)?;
+    let Yaml::Map(entries) = &yaml else {
+        return Err("expected a mapping".to_string());
+    };
+    if let Some((key, _)) = entries.iter().find(|(k, _)| {
+        ![
+            "output",
+            "name",
+            "template",
+            "strategy",
+            "recursive",
+            "profiles",
+            "platforms",
+            "sites",
+            "exclude",
+        ]
+        .contains(&k.as_str())
+    }) {
+        return Err(format!("unknown key `{}`", key));
+    }
+    let text = |yaml: &Yaml, key: &str| -> Result<Option<String>, String> {
+        match yaml.get(key) {
+            None | Some(Yaml::Null) => Ok(None),
+            Some(Yaml::Scalar(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(format!("`{}` must be a single value", key)),
+        }
+    };
+    let path = |yaml: &Yaml, key: &str| Ok::<_, String>(text(yaml, key)?.map(|p| base.join(p)));
+
+    let Some(Yaml::Map(profile_entries)) = yaml.get("profiles") else {
+        return Err("`profiles` must map profile names to their inputs".to_string());
+    };
+    let mut profiles = Vec::new();
+    for (name, profile) in profile_entries {
+        if let Yaml::Map(keys) = profile {
+            if let Some((key, _)) = keys
+                .iter()
+                .find(|(k, _)| !["inputs", "template", "suppress"].contains(&k.as_str()))
+            {
+                return Err(format!("profile {}: unknown key `{}`", name, key));
+            }
+        }
+        let inputs = match profile.get("inputs") {
+            Some(Yaml::Scalar(input)) => vec![base.join(input)],
+            Some(Yaml::List(inputs)) if !inputs.is_empty() => inputs
+                .iter()
+                .map(|i| i.as_str().map(|i| base.join(i)))
+                .collect::<Option<_>>()
+                .ok_or(format!("profile {}: `inputs` must list paths", name))?,
+            _ => return Err(format!("profile {} has no `inputs`", name)),
+        };
+        profiles.push(Profile {
+            name: name.clone(),
+            inputs,
+            template: path(profile, "template").map_err(|e| format!("profile {}: {}", name, e))?,
+            suppress: path(profile, "suppress").map_err(|e| format!("profile {}: {}", name, e))?,
+        });
+    }
+
+    let platforms = match yaml.get("platforms") {
+        None | Some(Yaml::Null) => vec![Platform::Windows],
+        Some(Yaml::Scalar(platform)) => vec![platform.parse()?],
+        Some(Yaml::List(platforms)) => platforms
+            .iter()
+            .map(|p| p.as_str().ok_or("`platforms` must list names")?.parse())
+            .collect::<Result<_, String>>()?,
+        Some(Yaml::Map(_)) => return Err("`platforms` must list names".to_string()),
+    };
+
+    let mut sites = Vec::new();
+    match yaml.get("sites") {
+        None | Some(Yaml::Null) => {}
+        Some(Yaml::Map(entries)) => {
+            for (name, site) in entries {
+                let pairs = match site {
+                    Yaml::Null => Vec::new(),
+                    Yaml::Map(values) => values
+                        .iter()
+                        .map(|(k, v)| {
+                            v.as_str()
+                                .map(|v| format!("{}={}", k, v))
+                                .ok_or(format!("site {}: {} must be a single value", name, k))
+                        })
+                        .collect::<Result<_, _>>()?,
+                    _ => return Err(format!("site {} must map variables to values", name)),
+                };
+                sites.push((name.clone(), pairs));
+            }
+        }
+        Some(_) => return Err("`sites` must map site names to their variables".to_string()),
+    }
+
+    let exclude = match yaml.get("exclude") {
+        None | Some(Yaml::Null) => Vec::new(),
+        Some(Yaml::List(items)) => items
+            .iter()
+            .map(|item| match item {
+                Yaml::Map(entries) => entries
+                    .iter()
+                    .map(|(k, v)| match (k.as_str(), v.as_str()) {
+                        // Compared with the platform's own spelling, as
+                        // `platforms` accepts any case.
+                        ("platform", Some(v)) => {
+                            Ok((k.clone(), v.parse::<Platform>()?.to_string()))
+                        }
+                        ("profile" | "site", Some(v)) => Ok((k.clone(), v.to_string())),
+                        _ => Err(format!(
+                            "`exclude` entries name a profile, platform or site, not `{}`",
+                            k
+                        )),
+                    })
+                    .collect(),
+                _ => Err("`exclude` must list combinations".to_string()),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err("`exclude` must list combinations".to_string()),
+    };
+
+    let matrix_path = Path::new("build matrix");
+    let strategy = text(&yaml, "strategy")?
+        .map(|s| user_config::parse_enum(matrix_path, "strategy", &s).map_err(|e| e.to_string()))
+        .transpose()?;
+    let recursive = match text(&yaml, "recursive")?.as_deref() {
+        None | Some("true") => true,
+        Some("false") => false,
+        Some(other) => return Err(format!("`recursive` must be true or false, not {}", other)),
+    };
+    let name = text(&yaml, "name")?.unwrap_or_else(|| {
+        let site = if sites.is_empty() { "" } else { "-{site}" };
+        format!("{{profile}}-{{platform}}{}.xml", site)
+    });
+    Ok(Matrix {
+        output: base.join(text(&yaml, "output")?.unwrap_or_else(|| "dist".to_string())),
+        name,
+        template: path(&yaml, "template")?,
+        strategy,
+        recursive,
+        profiles,
+        platforms,
+        sites,
+        exclude,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_expands_combinations_and_skips_excluded() {
+        let matrix = parse_matrix(
+            r#"template: baseconfig.xml
+profiles:
+  workstation:
+    inputs: [rules/common, rules/workstation]
+  server:
+    inputs: rules/server
+    suppress: server.txt
+platforms: [windows, linux]
+sites:
+  emea:
+  amer:
+    CORP_DOMAIN: amer.example.com
+exclude:
+  - profile: workstation
+    platform: linux
+  - site: emea
+    platform: Linux
+"#,
+            Path::new("repo"),
+        )
+        .unwrap();
+        assert_eq!(matrix.output, Path::new("repo/dist"));
+        assert_eq!(
+            matrix.profiles[0].inputs,
+            [
+                Path::new("repo/rules/common"),
+                Path::new("repo/rules/workstation")
+            ]
+        );
+        assert_eq!(
+            matrix.profiles[1].suppress.as_deref(),
+            Some(Path::new("repo/server.txt"))
+        );
+        assert_eq!(
+            matrix.sites[1],
+            (
+                "amer".to_string(),
+                vec!["CORP_DOMAIN=amer.example.com".to_string()]
+            )
+        );
+
+        let files: Vec<String> = matrix
+            .targets()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.file)
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "workstation-windows-emea.xml",
+                "workstation-windows-amer.xml",
+                "server-windows-emea.xml",
+                "server-windows-amer.xml",
+                "server-linux-amer.xml",
+            ]
+        );
+
+        let clashing = parse_matrix(
+            "name: out.xml\nprofiles:\n  a:\n    inputs: a\n  b:\n    inputs: b\n",
+            Path::new(""),
+        )
+        .unwrap();
+        assert!(clashing.targets().is_err());
+        assert!(parse_matrix("profiles:\n  a:\n    template: t.xml\n", Path::new("")).is_err());
+        assert_eq!(
+            parse_matrix(
+                "profiles:\n  a:\n    inputs: a\n    suppres: s.txt\n",
+                Path::new("")
+            ),
+            Err("profile a: unknown key `suppres`".to_string())
+        );
+        assert!(parse_matrix(
+            "profiles:\n  a:\n    inputs: a\nexclude:\n  - platform: macos\n",
+            Path::new("")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_build_writes_nothing_unless_every_output_validates() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, text: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        };
+        write(
+            "rules/good/a.xml",
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+            <ProcessCreate onmatch="include"><Image condition="end with">a.exe</Image>
+            </ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#,
+        );
+        write(
+            "rules/bad/a.xml",
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+            <ProcessTampering onmatch="include"><TargetFilename condition="is">x</TargetFilename>
+            </ProcessTampering></RuleGroup></EventFiltering></Sysmon>"#,
+        );
+        let matrix = dir.path().join("matrix.yml");
+        let output = dir.path().join("dist");
+        let args = BuildArgs {
+            matrix: matrix.clone(),
+            output: None,
+            max_depth: 10,
+            allow_outside_includes: false,
+            workers: Some(1),
+        };
+
+        write(
+            "matrix.yml",
+            "profiles:\n  good:\n    inputs: rules/good\n  bad:\n    inputs: rules/bad\n",
+        );
+        let err = run(&args).unwrap_err().to_string();
+        assert!(err.contains("1 of 2 output(s) failed"), "{}", err);
+        assert!(!output.exists());
+
+        write("matrix.yml", "profiles:\n  good:\n    inputs: rules/good\n");
+        run(&args).unwrap();
+        let mut written: Vec<String> = std::fs::read_dir(&output)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        written.sort();
+        assert_eq!(written, [MANIFEST, "good-windows.xml"]);
+    }
+
+    #[test]
+    fn test_changed_sources_names_what_differs() {
+        let file = |path: &str, sha256: &str| FileHash {
+            path: path.to_string(),
+            sha256: sha256.to_string(),
+            bytes: 0,
+        };
+        let artifact = |inputs: Vec<FileHash>, template: Option<FileHash>| Artifact {
+            file: "a-windows.xml".to_string(),
+            profile: "a".to_string(),
+            platform: "windows".to_string(),
+            site: None,
+            schema_version: "4.90".to_string(),
+            rules: 0,
+            bytes: 0,
+            sha256: String::new(),
+            warnings: 0,
+            vars: BTreeMap::new(),
+            template,
+            suppress: None,
+            inputs,
+        };
+        let recorded = artifact(
+            vec![file("rules/1.xml", "aa"), file("rules/2.xml", "bb")],
+            Some(file("base.xml", "cc")),
+        );
+        let rebuilt = artifact(
+            vec![file("rules/1.xml", "aa"), file("rules/3.xml", "dd")],
+            Some(file("base.xml", "ee")),
+        );
+        assert_eq!(
+            changed_sources(&recorded, &rebuilt),
+            [
+                "removed: rules/2.xml",
+                "changed: base.xml",
+                "added:   rules/3.xml"
+            ]
+        );
+        assert!(changed_sources(&recorded, &recorded)[0].starts_with("no recorded source"));
+
+        let mut resited = artifact(recorded.inputs.clone(), recorded.template.clone());
+        let mut recorded = recorded;
+        recorded.vars = BTreeMap::from([
+            ("DOMAIN".to_string(), "a.example".to_string()),
+            ("PROXY".to_string(), "10.0.0.1".to_string()),
+        ]);
+        resited.vars = BTreeMap::from([
+            ("DOMAIN".to_string(), "b.example".to_string()),
+            ("SITE".to_string(), "east".to_string()),
+        ]);
+        assert_eq!(
+            changed_sources(&recorded, &resited),
+            [
+                r#"changed: ${DOMAIN} "a.example" -> "b.example""#,
+                r#"removed: ${PROXY} "10.0.0.1""#,
+                r#"added:   ${SITE} "east""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stale_sources_are_found_without_rebuilding() {
+        let dir = tempfile::tempdir().unwrap();
+        let (kept, edited) = (dir.path().join("1.xml"), dir.path().join("2.xml"));
+        std::fs::write(&kept, "<Sysmon/>").unwrap();
+        std::fs::write(&edited, "<Sysmon/>").unwrap();
+        let gone = dir.path().join("3.xml");
+        let mut inputs =
+            provenance::hash_inputs(&[kept.clone(), edited.clone()], include::Limits::new(10))
+                .unwrap();
+        inputs[1].sha256 = "stale".to_string();
+        inputs.push(FileHash {
+            path: gone.display().to_string(),
+            sha256: String::new(),
+            bytes: 0,
+        });
+        let artifact = Artifact {
+            file: "a-windows.xml".to_string(),
+            profile: "a".to_string(),
+            platform: "windows".to_string(),
+            site: None,
+            schema_version: "4.90".to_string(),
+            rules: 0,
+            bytes: 0,
+            sha256: String::new(),
+            warnings: 0,
+            vars: BTreeMap::new(),
+            template: None,
+            suppress: None,
+            inputs,
+        };
+        assert_eq!(
+            stale_sources(&artifact),
+            [
+                format!("changed: {}", edited.display()),
+                format!("removed: {}", gone.display()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Cooperative cancellation of batch runs.
+//!
+//! The first Ctrl-C during a batch cancels its [`CancellationToken`]: no new
+//! file is started, files being converted stop before their output is
+//! written, and the run still writes its summary, checkpoint and
+//! incremental state before exiting with [`EXIT_INTERRUPTED`]. A second
+//! Ctrl-C exits at once, after removing the partial files of the outputs
+//! still being written and the staging directories in use. Outputs are
+//! renamed into place once complete, so an output that was being rewritten
+//! keeps its earlier content.
+//!
+//! Only the CLI's own batch runner is cancellable; `BatchProcessor` and
+//! `sysmon_cli::asynchronous` run to completion.
+
+use crate::io_guard;
+use log::warn;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use sysmon_json::error::ConversionError;
+
+/// Exit status of a run stopped by Ctrl-C (128 + SIGINT, as shells report it).
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static CTRL_C: OnceLock<CancellationToken> = OnceLock::new();
+
+/// Shared flag a batch checks between steps; cloning it gives another handle
+/// to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// Outputs being written right now.
+    in_flight: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails once the token is cancelled.
+    pub fn check(&self) -> Result<(), ConversionError> {
+        if self.is_cancelled() {
+            return Err(ConversionError::Other("Interrupted".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Marks `output` as being written until the returned guard is dropped.
+    pub fn track(&self, output: &Path) -> InFlight<'_> {
+        self.0
+            .in_flight
+            .lock()
+            .unwrap()
+            .insert(output.to_path_buf());
+        InFlight {
+            token: self,
+            output: output.to_path_buf(),
+        }
+    }
+
+    /// Removes the partial files (see [`io_guard::partial_path`]) of the
+    /// outputs still being written. The outputs themselves are left alone.
+    pub fn remove_in_flight(&self) {
+        for output in self.0.in_flight.lock().unwrap().iter() {
+            let _ = io_guard::remove(&io_guard::partial_path(output));
+        }
+    }
+}
+
+/// An output registered with [`CancellationToken::track`].
+pub struct InFlight<'a> {
+    token: &'a CancellationToken,
+    output: PathBuf,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.token.0.in_flight.lock().unwrap().remove(&self.output);
+    }
+}
+
+/// The token Ctrl-C cancels, installing the handler on first use.
+pub fn ctrl_c() -> Result<CancellationToken, ConversionError> {
+    if let Some(token) = CTRL_C.get() {
+        return Ok(token.clone());
+    }
+    let token = CancellationToken::new();
+    let handler = token.clone();
+    ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            handler.remove_in_flight();
+            io_guard::remove_tempdirs();
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        warn!("Interrupted; finishing files in progress (press Ctrl-C again to stop now)");
+        handler.cancel();
+    })
+    .map_err(|e| ConversionError::Other(format!("Failed to handle Ctrl-C: {}", e)))?;
+    Ok(CTRL_C.get_or_init(|| token).clone())
+}
+
+/// Whether Ctrl-C cancelled a batch in this process.
+pub fn interrupted() -> bool {
+    CTRL_C.get().is_some_and(CancellationToken::is_cancelled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_removes_partial_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let (done, writing) = (dir.path().join("a.json"), dir.path().join("b.json"));
+        let partial = |output: &Path| io_guard::partial_path(output);
+        for output in [&done, &writing] {
+            std::fs::write(output, "{}").unwrap();
+            std::fs::write(partial(output), "{").unwrap();
+        }
+
+        let token = CancellationToken::new();
+        drop(token.track(&done));
+        let _in_flight = token.track(&writing);
+        assert!(token.check().is_ok());
+        token.cancel();
+        assert!(token.check().is_err());
+        token.remove_in_flight();
+
+        // An output from an earlier run is kept; only the unfinished
+        // replacement goes.
+        assert!(writing.exists());
+        assert!(!partial(&writing).exists());
+        assert!(partial(&done).exists());
+    }
+}
@@ -0,0 +1,255 @@
+//! `changelog`: a Markdown summary of the rule changes between two versions
+//! of a config, for pasting into a policy-change pull request.
+//!
+//! Rules are paired the way `drift` pairs them, so an edited rule shows up
+//! as modified rather than removed and re-added. Changes are grouped by
+//! event type, each annotated with the ATT&CK techniques its rule names
+//! cite; global settings come last. With `--git`, both arguments are
+//! `REV:PATH` specs read with `git show`, e.g. `main:sysmonconfig.xml`.
+
+use crate::drift::{self, Change, RuleChange};
+use crate::{explain, io_guard, validate};
+use clap::Args;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use sysmon_cli::model::{Filter, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+#[derive(Args)]
+pub struct ChangelogArgs {
+    /// The earlier version of the config (a REV:PATH spec with --git)
+    pub old: String,
+
+    /// The later version of the config (a REV:PATH spec with --git)
+    pub new: String,
+
+    /// Read both versions from git, e.g. `main:sysmonconfig.xml HEAD:sysmonconfig.xml`
+    #[arg(long)]
+    pub git: bool,
+
+    /// Write the changelog to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn run(args: &ChangelogArgs) -> Result<(), ConversionError> {
+    let load = |spec: &str| -> Result<SysmonConfig, ConversionError> {
+        let (text, _) = if args.git {
+            validate::parse(Path::new(spec), &git_show(spec)?)?
+        } else {
+            validate::load(Path::new(spec))?
+        };
+        SysmonConfig::from_xml_str(&text)
+    };
+    let (old, new) = (load(&args.old)?, load(&args.new)?);
+    let text = changelog(&args.old, &args.new, &old, &new);
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn git_show(spec: &str) -> Result<Vec<u8>, ConversionError> {
+    if !spec.contains(':') {
+        return Err(ConversionError::ValidationError(format!(
+            "{}: expected a REV:PATH spec such as main:sysmonconfig.xml",
+            spec
+        )));
+    }
+    let output = Command::new("git")
+        .arg("show")
+        .arg(spec)
+        .output()
+        .map_err(|e| ConversionError::io_error(Path::new("git"), e))?;
+    if !output.status.success() {
+        return Err(ConversionError::InvalidFile(format!(
+            "git show {}: {}",
+            spec,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+pub fn changelog(old_name: &str, new_name: &str, old: &SysmonConfig, new: &SysmonConfig) -> String {
+    let changes = drift::rule_changes(old, new);
+    let settings: Vec<_> = drift::compare(old, new)
+        .into_iter()
+        .filter(|d| d.scope == "Sysmon")
+        .collect();
+
+    let count = |wanted: Change| changes.iter().filter(|c| change(c) == wanted).count();
+    let mut out = format!(
+        "## Sysmon config changes\n\n{} → {}: {} rule(s) added, {} removed, {} modified\n",
+        code(old_name),
+        code(new_name),
+        count(Change::Added),
+        count(Change::Removed),
+        count(Change::Modified)
+    );
+    if changes.is_empty() && settings.is_empty() {
+        out.push_str("\nNo changes.\n");
+        return out;
+    }
+
+    let mut events: Vec<&str> = Vec::new();
+    for c in &changes {
+        if !events.contains(&c.event.event.as_str()) {
+            events.push(&c.event.event);
+        }
+    }
+    for event in events {
+        let _ = writeln!(out, "\n### {}\n", event);
+        for c in changes.iter().filter(|c| c.event.event == event) {
+            let _ = writeln!(out, "{}", entry(c));
+        }
+    }
+
+    if !settings.is_empty() {
+        out.push_str("\n### Global settings\n\n");
+        for setting in &settings {
+            let value = |v: &Option<String>| v.as_deref().map(code).unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "- **{}**: {}",
+                label(setting.change),
+                match setting.change {
+                    Change::Added => value(&setting.current),
+                    Change::Removed => value(&setting.baseline),
+                    Change::Modified => {
+                        format!("{} → {}", value(&setting.baseline), value(&setting.current))
+                    }
+                }
+            );
+        }
+    }
+    out
+}
+
+fn change(c: &RuleChange) -> Change {
+    match (&c.baseline, &c.current) {
+        (Some(_), Some(_)) => Change::Modified,
+        (Some(_), None) => Change::Removed,
+        (None, _) => Change::Added,
+    }
+}
+
+fn label(change: Change) -> &'static str {
+    match change {
+        Change::Added => "Added",
+        Change::Removed => "Removed",
+        Change::Modified => "Modified",
+    }
+}
+
+/// One bullet: what changed, in which `onmatch`, and the techniques cited
+/// on either side.
+fn entry(c: &RuleChange) -> String {
+    let rules = match (&c.baseline, &c.current) {
+        (Some((_, old)), Some((_, new))) => format!("{} → {}", code(old), code(new)),
+        (Some((_, text)), None) | (None, Some((_, text))) => code(text),
+        (None, None) => String::new(),
+    };
+    let mut techniques: Vec<String> = [&c.baseline, &c.current]
+        .into_iter()
+        .flatten()
+        .flat_map(|(filter, _)| name(filter).map(explain::techniques).unwrap_or_default())
+        .collect();
+    techniques.sort();
+    techniques.dedup();
+    let mut line = format!(
+        "- **{}** ({}): {}",
+        label(change(c)),
+        c.event.onmatch.as_str(),
+        rules
+    );
+    if !techniques.is_empty() {
+        let _ = write!(line, " — {}", techniques.join(", "));
+    }
+    line
+}
+
+fn name(filter: &Filter) -> Option<&str> {
+    match filter {
+        Filter::Field(field) => field.name.as_deref(),
+        Filter::Rule(rule) => rule.name.as_deref(),
+    }
+}
+
+/// `text` as a Markdown code span, fenced with enough backticks to hold
+/// any it contains.
+fn code(text: &str) -> String {
+    let mut fence = "`".to_string();
+    while text.contains(fence.as_str()) {
+        fence.push('`');
+    }
+    if fence.len() > 1 || text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", fence, text, fence)
+    } else {
+        format!("{}{}{}", fence, text, fence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(schema: &str, rules: &str) -> SysmonConfig {
+        SysmonConfig::from_xml_str(&format!(
+            r#"<Sysmon schemaversion="{}"><EventFiltering><RuleGroup groupRelation="or">{}</RuleGroup></EventFiltering></Sysmon>"#,
+            schema, rules
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_changelog_groups_changes_by_event_type() {
+        let old = config(
+            "4.50",
+            r#"<ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001,technique_name=PowerShell" condition="end with">\powershell.exe</Image>
+            <Image name="technique_id=T1003" condition="end with">\procdump.exe</Image>
+            </ProcessCreate>"#,
+        );
+        let new = config(
+            "4.90",
+            r#"<ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001,technique_name=PowerShell" condition="end with">\pwsh.exe</Image>
+            </ProcessCreate>
+            <DnsQuery onmatch="exclude"><QueryName condition="end with">.local</QueryName></DnsQuery>"#,
+        );
+
+        let text = changelog("old.xml", "new.xml", &old, &new);
+        assert_eq!(
+            text,
+            r#"## Sysmon config changes
+
+`old.xml` → `new.xml`: 1 rule(s) added, 1 removed, 1 modified
+
+### ProcessCreate
+
+- **Modified** (include): `[technique_id=T1059.001,technique_name=PowerShell] Image end with "\\powershell.exe"` → `[technique_id=T1059.001,technique_name=PowerShell] Image end with "\\pwsh.exe"` — T1059.001 (PowerShell)
+- **Removed** (include): `[technique_id=T1003] Image end with "\\procdump.exe"` — T1003
+
+### DnsQuery
+
+- **Added** (exclude): `QueryName end with ".local"`
+
+### Global settings
+
+- **Modified**: `schemaversion 4.50` → `schemaversion 4.90`
+"#
+        );
+        assert!(changelog("a", "b", &old, &old).ends_with("\nNo changes.\n"));
+    }
+
+    #[test]
+    fn test_code_fences_backticks() {
+        assert_eq!(code("a"), "`a`");
+        assert_eq!(code("a`b"), "`` a`b ``");
+    }
+}
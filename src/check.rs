@@ -0,0 +1,80 @@
+//! `--check`: verify committed outputs are up to date without writing them.
+//!
+//! Each output is rendered in memory exactly as a normal run would produce
+//! it and compared with the file on disk, so CI can fail when, say, the JSON
+//! mirror of an XML config was not regenerated. Line endings are ignored so
+//! that checkouts with `core.autocrlf` do not report drift.
+
+use crate::compression;
+use log::info;
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drift {
+    Missing,
+    Differs,
+}
+
+/// Compares `expected` with the current contents of `output`.
+pub fn compare(output: &Path, expected: &[u8]) -> Result<Option<Drift>, ConversionError> {
+    if !output.exists() {
+        return Ok(Some(Drift::Missing));
+    }
+    let actual = compression::read(output)?;
+    Ok((normalize(&actual) != normalize(expected)).then_some(Drift::Differs))
+}
+
+/// Lists drifted outputs on stdout and fails if there are any.
+pub fn report(results: &[(PathBuf, Option<Drift>)]) -> Result<(), ConversionError> {
+    let mut drifted = 0;
+    for (output, drift) in results {
+        match drift {
+            Some(Drift::Missing) => println!("missing: {}", output.display()),
+            Some(Drift::Differs) => println!("differs: {}", output.display()),
+            None => continue,
+        }
+        drifted += 1;
+    }
+
+    if drifted > 0 {
+        return Err(ConversionError::ValidationError(format!(
+            "{} of {} output(s) are out of date",
+            drifted,
+            results.len()
+        )));
+    }
+    info!("All {} output(s) are up to date", results.len());
+    Ok(())
+}
+
+fn normalize(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().peekable();
+    while let Some(&b) = iter.next() {
+        if b == b'\r' && iter.peek() == Some(&&b'\n') {
+            continue;
+        }
+        out.push(b);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_ignores_line_endings() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("config.json");
+        assert_eq!(compare(&output, b"{}\n").unwrap(), Some(Drift::Missing));
+
+        std::fs::write(&output, "{\r\n}\r\n").unwrap();
+        assert_eq!(compare(&output, b"{\n}\n").unwrap(), None);
+        assert_eq!(compare(&output, b"{ }\n").unwrap(), Some(Drift::Differs));
+
+        let results = vec![(output, Some(Drift::Differs))];
+        assert!(report(&results).is_err());
+    }
+}
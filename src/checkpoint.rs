@@ -0,0 +1,231 @@
+//! Checkpoints for resuming an interrupted batch run.
+//!
+//! While a planned batch runs, every converted input is appended to a
+//! checkpoint file in the output directory, flushed every couple of
+//! seconds. A run that converts every input removes the file; one that is
+//! killed, stopped with Ctrl-C, stopped by `--max-errors` or left with
+//! failed inputs keeps it, and `--resume` then skips the inputs it lists.
+//! Failed inputs are never listed, so a resumed run retries them.
+
+use crate::batch::Plan;
+use crate::cancel::CancellationToken;
+use crate::io_guard;
+use log::{info, warn};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use sysmon_cli::progress::{FileEvent, FileStatus, Progress, Summary};
+use sysmon_json::error::ConversionError;
+
+pub const CHECKPOINT_FILE: &str = ".sysmon-helper-checkpoint";
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Moves jobs that a previous, interrupted run already converted from
+/// `plan.jobs` to `plan.skipped`.
+pub fn skip_completed(plan: &mut Plan, input_dir: &Path, output_dir: &Path) {
+    let path = output_dir.join(CHECKPOINT_FILE);
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        warn!(
+            "No checkpoint in {}; starting from the beginning",
+            output_dir.display()
+        );
+        return;
+    };
+    let completed: BTreeSet<&str> = text.lines().collect();
+
+    let (done, todo): (Vec<_>, Vec<_>) =
+        std::mem::take(&mut plan.jobs).into_iter().partition(|job| {
+            job.output.exists() && completed.contains(key(input_dir, &job.input).as_str())
+        });
+    info!("Resuming: {} file(s) already converted", done.len());
+    plan.skipped.extend(done.into_iter().map(|job| job.input));
+    plan.jobs = todo;
+}
+
+/// Wraps another reporter and records converted files as they finish.
+pub struct Checkpoint<'a> {
+    inner: &'a dyn Progress,
+    path: PathBuf,
+    input_dir: PathBuf,
+    /// Taken when the run finishes, closing the file.
+    writer: Mutex<Option<(BufWriter<File>, Instant)>>,
+    cancel: CancellationToken,
+    /// Inputs the run set out to convert, from `on_start`.
+    planned: AtomicUsize,
+}
+
+impl<'a> Checkpoint<'a> {
+    /// Opens the checkpoint in `output_dir`, keeping its entries when
+    /// `resume` is set and starting a new one otherwise. The checkpoint is
+    /// kept at the end of the run if `cancel` was cancelled or any planned
+    /// input was not converted.
+    pub fn open(
+        output_dir: &Path,
+        input_dir: &Path,
+        resume: bool,
+        cancel: &CancellationToken,
+        inner: &'a dyn Progress,
+    ) -> Result<Self, ConversionError> {
+        io_guard::create_dir_all(output_dir)?;
+        let path = output_dir.join(CHECKPOINT_FILE);
+        let file = if resume {
+            io_guard::append(&path)?
+        } else {
+            io_guard::create(&path)?
+        };
+        Ok(Checkpoint {
+            inner,
+            path,
+            input_dir: input_dir.to_path_buf(),
+            writer: Mutex::new(Some((BufWriter::new(file), Instant::now()))),
+            cancel: cancel.clone(),
+            planned: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Progress for Checkpoint<'_> {
+    fn on_start(&self, files: usize, bytes: u64) {
+        self.planned.store(files, Ordering::SeqCst);
+        self.inner.on_start(files, bytes);
+    }
+
+    fn on_file_start(&self, path: &Path) {
+        self.inner.on_file_start(path);
+    }
+
+    fn on_file_done(&self, event: &FileEvent) {
+        let mut guard = self.writer.lock().unwrap();
+        if let (FileStatus::Converted, Some((writer, last_flush))) = (event.status, &mut *guard) {
+            let mut result = writeln!(writer, "{}", key(&self.input_dir, event.path));
+            if result.is_ok() && last_flush.elapsed() >= FLUSH_INTERVAL {
+                result = writer.flush();
+                *last_flush = Instant::now();
+            }
+            if let Err(e) = result {
+                warn!("Failed to update checkpoint {}: {}", self.path.display(), e);
+            }
+        }
+        drop(guard);
+        self.inner.on_file_done(event);
+    }
+
+    fn on_error(&self, path: &Path, error: &ConversionError) {
+        self.inner.on_error(path, error);
+    }
+
+    fn on_finish(&self, summary: &Summary) {
+        let writer = self.writer.lock().unwrap().take();
+        // Failed, cancelled and never-started inputs all leave converted
+        // short of the plan.
+        let unfinished = summary.converted < self.planned.load(Ordering::SeqCst);
+        if self.cancel.is_cancelled() || unfinished {
+            let flushed = writer.map_or(Ok(()), |(mut writer, _)| writer.flush());
+            if let Err(e) = flushed {
+                warn!("Failed to update checkpoint {}: {}", self.path.display(), e);
+            } else {
+                info!("Run again with --resume to convert the remaining files");
+            }
+        } else {
+            // Closed first: Windows cannot remove a file that is still open.
+            drop(writer);
+            if let Err(e) = io_guard::remove(&self.path) {
+                warn!("Failed to remove checkpoint {}: {}", self.path.display(), e);
+            }
+        }
+        self.inner.on_finish(summary);
+    }
+}
+
+fn key(input_dir: &Path, input: &Path) -> String {
+    input
+        .strip_prefix(input_dir)
+        .unwrap_or(input)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::Job;
+    use sysmon_cli::progress::Silent;
+
+    #[test]
+    fn test_resume_skips_checkpointed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input_dir, output_dir) = (dir.path().join("in"), dir.path().join("out"));
+        let job = |name: &str| {
+            let output = output_dir.join(name).with_extension("json");
+            std::fs::create_dir_all(&output_dir).unwrap();
+            std::fs::write(&output, "{}").unwrap();
+            Job {
+                input: input_dir.join(name),
+                output,
+            }
+        };
+        let (done, todo) = (job("a.xml"), job("b.xml"));
+
+        let checkpoint = Checkpoint::open(
+            &output_dir,
+            &input_dir,
+            false,
+            &CancellationToken::new(),
+            &Silent,
+        )
+        .unwrap();
+        checkpoint.on_file_done(&FileEvent {
+            path: &done.input,
+            bytes: 1,
+            status: FileStatus::Converted,
+            elapsed: Duration::ZERO,
+        });
+        checkpoint.on_file_done(&FileEvent {
+            path: &todo.input,
+            bytes: 1,
+            status: FileStatus::Failed,
+            elapsed: Duration::ZERO,
+        });
+        drop(checkpoint);
+
+        let mut plan = Plan {
+            jobs: vec![done.clone(), todo.clone()],
+            ..Plan::default()
+        };
+        skip_completed(&mut plan, &input_dir, &output_dir);
+        assert_eq!(plan.skipped, vec![done.input]);
+        assert_eq!(plan.jobs.len(), 1);
+        assert_eq!(plan.jobs[0].input, todo.input);
+    }
+
+    #[test]
+    fn test_finished_run_removes_the_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let cancel = CancellationToken::new();
+        let open = || Checkpoint::open(dir.path(), dir.path(), false, &cancel, &Silent).unwrap();
+        let converted = FileEvent {
+            path: &dir.path().join("a.xml"),
+            bytes: 1,
+            status: FileStatus::Converted,
+            elapsed: Duration::ZERO,
+        };
+        let path = dir.path().join(CHECKPOINT_FILE);
+
+        let checkpoint = open();
+        checkpoint.on_file_done(&converted);
+        checkpoint.on_finish(&Summary::default());
+        assert!(!path.exists());
+
+        let checkpoint = open();
+        checkpoint.on_file_done(&converted);
+        cancel.cancel();
+        checkpoint.on_finish(&Summary::default());
+        // Flushed before the checkpoint itself is dropped.
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a.xml\n");
+    }
+}
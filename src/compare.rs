@@ -0,0 +1,411 @@
+//! `compare --against`: how a config measures up to a well-known community
+//! config such as SwiftOnSecurity's sysmon-config or sysmon-modular.
+//!
+//! Rules match on their event type, `onmatch` and conditions; names are
+//! ignored because each project words them differently, and values are
+//! compared case-insensitively the way Sysmon matches them. The report
+//! lists the baseline's rules this config is missing and the extras it
+//! carries. Known baselines are downloaded with `curl` on first use and
+//! cached; `--against` also takes the path of a config already on disk.
+
+use crate::{explain, io_guard, validate};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use sysmon_cli::model::{EventFilter, FieldCondition, Filter, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+/// Community configs `--against` knows by name, with where to fetch them.
+pub const BASELINES: &[(&str, &str)] = &[
+    (
+        "swiftonsecurity",
+        "https://raw.githubusercontent.com/SwiftOnSecurity/sysmon-config/master/sysmonconfig-export.xml",
+    ),
+    (
+        "sysmon-modular",
+        "https://raw.githubusercontent.com/olafhartong/sysmon-modular/master/sysmonconfig.xml",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompareFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct CompareArgs {
+    /// Configuration to compare (XML or JSON)
+    pub config: PathBuf,
+
+    /// Baseline to compare against: swiftonsecurity, sysmon-modular or a config path
+    #[arg(long)]
+    pub against: String,
+
+    /// Download a named baseline again instead of using the cached copy
+    #[arg(long)]
+    pub refresh: bool,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: CompareFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// A rule only one side has.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    /// `ProcessCreate/include`.
+    pub scope: String,
+    pub rule: String,
+    /// ATT&CK techniques cited in the rule's name.
+    pub techniques: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub baseline: String,
+    pub baseline_rules: usize,
+    pub rules: usize,
+    /// Baseline rules this config also has.
+    pub shared: usize,
+    /// Baseline rules this config does not have, in baseline order.
+    pub missing: Vec<Difference>,
+    /// Rules only this config has, in config order.
+    pub extra: Vec<Difference>,
+}
+
+pub fn run(args: &CompareArgs) -> Result<(), ConversionError> {
+    let load = |path: &Path| -> Result<SysmonConfig, ConversionError> {
+        SysmonConfig::from_xml_str(&validate::load(path)?.0)
+    };
+    let (name, path) = resolve(&args.against, args.refresh)?;
+    let comparison = compare(&name, &load(&path)?, &load(&args.config)?);
+
+    let text = match args.format {
+        CompareFormat::Text => render(&comparison),
+        CompareFormat::Json => serde_json::to_string_pretty(&comparison)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+/// The display name and local path of `against`, downloading a named
+/// baseline into the cache when it is not there yet or `refresh` is set.
+fn resolve(against: &str, refresh: bool) -> Result<(String, PathBuf), ConversionError> {
+    let Some((name, url)) = BASELINES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(against))
+    else {
+        let path = PathBuf::from(against);
+        if !path.exists() {
+            let names: Vec<&str> = BASELINES.iter().map(|(name, _)| *name).collect();
+            return Err(ConversionError::ValidationError(format!(
+                "{}: not a file or a known baseline ({})",
+                against,
+                names.join(", ")
+            )));
+        }
+        return Ok((path.display().to_string(), path));
+    };
+
+    let dir = cache_dir("baselines").ok_or_else(|| {
+        ConversionError::ValidationError(format!(
+            "no cache directory for {}; download {} and pass its path to --against",
+            name, url
+        ))
+    })?;
+    let path = dir.join(format!("{}.xml", name));
+    if refresh || !path.exists() {
+        download(url, &path, "pass its path to --against")?;
+    }
+    Ok((name.to_string(), path))
+}
+
+/// This tool's `name` subdirectory of the user's cache directory.
+pub fn cache_dir(name: &str) -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+            } else {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache"))
+            }
+        })?;
+    Some(base.join("sysmon-helper").join(name))
+}
+
+/// Fetches `url` with `curl` into a temporary file next to `path`, so an
+/// interrupted download never replaces a good cached copy. `hint` tells the
+/// user what to do with a copy downloaded by hand when `curl` cannot run or
+/// `--read-only` is set.
+pub fn download(url: &str, path: &Path, hint: &str) -> Result<(), ConversionError> {
+    if io_guard::is_read_only() {
+        return Err(ConversionError::InvalidFile(format!(
+            "Refusing to download {}: --read-only is set; download it and {}",
+            url, hint
+        )));
+    }
+    if let Some(dir) = path.parent() {
+        io_guard::create_dir_all(dir)?;
+    }
+    let partial = io_guard::partial_path(path);
+    let output = Command::new("curl")
+        .args(["-fsSL", "-o"])
+        .arg(&partial)
+        .arg(url)
+        .output()
+        .map_err(|e| {
+            ConversionError::ValidationError(format!("curl: {}; download {} and {}", e, url, hint))
+        })?;
+    if !output.status.success() {
+        let _ = io_guard::remove(&partial);
+        return Err(ConversionError::InvalidFile(format!(
+            "curl {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    io_guard::rename(&partial, path).inspect_err(|_| {
+        let _ = io_guard::remove(&partial);
+    })
+}
+
+pub fn compare(name: &str, baseline: &SysmonConfig, config: &SysmonConfig) -> Comparison {
+    let theirs = rules(baseline);
+    let ours = rules(config);
+    let mut unmatched: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, (key, _)) in ours.iter().enumerate().rev() {
+        unmatched.entry(key.as_str()).or_default().push(i);
+    }
+
+    let mut matched = vec![false; ours.len()];
+    let mut missing = Vec::new();
+    for (key, difference) in theirs.iter().map(|(k, d)| (k.as_str(), d)) {
+        match unmatched.get_mut(key).and_then(Vec::pop) {
+            Some(i) => matched[i] = true,
+            None => missing.push(difference.clone()),
+        }
+    }
+    let rules = ours.len();
+    let extra = ours
+        .into_iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|((_, difference), _)| difference)
+        .collect();
+
+    Comparison {
+        baseline: name.to_string(),
+        baseline_rules: theirs.len(),
+        rules,
+        shared: theirs.len() - missing.len(),
+        missing,
+        extra,
+    }
+}
+
+/// Every rule with the key it matches by: scope and conditions, values
+/// lowercased and a `<Rule>`'s conditions sorted.
+fn rules(config: &SysmonConfig) -> Vec<(String, Difference)> {
+    let mut rules = Vec::new();
+    for event in config.events() {
+        for filter in &event.filters {
+            let (name, key, rule) = match filter {
+                Filter::Field(field) => (field.name.as_deref(), key(field), condition(field)),
+                Filter::Rule(rule) => {
+                    let mut keys: Vec<String> = rule.fields.iter().map(key).collect();
+                    keys.sort();
+                    let text = format!(
+                        "Rule {}: {}",
+                        rule.group_relation.as_str(),
+                        rule.fields
+                            .iter()
+                            .map(condition)
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    );
+                    let key = format!("{}\0{}", rule.group_relation.as_str(), keys.join("\0"));
+                    (rule.name.as_deref(), key, text)
+                }
+            };
+            let scope = scope(event);
+            rules.push((
+                format!("{}\0{}", scope, key),
+                Difference {
+                    scope,
+                    rule,
+                    techniques: name.map(explain::techniques).unwrap_or_default(),
+                },
+            ));
+        }
+    }
+    rules
+}
+
+fn scope(event: &EventFilter) -> String {
+    format!("{}/{}", event.event, event.onmatch.as_str())
+}
+
+fn key(field: &FieldCondition) -> String {
+    format!(
+        "{}\0{}\0{}",
+        field.field,
+        field.condition.as_str(),
+        field.value.to_lowercase()
+    )
+}
+
+fn condition(field: &FieldCondition) -> String {
+    format!(
+        "{} {} {:?}",
+        field.field,
+        field.condition.as_str(),
+        field.value
+    )
+}
+
+fn render(comparison: &Comparison) -> String {
+    let percent = (comparison.shared * 100 + comparison.baseline_rules / 2)
+        .checked_div(comparison.baseline_rules)
+        .unwrap_or(100);
+    let mut out = format!(
+        "Against {}: {} of {} baseline rule(s) present ({}%), {} missing, {} extra\n",
+        comparison.baseline,
+        comparison.shared,
+        comparison.baseline_rules,
+        percent,
+        comparison.missing.len(),
+        comparison.extra.len()
+    );
+    let sections = [
+        (
+            format!("Missing (only in {})", comparison.baseline),
+            &comparison.missing,
+        ),
+        ("Extra (only in this config)".to_string(), &comparison.extra),
+    ];
+    for (title, differences) in sections {
+        if differences.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n{}:", title);
+        for d in differences {
+            let _ = write!(out, "  {}: {}", d.scope, d.rule);
+            if !d.techniques.is_empty() {
+                let _ = write!(out, " [{}]", d.techniques.join(", "));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(rules: &str) -> SysmonConfig {
+        SysmonConfig::from_xml_str(&format!(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">{}</RuleGroup></EventFiltering></Sysmon>"#,
+            rules
+        ))
+        .unwrap()
+    }
+
+    fn comparison() -> Comparison {
+        let baseline = config(
+            r#"<ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001,technique_name=PowerShell" condition="end with">\powershell.exe</Image>
+            <Image name="technique_id=T1003" condition="end with">\procdump.exe</Image>
+            <Rule groupRelation="and">
+            <Image condition="end with">\rundll32.exe</Image>
+            <CommandLine condition="contains">javascript:</CommandLine>
+            </Rule>
+            </ProcessCreate>"#,
+        );
+        let ours = config(
+            r#"<ProcessCreate onmatch="include">
+            <Image name="PowerShell" condition="end with">\PowerShell.exe</Image>
+            <Rule name="rundll32 script" groupRelation="and">
+            <CommandLine condition="contains">javascript:</CommandLine>
+            <Image condition="end with">\rundll32.exe</Image>
+            </Rule>
+            </ProcessCreate>
+            <DnsQuery onmatch="exclude"><QueryName condition="end with">.local</QueryName></DnsQuery>"#,
+        );
+        compare("swiftonsecurity", &baseline, &ours)
+    }
+
+    #[test]
+    fn test_compare_ignores_names_and_case() {
+        let comparison = comparison();
+        assert_eq!(
+            (
+                comparison.baseline_rules,
+                comparison.rules,
+                comparison.shared
+            ),
+            (3, 3, 2)
+        );
+        assert_eq!(
+            comparison.missing,
+            vec![Difference {
+                scope: "ProcessCreate/include".into(),
+                rule: r#"Image end with "\\procdump.exe""#.into(),
+                techniques: vec!["T1003".into()],
+            }]
+        );
+        assert_eq!(comparison.extra.len(), 1);
+        assert_eq!(comparison.extra[0].scope, "DnsQuery/exclude");
+    }
+
+    #[test]
+    fn test_render_lists_missing_and_extra_rules() {
+        let text = render(&comparison());
+        assert_eq!(
+            text,
+            r#"Against swiftonsecurity: 2 of 3 baseline rule(s) present (67%), 1 missing, 1 extra
+
+Missing (only in swiftonsecurity):
+  ProcessCreate/include: Image end with "\\procdump.exe" [T1003]
+
+Extra (only in this config):
+  DnsQuery/exclude: QueryName end with ".local"
+"#
+        );
+    }
+
+    #[test]
+    fn test_resolve_rejects_unknown_names() {
+        let err = resolve("no-such-baseline", false).unwrap_err().to_string();
+        assert!(err.contains("swiftonsecurity, sysmon-modular"));
+    }
+
+    #[test]
+    fn test_download_refused_under_read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("baselines").join("swiftonsecurity.xml");
+        let error = io_guard::with_read_only(|| {
+            download(BASELINES[0].1, &path, "pass its path to --against")
+        })
+        .unwrap_err();
+        assert!(error.to_string().contains("--read-only"), "{}", error);
+        assert!(!dir.path().join("baselines").exists());
+    }
+}
@@ -0,0 +1,89 @@
+//! Transparent gzip handling for single-file conversion.
+//!
+//! `config.xml.gz` is treated as `config.xml` that happens to be compressed:
+//! it is inflated in memory before conversion, and outputs ending in `.gz`
+//! are compressed after conversion.
+
+use crate::io_guard;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("gz"))
+        .unwrap_or(false)
+}
+
+/// `config.xml.gz` -> `config.xml`; other paths are returned unchanged.
+pub fn strip_gz(path: &Path) -> PathBuf {
+    if is_gzip(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Reads `path`, inflating it first if it is gzip-compressed.
+pub fn read(path: &Path) -> Result<Vec<u8>, ConversionError> {
+    let input = File::open(path).map_err(|e| ConversionError::io_error(path, e))?;
+    let mut content = Vec::new();
+    let result = if is_gzip(path) {
+        GzDecoder::new(BufReader::new(input)).read_to_end(&mut content)
+    } else {
+        BufReader::new(input).read_to_end(&mut content)
+    };
+    result.map_err(|e| ConversionError::io_error(path, e))?;
+    Ok(content)
+}
+
+/// Writes `content` to `path`, compressing it if `path` ends in `.gz`.
+pub fn write(path: &Path, content: &[u8]) -> Result<(), ConversionError> {
+    if !is_gzip(path) {
+        return io_guard::write(path, content);
+    }
+    let output = io_guard::create(path)?;
+    let mut encoder = GzEncoder::new(BufWriter::new(output), Compression::default());
+    encoder
+        .write_all(content)
+        .and_then(|_| encoder.finish())
+        .and_then(|mut out| out.flush())
+        .map_err(|e| ConversionError::io_error(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_strip_gz() {
+        assert_eq!(
+            strip_gz(Path::new("merged.xml.gz")),
+            PathBuf::from("merged.xml")
+        );
+        assert_eq!(
+            strip_gz(Path::new("merged.xml")),
+            PathBuf::from("merged.xml")
+        );
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let plain = temp_dir.path().join("config.xml");
+        let packed = temp_dir.path().join("config.xml.gz");
+        fs::write(&plain, "<Sysmon schemaversion=\"4.90\"/>").unwrap();
+
+        write(&packed, &fs::read(&plain).unwrap()).unwrap();
+        assert!(is_gzip(&packed));
+        assert_eq!(read(&packed).unwrap(), read(&plain).unwrap());
+        assert_eq!(read(&plain).unwrap(), b"<Sysmon schemaversion=\"4.90\"/>");
+    }
+}
@@ -0,0 +1,73 @@
+//! String-in, string-out conversion.
+//!
+//! `sysmon_json` only converts and preprocesses files. These wrappers hide
+//! that behind a private temporary directory, which `--read-only` refuses,
+//! so the rest of the CLI can hold documents in memory and decide
+//! separately where (and whether) anything gets written. The output is
+//! exactly what `sysmon_json::convert_file` writes, the layout earlier
+//! releases wrote and committed mirrors hold. The library's in-memory
+//! [`sysmon_cli::convert_xml_str_to_json`] writes the typed model's JSON
+//! instead, a different layout, and is not used here.
+
+use crate::io_guard;
+use std::path::PathBuf;
+use sysmon_cli::format::Format;
+use sysmon_cli::preprocess;
+use sysmon_json::{convert_file, error::ConversionError, preprocessor::preprocess_config};
+
+pub fn xml_str_to_json(xml: &str) -> Result<String, ConversionError> {
+    convert_via_files(xml, Format::Xml)
+}
+
+pub fn json_str_to_xml(json: &str) -> Result<String, ConversionError> {
+    convert_via_files(json, Format::Json)
+}
+
+/// Converts `text`, a document in `from`, to the other format.
+pub fn convert_str(text: &str, from: Format) -> Result<String, ConversionError> {
+    match from {
+        Format::Xml => xml_str_to_json(text),
+        Format::Json => json_str_to_xml(text),
+    }
+}
+
+fn convert_via_files(text: &str, from: Format) -> Result<String, ConversionError> {
+    let (dir, input) = scratch_file(text, from)?;
+    let output = dir
+        .path()
+        .join(format!("output.{}", from.target().extension()));
+    convert_file(input.as_path(), output.as_path())?;
+    std::fs::read_to_string(&output).map_err(|e| ConversionError::io_error(&output, e))
+}
+
+/// Runs `sysmon_json`'s `preprocess_config` over `text`.
+pub fn preprocess_str(text: &str, format: Format) -> Result<String, ConversionError> {
+    let (_dir, input) = scratch_file(text, format)?;
+    preprocess_config(&input).map_err(|e| preprocess::library_error(&input, e))
+}
+
+fn scratch_file(
+    text: &str,
+    format: Format,
+) -> Result<(io_guard::TempDir, PathBuf), ConversionError> {
+    let dir = io_guard::tempdir(&std::env::temp_dir())?;
+    let path = dir.path().join(format!("input.{}", format.extension()));
+    io_guard::write(&path, text)?;
+    Ok((dir, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_round_trip() {
+        let xml = r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include"><Image condition="end with">\cmd.exe</Image></ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#;
+        let json = xml_str_to_json(xml).unwrap();
+        assert!(json.contains("cmd.exe"));
+
+        let back = json_str_to_xml(&json).unwrap();
+        assert!(back.contains("schemaversion=\"4.90\""));
+        assert!(back.contains("cmd.exe"));
+    }
+}
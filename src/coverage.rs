@@ -0,0 +1,355 @@
+//! `coverage-diff`: how detection coverage changed between two versions of
+//! a config.
+//!
+//! Coverage is counted the way `stats` counts it: rules per event type and
+//! `onmatch`, and rules citing each ATT&CK technique in their names. Only
+//! counts that changed are reported, so the output reads as a changelog:
+//! techniques and event filters that are new, that are gone, and that
+//! gained or lost rules. Techniques are named, and their citations summed
+//! per tactic, from the ATT&CK data (see [`crate::attack`]). `--format
+//! markdown` is meant for release notes.
+
+use crate::attack::{self, Database};
+use crate::stats::{self, Stats};
+use crate::{io_guard, validate};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use sysmon_cli::model::SysmonConfig;
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CoverageFormat {
+    Text,
+    Markdown,
+    Json,
+}
+
+#[derive(Args)]
+pub struct CoverageDiffArgs {
+    /// The earlier version of the config
+    pub old: PathBuf,
+
+    /// The later version of the config
+    pub new: PathBuf,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: CoverageFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// A rule count that differs between the two configs.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct CoverageChange {
+    /// `ProcessCreate/include`, a technique ID such as `T1059.001`, or a
+    /// tactic such as `Execution`.
+    pub name: String,
+    /// The technique's name, when the ATT&CK data knows it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub old: usize,
+    pub new: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoverageDiff {
+    /// In order of first appearance, old config first.
+    pub events: Vec<CoverageChange>,
+    /// By technique ID.
+    pub techniques: Vec<CoverageChange>,
+    /// Technique citations per tactic, in kill-chain order.
+    pub tactics: Vec<CoverageChange>,
+}
+
+pub fn run(args: &CoverageDiffArgs) -> Result<(), ConversionError> {
+    let load = |path: &Path| -> Result<Stats, ConversionError> {
+        let config = SysmonConfig::from_xml_str(&validate::load(path)?.0)?;
+        Ok(stats::stats(&config, 0))
+    };
+    let diff = compare(&load(&args.old)?, &load(&args.new)?, &Database::load());
+
+    let text = match args.format {
+        CoverageFormat::Text => render_text(&diff),
+        CoverageFormat::Markdown => render_markdown(&diff),
+        CoverageFormat::Json => serde_json::to_string_pretty(&diff)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+pub fn compare(old: &Stats, new: &Stats, attack: &Database) -> CoverageDiff {
+    let filters = |stats: &Stats| {
+        stats
+            .events
+            .iter()
+            .flat_map(|e| {
+                [
+                    (format!("{}/include", e.event), e.include),
+                    (format!("{}/exclude", e.event), e.exclude),
+                ]
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect::<Vec<_>>()
+    };
+    let (old_filters, new_filters) = (filters(old), filters(new));
+    let mut events = Vec::new();
+    for (name, _) in old_filters.iter().chain(&new_filters) {
+        if events.iter().any(|c: &CoverageChange| c.name == *name) {
+            continue;
+        }
+        let count = |filters: &[(String, usize)]| {
+            filters
+                .iter()
+                .find(|(n, _)| n == name)
+                .map_or(0, |(_, count)| *count)
+        };
+        events.push(CoverageChange {
+            name: name.clone(),
+            title: None,
+            old: count(&old_filters),
+            new: count(&new_filters),
+        });
+    }
+    events.retain(|c| c.old != c.new);
+
+    let mut techniques: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for technique in &old.top_techniques {
+        techniques.entry(&technique.name).or_default().0 = technique.count;
+    }
+    for technique in &new.top_techniques {
+        techniques.entry(&technique.name).or_default().1 = technique.count;
+    }
+    let mut tactics = vec![(0, 0); attack::TACTIC_NAMES.len()];
+    for (id, (old, new)) in &techniques {
+        for tactic in attack.tactics(id) {
+            if let Some(i) = attack::TACTIC_NAMES.iter().position(|(t, _)| t == tactic) {
+                tactics[i].0 += old;
+                tactics[i].1 += new;
+            }
+        }
+    }
+    let tactics = attack::TACTIC_NAMES
+        .iter()
+        .zip(tactics)
+        .filter(|(_, (old, new))| old != new)
+        .map(|((_, name), (old, new))| CoverageChange {
+            name: name.to_string(),
+            title: None,
+            old,
+            new,
+        })
+        .collect();
+    let techniques = techniques
+        .into_iter()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(name, (old, new))| CoverageChange {
+            name: name.to_string(),
+            title: attack.name(name).map(str::to_string),
+            old,
+            new,
+        })
+        .collect();
+
+    CoverageDiff {
+        events,
+        techniques,
+        tactics,
+    }
+}
+
+/// `T1003 (OS Credential Dumping)`, or just the name when there is no title.
+fn label(c: &CoverageChange) -> String {
+    match &c.title {
+        Some(title) => format!("{} ({})", c.name, title),
+        None => c.name.clone(),
+    }
+}
+
+fn change(c: &CoverageChange) -> String {
+    let delta = c.new as isize - c.old as isize;
+    let note = match (c.old, c.new) {
+        (0, _) => ", new",
+        (_, 0) => ", gone",
+        _ => "",
+    };
+    format!("{}: {} -> {} ({:+}{})", label(c), c.old, c.new, delta, note)
+}
+
+fn render_text(diff: &CoverageDiff) -> String {
+    if diff.events.is_empty() && diff.techniques.is_empty() {
+        return "No coverage changes\n".to_string();
+    }
+    let mut out = String::new();
+    for (title, changes) in [
+        ("Techniques (rules)", &diff.techniques),
+        ("Tactics (technique citations)", &diff.tactics),
+        ("Event filters (rules)", &diff.events),
+    ] {
+        if changes.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "{}:", title);
+        for c in changes {
+            let _ = writeln!(out, "  {}", change(c));
+        }
+    }
+    let gained = diff.techniques.iter().filter(|c| c.old == 0).count();
+    let lost = diff.techniques.iter().filter(|c| c.new == 0).count();
+    let _ = writeln!(
+        out,
+        "{} technique(s) gained, {} lost, {} with changed rule counts",
+        gained,
+        lost,
+        diff.techniques.len() - gained - lost
+    );
+    out
+}
+
+fn render_markdown(diff: &CoverageDiff) -> String {
+    let mut out = String::from("## Detection coverage\n");
+    if diff.events.is_empty() && diff.techniques.is_empty() {
+        out.push_str("\nNo coverage changes.\n");
+        return out;
+    }
+    let section = |c: &CoverageChange| match (c.old, c.new) {
+        (0, _) => 0,
+        (_, 0) => 1,
+        (old, new) if new > old => 2,
+        _ => 3,
+    };
+    let titles = [
+        "New techniques",
+        "Techniques no longer covered",
+        "More rules",
+        "Fewer rules",
+    ];
+    for (i, title) in titles.into_iter().enumerate() {
+        let items: Vec<String> = diff
+            .techniques
+            .iter()
+            .filter(|c| section(c) == i)
+            .map(|c| match (c.old, c.new) {
+                (0, new) => format!("- {}: {} rule(s)", label(c), new),
+                (old, 0) => format!("- {}: had {} rule(s)", label(c), old),
+                (old, new) => format!("- {}: {} → {} rule(s)", label(c), old, new),
+            })
+            .collect();
+        if !items.is_empty() {
+            let _ = write!(out, "\n### {}\n\n{}\n", title, items.join("\n"));
+        }
+    }
+    for (title, column, changes) in [
+        ("Tactics", "Tactic", &diff.tactics),
+        ("Event filters", "Filter", &diff.events),
+    ] {
+        if changes.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n### {}\n", title);
+        let _ = writeln!(out, "| {} | Before | After |\n|---|---:|---:|", column);
+        for c in changes {
+            let _ = writeln!(out, "| {} | {} | {} |", c.name, c.old, c.new);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_of(rules: &str) -> Stats {
+        let config = SysmonConfig::from_xml_str(&format!(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">{}</RuleGroup></EventFiltering></Sysmon>"#,
+            rules
+        ))
+        .unwrap();
+        stats::stats(&config, 0)
+    }
+
+    fn diff() -> CoverageDiff {
+        let old = stats_of(
+            r#"<ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001" condition="end with">\powershell.exe</Image>
+            <Image name="technique_id=T1003" condition="end with">\procdump.exe</Image>
+            </ProcessCreate>
+            <DnsQuery onmatch="exclude"><QueryName condition="end with">.local</QueryName></DnsQuery>"#,
+        );
+        let new = stats_of(
+            r#"<ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001" condition="end with">\powershell.exe</Image>
+            <Image name="technique_id=T1059.001" condition="end with">\pwsh.exe</Image>
+            <Image name="technique_id=T1569.002" condition="end with">\psexesvc.exe</Image>
+            </ProcessCreate>
+            <DnsQuery onmatch="exclude"><QueryName condition="end with">.local</QueryName></DnsQuery>"#,
+        );
+        compare(&old, &new, &Database::bundled())
+    }
+
+    #[test]
+    fn test_compare_reports_changed_counts_only() {
+        let diff = diff();
+        let change = |name: &str, title: Option<&str>, old, new| CoverageChange {
+            name: name.into(),
+            title: title.map(Into::into),
+            old,
+            new,
+        };
+        assert_eq!(
+            diff.events,
+            vec![change("ProcessCreate/include", None, 2, 3)]
+        );
+        assert_eq!(
+            diff.techniques,
+            vec![
+                change("T1003", Some("OS Credential Dumping"), 1, 0),
+                change("T1059.001", Some("PowerShell"), 1, 2),
+                change("T1569.002", Some("Service Execution"), 0, 1),
+            ]
+        );
+        assert_eq!(
+            diff.tactics,
+            vec![
+                change("Execution", None, 1, 3),
+                change("Credential Access", None, 1, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_text_and_markdown() {
+        let text = render_text(&diff());
+        assert!(text.starts_with(
+            "Techniques (rules):\n  T1003 (OS Credential Dumping): 1 -> 0 (-1, gone)\n"
+        ));
+        assert!(text.contains("  T1569.002 (Service Execution): 0 -> 1 (+1, new)\n"));
+        assert!(text.contains("Tactics (technique citations):\n  Execution: 1 -> 3 (+2)\n"));
+        assert!(text.ends_with("1 technique(s) gained, 1 lost, 1 with changed rule counts\n"));
+
+        let markdown = render_markdown(&diff());
+        assert!(
+            markdown.contains("### New techniques\n\n- T1569.002 (Service Execution): 1 rule(s)\n")
+        );
+        assert!(markdown.contains(
+            "### Techniques no longer covered\n\n- T1003 (OS Credential Dumping): had 1 rule(s)\n"
+        ));
+        assert!(markdown.contains("| Execution | 1 | 3 |\n"));
+        assert!(markdown.contains("| ProcessCreate/include | 2 | 3 |\n"));
+
+        let unchanged = compare(&stats_of(""), &stats_of(""), &Database::bundled());
+        assert_eq!(render_text(&unchanged), "No coverage changes\n");
+    }
+}
@@ -0,0 +1,190 @@
+//! `deploy` (Windows only): apply a configuration with `sysmon -c`.
+//!
+//! The config is read once and checked before anything changes on the host:
+//! it must pass the `validate` checks, the bytes read must match its
+//! detached signature when `--signature` is given, and the prompt must be
+//! elevated. Those same bytes are what Sysmon is given. The driver's
+//! registry parameters are exported first, as in `dump-live`, so that if
+//! Sysmon rejects the new config the export is imported again unchanged.
+//! JSON configs are converted to XML first, since that is all Sysmon reads.
+
+use crate::live::{self, DEFAULT_DRIVER};
+use crate::{convert, encoding, io_guard};
+use clap::Args;
+use log::{info, warn};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use sysmon_cli::format::{self, Format};
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::validation::{self, Severity};
+use sysmon_json::error::ConversionError;
+
+#[derive(Args)]
+pub struct DeployArgs {
+    /// Sysmon configuration to apply (XML or JSON)
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Detached OpenPGP signature of the config, checked with `gpg --verify`
+    #[arg(long, value_name = "PATH")]
+    pub signature: Option<PathBuf>,
+
+    /// Sysmon executable (default: sysmon64.exe, then sysmon.exe, from PATH)
+    #[arg(long)]
+    pub sysmon: Option<PathBuf>,
+
+    /// Name of the Sysmon driver service
+    #[arg(long, default_value = DEFAULT_DRIVER)]
+    pub driver: String,
+
+    /// Apply without exporting the running config first, so a failure cannot be rolled back
+    #[arg(long)]
+    pub no_rollback: bool,
+}
+
+pub fn run(args: &DeployArgs) -> Result<(), ConversionError> {
+    let bytes =
+        std::fs::read(&args.config).map_err(|e| ConversionError::io_error(&args.config, e))?;
+    if let Some(signature) = &args.signature {
+        verify_signature(&bytes, &args.config, signature)?;
+    }
+    let (xml, staged_bytes) = load(&args.config, bytes)?;
+    let config = SysmonConfig::from_xml_str(&xml)?;
+    check(&config, &args.config)?;
+
+    let sysmon = live::find_sysmon(args.sysmon.as_deref())?;
+    if io_guard::is_read_only() {
+        println!(
+            "{} would be applied with {} -c",
+            args.config.display(),
+            sysmon.display()
+        );
+        return Ok(());
+    }
+    ensure_elevated()?;
+
+    // The registry export as it is, rather than decoded rules, so a
+    // rollback restores exactly what was running.
+    let previous = if args.no_rollback {
+        None
+    } else {
+        let previous = live::export_parameters(&args.driver).map_err(|e| {
+            ConversionError::Other(format!(
+                "Could not export the running config for rollback ({}); \
+                 pass --no-rollback to deploy anyway",
+                e
+            ))
+        })?;
+        Some(previous)
+    };
+
+    let staging = io_guard::tempdir(&args.config)?;
+    let staged = staging.path().join("sysmon-config.xml");
+    io_guard::write(&staged, &staged_bytes)?;
+    info!(
+        "Applying {} with {}",
+        args.config.display(),
+        sysmon.display()
+    );
+    let Err(e) = apply(&sysmon, &staged) else {
+        info!("Deployed {}", args.config.display());
+        return Ok(());
+    };
+
+    let Some(previous) = previous else {
+        return Err(e);
+    };
+    warn!("Sysmon rejected the new config; restoring the previous one");
+    let restore = staging.path().join("previous-parameters.reg");
+    io_guard::write(&restore, previous)?;
+    match live::import_parameters(&restore) {
+        Ok(()) => Err(ConversionError::Other(format!(
+            "Deploy failed and the previous config was restored: {}",
+            e
+        ))),
+        Err(rollback) => Err(ConversionError::Other(format!(
+            "Deploy failed: {}; restoring the previous config also failed: {}",
+            e, rollback
+        ))),
+    }
+}
+
+/// The config as XML text, and the bytes to hand Sysmon: `bytes` unchanged
+/// for an XML config, or the converted XML for a JSON one.
+fn load(path: &Path, bytes: Vec<u8>) -> Result<(String, Vec<u8>), ConversionError> {
+    let text = encoding::decode(&bytes)
+        .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", path.display(), e)))?;
+    match format::detect(path, text.as_bytes())? {
+        Format::Xml => Ok((text, bytes)),
+        Format::Json => {
+            let xml = convert::json_str_to_xml(&text)?;
+            let bytes = xml.clone().into_bytes();
+            Ok((xml, bytes))
+        }
+    }
+}
+
+/// Fails on validation errors; warnings are logged.
+fn check(config: &SysmonConfig, path: &Path) -> Result<(), ConversionError> {
+    let issues = validation::validate(config);
+    for issue in &issues {
+        warn!("{}: {}", issue.location, issue.message);
+    }
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(ConversionError::ValidationError(format!(
+            "{} has {} validation error(s); not deploying",
+            path.display(),
+            errors
+        )));
+    }
+    Ok(())
+}
+
+/// Checks `signature` against `bytes`, fed to `gpg` on stdin, so what is
+/// verified is what gets applied even if `config` changes in the meantime.
+fn verify_signature(bytes: &[u8], config: &Path, signature: &Path) -> Result<(), ConversionError> {
+    let gpg_error = |e| ConversionError::io_error(Path::new("gpg"), e);
+    let mut child = Command::new("gpg")
+        .arg("--verify")
+        .arg(signature)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(gpg_error)?;
+    let written = child.stdin.take().expect("stdin is piped").write_all(bytes);
+    let output = child.wait_with_output().map_err(gpg_error)?;
+    if !output.status.success() || written.is_err() {
+        return Err(ConversionError::ValidationError(format!(
+            "Signature check failed for {}: {}",
+            config.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    info!("Signature verified: {}", signature.display());
+    Ok(())
+}
+
+/// `net session` only succeeds with administrator rights.
+fn ensure_elevated() -> Result<(), ConversionError> {
+    let elevated = Command::new("net")
+        .arg("session")
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !elevated {
+        return Err(ConversionError::Other(
+            "deploy must run from an elevated prompt".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn apply(sysmon: &Path, config: &Path) -> Result<(), ConversionError> {
+    live::run_sysmon(sysmon, &["-c".as_ref(), config.as_os_str()]).map(|_| ())
+}
@@ -0,0 +1,286 @@
+//! `export deploy-script`: a PowerShell script that installs Sysmon with a
+//! given configuration.
+//!
+//! The script is generated from the config, so it cannot fall out of step
+//! with it: the config is embedded (base64, so the bytes and their SHA-256
+//! survive any line-ending conversion), and the oldest Sysmon release that
+//! accepts its `schemaversion` is baked in. Run on a host, the script
+//! downloads Sysmon and checks its hash, installs it, updates it in place
+//! when the download is newer than the installed driver, or only applies the
+//! config when it is not. Everything a deployment varies is a script
+//! parameter.
+
+use crate::{field_map, io_guard, validate};
+use clap::{Args, Subcommand};
+use log::info;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::schema::{self, Version};
+use sysmon_cli::validation::{self, Severity};
+use sysmon_json::error::ConversionError;
+
+const DEFAULT_SYSMON_URL: &str = "https://download.sysinternals.com/files/Sysmon.zip";
+
+const TEMPLATE: &str = r#"<#
+.SYNOPSIS
+    Installs or updates Sysmon and applies the embedded configuration.
+.NOTES
+    Generated by sysmon_cli {{VERSION}}. Do not edit: regenerate from the config instead.
+    Config schema {{SCHEMA}}, SHA-256 {{CONFIG_SHA256}}
+    Requires Sysmon {{MIN_SYSMON}} or later.
+#>
+[CmdletBinding()]
+param(
+    [string]$SysmonUrl = '{{SYSMON_URL}}',
+    # SHA-256 of the download; leave empty to skip the check (not recommended)
+    [string]$SysmonSha256 = '{{SYSMON_SHA256}}',
+    [string]$InstallDir = (Join-Path $env:ProgramData 'Sysmon'),
+    # Only apply the config to the installed Sysmon; do not download or install
+    [switch]$ConfigOnly
+)
+
+$ErrorActionPreference = 'Stop'
+$MinimumSysmon = '{{MIN_SYSMON}}'
+$ConfigSha256 = '{{CONFIG_SHA256}}'
+$ConfigBase64 = @'
+{{CONFIG_BASE64}}
+'@
+
+function ConvertTo-SysmonVersion([string]$Text) {
+    $parts = $Text -split '\.'
+    $minor = if ($parts.Count -gt 1) { $parts[1] } else { '0' }
+    if ($minor.Length -eq 1) { $minor += '0' }
+    [int]$parts[0] * 100 + [int]$minor
+}
+
+function Assert-SysmonVersion([string]$Exe) {
+    $version = (Get-Item $Exe).VersionInfo.FileVersion
+    if ((ConvertTo-SysmonVersion $version) -lt (ConvertTo-SysmonVersion $MinimumSysmon)) {
+        throw "$Exe is Sysmon $version; this config needs $MinimumSysmon or later"
+    }
+}
+
+function Invoke-Sysmon([string]$Exe, [string[]]$Arguments) {
+    & $Exe -accepteula @Arguments
+    if ($LASTEXITCODE -ne 0) { throw "$Exe $Arguments failed with exit code $LASTEXITCODE" }
+}
+
+$principal = [Security.Principal.WindowsPrincipal][Security.Principal.WindowsIdentity]::GetCurrent()
+if (-not $principal.IsInRole([Security.Principal.WindowsBuiltInRole]::Administrator)) {
+    throw 'Run this script from an elevated PowerShell prompt.'
+}
+
+New-Item -ItemType Directory -Force -Path $InstallDir | Out-Null
+$configFile = Join-Path $InstallDir 'sysmonconfig.xml'
+[IO.File]::WriteAllBytes($configFile, [Convert]::FromBase64String(($ConfigBase64 -replace '\s', '')))
+if ((Get-FileHash -Algorithm SHA256 -Path $configFile).Hash -ne $ConfigSha256) {
+    throw "The embedded config does not match its SHA-256; regenerate this script."
+}
+
+$exeName = if ([Environment]::Is64BitOperatingSystem) { 'Sysmon64.exe' } else { 'Sysmon.exe' }
+$serviceName = [IO.Path]::GetFileNameWithoutExtension($exeName)
+$service = Get-CimInstance Win32_Service -Filter "Name='$serviceName'"
+$installed = if ($service) { $service.PathName.Trim('"') }
+
+if ($ConfigOnly) {
+    if (-not $installed) { throw "Sysmon is not installed; run without -ConfigOnly" }
+    Assert-SysmonVersion $installed
+    Invoke-Sysmon $installed @('-c', $configFile)
+    Write-Host "Applied config to $installed"
+    return
+}
+
+$download = Join-Path ([IO.Path]::GetTempPath()) ("sysmon-" + [Guid]::NewGuid())
+New-Item -ItemType Directory -Path $download | Out-Null
+try {
+    $zip = Join-Path $download 'Sysmon.zip'
+    Invoke-WebRequest -Uri $SysmonUrl -OutFile $zip -UseBasicParsing
+    if ($SysmonSha256) {
+        if ((Get-FileHash -Algorithm SHA256 -Path $zip).Hash -ne $SysmonSha256) {
+            throw "SHA-256 of $SysmonUrl does not match -SysmonSha256"
+        }
+    } else {
+        Write-Warning 'No -SysmonSha256 given; the download is not verified.'
+    }
+    Expand-Archive -Path $zip -DestinationPath $download -Force
+    $exe = Join-Path $download $exeName
+    Assert-SysmonVersion $exe
+
+    if (-not $installed) {
+        Copy-Item $exe (Join-Path $InstallDir $exeName) -Force
+        Invoke-Sysmon (Join-Path $InstallDir $exeName) @('-i', $configFile)
+        Write-Host "Installed Sysmon $((Get-Item $exe).VersionInfo.FileVersion)"
+    } elseif ((ConvertTo-SysmonVersion (Get-Item $exe).VersionInfo.FileVersion) -gt
+              (ConvertTo-SysmonVersion (Get-Item $installed).VersionInfo.FileVersion)) {
+        Invoke-Sysmon $installed @('-u')
+        Copy-Item $exe (Join-Path $InstallDir $exeName) -Force
+        Invoke-Sysmon (Join-Path $InstallDir $exeName) @('-i', $configFile)
+        Write-Host "Updated Sysmon to $((Get-Item $exe).VersionInfo.FileVersion)"
+    } else {
+        Assert-SysmonVersion $installed
+        Invoke-Sysmon $installed @('-c', $configFile)
+        Write-Host "Applied config to $installed"
+    }
+} finally {
+    Remove-Item -Recurse -Force $download -ErrorAction SilentlyContinue
+}
+"#;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[command(subcommand)]
+    pub command: ExportCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ExportCommand {
+    /// PowerShell script that installs or updates Sysmon with this config
+    DeployScript(DeployScriptArgs),
+    /// Event IDs and fields the config makes Sysmon emit, with ECS and Splunk CIM names
+    FieldMap(field_map::FieldMapArgs),
+}
+
+#[derive(Args)]
+pub struct DeployScriptArgs {
+    /// Configuration to embed (XML or JSON)
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Default download location of the Sysmon archive
+    #[arg(long, default_value = DEFAULT_SYSMON_URL)]
+    pub sysmon_url: String,
+
+    /// Default SHA-256 of the Sysmon archive
+    #[arg(long, value_name = "HEX")]
+    pub sysmon_sha256: Option<String>,
+
+    /// Write the script here instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn run(args: &ExportArgs) -> Result<(), ConversionError> {
+    match &args.command {
+        ExportCommand::DeployScript(args) => run_deploy_script(args),
+        ExportCommand::FieldMap(args) => field_map::run(args),
+    }
+}
+
+fn run_deploy_script(args: &DeployScriptArgs) -> Result<(), ConversionError> {
+    let xml = validate::load(&args.config)?.0;
+    let config = SysmonConfig::from_xml_str(&xml)?;
+    let errors = validation::validate(&config)
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(ConversionError::ValidationError(format!(
+            "{} has {} validation error(s); run `validate` for details",
+            args.config.display(),
+            errors
+        )));
+    }
+
+    let script = render(&config.schema_version, xml.as_bytes(), args)?;
+    match &args.output {
+        Some(path) => {
+            io_guard::check_write(path)?;
+            io_guard::write(path, script)?;
+            info!("Wrote {}", path.display());
+        }
+        None => print!("{}", script),
+    }
+    Ok(())
+}
+
+fn render(schema: &str, config: &[u8], args: &DeployScriptArgs) -> Result<String, ConversionError> {
+    let version: Version = schema.parse().map_err(ConversionError::ValidationError)?;
+    let min_sysmon = schema::min_sysmon_for(version).ok_or_else(|| {
+        ConversionError::ValidationError(format!(
+            "No known Sysmon release accepts schema {}",
+            schema
+        ))
+    })?;
+
+    let sha256 = args.sysmon_sha256.as_deref().unwrap_or_default();
+    if !sha256.chars().all(|c| c.is_ascii_hexdigit()) || !matches!(sha256.len(), 0 | 64) {
+        return Err(ConversionError::ValidationError(
+            "--sysmon-sha256 must be 64 hex digits".to_string(),
+        ));
+    }
+    if args.sysmon_url.contains('\'') {
+        return Err(ConversionError::ValidationError(
+            "--sysmon-url must not contain quotes".to_string(),
+        ));
+    }
+
+    let config_sha256: String = Sha256::digest(config)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    let encoded = base64(config);
+    let wrapped: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| std::str::from_utf8(line).unwrap_or_default())
+        .collect();
+
+    Ok(TEMPLATE
+        .replace("{{VERSION}}", env!("CARGO_PKG_VERSION"))
+        .replace("{{SCHEMA}}", schema)
+        .replace("{{MIN_SYSMON}}", &min_sysmon.to_string())
+        .replace("{{SYSMON_URL}}", &args.sysmon_url)
+        .replace("{{SYSMON_SHA256}}", &sha256.to_ascii_uppercase())
+        .replace("{{CONFIG_SHA256}}", &config_sha256)
+        .replace("{{CONFIG_BASE64}}", &wrapped.join("\n")))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_render_fills_parameters() {
+        let args = DeployScriptArgs {
+            config: PathBuf::from("merged.xml"),
+            sysmon_url: DEFAULT_SYSMON_URL.to_string(),
+            sysmon_sha256: Some("ab".repeat(32)),
+            output: None,
+        };
+        let script = render("4.50", b"<Sysmon/>", &args).unwrap();
+        assert!(script.contains("Requires Sysmon 13.00 or later."));
+        assert!(script.contains(&format!("$SysmonSha256 = '{}'", "AB".repeat(32))));
+        assert!(script.contains("\nPFN5c21vbi8+\n'@"));
+        assert!(!script.contains("{{"));
+
+        assert!(render("9.99", b"<Sysmon/>", &args).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! Loading and saving Sysmon configurations as XML element trees.
+//!
+//! Commands that inspect or rewrite a configuration work on the XML form.
+//! JSON files are converted through `sysmon_json` on the way in and out, so
+//! callers never need to care which format the user handed them.
+
+use crate::{convert, encoding, io_guard};
+use std::path::Path;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, EmitterConfig, XMLNode};
+
+pub fn is_json(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+/// Parses a configuration file (XML or JSON) into its root `<Sysmon>` element.
+pub fn load(path: &Path) -> Result<Element, ConversionError> {
+    let bytes = std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+    let mut text = encoding::decode(&bytes)
+        .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", path.display(), e)))?;
+    if is_json(path) {
+        text = convert::json_str_to_xml(&text)?;
+    }
+    Element::parse(text.as_bytes())
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))
+}
+
+/// Writes `root` to `path`, as JSON if the path ends in `.json` and as
+/// indented XML otherwise.
+pub fn save(path: &Path, root: &Element) -> Result<(), ConversionError> {
+    let mut text = to_xml_string(root)?;
+    if is_json(path) {
+        text = convert::xml_str_to_json(&text)?;
+    }
+    io_guard::write(path, text)
+}
+
+/// Serializes `root` as indented XML.
+pub fn to_xml_string(root: &Element) -> Result<String, ConversionError> {
+    let mut buffer = Vec::new();
+    root.write_with_config(&mut buffer, EmitterConfig::new().perform_indent(true))
+        .map_err(|e| ConversionError::ParserError(e.to_string()))?;
+    String::from_utf8(buffer).map_err(|e| ConversionError::ParserError(e.to_string()))
+}
+
+/// Serializes `element` as an indented fragment, without an XML declaration.
+pub fn to_fragment_string(element: &Element) -> Result<String, ConversionError> {
+    let mut buffer = Vec::new();
+    let config = EmitterConfig::new()
+        .perform_indent(true)
+        .write_document_declaration(false);
+    element
+        .write_with_config(&mut buffer, config)
+        .map_err(|e| ConversionError::ParserError(e.to_string()))?;
+    String::from_utf8(buffer).map_err(|e| ConversionError::ParserError(e.to_string()))
+}
+
+/// Iterates over the element children of `element`, skipping text and comments.
+pub fn child_elements(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(|node| match node {
+        XMLNode::Element(child) => Some(child),
+        _ => None,
+    })
+}
+
+pub fn has_child_elements(element: &Element) -> bool {
+    child_elements(element).next().is_some()
+}
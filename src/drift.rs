@@ -0,0 +1,684 @@
+//! `drift`: compare a host's configuration with the golden one.
+//!
+//! The comparison is per rule, within each event type and `onmatch`, so
+//! moving rules between rule groups or reordering them is not drift. A rule
+//! that carries a `name` is matched by name, an unnamed field condition by
+//! its field and value, so a changed value or condition shows up as a
+//! modification rather than a removal plus an addition. Unnamed `<Rule>`
+//! combinations only match when identical, and a rule moved into or out of
+//! a `groupRelation="and"` group has changed. The schema version and global
+//! options are compared too. Any deviation makes the command fail, which is
+//! what a scheduled compliance check needs.
+//!
+//! A `dump-live` capture carries no rule or group names, since the registry
+//! does not keep them. When the current config has none, the baseline's are
+//! dropped too before rules are paired, so a host running the baseline
+//! shows no drift.
+//!
+//! With `--emit-patch` the rule changes come out as a `patch` file instead,
+//! so the same change can be applied to another copy of the baseline. A
+//! modified condition becomes a `replace`, unless its field changed.
+//! Operations name the rule group a rule is in, by name and
+//! `groupRelation`, so they do not touch a same-valued rule elsewhere.
+
+use crate::edit::{Changes, NewRule, Selector};
+use crate::patch::{Operation, Replacement};
+use crate::{io_guard, validate};
+use clap::{Args, ValueEnum};
+use log::{info, warn};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::{
+    EventFilter, FieldCondition, Filter, GroupRelation, RuleGroup, SysmonConfig,
+};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DriftFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct DriftArgs {
+    /// The golden configuration
+    #[arg(long)]
+    pub baseline: PathBuf,
+
+    /// The configuration found on the host, e.g. from `dump-live`
+    #[arg(long)]
+    pub current: PathBuf,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: DriftFormat,
+
+    /// Instead of a report, write the rule changes as a `patch` file that
+    /// turns the baseline into the current config
+    #[arg(long, conflicts_with = "format")]
+    pub emit_patch: bool,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Deviation {
+    pub change: Change,
+    /// `ProcessCreate/include`, or `Sysmon` for global settings.
+    pub scope: String,
+    /// The rule or setting in the baseline, if it has one there.
+    pub baseline: Option<String>,
+    /// The rule or setting in the current config, if it has one there.
+    pub current: Option<String>,
+}
+
+pub fn run(args: &DriftArgs) -> Result<(), ConversionError> {
+    let load = |path: &PathBuf| SysmonConfig::from_xml_str(&validate::load(path)?.0);
+    let (mut baseline, current) = (load(&args.baseline)?, load(&args.current)?);
+    if !has_names(&current) && has_names(&baseline) {
+        info!(
+            "{} has no rule names, as in a dump-live capture; comparing without them",
+            args.current.display()
+        );
+        drop_names(&mut baseline);
+    }
+    if args.emit_patch {
+        return emit_patch(args, &baseline, &current);
+    }
+    let deviations = compare(&baseline, &current);
+
+    let report = match args.format {
+        DriftFormat::Text => deviations.iter().fold(String::new(), |mut out, d| {
+            let _ = writeln!(out, "{}", describe(d));
+            out
+        }),
+        DriftFormat::Json => serde_json::to_string_pretty(&deviations)
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, report)?,
+        None => print!("{}", report),
+    }
+
+    if !deviations.is_empty() {
+        return Err(ConversionError::ValidationError(format!(
+            "{} deviates from {} in {} place(s)",
+            args.current.display(),
+            args.baseline.display(),
+            deviations.len()
+        )));
+    }
+    info!("{} matches the baseline", args.current.display());
+    Ok(())
+}
+
+fn emit_patch(
+    args: &DriftArgs,
+    baseline: &SysmonConfig,
+    current: &SysmonConfig,
+) -> Result<(), ConversionError> {
+    let (operations, skipped) = operations(baseline, current);
+    for deviation in &skipped {
+        warn!(
+            "Not expressible as a patch operation: {}",
+            describe(deviation)
+        );
+    }
+    let patch = serde_json::to_string_pretty(&operations)
+        .map_err(|e| ConversionError::Other(e.to_string()))?;
+    match &args.output {
+        Some(path) => io_guard::write(path, patch + "\n")?,
+        None => println!("{}", patch),
+    }
+    info!(
+        "{} patch operation(s) from {} to {}",
+        operations.len(),
+        args.baseline.display(),
+        args.current.display()
+    );
+    Ok(())
+}
+
+/// Whether any rule or rule group in `config` has a name.
+fn has_names(config: &SysmonConfig) -> bool {
+    config.rule_groups.iter().any(|group| group.name.is_some())
+        || config
+            .events()
+            .flat_map(|e| &e.filters)
+            .any(|filter| match filter {
+                Filter::Field(field) => field.name.is_some(),
+                Filter::Rule(rule) => rule.name.is_some(),
+            })
+}
+
+/// Drops rule and rule group names, as a `dump-live` capture has none.
+fn drop_names(config: &mut SysmonConfig) {
+    for group in &mut config.rule_groups {
+        group.name = None;
+        for event in &mut group.events {
+            for filter in &mut event.filters {
+                match filter {
+                    Filter::Field(field) => field.name = None,
+                    Filter::Rule(rule) => rule.name = None,
+                }
+            }
+        }
+    }
+}
+
+/// A rule flattened for comparison.
+struct Entry<'a> {
+    scope: String,
+    identity: String,
+    text: String,
+    group: &'a RuleGroup,
+    event: &'a EventFilter,
+    filter: &'a Filter,
+}
+
+impl Entry<'_> {
+    fn relation(&self) -> GroupRelation {
+        self.group.group_relation.unwrap_or(GroupRelation::Or)
+    }
+}
+
+pub fn compare(baseline: &SysmonConfig, current: &SysmonConfig) -> Vec<Deviation> {
+    let mut deviations = Vec::new();
+    let mut pair = |scope: &str, baseline: Option<String>, current: Option<String>| {
+        let change = match (&baseline, &current) {
+            (Some(a), Some(b)) if a == b => return,
+            (Some(_), Some(_)) => Change::Modified,
+            (Some(_), None) => Change::Removed,
+            (None, _) => Change::Added,
+        };
+        deviations.push(Deviation {
+            change,
+            scope: scope.to_string(),
+            baseline,
+            current,
+        });
+    };
+
+    pair(
+        "Sysmon",
+        Some(format!("schemaversion {}", baseline.schema_version)),
+        Some(format!("schemaversion {}", current.schema_version)),
+    );
+    for option in &baseline.options {
+        let theirs = current.options.iter().find(|o| o.name == option.name);
+        pair(
+            "Sysmon",
+            Some(format!("{} {}", option.name, option.value)),
+            theirs.map(|o| format!("{} {}", o.name, o.value)),
+        );
+    }
+    for option in &current.options {
+        if !baseline.options.iter().any(|o| o.name == option.name) {
+            pair(
+                "Sysmon",
+                None,
+                Some(format!("{} {}", option.name, option.value)),
+            );
+        }
+    }
+
+    for (ours, theirs) in rule_pairs(baseline, current) {
+        let scope = ours.as_ref().or(theirs.as_ref()).map(|e| e.scope.clone());
+        pair(
+            &scope.unwrap_or_default(),
+            ours.map(|e| e.text),
+            theirs.map(|e| e.text),
+        );
+    }
+    deviations
+}
+
+/// A rule only in the baseline, only in the current config, or changed
+/// between them, with the text `compare` reports it by.
+pub struct RuleChange<'a> {
+    /// The event filter holding the rule, in the baseline if it is there.
+    pub event: &'a EventFilter,
+    pub baseline: Option<(&'a Filter, String)>,
+    pub current: Option<(&'a Filter, String)>,
+}
+
+/// The rule-level part of `compare`, keeping the rules themselves.
+pub fn rule_changes<'a>(
+    baseline: &'a SysmonConfig,
+    current: &'a SysmonConfig,
+) -> Vec<RuleChange<'a>> {
+    rule_pairs(baseline, current)
+        .into_iter()
+        .filter_map(|(ours, theirs)| {
+            let event = ours.as_ref().or(theirs.as_ref())?.event;
+            Some(RuleChange {
+                event,
+                baseline: ours.map(|e| (e.filter, e.text)),
+                current: theirs.map(|e| (e.filter, e.text)),
+            })
+        })
+        .collect()
+}
+
+/// The rules of `baseline` that differ from `current`, each with its
+/// counterpart there if it has one, then the rules only `current` has.
+fn rule_pairs<'a>(
+    baseline: &'a SysmonConfig,
+    current: &'a SysmonConfig,
+) -> Vec<(Option<Entry<'a>>, Option<Entry<'a>>)> {
+    // Identical rules first, so that rules sharing an identity pair up
+    // with their exact counterparts wherever they are.
+    let mut remaining = entries(current);
+    let mut unmatched = Vec::new();
+    for ours in entries(baseline) {
+        match remaining
+            .iter()
+            .position(|e| e.scope == ours.scope && e.text == ours.text)
+        {
+            Some(i) => {
+                remaining.remove(i);
+            }
+            None => unmatched.push(ours),
+        }
+    }
+    let mut pairs = Vec::new();
+    for ours in unmatched {
+        let theirs = remaining
+            .iter()
+            .position(|e| e.scope == ours.scope && e.identity == ours.identity)
+            .map(|i| remaining.remove(i));
+        pairs.push((Some(ours), theirs));
+    }
+    pairs.extend(remaining.into_iter().map(|theirs| (None, Some(theirs))));
+    pairs
+}
+
+/// Patch operations turning `baseline` into `current`, and the deviations
+/// they cannot express: global settings, `<Rule>` combinations and moves
+/// between `or` and `and` groups, which the patch operations do not
+/// address.
+pub fn operations(
+    baseline: &SysmonConfig,
+    current: &SysmonConfig,
+) -> (Vec<Operation>, Vec<Deviation>) {
+    let mut operations = Vec::new();
+    let mut skipped: Vec<Deviation> = compare(baseline, current)
+        .into_iter()
+        .filter(|d| d.scope == "Sysmon")
+        .collect();
+    for (ours, theirs) in rule_pairs(baseline, current) {
+        let regrouped = ours
+            .as_ref()
+            .zip(theirs.as_ref())
+            .is_some_and(|(o, t)| o.relation() != t.relation());
+        let (Some(old), Some(new)) = (field_of(ours.as_ref()), field_of(theirs.as_ref())) else {
+            skipped.push(deviation(ours.as_ref(), theirs.as_ref()));
+            continue;
+        };
+        if regrouped {
+            skipped.push(deviation(ours.as_ref(), theirs.as_ref()));
+            continue;
+        }
+        match (old, new) {
+            (Some((o, old)), Some((_, new))) if old.field == new.field => {
+                let changed = |a: &String, b: &String| (a != b).then(|| b.clone());
+                operations.push(Operation::Replace(Replacement {
+                    select: selector(o, old),
+                    set: Changes {
+                        condition: (old.condition != new.condition).then_some(new.condition),
+                        value: changed(&old.value, &new.value),
+                        name: None,
+                    },
+                }));
+            }
+            (ours, theirs) => {
+                if let Some((o, old)) = ours {
+                    operations.push(Operation::Remove(selector(o, old)));
+                }
+                if let Some((t, new)) = theirs {
+                    operations.push(Operation::Add(NewRule {
+                        event: t.event.event.clone(),
+                        onmatch: t.event.onmatch,
+                        field: new.field.clone(),
+                        condition: new.condition,
+                        value: new.value.clone(),
+                        name: new.name.clone(),
+                        group: t.group.name.clone(),
+                    }));
+                }
+            }
+        }
+    }
+    (operations, skipped)
+}
+
+type Side<'e, 'a> = Option<(&'e Entry<'a>, &'a FieldCondition)>;
+
+/// The side of a pair as a field condition: `Some(None)` if it is absent,
+/// `None` if it is a `<Rule>` combination.
+fn field_of<'e, 'a>(entry: Option<&'e Entry<'a>>) -> Option<Side<'e, 'a>> {
+    match entry {
+        None => Some(None),
+        Some(entry) => match entry.filter {
+            Filter::Field(field) => Some(Some((entry, field))),
+            Filter::Rule(_) => None,
+        },
+    }
+}
+
+/// Selects exactly `field` within its event type and `onmatch`, in rule
+/// groups with the name and `groupRelation` of the one it is in.
+fn selector(entry: &Entry, field: &FieldCondition) -> Selector {
+    let mut predicates = String::new();
+    if let Some(name) = &entry.group.name {
+        predicates += &attribute_predicate("name", name);
+    }
+    if let Some(relation) = entry.group.group_relation {
+        predicates += &attribute_predicate("groupRelation", relation.as_str());
+    }
+    Selector {
+        name: field.name.clone(),
+        field: Some(field.field.clone()),
+        value: Some(field.value.clone()),
+        event: Some(entry.event.event.clone()),
+        query: Some(format!("onmatch=={}", entry.event.onmatch.as_str())),
+        path: (!predicates.is_empty()).then(|| format!("//RuleGroup{}", predicates)),
+    }
+}
+
+/// `[@attribute="value"]`, quoted with `'` when `value` holds a `"`. XPath
+/// strings have no escapes, so a value holding both quotes cannot be
+/// tested and is left out.
+fn attribute_predicate(attribute: &str, value: &str) -> String {
+    match (value.contains('"'), value.contains('\'')) {
+        (false, _) => format!("[@{}=\"{}\"]", attribute, value),
+        (true, false) => format!("[@{}='{}']", attribute, value),
+        (true, true) => String::new(),
+    }
+}
+
+fn deviation(ours: Option<&Entry>, theirs: Option<&Entry>) -> Deviation {
+    let entry = ours.or(theirs).expect("one side of a pair is present");
+    Deviation {
+        change: match (ours, theirs) {
+            (Some(_), Some(_)) => Change::Modified,
+            (Some(_), None) => Change::Removed,
+            (None, _) => Change::Added,
+        },
+        scope: entry.scope.clone(),
+        baseline: ours.map(|e| e.text.clone()),
+        current: theirs.map(|e| e.text.clone()),
+    }
+}
+
+fn entries(config: &SysmonConfig) -> Vec<Entry<'_>> {
+    let mut entries = Vec::new();
+    let events = config
+        .rule_groups
+        .iter()
+        .flat_map(|group| group.events.iter().map(move |event| (group, event)));
+    for (group, event) in events {
+        let scope = format!("{}/{}", event.event, event.onmatch.as_str());
+        for filter in &event.filters {
+            let (name, identity, text) = match filter {
+                Filter::Field(field) => (
+                    field.name.as_deref(),
+                    format!("{}\0{}", field.field, field.value),
+                    condition(field),
+                ),
+                Filter::Rule(rule) => {
+                    let text = format!(
+                        "Rule {}: {}",
+                        rule.group_relation.as_str(),
+                        rule.fields
+                            .iter()
+                            .map(condition)
+                            .collect::<Vec<_>>()
+                            .join("; ")
+                    );
+                    (rule.name.as_deref(), text.clone(), text)
+                }
+            };
+            let (identity, mut text) = match name {
+                Some(name) => (format!("name\0{}", name), format!("[{}] {}", name, text)),
+                None => (identity, text),
+            };
+            if group.group_relation == Some(GroupRelation::And) {
+                text.push_str(" (and group)");
+            }
+            entries.push(Entry {
+                scope: scope.clone(),
+                identity,
+                text,
+                group,
+                event,
+                filter,
+            });
+        }
+    }
+    entries
+}
+
+fn condition(field: &FieldCondition) -> String {
+    format!(
+        "{} {} {:?}",
+        field.field,
+        field.condition.as_str(),
+        field.value
+    )
+}
+
+fn describe(deviation: &Deviation) -> String {
+    let none = || "-".to_string();
+    match deviation.change {
+        Change::Added => format!(
+            "added: {}: {}",
+            deviation.scope,
+            deviation.current.clone().unwrap_or_else(none)
+        ),
+        Change::Removed => format!(
+            "removed: {}: {}",
+            deviation.scope,
+            deviation.baseline.clone().unwrap_or_else(none)
+        ),
+        Change::Modified => format!(
+            "modified: {}: {} -> {}",
+            deviation.scope,
+            deviation.baseline.clone().unwrap_or_else(none),
+            deviation.current.clone().unwrap_or_else(none)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sysmon_cli::model::Condition;
+
+    fn config(schema: &str, filters: &str) -> SysmonConfig {
+        SysmonConfig::from_xml_str(&format!(
+            r#"<Sysmon schemaversion="{}"><HashAlgorithms>sha256</HashAlgorithms><EventFiltering>
+            <RuleGroup groupRelation="or"><ProcessCreate onmatch="include">{}</ProcessCreate></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+            schema, filters
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compare_reports_rule_changes() {
+        let baseline = config(
+            "4.90",
+            r#"<Image condition="end with">\cmd.exe</Image>
+            <CommandLine condition="contains">-enc</CommandLine>
+            <ParentImage name="office" condition="is">winword.exe</ParentImage>"#,
+        );
+        let current = config(
+            "4.90",
+            r#"<ParentImage name="office" condition="is">excel.exe</ParentImage>
+            <Image condition="is">\cmd.exe</Image>
+            <User condition="is">SYSTEM</User>"#,
+        );
+
+        let report: Vec<String> = compare(&baseline, &current).iter().map(describe).collect();
+        assert_eq!(
+            report,
+            vec![
+                r#"modified: ProcessCreate/include: Image end with "\\cmd.exe" -> Image is "\\cmd.exe""#,
+                r#"removed: ProcessCreate/include: CommandLine contains "-enc""#,
+                r#"modified: ProcessCreate/include: [office] ParentImage is "winword.exe" -> [office] ParentImage is "excel.exe""#,
+                r#"added: ProcessCreate/include: User is "SYSTEM""#,
+            ]
+        );
+        assert!(compare(&baseline, &baseline).is_empty());
+    }
+
+    #[test]
+    fn test_operations_turn_baseline_into_current() {
+        let baseline = config(
+            "4.90",
+            r#"<Image condition="end with">\cmd.exe</Image>
+            <CommandLine condition="contains">-enc</CommandLine>
+            <ParentImage name="office" condition="is">winword.exe</ParentImage>"#,
+        );
+        let current = config(
+            "4.91",
+            r#"<ParentImage name="office" condition="is">excel.exe</ParentImage>
+            <Image condition="is">\cmd.exe</Image>
+            <Rule groupRelation="and"><User condition="is">SYSTEM</User></Rule>"#,
+        );
+
+        let (operations, skipped) = operations(&baseline, &current);
+        assert_eq!(operations.len(), 3);
+        assert!(matches!(
+            &operations[0],
+            Operation::Replace(Replacement { select, set })
+                if select.field.as_deref() == Some("Image")
+                    && select.query.as_deref() == Some("onmatch==include")
+                    && set.condition == Some(Condition::Is)
+                    && set.value.is_none()
+        ));
+        assert!(matches!(
+            &operations[1],
+            Operation::Remove(select) if select.value.as_deref() == Some("-enc")
+        ));
+        assert!(matches!(
+            &operations[2],
+            Operation::Replace(Replacement { select, set })
+                if select.name.as_deref() == Some("office")
+                    && set.value.as_deref() == Some("excel.exe")
+        ));
+        let skipped: Vec<String> = skipped.iter().map(describe).collect();
+        assert_eq!(
+            skipped,
+            vec![
+                "modified: Sysmon: schemaversion 4.90 -> schemaversion 4.91",
+                r#"added: ProcessCreate/include: Rule and: User is "SYSTEM""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_reports_global_settings() {
+        let deviations = compare(&config("4.50", ""), &config("4.90", ""));
+        assert_eq!(deviations.len(), 1);
+        assert_eq!(deviations[0].scope, "Sysmon");
+        assert_eq!(
+            describe(&deviations[0]),
+            "modified: Sysmon: schemaversion 4.50 -> schemaversion 4.90"
+        );
+    }
+
+    #[test]
+    fn test_dump_of_a_matching_config_shows_no_drift() {
+        let file = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><HashAlgorithms>sha256</HashAlgorithms><EventFiltering>
+            <RuleGroup name="process" groupRelation="or"><ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001" condition="end with">\powershell.exe</Image>
+            <CommandLine name="technique_id=T1027" condition="contains">-enc</CommandLine>
+            </ProcessCreate></RuleGroup>
+            <RuleGroup name="dns"><DnsQuery onmatch="exclude">
+            <QueryName condition="end with">.local</QueryName>
+            </DnsQuery></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        // What `dump-live` decodes from the registry for it: no names, and
+        // one `or` group per event filter.
+        let mut dump = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><HashAlgorithms>sha256</HashAlgorithms><EventFiltering>
+            <RuleGroup groupRelation="or"><ProcessCreate onmatch="include">
+            <Image condition="end with">\powershell.exe</Image>
+            <CommandLine condition="contains">-enc</CommandLine>
+            </ProcessCreate></RuleGroup>
+            <RuleGroup groupRelation="or"><DnsQuery onmatch="exclude">
+            <QueryName condition="end with">.local</QueryName>
+            </DnsQuery></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        assert!(has_names(&file) && !has_names(&dump));
+        // Named rules find no counterpart by name: two removed, two added.
+        assert_eq!(compare(&file, &dump).len(), 4);
+
+        let mut unnamed = file.clone();
+        drop_names(&mut unnamed);
+        assert!(compare(&unnamed, &dump).is_empty());
+
+        // Moving a condition into an `and` group is drift.
+        dump.rule_groups[0].group_relation = Some(GroupRelation::And);
+        let report: Vec<String> = compare(&unnamed, &dump).iter().map(describe).collect();
+        assert_eq!(
+            report[0],
+            r#"modified: ProcessCreate/include: Image end with "\\powershell.exe" -> Image end with "\\powershell.exe" (and group)"#
+        );
+        let (operations, skipped) = operations(&unnamed, &dump);
+        assert!(operations.is_empty());
+        assert_eq!(skipped.len(), 2);
+    }
+
+    #[test]
+    fn test_operations_select_the_rule_group() {
+        let xml = |condition: &str| {
+            format!(
+                r#"<Sysmon schemaversion="4.90"><EventFiltering>
+<RuleGroup name="a" groupRelation="or"><ProcessCreate onmatch="include"><Image condition="is">x.exe</Image></ProcessCreate></RuleGroup>
+<RuleGroup name="b" groupRelation="or"><ProcessCreate onmatch="include"><Image condition="{}">x.exe</Image></ProcessCreate></RuleGroup>
+</EventFiltering></Sysmon>"#,
+                condition
+            )
+        };
+        let baseline = SysmonConfig::from_xml_str(&xml("is")).unwrap();
+        let current = SysmonConfig::from_xml_str(&xml("end with")).unwrap();
+
+        let (operations, skipped) = operations(&baseline, &current);
+        assert!(skipped.is_empty());
+        let [Operation::Replace(replacement)] = &operations[..] else {
+            panic!("expected one replace: {:?}", operations);
+        };
+        assert_eq!(
+            replacement.select.path.as_deref(),
+            Some(r#"//RuleGroup[@name="b"][@groupRelation="or"]"#)
+        );
+
+        let mut root = xmltree::Element::parse(xml("is").as_bytes()).unwrap();
+        crate::patch::apply(&mut root, std::path::Path::new("c.xml"), &operations).unwrap();
+        let patched = SysmonConfig::from_xml_str(&crate::document::to_xml_string(&root).unwrap());
+        assert!(compare(&patched.unwrap(), &current).is_empty());
+        assert_eq!(
+            attribute_predicate("name", r#"say "hi""#),
+            r#"[@name='say "hi"']"#
+        );
+    }
+}
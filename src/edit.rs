@@ -0,0 +1,948 @@
+//! `add-rule` and `remove-rule`: scripted edits to an existing
+//! configuration, also used by `patch`.
+//!
+//! Edits work on the XML element tree, so everything the edit does not
+//! touch is written back as it was. JSON configs are edited in their XML
+//! form and converted back on save.
+
+use crate::document::{self, child_elements, has_child_elements};
+use crate::query::{self, Expr, Key, Match, Op};
+use crate::{validate, xpath};
+use clap::Args;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use sysmon_cli::model::{Condition, OnMatch};
+use sysmon_cli::schema::{self, Version};
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+/// A rule to add: one field condition.
+#[derive(Debug, Clone, Args, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewRule {
+    /// Event type, e.g. ProcessCreate
+    #[arg(long)]
+    pub event: String,
+
+    /// Whether a match includes or excludes the event
+    #[arg(long)]
+    pub onmatch: OnMatch,
+
+    /// Field to test, e.g. Image
+    #[arg(long)]
+    pub field: String,
+
+    /// Condition, e.g. "end with"
+    #[arg(long, default_value = "is")]
+    #[serde(default = "default_condition")]
+    pub condition: Condition,
+
+    /// Value to compare the field with
+    #[arg(long)]
+    pub value: String,
+
+    /// Rule name, e.g. technique_id=T1569.002
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Put the rule in the RuleGroup with this name, creating it if needed
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+fn default_condition() -> Condition {
+    Condition::Is
+}
+
+/// Which conditions an edit applies to. Every selector given must hold.
+#[derive(Debug, Default, Clone, Args, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Selector {
+    /// Rules with this name, the rule ID in most configs
+    #[arg(long, required_unless_present_any = ["field", "query", "path"])]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Conditions on this field with the --value given
+    #[arg(long, requires = "value")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+
+    /// Value the --field condition compares with
+    #[arg(long, requires = "field")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+
+    /// Only rules filtering this event type
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+
+    /// Rules with a condition matching this `query` expression
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+
+    /// Only rules at or under the elements this XPath selects, e.g.
+    /// '//RuleGroup[@name="legacy"]'
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// New attributes for the conditions a `patch` replace selects.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Changes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Args)]
+pub struct AddRuleArgs {
+    /// Configuration to edit (XML or JSON); rewritten in place unless --output is given
+    pub config: PathBuf,
+
+    #[command(flatten)]
+    pub rule: NewRule,
+
+    /// Write the edited config here instead of over the input
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct RemoveRuleArgs {
+    /// Configuration to edit (XML or JSON); rewritten in place unless --output is given
+    pub config: PathBuf,
+
+    #[command(flatten)]
+    pub select: Selector,
+
+    /// Print what would be removed without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Write the edited config here instead of over the input
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Something `remove_rules` deleted.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Removal {
+    /// Line of the removed element; only known for XML input.
+    pub line: Option<usize>,
+    /// `RuleGroup[1]/ProcessCreate/include: Image end with "\\psexec.exe"`.
+    pub what: String,
+}
+
+/// Where `add_rule` put the rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Appended to an existing event filter.
+    Existing,
+    /// In a new event filter inside an existing rule group.
+    NewEvent,
+    /// In a new rule group.
+    NewGroup,
+    /// The event filter already had an identical condition.
+    AlreadyPresent,
+}
+
+/// Selected conditions by their child-index path from the root element,
+/// with the event filter each belongs to (`RuleGroup[1]/ProcessCreate/include`).
+pub type Selection = BTreeMap<Vec<usize>, String>;
+
+pub fn run_add(args: &AddRuleArgs) -> Result<(), ConversionError> {
+    let mut root = document::load(&args.config)?;
+    check(&root, &args.rule)?;
+
+    let rule = describe_rule(&args.rule);
+    if add_rule(&mut root, &args.rule) == Placement::AlreadyPresent {
+        info!("{} already has {}", args.config.display(), rule);
+        return Ok(());
+    }
+    let output = args.output.as_ref().unwrap_or(&args.config);
+    document::save(output, &root)?;
+    info!("Added {} to {}", rule, output.display());
+    Ok(())
+}
+
+pub fn run_remove(args: &RemoveRuleArgs) -> Result<(), ConversionError> {
+    let (text, is_xml) = validate::load(&args.config)?;
+    let mut root = Element::parse(text.as_bytes())
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", args.config.display(), e)))?;
+
+    let selected = args.select.select(&root, &args.config)?;
+    let removed = remove_rules(&mut root, &selected, is_xml.then_some(&text));
+    if removed.is_empty() {
+        warn!("No rule in {} matches", args.config.display());
+        return Ok(());
+    }
+    let report = removed.iter().fold(String::new(), |mut out, removal| {
+        let _ = match removal.line {
+            Some(line) => writeln!(out, "{}:{}: {}", args.config.display(), line, removal.what),
+            None => writeln!(out, "{}: {}", args.config.display(), removal.what),
+        };
+        out
+    });
+    if args.dry_run {
+        print!("{}", report);
+        return Ok(());
+    }
+
+    let output = args.output.as_ref().unwrap_or(&args.config);
+    document::save(output, &root)?;
+    for line in report.lines() {
+        info!("Removed {}", line);
+    }
+    Ok(())
+}
+
+/// `Image end with "\\psexec.exe" in ProcessCreate/include`.
+pub fn describe_rule(rule: &NewRule) -> String {
+    format!(
+        "{} {} {:?} in {}/{}",
+        rule.field,
+        rule.condition,
+        rule.value,
+        rule.event,
+        rule.onmatch.as_str()
+    )
+}
+
+impl Selector {
+    /// The conditions of `root` this selector picks; `file` is what a
+    /// `file==` query compares with.
+    pub fn select(&self, root: &Element, file: &Path) -> Result<Selection, ConversionError> {
+        let expr = self.expr()?;
+        let under = match &self.path {
+            Some(path) => Some(index_paths(root, &xpath::parse(path)?)?),
+            None => None,
+        };
+        if expr.is_none() && under.is_none() {
+            return Err(ConversionError::ValidationError(
+                "Select rules with a name, a field and value, a query or a path".into(),
+            ));
+        }
+
+        let mut selected = Selection::new();
+        for_each_condition(root, file, |path, scope, candidate| {
+            let covered = under
+                .as_ref()
+                .is_none_or(|under| (0..=path.len()).any(|n| under.contains(&path[..n])));
+            if covered && expr.as_ref().is_none_or(|e| query::evaluate(e, candidate)) {
+                selected.insert(path.to_vec(), scope.to_string());
+            }
+        });
+        Ok(selected)
+    }
+
+    /// The non-path selectors as one `query` expression, if any were given.
+    fn expr(&self) -> Result<Option<Expr>, ConversionError> {
+        let exact = [
+            (Key::Name, &self.name),
+            (Key::Field, &self.field),
+            (Key::Value, &self.value),
+            (Key::Event, &self.event),
+        ];
+        let mut parts: Vec<Expr> = exact
+            .into_iter()
+            .filter_map(|(key, wanted)| Some(Expr::Compare(key, Op::Eq, wanted.clone()?)))
+            .collect();
+        if let Some(expression) = &self.query {
+            parts.push(query::parse(expression)?);
+        }
+        Ok(parts
+            .into_iter()
+            .reduce(|a, b| Expr::And(Box::new(a), Box::new(b))))
+    }
+}
+
+/// Child-index paths of the elements `path` selects.
+fn index_paths(root: &Element, path: &xpath::Path) -> Result<HashSet<Vec<usize>>, ConversionError> {
+    fn walk(
+        element: &Element,
+        at: &mut Vec<usize>,
+        targets: &[&Element],
+        out: &mut HashSet<Vec<usize>>,
+    ) {
+        if targets.iter().any(|t| std::ptr::eq(*t, element)) {
+            out.insert(at.clone());
+        }
+        for (i, node) in element.children.iter().enumerate() {
+            if let XMLNode::Element(child) = node {
+                at.push(i);
+                walk(child, at, targets, out);
+                at.pop();
+            }
+        }
+    }
+
+    let mut targets = Vec::new();
+    for node in path.select(root) {
+        match node {
+            xpath::Node::Element(element) => targets.push(element),
+            xpath::Node::Value(_) => {
+                return Err(ConversionError::ValidationError(
+                    "The path must select elements, not attributes or text".into(),
+                ))
+            }
+        }
+    }
+    let mut out = HashSet::new();
+    walk(root, &mut Vec::new(), &targets, &mut out);
+    Ok(out)
+}
+
+/// Calls `visit` with the path, event filter scope and query candidate of
+/// every field condition, including those inside `<Rule>`s.
+fn for_each_condition(root: &Element, file: &Path, mut visit: impl FnMut(&[usize], &str, &Match)) {
+    let Some(f) = root.children.iter().position(|node| {
+        node.as_element()
+            .is_some_and(|e| e.name == "EventFiltering")
+    }) else {
+        return;
+    };
+    let filtering = root.children[f].as_element().expect("found above");
+
+    let mut groups = 0;
+    for (j, node) in filtering.children.iter().enumerate() {
+        let Some(element) = node.as_element() else {
+            continue;
+        };
+        // Bare event filters, as older configs write them, have no group.
+        let (group, events): (Option<&Element>, Vec<(Vec<usize>, &Element)>) =
+            if element.name == "RuleGroup" {
+                groups += 1;
+                let events = element
+                    .children
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(k, node)| Some((vec![f, j, k], node.as_element()?)))
+                    .collect();
+                (Some(element), events)
+            } else {
+                (None, vec![(vec![f, j], element)])
+            };
+        let group_name = group
+            .and_then(|g| g.attributes.get("name"))
+            .filter(|n| !n.is_empty());
+
+        for (event_path, event) in events {
+            let scope = match group {
+                Some(_) => format!("RuleGroup[{}]/{}/{}", groups, event.name, onmatch(event)),
+                None => format!("{}/{}", event.name, onmatch(event)),
+            };
+            for (l, node) in event.children.iter().enumerate() {
+                let Some(filter) = node.as_element() else {
+                    continue;
+                };
+                let mut path = event_path.clone();
+                path.push(l);
+                let (fields, rule_name) = if filter.name == "Rule" {
+                    let fields = filter
+                        .children
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(m, node)| {
+                            let mut path = path.clone();
+                            path.push(m);
+                            Some((path, node.as_element()?))
+                        })
+                        .collect();
+                    (fields, filter.attributes.get("name"))
+                } else {
+                    (vec![(path, filter)], None)
+                };
+                for (path, field) in fields {
+                    let candidate = Match {
+                        file: file.to_path_buf(),
+                        line: None,
+                        group: group_name.cloned(),
+                        event: event.name.clone(),
+                        onmatch: onmatch(event).to_string(),
+                        name: field.attributes.get("name").or(rule_name).cloned(),
+                        field: field.name.clone(),
+                        condition: condition(field).to_string(),
+                        value: field.get_text().unwrap_or_default().into_owned(),
+                    };
+                    visit(&path, &scope, &candidate);
+                }
+            }
+        }
+    }
+}
+
+/// Removes every rule with a selected condition: a whole `<Rule>` when any
+/// of its conditions is selected, since dropping one condition would loosen
+/// it. Event filters and rule groups this leaves empty are removed too; an
+/// empty exclude filter would otherwise log every event. Filters and groups
+/// that were already empty are kept.
+pub fn remove_rules(root: &mut Element, selected: &Selection, text: Option<&str>) -> Vec<Removal> {
+    let mut removed = Vec::new();
+    let Some(f) = root.children.iter().position(|node| {
+        node.as_element()
+            .is_some_and(|e| e.name == "EventFiltering")
+    }) else {
+        return removed;
+    };
+    let filtering = root.children[f].as_mut_element().expect("found above");
+    let hit = |prefix: &[usize]| selected.keys().any(|path| path.starts_with(prefix));
+    let line = |location: &str| text.and_then(|text| validate::line_of(text, location));
+
+    let mut groups = 0;
+    let mut j = 0;
+    filtering.children.retain_mut(|node| {
+        j += 1;
+        let Some(element) = node.as_mut_element() else {
+            return true;
+        };
+        let at = vec![f, j - 1];
+        if element.name != "RuleGroup" {
+            // A bare event filter; there is no location to find its line by.
+            let scope = format!("{}/{}", element.name, onmatch(element));
+            return !prune_event(element, &at, &scope, &hit, &|_| None, &mut removed);
+        }
+
+        groups += 1;
+        let group_location = format!("RuleGroup[{}]", groups);
+        let had_events = has_child_elements(element);
+        let mut seen: Vec<String> = Vec::new();
+        let mut k = 0;
+        element.children.retain_mut(|node| {
+            k += 1;
+            let Some(event) = node.as_mut_element() else {
+                return true;
+            };
+            let nth = seen.iter().filter(|e| **e == event.name).count() + 1;
+            seen.push(event.name.clone());
+            let location = format!("{}/{}[{}]", group_location, event.name, nth);
+            let scope = format!("{}/{}/{}", group_location, event.name, onmatch(event));
+            let mut event_at = at.clone();
+            event_at.push(k - 1);
+            let rule_line = |segment: &str| line(&format!("{}/{}", location, segment));
+            let emptied = prune_event(event, &event_at, &scope, &hit, &rule_line, &mut removed);
+            if emptied {
+                removed.push(Removal {
+                    line: line(&location),
+                    what: format!("{}: left empty, removed", scope),
+                });
+            }
+            !emptied
+        });
+
+        let emptied = had_events && !has_child_elements(element);
+        if emptied {
+            removed.push(Removal {
+                line: line(&group_location),
+                what: format!("{}: left empty, removed", group_location),
+            });
+        }
+        !emptied
+    });
+    removed
+}
+
+/// Removes the selected rules of the event filter at `at`; true if that
+/// left it empty. `line` finds the line of a rule from its location in the
+/// filter.
+fn prune_event(
+    event: &mut Element,
+    at: &[usize],
+    scope: &str,
+    hit: &dyn Fn(&[usize]) -> bool,
+    line: &dyn Fn(&str) -> Option<usize>,
+    removed: &mut Vec<Removal>,
+) -> bool {
+    let had_filters = has_child_elements(event);
+    let mut seen: Vec<String> = Vec::new();
+    let mut rules = 0;
+    let mut l = 0;
+    event.children.retain(|node| {
+        l += 1;
+        let Some(filter) = node.as_element() else {
+            return true;
+        };
+        let segment = if filter.name == "Rule" {
+            rules += 1;
+            seen.extend(child_elements(filter).map(|f| f.name.clone()));
+            format!("Rule[{}]", rules)
+        } else {
+            let nth = seen.iter().filter(|f| **f == filter.name).count() + 1;
+            seen.push(filter.name.clone());
+            format!("{}[{}]", filter.name, nth)
+        };
+
+        let mut path = at.to_vec();
+        path.push(l - 1);
+        if !hit(&path) {
+            return true;
+        }
+        let what = if filter.name == "Rule" {
+            format!(
+                "Rule{} ({} condition(s))",
+                filter
+                    .attributes
+                    .get("name")
+                    .map(|n| format!(" {:?}", n))
+                    .unwrap_or_default(),
+                child_elements(filter).count()
+            )
+        } else {
+            describe_condition(filter)
+        };
+        removed.push(Removal {
+            line: line(&segment),
+            what: format!("{}: {}", scope, what),
+        });
+        false
+    });
+    had_filters && !has_child_elements(event)
+}
+
+/// Applies `changes` to every selected condition and describes each change.
+pub fn replace_conditions(
+    root: &mut Element,
+    selected: &Selection,
+    changes: &Changes,
+) -> Vec<String> {
+    let mut replaced = Vec::new();
+    for (path, scope) in selected {
+        let mut element = &mut *root;
+        for &i in path {
+            element = element.children[i]
+                .as_mut_element()
+                .expect("selected paths lead to elements");
+        }
+        let before = describe_condition(element);
+        if let Some(condition) = changes.condition {
+            element
+                .attributes
+                .insert("condition".into(), condition.as_str().into());
+        }
+        if let Some(value) = &changes.value {
+            element
+                .children
+                .retain(|node| !matches!(node, XMLNode::Text(_) | XMLNode::CData(_)));
+            element.children.push(XMLNode::Text(value.clone()));
+        }
+        if let Some(name) = &changes.name {
+            element.attributes.insert("name".into(), name.clone());
+        }
+        replaced.push(format!(
+            "{}: {} -> {}",
+            scope,
+            before,
+            describe_condition(element)
+        ));
+    }
+    replaced
+}
+
+/// Runs [`check`] on each condition in `selected` as `changes` would leave
+/// it, so a replacement cannot bring in what adding the rule could not.
+pub fn check_replacement(
+    root: &Element,
+    selected: &Selection,
+    changes: &Changes,
+) -> Result<(), ConversionError> {
+    for path in selected.keys() {
+        let mut chain = vec![root];
+        for &i in path {
+            let element = chain[chain.len() - 1].children[i]
+                .as_element()
+                .expect("selected paths lead to elements");
+            chain.push(element);
+        }
+        let field = chain[chain.len() - 1];
+        // The event filter is the element inside the rule group.
+        let Some(event) = chain
+            .windows(2)
+            .find(|pair| pair[0].name == "RuleGroup")
+            .map(|pair| pair[1])
+        else {
+            continue;
+        };
+        let current = condition(field).parse().unwrap_or(Condition::Is);
+        let rule = NewRule {
+            event: event.name.clone(),
+            onmatch: OnMatch::Include,
+            field: field.name.clone(),
+            condition: changes.condition.unwrap_or(current),
+            value: String::new(),
+            name: None,
+            group: None,
+        };
+        check(root, &rule)?;
+    }
+    Ok(())
+}
+
+fn describe_condition(field: &Element) -> String {
+    format!(
+        "{} {} {:?}",
+        field.name,
+        condition(field),
+        field.get_text().unwrap_or_default()
+    )
+}
+
+fn onmatch(event: &Element) -> &str {
+    event.attributes.get("onmatch").map_or("", String::as_str)
+}
+
+/// A condition element's operator; Sysmon defaults to `is`.
+fn condition(field: &Element) -> &str {
+    field
+        .attributes
+        .get("condition")
+        .map_or("is", String::as_str)
+}
+
+/// Rejects rules Sysmon would refuse: unknown events and fields, and events
+/// or conditions newer than the schema the config declares.
+pub fn check(root: &Element, rule: &NewRule) -> Result<(), ConversionError> {
+    let Some(kind) = schema::event_type(&rule.event) else {
+        return Err(ConversionError::ValidationError(format!(
+            "Unknown event type: {}",
+            rule.event
+        )));
+    };
+    if !kind.has_field(&rule.field) {
+        return Err(ConversionError::ValidationError(format!(
+            "{} has no field {}",
+            rule.event, rule.field
+        )));
+    }
+
+    let Some(declared) = root
+        .attributes
+        .get("schemaversion")
+        .and_then(|v| v.parse::<Version>().ok())
+    else {
+        return Ok(());
+    };
+    let needed = [
+        (kind.min_schema, rule.event.clone()),
+        (
+            schema::condition_min_schema(rule.condition),
+            format!("Condition \"{}\"", rule.condition),
+        ),
+    ];
+    match needed.into_iter().find(|(min, _)| *min > declared) {
+        Some((min, what)) => Err(ConversionError::ValidationError(format!(
+            "{} requires schema {} but the config declares {}",
+            what, min, declared
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Adds `rule` to `root`. It goes into the first filter for the same event
+/// and `onmatch` in an `or` rule group (the named one, with `group`);
+/// failing that, into a new filter in the named group, or into a new rule
+/// group placed after the last one filtering the same event type. Groups
+/// with `groupRelation="and"` are never extended, since another condition
+/// there would narrow every rule already in them.
+pub fn add_rule(root: &mut Element, rule: &NewRule) -> Placement {
+    let mut condition = Element::new(&rule.field);
+    if let Some(name) = &rule.name {
+        condition.attributes.insert("name".into(), name.clone());
+    }
+    condition
+        .attributes
+        .insert("condition".into(), rule.condition.as_str().into());
+    condition.children.push(XMLNode::Text(rule.value.clone()));
+
+    if root.get_child("EventFiltering").is_none() {
+        root.children
+            .push(XMLNode::Element(Element::new("EventFiltering")));
+    }
+    let filtering = root
+        .get_mut_child("EventFiltering")
+        .expect("EventFiltering was just ensured");
+
+    let group = rule.group.as_deref();
+    let existing = filtering
+        .children
+        .iter_mut()
+        .filter_map(XMLNode::as_mut_element)
+        .flat_map(|element| {
+            if element.name == "RuleGroup" {
+                if !extends(element, group) {
+                    return Vec::new();
+                }
+                element
+                    .children
+                    .iter_mut()
+                    .filter_map(XMLNode::as_mut_element)
+                    .collect()
+            } else if group.is_none() {
+                // A bare event filter, as older configs write them.
+                vec![element]
+            } else {
+                Vec::new()
+            }
+        })
+        .find(|event| is_filter(event, rule));
+    if let Some(event) = existing {
+        if child_elements(event).any(|c| same_condition(c, &condition)) {
+            return Placement::AlreadyPresent;
+        }
+        event.children.push(XMLNode::Element(condition));
+        return Placement::Existing;
+    }
+
+    let mut event = Element::new(&rule.event);
+    event
+        .attributes
+        .insert("onmatch".into(), rule.onmatch.as_str().into());
+    event.children.push(XMLNode::Element(condition));
+
+    if let Some(name) = group {
+        let named = filtering
+            .children
+            .iter_mut()
+            .filter_map(XMLNode::as_mut_element)
+            .find(|g| g.name == "RuleGroup" && extends(g, Some(name)));
+        if let Some(named) = named {
+            named.children.push(XMLNode::Element(event));
+            return Placement::NewEvent;
+        }
+    }
+
+    let mut new_group = Element::new("RuleGroup");
+    new_group
+        .attributes
+        .insert("name".into(), group.unwrap_or_default().into());
+    new_group
+        .attributes
+        .insert("groupRelation".into(), "or".into());
+    new_group.children.push(XMLNode::Element(event));
+    let at = filtering
+        .children
+        .iter()
+        .rposition(|node| {
+            node.as_element().is_some_and(|g| {
+                g.name == "RuleGroup" && child_elements(g).any(|e| e.name == rule.event)
+            })
+        })
+        .map_or(filtering.children.len(), |i| i + 1);
+    filtering.children.insert(at, XMLNode::Element(new_group));
+    Placement::NewGroup
+}
+
+/// True if new conditions may go into `group`: an `or` group (Sysmon's
+/// default) with the wanted name, if one was asked for.
+fn extends(group: &Element, name: Option<&str>) -> bool {
+    let or = group
+        .attributes
+        .get("groupRelation")
+        .is_none_or(|r| r.eq_ignore_ascii_case("or"));
+    let named =
+        name.is_none_or(|name| group.attributes.get("name").map(String::as_str) == Some(name));
+    or && named
+}
+
+fn is_filter(event: &Element, rule: &NewRule) -> bool {
+    event.name == rule.event
+        && event
+            .attributes
+            .get("onmatch")
+            .is_some_and(|m| m.eq_ignore_ascii_case(rule.onmatch.as_str()))
+}
+
+fn same_condition(a: &Element, b: &Element) -> bool {
+    a.name == b.name
+        && a.attributes.get("condition") == b.attributes.get("condition")
+        && a.get_text() == b.get_text()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+<EventFiltering>
+<RuleGroup name="" groupRelation="and">
+<ProcessCreate onmatch="include">
+<Image condition="end with">\cmd.exe</Image>
+</ProcessCreate>
+</RuleGroup>
+<RuleGroup name="" groupRelation="or">
+<ProcessCreate onmatch="include">
+<Image condition="end with">\powershell.exe</Image>
+</ProcessCreate>
+</RuleGroup>
+<RuleGroup name="dns" groupRelation="or">
+<DnsQuery onmatch="exclude">
+<QueryName condition="end with">.microsoft.com</QueryName>
+</DnsQuery>
+</RuleGroup>
+</EventFiltering>
+</Sysmon>"#;
+
+    fn rule(event: &str, onmatch: OnMatch, field: &str, group: Option<&str>) -> NewRule {
+        NewRule {
+            event: event.into(),
+            onmatch,
+            field: field.into(),
+            condition: Condition::EndWith,
+            value: r"\psexec.exe".into(),
+            name: Some("technique_id=T1569.002".into()),
+            group: group.map(str::to_string),
+        }
+    }
+
+    fn groups(root: &Element) -> Vec<String> {
+        child_elements(root.get_child("EventFiltering").unwrap())
+            .map(|g| {
+                let events: Vec<String> = child_elements(g)
+                    .map(|e| format!("{}:{}", e.name, child_elements(e).count()))
+                    .collect();
+                format!(
+                    "{}[{}]",
+                    g.attributes.get("name").unwrap(),
+                    events.join(",")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_add_rule_places_rules_in_or_groups() {
+        let mut root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let add = rule("ProcessCreate", OnMatch::Include, "Image", None);
+        assert_eq!(add_rule(&mut root, &add), Placement::Existing);
+        assert_eq!(add_rule(&mut root, &add), Placement::AlreadyPresent);
+
+        let exclude = rule("ProcessCreate", OnMatch::Exclude, "Image", None);
+        assert_eq!(add_rule(&mut root, &exclude), Placement::NewGroup);
+        let named = rule("DnsQuery", OnMatch::Include, "QueryName", Some("dns"));
+        assert_eq!(add_rule(&mut root, &named), Placement::NewEvent);
+        assert_eq!(
+            groups(&root),
+            vec![
+                "[ProcessCreate:1]",
+                "[ProcessCreate:2]",
+                "[ProcessCreate:1]",
+                "dns[DnsQuery:1,DnsQuery:1]",
+            ]
+        );
+
+        let added = child_elements(root.get_child("EventFiltering").unwrap())
+            .nth(1)
+            .and_then(|g| g.get_child("ProcessCreate"))
+            .and_then(|e| child_elements(e).nth(1))
+            .unwrap();
+        assert_eq!(
+            added.attributes.get("name").unwrap(),
+            "technique_id=T1569.002"
+        );
+        assert_eq!(added.attributes.get("condition").unwrap(), "end with");
+        assert_eq!(added.get_text().unwrap(), r"\psexec.exe");
+    }
+
+    #[test]
+    fn test_remove_rules_prunes_emptied_filters_and_groups() {
+        let mut root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let select = Selector {
+            query: Some(r#"field==Image && value~="powershell""#.into()),
+            ..Selector::default()
+        };
+        let selected = select.select(&root, Path::new("c.xml")).unwrap();
+        let removed = remove_rules(&mut root, &selected, Some(CONFIG));
+        let removed: Vec<(Option<usize>, &str)> =
+            removed.iter().map(|r| (r.line, r.what.as_str())).collect();
+        assert_eq!(
+            removed,
+            vec![
+                (
+                    Some(10),
+                    r#"RuleGroup[2]/ProcessCreate/include: Image end with "\\powershell.exe""#
+                ),
+                (
+                    Some(9),
+                    "RuleGroup[2]/ProcessCreate/include: left empty, removed"
+                ),
+                (Some(8), "RuleGroup[2]: left empty, removed"),
+            ]
+        );
+        assert_eq!(groups(&root), vec!["[ProcessCreate:1]", "dns[DnsQuery:1]"]);
+    }
+
+    #[test]
+    fn test_selector_requires_every_option_given() {
+        let root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let file = Path::new("c.xml");
+        let select = Selector {
+            field: Some("QueryName".into()),
+            value: Some(".MICROSOFT.com".into()),
+            event: Some("ProcessCreate".into()),
+            ..Selector::default()
+        };
+        assert!(select.select(&root, file).unwrap().is_empty());
+        let select = Selector {
+            event: None,
+            ..select
+        };
+        assert_eq!(select.select(&root, file).unwrap().len(), 1);
+
+        let select = Selector {
+            path: Some(r#"//RuleGroup[@groupRelation="and"]"#.into()),
+            ..Selector::default()
+        };
+        let selected = select.select(&root, file).unwrap();
+        assert_eq!(
+            selected.values().collect::<Vec<_>>(),
+            vec!["RuleGroup[1]/ProcessCreate/include"]
+        );
+        assert!(Selector::default().select(&root, file).is_err());
+    }
+
+    #[test]
+    fn test_replace_conditions_rewrites_selected_conditions() {
+        let mut root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let select = Selector {
+            query: Some("event==DnsQuery".into()),
+            ..Selector::default()
+        };
+        let selected = select.select(&root, Path::new("c.xml")).unwrap();
+        let changes = Changes {
+            condition: Some(Condition::Contains),
+            value: Some("microsoft".into()),
+            name: None,
+        };
+        assert_eq!(
+            replace_conditions(&mut root, &selected, &changes),
+            vec![
+                r#"RuleGroup[3]/DnsQuery/exclude: QueryName end with ".microsoft.com" -> QueryName contains "microsoft""#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_rejects_unknown_fields_and_newer_conditions() {
+        let root = Element::parse(r#"<Sysmon schemaversion="4.21"/>"#.as_bytes()).unwrap();
+        let error = |rule: &NewRule| check(&root, rule).unwrap_err().to_string();
+
+        let field = rule("ProcessCreate", OnMatch::Include, "QueryName", None);
+        assert!(error(&field).contains("ProcessCreate has no field QueryName"));
+        let mut condition = rule("ProcessCreate", OnMatch::Include, "Image", None);
+        condition.condition = Condition::ContainsAny;
+        assert!(error(&condition).contains("requires schema 4.22"));
+        let event = rule("FileDelete", OnMatch::Include, "TargetFilename", None);
+        assert!(error(&event).contains("FileDelete requires schema"));
+    }
+}
@@ -0,0 +1,167 @@
+//! Text encodings of configuration files.
+//!
+//! Windows tools commonly export Sysmon configs as UTF-16 LE with a BOM, and
+//! editors like to prepend a UTF-8 BOM. Inputs are normalized to plain UTF-8
+//! before anything parses them, with the XML declaration updated to match,
+//! and outputs can be re-encoded with `--output-encoding`.
+
+use clap::ValueEnum;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16_LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16_BE_BOM: &[u8] = b"\xFE\xFF";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// UTF-8 without a byte order mark
+    #[value(name = "utf-8")]
+    Utf8,
+    /// UTF-8 with a byte order mark
+    #[value(name = "utf-8-bom")]
+    Utf8Bom,
+    /// UTF-16 little endian with a byte order mark
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    /// UTF-16 big endian with a byte order mark
+    #[value(name = "utf-16be")]
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Guesses the encoding from a BOM, or for BOM-less UTF-16 from the zero
+    /// byte next to the leading ASCII character.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(UTF8_BOM) {
+            Self::Utf8Bom
+        } else if bytes.starts_with(UTF16_LE_BOM) {
+            Self::Utf16Le
+        } else if bytes.starts_with(UTF16_BE_BOM) {
+            Self::Utf16Be
+        } else {
+            match bytes {
+                [0, b, ..] if *b != 0 => Self::Utf16Be,
+                [b, 0, ..] if *b != 0 => Self::Utf16Le,
+                _ => Self::Utf8,
+            }
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => "UTF-8",
+            Self::Utf16Le | Self::Utf16Be => "UTF-16",
+        }
+    }
+}
+
+/// Decodes `bytes` into UTF-8 text without a BOM, rewriting any XML
+/// declaration to say `encoding="UTF-8"`.
+pub fn decode(bytes: &[u8]) -> Result<String, String> {
+    let encoding = Encoding::detect(bytes);
+    let text = match encoding {
+        Encoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())?,
+        Encoding::Utf8Bom => {
+            String::from_utf8(bytes[UTF8_BOM.len()..].to_vec()).map_err(|e| e.to_string())?
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let body = bytes
+                .strip_prefix(UTF16_LE_BOM)
+                .or_else(|| bytes.strip_prefix(UTF16_BE_BOM))
+                .unwrap_or(bytes);
+            let pairs = body.chunks_exact(2);
+            if !pairs.remainder().is_empty() {
+                return Err("truncated UTF-16 input (odd number of bytes)".to_string());
+            }
+            let units: Vec<u16> = pairs
+                .map(|pair| match encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            String::from_utf16(&units).map_err(|e| e.to_string())?
+        }
+    };
+    Ok(declare_encoding(&text, Encoding::Utf8.label()))
+}
+
+/// Encodes UTF-8 `text` as `encoding`, including the BOM and a matching XML
+/// declaration.
+pub fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+    let text = declare_encoding(text, encoding.label());
+    match encoding {
+        Encoding::Utf8 => text.into_bytes(),
+        Encoding::Utf8Bom => [UTF8_BOM, text.as_bytes()].concat(),
+        Encoding::Utf16Le => UTF16_LE_BOM
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect(),
+        Encoding::Utf16Be => UTF16_BE_BOM
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect(),
+    }
+}
+
+/// Sets the `encoding` pseudo-attribute of a leading `<?xml ...?>`
+/// declaration. Text without a declaration is returned unchanged.
+fn declare_encoding(text: &str, label: &str) -> String {
+    let Some(end) = text.starts_with("<?xml").then(|| text.find("?>")).flatten() else {
+        return text.to_string();
+    };
+    let declaration = &text[..end];
+    let rest = &text[end..];
+
+    let Some(start) = declaration.find("encoding=") else {
+        return format!("{} encoding=\"{}\"{}", declaration.trim_end(), label, rest);
+    };
+    let value_start = start + "encoding=".len();
+    let quote = declaration[value_start..].chars().next().unwrap_or('"');
+    let value_end = declaration[value_start + 1..]
+        .find(quote)
+        .map(|i| value_start + 1 + i + 1)
+        .unwrap_or(declaration.len());
+    format!(
+        "{}encoding=\"{}\"{}{}",
+        &declaration[..start],
+        label,
+        &declaration[value_end..],
+        rest
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_utf16le_with_declaration() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><Sysmon schemaversion=\"4.90\"/>";
+        let bytes = encode(xml, Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(&bytes), Encoding::Utf16Le);
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Sysmon schemaversion=\"4.90\"/>"
+        );
+    }
+
+    #[test]
+    fn test_decode_strips_utf8_bom_and_detects_bomless_utf16() {
+        assert_eq!(decode(b"\xEF\xBB\xBF<Sysmon/>").unwrap(), "<Sysmon/>");
+        let bomless: Vec<u8> = "<Sysmon/>"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(decode(&bomless).unwrap(), "<Sysmon/>");
+    }
+
+    #[test]
+    fn test_declare_encoding_adds_missing_attribute() {
+        assert_eq!(
+            declare_encoding("<?xml version='1.0'?><a/>", "UTF-16"),
+            "<?xml version='1.0' encoding=\"UTF-16\"?><a/>"
+        );
+        assert_eq!(declare_encoding("{\"a\": 1}", "UTF-16"), "{\"a\": 1}");
+    }
+}
@@ -0,0 +1,154 @@
+//! Machine-readable description of a failed run, for `--error-format json`.
+//!
+//! `PreprocessError`s reach this point already mapped onto `ConversionError`
+//! by `preprocess::library_error`, so both are classified here. The position
+//! of a parse error is recovered from the error message (`line:column`, as
+//! the XML parser reports it) and turned into a byte offset by reading the
+//! offending file.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub kind: &'static str,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// Byte offset of `line`/`column` in `file`.
+    pub offset: Option<usize>,
+    pub hint: &'static str,
+}
+
+impl ErrorReport {
+    /// `input` is the file the run was working on, used when the error
+    /// itself does not name one.
+    pub fn new(e: &ConversionError, input: Option<&Path>) -> Self {
+        let message = e.to_string();
+        let file = match e {
+            ConversionError::Io { path, .. } => Some(path.clone()),
+            _ => input.filter(|p| p.is_file()).map(Path::to_path_buf),
+        };
+        let position = match e {
+            ConversionError::Io { .. } => None,
+            _ => position(&message),
+        };
+        let offset = position
+            .zip(file.as_ref())
+            .and_then(|((line, column), file)| {
+                let text = std::fs::read_to_string(file).ok()?;
+                byte_offset(&text, line, column)
+            });
+
+        ErrorReport {
+            kind: kind(e),
+            message,
+            file,
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            offset,
+            hint: hint(e),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| format!("{{\"kind\":\"{}\"}}", self.kind))
+    }
+}
+
+/// Short, stable name for the kind of error.
+pub fn kind(e: &ConversionError) -> &'static str {
+    match e {
+        ConversionError::Io { .. } => "io",
+        ConversionError::InvalidFile(_) => "invalid_file",
+        ConversionError::XmlParse(_) => "xml_parse",
+        ConversionError::ValidationError(_) => "validation",
+        ConversionError::ParserError(_) => "parser",
+        ConversionError::Other(_) => "other",
+    }
+}
+
+fn hint(e: &ConversionError) -> &'static str {
+    match e {
+        ConversionError::Io { .. } => {
+            "Check that the path exists and that the current user can read it and write its output location."
+        }
+        ConversionError::InvalidFile(_) => {
+            "Check the input path and --max-size; use --from if the format cannot be detected."
+        }
+        ConversionError::XmlParse(_) => {
+            "Fix the XML at the reported position, or retry with --lenient to repair common problems."
+        }
+        ConversionError::ValidationError(_) => {
+            "Check event names, conditions and schemaversion against the Sysmon schema."
+        }
+        ConversionError::ParserError(_) => {
+            "The document is not a Sysmon configuration in the expected shape; retry with -v, or with --skip-preprocessing to rule out preprocessing."
+        }
+        ConversionError::Other(_) => "Retry with -v for more detail.",
+    }
+}
+
+/// Finds the first `line:column` pair in an error message.
+pub fn position(message: &str) -> Option<(usize, usize)> {
+    message
+        .split(|c: char| !(c.is_ascii_digit() || c == ':'))
+        .find_map(|token| {
+            let (line, column) = token.trim_matches(':').split_once(':')?;
+            let line = line.parse().ok()?;
+            let column = column.parse().ok()?;
+            (line > 0 && column > 0).then_some((line, column))
+        })
+}
+
+/// Byte offset of a 1-based line and character column.
+fn byte_offset(text: &str, line: usize, column: usize) -> Option<usize> {
+    let start: usize = text
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum();
+    let line_text = text.get(start..)?.split('\n').next()?;
+    let within = match line_text.char_indices().nth(column - 1) {
+        Some((index, _)) => index,
+        None if line_text.chars().count() == column - 1 => line_text.len(),
+        None => return None,
+    };
+    Some(start + within)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locates_parse_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.xml");
+        std::fs::write(&file, "<Sysmon>\n  <Bad></Sysmon>\n").unwrap();
+
+        let error = ConversionError::ParserError("2:9 Unexpected closing tag".to_string());
+        let report = ErrorReport::new(&error, Some(&file));
+        assert_eq!(report.kind, "parser");
+        assert_eq!(report.file.as_deref(), Some(file.as_path()));
+        assert_eq!((report.line, report.column), (Some(2), Some(9)));
+        assert_eq!(report.offset, Some(17));
+    }
+
+    #[test]
+    fn test_byte_offset_counts_characters() {
+        assert_eq!(byte_offset("é<a>\n", 1, 2), Some(2));
+        assert_eq!(byte_offset("a\nb", 2, 2), Some(3));
+        assert_eq!(byte_offset("a\nb", 2, 5), None);
+    }
+}
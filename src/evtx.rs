@@ -0,0 +1,650 @@
+//! Reading Windows event log (`.evtx`) files.
+//!
+//! An EVTX file is a 4 KiB header followed by 64 KiB chunks, each holding
+//! event records whose XML is stored as binary XML: a token stream in
+//! which element and attribute names are offsets into the chunk and an
+//! event is usually a template, defined once per chunk, instantiated with
+//! an array of typed substitution values. This reads records back into
+//! XML element trees, enough to get at an event's `System` header and
+//! `EventData`; it does not verify checksums, and a record that does not
+//! parse is skipped rather than failing the file.
+
+use log::debug;
+use std::path::Path;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+const FILE_HEADER_SIZE: usize = 4096;
+const CHUNK_SIZE: usize = 65536;
+/// Records start after the chunk header and its string and template tables.
+const CHUNK_RECORDS: usize = 512;
+
+/// The Sysmon event log's provider name.
+pub const SYSMON_PROVIDER: &str = "Microsoft-Windows-Sysmon";
+
+/// One event record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub record_id: u64,
+    /// When the record was written, in seconds since the Unix epoch.
+    pub time: i64,
+    pub provider: String,
+    pub id: u32,
+    /// `EventData` values by `Name`, in document order.
+    pub data: Vec<(String, String)>,
+}
+
+impl Event {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Reads an event from the `<Event>` element of a record.
+    pub fn from_element(record_id: u64, time: i64, root: &Element) -> Option<Event> {
+        let system = root.get_child("System")?;
+        let provider = system
+            .get_child("Provider")
+            .and_then(|p| p.attributes.get("Name"))
+            .cloned()
+            .unwrap_or_default();
+        let id = text(system.get_child("EventID")?).trim().parse().ok()?;
+        let data = root
+            .get_child("EventData")
+            .map(|data| {
+                data.children
+                    .iter()
+                    .filter_map(|node| match node {
+                        XMLNode::Element(e) if e.name == "Data" => Some(e),
+                        _ => None,
+                    })
+                    .filter_map(|e| Some((e.attributes.get("Name")?.clone(), text(e))))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Event {
+            record_id,
+            time,
+            provider,
+            id,
+            data,
+        })
+    }
+}
+
+/// The events of an EVTX file, and how many records could not be read.
+#[derive(Debug, Default)]
+pub struct Log {
+    pub events: Vec<Event>,
+    pub unreadable: usize,
+}
+
+pub fn read(path: &Path) -> Result<Log, ConversionError> {
+    let bytes = std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+    parse(&bytes).map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Log, String> {
+    if !bytes.starts_with(b"ElfFile\0") {
+        return Err("not an EVTX file".to_string());
+    }
+    let mut log = Log::default();
+    let mut offset = FILE_HEADER_SIZE;
+    while offset + CHUNK_SIZE <= bytes.len() {
+        let chunk = &bytes[offset..offset + CHUNK_SIZE];
+        offset += CHUNK_SIZE;
+        // Space preallocated for chunks not yet written is zeroed.
+        if !chunk.starts_with(b"ElfChnk\0") {
+            continue;
+        }
+        read_chunk(chunk, &mut log);
+    }
+    Ok(log)
+}
+
+fn read_chunk(chunk: &[u8], log: &mut Log) {
+    let free = u32_at(chunk, 48).map_or(chunk.len(), |f| (f as usize).min(chunk.len()));
+    let mut offset = CHUNK_RECORDS;
+    while offset + 28 <= free {
+        if chunk[offset..offset + 4] != [0x2a, 0x2a, 0, 0] {
+            break;
+        }
+        let size = u32_at(chunk, offset + 4).unwrap_or(0) as usize;
+        if size < 28 || offset + size > free {
+            break;
+        }
+        let record_id = u64_at(chunk, offset + 8).unwrap_or(0);
+        let time = filetime_to_unix(u64_at(chunk, offset + 16).unwrap_or(0));
+        let event = Parser { chunk }
+            .document(offset + 24, offset + size - 4)
+            .and_then(|root| {
+                Event::from_element(record_id, time, &root)
+                    .ok_or_else(|| "no System/EventID".to_string())
+            });
+        match event {
+            Ok(event) => log.events.push(event),
+            Err(e) => {
+                debug!("Skipping event record {}: {}", record_id, e);
+                log.unreadable += 1;
+            }
+        }
+        offset += size;
+    }
+}
+
+/// Seconds since the Unix epoch of a FILETIME (100 ns ticks since 1601).
+fn filetime_to_unix(filetime: u64) -> i64 {
+    (filetime / 10_000_000) as i64 - 11_644_473_600
+}
+
+/// A substitution value: its type and where its bytes are in the chunk.
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    kind: u8,
+    start: usize,
+    end: usize,
+}
+
+struct Parser<'a> {
+    chunk: &'a [u8],
+}
+
+impl Parser<'_> {
+    /// The root element of the binary XML between `start` and `end`.
+    fn document(&self, start: usize, end: usize) -> Result<Element, String> {
+        let mut pos = start;
+        let nodes = self.content(&mut pos, end, &[])?;
+        nodes
+            .into_iter()
+            .find_map(|node| match node {
+                XMLNode::Element(element) => Some(element),
+                _ => None,
+            })
+            .ok_or_else(|| "record holds no element".to_string())
+    }
+
+    /// Nodes up to an end-of-element or end-of-fragment token, or `end`.
+    fn content(&self, pos: &mut usize, end: usize, subs: &[Value]) -> Result<Vec<XMLNode>, String> {
+        let mut nodes = Vec::new();
+        while *pos < end {
+            let token = self.u8(*pos)?;
+            match token & 0xbf {
+                0x00 | 0x04 => {
+                    *pos += 1;
+                    break;
+                }
+                0x0f => *pos += 4,
+                0x01 => nodes.push(XMLNode::Element(self.element(pos, subs)?)),
+                0x0c => nodes.extend(self.template(pos)?),
+                0x05 | 0x07 | 0x08 | 0x09 | 0x0d | 0x0e => {
+                    let text = self.value_token(pos, subs, &mut nodes)?;
+                    push_text(&mut nodes, text);
+                }
+                0x0a => {
+                    *pos += 1;
+                    self.name(pos)?;
+                }
+                0x0b => {
+                    let count = self.u16(*pos + 1)? as usize;
+                    *pos += 3 + 2 * count;
+                }
+                _ => return Err(format!("unexpected token {:#04x} at {:#x}", token, pos)),
+            }
+        }
+        Ok(nodes)
+    }
+
+    /// The text of a value, character or entity reference, or
+    /// substitution token. A substitution holding binary XML adds its
+    /// nodes to `nodes` instead.
+    fn value_token(
+        &self,
+        pos: &mut usize,
+        subs: &[Value],
+        nodes: &mut Vec<XMLNode>,
+    ) -> Result<String, String> {
+        let token = self.u8(*pos)?;
+        *pos += 1;
+        match token & 0xbf {
+            0x05 => {
+                *pos += 1;
+                self.counted_string(pos)
+            }
+            0x07 => self.counted_string(pos),
+            0x08 => {
+                let c = self.u16(*pos)?;
+                *pos += 2;
+                Ok(char::from_u32(c.into())
+                    .map(String::from)
+                    .unwrap_or_default())
+            }
+            0x09 => {
+                let name = self.name(pos)?;
+                Ok(match name.as_str() {
+                    "amp" => "&",
+                    "lt" => "<",
+                    "gt" => ">",
+                    "quot" => "\"",
+                    "apos" => "'",
+                    _ => "",
+                }
+                .to_string())
+            }
+            _ => {
+                let index = self.u16(*pos)? as usize;
+                *pos += 3;
+                let Some(value) = subs.get(index) else {
+                    return Ok(String::new());
+                };
+                if value.kind == 0x21 {
+                    let mut inner = value.start;
+                    nodes.extend(self.content(&mut inner, value.end, &[])?);
+                    return Ok(String::new());
+                }
+                Ok(self.render(value))
+            }
+        }
+    }
+
+    fn element(&self, pos: &mut usize, subs: &[Value]) -> Result<Element, String> {
+        let token = self.u8(*pos)?;
+        // Token, dependency ID and data size.
+        *pos += 7;
+        let mut element = Element::new(&self.name(pos)?);
+        if token & 0x40 != 0 {
+            let size = self.u32(*pos)? as usize;
+            *pos += 4;
+            let end = *pos + size;
+            while *pos < end {
+                let attribute = self.u8(*pos)?;
+                if attribute & 0xbf != 0x06 {
+                    return Err(format!("expected an attribute at {:#x}", pos));
+                }
+                *pos += 1;
+                let name = self.name(pos)?;
+                let mut value = String::new();
+                while *pos < end
+                    && matches!(self.u8(*pos)? & 0xbf, 0x05 | 0x08 | 0x09 | 0x0d | 0x0e)
+                {
+                    value += &self.value_token(pos, subs, &mut Vec::new())?;
+                }
+                element.attributes.insert(name, value);
+            }
+        }
+        match self.u8(*pos)? {
+            0x02 => {
+                *pos += 1;
+                element.children = self.content(pos, self.chunk.len(), subs)?;
+            }
+            0x03 => *pos += 1,
+            token => return Err(format!("unexpected token {:#04x} at {:#x}", token, pos)),
+        }
+        Ok(element)
+    }
+
+    /// Instantiates the template at `pos` with its substitution values.
+    fn template(&self, pos: &mut usize) -> Result<Vec<XMLNode>, String> {
+        // Token, a byte of unknown use, and the template ID.
+        let definition = self.u32(*pos + 6)? as usize;
+        *pos += 10;
+        let body_size = self.u32(definition + 20)? as usize;
+        // A template used for the first time in a chunk is defined inline.
+        if definition == *pos {
+            *pos += 24 + body_size;
+        }
+
+        let count = self.u32(*pos)? as usize;
+        *pos += 4;
+        let mut subs = Vec::with_capacity(count);
+        let mut start = *pos + 4 * count;
+        for i in 0..count {
+            let size = self.u16(*pos + 4 * i)? as usize;
+            let kind = self.u8(*pos + 4 * i + 2)?;
+            subs.push(Value {
+                kind,
+                start,
+                end: start + size,
+            });
+            start += size;
+        }
+        if start > self.chunk.len() {
+            return Err("substitution values run past the chunk".to_string());
+        }
+        *pos = start;
+
+        let mut body = definition + 24;
+        self.content(&mut body, definition + 24 + body_size, &subs)
+    }
+
+    /// The name at the offset stored at `pos`, skipping it when it is
+    /// stored inline.
+    fn name(&self, pos: &mut usize) -> Result<String, String> {
+        let offset = self.u32(*pos)? as usize;
+        *pos += 4;
+        let count = self.u16(offset + 6)? as usize;
+        let name = self.utf16(offset + 8, count)?;
+        if offset == *pos {
+            *pos += 10 + 2 * count;
+        }
+        Ok(name)
+    }
+
+    /// A string stored as a character count and UTF-16 characters.
+    fn counted_string(&self, pos: &mut usize) -> Result<String, String> {
+        let count = self.u16(*pos)? as usize;
+        let text = self.utf16(*pos + 2, count)?;
+        *pos += 2 + 2 * count;
+        Ok(text)
+    }
+
+    /// A substitution value as Windows renders it in event XML.
+    fn render(&self, value: &Value) -> String {
+        let bytes = &self.chunk[value.start..value.end];
+        let int = |n: usize| -> u64 {
+            bytes
+                .iter()
+                .take(n)
+                .rev()
+                .fold(0, |acc, b| acc << 8 | u64::from(*b))
+        };
+        match value.kind {
+            0x00 => String::new(),
+            0x01 => utf16(bytes).trim_end_matches('\0').to_string(),
+            0x02 => String::from_utf8_lossy(bytes)
+                .trim_end_matches('\0')
+                .to_string(),
+            0x03 => (int(1) as i8).to_string(),
+            0x04 => int(1).to_string(),
+            0x05 => (int(2) as i16).to_string(),
+            0x06 => int(2).to_string(),
+            0x07 => (int(4) as i32).to_string(),
+            0x08 => int(4).to_string(),
+            0x09 => (int(8) as i64).to_string(),
+            0x0a | 0x11 => int(8).to_string(),
+            0x0b => f32::from_bits(int(4) as u32).to_string(),
+            0x0c => f64::from_bits(int(8)).to_string(),
+            0x0d => (int(4) != 0).to_string(),
+            0x0f if bytes.len() == 16 => guid(bytes),
+            0x10 | 0x14 | 0x15 => format!("{:#x}", int(bytes.len().min(8))),
+            0x13 => sid(bytes),
+            0x81 => utf16(bytes)
+                .split('\0')
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => bytes.iter().map(|b| format!("{:02X}", b)).collect(),
+        }
+    }
+
+    fn utf16(&self, start: usize, count: usize) -> Result<String, String> {
+        let bytes = self.slice(start, 2 * count)?;
+        Ok(utf16(bytes))
+    }
+
+    fn slice(&self, start: usize, len: usize) -> Result<&[u8], String> {
+        self.chunk
+            .get(start..start + len)
+            .ok_or_else(|| format!("offset {:#x} is past the chunk", start))
+    }
+
+    fn u8(&self, at: usize) -> Result<u8, String> {
+        Ok(self.slice(at, 1)?[0])
+    }
+
+    fn u16(&self, at: usize) -> Result<u16, String> {
+        let bytes = self.slice(at, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&self, at: usize) -> Result<u32, String> {
+        u32_at(self.chunk, at).ok_or_else(|| format!("offset {:#x} is past the chunk", at))
+    }
+}
+
+fn push_text(nodes: &mut Vec<XMLNode>, text: String) {
+    if text.is_empty() {
+        return;
+    }
+    match nodes.last_mut() {
+        Some(XMLNode::Text(last)) => last.push_str(&text),
+        _ => nodes.push(XMLNode::Text(text)),
+    }
+}
+
+fn text(element: &Element) -> String {
+    element
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            XMLNode::Text(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// `{XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX}`, with the first three groups
+/// little-endian.
+fn guid(bytes: &[u8]) -> String {
+    let hex = |range: &[u8]| -> String { range.iter().map(|b| format!("{:02X}", b)).collect() };
+    let reversed =
+        |range: &[u8]| -> String { range.iter().rev().map(|b| format!("{:02X}", b)).collect() };
+    format!(
+        "{{{}-{}-{}-{}-{}}}",
+        reversed(&bytes[0..4]),
+        reversed(&bytes[4..6]),
+        reversed(&bytes[6..8]),
+        hex(&bytes[8..10]),
+        hex(&bytes[10..16])
+    )
+}
+
+/// `S-1-5-18`.
+fn sid(bytes: &[u8]) -> String {
+    if bytes.len() < 8 {
+        return String::new();
+    }
+    let authority = bytes[2..8]
+        .iter()
+        .fold(0u64, |acc, b| acc << 8 | u64::from(*b));
+    let mut out = format!("S-{}-{}", bytes[0], authority);
+    for sub in bytes[8..].chunks_exact(4).take(bytes[1].into()) {
+        out += &format!("-{}", u32::from_le_bytes([sub[0], sub[1], sub[2], sub[3]]));
+    }
+    out
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> Option<u64> {
+    let b = bytes.get(at..at + 8)?;
+    Some(u64::from_le_bytes(b.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes binary XML into a chunk, at offsets from the chunk's start.
+    struct Chunk(Vec<u8>);
+
+    impl Chunk {
+        fn u16(&mut self, n: u16) {
+            self.0.extend(n.to_le_bytes());
+        }
+
+        fn u32(&mut self, n: u32) {
+            self.0.extend(n.to_le_bytes());
+        }
+
+        fn utf16(&mut self, text: &str) {
+            self.u16(text.len() as u16);
+            for unit in text.encode_utf16() {
+                self.u16(unit);
+            }
+        }
+
+        /// A name stored inline, right after its offset.
+        fn name(&mut self, name: &str) {
+            let offset = self.0.len() as u32 + 4;
+            self.u32(offset);
+            self.u32(0);
+            self.u16(0);
+            self.utf16(name);
+            self.u16(0);
+        }
+
+        fn open(&mut self, name: &str, attribute: Option<(&str, &str)>, empty: bool) {
+            self.0.push(if attribute.is_some() { 0x41 } else { 0x01 });
+            self.u16(0xffff);
+            self.u32(0);
+            self.name(name);
+            if let Some((name, value)) = attribute {
+                let size_at = self.0.len();
+                self.u32(0);
+                self.0.push(0x06);
+                self.name(name);
+                self.0.push(0x05);
+                self.0.push(0x01);
+                self.utf16(value);
+                let size = (self.0.len() - size_at - 4) as u32;
+                self.0[size_at..size_at + 4].copy_from_slice(&size.to_le_bytes());
+            }
+            self.0.push(if empty { 0x03 } else { 0x02 });
+        }
+
+        fn substitution(&mut self, index: u16, kind: u8) {
+            self.0.push(0x0d);
+            self.u16(index);
+            self.0.push(kind);
+        }
+
+        /// A record instantiating the template at `definition`, defining it
+        /// inline when `None`; returns where the definition is.
+        fn record(&mut self, id: u64, definition: Option<u32>, values: &[(u8, Vec<u8>)]) -> u32 {
+            let start = self.0.len();
+            self.0.extend([0x2a, 0x2a, 0, 0]);
+            self.u32(0);
+            self.0.extend(id.to_le_bytes());
+            self.0
+                .extend((116_444_736_000_000_000u64 + id * 10_000_000).to_le_bytes());
+            self.0.extend([0x0f, 1, 1, 0, 0x0c, 1]);
+            self.u32(1);
+            let definition = match definition {
+                Some(definition) => {
+                    self.u32(definition);
+                    definition
+                }
+                None => {
+                    let definition = self.0.len() as u32 + 4;
+                    self.u32(definition);
+                    self.u32(0);
+                    self.0.extend([0; 16]);
+                    let size_at = self.0.len();
+                    self.u32(0);
+                    self.0.extend([0x0f, 1, 1, 0]);
+                    self.open("Event", None, false);
+                    self.open("System", None, false);
+                    self.open("Provider", Some(("Name", SYSMON_PROVIDER)), true);
+                    self.open("EventID", None, false);
+                    self.substitution(0, 0x06);
+                    self.0.push(0x04);
+                    self.0.push(0x04);
+                    self.open("EventData", None, false);
+                    for (index, name) in [(1, "Image"), (2, "ProcessId"), (3, "ProcessGuid")] {
+                        self.open("Data", Some(("Name", name)), false);
+                        self.substitution(index, values[index as usize].0);
+                        self.0.push(0x04);
+                    }
+                    self.0.extend([0x04, 0x04, 0x00]);
+                    let size = (self.0.len() - size_at - 4) as u32;
+                    self.0[size_at..size_at + 4].copy_from_slice(&size.to_le_bytes());
+                    definition
+                }
+            };
+            self.u32(values.len() as u32);
+            for (kind, bytes) in values {
+                self.u16(bytes.len() as u16);
+                self.0.extend([*kind, 0]);
+            }
+            for (_, bytes) in values {
+                self.0.extend(bytes);
+            }
+            self.0.push(0x00);
+            let size = (self.0.len() - start + 4) as u32;
+            self.u32(size);
+            self.0[start + 4..start + 8].copy_from_slice(&size.to_le_bytes());
+            definition
+        }
+    }
+
+    fn values(id: u16, image: &str, pid: u32) -> Vec<(u8, Vec<u8>)> {
+        let image = image.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let guid = (1..=16).collect();
+        vec![
+            (0x06, id.to_le_bytes().to_vec()),
+            (0x01, image),
+            (0x08, pid.to_le_bytes().to_vec()),
+            (0x0f, guid),
+        ]
+    }
+
+    #[test]
+    fn test_parse_reads_template_records() {
+        let mut chunk = Chunk(b"ElfChnk\0".to_vec());
+        chunk.0.resize(CHUNK_RECORDS, 0);
+        let definition = chunk.record(1, None, &values(1, r"C:\Windows\cmd.exe", 4242));
+        chunk.record(2, Some(definition), &values(5, r"C:\Tools\a&b.exe", 7));
+        let free = chunk.0.len() as u32;
+        chunk.0[48..52].copy_from_slice(&free.to_le_bytes());
+        chunk.0.resize(CHUNK_SIZE, 0);
+        let mut file = b"ElfFile\0".to_vec();
+        file.resize(FILE_HEADER_SIZE, 0);
+        file.extend(chunk.0);
+
+        let log = parse(&file).unwrap();
+        assert_eq!(log.unreadable, 0);
+        let data = |image: &str, pid: &str| {
+            vec![
+                ("Image".to_string(), image.to_string()),
+                ("ProcessId".to_string(), pid.to_string()),
+                (
+                    "ProcessGuid".to_string(),
+                    "{04030201-0605-0807-090A-0B0C0D0E0F10}".to_string(),
+                ),
+            ]
+        };
+        assert_eq!(
+            log.events,
+            [
+                Event {
+                    record_id: 1,
+                    time: 1,
+                    provider: SYSMON_PROVIDER.to_string(),
+                    id: 1,
+                    data: data(r"C:\Windows\cmd.exe", "4242"),
+                },
+                Event {
+                    record_id: 2,
+                    time: 2,
+                    provider: SYSMON_PROVIDER.to_string(),
+                    id: 5,
+                    data: data(r"C:\Tools\a&b.exe", "7"),
+                },
+            ]
+        );
+        assert!(parse(b"not a log").is_err());
+    }
+}
@@ -0,0 +1,617 @@
+//! `explain`: describe an event type or a rule in plain English.
+//!
+//! Sysmon's filtering semantics are easy to misread from the XML: an event
+//! is logged when an include rule matches and no exclude rule does, the
+//! conditions directly under an event filter are combined with their
+//! rule group's `groupRelation`, and a `<Rule>` combines its own conditions
+//! with its own. `explain` spells that out for one event type, for the
+//! rules with a given name, or for every rule with a condition matching a
+//! `query` expression, along with the ATT&CK techniques the names cite and
+//! their tactics.
+
+use crate::attack::{self, Database, Lookup};
+use crate::{io_guard, query, validate};
+use clap::Args;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::{
+    Condition, EventFilter, FieldCondition, Filter, GroupRelation, OnMatch, RuleGroup, SysmonConfig,
+};
+use sysmon_cli::schema;
+use sysmon_json::error::ConversionError;
+
+#[derive(Args)]
+pub struct ExplainArgs {
+    /// Configuration to explain (XML or JSON)
+    pub config: PathBuf,
+
+    /// Event type (e.g. ProcessCreate), or the name of the rules to explain
+    #[arg(required_unless_present = "query")]
+    pub target: Option<String>,
+
+    /// Explain every rule with a condition matching this `query` expression
+    #[arg(long, conflicts_with = "target")]
+    pub query: Option<String>,
+
+    /// Write the explanation to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// A rule picked for explanation: a field condition or a `<Rule>`, with
+/// where it sits in the config.
+struct Selected<'a> {
+    group_index: usize,
+    group: &'a RuleGroup,
+    event: &'a EventFilter,
+    /// XML path of the rule, as understood by `validate::line_of`.
+    location: String,
+    filter: &'a Filter,
+}
+
+pub fn run(args: &ExplainArgs) -> Result<(), ConversionError> {
+    let (text, is_xml) = validate::load(&args.config)?;
+    let config = SysmonConfig::from_xml_str(&text)?;
+    let text = is_xml.then_some(text.as_str());
+    let attack = Database::load();
+
+    let explanation = match (&args.query, &args.target) {
+        (Some(expression), _) => {
+            let expr = query::parse(expression)?;
+            let selected = select(&config, |event, field, name| {
+                query::evaluate(
+                    &expr,
+                    &query::candidate(&args.config, event.0, event.1, field, name),
+                )
+            });
+            if selected.is_empty() {
+                return Err(ConversionError::Other(format!(
+                    "No rule in {} has a condition matching {:?}",
+                    args.config.display(),
+                    expression
+                )));
+            }
+            explain_rules(&config, &attack, &selected, text)
+        }
+        (None, Some(target)) if schema::event_type(target).is_some() => {
+            explain_event(&config, &attack, target, text)
+        }
+        (None, Some(target)) => {
+            let selected = select(&config, |_, _, name| {
+                name.is_some_and(|n| n.eq_ignore_ascii_case(target))
+            });
+            if selected.is_empty() {
+                return Err(ConversionError::Other(format!(
+                    "{} has no event type or rule named {:?}",
+                    args.config.display(),
+                    target
+                )));
+            }
+            explain_rules(&config, &attack, &selected, text)
+        }
+        (None, None) => unreachable!("clap requires a target unless --query is given"),
+    };
+
+    match &args.output {
+        Some(path) => io_guard::write(path, explanation),
+        None => {
+            print!("{}", explanation);
+            Ok(())
+        }
+    }
+}
+
+/// Rules with at least one condition accepted by `wanted`, which sees the
+/// condition's group and event filter, the condition, and its name or its
+/// `<Rule>`'s.
+fn select<'a>(
+    config: &'a SysmonConfig,
+    wanted: impl Fn((&RuleGroup, &EventFilter), &FieldCondition, Option<&str>) -> bool,
+) -> Vec<Selected<'a>> {
+    let mut selected = Vec::new();
+    for (group_index, group) in config.rule_groups.iter().enumerate() {
+        for (event_index, event) in group.events.iter().enumerate() {
+            let event_nth = group.events[..event_index]
+                .iter()
+                .filter(|e| e.event == event.event)
+                .count()
+                + 1;
+            let mut seen: Vec<&str> = Vec::new();
+            let mut rules = 0;
+            for filter in &event.filters {
+                let (fields, rule_name, segment) = match filter {
+                    Filter::Field(field) => {
+                        let nth = seen.iter().filter(|f| **f == field.field).count() + 1;
+                        (
+                            std::slice::from_ref(field),
+                            None,
+                            format!("{}[{}]", field.field, nth),
+                        )
+                    }
+                    Filter::Rule(rule) => {
+                        rules += 1;
+                        (
+                            rule.fields.as_slice(),
+                            rule.name.as_deref(),
+                            format!("Rule[{}]", rules),
+                        )
+                    }
+                };
+                seen.extend(fields.iter().map(|f| f.field.as_str()));
+                let matched = fields.iter().any(|field| {
+                    wanted((group, event), field, field.name.as_deref().or(rule_name))
+                });
+                if matched {
+                    selected.push(Selected {
+                        group_index,
+                        group,
+                        event,
+                        location: format!(
+                            "RuleGroup[{}]/{}[{}]/{}",
+                            group_index + 1,
+                            event.event,
+                            event_nth,
+                            segment
+                        ),
+                        filter,
+                    });
+                }
+            }
+        }
+    }
+    selected
+}
+
+fn explain_event(
+    config: &SysmonConfig,
+    attack: &Database,
+    name: &str,
+    text: Option<&str>,
+) -> String {
+    let mut out = format!("{}.\n", applies_to(name));
+    let filters: Vec<(usize, &RuleGroup, &EventFilter)> = config
+        .rule_groups
+        .iter()
+        .enumerate()
+        .flat_map(|(i, group)| group.events.iter().map(move |event| (i, group, event)))
+        .filter(|(_, _, event)| event.event == name)
+        .collect();
+    if filters.is_empty() {
+        let _ = writeln!(
+            out,
+            "This config has no {} filter, so Sysmon's built-in default for it applies.",
+            name
+        );
+        return out;
+    }
+
+    let count = |onmatch: OnMatch| -> usize {
+        filters
+            .iter()
+            .filter(|(_, _, event)| event.onmatch == onmatch)
+            .map(|(_, _, event)| event.filters.len())
+            .sum()
+    };
+    let (includes, excludes) = (count(OnMatch::Include), count(OnMatch::Exclude));
+    let has = |onmatch: OnMatch| filters.iter().any(|(_, _, e)| e.onmatch == onmatch);
+    let effect = match (has(OnMatch::Include), has(OnMatch::Exclude)) {
+        (true, false) if includes == 0 => "None are logged: the include filter is empty.".into(),
+        (true, false) => format!(
+            "Logged only when one of its {} include rule(s) matches.",
+            includes
+        ),
+        (false, _) if excludes == 0 => "Every event is logged: the exclude filter is empty.".into(),
+        (false, _) => format!(
+            "Every event is logged except those matching one of its {} exclude rule(s).",
+            excludes
+        ),
+        (true, true) => format!(
+            "Logged when one of its {} include rule(s) matches and none of its {} exclude \
+             rule(s) does; exclusions win.",
+            includes, excludes
+        ),
+    };
+    let _ = writeln!(out, "{}", effect);
+
+    let mut techniques = Vec::new();
+    for (index, group, event) in &filters {
+        let event_nth = group
+            .events
+            .iter()
+            .take_while(|e| !std::ptr::eq(*e, *event))
+            .filter(|e| e.event == name)
+            .count()
+            + 1;
+        let location = format!("RuleGroup[{}]/{}[{}]", index + 1, name, event_nth);
+        let _ = writeln!(
+            out,
+            "  {}{}: {} {} rule(s), combined with {}",
+            group_label(*index, group),
+            line_suffix(text, &location),
+            event.onmatch.as_str(),
+            event.filters.len(),
+            relation(group).as_str().to_uppercase()
+        );
+        techniques.extend(group.name.iter().flat_map(|n| self::techniques(n)));
+        for filter in &event.filters {
+            techniques.extend(names(filter).into_iter().flat_map(self::techniques));
+        }
+    }
+    let _ = writeln!(out, "{}", attack_line(attack, techniques));
+    out
+}
+
+fn explain_rules(
+    config: &SysmonConfig,
+    attack: &Database,
+    selected: &[Selected],
+    text: Option<&str>,
+) -> String {
+    let mut out = String::new();
+    for (i, rule) in selected.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let title = match rule.filter {
+            Filter::Field(field) => match &field.name {
+                Some(name) => format!("{} condition {:?}", field.field, name),
+                None => format!("{} condition", field.field),
+            },
+            Filter::Rule(r) => match &r.name {
+                Some(name) => format!("Rule {:?}", name),
+                None => "Rule".to_string(),
+            },
+        };
+        let _ = writeln!(
+            out,
+            "{}/{}{}: {}",
+            group_label(rule.group_index, rule.group),
+            rule.event.event,
+            line_suffix(text, &rule.location),
+            title
+        );
+        let _ = writeln!(out, "  Applies to {}.", applies_to(&rule.event.event));
+        let _ = writeln!(out, "  Effect: {}", effect(config, rule.event));
+        let _ = writeln!(out, "  Matches when {}", matches_when(rule.filter));
+        let _ = writeln!(out, "  Relation: {}", sibling_relation(rule));
+
+        let mut techniques: Vec<String> =
+            rule.group.name.iter().flat_map(|n| techniques(n)).collect();
+        techniques.extend(names(rule.filter).into_iter().flat_map(self::techniques));
+        let _ = writeln!(out, "  {}", attack_line(attack, techniques));
+    }
+    out
+}
+
+fn applies_to(event: &str) -> String {
+    let ids = schema::event_type(event)
+        .map(|kind| {
+            kind.ids
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_else(|| "unknown".into());
+    let plural = if ids.contains(',') { "s" } else { "" };
+    format!(
+        "{} (Sysmon event ID{} {}), logged when {}",
+        event,
+        plural,
+        ids,
+        description(event)
+    )
+}
+
+/// What an event type records, completing "logged when ...".
+fn description(event: &str) -> &'static str {
+    match event {
+        "ProcessCreate" => "a process starts",
+        "FileCreateTime" => "a process changes a file's creation time",
+        "NetworkConnect" => "a process opens a TCP or UDP connection",
+        "ProcessTerminate" => "a process exits",
+        "DriverLoad" => "a driver is loaded",
+        "ImageLoad" => "a process loads a DLL or other module",
+        "CreateRemoteThread" => "a process starts a thread in another process",
+        "RawAccessRead" => "a process reads a drive directly, bypassing the file system",
+        "ProcessAccess" => "a process opens another process",
+        "FileCreate" => "a file is created or overwritten",
+        "RegistryEvent" => "a registry key or value is created, deleted, set or renamed",
+        "FileCreateStreamHash" => "an alternate data stream is created",
+        "PipeEvent" => "a named pipe is created or connected to",
+        "WmiEvent" => "a WMI filter, consumer or binding is registered",
+        "DnsQuery" => "a process performs a DNS query",
+        "FileDelete" => "a file is deleted, keeping a copy in the archive directory",
+        "ClipboardChange" => "the clipboard contents change",
+        "ProcessTampering" => "a process image is hollowed or herpaderped",
+        "FileDeleteDetected" => "a file is deleted, without archiving it",
+        "FileBlockExecutable" => "Sysmon blocks the creation of an executable",
+        "FileBlockShredding" => "Sysmon blocks a file from being shredded",
+        "FileExecutableDetected" => "an executable file is created",
+        _ => "its events occur",
+    }
+}
+
+fn effect(config: &SysmonConfig, event: &EventFilter) -> String {
+    let other = match event.onmatch {
+        OnMatch::Include => OnMatch::Exclude,
+        OnMatch::Exclude => OnMatch::Include,
+    };
+    let has_other = config
+        .events()
+        .any(|e| e.event == event.event && e.onmatch == other);
+    match (event.onmatch, has_other) {
+        (OnMatch::Include, true) => format!(
+            "include. A match logs the event unless an exclude rule for {} also matches.",
+            event.event
+        ),
+        (OnMatch::Include, false) => "include. A match logs the event.".to_string(),
+        (OnMatch::Exclude, true) => {
+            "exclude. A match drops the event, even when an include rule matches too.".to_string()
+        }
+        (OnMatch::Exclude, false) => format!(
+            "exclude. A match drops the event; with no include filter for {}, every event \
+             no exclusion matches is logged.",
+            event.event
+        ),
+    }
+}
+
+fn matches_when(filter: &Filter) -> String {
+    match filter {
+        Filter::Field(field) => format!("{}.", phrase(field)),
+        Filter::Rule(rule) => {
+            let conditions: Vec<String> = rule.fields.iter().map(phrase).collect();
+            match (rule.group_relation, conditions.len()) {
+                (_, 1) => format!("{}.", conditions[0]),
+                (GroupRelation::And, n) => {
+                    format!("all {} of these hold: {}.", n, conditions.join("; "))
+                }
+                (GroupRelation::Or, n) => {
+                    format!("any of these {} holds: {}.", n, conditions.join("; "))
+                }
+            }
+        }
+    }
+}
+
+fn sibling_relation(rule: &Selected) -> String {
+    let siblings = rule.event.filters.len();
+    if siblings <= 1 {
+        return format!(
+            "the only rule in this {} {} filter.",
+            rule.event.event,
+            rule.event.onmatch.as_str()
+        );
+    }
+    match relation(rule.group) {
+        GroupRelation::Or => format!(
+            "one of {} rules in this {} filter, combined with OR: any one of them matching \
+             is enough.",
+            siblings, rule.event.event
+        ),
+        GroupRelation::And => format!(
+            "one of {} rules in this {} filter, combined with AND (groupRelation=\"and\"): \
+             the other {} must match as well.",
+            siblings,
+            rule.event.event,
+            siblings - 1
+        ),
+    }
+}
+
+/// A rule group's relation; Sysmon treats a missing `groupRelation` as `or`.
+fn relation(group: &RuleGroup) -> GroupRelation {
+    group.group_relation.unwrap_or(GroupRelation::Or)
+}
+
+fn phrase(field: &FieldCondition) -> String {
+    let values = || {
+        field
+            .value
+            .split(';')
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("{:?}", v))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let (verb, value) = match field.condition {
+        Condition::Is => ("is", format!("{:?}", field.value)),
+        Condition::IsNot => ("is not", format!("{:?}", field.value)),
+        Condition::IsAny => ("is one of", values()),
+        Condition::Contains => ("contains", format!("{:?}", field.value)),
+        Condition::ContainsAny => ("contains any of", values()),
+        Condition::ContainsAll => ("contains all of", values()),
+        Condition::Excludes => ("does not contain", format!("{:?}", field.value)),
+        Condition::ExcludesAny => ("lacks at least one of", values()),
+        Condition::ExcludesAll => ("contains none of", values()),
+        Condition::BeginWith => ("begins with", format!("{:?}", field.value)),
+        Condition::NotBeginWith => ("does not begin with", format!("{:?}", field.value)),
+        Condition::EndWith => ("ends with", format!("{:?}", field.value)),
+        Condition::NotEndWith => ("does not end with", format!("{:?}", field.value)),
+        Condition::LessThan => ("is less than", format!("{:?}", field.value)),
+        Condition::MoreThan => ("is greater than", format!("{:?}", field.value)),
+        Condition::Image => ("is the image name or path", format!("{:?}", field.value)),
+    };
+    format!("{} {} {}", field.field, verb, value)
+}
+
+fn names(filter: &Filter) -> Vec<&str> {
+    match filter {
+        Filter::Field(field) => field.name.as_deref().into_iter().collect(),
+        Filter::Rule(rule) => rule
+            .name
+            .as_deref()
+            .into_iter()
+            .chain(rule.fields.iter().filter_map(|f| f.name.as_deref()))
+            .collect(),
+    }
+}
+
+fn group_label(index: usize, group: &RuleGroup) -> String {
+    match group.name.as_deref().filter(|n| !n.is_empty()) {
+        Some(name) => format!("RuleGroup[{}] {:?}", index + 1, name),
+        None => format!("RuleGroup[{}]", index + 1),
+    }
+}
+
+fn line_suffix(text: Option<&str>, location: &str) -> String {
+    text.and_then(|text| validate::line_of(text, location))
+        .map(|line| format!(" (line {})", line))
+        .unwrap_or_default()
+}
+
+/// ATT&CK technique IDs (`T1055`, `T1059.001`) cited in a rule name, with
+/// the `technique_name=` that sysmon-modular style names pair them with.
+pub fn techniques(name: &str) -> Vec<String> {
+    let technique_name = name
+        .split(',')
+        .find_map(|part| part.trim().strip_prefix("technique_name="))
+        .map(str::trim)
+        .filter(|n| !n.is_empty());
+    let bytes = name.as_bytes();
+    let mut found = Vec::new();
+    for (at, _) in name.match_indices('T') {
+        let boundary = at == 0 || !bytes[at - 1].is_ascii_alphanumeric();
+        let digits = |from: usize, count: usize| {
+            bytes.len() >= from + count && bytes[from..from + count].iter().all(u8::is_ascii_digit)
+        };
+        if !boundary || !digits(at + 1, 4) {
+            continue;
+        }
+        let mut end = at + 5;
+        if bytes.get(end) == Some(&b'.') && digits(end + 1, 3) {
+            end += 4;
+        }
+        if bytes.get(end).is_some_and(u8::is_ascii_alphanumeric) {
+            continue;
+        }
+        let id = &name[at..end];
+        found.push(match technique_name {
+            Some(technique) => format!("{} ({})", id, technique),
+            None => id.to_string(),
+        });
+    }
+    found
+}
+
+/// `ATT&CK: T1059.001 (PowerShell; Execution).`, naming each of
+/// `techniques` and its tactics from the ATT&CK data where it knows them.
+fn attack_line(attack: &Database, techniques: Vec<String>) -> String {
+    let mut techniques: Vec<String> = techniques
+        .into_iter()
+        .map(|technique| {
+            let (id, cited) = match technique.split_once(' ') {
+                Some((id, cited)) => (id, cited.trim_matches(['(', ')'])),
+                None => (technique.as_str(), ""),
+            };
+            let name = match attack.lookup(id) {
+                Lookup::Current(t) => t.name.as_str(),
+                Lookup::Retired(Some(new)) => return format!("{} (retired; now {})", id, new),
+                Lookup::Retired(None) => return format!("{} (deprecated)", id),
+                Lookup::UnlistedSubTechnique | Lookup::Unknown => cited,
+            };
+            let tactics: Vec<&str> = attack
+                .tactics(id)
+                .iter()
+                .map(|t| attack::tactic_name(t))
+                .collect();
+            match (name.is_empty(), tactics.is_empty()) {
+                (true, true) => id.to_string(),
+                (false, true) => format!("{} ({})", id, name),
+                (true, false) => format!("{} ({})", id, tactics.join("/")),
+                (false, false) => format!("{} ({}; {})", id, name, tactics.join("/")),
+            }
+        })
+        .collect();
+    techniques.sort();
+    techniques.dedup();
+    if techniques.is_empty() {
+        "ATT&CK: no techniques referenced in its names.".to_string()
+    } else {
+        format!("ATT&CK: {}.", techniques.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+<EventFiltering>
+<RuleGroup name="" groupRelation="or">
+<ProcessCreate onmatch="include">
+<ParentImage name="technique_id=T1566.001,technique_name=Spearphishing Attachment" condition="end with">\winword.exe</ParentImage>
+<Rule name="technique_id=T1059.001,technique_name=PowerShell" groupRelation="and">
+<Image condition="end with">\powershell.exe</Image>
+<CommandLine condition="contains any">-enc;-ec</CommandLine>
+</Rule>
+</ProcessCreate>
+</RuleGroup>
+<RuleGroup name="" groupRelation="or">
+<ProcessCreate onmatch="exclude">
+<Image condition="is">C:\Windows\System32\backgroundTaskHost.exe</Image>
+</ProcessCreate>
+</RuleGroup>
+</EventFiltering>
+</Sysmon>
+"#;
+
+    #[test]
+    fn test_explain_rules_selected_by_query() {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        let expr = query::parse("field==CommandLine").unwrap();
+        let selected = select(&config, |(group, event), field, name| {
+            query::evaluate(
+                &expr,
+                &query::candidate(std::path::Path::new("c.xml"), group, event, field, name),
+            )
+        });
+        assert_eq!(selected.len(), 1);
+        assert_eq!(
+            selected[0].location,
+            "RuleGroup[1]/ProcessCreate[1]/Rule[1]"
+        );
+
+        let text = explain_rules(&config, &Database::bundled(), &selected, Some(CONFIG));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"RuleGroup[1]/ProcessCreate (line 6): Rule "technique_id=T1059.001,technique_name=PowerShell""#
+        );
+        assert!(lines[1].contains("(Sysmon event ID 1), logged when a process starts"));
+        assert!(lines[2].contains("unless an exclude rule for ProcessCreate also matches"));
+        assert_eq!(
+            lines[3],
+            r#"  Matches when all 2 of these hold: Image ends with "\\powershell.exe"; CommandLine contains any of "-enc", "-ec"."#
+        );
+        assert!(lines[4].contains("one of 2 rules in this ProcessCreate filter, combined with OR"));
+        assert_eq!(lines[5], "  ATT&CK: T1059.001 (PowerShell; Execution).");
+    }
+
+    #[test]
+    fn test_explain_event_summarizes_filters() {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        let attack = Database::bundled();
+        let text = explain_event(&config, &attack, "ProcessCreate", Some(CONFIG));
+        assert!(text.contains("one of its 2 include rule(s) matches and none of its 1 exclude"));
+        assert!(text.contains("  RuleGroup[2] (line 13): exclude 1 rule(s), combined with OR\n"));
+        assert!(text.ends_with(
+            "ATT&CK: T1059.001 (PowerShell; Execution), \
+             T1566.001 (Spearphishing Attachment; Initial Access).\n"
+        ));
+
+        let text = explain_event(&config, &attack, "DnsQuery", None);
+        assert!(text.contains("no DnsQuery filter"));
+    }
+
+    #[test]
+    fn test_techniques_need_word_boundaries() {
+        assert_eq!(techniques("T1055"), vec!["T1055"]);
+        assert_eq!(
+            techniques("technique_id=T1003.001,technique_name=LSASS Memory"),
+            vec!["T1003.001 (LSASS Memory)"]
+        );
+        assert!(techniques("AT1055 T10555 T12").is_empty());
+    }
+}
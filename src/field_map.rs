@@ -0,0 +1,423 @@
+//! `export field-map`: the event IDs and fields a config makes Sysmon
+//! emit, with their Elastic Common Schema (ECS) and Splunk Common
+//! Information Model (CIM) names.
+//!
+//! Event types the config logs nothing of are left out, as are those it
+//! does not configure unless Sysmon logs them by default (see
+//! [`schema::LOGGED_BY_DEFAULT`]); what is listed is what an ingestion
+//! pipeline for the deployed policy will see. The ECS
+//! names follow Elastic's Sysmon integration, which keeps fields ECS has
+//! no place for under `winlog.event_data`; a field with no CIM
+//! counterpart has a `null` CIM name. `Hashes` is listed once per
+//! algorithm in `<HashAlgorithms>`, as the pipelines split it.
+
+use crate::posture::{self, Posture};
+use crate::{io_guard, validate};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::schema::{self, Platform};
+use sysmon_json::error::ConversionError;
+
+/// Sysmon fields with the same ECS and CIM names in every event type.
+const FIELDS: &[(&str, &str, Option<&str>)] = &[
+    ("UtcTime", "@timestamp", Some("_time")),
+    ("RuleName", "rule.name", None),
+    ("EventType", "event.action", Some("action")),
+    ("ProcessGuid", "process.entity_id", Some("process_guid")),
+    ("ProcessId", "process.pid", Some("process_id")),
+    ("Image", "process.executable", Some("process_path")),
+    ("FileVersion", "process.pe.file_version", None),
+    ("Description", "process.pe.description", None),
+    ("Product", "process.pe.product", None),
+    ("Company", "process.pe.company", None),
+    (
+        "OriginalFileName",
+        "process.pe.original_file_name",
+        Some("original_file_name"),
+    ),
+    ("CommandLine", "process.command_line", Some("process")),
+    (
+        "CurrentDirectory",
+        "process.working_directory",
+        Some("process_current_directory"),
+    ),
+    ("User", "user.name", Some("user")),
+    ("LogonId", "winlog.logon.id", None),
+    (
+        "IntegrityLevel",
+        "winlog.event_data.IntegrityLevel",
+        Some("process_integrity_level"),
+    ),
+    (
+        "ParentProcessGuid",
+        "process.parent.entity_id",
+        Some("parent_process_guid"),
+    ),
+    (
+        "ParentProcessId",
+        "process.parent.pid",
+        Some("parent_process_id"),
+    ),
+    (
+        "ParentImage",
+        "process.parent.executable",
+        Some("parent_process_path"),
+    ),
+    (
+        "ParentCommandLine",
+        "process.parent.command_line",
+        Some("parent_process"),
+    ),
+    ("TargetFilename", "file.path", Some("file_path")),
+    ("CreationUtcTime", "file.created", Some("file_create_time")),
+    ("Protocol", "network.transport", Some("transport")),
+    ("Initiated", "network.direction", Some("direction")),
+    ("SourceIp", "source.ip", Some("src_ip")),
+    ("SourceHostname", "source.domain", Some("src")),
+    ("SourcePort", "source.port", Some("src_port")),
+    ("DestinationIp", "destination.ip", Some("dest_ip")),
+    ("DestinationHostname", "destination.domain", Some("dest")),
+    ("DestinationPort", "destination.port", Some("dest_port")),
+    ("ImageLoaded", "file.path", Some("file_path")),
+    ("Signed", "file.code_signature.signed", None),
+    ("Signature", "file.code_signature.subject_name", None),
+    ("SignatureStatus", "file.code_signature.status", None),
+    ("SourceProcessGuid", "process.entity_id", None),
+    ("SourceProcessGUID", "process.entity_id", None),
+    ("SourceProcessId", "process.pid", None),
+    ("SourceThreadId", "process.thread.id", None),
+    ("SourceImage", "process.executable", None),
+    ("SourceUser", "user.name", None),
+    ("TargetObject", "registry.path", Some("registry_path")),
+    (
+        "Details",
+        "registry.data.strings",
+        Some("registry_value_data"),
+    ),
+    ("PipeName", "file.name", None),
+    ("QueryName", "dns.question.name", Some("query")),
+    ("QueryStatus", "sysmon.dns.status", Some("reply_code")),
+    ("QueryResults", "dns.answers", Some("answer")),
+];
+
+/// Fields an image load describes the DLL with, where other event types
+/// describe the process.
+const IMAGE_LOAD_FIELDS: &[(&str, &str, Option<&str>)] = &[
+    ("FileVersion", "file.pe.file_version", None),
+    ("Description", "file.pe.description", None),
+    ("Product", "file.pe.product", None),
+    ("Company", "file.pe.company", None),
+    ("OriginalFileName", "file.pe.original_file_name", None),
+];
+
+/// Per event type, its ECS `event.category` and CIM data model.
+const EVENTS: &[(&str, Option<&str>, Option<&str>)] = &[
+    ("ProcessCreate", Some("process"), Some("Endpoint.Processes")),
+    ("FileCreateTime", Some("file"), Some("Endpoint.Filesystem")),
+    ("NetworkConnect", Some("network"), Some("Network_Traffic")),
+    (
+        "ProcessTerminate",
+        Some("process"),
+        Some("Endpoint.Processes"),
+    ),
+    ("DriverLoad", Some("driver"), None),
+    ("ImageLoad", Some("library"), None),
+    ("CreateRemoteThread", Some("process"), None),
+    ("RawAccessRead", Some("file"), None),
+    ("ProcessAccess", Some("process"), None),
+    ("FileCreate", Some("file"), Some("Endpoint.Filesystem")),
+    ("RegistryEvent", Some("registry"), Some("Endpoint.Registry")),
+    (
+        "FileCreateStreamHash",
+        Some("file"),
+        Some("Endpoint.Filesystem"),
+    ),
+    ("PipeEvent", Some("file"), None),
+    ("WmiEvent", None, None),
+    ("DnsQuery", Some("network"), Some("Network_Resolution.DNS")),
+    ("FileDelete", Some("file"), Some("Endpoint.Filesystem")),
+    ("ClipboardChange", None, None),
+    ("ProcessTampering", Some("process"), None),
+    (
+        "FileDeleteDetected",
+        Some("file"),
+        Some("Endpoint.Filesystem"),
+    ),
+    (
+        "FileBlockExecutable",
+        Some("file"),
+        Some("Endpoint.Filesystem"),
+    ),
+    (
+        "FileBlockShredding",
+        Some("file"),
+        Some("Endpoint.Filesystem"),
+    ),
+    (
+        "FileExecutableDetected",
+        Some("file"),
+        Some("Endpoint.Filesystem"),
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FieldMapFormat {
+    Json,
+    Yaml,
+}
+
+#[derive(Args)]
+pub struct FieldMapArgs {
+    /// Configuration to map (XML or JSON)
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Document format
+    #[arg(long, value_enum, default_value = "json")]
+    pub format: FieldMapFormat,
+
+    /// Write the mapping here instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldMap {
+    pub schema_version: String,
+    /// The algorithms `Hashes` holds, from `<HashAlgorithms>`.
+    pub hash_algorithms: Vec<String>,
+    pub events: Vec<EventMapping>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventMapping {
+    pub event: String,
+    pub event_ids: Vec<u32>,
+    /// Whether every event is logged or only some.
+    pub posture: Posture,
+    pub ecs_category: Option<String>,
+    pub cim_data_model: Option<String>,
+    pub fields: Vec<FieldMapping>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct FieldMapping {
+    pub sysmon: String,
+    pub ecs: String,
+    pub cim: Option<String>,
+}
+
+pub fn run(args: &FieldMapArgs) -> Result<(), ConversionError> {
+    let config = SysmonConfig::from_xml_str(&validate::load(&args.config)?.0)?;
+    let map = field_map(&config);
+    let text = match args.format {
+        FieldMapFormat::Json => serde_json::to_string_pretty(&map)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+        FieldMapFormat::Yaml => to_yaml(&map),
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+/// The mapping for the event types `config` logs, in schema order.
+pub fn field_map(config: &SysmonConfig) -> FieldMap {
+    let algorithms = hash_algorithms(config);
+    let postures = posture::posture(config, Platform::Windows);
+    let mut events = Vec::new();
+    for event in schema::EVENT_TYPES {
+        let Some(posture) = postures.iter().find(|p| p.event == event.name) else {
+            continue;
+        };
+        let logged = match posture.posture {
+            Posture::Nothing => false,
+            Posture::NotConfigured => schema::LOGGED_BY_DEFAULT.contains(&event.name),
+            _ => true,
+        };
+        if !logged {
+            continue;
+        }
+        let (_, category, model) = EVENTS
+            .iter()
+            .find(|(name, _, _)| *name == event.name)
+            .copied()
+            .unwrap_or((event.name, None, None));
+        let mut fields = Vec::new();
+        for field in event.fields {
+            if matches!(*field, "Hashes" | "Hash") {
+                fields.extend(hash_fields(event.name, field, &algorithms));
+                continue;
+            }
+            let mapping = IMAGE_LOAD_FIELDS
+                .iter()
+                .filter(|_| event.name == "ImageLoad")
+                .chain(FIELDS)
+                .find(|(name, _, _)| name == field);
+            fields.push(match mapping {
+                Some((_, ecs, cim)) => FieldMapping {
+                    sysmon: field.to_string(),
+                    ecs: ecs.to_string(),
+                    cim: cim.map(str::to_string),
+                },
+                None => FieldMapping {
+                    sysmon: field.to_string(),
+                    ecs: format!("winlog.event_data.{}", field),
+                    cim: None,
+                },
+            });
+        }
+        events.push(EventMapping {
+            event: event.name.to_string(),
+            event_ids: event.ids.to_vec(),
+            posture: posture.posture,
+            ecs_category: category.map(str::to_string),
+            cim_data_model: model.map(str::to_string),
+            fields,
+        });
+    }
+    FieldMap {
+        schema_version: config.schema_version.clone(),
+        hash_algorithms: algorithms,
+        events,
+    }
+}
+
+/// The algorithms `<HashAlgorithms>` selects, in schema order; Sysmon uses
+/// SHA1 when the option is absent.
+fn hash_algorithms(config: &SysmonConfig) -> Vec<String> {
+    let Some(option) = config.options.iter().find(|o| o.name == "HashAlgorithms") else {
+        return vec!["SHA1".to_string()];
+    };
+    let selected: Vec<String> = option
+        .value
+        .split(',')
+        .map(|a| a.trim().to_ascii_uppercase())
+        .collect();
+    schema::HASH_ALGORITHMS
+        .iter()
+        .filter(|a| selected.iter().any(|s| s == "*" || s == *a))
+        .map(|a| a.to_string())
+        .collect()
+}
+
+/// One mapping per algorithm for an event type's `Hashes` field.
+fn hash_fields(event: &str, field: &str, algorithms: &[String]) -> Vec<FieldMapping> {
+    let (ecs, cim) = match event {
+        "ProcessCreate" => ("process.hash", "process_hash"),
+        _ => ("file.hash", "file_hash"),
+    };
+    algorithms
+        .iter()
+        .map(|algorithm| FieldMapping {
+            sysmon: field.to_string(),
+            ecs: format!("{}.{}", ecs, algorithm.to_ascii_lowercase()),
+            cim: Some(cim.to_string()),
+        })
+        .collect()
+}
+
+fn to_yaml(map: &FieldMap) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let optional = |text: &Option<String>| text.as_deref().map_or("null".to_string(), quote);
+    let mut out = String::new();
+    let _ = writeln!(out, "schema_version: {}", quote(&map.schema_version));
+    let algorithms: Vec<String> = map.hash_algorithms.iter().map(|a| quote(a)).collect();
+    let _ = writeln!(out, "hash_algorithms: [{}]", algorithms.join(", "));
+    let _ = writeln!(out, "events:");
+    for event in &map.events {
+        let ids: Vec<String> = event.event_ids.iter().map(u32::to_string).collect();
+        let _ = writeln!(out, "  - event: {}", quote(&event.event));
+        let _ = writeln!(out, "    event_ids: [{}]", ids.join(", "));
+        let _ = writeln!(out, "    posture: {}", event.posture.as_str());
+        let _ = writeln!(out, "    ecs_category: {}", optional(&event.ecs_category));
+        let _ = writeln!(
+            out,
+            "    cim_data_model: {}",
+            optional(&event.cim_data_model)
+        );
+        let _ = writeln!(out, "    fields:");
+        for field in &event.fields {
+            let _ = writeln!(
+                out,
+                "      - {{sysmon: {}, ecs: {}, cim: {}}}",
+                quote(&field.sysmon),
+                quote(&field.ecs),
+                optional(&field.cim)
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_map_lists_logged_events_and_hashes() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><HashAlgorithms>md5,IMPHASH</HashAlgorithms><EventFiltering>
+<RuleGroup name="" groupRelation="or">
+<ProcessCreate onmatch="exclude"/>
+<ImageLoad onmatch="include"/>
+<DnsQuery onmatch="include"><QueryName condition="end with">.onion</QueryName></DnsQuery>
+</RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let map = field_map(&config);
+        assert_eq!(map.hash_algorithms, ["MD5", "IMPHASH"]);
+        let events: Vec<(&str, Posture)> = map
+            .events
+            .iter()
+            .map(|e| (e.event.as_str(), e.posture))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("ProcessCreate", Posture::Everything),
+                ("FileCreateTime", Posture::NotConfigured),
+                ("ProcessTerminate", Posture::NotConfigured),
+                ("DriverLoad", Posture::NotConfigured),
+                ("DnsQuery", Posture::IncludeList),
+            ]
+        );
+
+        let mapping = |sysmon: &str, ecs: &str, cim: Option<&str>| FieldMapping {
+            sysmon: sysmon.to_string(),
+            ecs: ecs.to_string(),
+            cim: cim.map(str::to_string),
+        };
+        let process = &map.events[0].fields;
+        assert!(process.contains(&mapping(
+            "CommandLine",
+            "process.command_line",
+            Some("process")
+        )));
+        assert!(process.contains(&mapping(
+            "Hashes",
+            "process.hash.imphash",
+            Some("process_hash")
+        )));
+        assert!(process.contains(&mapping("LogonGuid", "winlog.event_data.LogonGuid", None)));
+        assert_eq!(
+            map.events[4].cim_data_model.as_deref(),
+            Some("Network_Resolution.DNS")
+        );
+
+        let yaml = to_yaml(&map);
+        assert!(yaml
+            .contains("  - event: \"DnsQuery\"\n    event_ids: [22]\n    posture: include_list\n"));
+        assert!(yaml.contains(
+            "      - {sysmon: \"QueryName\", ecs: \"dns.question.name\", cim: \"query\"}\n"
+        ));
+    }
+}
@@ -0,0 +1,49 @@
+//! `--files-from`: take the files to process from a list instead of walking
+//! a directory.
+//!
+//! The list has one path per line, as printed by `git diff --name-only` or
+//! `find`; blank lines are ignored and `-` reads it from stdin. Entries that
+//! no longer exist (files deleted in the diff) or that are not
+//! configurations are dropped by the callers, so a pre-commit hook can pass
+//! the raw list along.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+
+pub fn read(source: &Path) -> Result<Vec<PathBuf>, ConversionError> {
+    let text = if source == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| ConversionError::io_error(source, e))?;
+        text
+    } else {
+        std::fs::read_to_string(source).map_err(|e| ConversionError::io_error(source, e))?
+    };
+    Ok(parse(&text))
+}
+
+fn parse(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_skips_blank_lines() {
+        assert_eq!(
+            parse("configs/a.xml\r\n\n  configs/b.json  \n"),
+            vec![
+                PathBuf::from("configs/a.xml"),
+                PathBuf::from("configs/b.json")
+            ]
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! `fix`: bring rule names in line with one convention.
+//!
+//! Names that cite a single ATT&CK technique, as a bare ID or as
+//! `technique_id=`/`technique_name=` pairs, are rewritten to the
+//! `--convention` template, by default the sysmon-modular form
+//! `technique_id={id},technique_name={name}`. The technique name comes
+//! from the ATT&CK data (see [`crate::attack`]), which fills in
+//! missing names and corrects outdated ones; for a technique the data does
+//! not list, the name the rule already has is kept. Names with anything
+//! else in them, or with several techniques, are left as they are.
+//!
+//! Technique IDs that ATT&CK has revoked or deprecated are reported with
+//! the technique that replaced them, and IDs the data does not know at
+//! all are reported too, as are sub-techniques the bundled table does not
+//! list; `fix` does not change which technique a rule cites.
+
+use crate::attack::{Database, Lookup};
+use crate::document;
+use clap::Args;
+use log::{info, warn};
+use std::path::PathBuf;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+#[derive(Args)]
+pub struct FixArgs {
+    /// Configuration to fix (XML or JSON); rewritten in place unless --output is given
+    pub config: PathBuf,
+
+    /// Name template, with {id} and {name} for the technique's ID and name
+    #[arg(long, default_value = "technique_id={id},technique_name={name}")]
+    pub convention: String,
+
+    /// Print what would change without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Write the fixed config here instead of over the input
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// A rule name `fix` rewrote.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rename {
+    /// `RuleGroup[1]/ProcessCreate/Image`.
+    pub location: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// A technique ID the ATT&CK data does not list as current.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stale {
+    pub location: String,
+    pub id: String,
+    /// Whether ATT&CK revoked or deprecated it, rather than never having it.
+    pub retired: bool,
+    /// The ID and name of the technique that replaced it.
+    pub replaced_by: Option<(String, String)>,
+    /// A sub-technique the bundled table does not list, which ATT&CK may
+    /// still have.
+    pub unlisted: bool,
+}
+
+impl std::fmt::Display for Stale {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.replaced_by, self.retired) {
+            (Some((new, name)), _) => write!(
+                f,
+                "{}: {} is no longer in ATT&CK; it was replaced by {} ({})",
+                self.location, self.id, new, name
+            ),
+            (None, true) => write!(f, "{}: {} is deprecated in ATT&CK", self.location, self.id),
+            (None, false) if self.unlisted => write!(
+                f,
+                "{}: {} is not in the bundled ATT&CK data; run update-attack-data to check it",
+                self.location, self.id
+            ),
+            (None, false) => write!(
+                f,
+                "{}: {} is not a technique in the ATT&CK data",
+                self.location, self.id
+            ),
+        }
+    }
+}
+
+pub fn run(args: &FixArgs) -> Result<(), ConversionError> {
+    if !args.convention.contains("{id}") {
+        return Err(ConversionError::ValidationError(
+            "--convention must contain {id}".to_string(),
+        ));
+    }
+    let mut root = document::load(&args.config)?;
+    let (renames, stale) = fix(&mut root, &args.convention, &Database::load());
+
+    for entry in &stale {
+        warn!("{}", entry);
+    }
+    if args.dry_run {
+        for rename in &renames {
+            println!("{}: {:?} -> {:?}", rename.location, rename.old, rename.new);
+        }
+        return Ok(());
+    }
+    if renames.is_empty() {
+        info!(
+            "Rule names in {} already follow the convention",
+            args.config.display()
+        );
+        return Ok(());
+    }
+    let output = args.output.as_ref().unwrap_or(&args.config);
+    document::save(output, &root)?;
+    info!("Renamed {} rule(s) in {}", renames.len(), output.display());
+    Ok(())
+}
+
+/// Rewrites the rule names under `root`'s `<EventFiltering>` to
+/// `convention`, returning the renames and the stale technique IDs.
+pub fn fix(root: &mut Element, convention: &str, attack: &Database) -> (Vec<Rename>, Vec<Stale>) {
+    let mut renames = Vec::new();
+    let mut stale = Vec::new();
+    let Some(filtering) = root.get_mut_child("EventFiltering") else {
+        return (renames, stale);
+    };
+    let mut groups = 0;
+    for node in &mut filtering.children {
+        let XMLNode::Element(element) = node else {
+            continue;
+        };
+        let location = if element.name == "RuleGroup" {
+            groups += 1;
+            format!("RuleGroup[{}]", groups)
+        } else {
+            String::new()
+        };
+        let mut fixer = Fixer {
+            convention,
+            attack,
+            renames: &mut renames,
+            stale: &mut stale,
+        };
+        fixer.walk(element, &location);
+    }
+    (renames, stale)
+}
+
+/// The rename settings, and what was found so far.
+struct Fixer<'a> {
+    convention: &'a str,
+    attack: &'a Database,
+    renames: &'a mut Vec<Rename>,
+    stale: &'a mut Vec<Stale>,
+}
+
+impl Fixer<'_> {
+    fn walk(&mut self, element: &mut Element, location: &str) {
+        let location = match (location.is_empty(), element.name == "RuleGroup") {
+            (_, true) => location.to_string(),
+            (true, false) => element.name.clone(),
+            (false, false) => format!("{}/{}", location, element.name),
+        };
+        let name = element.attributes.get("name").cloned();
+        if let Some(name) = name.filter(|_| element.name != "RuleGroup") {
+            if let Some(new) = self.rename(&name, &location) {
+                element.attributes.insert("name".to_string(), new.clone());
+                self.renames.push(Rename {
+                    location: location.clone(),
+                    old: name,
+                    new,
+                });
+            }
+        }
+        for node in &mut element.children {
+            if let XMLNode::Element(child) = node {
+                self.walk(child, &location);
+            }
+        }
+    }
+
+    /// `name` following the convention, if that differs from `name`. A
+    /// stale technique ID is added to `stale`.
+    fn rename(&mut self, name: &str, location: &str) -> Option<String> {
+        let cited = parse(name)?;
+        let lookup = self.attack.lookup(&cited.id);
+        let stale = |retired, replaced_by, unlisted| Stale {
+            location: location.to_string(),
+            id: cited.id.clone(),
+            retired,
+            replaced_by,
+            unlisted,
+        };
+        match lookup {
+            Lookup::Retired(new) => {
+                let replaced_by = new.map(|new| {
+                    let name = self.attack.name(new).unwrap_or_default();
+                    (new.to_string(), name.to_string())
+                });
+                self.stale.push(stale(true, replaced_by, false));
+            }
+            Lookup::UnlistedSubTechnique => self.stale.push(stale(false, None, true)),
+            Lookup::Unknown => self.stale.push(stale(false, None, false)),
+            Lookup::Current(_) => {}
+        }
+
+        let technique = match lookup {
+            Lookup::Current(technique) => Some(technique.name.clone()),
+            _ => cited.name,
+        };
+        let convention = self.convention;
+        let new = match technique {
+            Some(technique) => convention
+                .replace("{id}", &cited.id)
+                .replace("{name}", &technique),
+            None if !convention.contains("{name}") => convention.replace("{id}", &cited.id),
+            None => return None,
+        };
+        (new != name).then_some(new)
+    }
+}
+
+/// The technique a rule name cites.
+#[derive(Debug, PartialEq, Eq)]
+struct Cited {
+    id: String,
+    name: Option<String>,
+}
+
+/// The one technique `name` cites, if it holds nothing but that technique's
+/// ID and name: `T1086`, `technique_id=T1086` or
+/// `technique_id=T1086,technique_name=PowerShell`.
+fn parse(name: &str) -> Option<Cited> {
+    let mut id = None;
+    let mut technique = None;
+    for part in name.split(',').map(str::trim) {
+        let value = if let Some(value) = part.strip_prefix("technique_id=") {
+            value.trim()
+        } else if let Some(value) = part.strip_prefix("technique_name=") {
+            technique = Some(value.trim().to_string()).filter(|n| !n.is_empty());
+            continue;
+        } else {
+            part
+        };
+        if id.is_some() || !is_technique_id(value) {
+            return None;
+        }
+        id = Some(value.to_string());
+    }
+    Some(Cited {
+        id: id?,
+        name: technique,
+    })
+}
+
+/// `T1055` or `T1059.001`.
+fn is_technique_id(text: &str) -> bool {
+    let Some(digits) = text.strip_prefix('T') else {
+        return false;
+    };
+    let (technique, sub) = match digits.split_once('.') {
+        Some((technique, sub)) => (technique, Some(sub)),
+        None => (digits, None),
+    };
+    let numeric = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+    numeric(technique, 4) && sub.is_none_or(|sub| numeric(sub, 3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fix_normalizes_names_and_reports_stale_ids() {
+        let mut root = Element::parse(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup name="T1059" groupRelation="or"><ProcessCreate onmatch="include">
+<Image name="T1059.001" condition="end with">\powershell.exe</Image>
+<Image name="technique_id=T1218.011,technique_name=Rundll32" condition="end with">\rundll32.exe</Image>
+<Image name="technique_id=T1086,technique_name=PowerShell" condition="end with">\pwsh.exe</Image>
+<Image name="technique_id=T1059.999,technique_name=Local Name" condition="end with">\x.exe</Image>
+<Image name="T1036 and T1055" condition="end with">\y.exe</Image>
+<Rule name="technique_id=T9999" groupRelation="and"><Image condition="is">z.exe</Image></Rule>
+</ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let attack = Database::bundled();
+        let (renames, stale) = fix(
+            &mut root,
+            "technique_id={id},technique_name={name}",
+            &attack,
+        );
+        assert_eq!(
+            renames,
+            [Rename {
+                location: "RuleGroup[1]/ProcessCreate/Image".to_string(),
+                old: "T1059.001".to_string(),
+                new: "technique_id=T1059.001,technique_name=PowerShell".to_string(),
+            }]
+        );
+        let stale: Vec<String> = stale.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            stale,
+            [
+                "RuleGroup[1]/ProcessCreate/Image: T1086 is no longer in ATT&CK; it was replaced by T1059.001 (PowerShell)",
+                "RuleGroup[1]/ProcessCreate/Image: T1059.999 is not in the bundled ATT&CK data; run update-attack-data to check it",
+                "RuleGroup[1]/ProcessCreate/Rule: T9999 is not a technique in the ATT&CK data",
+            ]
+        );
+        let group = root
+            .get_child("EventFiltering")
+            .unwrap()
+            .get_child("RuleGroup")
+            .unwrap();
+        assert_eq!(
+            group.attributes.get("name").map(String::as_str),
+            Some("T1059")
+        );
+
+        let (renames, _) = fix(&mut root, "{id} {name}", &attack);
+        let new: Vec<&str> = renames.iter().map(|r| r.new.as_str()).collect();
+        assert_eq!(
+            new,
+            [
+                "T1059.001 PowerShell",
+                "T1218.011 Rundll32",
+                "T1086 PowerShell",
+                "T1059.999 Local Name",
+            ]
+        );
+    }
+}
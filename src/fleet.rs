@@ -0,0 +1,427 @@
+//! `fleet-build`: per-version configuration variants for mixed Sysmon fleets.
+//!
+//! Hosts are grouped by the newest schema their Sysmon release accepts. Each
+//! group gets a copy of the configuration with `schemaversion` lowered and any
+//! event types, fields and conditions the release does not understand
+//! removed, along with rules and event filters left empty. Groups whose
+//! downgraded configurations would be identical share a single variant, so
+//! the output is the smallest set of files that gives every host as much
+//! visibility as its agent supports.
+
+use crate::document::{self, has_child_elements};
+use crate::io_guard;
+use clap::Args;
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::Condition;
+use sysmon_cli::schema::{self, EventType, Version};
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+const MAPPING_FILE: &str = "fleet-mapping.csv";
+
+#[derive(Args)]
+pub struct FleetBuildArgs {
+    /// Sysmon configuration to derive variants from
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// CSV inventory with `hostname,sysmon_version` rows
+    #[arg(long)]
+    pub inventory: PathBuf,
+
+    /// Directory that receives the variants and the host mapping file
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct Host {
+    pub name: String,
+    pub sysmon: Version,
+}
+
+#[derive(Debug)]
+pub struct Variant {
+    pub schema: Version,
+    pub dropped: BTreeSet<String>,
+    pub hosts: Vec<Host>,
+}
+
+pub fn run(args: &FleetBuildArgs) -> Result<(), ConversionError> {
+    let content = std::fs::read_to_string(&args.inventory)
+        .map_err(|e| ConversionError::io_error(&args.inventory, e))?;
+    let hosts = parse_inventory(&content)?;
+    if hosts.is_empty() {
+        return Err(ConversionError::InvalidFile(format!(
+            "Inventory contains no hosts: {}",
+            args.inventory.display()
+        )));
+    }
+
+    let root = document::load(&args.config)?;
+    let variants = plan_variants(&root, &hosts)?;
+
+    let read_only = io_guard::is_read_only();
+    if !read_only {
+        io_guard::create_dir_all(&args.output)?;
+    }
+
+    let stem = args
+        .config
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("sysmonconfig");
+    let mut mapping = String::from("hostname,sysmon_version,schema_version,config\n");
+
+    for variant in &variants {
+        let file_name = format!("{}-schema-{}.xml", stem, variant.schema);
+        let dropped = variant
+            .dropped
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if read_only {
+            println!(
+                "{}: schema {}, {} host(s), removes [{}]",
+                file_name,
+                variant.schema,
+                variant.hosts.len(),
+                dropped
+            );
+        } else {
+            let path = args.output.join(&file_name);
+            let (downgraded, _) = downgrade(&root, variant.schema);
+            io_guard::write(&path, document::to_xml_string(&downgraded)?)?;
+
+            if dropped.is_empty() {
+                info!(
+                    "Wrote {} for {} host(s)",
+                    path.display(),
+                    variant.hosts.len()
+                );
+            } else {
+                warn!(
+                    "Wrote {} for {} host(s); removed unsupported events and conditions: {}",
+                    path.display(),
+                    variant.hosts.len(),
+                    dropped
+                );
+            }
+        }
+
+        for host in &variant.hosts {
+            let _ = writeln!(
+                mapping,
+                "{},{},{},{}",
+                host.name, host.sysmon, variant.schema, file_name
+            );
+        }
+    }
+
+    if read_only {
+        print!("{}", mapping);
+        return Ok(());
+    }
+
+    let mapping_path = args.output.join(MAPPING_FILE);
+    io_guard::write(&mapping_path, mapping)?;
+    info!(
+        "Built {} variant(s) for {} host(s); mapping written to {}",
+        variants.len(),
+        hosts.len(),
+        mapping_path.display()
+    );
+
+    Ok(())
+}
+
+/// Parses `hostname,version` rows. Blank lines, `#` comments, a header row
+/// and any columns after the second are ignored.
+pub fn parse_inventory(content: &str) -> Result<Vec<Host>, ConversionError> {
+    let mut hosts = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut columns = line.split(',').map(|c| c.trim().trim_matches('"'));
+        let name = columns.next().unwrap_or_default();
+        let version = columns.next().unwrap_or_default();
+
+        match version.parse::<Version>() {
+            Ok(sysmon) if !name.is_empty() => hosts.push(Host {
+                name: name.to_string(),
+                sysmon,
+            }),
+            _ if hosts.is_empty() && name.to_ascii_lowercase().contains("host") => continue,
+            _ => {
+                return Err(ConversionError::InvalidFile(format!(
+                    "Inventory line {}: expected `hostname,sysmon_version`, got `{}`",
+                    index + 1,
+                    line
+                )))
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+/// Groups hosts into the minimal set of variants for `root`.
+pub fn plan_variants(root: &Element, hosts: &[Host]) -> Result<Vec<Variant>, ConversionError> {
+    let config_schema = root
+        .attributes
+        .get("schemaversion")
+        .ok_or_else(|| {
+            ConversionError::ValidationError(
+                "Configuration has no schemaversion attribute".to_string(),
+            )
+        })?
+        .parse::<Version>()
+        .map_err(ConversionError::ValidationError)?;
+
+    let mut by_schema: BTreeMap<Version, Vec<Host>> = BTreeMap::new();
+    for host in hosts {
+        let supported = schema::max_schema_for(host.sysmon).ok_or_else(|| {
+            ConversionError::ValidationError(format!(
+                "Host {} runs Sysmon {}, which is older than any supported release",
+                host.name, host.sysmon
+            ))
+        })?;
+        by_schema
+            .entry(supported.min(config_schema))
+            .or_default()
+            .push(host.clone());
+    }
+
+    // Lower schemas drop a superset of what higher ones drop, so equal drop
+    // sets are always adjacent and can share the lowest schema among them.
+    let mut variants: Vec<Variant> = Vec::new();
+    for (target, group) in by_schema {
+        let dropped = unsupported_events(root, target);
+        match variants.last_mut() {
+            Some(last) if last.dropped == dropped => last.hosts.extend(group),
+            _ => variants.push(Variant {
+                schema: target,
+                dropped,
+                hosts: group,
+            }),
+        }
+    }
+    Ok(variants)
+}
+
+fn unsupported_events(root: &Element, target: Version) -> BTreeSet<String> {
+    let (_, dropped) = downgrade(root, target);
+    dropped
+}
+
+/// Returns a copy of `root` targeting schema `target`, together with the event
+/// types, fields and conditions that had to be removed.
+pub fn downgrade(root: &Element, target: Version) -> (Element, BTreeSet<String>) {
+    let mut root = root.clone();
+    let mut dropped = BTreeSet::new();
+    root.attributes
+        .insert("schemaversion".to_string(), target.to_string());
+
+    if let Some(filtering) = root.get_mut_child("EventFiltering") {
+        prune_events(&mut filtering.children, target, &mut dropped);
+        for node in filtering.children.iter_mut() {
+            if let XMLNode::Element(group) = node {
+                if group.name == "RuleGroup" {
+                    prune_events(&mut group.children, target, &mut dropped);
+                }
+            }
+        }
+        filtering.children.retain(|node| match node {
+            XMLNode::Element(group) if group.name == "RuleGroup" => has_child_elements(group),
+            _ => true,
+        });
+    }
+
+    (root, dropped)
+}
+
+fn prune_events(children: &mut Vec<XMLNode>, target: Version, dropped: &mut BTreeSet<String>) {
+    children.retain_mut(|node| match node {
+        XMLNode::Element(element) => match schema::event_type(&element.name) {
+            Some(event) if event.min_schema > target => {
+                dropped.insert(element.name.clone());
+                false
+            }
+            // A filter that was empty to begin with still means something.
+            Some(event) if has_child_elements(element) => {
+                prune_conditions(&mut element.children, event, target, dropped);
+                has_child_elements(element)
+            }
+            _ => true,
+        },
+        _ => true,
+    });
+}
+
+/// Removes field conditions that need a schema newer than `target`, and any
+/// `<Rule>` left without conditions.
+fn prune_conditions(
+    children: &mut Vec<XMLNode>,
+    event: &EventType,
+    target: Version,
+    dropped: &mut BTreeSet<String>,
+) {
+    children.retain_mut(|node| {
+        let XMLNode::Element(element) = node else {
+            return true;
+        };
+        if element.name == "Rule" {
+            prune_conditions(&mut element.children, event, target, dropped);
+            return has_child_elements(element);
+        }
+        if schema::field_min_schema(event, &element.name) > target {
+            dropped.insert(format!("{}/{}", event.name, element.name));
+            return false;
+        }
+        let condition = match element.attributes.get("condition") {
+            Some(condition) => condition.parse().ok(),
+            None => Some(Condition::Is),
+        };
+        match condition {
+            Some(condition) if schema::condition_min_schema(condition) > target => {
+                dropped.insert(format!(
+                    "{}/{} condition \"{}\"",
+                    event.name, element.name, condition
+                ));
+                false
+            }
+            _ => true,
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+  <EventFiltering>
+    <RuleGroup name="" groupRelation="or">
+      <ProcessCreate onmatch="include"><Image condition="end with">cmd.exe</Image></ProcessCreate>
+    </RuleGroup>
+    <RuleGroup name="" groupRelation="or">
+      <ProcessTampering onmatch="include"><Type condition="is">Image is replaced</Type></ProcessTampering>
+    </RuleGroup>
+    <RuleGroup name="" groupRelation="or">
+      <FileBlockExecutable onmatch="include"><TargetFilename condition="end with">.exe</TargetFilename></FileBlockExecutable>
+    </RuleGroup>
+  </EventFiltering>
+</Sysmon>"#;
+
+    fn host(name: &str, version: &str) -> Host {
+        Host {
+            name: name.to_string(),
+            sysmon: version.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_inventory_skips_header_and_comments() {
+        let hosts =
+            parse_inventory("hostname,version\n# lab\nws01,13.34\n\nws02, 15.15\n").unwrap();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[1].sysmon, Version::new(15, 15));
+        assert!(parse_inventory("ws01\n").is_err());
+    }
+
+    #[test]
+    fn test_plan_variants_merges_equivalent_schemas() {
+        let root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let hosts = vec![
+            host("old", "13.0"),
+            host("mid", "13.34"),
+            host("new", "15.15"),
+        ];
+
+        let variants = plan_variants(&root, &hosts).unwrap();
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].schema, Version::new(4, 50));
+        assert_eq!(variants[0].hosts.len(), 2);
+        assert!(variants[0].dropped.contains("FileBlockExecutable"));
+        assert_eq!(variants[1].schema, Version::new(4, 90));
+        assert!(variants[1].dropped.is_empty());
+    }
+
+    #[test]
+    fn test_downgrade_removes_emptied_rule_groups() {
+        let root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let (downgraded, dropped) = downgrade(&root, Version::new(4, 40));
+        assert_eq!(dropped.len(), 2);
+        assert_eq!(
+            downgraded
+                .attributes
+                .get("schemaversion")
+                .map(String::as_str),
+            Some("4.40")
+        );
+        let filtering = downgraded.get_child("EventFiltering").unwrap();
+        assert_eq!(document::child_elements(filtering).count(), 1);
+    }
+
+    #[test]
+    fn test_downgrade_drops_newer_conditions_and_fields() {
+        let root = Element::parse(
+            r#"<Sysmon schemaversion="4.90">
+  <EventFiltering>
+    <RuleGroup name="" groupRelation="or">
+      <ProcessCreate onmatch="exclude">
+        <Image condition="not end with">.exe</Image>
+        <Rule groupRelation="and">
+          <CommandLine condition="excludes any">-enc;-e </CommandLine>
+        </Rule>
+        <ParentImage>C:\Windows\explorer.exe</ParentImage>
+      </ProcessCreate>
+      <ImageLoad onmatch="include">
+        <OriginalFileName condition="is">mimikatz.exe</OriginalFileName>
+      </ImageLoad>
+      <NetworkConnect onmatch="include"/>
+    </RuleGroup>
+  </EventFiltering>
+</Sysmon>"#
+                .as_bytes(),
+        )
+        .unwrap();
+
+        let (downgraded, dropped) = downgrade(&root, Version::new(4, 22));
+        assert_eq!(
+            dropped.into_iter().collect::<Vec<_>>(),
+            ["ProcessCreate/Image condition \"not end with\""]
+        );
+        let group = downgraded
+            .get_child("EventFiltering")
+            .and_then(|f| f.get_child("RuleGroup"))
+            .unwrap();
+        let process = group.get_child("ProcessCreate").unwrap();
+        assert_eq!(
+            document::child_elements(process)
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Rule", "ParentImage"]
+        );
+
+        let (downgraded, dropped) = downgrade(&root, Version::new(4, 0));
+        assert!(dropped.contains("ProcessCreate/CommandLine condition \"excludes any\""));
+        assert!(dropped.contains("ImageLoad/OriginalFileName"));
+        let group = downgraded
+            .get_child("EventFiltering")
+            .and_then(|f| f.get_child("RuleGroup"))
+            .unwrap();
+        let process = group.get_child("ProcessCreate").unwrap();
+        assert_eq!(document::child_elements(process).count(), 1);
+        // Emptied by the downgrade, unlike the filter that started empty.
+        assert!(group.get_child("ImageLoad").is_none());
+        assert!(group.get_child("NetworkConnect").is_some());
+    }
+}
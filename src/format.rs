@@ -0,0 +1,92 @@
+//! Deciding whether an input is XML or JSON.
+//!
+//! File names are unreliable (`config.txt`, `sysmon` with no extension), so
+//! the first meaningful byte of the content decides. The extension is only
+//! consulted when the content is inconclusive, and `--from` overrides both.
+
+use clap::ValueEnum;
+use std::path::Path;
+use sysmon_json::error::ConversionError;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Xml,
+    Json,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Xml => "xml",
+            Self::Json => "json",
+        }
+    }
+
+    /// The format a conversion from `self` produces.
+    pub fn target(self) -> Self {
+        match self {
+            Self::Xml => Self::Json,
+            Self::Json => Self::Xml,
+        }
+    }
+
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("xml") => Some(Self::Xml),
+            Some("json") => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Looks at the first non-whitespace byte after an optional UTF-8 BOM.
+pub fn sniff(content: &[u8]) -> Option<Format> {
+    let content = content.strip_prefix(UTF8_BOM).unwrap_or(content);
+    match content.iter().find(|b| !b.is_ascii_whitespace())? {
+        b'<' => Some(Format::Xml),
+        b'{' | b'[' => Some(Format::Json),
+        _ => None,
+    }
+}
+
+/// Content first, then extension.
+pub fn detect(path: &Path, content: &[u8]) -> Result<Format, ConversionError> {
+    sniff(content)
+        .or_else(|| Format::from_extension(path))
+        .ok_or_else(|| {
+            ConversionError::InvalidFile(format!(
+                "{}: cannot tell whether this is XML or JSON; pass --from xml or --from json",
+                path.display()
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_ignores_bom_and_whitespace() {
+        assert_eq!(sniff(b"\xEF\xBB\xBF\r\n  <Sysmon/>"), Some(Format::Xml));
+        assert_eq!(sniff(b"\n{\"Sysmon\": {}}"), Some(Format::Json));
+        assert_eq!(sniff(b"[]"), Some(Format::Json));
+        assert_eq!(sniff(b"   "), None);
+    }
+
+    #[test]
+    fn test_detect_prefers_content_over_extension() {
+        assert_eq!(
+            detect(Path::new("config.json"), b"<Sysmon/>").unwrap(),
+            Format::Xml
+        );
+        assert_eq!(detect(Path::new("config.xml"), b"").unwrap(), Format::Xml);
+        assert!(detect(Path::new("config.txt"), b"").is_err());
+    }
+}
@@ -0,0 +1,493 @@
+//! `from-sigma`: a starter config that logs what a set of Sigma rules
+//! looks at.
+//!
+//! Each Sigma rule for a Sysmon log source (see [`crate::sigma`]) turns on
+//! the event type it needs, with include rules translated from its
+//! selections: `Image|endswith: '\rundll32.exe'` becomes
+//! `<Image condition="end with">\rundll32.exe</Image>`, a list of values
+//! becomes `is any` or `contains any`, and the tests of one selection are
+//! ANDed in a `<Rule>`. Rules are named after the ATT&CK technique the
+//! Sigma rule is tagged with, or after its title.
+//!
+//! Include rules can only widen what is logged, so a test that does not
+//! translate (a `re` modifier, a wildcard inside a value, a field Sysmon
+//! does not record) is left out of its rule rather than guessed at. A
+//! selection with nothing left to test, a keyword search, or a rule whose
+//! selections are all filters makes the event type log everything, since
+//! no include rule would be sure to catch what the rule looks for.
+//! Selections named `filter...` are negated in Sigma rules and are not
+//! turned into excludes; the output is a starting point to tune, not a
+//! finished config.
+
+use crate::attack::{Database, Lookup};
+use crate::document;
+use crate::sigma::{self, FieldMatch, SigmaRule, Test};
+use clap::Args;
+use log::{info, warn};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use sysmon_cli::model::{
+    Condition, ConfigOption, EventFilter, FieldCondition, Filter, GroupRelation, OnMatch, Rule,
+    RuleGroup, SysmonConfig,
+};
+use sysmon_cli::schema::{self, EventType, Version};
+use sysmon_json::error::ConversionError;
+
+#[derive(Args)]
+pub struct FromSigmaArgs {
+    /// Sigma rule file, or a directory searched recursively for .yml and .yaml rules
+    pub rules: PathBuf,
+
+    /// Schema version of the generated config [default: newest known]
+    #[arg(long)]
+    pub schema_version: Option<Version>,
+
+    /// Write the config here (JSON if it ends in .json, XML otherwise) instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// What the rules need of one event type.
+#[derive(Default)]
+struct Need {
+    /// Some rule cannot be served by include rules.
+    everything: bool,
+    filters: Vec<Filter>,
+    rules: usize,
+}
+
+pub fn run(args: &FromSigmaArgs) -> Result<(), ConversionError> {
+    let schema = args
+        .schema_version
+        .unwrap_or_else(|| schema::RELEASES[schema::RELEASES.len() - 1].1);
+    let mut rules = Vec::new();
+    for file in sigma::files(&args.rules) {
+        match sigma::load(&file) {
+            Ok(loaded) => rules.extend(loaded),
+            Err(e) => warn!("Skipping {}", e),
+        }
+    }
+    let config = generate(&rules, schema, &Database::load());
+
+    let events: Vec<&str> = config.events().map(|e| e.event.as_str()).collect();
+    info!(
+        "Enabled {} event type(s) for {} Sigma rule(s): {}",
+        events.len(),
+        rules.len(),
+        events.join(", ")
+    );
+    let root = config.to_element();
+    match &args.output {
+        Some(path) => document::save(path, &root),
+        None => {
+            print!("{}", document::to_xml_string(&root)?);
+            Ok(())
+        }
+    }
+}
+
+/// A config enabling the event types `rules` need, each in its own
+/// `or` rule group, in schema order.
+pub fn generate(rules: &[SigmaRule], schema: Version, attack: &Database) -> SysmonConfig {
+    let mut needs: Vec<Need> = schema::EVENT_TYPES
+        .iter()
+        .map(|_| Need::default())
+        .collect();
+    let mut algorithms = BTreeSet::new();
+    let mut other = 0;
+    for rule in rules {
+        let Some((index, event)) = rule.event_type().and_then(|name| {
+            schema::EVENT_TYPES
+                .iter()
+                .enumerate()
+                .find(|(_, e)| e.name == name)
+        }) else {
+            other += 1;
+            continue;
+        };
+        if event.min_schema > schema {
+            warn!(
+                "{:?} needs {}, which schema {} does not have",
+                rule.title, event.name, schema
+            );
+            continue;
+        }
+        let need = &mut needs[index];
+        need.rules += 1;
+        let name = rule_name(rule, attack);
+        let mut selections = rule.positive_selections().peekable();
+        need.everything |= selections.peek().is_none();
+        for selection in selections {
+            for alternative in &selection.alternatives {
+                algorithms.extend(alternative.iter().filter_map(hash_algorithm).flatten());
+                match translate(alternative, event, schema, &name) {
+                    Some(filters) => need.filters.extend(filters),
+                    None => need.everything = true,
+                }
+            }
+        }
+    }
+    if other > 0 {
+        info!("{} rule(s) are not for Sysmon event types", other);
+    }
+
+    let mut config = SysmonConfig {
+        schema_version: schema.to_string(),
+        options: Vec::new(),
+        rule_groups: Vec::new(),
+    };
+    if !algorithms.is_empty() {
+        // Sysmon hashes with SHA1 alone unless told otherwise.
+        let value = match algorithms.contains("*") {
+            true => "*".to_string(),
+            false => schema::HASH_ALGORITHMS
+                .iter()
+                .filter(|a| algorithms.contains(**a))
+                .copied()
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        config.options.push(ConfigOption {
+            name: "HashAlgorithms".to_string(),
+            value,
+        });
+    }
+    for (event, need) in schema::EVENT_TYPES.iter().zip(needs) {
+        if need.rules == 0 {
+            continue;
+        }
+        // An empty exclude filter logs every event of the type.
+        let (onmatch, filters) = match need.everything {
+            true => (OnMatch::Exclude, Vec::new()),
+            false => (OnMatch::Include, dedupe(need.filters)),
+        };
+        config.rule_groups.push(RuleGroup {
+            name: Some(String::new()),
+            group_relation: Some(GroupRelation::Or),
+            events: vec![EventFilter {
+                event: event.name.to_string(),
+                onmatch,
+                filters,
+            }],
+        });
+    }
+    config
+}
+
+/// `technique_id=T1218.011,technique_name=Rundll32` for the first ATT&CK
+/// technique the rule is tagged with, or the rule's title.
+fn rule_name(rule: &SigmaRule, attack: &Database) -> String {
+    let technique = rule.tags.iter().find_map(|tag| {
+        let id = tag.strip_prefix("attack.t")?;
+        id.starts_with(|c: char| c.is_ascii_digit())
+            .then(|| format!("T{}", id))
+    });
+    match technique {
+        Some(id) => match attack.lookup(&id) {
+            Lookup::Current(technique) => {
+                format!("technique_id={},technique_name={}", id, technique.name)
+            }
+            _ => format!("technique_id={}", id),
+        },
+        None => rule.title.clone(),
+    }
+}
+
+/// The include filters for one selection alternative; `None` when none
+/// of its tests translate, so only logging everything is sure to catch it.
+fn translate(
+    alternative: &[FieldMatch],
+    event: &EventType,
+    schema: Version,
+    name: &str,
+) -> Option<Vec<Filter>> {
+    let mut fields = Vec::new();
+    for m in alternative {
+        // A keyword search can match any field.
+        let field = m.field.as_deref()?;
+        let Some((field, tests)) = field_tests(field, m, event) else {
+            continue;
+        };
+        let condition = |kind, value: String| FieldCondition {
+            field: field.to_string(),
+            condition: condition(kind),
+            value,
+            name: None,
+        };
+        if m.has_modifier("all") || tests.len() == 1 {
+            fields.extend(
+                tests
+                    .into_iter()
+                    .map(|(kind, value)| condition(kind, value)),
+            );
+            continue;
+        }
+        let kind = tests[0].0;
+        let any = match kind {
+            Test::Equals => Condition::IsAny,
+            Test::Contains => Condition::ContainsAny,
+            _ => Condition::Is,
+        };
+        let joinable = any != Condition::Is
+            && schema::condition_min_schema(any) <= schema
+            && tests.iter().all(|(k, v)| *k == kind && !v.contains(';'));
+        if joinable {
+            let values: Vec<String> = tests.into_iter().map(|(_, value)| value).collect();
+            fields.push(FieldCondition {
+                condition: any,
+                ..condition(kind, values.join(";"))
+            });
+        } else if alternative.len() == 1 {
+            // One condition per value; the rule group ORs them.
+            return Some(
+                tests
+                    .into_iter()
+                    .map(|(kind, value)| {
+                        Filter::Field(FieldCondition {
+                            name: Some(name.to_string()),
+                            ..condition(kind, value)
+                        })
+                    })
+                    .collect(),
+            );
+        }
+    }
+    match fields.len() {
+        0 => None,
+        1 => {
+            let mut field = fields.remove(0);
+            field.name = Some(name.to_string());
+            Some(vec![Filter::Field(field)])
+        }
+        _ => Some(vec![Filter::Rule(Rule {
+            name: Some(name.to_string()),
+            group_relation: GroupRelation::And,
+            fields,
+        })]),
+    }
+}
+
+/// The Sysmon field a Sigma test is about and what it tests, or `None` if
+/// it does not translate. Sigma's `Imphash`, `md5`, `sha1` and `sha256`
+/// fields are tests of the `ALGORITHM=value` entry in `Hashes`.
+fn field_tests(
+    field: &str,
+    m: &FieldMatch,
+    event: &EventType,
+) -> Option<(&'static str, Vec<(Test, String)>)> {
+    let tests = m.tests()?;
+    if let Some(algorithm) = hash_field(field).filter(|_| event.has_field("Hashes")) {
+        let tests = tests
+            .into_iter()
+            .map(|(kind, value)| match kind {
+                Test::Equals => Some((Test::Contains, format!("{}={}", algorithm, value))),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        return Some(("Hashes", tests));
+    }
+    let field = event.fields.iter().find(|f| **f == field)?;
+    Some((field, tests))
+}
+
+/// `IMPHASH` for Sigma's `Imphash` field.
+fn hash_field(field: &str) -> Option<&'static str> {
+    schema::HASH_ALGORITHMS
+        .iter()
+        .find(|a| a.eq_ignore_ascii_case(field))
+        .copied()
+}
+
+/// The hash algorithms a test needs Sysmon to record: those named by a
+/// hash field or by `ALGORITHM=` prefixes of `Hashes` values, or `*` when
+/// a `Hashes` value does not say.
+fn hash_algorithm(m: &FieldMatch) -> Option<Vec<&'static str>> {
+    let field = m.field.as_deref()?;
+    if let Some(algorithm) = hash_field(field) {
+        return Some(vec![algorithm]);
+    }
+    if field != "Hashes" {
+        return None;
+    }
+    let algorithms = m
+        .values
+        .iter()
+        .flatten()
+        .map(|value| {
+            let prefix = value.trim_start_matches('*').split('=').next()?;
+            hash_field(prefix).filter(|_| value.contains('='))
+        })
+        .map(|algorithm| algorithm.unwrap_or("*"))
+        .collect();
+    Some(algorithms)
+}
+
+fn condition(kind: Test) -> Condition {
+    match kind {
+        Test::Equals => Condition::Is,
+        Test::Contains => Condition::Contains,
+        Test::StartsWith => Condition::BeginWith,
+        Test::EndsWith => Condition::EndWith,
+    }
+}
+
+/// `filters` without the ones that test the same as an earlier one under
+/// another name.
+fn dedupe(filters: Vec<Filter>) -> Vec<Filter> {
+    let unnamed = |filter: &Filter| {
+        let mut filter = filter.clone();
+        match &mut filter {
+            Filter::Field(field) => field.name = None,
+            Filter::Rule(rule) => rule.name = None,
+        }
+        filter
+    };
+    let mut seen = Vec::new();
+    let mut kept = Vec::new();
+    for filter in filters {
+        let key = unnamed(&filter);
+        if !seen.contains(&key) {
+            seen.push(key);
+            kept.push(filter);
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_translates_selections_into_includes() {
+        let rules = sigma::parse_rules(
+            r#"title: Rundll32 Without Arguments
+tags:
+    - attack.defense-evasion
+    - attack.t1218.011
+logsource:
+    category: process_creation
+    product: windows
+detection:
+    selection:
+        Image|endswith: '\rundll32.exe'
+        CommandLine|endswith:
+            - 'rundll32.exe'
+            - 'rundll32'
+    filter_main:
+        ParentImage|contains: '\svchost.exe'
+    condition: selection and not filter_main
+---
+title: Suspicious Shells
+logsource:
+    category: process_creation
+    product: windows
+detection:
+    selection:
+        - Image|endswith:
+            - '\powershell.exe'
+            - '\pwsh.exe'
+        - OriginalFileName:
+            - 'PowerShell.EXE'
+            - 'pwsh.dll'
+        - Image|endswith: '\rundll32.exe'
+          CommandLine|re: 'rundll32(\.exe)?$'
+    condition: selection
+---
+title: Known Bad Driver
+logsource:
+    category: driver_load
+    product: windows
+detection:
+    selection:
+        Imphash: 'f0a1b2'
+    condition: selection
+---
+title: Any Named Pipe
+logsource:
+    category: pipe_created
+    product: windows
+detection:
+    keywords:
+        - 'evil'
+    condition: keywords
+---
+title: Failed Logon
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    condition: selection
+"#,
+        )
+        .unwrap();
+
+        let config = generate(&rules, Version::new(4, 90), &Database::bundled());
+        assert_eq!(
+            config.options,
+            [ConfigOption {
+                name: "HashAlgorithms".to_string(),
+                value: "IMPHASH".to_string(),
+            }]
+        );
+        let events: Vec<(&str, OnMatch)> = config
+            .events()
+            .map(|e| (e.event.as_str(), e.onmatch))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("ProcessCreate", OnMatch::Include),
+                ("DriverLoad", OnMatch::Include),
+                ("PipeEvent", OnMatch::Exclude),
+            ]
+        );
+
+        let field = |field: &str, condition, value: &str, name: &str| FieldCondition {
+            field: field.to_string(),
+            condition,
+            value: value.to_string(),
+            name: Some(name).filter(|n| !n.is_empty()).map(str::to_string),
+        };
+        let technique = "technique_id=T1218.011,technique_name=Rundll32";
+        let shells = "Suspicious Shells";
+        let process = config.events().next().unwrap();
+        assert_eq!(
+            process.filters,
+            [
+                Filter::Field(field(
+                    "Image",
+                    Condition::EndWith,
+                    "\\rundll32.exe",
+                    technique
+                )),
+                Filter::Field(field(
+                    "Image",
+                    Condition::EndWith,
+                    "\\powershell.exe",
+                    shells
+                )),
+                Filter::Field(field("Image", Condition::EndWith, "\\pwsh.exe", shells)),
+                Filter::Field(field(
+                    "OriginalFileName",
+                    Condition::IsAny,
+                    "PowerShell.EXE;pwsh.dll",
+                    shells
+                )),
+            ]
+        );
+        let driver = config.events().nth(1).unwrap();
+        assert_eq!(
+            driver.filters,
+            [Filter::Field(field(
+                "Hashes",
+                Condition::Contains,
+                "IMPHASH=f0a1b2",
+                "Known Bad Driver"
+            ))]
+        );
+        assert!(config.events().nth(2).unwrap().filters.is_empty());
+    }
+}
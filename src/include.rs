@@ -0,0 +1,324 @@
+//! `<?include href="..."?>`: one XML config pulling in another.
+//!
+//! Includes are resolved as a file is read, before preprocessing, for
+//! single-file and batch conversion and for merging alike. `href` is
+//! relative to the including file, and must stay within the directory of
+//! the file being converted or merged (the input root) unless
+//! `--allow-outside-includes` is given. An included file can be a fragment
+//! (event filters, or a whole `<RuleGroup>`) or a complete `<Sysmon>`
+//! config, in which case only the contents of its `<EventFiltering>` are
+//! spliced in, so shared rule files stay valid configs of their own.
+//!
+//! Included files may include others, up to `--max-depth` levels; an
+//! include that leads back to a file already being expanded is reported
+//! as a cycle. Directives inside comments are left alone.
+//!
+//! ```xml
+//! <EventFiltering>
+//!   <?include href="common/excludes.xml"?>
+//!   <RuleGroup name="" groupRelation="or">...</RuleGroup>
+//! </EventFiltering>
+//! ```
+
+use crate::encoding;
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+
+const DIRECTIVE: &str = "<?include";
+
+/// How far includes may reach.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Levels of nesting allowed (`--max-depth`).
+    pub max_depth: usize,
+    /// Whether an `href` may lead outside the input root
+    /// (`--allow-outside-includes`).
+    pub outside_root: bool,
+}
+
+impl Limits {
+    /// Includes up to `max_depth` levels deep, within the input root.
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+            outside_root: false,
+        }
+    }
+}
+
+/// Whether `text` has an include directive to expand.
+pub fn has_includes(text: &str) -> bool {
+    text.contains(DIRECTIVE)
+}
+
+/// `text`, read from `path`, with every include directive replaced by the
+/// file it names, recursively within `limits`.
+pub fn expand(path: &Path, text: &str, limits: Limits) -> Result<String, ConversionError> {
+    expand_listing(path, text, limits).map(|(text, _)| text)
+}
+
+/// Like [`expand`], also returning every file included, directly or not,
+/// in the order they were read.
+pub fn expand_listing(
+    path: &Path,
+    text: &str,
+    limits: Limits,
+) -> Result<(String, Vec<PathBuf>), ConversionError> {
+    let mut included = Vec::new();
+    if !has_includes(text) {
+        return Ok((text.to_string(), included));
+    }
+    let root = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => canonical(parent),
+        _ => canonical(Path::new(".")),
+    };
+    let mut chain = vec![canonical(path)];
+    let text = expand_within(path, text, limits, &root, &mut chain, &mut included)?;
+    Ok((text, included))
+}
+
+fn expand_within(
+    path: &Path,
+    text: &str,
+    limits: Limits,
+    root: &Path,
+    chain: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+) -> Result<String, ConversionError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    loop {
+        let directive = rest.find(DIRECTIVE);
+        let comment = rest.find("<!--");
+        match (directive, comment) {
+            (Some(at), Some(c)) if c < at => {
+                let end = rest[c..].find("-->").map_or(rest.len(), |e| c + e + 3);
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+            (Some(at), _) => {
+                let end = rest[at..].find("?>").map(|e| at + e + 2).ok_or_else(|| {
+                    invalid(path, "unterminated <?include ...?> directive".to_string())
+                })?;
+                let href = href(&rest[at + DIRECTIVE.len()..end - 2])
+                    .ok_or_else(|| invalid(path, format!("{} has no href", &rest[at..end])))?;
+                out.push_str(&rest[..at]);
+                out.push_str(&include(path, &href, limits, root, chain, included)?);
+                rest = &rest[end..];
+            }
+            (None, _) => break,
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The expanded contents of the file `href` names, relative to `from`.
+fn include(
+    from: &Path,
+    href: &str,
+    limits: Limits,
+    root: &Path,
+    chain: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+) -> Result<String, ConversionError> {
+    let target = from.parent().unwrap_or(Path::new("")).join(href);
+    let key = canonical(&target);
+    if chain.contains(&key) {
+        let mut cycle: Vec<String> = chain.iter().map(|p| p.display().to_string()).collect();
+        cycle.push(key.display().to_string());
+        return Err(invalid(
+            from,
+            format!("include cycle: {}", cycle.join(" -> ")),
+        ));
+    }
+    if !limits.outside_root && target.exists() && !key.starts_with(root) {
+        return Err(invalid(
+            from,
+            format!(
+                "{} is outside {} (--allow-outside-includes includes it anyway)",
+                href,
+                root.display()
+            ),
+        ));
+    }
+    if chain.len() > limits.max_depth {
+        return Err(invalid(
+            from,
+            format!(
+                "including {} would nest more than {} level(s) deep (--max-depth)",
+                target.display(),
+                limits.max_depth
+            ),
+        ));
+    }
+
+    let bytes = std::fs::read(&target).map_err(|e| ConversionError::io_error(&target, e))?;
+    let text = encoding::decode(&bytes)
+        .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", target.display(), e)))?;
+    if !included.contains(&target) {
+        included.push(target.clone());
+    }
+    chain.push(key);
+    let expanded = expand_within(&target, body(&text), limits, root, chain, included);
+    chain.pop();
+    expanded
+}
+
+/// What an included file contributes: the inside of `<EventFiltering>` for
+/// a complete config, otherwise everything after the XML declaration.
+fn body(text: &str) -> &str {
+    let text = match text.trim_start().strip_prefix("<?xml") {
+        Some(rest) => rest.find("?>").map_or("", |end| &rest[end + 2..]),
+        None => text,
+    };
+    if root(text) != Some("Sysmon") {
+        return text;
+    }
+    let Some(start) = text.find("<EventFiltering") else {
+        return "";
+    };
+    let Some(open_end) = text[start..].find('>').map(|e| start + e) else {
+        return "";
+    };
+    if text[..open_end].ends_with('/') {
+        return "";
+    }
+    match text[open_end + 1..].find("</EventFiltering>") {
+        Some(end) => &text[open_end + 1..open_end + 1 + end],
+        None => "",
+    }
+}
+
+/// The name of the first element in `text`, skipping comments and
+/// processing instructions.
+fn root(text: &str) -> Option<&str> {
+    let mut rest = text;
+    loop {
+        let at = rest.find('<')?;
+        rest = &rest[at..];
+        if rest.starts_with("<!--") {
+            rest = &rest[rest.find("-->")? + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest = &rest[rest.find('>')? + 1..];
+        } else {
+            let name = &rest[1..];
+            let end = name
+                .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .unwrap_or(name.len());
+            return Some(&name[..end]);
+        }
+    }
+}
+
+/// The `href` pseudo-attribute of a directive's contents.
+fn href(attributes: &str) -> Option<String> {
+    let at = attributes.find("href")?;
+    let rest = attributes[at + 4..]
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    let end = value.find(quote)?;
+    Some(value[..end].to_string())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn invalid(path: &Path, message: String) -> ConversionError {
+    ConversionError::InvalidFile(format!("{}: {}", path.display(), message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_splices_fragments_and_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("common")).unwrap();
+        std::fs::write(
+            dir.path().join("common/excludes.xml"),
+            "<?xml version=\"1.0\"?>\n<Sysmon schemaversion=\"4.90\"><EventFiltering><DnsQuery onmatch=\"exclude\"/><?include href='pipes.xml'?></EventFiltering></Sysmon>",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("common/pipes.xml"),
+            "<PipeEvent onmatch=\"include\"/>",
+        )
+        .unwrap();
+        let main = dir.path().join("main.xml");
+        let text = "<Sysmon><EventFiltering><?include href=\"common/excludes.xml\"?><!-- <?include href=\"missing.xml\"?> --></EventFiltering></Sysmon>";
+
+        assert_eq!(
+            expand(&main, text, Limits::new(10)).unwrap(),
+            "<Sysmon><EventFiltering><DnsQuery onmatch=\"exclude\"/><PipeEvent onmatch=\"include\"/><!-- <?include href=\"missing.xml\"?> --></EventFiltering></Sysmon>"
+        );
+        let (_, included) = expand_listing(&main, text, Limits::new(10)).unwrap();
+        assert_eq!(
+            included,
+            [
+                dir.path().join("common/excludes.xml"),
+                dir.path().join("common/pipes.xml")
+            ]
+        );
+        let err = expand(&main, text, Limits::new(1)).unwrap_err().to_string();
+        assert!(err.contains("more than 1 level(s) deep"), "{}", err);
+    }
+
+    #[test]
+    fn test_expand_reports_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.xml");
+        std::fs::write(&a, "<?include href=\"b.xml\"?>").unwrap();
+        std::fs::write(dir.path().join("b.xml"), "<?include href=\"a.xml\"?>").unwrap();
+
+        let err = expand(&a, "<?include href=\"b.xml\"?>", Limits::new(10))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("include cycle:"), "{}", err);
+        assert!(err.ends_with("a.xml"), "{}", err);
+        assert!(expand(&a, "<?include file=\"b.xml\"?>", Limits::new(10)).is_err());
+    }
+
+    #[test]
+    fn test_expand_keeps_includes_within_the_input_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("configs/common")).unwrap();
+        std::fs::write(
+            dir.path().join("shared.xml"),
+            "<DnsQuery onmatch=\"exclude\"/>",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("configs/common/up.xml"),
+            "<?include href=\"../../shared.xml\"?>",
+        )
+        .unwrap();
+        let main = dir.path().join("configs/main.xml");
+
+        // `href` is relative to the file that has the directive.
+        let nested = "<?include href=\"common/up.xml\"?>";
+        let err = expand(&main, nested, Limits::new(10))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("../../shared.xml is outside"), "{}", err);
+        let absolute = format!(
+            "<?include href=\"{}\"?>",
+            dir.path().join("shared.xml").display()
+        );
+        assert!(expand(&main, &absolute, Limits::new(10)).is_err());
+
+        let limits = Limits {
+            max_depth: 10,
+            outside_root: true,
+        };
+        assert_eq!(
+            expand(&main, nested, limits).unwrap(),
+            "<DnsQuery onmatch=\"exclude\"/>"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! `--incremental`: skip batch inputs that have not changed since the last
+//! run.
+//!
+//! The output directory holds a small state file with one line per input:
+//! its SHA-256, its modification time and its path relative to the input
+//! directory. An input is unchanged when its output still exists and either
+//! its mtime or, failing that, its hash matches what was recorded, so a
+//! `touch` or a fresh checkout does not force a reconversion. The state
+//! does not cover conversion options; after changing them, run once without
+//! `--incremental`. Each line also records how long the input took to
+//! convert, which `--schedule slowest-first` starts the slowest inputs by.
+
+use crate::batch::Plan;
+use crate::io_guard;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use sysmon_json::error::ConversionError;
+
+pub const STATE_FILE: &str = ".sysmon-helper-state";
+
+const HEADER: &str = concat!("# sysmon_cli ", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub sha256: String,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub mtime: u128,
+    /// How long the last conversion took, when one was timed.
+    pub duration: Option<Duration>,
+}
+
+/// Recorded fingerprints, keyed by path relative to the input directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct State {
+    entries: BTreeMap<PathBuf, Fingerprint>,
+}
+
+impl State {
+    /// Reads the state from `output_dir`. A missing, unreadable or
+    /// outdated state file gives an empty state, i.e. a full run.
+    pub fn load(output_dir: &Path) -> Self {
+        let path = output_dir.join(STATE_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).unwrap_or_else(|| {
+                warn!("Ignoring unreadable state file {}", path.display());
+                State::default()
+            }),
+            Err(_) => State::default(),
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+        let mut entries = BTreeMap::new();
+        for line in lines {
+            let mut fields = line.splitn(4, '\t');
+            let sha256 = fields.next()?.to_string();
+            let mtime = fields.next()?.parse().ok()?;
+            let duration = match fields.next()? {
+                "-" => None,
+                micros => Some(Duration::from_micros(micros.parse().ok()?)),
+            };
+            let relative = PathBuf::from(fields.next()?);
+            entries.insert(
+                relative,
+                Fingerprint {
+                    sha256,
+                    mtime,
+                    duration,
+                },
+            );
+        }
+        Some(State { entries })
+    }
+
+    fn render(&self) -> String {
+        let mut out = format!("{}\n", HEADER);
+        for (relative, fingerprint) in &self.entries {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                fingerprint.sha256,
+                fingerprint.mtime,
+                fingerprint
+                    .duration
+                    .map_or("-".to_string(), |d| d.as_micros().to_string()),
+                relative.to_string_lossy().replace('\\', "/")
+            ));
+        }
+        out
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<(), ConversionError> {
+        io_guard::create_dir_all(output_dir)?;
+        io_guard::write(&output_dir.join(STATE_FILE), self.render())
+    }
+}
+
+/// State still to be written once the run has finished.
+pub struct Pending {
+    state: State,
+    /// Inputs that will be converted, with their new fingerprints.
+    changed: Vec<(PathBuf, PathBuf, Fingerprint)>,
+}
+
+/// Moves jobs whose inputs are unchanged from `plan.jobs` to `plan.skipped`.
+pub fn filter(
+    plan: &mut Plan,
+    input_dir: &Path,
+    output_dir: &Path,
+) -> Result<Pending, ConversionError> {
+    let previous = State::load(output_dir);
+    let mut state = State::default();
+    let mut changed = Vec::new();
+    let mut jobs = Vec::new();
+
+    for job in std::mem::take(&mut plan.jobs) {
+        let relative = relative_key(input_dir, &job.input);
+        let recorded = previous.entries.get(&relative);
+        let mtime = mtime(&job.input)?;
+        if let Some(duration) = recorded.and_then(|r| r.duration) {
+            plan.history.insert(job.input.clone(), duration);
+        }
+
+        let (fingerprint, unchanged) = match recorded.filter(|_| job.output.exists()) {
+            Some(recorded) if recorded.mtime == mtime => (recorded.clone(), true),
+            Some(recorded) => {
+                let sha256 = hash(&job.input)?;
+                let unchanged = recorded.sha256 == sha256;
+                let duration = recorded.duration;
+                let fingerprint = Fingerprint {
+                    sha256,
+                    mtime,
+                    duration,
+                };
+                (fingerprint, unchanged)
+            }
+            None => {
+                let sha256 = hash(&job.input)?;
+                let fingerprint = Fingerprint {
+                    sha256,
+                    mtime,
+                    duration: None,
+                };
+                (fingerprint, false)
+            }
+        };
+
+        if unchanged {
+            state.entries.insert(relative, fingerprint);
+            plan.skipped.push(job.input);
+        } else {
+            changed.push((relative, job.input.clone(), fingerprint));
+            jobs.push(job);
+        }
+    }
+
+    info!(
+        "Incremental run: {} changed, {} unchanged",
+        jobs.len(),
+        state.entries.len()
+    );
+    plan.jobs = jobs;
+    Ok(Pending { state, changed })
+}
+
+impl Pending {
+    /// Records every converted input except those in `failures`, which are
+    /// retried next time, with the time `durations` says it took.
+    pub fn save(
+        mut self,
+        failures: &[PathBuf],
+        durations: &[(PathBuf, Duration)],
+        output_dir: &Path,
+    ) -> Result<(), ConversionError> {
+        let durations: BTreeMap<&PathBuf, Duration> =
+            durations.iter().map(|(input, d)| (input, *d)).collect();
+        for (relative, input, mut fingerprint) in self.changed {
+            if !failures.contains(&input) {
+                if let Some(duration) = durations.get(&input) {
+                    fingerprint.duration = Some(*duration);
+                }
+                self.state.entries.insert(relative, fingerprint);
+            }
+        }
+        self.state.save(output_dir)
+    }
+}
+
+/// Fills `plan.history` with the conversion times the state in
+/// `output_dir` recorded, without skipping anything.
+pub fn history(plan: &mut Plan, input_dir: &Path, output_dir: &Path) {
+    let state = State::load(output_dir);
+    for job in &plan.jobs {
+        let recorded = state.entries.get(&relative_key(input_dir, &job.input));
+        if let Some(duration) = recorded.and_then(|r| r.duration) {
+            plan.history.insert(job.input.clone(), duration);
+        }
+    }
+}
+
+fn relative_key(input_dir: &Path, input: &Path) -> PathBuf {
+    input.strip_prefix(input_dir).unwrap_or(input).to_path_buf()
+}
+
+fn mtime(path: &Path) -> Result<u128, ConversionError> {
+    let modified = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| ConversionError::io_error(path, e))?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0))
+}
+
+fn hash(path: &Path) -> Result<String, ConversionError> {
+    let bytes = std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+    Ok(Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut state = State::default();
+        state.entries.insert(
+            PathBuf::from("1_process/include.xml"),
+            Fingerprint {
+                sha256: "ab".repeat(32),
+                mtime: 1_700_000_000_123_456_789,
+                duration: Some(Duration::from_millis(1500)),
+            },
+        );
+        state.entries.insert(
+            PathBuf::from("3_network/include.xml"),
+            Fingerprint {
+                sha256: "cd".repeat(32),
+                mtime: 1_700_000_000_000_000_000,
+                duration: None,
+            },
+        );
+        assert_eq!(State::parse(&state.render()), Some(state));
+        assert_eq!(State::parse("# sysmon_cli 0.0.0\n"), None);
+    }
+
+    #[test]
+    fn test_unchanged_inputs_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input_dir, output_dir) = (dir.path().join("in"), dir.path().join("out"));
+        std::fs::create_dir_all(&input_dir).unwrap();
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let input = input_dir.join("a.xml");
+        let output = output_dir.join("a.json");
+        std::fs::write(&input, "<Sysmon/>").unwrap();
+        std::fs::write(&output, "{}").unwrap();
+        let plan = || Plan {
+            jobs: vec![crate::batch::Job {
+                input: input.clone(),
+                output: output.clone(),
+            }],
+            ..Plan::default()
+        };
+
+        let mut first = plan();
+        filter(&mut first, &input_dir, &output_dir)
+            .unwrap()
+            .save(&[], &[(input.clone(), Duration::from_secs(2))], &output_dir)
+            .unwrap();
+        assert_eq!(first.jobs.len(), 1);
+
+        let mut second = plan();
+        filter(&mut second, &input_dir, &output_dir).unwrap();
+        assert!(second.jobs.is_empty());
+        assert_eq!(second.history[&input], Duration::from_secs(2));
+        assert_eq!(second.skipped, vec![input.clone()]);
+
+        std::fs::remove_file(&output).unwrap();
+        let mut third = plan();
+        filter(&mut third, &input_dir, &output_dir).unwrap();
+        assert_eq!(third.jobs.len(), 1);
+    }
+}
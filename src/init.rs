@@ -0,0 +1,234 @@
+//! `init`: scaffold a modular rules repository in the sysmon-modular layout.
+//!
+//! The skeleton has one numbered folder per event type, named after the
+//! event IDs it filters (`1_process_create`, `12_13_14_registry_event`),
+//! each holding an include and an exclude starter file; `baseconfig.xml`
+//! with the schema version and global options; and `sysmon-helper.toml`,
+//! the merge manifest, which holds the options the repository is merged
+//! with. The starter files keep their event filter commented out: an empty
+//! include logs nothing but an empty exclude logs everything, so a freshly
+//! scaffolded repository merges to a config with no rules at all.
+
+use crate::io_guard;
+use clap::Args;
+use log::info;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use sysmon_cli::schema::{self, EventType, Platform, Version};
+use sysmon_json::error::ConversionError;
+
+/// The merge manifest's file name.
+pub const MANIFEST: &str = "sysmon-helper.toml";
+
+#[derive(Args)]
+pub struct InitArgs {
+    /// Directory to create the repository in
+    #[arg(default_value = ".")]
+    pub dir: PathBuf,
+
+    /// Schema version for the base template and starter files [default: newest known]
+    #[arg(long)]
+    pub schema_version: Option<Version>,
+
+    /// Platform the rules are for: windows or linux (only Linux event types get folders)
+    #[arg(long, default_value = "windows")]
+    pub platform: Platform,
+
+    /// Overwrite files that already exist
+    #[arg(long)]
+    pub force: bool,
+}
+
+pub fn run(args: &InitArgs) -> Result<(), ConversionError> {
+    let schema = args
+        .schema_version
+        .unwrap_or_else(|| schema::RELEASES[schema::RELEASES.len() - 1].1);
+    let files = scaffold(schema, args.platform);
+    if !args.force {
+        let existing: Vec<String> = files
+            .iter()
+            .map(|(path, _)| args.dir.join(path))
+            .filter(|path| path.exists())
+            .map(|path| path.display().to_string())
+            .collect();
+        if !existing.is_empty() {
+            return Err(ConversionError::ValidationError(format!(
+                "Refusing to overwrite {} (use --force)",
+                existing.join(", ")
+            )));
+        }
+    }
+
+    for (path, text) in &files {
+        let path = args.dir.join(path);
+        if let Some(parent) = path.parent() {
+            io_guard::create_dir_all(parent)?;
+        }
+        io_guard::write(&path, text)?;
+    }
+    info!(
+        "Created {} file(s) in {}; merge them with: sysmon_cli --config {} -i {} --merge",
+        files.len(),
+        args.dir.display(),
+        args.dir.join(MANIFEST).display(),
+        args.dir.display()
+    );
+    Ok(())
+}
+
+/// The files of a new repository, relative to its root.
+pub fn scaffold(schema: Version, platform: Platform) -> Vec<(PathBuf, String)> {
+    let mut files = vec![
+        (
+            PathBuf::from("baseconfig.xml"),
+            base_template(schema, platform),
+        ),
+        (PathBuf::from(MANIFEST), manifest(platform)),
+    ];
+    for event in schema::EVENT_TYPES
+        .iter()
+        .filter(|e| e.min_schema <= schema && platform.supports(e.name))
+    {
+        let folder = folder(event);
+        let stem = snake_case(event.name);
+        for onmatch in ["include", "exclude"] {
+            files.push((
+                Path::new(&folder).join(format!("{}_{}.xml", onmatch, stem)),
+                starter(schema, event, onmatch),
+            ));
+        }
+    }
+    files
+}
+
+/// `1_process_create`: the event IDs, then the event type in snake case.
+fn folder(event: &EventType) -> String {
+    let mut name = String::new();
+    for id in event.ids {
+        let _ = write!(name, "{}_", id);
+    }
+    name + &snake_case(event.name)
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
+
+fn base_template(schema: Version, platform: Platform) -> String {
+    let options = match platform {
+        Platform::Windows => {
+            "  <HashAlgorithms>md5,sha256,IMPHASH</HashAlgorithms>\n  <CheckRevocation/>\n"
+        }
+        Platform::Linux => "",
+    };
+    format!(
+        "<Sysmon schemaversion=\"{}\">\n{}  <EventFiltering>\n    <!-- Rules live in the numbered folders; this file only carries the schema\n         version and global options. -->\n  </EventFiltering>\n</Sysmon>\n",
+        schema, options
+    )
+}
+
+fn manifest(platform: Platform) -> String {
+    format!(
+        "# Merge manifest for this rules repository. Build the config with:\n\
+         #\n\
+         #   sysmon_cli --config {} -i . -o sysmonconfig.xml --merge\n\
+         #\n\
+         # baseconfig.xml carries the schema version and global options; every\n\
+         # other XML file under the numbered folders contributes rules.\n\
+         recursive = true\n\
+         platform = \"{}\"\n\
+         # How to settle a rule one file includes and another excludes:\n\
+         # ours, theirs, both or skip.\n\
+         # strategy = \"ours\"\n",
+        MANIFEST, platform
+    )
+}
+
+fn starter(schema: Version, event: &EventType, onmatch: &str) -> String {
+    let field = event
+        .fields
+        .iter()
+        .find(|f| !["RuleName", "UtcTime", "ProcessGuid", "ProcessId"].contains(f))
+        .unwrap_or(&"RuleName");
+    format!(
+        "<Sysmon schemaversion=\"{schema}\">\n  <EventFiltering>\n    <RuleGroup name=\"\" groupRelation=\"or\">\n      <!-- Uncomment to start adding {onmatch} rules for {name} (event ID {ids}).\n      <{name} onmatch=\"{onmatch}\">\n        <{field} name=\"technique_id=T0000,technique_name=Example\" condition=\"contains\">example</{field}>\n      </{name}>\n      -->\n    </RuleGroup>\n  </EventFiltering>\n</Sysmon>\n",
+        name = event.name,
+        ids = event
+            .ids
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge;
+    use sysmon_cli::model::SysmonConfig;
+
+    #[test]
+    fn test_scaffold_follows_the_modular_layout() {
+        let files = scaffold(Version::new(4, 90), Platform::Windows);
+        let paths: Vec<String> = files
+            .iter()
+            .map(|(p, _)| p.to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(
+            paths[..4],
+            [
+                "baseconfig.xml",
+                "sysmon-helper.toml",
+                "1_process_create/include_process_create.xml",
+                "1_process_create/exclude_process_create.xml",
+            ]
+        );
+        assert!(paths.contains(&"12_13_14_registry_event/exclude_registry_event.xml".to_string()));
+
+        for (path, text) in files
+            .iter()
+            .filter(|(p, _)| p.extension().is_some_and(|e| e == "xml"))
+        {
+            let config = SysmonConfig::from_xml_str(text)
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert!(config.rule_groups.iter().all(|g| g.events.is_empty()));
+        }
+    }
+
+    #[test]
+    fn test_scaffold_merges_to_a_config_without_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, text) in scaffold(Version::new(4, 90), Platform::Windows) {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        let output = dir.path().join("sysmonconfig.xml");
+        let sources = merge::sources(dir.path(), true, false, &output);
+        let mut resolver = merge::Resolver::new(None);
+        let merged =
+            merge::merge_sources(&sources, Some(1), 10, &Default::default(), &mut resolver)
+                .unwrap();
+        assert_eq!(merged.schema_version, "4.90");
+        assert!(merged.rule_groups.iter().all(|g| g.events.is_empty()));
+    }
+
+    #[test]
+    fn test_scaffold_limits_folders_to_platform_and_schema() {
+        let linux = scaffold(Version::new(4, 90), Platform::Linux);
+        assert_eq!(linux.len(), 2 + 2 * schema::LINUX_EVENT_TYPES.len());
+        assert!(!linux[0].1.contains("HashAlgorithms"));
+
+        let old = scaffold(Version::new(4, 22), Platform::Windows);
+        assert!(old.iter().all(|(p, _)| !p.starts_with("23_file_delete")));
+        assert!(old[2].1.starts_with("<Sysmon schemaversion=\"4.22\">"));
+    }
+}
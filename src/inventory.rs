@@ -0,0 +1,478 @@
+//! `inventory`: what a config, or a tree of modular files, detects.
+//!
+//! By default each file is listed with its rule count and the ATT&CK
+//! techniques its rules cite and the CVEs it references. With `--cves` the report is turned
+//! around: one entry per CVE, with every place it is referenced. sysmon-modular
+//! names exploit detections after the CVE (`include_cve_2021_40444.xml`),
+//! and rules and comments cite them too, so file names, XML comments and
+//! rule names are all searched. IDs are recognised however they are
+//! separated (`CVE-2021-40444`, `cve_2021_40444`) and reported in the
+//! standard form.
+//!
+//! A file in a tree that cannot be read or parsed is skipped with a
+//! warning and listed as unreadable, rather than ending the inventory.
+
+use crate::{explain, io_guard, validate, walk};
+use clap::{Args, ValueEnum};
+use log::warn;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use sysmon_cli::model::{Filter, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InventoryFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct InventoryArgs {
+    /// Config file, or a directory searched recursively for XML and JSON configs
+    pub path: PathBuf,
+
+    /// Report the CVEs referenced by file names, comments and rule names
+    #[arg(long)]
+    pub cves: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: InventoryFormat,
+
+    /// Write the inventory to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// What one file contains.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct FileSummary {
+    pub file: PathBuf,
+    pub rules: usize,
+    pub techniques: Vec<String>,
+    pub cves: Vec<String>,
+    /// Why the file could not be read, for a file skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Where a CVE reference was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    FileName,
+    Comment,
+    RuleName,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Source::FileName => "file name",
+            Source::Comment => "comment",
+            Source::RuleName => "rule name",
+        }
+    }
+}
+
+/// One mention of a CVE.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Reference {
+    /// Grouped under in the report rather than repeated.
+    #[serde(skip)]
+    pub cve: String,
+    pub file: PathBuf,
+    pub source: Source,
+    /// Only known for XML input, and not for file names.
+    pub line: Option<usize>,
+    /// The file name, comment or rule name that mentions the CVE.
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Cve {
+    pub id: String,
+    pub references: Vec<Reference>,
+}
+
+pub fn run(args: &InventoryArgs) -> Result<(), ConversionError> {
+    let files = if args.path.is_dir() {
+        walk::files(&args.path, usize::MAX, false)
+            .into_iter()
+            .filter(|p| {
+                p.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+                    e.eq_ignore_ascii_case("xml") || e.eq_ignore_ascii_case("json")
+                })
+            })
+            .collect()
+    } else {
+        vec![args.path.clone()]
+    };
+
+    let mut summaries = Vec::new();
+    let mut references = Vec::new();
+    for file in &files {
+        let (text, is_xml, config) = match read(file) {
+            Ok(read) => read,
+            Err(e) if args.path.is_dir() => {
+                warn!("Skipping {}: {}", file.display(), e);
+                summaries.push(FileSummary {
+                    file: file.clone(),
+                    rules: 0,
+                    techniques: Vec::new(),
+                    cves: Vec::new(),
+                    error: Some(e.to_string()),
+                });
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let found = cve_references(file, &config, is_xml.then_some(text.as_str()));
+        summaries.push(summarize(file, &config, &found));
+        references.extend(found);
+    }
+
+    let report = match (args.cves, args.format) {
+        (false, InventoryFormat::Text) => render_files(&summaries),
+        (false, InventoryFormat::Json) => json(&summaries)?,
+        (true, InventoryFormat::Text) => render_cves(&by_cve(references), &summaries),
+        (true, InventoryFormat::Json) => json(&by_cve(references))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, report),
+        None => {
+            print!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+/// A config's text, whether it is XML, and the config parsed.
+fn read(file: &Path) -> Result<(String, bool, SysmonConfig), ConversionError> {
+    let (text, is_xml) = validate::load(file)?;
+    let config = SysmonConfig::from_xml_str(&text)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", file.display(), e)))?;
+    Ok((text, is_xml, config))
+}
+
+fn json<T: Serialize>(value: &T) -> Result<String, ConversionError> {
+    serde_json::to_string_pretty(value)
+        .map(|json| json + "\n")
+        .map_err(|e| ConversionError::Other(e.to_string()))
+}
+
+/// Every CVE mention in one file: its name, its comments (XML only) and
+/// the names of its rules.
+pub fn cve_references(file: &Path, config: &SysmonConfig, text: Option<&str>) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let mut cite = |source, line, mention: &str| {
+        for cve in cves(mention) {
+            references.push(Reference {
+                cve,
+                file: file.to_path_buf(),
+                source,
+                line,
+                text: mention.to_string(),
+            });
+        }
+    };
+
+    if let Some(name) = file.file_name() {
+        cite(Source::FileName, None, &name.to_string_lossy());
+    }
+    if let Some(text) = text {
+        for (line, comment) in comments(text) {
+            cite(Source::Comment, Some(line), &comment);
+        }
+    }
+    for (index, group) in config.rule_groups.iter().enumerate() {
+        for event in &group.events {
+            let mut seen: Vec<&str> = Vec::new();
+            for filter in &event.filters {
+                let (element, name) = match filter {
+                    Filter::Field(field) => (field.field.as_str(), field.name.as_deref()),
+                    Filter::Rule(rule) => ("Rule", rule.name.as_deref()),
+                };
+                seen.push(element);
+                let Some(name) = name else { continue };
+                let nth = seen.iter().filter(|e| **e == element).count();
+                let location = format!(
+                    "RuleGroup[{}]/{}/{}[{}]",
+                    index + 1,
+                    event.event,
+                    element,
+                    nth
+                );
+                let line = text.and_then(|text| validate::line_of(text, &location));
+                cite(Source::RuleName, line, name);
+            }
+        }
+    }
+
+    references
+}
+
+fn summarize(file: &Path, config: &SysmonConfig, references: &[Reference]) -> FileSummary {
+    let mut rules = 0;
+    let mut techniques = Vec::new();
+    for event in config.rule_groups.iter().flat_map(|g| &g.events) {
+        for filter in &event.filters {
+            rules += 1;
+            let name = match filter {
+                Filter::Field(field) => field.name.as_deref(),
+                Filter::Rule(rule) => rule.name.as_deref(),
+            };
+            techniques.extend(name.map(explain::techniques).unwrap_or_default());
+        }
+    }
+    techniques.sort();
+    techniques.dedup();
+    let mut cves: Vec<String> = references.iter().map(|r| r.cve.clone()).collect();
+    sort_cves(&mut cves);
+    cves.dedup();
+    FileSummary {
+        file: file.to_path_buf(),
+        rules,
+        techniques,
+        cves,
+        error: None,
+    }
+}
+
+/// References grouped by CVE, in ID order.
+fn by_cve(references: Vec<Reference>) -> Vec<Cve> {
+    let mut grouped: BTreeMap<(u32, u64), Cve> = BTreeMap::new();
+    for reference in references {
+        grouped
+            .entry(cve_key(&reference.cve))
+            .or_insert_with(|| Cve {
+                id: reference.cve.clone(),
+                references: Vec::new(),
+            })
+            .references
+            .push(reference);
+    }
+    grouped.into_values().collect()
+}
+
+fn render_files(summaries: &[FileSummary]) -> String {
+    let mut out = String::new();
+    for summary in summaries {
+        if let Some(error) = &summary.error {
+            let _ = writeln!(out, "{}: unreadable: {}", summary.file.display(), error);
+            continue;
+        }
+        let _ = write!(out, "{}: {} rule(s)", summary.file.display(), summary.rules);
+        if !summary.techniques.is_empty() {
+            let _ = write!(out, "; techniques: {}", summary.techniques.join(", "));
+        }
+        if !summary.cves.is_empty() {
+            let _ = write!(out, "; CVEs: {}", summary.cves.join(", "));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn render_cves(cves: &[Cve], summaries: &[FileSummary]) -> String {
+    let mut out = String::new();
+    for cve in cves {
+        let _ = writeln!(out, "{} ({} reference(s))", cve.id, cve.references.len());
+        for reference in &cve.references {
+            let file = match reference.line {
+                Some(line) => format!("{}:{}", reference.file.display(), line),
+                None => reference.file.display().to_string(),
+            };
+            match reference.source {
+                Source::FileName => {
+                    let _ = writeln!(out, "  {}: {}", file, reference.source.as_str());
+                }
+                source => {
+                    let _ = writeln!(out, "  {}: {} {:?}", file, source.as_str(), reference.text);
+                }
+            }
+        }
+    }
+    let (unreadable, read): (Vec<_>, Vec<_>) = summaries.iter().partition(|s| s.error.is_some());
+    let _ = writeln!(
+        out,
+        "{} CVE(s) referenced in {} file(s)",
+        cves.len(),
+        read.len()
+    );
+    for summary in unreadable {
+        let error = summary.error.as_deref().unwrap_or_default();
+        let _ = writeln!(out, "unreadable: {}: {}", summary.file.display(), error);
+    }
+    out
+}
+
+/// XML comments in `text` with the line each starts on, whitespace
+/// collapsed.
+fn comments(text: &str) -> Vec<(usize, String)> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = text[pos..].find("<!--").map(|at| pos + at) {
+        let body = start + 4;
+        let end = text[body..].find("-->").map_or(text.len(), |at| body + at);
+        let line = text[..start].matches('\n').count() + 1;
+        let comment = text[body..end]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        found.push((line, comment));
+        pos = (end + 3).min(text.len());
+    }
+    found
+}
+
+/// CVE IDs in `text`, as `CVE-YYYY-NNNN`. The parts may be separated by
+/// `-`, `_` or a space, and the prefix may be in any case.
+pub fn cves(text: &str) -> Vec<String> {
+    let bytes = text.as_bytes();
+    let digits = |from: usize| {
+        bytes[from.min(bytes.len())..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    let separator = |at: usize| matches!(bytes.get(at), Some(b'-' | b'_' | b' '));
+    let mut found = Vec::new();
+    let mut at = 0;
+    while at + 3 <= bytes.len() {
+        let boundary = at == 0 || !bytes[at - 1].is_ascii_alphanumeric();
+        if !boundary || !bytes[at..at + 3].eq_ignore_ascii_case(b"cve") {
+            at += 1;
+            continue;
+        }
+        let mut year = at + 3;
+        if separator(year) {
+            year += 1;
+        }
+        let number = year + 5;
+        let (year_len, number_len) = (digits(year), digits(number));
+        if year_len == 4 && separator(year + 4) && (4..=7).contains(&number_len) {
+            let end = number + number_len;
+            if !bytes.get(end).is_some_and(u8::is_ascii_alphabetic) {
+                found.push(format!(
+                    "CVE-{}-{}",
+                    &text[year..year + 4],
+                    &text[number..end]
+                ));
+                at = end;
+                continue;
+            }
+        }
+        at += 1;
+    }
+    sort_cves(&mut found);
+    found.dedup();
+    found
+}
+
+fn cve_key(id: &str) -> (u32, u64) {
+    let mut parts = id.split('-').skip(1).map(|p| p.parse::<u64>().unwrap_or(0));
+    let year = parts.next().unwrap_or(0) as u32;
+    (year, parts.next().unwrap_or(0))
+}
+
+fn sort_cves(ids: &mut [String]) {
+    ids.sort_by_key(|id| cve_key(id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cves_normalizes_separators_and_case() {
+        assert_eq!(
+            cves("include_cve_2021_40444.xml"),
+            vec!["CVE-2021-40444".to_string()]
+        );
+        assert_eq!(
+            cves("technique_id=T1203,CVE-2022-30190 and cve 2017-11882, CVE-2021-40444"),
+            vec!["CVE-2017-11882", "CVE-2021-40444", "CVE-2022-30190"]
+        );
+        assert!(cves("MSCVE-2021-40444 CVE-21-1 CVE-2021-123").is_empty());
+    }
+
+    #[test]
+    fn test_cve_references_searches_file_names_comments_and_rule_names() {
+        let text = "<Sysmon schemaversion=\"4.90\">\n<EventFiltering>\n\
+            <!-- Follina, CVE-2022-30190 -->\n<RuleGroup groupRelation=\"or\">\n\
+            <ProcessCreate onmatch=\"include\">\n\
+            <ParentImage name=\"technique_id=T1203,CVE-2022-30190\" condition=\"end with\">\\WINWORD.EXE</ParentImage>\n\
+            <Rule name=\"CVE-2021-40444 cpl\" groupRelation=\"and\">\n\
+            <CommandLine condition=\"contains\">.cpl:</CommandLine>\n</Rule>\n\
+            </ProcessCreate>\n</RuleGroup>\n</EventFiltering>\n</Sysmon>\n";
+        let config = SysmonConfig::from_xml_str(text).unwrap();
+        let file = Path::new("include_cve_2021_40444.xml");
+        let references = cve_references(file, &config, Some(text));
+
+        let summary = summarize(file, &config, &references);
+        assert_eq!(summary.rules, 2);
+        assert_eq!(summary.techniques, vec!["T1203"]);
+        assert_eq!(summary.cves, vec!["CVE-2021-40444", "CVE-2022-30190"]);
+
+        let report = render_cves(&by_cve(references), &[summary]);
+        assert_eq!(
+            report,
+            "CVE-2021-40444 (2 reference(s))\n  \
+             include_cve_2021_40444.xml: file name\n  \
+             include_cve_2021_40444.xml:7: rule name \"CVE-2021-40444 cpl\"\n\
+             CVE-2022-30190 (2 reference(s))\n  \
+             include_cve_2021_40444.xml:3: comment \"Follina, CVE-2022-30190\"\n  \
+             include_cve_2021_40444.xml:6: rule name \"technique_id=T1203,CVE-2022-30190\"\n\
+             2 CVE(s) referenced in 1 file(s)\n"
+        );
+    }
+
+    #[test]
+    fn test_run_skips_unreadable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("include_cve_2021_40444.xml"),
+            "<Sysmon schemaversion=\"4.90\"><EventFiltering/></Sysmon>",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.xml"), "<Sysmon").unwrap();
+        let output = dir.path().join("report.txt");
+        let args = |cves| InventoryArgs {
+            path: dir.path().to_path_buf(),
+            cves,
+            format: InventoryFormat::Text,
+            output: Some(output.clone()),
+        };
+
+        run(&args(false)).unwrap();
+        let report = std::fs::read_to_string(&output).unwrap();
+        let broken = dir.path().join("broken.xml").display().to_string();
+        assert!(
+            report.contains(&format!("{}: unreadable: ", broken)),
+            "{}",
+            report
+        );
+        assert!(
+            report.contains("include_cve_2021_40444.xml: 0 rule(s)"),
+            "{}",
+            report
+        );
+
+        run(&args(true)).unwrap();
+        let report = std::fs::read_to_string(&output).unwrap();
+        assert!(
+            report.contains("1 CVE(s) referenced in 1 file(s)\nunreadable: "),
+            "{}",
+            report
+        );
+
+        let single = InventoryArgs {
+            path: dir.path().join("broken.xml"),
+            ..args(false)
+        };
+        assert!(run(&single).is_err());
+    }
+}
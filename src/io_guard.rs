@@ -0,0 +1,268 @@
+//! Single choke point for filesystem writes.
+//!
+//! Every write the CLI performs (outputs, backups, staging directories, temp
+//! files) goes through these helpers. With `--read-only` enabled they refuse
+//! to touch the filesystem, which turns any code path that would have written
+//! into a hard error rather than a silent side effect.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use sysmon_json::error::ConversionError;
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Staging directories that exist right now, for [`remove_tempdirs`].
+static TEMPDIRS: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+#[cfg(test)]
+thread_local! {
+    /// Per-thread `--read-only`, so tests don't switch it on for each other.
+    static TEST_READ_ONLY: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+pub fn enable_read_only() {
+    READ_ONLY.store(true, Ordering::SeqCst);
+}
+
+pub fn is_read_only() -> bool {
+    #[cfg(test)]
+    if TEST_READ_ONLY.with(std::cell::Cell::get) {
+        return true;
+    }
+    READ_ONLY.load(Ordering::SeqCst)
+}
+
+/// Runs `f` as if `--read-only` were set, on this thread only.
+#[cfg(test)]
+pub fn with_read_only<T>(f: impl FnOnce() -> T) -> T {
+    TEST_READ_ONLY.with(|read_only| read_only.set(true));
+    let result = f();
+    TEST_READ_ONLY.with(|read_only| read_only.set(false));
+    result
+}
+
+/// Fails if writing to `path` is not allowed.
+pub fn check_write(path: &Path) -> Result<(), ConversionError> {
+    if is_read_only() {
+        return Err(ConversionError::InvalidFile(format!(
+            "Refusing to write {}: --read-only is set",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), ConversionError> {
+    check_write(path)?;
+    std::fs::write(path, contents).map_err(|e| ConversionError::io_error(path, e))
+}
+
+/// Writes `contents` to a file beside `path` and renames it over `path`, so
+/// `path` is replaced rather than rewritten in place: other hard links to
+/// the old file keep their content, and `path` never holds a partly
+/// written file.
+pub fn replace(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), ConversionError> {
+    let partial = partial_path(path);
+    write(&partial, contents)?;
+    rename(&partial, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&partial);
+    })
+}
+
+/// The hidden file [`replace`] writes before renaming it over `path`:
+/// `.out.partial.json` for `out.json`, keeping the extension.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let partial = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!(".{}.partial.{}", stem, extension)
+        }
+        _ => format!(".{}.partial", name),
+    };
+    path.with_file_name(partial)
+}
+
+pub fn rename(from: &Path, to: &Path) -> Result<(), ConversionError> {
+    check_write(to)?;
+    std::fs::rename(from, to).map_err(|e| ConversionError::io_error(to, e))
+}
+
+pub fn create(path: &Path) -> Result<File, ConversionError> {
+    check_write(path)?;
+    File::create(path).map_err(|e| ConversionError::io_error(path, e))
+}
+
+/// Opens `path` for appending, creating it if needed.
+pub fn append(path: &Path) -> Result<File, ConversionError> {
+    check_write(path)?;
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| ConversionError::io_error(path, e))
+}
+
+pub fn create_dir_all(path: &Path) -> Result<(), ConversionError> {
+    check_write(path)?;
+    std::fs::create_dir_all(path).map_err(|e| ConversionError::io_error(path, e))
+}
+
+pub fn copy(from: &Path, to: &Path) -> Result<(), ConversionError> {
+    check_write(to)?;
+    std::fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|e| ConversionError::io_error(from, e))
+}
+
+/// Replaces `link` with a hard link to `original`.
+pub fn hard_link(original: &Path, link: &Path) -> Result<(), ConversionError> {
+    remove(link)?;
+    std::fs::hard_link(original, link).map_err(|e| ConversionError::io_error(link, e))
+}
+
+/// Removes the file at `path`, if there is one.
+pub fn remove(path: &Path) -> Result<(), ConversionError> {
+    check_write(path)?;
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(ConversionError::io_error(path, e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A staging directory from [`tempdir`], removed when dropped.
+pub struct TempDir(tempfile::TempDir);
+
+impl TempDir {
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        TEMPDIRS.lock().unwrap().remove(self.0.path());
+    }
+}
+
+/// Creates a temporary staging directory. `context` is only used to label
+/// errors.
+pub fn tempdir(context: &Path) -> Result<TempDir, ConversionError> {
+    check_write(&std::env::temp_dir())?;
+    let dir = tempfile::tempdir().map_err(|e| ConversionError::io_error(context, e))?;
+    TEMPDIRS.lock().unwrap().insert(dir.path().to_path_buf());
+    Ok(TempDir(dir))
+}
+
+/// Removes every staging directory still in use, for a process about to
+/// exit without running destructors.
+pub fn remove_tempdirs() {
+    for dir in TEMPDIRS.lock().unwrap().iter() {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_leaves_other_hard_links_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let (original, link) = (dir.path().join("a.json"), dir.path().join("b.json"));
+        write(&original, "{}").unwrap();
+        hard_link(&original, &link).unwrap();
+
+        replace(&link, "[]").unwrap();
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "[]");
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "{}");
+        assert!(!partial_path(&link).exists());
+        assert_eq!(partial_path(&link), dir.path().join(".b.partial.json"));
+    }
+
+    /// Runs `op` under `--read-only` in a directory holding `a.json`, and
+    /// checks it fails without changing the directory.
+    fn assert_refused<T>(op: impl FnOnce(&Path) -> Result<T, ConversionError>) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.json"), "{}").unwrap();
+        let listing = |dir: &Path| {
+            let mut names = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        let before = listing(dir.path());
+
+        let result = with_read_only(|| op(dir.path()));
+
+        let message = result.err().map(|e| e.to_string()).unwrap_or_default();
+        assert!(message.contains("--read-only"), "{}", message);
+        assert_eq!(listing(dir.path()), before);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.json")).unwrap(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn test_read_only_refuses_write() {
+        assert_refused(|dir| write(&dir.join("a.json"), "[]"));
+        assert_refused(|dir| write(&dir.join("b.json"), "[]"));
+    }
+
+    #[test]
+    fn test_read_only_refuses_replace() {
+        assert_refused(|dir| replace(&dir.join("a.json"), "[]"));
+    }
+
+    #[test]
+    fn test_read_only_refuses_rename() {
+        assert_refused(|dir| rename(&dir.join("a.json"), &dir.join("b.json")));
+    }
+
+    #[test]
+    fn test_read_only_refuses_create() {
+        assert_refused(|dir| create(&dir.join("a.json")));
+        assert_refused(|dir| create(&dir.join("b.json")));
+    }
+
+    #[test]
+    fn test_read_only_refuses_append() {
+        assert_refused(|dir| append(&dir.join("a.json")));
+        assert_refused(|dir| append(&dir.join("b.log")));
+    }
+
+    #[test]
+    fn test_read_only_refuses_create_dir_all() {
+        assert_refused(|dir| create_dir_all(&dir.join("out/json")));
+    }
+
+    #[test]
+    fn test_read_only_refuses_copy() {
+        assert_refused(|dir| copy(&dir.join("a.json"), &dir.join("b.json")));
+    }
+
+    #[test]
+    fn test_read_only_refuses_hard_link() {
+        assert_refused(|dir| hard_link(&dir.join("a.json"), &dir.join("b.json")));
+    }
+
+    #[test]
+    fn test_read_only_refuses_remove() {
+        assert_refused(|dir| remove(&dir.join("a.json")));
+    }
+
+    #[test]
+    fn test_read_only_refuses_tempdir() {
+        assert_refused(tempdir);
+    }
+}
@@ -0,0 +1,28 @@
+//! Library side of `sysmon_cli`.
+//!
+//! The command-line tool is the main product. This crate root exposes the
+//! parts that are useful to other Rust programs working with Sysmon
+//! configurations. Preprocessing and batch progress read files through
+//! `sysmon_json`, so they are left out of wasm32 builds.
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod format;
+pub mod lint;
+pub mod model;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod preprocess;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod progress;
+pub mod repair;
+pub mod schema;
+#[cfg(feature = "ffi")]
+pub mod sysmon_json_ffi;
+pub mod validation;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
+
+pub use format::Format;
+pub use model::{convert_json_str_to_xml, convert_xml_str_to_json};
+#[cfg(not(target_arch = "wasm32"))]
+pub use preprocess::{Pipeline, Preprocessor};
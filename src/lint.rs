@@ -0,0 +1,211 @@
+//! Style and correctness warnings for configurations that are valid but
+//! probably not what the author meant.
+
+use crate::model::{FieldCondition, Filter, OnMatch, SysmonConfig};
+use crate::schema::Platform;
+use crate::validation::Issue;
+use std::collections::HashSet;
+
+/// Rule ids reported by [`lint`], with a one-line description each.
+pub const RULES: &[(&str, &str)] = &[
+    ("empty-rule-group", "Rule group contains no event filters"),
+    (
+        "duplicate-event-filter",
+        "Event filter repeated within one rule group",
+    ),
+    (
+        "catch-all-exclude",
+        "Empty exclude filter logs every event of its type",
+    ),
+    (
+        "empty-include",
+        "Empty include filter logs no events of its type",
+    ),
+    (
+        "duplicate-condition",
+        "Identical field condition repeated in one filter",
+    ),
+    ("empty-rule", "Rule element contains no field conditions"),
+    (
+        "mixed-platform",
+        "Config combines Windows-only rules with Linux paths",
+    ),
+];
+
+pub fn lint(config: &SysmonConfig) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for (index, group) in config.rule_groups.iter().enumerate() {
+        let group_location = format!("RuleGroup[{}]", index + 1);
+        if group.events.is_empty() {
+            issues.push(Issue::warning(
+                "empty-rule-group",
+                group_location.clone(),
+                "Rule group has no event filters",
+            ));
+        }
+
+        let mut seen_events = HashSet::new();
+        for event in &group.events {
+            let location = format!("{}/{}", group_location, event.event);
+            if !seen_events.insert((event.event.as_str(), event.onmatch)) {
+                issues.push(Issue::warning(
+                    "duplicate-event-filter",
+                    location.clone(),
+                    format!(
+                        "{} onmatch=\"{}\" appears more than once in the same rule group",
+                        event.event,
+                        event.onmatch.as_str()
+                    ),
+                ));
+            }
+
+            if event.filters.is_empty() {
+                issues.push(match event.onmatch {
+                    OnMatch::Exclude => Issue::warning(
+                        "catch-all-exclude",
+                        location.clone(),
+                        format!("Empty exclude filter logs every {} event", event.event),
+                    ),
+                    OnMatch::Include => Issue::warning(
+                        "empty-include",
+                        location.clone(),
+                        format!(
+                            "Empty include filter logs no {} events; \
+                             use onmatch=\"exclude\" to log them all",
+                            event.event
+                        ),
+                    ),
+                });
+            }
+
+            let mut seen_conditions = HashSet::new();
+            for filter in &event.filters {
+                match filter {
+                    Filter::Field(field) => {
+                        if !seen_conditions.insert((&field.field, field.condition, &field.value)) {
+                            issues.push(Issue::warning(
+                                "duplicate-condition",
+                                format!("{}/{}", location, field.field),
+                                format!(
+                                    "Duplicate condition {} {} \"{}\"",
+                                    field.field, field.condition, field.value
+                                ),
+                            ));
+                        }
+                    }
+                    Filter::Rule(rule) if rule.fields.is_empty() => issues.push(Issue::warning(
+                        "empty-rule",
+                        format!("{}/Rule", location),
+                        "Rule has no field conditions",
+                    )),
+                    Filter::Rule(_) => {}
+                }
+            }
+        }
+    }
+
+    if let (Some(windows), Some(linux)) = platform_evidence(config) {
+        issues.push(Issue::warning(
+            "mixed-platform",
+            "Sysmon",
+            format!(
+                "Config mixes platforms: {} is Windows-only but {} is a Linux path",
+                windows, linux
+            ),
+        ));
+    }
+    issues
+}
+
+/// The first sign that `config` targets Windows (a Windows-only event type
+/// or a Windows path) and the first Linux path, if any.
+fn platform_evidence(config: &SysmonConfig) -> (Option<String>, Option<String>) {
+    let mut windows = None;
+    let mut linux = None;
+    for event in config.events() {
+        if windows.is_none() && !Platform::Linux.supports(&event.event) {
+            windows = Some(event.event.clone());
+        }
+        let fields = event.filters.iter().flat_map(|filter| match filter {
+            Filter::Field(field) => std::slice::from_ref(field),
+            Filter::Rule(rule) => rule.fields.as_slice(),
+        });
+        for field in fields.filter(|f| is_path_field(f)) {
+            let value = &field.value;
+            let described = || format!("{}/{} \"{}\"", event.event, field.field, value);
+            if windows.is_none() && (value.contains('\\') || value.get(1..3) == Some(":\\")) {
+                windows = Some(described());
+            }
+            if linux.is_none() && value.starts_with('/') && value[1..].contains('/') {
+                linux = Some(described());
+            }
+        }
+    }
+    (windows, linux)
+}
+
+fn is_path_field(field: &FieldCondition) -> bool {
+    ["Image", "Filename", "Directory", "ImageLoaded"]
+        .iter()
+        .any(|suffix| field.field.ends_with(suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_duplicates_and_catch_all_excludes() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+                <RuleGroup name="" groupRelation="or">
+                    <ProcessCreate onmatch="include">
+                        <Image condition="end with">\cmd.exe</Image>
+                        <Image condition="end with">\cmd.exe</Image>
+                    </ProcessCreate>
+                    <NetworkConnect onmatch="exclude"/>
+                    <DnsQuery onmatch="include"/>
+                </RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let messages: Vec<String> = lint(&config).into_iter().map(|i| i.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Duplicate condition Image end with \"\\cmd.exe\"".to_string(),
+                "Empty exclude filter logs every NetworkConnect event".to_string(),
+                "Empty include filter logs no DnsQuery events; \
+                 use onmatch=\"exclude\" to log them all"
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flags_mixed_platforms() {
+        let config = |extra: &str| {
+            SysmonConfig::from_xml_str(&format!(
+                r#"<Sysmon schemaversion="4.81"><EventFiltering><RuleGroup groupRelation="or">
+                    <ProcessCreate onmatch="include"><Image condition="is">/usr/bin/curl</Image></ProcessCreate>
+                    {}
+                </RuleGroup></EventFiltering></Sysmon>"#,
+                extra
+            ))
+            .unwrap()
+        };
+
+        assert!(lint(&config("")).is_empty());
+        let issues = lint(&config(
+            r#"<RegistryEvent onmatch="include"><TargetObject condition="contains">\Run\</TargetObject></RegistryEvent>"#,
+        ));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].message,
+            "Config mixes platforms: RegistryEvent is Windows-only but \
+             ProcessCreate/Image \"/usr/bin/curl\" is a Linux path"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! `dump-live` (Windows only): capture the configuration Sysmon is running
+//! with.
+//!
+//! `sysmon -c` prints the active rules in a report format that cannot be
+//! read back, so the rules come from the registry instead: the driver's
+//! `Parameters` key is exported with `reg export` and decoded as in
+//! `decode-rules`, with the schema taken from the installed Sysmon's
+//! `sysmon -s`. The result is checked with the same structural checks as
+//! `validate` before it is written. Sysmon restricts access to the key, so
+//! this needs an elevated prompt.
+
+use crate::io_guard;
+use crate::rules_blob::{self, Schema};
+use clap::Args;
+use log::{info, warn};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::validation::{self, Severity};
+use sysmon_json::error::ConversionError;
+
+pub const DEFAULT_DRIVER: &str = "SysmonDrv";
+
+/// Executables tried, in order, when `--sysmon` is not given.
+const SYSMON_EXECUTABLES: &[&str] = &["sysmon64.exe", "sysmon.exe"];
+
+#[derive(Args)]
+pub struct DumpLiveArgs {
+    /// Sysmon executable (default: sysmon64.exe, then sysmon.exe, from PATH)
+    #[arg(long)]
+    pub sysmon: Option<PathBuf>,
+
+    /// Name of the Sysmon driver service
+    #[arg(long, default_value = DEFAULT_DRIVER)]
+    pub driver: String,
+
+    /// Output file; `.json` writes JSON, anything else XML (default: XML on stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn run(args: &DumpLiveArgs) -> Result<(), ConversionError> {
+    let config = capture(&find_sysmon(args.sysmon.as_deref())?, &args.driver)?;
+    info!(
+        "Captured {} event filter(s) from the running Sysmon",
+        config.events().count()
+    );
+
+    let issues = validation::validate(&config);
+    for issue in &issues {
+        warn!("{}: {}", issue.location, issue.message);
+    }
+    rules_blob::write_config(&config, args.output.as_deref())?;
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(ConversionError::ValidationError(format!(
+            "The live config has {} error(s)",
+            errors
+        )));
+    }
+    Ok(())
+}
+
+/// Decodes the rules the driver `driver` is running with.
+pub fn capture(sysmon: &Path, driver: &str) -> Result<SysmonConfig, ConversionError> {
+    let schema = Schema::parse(&run_sysmon(sysmon, &["-s".as_ref()])?)?;
+    rules_blob::decode_input(&export_parameters(driver)?, &schema)
+}
+
+/// `sysmon`, or the first of [`SYSMON_EXECUTABLES`] found on `PATH`.
+pub fn find_sysmon(sysmon: Option<&Path>) -> Result<PathBuf, ConversionError> {
+    if let Some(path) = sysmon {
+        return Ok(path.to_path_buf());
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    SYSMON_EXECUTABLES
+        .iter()
+        .flat_map(|exe| std::env::split_paths(&path).map(move |dir| dir.join(exe)))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| {
+            ConversionError::Other("Sysmon not found on PATH; pass --sysmon".to_string())
+        })
+}
+
+/// Runs Sysmon with `args` and returns its output.
+pub fn run_sysmon(sysmon: &Path, args: &[&OsStr]) -> Result<String, ConversionError> {
+    let output = Command::new(sysmon)
+        .arg("-accepteula")
+        .args(args)
+        .output()
+        .map_err(|e| ConversionError::io_error(sysmon, e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        return Err(ConversionError::Other(format!(
+            "{} {} failed: {}",
+            sysmon.display(),
+            args.iter()
+                .map(|a| a.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            [
+                stdout.trim(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ]
+            .join(" ")
+            .trim()
+        )));
+    }
+    Ok(stdout)
+}
+
+/// Exports the driver's `Parameters` key and returns the `.reg` file.
+pub fn export_parameters(driver: &str) -> Result<Vec<u8>, ConversionError> {
+    let key = format!(
+        r"HKLM\SYSTEM\CurrentControlSet\Services\{}\Parameters",
+        driver
+    );
+    let dir = io_guard::tempdir(Path::new(&key))?;
+    let file = dir.path().join("parameters.reg");
+
+    let output = Command::new("reg")
+        .args(["export", &key])
+        .arg(&file)
+        .arg("/y")
+        .output()
+        .map_err(|e| ConversionError::io_error(Path::new("reg"), e))?;
+    if !output.status.success() {
+        return Err(ConversionError::Other(format!(
+            "Could not export {} (is Sysmon installed, and is this prompt elevated?): {}",
+            key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    std::fs::read(&file).map_err(|e| ConversionError::io_error(&file, e))
+}
+
+/// Imports a `.reg` file from [`export_parameters`], putting the driver's
+/// parameters back as they were; Sysmon reloads its rules when they change.
+pub fn import_parameters(file: &Path) -> Result<(), ConversionError> {
+    io_guard::check_write(file)?;
+    let output = Command::new("reg")
+        .arg("import")
+        .arg(file)
+        .output()
+        .map_err(|e| ConversionError::io_error(Path::new("reg"), e))?;
+    if !output.status.success() {
+        return Err(ConversionError::Other(format!(
+            "Could not import {}: {}",
+            file.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,361 @@
+//! Logger setup.
+//!
+//! The console level comes from `-v`/`-q` and `--debug <module>`, falling
+//! back to `RUST_LOG` (or `info`) when none of them are given.
+//!
+//! Text output is env_logger's default. `--log-format json` writes one JSON
+//! object per record instead, with the timestamp, level, target, source
+//! location and message, plus any structured fields the call site attached
+//! (for example `error!(kind = "io"; ...)`).
+//!
+//! `--log-file` additionally keeps a persistent log in the same format. The
+//! file always records `info` and above, whatever the console shows, and is
+//! rotated by size: `run.log` moves to `run.log.1`, `run.log.1` to
+//! `run.log.2`, and so on, with the oldest beyond the kept count deleted.
+
+use crate::io_guard;
+use clap::ValueEnum;
+use log::kv::{self, Key, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Console verbosity from the command line.
+#[derive(Debug, Default)]
+pub struct Verbosity {
+    /// `-v` count: debug for this tool and `sysmon_json`, then debug for
+    /// everything, then trace for everything.
+    pub verbose: u8,
+    /// `-q` count: warnings only, then errors only, then nothing.
+    pub quiet: u8,
+    /// Modules to log at debug level, e.g. `merger` or `sysmon_cli::batch`.
+    pub debug: Vec<String>,
+}
+
+impl Verbosity {
+    /// env_logger filter directives for these flags. `rust_log` is used
+    /// only when no flag is given.
+    fn directives(&self, rust_log: Option<String>) -> String {
+        let mut directives = match (self.verbose, self.quiet) {
+            (0, 0) => rust_log.unwrap_or_else(|| "info".to_string()),
+            (0, 1) => "warn".to_string(),
+            (0, 2) => "error".to_string(),
+            (0, _) => "off".to_string(),
+            (1, _) => "info,sysmon_cli=debug,sysmon_json=debug".to_string(),
+            (2, _) => "debug".to_string(),
+            _ => "trace".to_string(),
+        };
+        for module in &self.debug {
+            if module.contains("::") || module == "sysmon_cli" || module == "sysmon_json" {
+                directives.push_str(&format!(",{}=debug", module));
+            } else {
+                // Bare names may refer to a module of this tool or of the
+                // library; a directive for a module that does not exist is
+                // harmless.
+                directives.push_str(&format!(
+                    ",sysmon_cli::{0}=debug,sysmon_json::{0}=debug",
+                    module
+                ));
+            }
+        }
+        directives
+    }
+}
+
+/// Settings for `--log-file`.
+pub struct LogFile {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    /// Number of rotated files to keep next to the live one.
+    pub keep: usize,
+}
+
+pub fn init(
+    format: LogFormat,
+    verbosity: &Verbosity,
+    log_file: Option<LogFile>,
+) -> Result<(), ConversionError> {
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(&verbosity.directives(std::env::var("RUST_LOG").ok()));
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json_line(&buf.timestamp_millis().to_string(), record);
+            writeln!(buf, "{}", line)
+        });
+    }
+    let console = builder.build();
+
+    let Some(log_file) = log_file else {
+        log::set_max_level(console.filter());
+        return log::set_boxed_logger(Box::new(console))
+            .map_err(|e| ConversionError::Other(e.to_string()));
+    };
+
+    let file_level = console.filter().max(LevelFilter::Info);
+    log::set_max_level(file_level);
+    let logger = Tee {
+        console,
+        file: Mutex::new(RotatingFile::open(log_file)?),
+        file_level,
+        format,
+    };
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| ConversionError::Other(e.to_string()))
+}
+
+/// Sends each record to the console logger, subject to its filters, and to
+/// the log file.
+struct Tee {
+    console: env_logger::Logger,
+    file: Mutex<RotatingFile>,
+    file_level: LevelFilter,
+    format: LogFormat,
+}
+
+impl Log for Tee {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.file_level || self.console.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+        if record.level() <= self.file_level {
+            let timestamp = timestamp(SystemTime::now());
+            let line = match self.format {
+                LogFormat::Text => text_line(&timestamp, record),
+                LogFormat::Json => json_line(&timestamp, record),
+            };
+            let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = file.write_line(&line) {
+                eprintln!("Failed to write log file {}: {}", file.path.display(), e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        let _ = self
+            .file
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .file
+            .flush();
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    fn open(settings: LogFile) -> Result<Self, ConversionError> {
+        let file = io_guard::append(&settings.path)?;
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(RotatingFile {
+            path: settings.path,
+            max_bytes: settings.max_bytes,
+            keep: settings.keep,
+            file,
+            len,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let size = line.len() as u64 + 1;
+        if self.len > 0 && self.len + size > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.len += size;
+        Ok(())
+    }
+
+    // `--read-only` has already been checked when the file was opened.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.keep > 0 {
+            remove_if_exists(&rotated(&self.path, self.keep))?;
+            for n in (1..self.keep).rev() {
+                let from = rotated(&self.path, n);
+                if from.exists() {
+                    std::fs::rename(&from, rotated(&self.path, n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// RFC 3339 UTC timestamp with milliseconds, as env_logger prints them.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+fn text_line(timestamp: &str, record: &Record) -> String {
+    format!(
+        "[{} {:<5} {}] {}",
+        timestamp,
+        record.level(),
+        record.target(),
+        record.args()
+    )
+}
+
+fn json_line(timestamp: &str, record: &Record) -> String {
+    let mut fields = Map::new();
+    fields.insert("timestamp".to_string(), timestamp.into());
+    fields.insert("level".to_string(), record.level().as_str().into());
+    fields.insert("target".to_string(), record.target().into());
+    if let Some(file) = record.file() {
+        let location = match record.line() {
+            Some(line) => format!("{}:{}", file, line),
+            None => file.to_string(),
+        };
+        fields.insert("file".to_string(), location.into());
+    }
+    fields.insert("message".to_string(), record.args().to_string().into());
+    let _ = record.key_values().visit(&mut Fields(&mut fields));
+    Value::Object(fields).to_string()
+}
+
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0
+            .insert(key.as_str().to_string(), value.to_string().into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_verbosity_directives() {
+        let flags = |verbose, quiet, debug: &[&str]| Verbosity {
+            verbose,
+            quiet,
+            debug: debug.iter().map(|m| m.to_string()).collect(),
+        };
+        let env = || Some("warn,sysmon_cli::batch=trace".to_string());
+
+        assert_eq!(flags(0, 0, &[]).directives(None), "info");
+        assert_eq!(
+            flags(0, 0, &[]).directives(env()),
+            "warn,sysmon_cli::batch=trace"
+        );
+        assert_eq!(flags(0, 2, &[]).directives(env()), "error");
+        assert_eq!(flags(3, 0, &[]).directives(None), "trace");
+        assert_eq!(
+            flags(0, 1, &["merger", "sysmon_cli::batch"]).directives(None),
+            "warn,sysmon_cli::merger=debug,sysmon_json::merger=debug,sysmon_cli::batch=debug"
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        assert_eq!(timestamp(time), "2023-11-14T22:13:20.123Z");
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_rotation_keeps_bounded_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.log");
+        let mut file = RotatingFile::open(LogFile {
+            path: path.clone(),
+            max_bytes: 10,
+            keep: 2,
+        })
+        .unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+
+        let read = |p: PathBuf| std::fs::read_to_string(p).unwrap();
+        assert_eq!(read(path.clone()), "fourth\n");
+        assert_eq!(read(rotated(&path, 1)), "third\n");
+        assert_eq!(read(rotated(&path, 2)), "second\n");
+        assert!(!rotated(&path, 3).exists());
+    }
+
+    #[test]
+    fn test_json_line_includes_fields() {
+        let line = json_line(
+            "2025-01-01T00:00:00.000Z",
+            &Record::builder()
+                .args(format_args!("Error: bad \"input\""))
+                .level(log::Level::Error)
+                .target("sysmon_cli")
+                .file(Some("src/main.rs"))
+                .line(Some(7))
+                .key_values(&[("kind", "io")])
+                .build(),
+        );
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "ERROR");
+        assert_eq!(value["file"], "src/main.rs:7");
+        assert_eq!(value["message"], "Error: bad \"input\"");
+        assert_eq!(value["kind"], "io");
+    }
+}
@@ -1,86 +1,708 @@
-use clap::Parser;
-use env_logger;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use log::{error, info, warn};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use sysmon_json::{
-    convert_file, 
-    BatchProcessor,
     ProcessingOptions,
     ProcessingOptionsBuilder,
-    merger::merge_configs,
-    error::{ConversionError, PreprocessError},
+    error::ConversionError,
     preprocessor::preprocess_config,
 };
-use sysmon_json::batch::ProgressReporter;
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::progress;
+use sysmon_cli::schema::Platform;
+use sysmon_cli::{format, preprocess, repair};
+
+mod analyze;
+mod archive;
+mod attack;
+mod batch;
+mod bench;
+mod build;
+mod cancel;
+mod changelog;
+mod check;
+mod checkpoint;
+mod compare;
+mod compression;
+mod convert;
+mod coverage;
+#[cfg(windows)]
+mod deploy;
+mod deploy_script;
+mod document;
+mod drift;
+mod edit;
+mod encoding;
+mod error_report;
+mod evtx;
+mod explain;
+mod field_map;
+mod file_list;
+mod fix;
+mod fleet;
+mod from_sigma;
+mod incremental;
+mod include;
+mod init;
+mod inventory;
+mod io_guard;
+#[cfg(windows)]
+mod live;
+mod logging;
+mod mangen;
+mod merge;
+mod metrics;
+mod minify;
+mod patch;
+mod policy;
+mod posture;
+mod pretty;
+mod provenance;
+mod query;
+mod redact;
+mod replay;
+mod rules_blob;
+mod sarif;
+mod secrets;
+mod serve;
+mod sigma;
+mod sigma_coverage;
+mod since;
+mod stats;
+mod suppress;
+mod template;
+#[cfg(feature = "tui")]
+mod tui;
+mod user_config;
+mod validate;
+mod vars;
+mod visualize;
+mod volume;
+mod walk;
+mod xpath;
+mod xsd;
+mod yaml;
 
 /// CLI tool for converting Sysmon configurations between XML and JSON formats
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    after_long_help = mangen::EXAMPLES
+)]
 struct Cli {
-    /// Input file or directory path
-    #[arg(short, long, value_parser = clap::value_parser!(PathBuf))]
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input file or directory path (can be specified multiple times)
+    #[arg(
+        short,
+        long,
+        required_unless_present_any = ["list_preprocessors", "files_from"],
+        value_parser = clap::value_parser!(PathBuf),
+        env = "SYSMON_HELPER_INPUT"
+    )]
+    input: Vec<PathBuf>,
 
-    /// Output file or directory path
-    #[arg(short, long, value_parser = clap::value_parser!(PathBuf))]
+    /// Output file or directory path; with several inputs, the directory their outputs go in
+    #[arg(short, long, value_parser = clap::value_parser!(PathBuf), env = "SYSMON_HELPER_OUTPUT")]
     output: Option<PathBuf>,
 
+    /// With several inputs, the `--output` directory: each input's default
+    /// output goes inside it instead of next to the input.
+    #[arg(skip)]
+    output_dir: Option<PathBuf>,
+
     /// Process directories recursively
-    #[arg(short, long)]
+    #[arg(short, long, env = "SYSMON_HELPER_RECURSIVE")]
     recursive: bool,
 
     /// Process input as a directory containing multiple files
-    #[arg(short, long)]
+    #[arg(short, long, env = "SYSMON_HELPER_BATCH")]
     batch: bool,
 
     /// Merge all Sysmon configs in the input directory into a single file
-    #[arg(short, long)]
+    #[arg(short, long, env = "SYSMON_HELPER_MERGE")]
     merge: bool,
 
+    /// Platform the merged config is for: windows or linux (drops Windows-only events)
+    #[arg(long, default_value = "windows", requires = "merge", env = "SYSMON_HELPER_PLATFORM")]
+    platform: Platform,
+
+    /// How to settle merge conflicts (the same rule included and excluded, or under two
+    /// names); by default asked on a terminal, otherwise both rules are kept
+    #[arg(long, value_enum, requires = "merge", env = "SYSMON_HELPER_STRATEGY")]
+    strategy: Option<merge::Resolution>,
+
+    /// Base config whose root, schema version and global options the merged rules are put into
+    #[arg(long, value_name = "PATH", requires = "merge", env = "SYSMON_HELPER_TEMPLATE")]
+    template: Option<PathBuf>,
+
+    /// Combine merged rule groups of the same event type, onmatch and relation into one
+    #[arg(long, requires = "merge", env = "SYSMON_HELPER_COALESCE_RULEGROUPS")]
+    coalesce_rulegroups: bool,
+
+    /// Let a named rule replace earlier sources' rules of the same name and event type
+    #[arg(long, requires = "merge", env = "SYSMON_HELPER_OVERRIDE_BY_NAME")]
+    override_by_name: bool,
+
+    /// Tuning file of rules to drop from the merged config, by ID, name or field and value
+    #[arg(long, value_name = "FILE", requires = "merge", env = "SYSMON_HELPER_SUPPRESS")]
+    suppress: Option<PathBuf>,
+
+    /// Split the merged config into standalone parts of at most this many KB
+    #[arg(long, value_name = "KB", requires = "merge", env = "SYSMON_HELPER_MAX_OUTPUT_KB")]
+    max_output_kb: Option<u64>,
+
+    /// Write a JSON manifest of the tool version, git commit, options, and input and output hashes
+    #[arg(long, value_name = "PATH", requires = "merge", env = "SYSMON_HELPER_MANIFEST")]
+    manifest: Option<PathBuf>,
+
     /// Maximum file size in MB
-    #[arg(long, default_value = "10")]
+    #[arg(long, default_value = "10", env = "SYSMON_HELPER_MAX_SIZE")]
     max_size: u64,
 
-    /// Maximum recursion depth
-    #[arg(long, default_value = "10")]
+    /// Skip batch inputs smaller than this, e.g. 1 for empty placeholders (bytes, or K/M/G)
+    #[arg(long, value_name = "SIZE", env = "SYSMON_HELPER_MIN_SIZE")]
+    min_size: Option<batch::ByteSize>,
+
+    /// Skip batch inputs larger than this instead of failing them (bytes, or K/M/G)
+    #[arg(long, value_name = "SIZE", env = "SYSMON_HELPER_MAX_SIZE_SKIP")]
+    max_size_skip: Option<batch::ByteSize>,
+
+    /// Maximum recursion depth, for directories and for nested <?include?> directives
+    #[arg(long, default_value = "10", env = "SYSMON_HELPER_MAX_DEPTH")]
     max_depth: u32,
 
+    /// Let <?include?> directives name files outside the including input's directory
+    #[arg(long, env = "SYSMON_HELPER_ALLOW_OUTSIDE_INCLUDES")]
+    allow_outside_includes: bool,
+
+    /// Stop a batch at the first file that fails to convert
+    #[arg(long, conflicts_with = "max_errors", env = "SYSMON_HELPER_FAIL_FAST")]
+    fail_fast: bool,
+
+    /// Stop a batch once this many files have failed to convert
+    #[arg(long, value_name = "N", env = "SYSMON_HELPER_MAX_ERRORS")]
+    max_errors: Option<usize>,
+
+    /// Cap on memory held by batch conversions in flight, in MB; larger files are rejected
+    #[arg(long, value_name = "MB", env = "SYSMON_HELPER_MAX_MEMORY_MB")]
+    max_memory_mb: Option<u64>,
+
+    /// Follow symbolic links when walking directories, skipping loops and duplicates
+    #[arg(long, overrides_with = "no_follow_symlinks", env = "SYSMON_HELPER_FOLLOW_SYMLINKS")]
+    follow_symlinks: bool,
+
+    /// Skip symbolic links when walking directories (the default)
+    #[arg(
+        long,
+        overrides_with = "follow_symlinks",
+        env = "SYSMON_HELPER_NO_FOLLOW_SYMLINKS"
+    )]
+    no_follow_symlinks: bool,
+
+    /// Convert the files listed in this file (one per line, `-` for stdin) instead of walking
+    /// the input directory
+    #[arg(long, value_name = "PATH", conflicts_with = "merge", env = "SYSMON_HELPER_FILES_FROM")]
+    files_from: Option<PathBuf>,
+
+    /// Skip batch inputs that are unchanged since the last --incremental run
+    #[arg(long, conflicts_with = "check", env = "SYSMON_HELPER_INCREMENTAL")]
+    incremental: bool,
+
+    /// Continue an interrupted batch run from its checkpoint
+    #[arg(long, conflicts_with = "check", env = "SYSMON_HELPER_RESUME")]
+    resume: bool,
+
+    /// Only convert batch inputs modified since a duration ago (36h, 7d) or a UTC time
+    #[arg(long, value_name = "WHEN", env = "SYSMON_HELPER_MODIFIED_SINCE")]
+    modified_since: Option<since::ModifiedSince>,
+
+    /// Give up on a batch file after this many seconds and record it as failed
+    #[arg(long, value_name = "SECS", env = "SYSMON_HELPER_TIMEOUT_SECS")]
+    timeout_secs: Option<u64>,
+
     /// Number of worker threads (default: number of CPU cores)
-    #[arg(long)]
+    #[arg(long, env = "SYSMON_HELPER_WORKERS")]
     workers: Option<usize>,
 
     /// Verify output after conversion
-    #[arg(long)]
+    #[arg(long, env = "SYSMON_HELPER_VERIFY")]
     verify: bool,
 
     /// Suppress progress output
-    #[arg(long)]
+    #[arg(long, env = "SYSMON_HELPER_SILENT")]
     silent: bool,
 
     /// Create backups of existing files
-    #[arg(long)]
+    #[arg(long, env = "SYSMON_HELPER_BACKUP")]
     backup: bool,
 
     /// Pattern to ignore (can be specified multiple times)
-    #[arg(long = "ignore")]
+    #[arg(long = "ignore", env = "SYSMON_HELPER_IGNORE")]
     ignore_patterns: Vec<String>,
 
-    #[arg(long)]
+    #[arg(long, env = "SYSMON_HELPER_SKIP_PREPROCESSING")]
     skip_preprocessing: bool,
+
+    /// Run only these preprocessing steps, in pipeline order (comma separated)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with = "skip_preprocessing",
+        env = "SYSMON_HELPER_PREPROCESS_ONLY"
+    )]
+    preprocess_only: Option<Vec<String>>,
+
+    /// TOML file with additional regex rewrite rules for preprocessing
+    #[arg(long, env = "SYSMON_HELPER_PREPROCESS_RULES")]
+    preprocess_rules: Option<PathBuf>,
+
+    /// Value for a ${KEY} placeholder in the input (repeatable or comma separated)
+    #[arg(
+        long = "var",
+        value_name = "KEY=VALUE",
+        value_delimiter = ',',
+        env = "SYSMON_HELPER_VAR"
+    )]
+    vars: Vec<String>,
+
+    /// TOML file of placeholder values; --var takes precedence, and the environment is the
+    /// fallback for names in neither
+    #[arg(long, value_name = "PATH", env = "SYSMON_HELPER_VARS_FILE")]
+    vars_file: Option<PathBuf>,
+
+    /// List the available preprocessing steps and exit
+    #[arg(long, env = "SYSMON_HELPER_LIST_PREPROCESSORS")]
+    list_preprocessors: bool,
+
+    /// Write all batch outputs directly into the output directory
+    #[arg(long, env = "SYSMON_HELPER_FLATTEN")]
+    flatten: bool,
+
+    /// Batch output path relative to the output directory, e.g. "{relpath}/{stem}.{ext}";
+    /// placeholders: {relpath}, {stem}, {ext}, {format}, {schema}, {event}
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with = "flatten",
+        env = "SYSMON_HELPER_OUTPUT_TEMPLATE"
+    )]
+    output_template: Option<template::OutputTemplate>,
+
+    /// How to resolve batch outputs that would share a path
+    #[arg(long, value_enum, default_value = "error", env = "SYSMON_HELPER_ON_COLLISION")]
+    on_collision: batch::CollisionPolicy,
+
+    /// Hard-link (link) or only list (record) batch outputs identical to one already written
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "link",
+        env = "SYSMON_HELPER_DEDUPE_OUTPUTS"
+    )]
+    dedupe_outputs: Option<batch::DedupeMode>,
+
+    /// Order to start batch files in: input-order, largest-first or slowest-first
+    #[arg(long, value_enum, default_value = "input-order", env = "SYSMON_HELPER_SCHEDULE")]
+    schedule: batch::Schedule,
+
+    /// Input format, overriding detection from the file content
+    #[arg(long, value_enum, env = "SYSMON_HELPER_FROM")]
+    from: Option<format::Format>,
+
+    /// Repair recoverable XML problems instead of failing, and report each fix
+    #[arg(long, env = "SYSMON_HELPER_LENIENT")]
+    lenient: bool,
+
+    /// Write the smallest equivalent output: no comments, whitespace or default attributes
+    #[arg(long, env = "SYSMON_HELPER_MINIFY")]
+    minify: bool,
+
+    /// Re-indent output with this many indent characters per level
+    #[arg(long, conflicts_with = "minify", env = "SYSMON_HELPER_INDENT")]
+    indent: Option<usize>,
+
+    /// Character to indent output with [default: space]
+    #[arg(long, value_enum, conflicts_with = "minify", env = "SYSMON_HELPER_INDENT_CHAR")]
+    indent_char: Option<pretty::IndentChar>,
+
+    /// Order of JSON keys and XML attributes in output [default: preserve]
+    #[arg(long, value_enum, conflicts_with = "minify", env = "SYSMON_HELPER_KEY_ORDER")]
+    key_order: Option<pretty::KeyOrder>,
+
+    /// Put each attribute of an XML element with several on its own line
+    #[arg(long, conflicts_with = "minify", env = "SYSMON_HELPER_ATTRIBUTE_PER_LINE")]
+    attribute_per_line: bool,
+
+    /// Text encoding of the converted output
+    #[arg(long, value_enum, env = "SYSMON_HELPER_OUTPUT_ENCODING")]
+    output_encoding: Option<encoding::Encoding>,
+
+    /// Never write to the filesystem; report what would happen on stdout
+    #[arg(long, global = true, env = "SYSMON_HELPER_READ_ONLY")]
+    read_only: bool,
+
+    /// Write nothing; fail if any output on disk differs from what a run would produce
+    #[arg(long, conflicts_with = "read_only", env = "SYSMON_HELPER_CHECK")]
+    check: bool,
+
+    /// More log detail: -v debug for this tool, -vv debug for everything, -vvv trace
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        global = true,
+        conflicts_with = "quiet",
+        env = "SYSMON_HELPER_VERBOSE"
+    )]
+    verbose: u8,
+
+    /// Less log output: -q warnings only, -qq errors only, -qqq nothing
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        global = true,
+        env = "SYSMON_HELPER_QUIET"
+    )]
+    quiet: u8,
+
+    /// Log these modules at debug level, e.g. `merger` or `preprocess` (comma separated)
+    #[arg(long, value_delimiter = ',', global = true, env = "SYSMON_HELPER_DEBUG")]
+    debug: Vec<String>,
+
+    /// Config file with option defaults [default: ~/.config/sysmon-helper/config.toml]
+    #[arg(long, global = true, env = "SYSMON_HELPER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Format of the error printed when the run fails
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value = "text",
+        env = "SYSMON_HELPER_ERROR_FORMAT"
+    )]
+    error_format: error_report::ErrorFormat,
+
+    /// Log output format
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value = "text",
+        env = "SYSMON_HELPER_LOG_FORMAT"
+    )]
+    log_format: logging::LogFormat,
+
+    /// Also write the log to this file, independent of the console
+    #[arg(long, global = true, env = "SYSMON_HELPER_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// Size in MB at which the log file is rotated
+    #[arg(long, global = true, default_value = "10", env = "SYSMON_HELPER_LOG_FILE_MAX_MB")]
+    log_file_max_mb: u64,
+
+    /// Number of rotated log files to keep
+    #[arg(long, global = true, default_value = "5", env = "SYSMON_HELPER_LOG_FILE_KEEP")]
+    log_file_keep: usize,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Add a rule to a config, creating its event filter or rule group if needed
+    AddRule(edit::AddRuleArgs),
+    /// Estimate a config's endpoint overhead and rank its most expensive rules
+    Analyze(analyze::AnalyzeArgs),
+    /// Time conversion or merging over a fixture set: throughput, latency percentiles, peak RSS
+    Bench(bench::BenchArgs),
+    /// Build every config a YAML build matrix declares, each validated, hashed and listed
+    /// in a build manifest
+    Build(build::BuildArgs),
+    /// Write a Markdown changelog of the rules added, removed and modified between two
+    /// versions of a config
+    Changelog(changelog::ChangelogArgs),
+    /// Compare a config with a community baseline such as SwiftOnSecurity's, listing the
+    /// baseline rules it is missing and the extras it carries
+    Compare(compare::CompareArgs),
+    /// Report the ATT&CK techniques and event filters that gained or lost rules between
+    /// two versions of a config
+    CoverageDiff(coverage::CoverageDiffArgs),
+    /// Recover a config from the binary rule blob Sysmon stores in the registry
+    DecodeRules(rules_blob::DecodeRulesArgs),
+    /// Validate a config and apply it with sysmon -c, rolling back on failure
+    #[cfg(windows)]
+    Deploy(deploy::DeployArgs),
+    /// Compare a config with a golden baseline, rule by rule
+    Drift(drift::DriftArgs),
+    /// Capture, decode and check the config the local Sysmon is running with
+    #[cfg(windows)]
+    DumpLive(live::DumpLiveArgs),
+    /// Project events/day under a candidate config by replaying sampled Sysmon event logs
+    EstimateVolume(volume::EstimateVolumeArgs),
+    /// Describe an event type or rule in plain English
+    Explain(explain::ExplainArgs),
+    /// Generate artifacts from a config, such as a deployment script
+    Export(deploy_script::ExportArgs),
+    /// Rewrite rule names to one technique naming convention and report retired ATT&CK IDs
+    Fix(fix::FixArgs),
+    /// Build per-Sysmon-version config variants for a mixed fleet
+    FleetBuild(fleet::FleetBuildArgs),
+    /// Generate a starter config logging the events and fields a set of Sigma rules need
+    FromSigma(from_sigma::FromSigmaArgs),
+    /// Scaffold a modular rules repository: event-type folders, base template, merge manifest
+    Init(init::InitArgs),
+    /// List what each file detects, or with --cves which CVEs are covered and where
+    Inventory(inventory::InventoryArgs),
+    /// Apply a patch file of rule additions, removals and replacements to a config
+    Patch(patch::PatchArgs),
+    /// Summarize what each event type logs: include-list, exclude-list, everything or nothing
+    Posture(posture::PostureArgs),
+    /// Print the rules matching an expression, across one config or a directory of them
+    Query(query::QueryArgs),
+    /// Replace internal hostnames, IP ranges, user names and share paths with placeholders
+    Redact(redact::RedactArgs),
+    /// Remove the rules matching a name, field and value, or query expression
+    RemoveRule(edit::RemoveRuleArgs),
+    /// Run an HTTP service with convert, validate, lint and merge endpoints
+    Serve(serve::ServeArgs),
+    /// Check which Sigma rules a config leaves blind by not logging the events they need
+    SigmaCoverage(sigma_coverage::SigmaCoverageArgs),
+    /// Print rule counts, field and operator use, and technique coverage for a config
+    Stats(stats::StatsArgs),
+    /// Browse a config in an interactive terminal tree with fuzzy search
+    #[cfg(feature = "tui")]
+    Tui(tui::TuiArgs),
+    /// Refresh the ATT&CK technique data from MITRE's STIX feed into the local cache
+    UpdateAttackData(attack::UpdateAttackDataArgs),
+    /// Check configs for structural errors
+    Validate(validate::ValidateArgs),
+    /// Re-run a build from its manifest and confirm every output hashes the same
+    VerifyBuild(build::VerifyBuildArgs),
+    /// Draw a config's rule groups, event filters and rules as a DOT or Mermaid graph
+    Visualize(visualize::VisualizeArgs),
+    /// Check configs for structural errors and likely mistakes
+    Lint(validate::LintArgs),
+    /// Generate man pages
+    #[command(hide = true)]
+    Mangen(mangen::MangenArgs),
+}
+
+impl Cli {
+    fn input(&self) -> &PathBuf {
+        self.input
+            .first()
+            .expect("clap requires --input unless another mode is selected")
+    }
+
+    /// `default`, the output an input gets when `--output` is not given,
+    /// moved into the output directory when there are several inputs.
+    fn default_output(&self, default: PathBuf) -> PathBuf {
+        match (&self.output_dir, default.file_name()) {
+            (Some(dir), Some(name)) => dir.join(name),
+            _ => default,
+        }
+    }
 }
 
 fn main() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Err(e) = apply_user_config(&mut cli, &matches) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
 
-    if let Err(e) = try_main() {
-        error!("Error: {}", e);
+    if cli.read_only {
+        io_guard::enable_read_only();
+    }
+
+    let log_file = cli.log_file.clone().map(|path| logging::LogFile {
+        path,
+        max_bytes: cli.log_file_max_mb * 1024 * 1024,
+        keep: cli.log_file_keep,
+    });
+    let verbosity = logging::Verbosity {
+        verbose: cli.verbose,
+        quiet: cli.quiet,
+        debug: cli.debug.clone(),
+    };
+    if let Err(e) = logging::init(cli.log_format, &verbosity, log_file) {
+        eprintln!("Error: {}", e);
         process::exit(1);
     }
+
+    let error_format = cli.error_format;
+    if let Err(e) = try_main(&mut cli) {
+        // Several inputs leave only the failed ones behind.
+        let input = cli.input.first().cloned();
+        match error_format {
+            error_report::ErrorFormat::Text => {
+                error!(kind = error_report::kind(&e); "Error: {}", e);
+            }
+            error_report::ErrorFormat::Json => {
+                eprintln!(
+                    "{}",
+                    error_report::ErrorReport::new(&e, input.as_deref()).to_json()
+                );
+            }
+        }
+        process::exit(if cancel::interrupted() {
+            cancel::EXIT_INTERRUPTED
+        } else {
+            1
+        });
+    }
 }
 
-fn try_main() -> Result<(), ConversionError> {
-    let cli = Cli::parse();
+/// Fills in options that were not given on the command line or through
+/// `SYSMON_HELPER_*` environment variables from the user config file.
+fn apply_user_config(cli: &mut Cli, matches: &ArgMatches) -> Result<(), ConversionError> {
+    let Some((path, config)) = user_config::load(cli.config.as_deref())? else {
+        return Ok(());
+    };
+    let unset = |id: &str| {
+        !matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+
+    if let Some(value) = config.max_size.filter(|_| unset("max_size")) {
+        cli.max_size = value;
+    }
+    if let Some(value) = config.max_depth.filter(|_| unset("max_depth")) {
+        cli.max_depth = value;
+    }
+    if let Some(value) = config.platform.filter(|_| unset("platform")) {
+        cli.platform = value.parse().map_err(|e| {
+            ConversionError::ValidationError(format!("{}: platform: {}", path.display(), e))
+        })?;
+    }
+    if let Some(value) = config.strategy.filter(|_| unset("strategy")) {
+        cli.strategy = Some(user_config::parse_enum(&path, "strategy", &value)?);
+    }
+    if let Some(value) = config.max_output_kb.filter(|_| unset("max_output_kb")) {
+        cli.max_output_kb = Some(value);
+    }
+    if let Some(value) = config.max_memory_mb.filter(|_| unset("max_memory_mb")) {
+        cli.max_memory_mb = Some(value);
+    }
+    if let Some(value) = config
+        .follow_symlinks
+        .filter(|_| unset("follow_symlinks") && unset("no_follow_symlinks"))
+    {
+        cli.follow_symlinks = value;
+    }
+    if let Some(value) = config.incremental.filter(|_| unset("incremental")) {
+        cli.incremental = value;
+    }
+    if let Some(value) = config.timeout_secs.filter(|_| unset("timeout_secs")) {
+        cli.timeout_secs = Some(value);
+    }
+    if let Some(value) = config.workers.filter(|_| unset("workers")) {
+        cli.workers = Some(value);
+    }
+    if let Some(value) = config.ignore.filter(|_| unset("ignore_patterns")) {
+        cli.ignore_patterns = value;
+    }
+    if let Some(value) = config.recursive.filter(|_| unset("recursive")) {
+        cli.recursive = value;
+    }
+    if let Some(value) = config.backup.filter(|_| unset("backup")) {
+        cli.backup = value;
+    }
+    if let Some(value) = config.verify.filter(|_| unset("verify")) {
+        cli.verify = value;
+    }
+    if let Some(value) = config.silent.filter(|_| unset("silent")) {
+        cli.silent = value;
+    }
+    if let Some(value) = config.preprocess_rules.filter(|_| unset("preprocess_rules")) {
+        cli.preprocess_rules = Some(value);
+    }
+    if let Some(value) = config
+        .output_template
+        .filter(|_| unset("output_template") && !cli.flatten)
+    {
+        cli.output_template = Some(value.parse().map_err(|e| {
+            ConversionError::ValidationError(format!("{}: output_template: {}", path.display(), e))
+        })?);
+    }
+    if let Some(value) = config.on_collision.filter(|_| unset("on_collision")) {
+        cli.on_collision = user_config::parse_enum(&path, "on_collision", &value)?;
+    }
+    if let Some(value) = config.output_encoding.filter(|_| unset("output_encoding")) {
+        cli.output_encoding = Some(user_config::parse_enum(&path, "output_encoding", &value)?);
+    }
+    if let Some(value) = config.log_format.filter(|_| unset("log_format")) {
+        cli.log_format = user_config::parse_enum(&path, "log_format", &value)?;
+    }
+    if let Some(value) = config.error_format.filter(|_| unset("error_format")) {
+        cli.error_format = user_config::parse_enum(&path, "error_format", &value)?;
+    }
+    Ok(())
+}
+
+fn try_main(cli: &mut Cli) -> Result<(), ConversionError> {
+    if let Some(command) = &cli.command {
+        return match command {
+            Command::AddRule(args) => edit::run_add(args),
+            Command::Analyze(args) => analyze::run(args),
+            Command::Bench(args) => bench::run(args),
+            Command::Build(args) => build::run(args),
+            Command::Changelog(args) => changelog::run(args),
+            Command::Compare(args) => compare::run(args),
+            Command::CoverageDiff(args) => coverage::run(args),
+            Command::DecodeRules(args) => rules_blob::run(args),
+            #[cfg(windows)]
+            Command::Deploy(args) => deploy::run(args),
+            Command::Drift(args) => drift::run(args),
+            #[cfg(windows)]
+            Command::DumpLive(args) => live::run(args),
+            Command::EstimateVolume(args) => volume::run(args),
+            Command::Explain(args) => explain::run(args),
+            Command::Export(args) => deploy_script::run(args),
+            Command::Fix(args) => fix::run(args),
+            Command::FleetBuild(args) => fleet::run(args),
+            Command::FromSigma(args) => from_sigma::run(args),
+            Command::Init(args) => init::run(args),
+            Command::Inventory(args) => inventory::run(args),
+            Command::Patch(args) => patch::run(args),
+            Command::Posture(args) => posture::run(args),
+            Command::Query(args) => query::run(args),
+            Command::Redact(args) => redact::run(args),
+            Command::RemoveRule(args) => edit::run_remove(args),
+            Command::Serve(args) => serve::run(args),
+            Command::SigmaCoverage(args) => sigma_coverage::run(args),
+            Command::Stats(args) => stats::run(args),
+            #[cfg(feature = "tui")]
+            Command::Tui(args) => tui::run(args),
+            Command::UpdateAttackData(args) => attack::run(args),
+            Command::Validate(args) => validate::run_validate(args),
+            Command::VerifyBuild(args) => build::run_verify(args),
+            Command::Visualize(args) => visualize::run(args),
+            Command::Lint(args) => validate::run_lint(args),
+            Command::Mangen(args) => mangen::run(args, Cli::command()),
+        };
+    }
+
+    if cli.list_preprocessors {
+        return preprocess::list(cli.preprocess_rules.as_deref());
+    }
+
+    // Listed files are resolved against the current directory by default.
+    if cli.input.is_empty() && cli.files_from.is_some() {
+        cli.input.push(PathBuf::from("."));
+    }
 
     let options = ProcessingOptionsBuilder::new()
         .max_file_size(cli.max_size * 1024 * 1024)
@@ -96,29 +718,119 @@ fn try_main() -> Result<(), ConversionError> {
         })
         .build();
 
-    if !cli.input.exists() {
+    if cli.input.len() > 1 && !cli.merge {
+        return handle_inputs(cli, &options);
+    }
+    run_input(cli, &options)
+}
+
+/// Converts or batches the one input `cli` names, or merges its inputs.
+fn run_input(cli: &Cli, options: &ProcessingOptions) -> Result<(), ConversionError> {
+    if let Some(missing) = cli.input.iter().find(|input| !input.exists()) {
         return Err(ConversionError::InvalidFile(format!(
             "Input path does not exist: {}",
-            cli.input.display()
+            missing.display()
         )));
     }
 
     if cli.merge {
-        handle_merge_mode(&cli)?;
+        handle_merge_mode(cli)?;
         return Ok(());
     }
 
-    if cli.batch || cli.input.is_dir() {
-        handle_batch_mode(&cli, &options)?;
+    if cli.batch
+        || cli.files_from.is_some()
+        || cli.input().is_dir()
+        || archive::ArchiveKind::from_path(cli.input()).is_some()
+    {
+        handle_batch_mode(cli)?;
         return Ok(());
     }
 
-    handle_single_file(&cli, &options)?;
+    handle_single_file(cli, options)?;
     Ok(())
 }
 
+/// Runs each of several `--input` paths in turn, as if it were the only
+/// one, carrying on past failures (unless --fail-fast). Outputs land where
+/// each input's would by default, or inside `--output` when given.
+fn handle_inputs(cli: &mut Cli, options: &ProcessingOptions) -> Result<(), ConversionError> {
+    if cli.files_from.is_some() {
+        return Err(ConversionError::InvalidFile(
+            "--files-from cannot be combined with several inputs".to_string(),
+        ));
+    }
+    let inputs = std::mem::take(&mut cli.input);
+    let mut names = std::collections::BTreeMap::new();
+    for input in inputs.iter().filter(|_| cli.output.is_some()) {
+        let Some(name) = input.file_name() else {
+            continue;
+        };
+        if let Some(first) = names.insert(name, input) {
+            return Err(ConversionError::InvalidFile(format!(
+                "{} and {} would write the same output; convert them separately",
+                first.display(),
+                input.display()
+            )));
+        }
+    }
+    cli.output_dir = cli.output.take();
+    if let Some(dir) = &cli.output_dir {
+        if !dir.is_dir() && !io_guard::is_read_only() && !cli.check {
+            io_guard::create_dir_all(dir)?;
+        }
+    }
+
+    let mut failed = Vec::new();
+    let mut first_error = None;
+    for input in &inputs {
+        cli.input = vec![input.clone()];
+        if let Err(e) = run_input(cli, options) {
+            error!("{}: {}", input.display(), e);
+            failed.push(input.clone());
+            first_error.get_or_insert(e);
+            if cli.fail_fast {
+                break;
+            }
+        }
+    }
+    // The failed inputs are what main() reports the error against.
+    cli.input = failed;
+    let Some(e) = first_error else {
+        return Ok(());
+    };
+    let names: Vec<_> = cli.input.iter().map(|p| p.display().to_string()).collect();
+    Err(with_context(
+        e,
+        &format!(
+            "{} of {} input(s) failed ({}); {}",
+            names.len(),
+            inputs.len(),
+            names.join(", "),
+            names[0]
+        ),
+    ))
+}
+
+/// `e` with `context` in front of its message, keeping its kind.
+fn with_context(e: ConversionError, context: &str) -> ConversionError {
+    let prefix = |message: String| format!("{}: {}", context, message);
+    match e {
+        ConversionError::Io { path, source } => ConversionError::Io {
+            path,
+            source: std::io::Error::new(source.kind(), prefix(source.to_string())),
+        },
+        ConversionError::InvalidFile(m) => ConversionError::InvalidFile(prefix(m)),
+        ConversionError::ValidationError(m) => ConversionError::ValidationError(prefix(m)),
+        ConversionError::ParserError(m) => ConversionError::ParserError(prefix(m)),
+        ConversionError::Other(m) => ConversionError::Other(prefix(m)),
+        // The parser's own error type carries no message to extend.
+        e @ ConversionError::XmlParse(_) => e,
+    }
+}
+
 fn handle_merge_mode(cli: &Cli) -> Result<(), ConversionError> {
-    if !cli.input.is_dir() {
+    if !cli.input.iter().all(|input| input.is_dir()) {
         return Err(ConversionError::InvalidFile(
             "Merge mode requires input to be a directory".to_string(),
         ));
@@ -127,165 +839,600 @@ fn handle_merge_mode(cli: &Cli) -> Result<(), ConversionError> {
     let output_path = cli
         .output
         .clone()
-        .unwrap_or_else(|| cli.input.join("merged.xml"));
+        .unwrap_or_else(|| cli.input().join("merged.xml"));
+
+    if io_guard::is_read_only() {
+        return report_merge(cli, &output_path);
+    }
 
+    let max_bytes = cli.max_output_kb.map(|kb| kb as usize * 1024);
+    let layers = merge_sources(cli, &output_path);
+
+    let vars = vars::Vars::load(&cli.vars, cli.vars_file.as_deref())?;
+    if cli.check {
+        let merged = merged(cli, &layers, &vars)?;
+        let mut results = Vec::new();
+        for (path, expected) in merge_outputs(cli, merged, &output_path, max_bytes)? {
+            let status = check::compare(&path, &expected)?;
+            results.push((path, status));
+        }
+        return check::report(&results);
+    }
+
+    let inputs: Vec<String> = cli.input.iter().map(|i| i.display().to_string()).collect();
     info!(
         "Merging configs from {} to {}",
-        cli.input.display(),
+        inputs.join(", "),
         output_path.display()
     );
 
-    merge_configs(&cli.input, &output_path, cli.recursive)?;
-    info!("Merge completed successfully");
+    let merged = merged(cli, &layers, &vars)?;
+    let outputs = merge_outputs(cli, merged, &output_path, max_bytes)?;
+    for (path, xml) in &outputs {
+        io_guard::check_write(path)?;
+        io_guard::write(path, xml)?;
+        info!("Wrote {}", path.display());
+    }
+    for stale in merge::stale_parts(&output_path, &outputs) {
+        io_guard::remove(&stale)?;
+        info!("Removed {}, left by an earlier merge", stale.display());
+    }
+    if let Some(path) = &cli.manifest {
+        write_merge_manifest(cli, path, &layers, &vars, &outputs)?;
+        info!("Wrote manifest {}", path.display());
+    }
+    info!(
+        "Merged {} file(s) successfully",
+        layers.iter().map(Vec::len).sum::<usize>()
+    );
+
+    Ok(())
+}
+
+fn merged(
+    cli: &Cli,
+    layers: &[Vec<PathBuf>],
+    vars: &vars::Vars,
+) -> Result<SysmonConfig, ConversionError> {
+    let mut resolver = merge::Resolver::new(cli.strategy);
+    let (includes, by_name) = (includes(cli), cli.override_by_name);
+    let mut config = merge::merge_layers(
+        layers,
+        cli.workers,
+        includes,
+        vars,
+        by_name,
+        cli.lenient,
+        &mut resolver,
+    )?;
+    if let Some(template) = &cli.template {
+        let template = merge::template(template, includes, vars, cli.lenient)?;
+        config = merge::apply_template(template, config);
+    }
+    if cli.coalesce_rulegroups {
+        let removed = merge::coalesce(&mut config);
+        info!("Coalesced {} rule group(s) into others", removed);
+    }
+    merge::retain_platform(&mut config, cli.platform);
+    if let Some(path) = &cli.suppress {
+        let report = suppress::apply(&mut config, &suppress::load(path)?);
+        for (suppression, dropped) in report.dropped.iter().filter(|(_, n)| *n > 0) {
+            info!("Suppressed {} rule(s) matching {}", dropped, suppression);
+        }
+        info!("Suppressed {} rule(s) listed in {}", report.total(), path.display());
+    }
+    Ok(config)
+}
+
+/// Records what a merge was made from, with the placeholder values `vars`
+/// resolved, and the hashes of what it wrote.
+fn write_merge_manifest(
+    cli: &Cli,
+    path: &Path,
+    layers: &[Vec<PathBuf>],
+    vars: &vars::Vars,
+    outputs: &[(PathBuf, Vec<u8>)],
+) -> Result<(), ConversionError> {
+    let optional = |path: &Option<PathBuf>| path.as_deref().map(provenance::hash_file);
+    let mut sources: Vec<&Path> = cli.input.iter().map(PathBuf::as_path).collect();
+    sources.extend(cli.template.iter().chain(&cli.suppress).map(PathBuf::as_path));
+    let manifest = provenance::MergeManifest {
+        provenance: provenance::Provenance::new(&sources),
+        platform: cli.platform.to_string(),
+        strategy: cli.strategy,
+        vars: vars.values(),
+        template: optional(&cli.template).transpose()?,
+        suppress: optional(&cli.suppress).transpose()?,
+        inputs: provenance::hash_inputs(&layers.concat(), includes(cli))?,
+        outputs: outputs
+            .iter()
+            .map(|(path, xml)| provenance::hash(path, xml))
+            .collect(),
+    };
+    provenance::write(path, &manifest)
+}
+
+/// The files a merge writes, minified with --minify or laid out with the
+/// pretty-print options, in the --output-encoding.
+fn merge_outputs(
+    cli: &Cli,
+    merged: SysmonConfig,
+    output_path: &Path,
+    max_bytes: Option<usize>,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, ConversionError> {
+    let style = pretty_style(cli);
+    let mut outputs = Vec::new();
+    for (path, mut xml) in merge::outputs(merged, output_path, max_bytes)? {
+        if cli.minify {
+            xml = minify::minify(&xml, format::Format::Xml)?;
+        } else if let Some(style) = &style {
+            xml = pretty::format(&xml, format::Format::Xml, style)?;
+        }
+        let bytes = match cli.output_encoding {
+            Some(encoding) => encoding::encode(&xml, encoding),
+            None => xml.into_bytes(),
+        };
+        outputs.push((path, bytes));
+    }
+    Ok(outputs)
+}
+
+/// The output layout the pretty-print options ask for, if any is given.
+fn pretty_style(cli: &Cli) -> Option<pretty::Style> {
+    if cli.indent.is_none()
+        && cli.indent_char.is_none()
+        && cli.key_order.is_none()
+        && !cli.attribute_per_line
+    {
+        return None;
+    }
+    let default = pretty::Style::default();
+    Some(pretty::Style {
+        indent: cli.indent.unwrap_or(default.indent),
+        indent_char: cli.indent_char.unwrap_or(default.indent_char),
+        key_order: cli.key_order.unwrap_or(default.key_order),
+        attribute_per_line: cli.attribute_per_line,
+    })
+}
 
+/// The files to merge, one layer per input directory: its XML files,
+/// without the output and the template.
+fn merge_sources(cli: &Cli, output_path: &Path) -> Vec<Vec<PathBuf>> {
+    let template = cli
+        .template
+        .as_ref()
+        .map(|t| std::fs::canonicalize(t).unwrap_or_else(|_| t.clone()));
+    let mut layers = Vec::new();
+    for input in &cli.input {
+        let mut sources = merge::sources(input, cli.recursive, cli.follow_symlinks, output_path);
+        if let Some(template) = &template {
+            sources.retain(|source| {
+                std::fs::canonicalize(source).unwrap_or_else(|_| source.clone()) != *template
+            });
+        }
+        layers.push(sources);
+    }
+    layers
+}
+
+fn report_merge(cli: &Cli, output_path: &Path) -> Result<(), ConversionError> {
+    let sources = merge_sources(cli, output_path).concat();
+    for source in &sources {
+        println!("{}", source.display());
+    }
+    println!(
+        "{} file(s) would be merged into {}",
+        sources.len(),
+        output_path.display()
+    );
     Ok(())
 }
 
-fn handle_batch_mode(cli: &Cli, options: &ProcessingOptions) -> Result<(), ConversionError> {
-    if !cli.input.is_dir() {
+fn handle_batch_mode(cli: &Cli) -> Result<(), ConversionError> {
+    let input_archive = archive::ArchiveKind::from_path(cli.input());
+    if input_archive.is_none() && !cli.input().is_dir() {
         return Err(ConversionError::InvalidFile(
-            "Batch mode requires input to be a directory".to_string(),
+            "Batch mode requires input to be a directory or archive".to_string(),
         ));
     }
 
-    let output_dir = cli.output.clone().unwrap_or_else(|| {
-        let mut out = cli.input.clone();
-        out.set_file_name(format!(
-            "{}_converted",
-            cli.input
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("output")
+    if cli.files_from.is_some() && input_archive.is_some() {
+        return Err(ConversionError::InvalidFile(
+            "--files-from cannot be combined with an archive input".to_string(),
         ));
-        out
+    }
+
+    let output = cli.output.clone().unwrap_or_else(|| {
+        cli.default_output(match input_archive {
+            Some(kind) => archive::default_output(cli.input(), kind),
+            None => {
+                let mut out = cli.input().clone();
+                out.set_file_name(format!(
+                    "{}_converted",
+                    cli.input()
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("output")
+                ));
+                out
+            }
+        })
     });
+    let output_archive = archive::ArchiveKind::from_path(&output);
 
-    info!("Processing directory: {}", cli.input.display());
-    info!("Output directory: {}", output_dir.display());
+    if io_guard::is_read_only() {
+        return report_batch(cli, input_archive, &output);
+    }
 
-    let processor = BatchProcessor::new();
-    let stats = if !cli.silent {
-        process_with_progress(&cli.input, &output_dir, cli.recursive, options, &processor)?
+    if cli.check {
+        if input_archive.is_some() || output_archive.is_some() {
+            return Err(ConversionError::InvalidFile(
+                "--check does not support archives".to_string(),
+            ));
+        }
+        return check_batch(cli, &output);
+    }
+
+    if (cli.incremental || cli.resume) && (input_archive.is_some() || output_archive.is_some()) {
+        return Err(ConversionError::InvalidFile(
+            "--incremental and --resume need a directory as input and output".to_string(),
+        ));
+    }
+
+    // Archives are unpacked into, and packed from, a private staging area.
+    let staging = io_guard::tempdir(&output)?;
+
+    let input_dir = match input_archive {
+        Some(kind) => {
+            let dir = staging.path().join("input");
+            info!("Extracting archive: {}", cli.input().display());
+            archive::extract(kind, cli.input(), &dir)?;
+            dir
+        }
+        None => cli.input().clone(),
+    };
+    let output_dir = match output_archive {
+        Some(_) => staging.path().join("output"),
+        None => output.clone(),
+    };
+
+    info!("Processing directory: {}", cli.input().display());
+    info!("Output directory: {}", output.display());
+
+    io_guard::check_write(&output_dir)?;
+    handle_planned_batch(cli, &input_dir, &output_dir)?;
+
+    if let Some(kind) = output_archive {
+        info!("Writing archive: {}", output.display());
+        archive::pack(kind, &output_dir, &output)?;
+    }
+
+    Ok(())
+}
+
+fn report_batch(
+    cli: &Cli,
+    input_archive: Option<archive::ArchiveKind>,
+    output: &Path,
+) -> Result<(), ConversionError> {
+    let settings = batch_settings(cli)?;
+    let files = match input_archive {
+        Some(kind) => archive::list(kind, cli.input())?
+            .into_iter()
+            .map(|name| cli.input().join(name))
+            .filter(|path| batch::is_convertible(path))
+            .collect(),
+        None => batch_inputs(cli, cli.input(), &settings)?,
+    };
+    let plan = batch::plan(cli.input(), output, files, &settings)?;
+    batch::print_plan(&plan);
+    Ok(())
+}
+
+fn check_batch(cli: &Cli, output: &Path) -> Result<(), ConversionError> {
+    let settings = batch_settings(cli)?;
+    let files = batch_inputs(cli, cli.input(), &settings)?;
+    let plan = batch::plan(cli.input(), output, files, &settings)?;
+
+    let mut results = Vec::new();
+    for job in &plan.jobs {
+        let expected = batch::render(job, &settings)?;
+        results.push((
+            job.output.clone(),
+            check::compare(&job.output, &expected)?,
+        ));
+    }
+    check::report(&results)
+}
+
+fn handle_planned_batch(
+    cli: &Cli,
+    input_dir: &Path,
+    output_dir: &Path,
+) -> Result<(), ConversionError> {
+    let mut settings = batch_settings(cli)?;
+    settings.cancel = cancel::ctrl_c()?;
+    let files = batch_inputs(cli, input_dir, &settings)?;
+    let mut plan = batch::plan(input_dir, output_dir, files, &settings)?;
+    if cli.resume {
+        checkpoint::skip_completed(&mut plan, input_dir, output_dir);
+    }
+    let pending = if cli.incremental {
+        Some(incremental::filter(&mut plan, input_dir, output_dir)?)
+    } else {
+        if settings.schedule == batch::Schedule::SlowestFirst {
+            incremental::history(&mut plan, input_dir, output_dir);
+        }
+        None
+    };
+    let reporter: Box<dyn progress::Progress> = if cli.silent {
+        Box::new(progress::Silent)
     } else {
-        processor.process_directory(&cli.input, &output_dir, cli.recursive, options)?
+        Box::new(progress::Bars::new())
     };
+    let checkpoint = checkpoint::Checkpoint::open(
+        output_dir,
+        input_dir,
+        cli.resume,
+        &settings.cancel,
+        reporter.as_ref(),
+    )?;
+    let report = batch::run(plan, &settings, &checkpoint);
+    if settings.dedupe_outputs == Some(batch::DedupeMode::Record) && !report.duplicates.is_empty()
+    {
+        batch::write_duplicates(output_dir, &report.duplicates)?;
+    }
 
-    if stats.errors > 0 {
+    if let Some(pending) = pending {
+        let unfinished = [
+            report.failures.as_slice(),
+            &report.not_started,
+            &report.cancelled,
+        ]
+        .concat();
+        pending.save(&unfinished, &report.durations, output_dir)?;
+    }
+    if settings.cancel.is_cancelled() {
+        return Err(ConversionError::Other(format!(
+            "Interrupted after converting {} file(s); {} file(s) were not converted",
+            report.processed,
+            report.cancelled.len()
+        )));
+    }
+    if !report.not_started.is_empty() {
+        return Err(ConversionError::Other(format!(
+            "Batch stopped after {} failed file(s); {} file(s) were not converted",
+            report.errors,
+            report.not_started.len()
+        )));
+    }
+    if report.errors > 0 {
         warn!("Some files failed to process. Check the log for details.");
     }
 
     Ok(())
 }
 
-fn process_with_progress(
-    input: &PathBuf,
-    output: &PathBuf,
-    recursive: bool,
-    options: &ProcessingOptions,
-    processor: &BatchProcessor,
-) -> Result<sysmon_json::batch::BatchProcessingStats, ConversionError> {
-    let total_files = walkdir::WalkDir::new(input)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .count();
-
-    let progress = ProgressReporter::new(total_files);
-    let stats = processor.process_directory_with_progress(
-        input,
-        output,
-        recursive,
-        options,
-        &progress,
-    )?;
-    Ok(stats)
+/// The files a batch run converts: the `--files-from` list, or else every
+/// convertible file under `input_dir`.
+fn batch_inputs(
+    cli: &Cli,
+    input_dir: &Path,
+    settings: &batch::BatchSettings,
+) -> Result<Vec<PathBuf>, ConversionError> {
+    match &cli.files_from {
+        Some(source) => Ok(batch::listed_inputs(input_dir, file_list::read(source)?, settings)),
+        None => Ok(batch::collect_inputs(input_dir, settings)),
+    }
+}
+
+/// How far `<?include?>` directives may reach.
+fn includes(cli: &Cli) -> include::Limits {
+    include::Limits {
+        max_depth: cli.max_depth as usize,
+        outside_root: cli.allow_outside_includes,
+    }
+}
+
+fn batch_settings(cli: &Cli) -> Result<batch::BatchSettings, ConversionError> {
+    Ok(batch::BatchSettings {
+        recursive: cli.recursive,
+        max_depth: cli.max_depth,
+        allow_outside_includes: cli.allow_outside_includes,
+        max_file_size: cli.max_size * 1024 * 1024,
+        min_size: cli.min_size.map(|size| size.0),
+        max_size_skip: cli.max_size_skip.map(|size| size.0),
+        workers: cli.workers,
+        create_backup: cli.backup,
+        ignore_patterns: cli.ignore_patterns.clone(),
+        flatten: cli.flatten,
+        on_collision: cli.on_collision,
+        pipeline: preprocess_pipeline(cli)?,
+        lenient: cli.lenient,
+        output_encoding: cli.output_encoding,
+        verify: cli.verify,
+        max_memory: cli.max_memory_mb.map(|mb| mb * 1024 * 1024),
+        timeout: cli.timeout_secs.map(std::time::Duration::from_secs),
+        follow_symlinks: cli.follow_symlinks,
+        output_template: cli.output_template.clone(),
+        max_errors: if cli.fail_fast {
+            Some(1)
+        } else {
+            cli.max_errors
+        },
+        modified_since: cli.modified_since.clone(),
+        minify: cli.minify,
+        pretty: pretty_style(cli),
+        dedupe_outputs: cli.dedupe_outputs,
+        schedule: cli.schedule,
+        cancel: cancel::CancellationToken::new(),
+    })
+}
+
+/// The selected preprocessing steps, then placeholder substitution, which
+/// runs even with --skip-preprocessing.
+fn preprocess_pipeline(cli: &Cli) -> Result<preprocess::Pipeline, ConversionError> {
+    let mut pipeline = if cli.skip_preprocessing {
+        preprocess::Pipeline::default()
+    } else {
+        preprocess::pipeline(
+            cli.preprocess_rules.as_deref(),
+            cli.preprocess_only.as_deref(),
+        )?
+    };
+    let vars = vars::Vars::load(&cli.vars, cli.vars_file.as_deref())?;
+    pipeline.register(std::sync::Arc::new(vars));
+    Ok(pipeline)
 }
 
 fn handle_single_file(cli: &Cli, options: &ProcessingOptions) -> Result<(), ConversionError> {
+    let plain_input = compression::strip_gz(cli.input());
+    let raw = compression::read(cli.input())?;
+    let mut content = encoding::decode(&raw).map_err(|e| {
+        ConversionError::InvalidFile(format!("{}: {}", cli.input().display(), e))
+    })?;
+    let from = match cli.from {
+        Some(from) => from,
+        None => format::detect(&plain_input, content.as_bytes())?,
+    };
+    if cli.lenient && from == format::Format::Xml {
+        content = repair::repair_file(cli.input(), &content);
+    }
+    if from == format::Format::Xml {
+        content = include::expand(cli.input(), &content, includes(cli))?;
+    }
+
+    let pipeline = preprocess_pipeline(cli)?;
+    if !pipeline.runs_library() {
+        content = pipeline.apply(&content, from)?;
+    }
+    let to = from.target();
+
     let output_path = cli.output.clone().unwrap_or_else(|| {
-        let mut out = cli.input.clone();
-        let new_ext = if cli.input.extension().and_then(|e| e.to_str()) == Some("xml") {
-            "json"
-        } else {
-            "xml"
-        };
-        out.set_extension(new_ext);
-        out
+        let mut out = plain_input.clone();
+        out.set_extension(to.extension());
+        if compression::is_gzip(cli.input()) {
+            out.set_extension(format!("{}.gz", to.extension()));
+        }
+        cli.default_output(out)
     });
 
+    // The library preprocessor reads from disk, so it can read the input
+    // directly while that still matches `content`.
+    let pristine = !compression::is_gzip(cli.input()) && content.as_bytes() == raw.as_slice();
+    if io_guard::is_read_only() {
+        return report_single_file(cli, &content, &pipeline, pristine, from, &output_path);
+    }
+
+    if cli.check {
+        let expected = render_single_file(cli, content, &pipeline, pristine, from)?;
+        return check::report(&[(
+            output_path.clone(),
+            check::compare(&output_path, &expected)?,
+        )]);
+    }
+
     if options.create_backup && output_path.exists() {
         let backup_path = output_path.with_extension("bak");
         info!("Creating backup: {}", backup_path.display());
-        std::fs::copy(&output_path, &backup_path)
-            .map_err(|e| ConversionError::io_error(&output_path, e))?;
+        io_guard::copy(&output_path, &backup_path)?;
     }
 
-    if !cli.skip_preprocessing {
+    io_guard::check_write(&output_path)?;
+
+    info!(
+        "Converting {} to {}",
+        cli.input().display(),
+        output_path.display()
+    );
+    let converted = render_single_file(cli, content, &pipeline, pristine, from)?;
+    compression::write(&output_path, &converted)?;
+
+    info!("Conversion completed successfully");
+    Ok(())
+}
+
+/// Finishes preprocessing `content` and converts it, returning the output
+/// bytes before any compression. `pristine` says the input file still
+/// holds `content`.
+fn render_single_file(
+    cli: &Cli,
+    mut content: String,
+    pipeline: &preprocess::Pipeline,
+    pristine: bool,
+    from: format::Format,
+) -> Result<Vec<u8>, ConversionError> {
+    if pipeline.runs_library() {
         info!("Preprocessing configuration file...");
-        match preprocess_config(&cli.input) {
-            Ok(processed_content) => {
-                let temp_dir = tempfile::tempdir()
-                    .map_err(|e| ConversionError::io_error(&cli.input, e))?;
-                let temp_path = temp_dir.path().join(cli.input.file_name().unwrap());
-                
-                std::fs::write(&temp_path, &processed_content)
-                    .map_err(|e| ConversionError::io_error(&temp_path, e))?;
-
-                info!(
-                    "Converting {} to {}",
-                    cli.input.display(),
-                    output_path.display()
-                );
+        let processed = if pristine {
+            preprocess_config(cli.input()).map_err(|e| preprocess::library_error(cli.input(), e))?
+        } else {
+            convert::preprocess_str(&content, from)?
+        };
+        content = pipeline.apply(&processed, from)?;
+    }
 
-                match convert_file(&temp_path, &output_path) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error!("Conversion failed after preprocessing: {}", e);
-                        return Err(e);
-                    }
-                }
-            }
-            Err(e) => {
-                match e {
-                    PreprocessError::IoError(e) => {
-                        error!("IO error during preprocessing: {}", e);
-                        return Err(ConversionError::io_error(&cli.input, e));
-                    }
-                    PreprocessError::XmlError(e) => {
-                        error!("XML parsing error during preprocessing: {}", e);
-                        return Err(ConversionError::XmlParse(e.into()));
-                    }
-                    PreprocessError::ValidationError(e) => {
-                        error!("Validation error during preprocessing: {}", e);
-                        return Err(ConversionError::ValidationError(e.to_string()));
-                    }
-                    PreprocessError::PathError(e) => {
-                        error!("Path error during preprocessing: {}", e);
-                        return Err(ConversionError::InvalidFile(e));
-                    }
-                    PreprocessError::ParserError(e) => {
-                        error!("Parser error during preprocessing: {}", e);
-                        return Err(ConversionError::ParserError(e.to_string()));
-                    }
-                }
-            }
-        }
+    let mut converted = convert::convert_str(&content, from).inspect_err(|e| {
+        error!("Conversion failed: {}", e);
+    })?;
+    if cli.minify {
+        converted = minify::minify(&converted, from.target())?;
+    } else if let Some(style) = pretty_style(cli) {
+        converted = pretty::format(&converted, from.target(), &style)?;
+    }
+    Ok(match cli.output_encoding {
+        Some(encoding) => encoding::encode(&converted, encoding),
+        None => converted.into_bytes(),
+    })
+}
+
+/// Read-only counterpart of `handle_single_file`: everything happens in
+/// memory and the outcome is reported on stdout.
+fn report_single_file(
+    cli: &Cli,
+    content: &str,
+    pipeline: &preprocess::Pipeline,
+    pristine: bool,
+    from: format::Format,
+    output_path: &Path,
+) -> Result<(), ConversionError> {
+    // Staging `content` for the library preprocessor would write a file.
+    let content = if pipeline.runs_library() && pristine {
+        let processed =
+            preprocess_config(cli.input()).map_err(|e| preprocess::library_error(cli.input(), e))?;
+        pipeline.apply(&processed, from)?
     } else {
-        info!(
-            "Converting {} to {}",
-            cli.input.display(),
-            output_path.display()
+        content.to_string()
+    };
+
+    if from == format::Format::Xml {
+        let root = xmltree::Element::parse(content.as_bytes()).map_err(|e| {
+            ConversionError::ParserError(format!("{}: {}", cli.input().display(), e))
+        })?;
+        println!(
+            "{}: well-formed XML, root <{}>, schemaversion {}",
+            cli.input().display(),
+            root.name,
+            root.attributes
+                .get("schemaversion")
+                .map(String::as_str)
+                .unwrap_or("missing")
         );
-        convert_file(&cli.input, &output_path)?;
+    } else {
+        println!("{}: {} bytes of JSON", cli.input().display(), content.len());
     }
-
-    info!("Conversion completed successfully");
+    println!(
+        "{} would be converted to {}",
+        cli.input().display(),
+        output_path.display()
+    );
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sysmon_json::{convert_file, BatchProcessor};
     use std::fs;
     use tempfile::tempdir;
 
@@ -329,4 +1476,66 @@ mod tests {
         assert!(output_dir.join("test1.json").exists());
         assert!(output_dir.join("test2.json").exists());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_repeated_inputs_write_into_output_dir() {
+        let temp_dir = tempdir().unwrap();
+        let configs = temp_dir.path().join("configs");
+        fs::create_dir(&configs).unwrap();
+        fs::write(configs.join("a.xml"), "<Sysmon/>").unwrap();
+        let single = temp_dir.path().join("extra.xml");
+        fs::write(&single, "<Sysmon/>").unwrap();
+        let out = temp_dir.path().join("out");
+
+        let mut cli = Cli::try_parse_from([
+            "sysmon_cli".as_ref(),
+            "-i".as_ref(),
+            configs.as_os_str(),
+            "-i".as_ref(),
+            single.as_os_str(),
+            "-o".as_ref(),
+            out.as_os_str(),
+        ])
+        .unwrap();
+        assert_eq!(cli.input, [configs.clone(), single]);
+        try_main(&mut cli).unwrap();
+        assert!(out.join("configs_converted").join("a.json").exists());
+        assert!(out.join("extra.json").exists());
+
+        let mut clash = Cli::try_parse_from([
+            "sysmon_cli".as_ref(),
+            "-i".as_ref(),
+            configs.join("a.xml").as_os_str(),
+            "-i".as_ref(),
+            configs.join("a.xml").as_os_str(),
+            "-o".as_ref(),
+            out.as_os_str(),
+        ])
+        .unwrap();
+        assert!(try_main(&mut clash).is_err());
+    }
+
+    #[test]
+    fn test_failed_inputs_keep_their_error_kind() {
+        let temp_dir = tempdir().unwrap();
+        let missing = temp_dir.path().join("missing.xml");
+        let also_missing = temp_dir.path().join("also_missing.xml");
+        let mut single =
+            Cli::try_parse_from(["sysmon_cli".as_ref(), "-i".as_ref(), missing.as_os_str()])
+                .unwrap();
+        let kind = error_report::kind(&try_main(&mut single).unwrap_err());
+
+        let mut cli = Cli::try_parse_from([
+            "sysmon_cli".as_ref(),
+            "-i".as_ref(),
+            missing.as_os_str(),
+            "-i".as_ref(),
+            also_missing.as_os_str(),
+        ])
+        .unwrap();
+        let e = try_main(&mut cli).unwrap_err();
+        assert_eq!(error_report::kind(&e), kind);
+        assert!(e.to_string().contains("2 of 2 input(s) failed"));
+        assert_eq!(cli.input, [missing, also_missing]);
+    }
+}
@@ -0,0 +1,67 @@
+//! `mangen` (hidden): man pages for packagers, generated from the clap
+//! definitions so they never drift from `--help`.
+
+use crate::io_guard;
+use clap::{Args, Command};
+use log::info;
+use std::path::PathBuf;
+use sysmon_json::error::ConversionError;
+
+/// Workflow examples shown by `--help` and in the man page.
+pub const EXAMPLES: &str = "\
+Examples:
+  Convert a single file (the direction is detected from the content):
+    sysmon_cli -i sysmonconfig.xml -o sysmonconfig.json
+
+  Convert a directory tree, keeping backups of replaced outputs:
+    sysmon_cli -i configs/ -o converted/ --batch --recursive --backup
+
+  Merge a sysmon-modular checkout into one config:
+    sysmon_cli -i sysmon-modular/ -o merged.xml --merge --recursive
+
+  Validate, then lint with SARIF output for code scanning:
+    sysmon_cli validate merged.xml
+    sysmon_cli lint configs/*.xml --format sarif -o sysmon.sarif
+
+  Fail CI when committed outputs are stale:
+    sysmon_cli -i configs/ -o json/ --check";
+
+#[derive(Args)]
+pub struct MangenArgs {
+    /// Write `sysmon_cli.1` and one page per subcommand into this directory
+    /// instead of printing the main page to stdout
+    #[arg(short, long)]
+    pub out_dir: Option<PathBuf>,
+}
+
+pub fn run(args: &MangenArgs, mut command: Command) -> Result<(), ConversionError> {
+    command.build();
+    let name = command.get_name().to_string();
+
+    let Some(out_dir) = &args.out_dir else {
+        print!("{}", render(command)?);
+        return Ok(());
+    };
+
+    io_guard::create_dir_all(out_dir)?;
+    let mut pages = vec![(name.clone(), command.clone())];
+    for sub in command.get_subcommands().filter(|s| !s.is_hide_set()) {
+        let page = format!("{}-{}", name, sub.get_name());
+        pages.push((page.clone(), sub.clone().name(page)));
+    }
+
+    for (page, command) in pages {
+        let path = out_dir.join(format!("{}.1", page));
+        io_guard::write(&path, render(command)?)?;
+        info!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn render(command: Command) -> Result<String, ConversionError> {
+    let mut buffer = Vec::new();
+    clap_mangen::Man::new(command)
+        .render(&mut buffer)
+        .map_err(|e| ConversionError::Other(format!("Failed to render man page: {}", e)))?;
+    String::from_utf8(buffer).map_err(|e| ConversionError::Other(e.to_string()))
+}
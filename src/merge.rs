@@ -0,0 +1,1606 @@
+//! Merging a tree of Sysmon configurations into one.
+//!
+//! Sources are parsed into the typed model in parallel, then folded together
+//! one at a time in merge order. Only the parsing is parallel, so the
+//! result does not depend on the number of workers: one worker produces the
+//! same bytes as many.
+//!
+//! Merge order is path order, with two refinements for rule order in the
+//! result: numeric name prefixes compare as numbers, so `2_network` comes
+//! before `10_image_load`, and a directory can hold a `.order` file listing
+//! its files and subdirectories, one per line, to be merged first in that
+//! order. Anything it does not list follows in the usual order.
+//!
+//! The fold keeps the highest `schemaversion`, the first value seen for
+//! each global option, and gathers every event filter into one
+//! `groupRelation="or"` rule group per event type and `onmatch`, the same
+//! layout sysmon-modular's merged configs use. The conditions of a
+//! `groupRelation="and"` group are kept together as one
+//! `<Rule groupRelation="and">`, so they still all have to match. Comments
+//! and formatting in the sources are not carried over.
+//!
+//! Several trees can be merged as layers, such as upstream sysmon-modular
+//! followed by a local overrides directory. Layers are folded in the order
+//! given, so a later layer's rules supplement the earlier ones, and a
+//! global option a later layer sets replaces the earlier value. With
+//! `--override-by-name`, a named rule replaces the rules of the same name
+//! and event type from earlier sources instead of joining them, which is
+//! how local tuning replaces an upstream rule.
+//!
+//! Two kinds of conflict between sources are detected while folding: the
+//! same rule both included and excluded for an event type, and the same
+//! rule under two different names. Each is settled by keeping the rule
+//! merged first ("ours"), the later one ("theirs"), both, or neither, chosen
+//! by `--strategy` or, on a terminal, asked for one conflict at a time.
+//! Without either, both are kept, as before conflicts were detected.
+//!
+//! With `--platform linux`, event types Sysmon for Linux does not report are
+//! dropped from the result, so a tree of shared modules can produce a Linux
+//! config.
+//!
+//! With `--max-output-kb`, a result over the limit is split into several
+//! standalone configs (`merged.1.xml`, `merged.2.xml`, ...) for deployment
+//! channels with a size cap such as Group Policy. Every part keeps the
+//! schema version and global options; the rules for one event type always
+//! stay together, and event types are spread so the parts come out about
+//! the same size.
+
+use crate::vars::Vars;
+use crate::{encoding, include, walk};
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use sysmon_cli::format::Format;
+use sysmon_cli::model::{
+    ConfigOption, EventFilter, FieldCondition, Filter, GroupRelation, OnMatch, Rule, RuleGroup,
+    SysmonConfig,
+};
+use sysmon_cli::repair;
+use sysmon_cli::schema::{Platform, Version};
+use sysmon_json::error::ConversionError;
+
+/// How to settle a conflict between a rule merged earlier and a later one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    /// Keep the rule merged first and drop the later one
+    Ours,
+    /// Drop the rule merged first and keep the later one
+    Theirs,
+    /// Keep both rules
+    Both,
+    /// Drop both rules
+    Skip,
+}
+
+impl Resolution {
+    fn as_str(self) -> &'static str {
+        match self {
+            Resolution::Ours => "ours",
+            Resolution::Theirs => "theirs",
+            Resolution::Both => "both",
+            Resolution::Skip => "neither",
+        }
+    }
+}
+
+/// Decides each conflict found while merging.
+pub enum Resolver {
+    /// Settle every conflict the same way.
+    Fixed(Resolution),
+    /// Ask on the terminal, until an answer is given for all the rest.
+    Prompt,
+    /// Keep both rules, as when nothing was asked for: conflicts are only
+    /// logged at debug level.
+    KeepBoth,
+}
+
+impl Resolver {
+    /// `--strategy` if given; otherwise a prompt when stdin is a terminal
+    /// and keeping both rules when it is not.
+    pub fn new(strategy: Option<Resolution>) -> Resolver {
+        match strategy {
+            Some(resolution) => Resolver::Fixed(resolution),
+            None if io::stdin().is_terminal() => Resolver::Prompt,
+            None => Resolver::KeepBoth,
+        }
+    }
+
+    fn resolve(&mut self, conflict: &Conflict) -> Result<Resolution, ConversionError> {
+        if let Resolver::KeepBoth = self {
+            debug!("Merge conflict, {}: keeping both", conflict);
+            return Ok(Resolution::Both);
+        }
+        if let Resolver::Fixed(resolution) = *self {
+            warn!(
+                "Merge conflict, {}: keeping {}",
+                conflict,
+                resolution.as_str()
+            );
+            return Ok(resolution);
+        }
+        let (resolution, for_all) = ask(conflict, &mut io::stdin().lock(), &mut io::stderr())
+            .map_err(|e| ConversionError::Other(format!("Merge conflict prompt failed: {}", e)))?;
+        if for_all {
+            *self = Resolver::Fixed(resolution);
+        }
+        Ok(resolution)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// One source includes what another excludes.
+    IncludeExclude,
+    /// Two sources have the same rule under different names.
+    Names,
+}
+
+/// A rule from `source` that clashes with one merged earlier.
+pub struct Conflict<'a> {
+    pub kind: ConflictKind,
+    pub event: &'a str,
+    pub ours: (OnMatch, &'a Filter),
+    pub theirs: (OnMatch, &'a Filter),
+    pub source: &'a str,
+}
+
+impl fmt::Display for Conflict<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            ConflictKind::IncludeExclude => "rule both included and excluded",
+            ConflictKind::Names => "rule under two names",
+        };
+        write!(
+            f,
+            "{} {}: {} {} vs {} {} from {}",
+            self.event,
+            what,
+            self.ours.0.as_str(),
+            describe(self.ours.1),
+            self.theirs.0.as_str(),
+            describe(self.theirs.1),
+            self.source
+        )
+    }
+}
+
+/// Asks how to settle `conflict`, like `git add -p`; an upper-case answer
+/// also applies to every later conflict.
+fn ask(
+    conflict: &Conflict,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<(Resolution, bool)> {
+    writeln!(output, "Merge conflict: {}", conflict)?;
+    loop {
+        write!(
+            output,
+            "Keep [o]urs, [t]heirs, [b]oth or [s]kip both (O/T/B/S: this and all later)? "
+        )?;
+        output.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "no answer given",
+            ));
+        }
+        let answer = answer.trim();
+        let resolution = match answer.to_ascii_lowercase().as_str() {
+            "o" => Resolution::Ours,
+            "t" => Resolution::Theirs,
+            "b" => Resolution::Both,
+            "s" => Resolution::Skip,
+            _ => continue,
+        };
+        let for_all = answer.chars().all(|c| c.is_ascii_uppercase());
+        return Ok((resolution, for_all));
+    }
+}
+
+/// Lists the XML files under `input_dir` in merge order (see
+/// [`merge_order`]), leaving out `output` so a previous result is not
+/// merged into itself.
+pub fn sources(
+    input_dir: &Path,
+    recursive: bool,
+    follow_symlinks: bool,
+    output: &Path,
+) -> Vec<PathBuf> {
+    let max_depth = if recursive { usize::MAX } else { 1 };
+    let files = walk::files(input_dir, max_depth, follow_symlinks)
+        .into_iter()
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("xml"))
+        .filter(|p| p != output && !is_part_of(p, output))
+        .collect();
+    merge_order(input_dir, files)
+}
+
+/// Names a directory lists, one per line, to have its files and
+/// subdirectories merged in that order.
+const ORDER_FILE: &str = ".order";
+
+/// Sorts `files` directory by directory: the names a directory's `.order`
+/// file lists come first, in the order listed, then the rest by numeric
+/// prefix (`2_` before `10_`) and then by name.
+fn merge_order(input_dir: &Path, mut files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut orders: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    files.sort_by_cached_key(|path| {
+        let relative = path.strip_prefix(input_dir).unwrap_or(path);
+        let mut dir = input_dir.to_path_buf();
+        let mut key = Vec::new();
+        for component in relative.components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            let order = orders
+                .entry(dir.clone())
+                .or_insert_with(|| read_order(&dir));
+            let rank = order
+                .iter()
+                .position(|listed| *listed == name)
+                .unwrap_or(order.len());
+            dir.push(&name);
+            key.push((rank, numeric_prefix(&name).unwrap_or(u64::MAX), name));
+        }
+        key
+    });
+    files
+}
+
+/// The names listed in `dir`'s `.order` file; blank lines and `#` comments
+/// are ignored.
+fn read_order(dir: &Path) -> Vec<String> {
+    let path = dir.join(ORDER_FILE);
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    let names: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    for name in &names {
+        if !dir.join(name).exists() {
+            warn!("{} lists {}, which does not exist", path.display(), name);
+        }
+    }
+    debug!(
+        "Merging {} in the order {} lists",
+        dir.display(),
+        path.display()
+    );
+    names
+}
+
+/// `12` for `12_13_14_registry_event`.
+fn numeric_prefix(name: &str) -> Option<u64> {
+    let end = name
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(name.len());
+    name[..end].parse().ok()
+}
+
+/// [`merge_layers`] for a single layer.
+#[cfg(test)]
+pub fn merge_sources(
+    sources: &[PathBuf],
+    workers: Option<usize>,
+    max_depth: usize,
+    vars: &Vars,
+    resolver: &mut Resolver,
+) -> Result<SysmonConfig, ConversionError> {
+    merge_layers(
+        &[sources.to_vec()],
+        workers,
+        include::Limits::new(max_depth),
+        vars,
+        false,
+        false,
+        resolver,
+    )
+}
+
+/// Parses and merges layers of sources in order, expanding `<?include?>`
+/// directives within `includes` and resolving `${VAR}` placeholders
+/// from `vars`. `workers` bounds the parsing threads (default: CPU cores).
+/// Within a layer the first value of a global option wins; across layers,
+/// the last layer that sets it does. With `by_name`, rules are overridden
+/// by name first (see [`override_by_name`]); with `lenient`, sources are
+/// repaired as `--lenient` does before they are parsed.
+pub fn merge_layers(
+    layers: &[Vec<PathBuf>],
+    workers: Option<usize>,
+    includes: include::Limits,
+    vars: &Vars,
+    by_name: bool,
+    lenient: bool,
+    resolver: &mut Resolver,
+) -> Result<SysmonConfig, ConversionError> {
+    let sources: Vec<(usize, &PathBuf)> = layers
+        .iter()
+        .enumerate()
+        .flat_map(|(layer, sources)| sources.iter().map(move |path| (layer, path)))
+        .collect();
+    if sources.is_empty() {
+        return Err(ConversionError::InvalidFile(
+            "No XML files found to merge".to_string(),
+        ));
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers.unwrap_or(0))
+        .build()
+        .map_err(|e| ConversionError::Other(format!("Failed to start merge workers: {}", e)))?;
+    let configs = pool.install(|| {
+        sources
+            .par_iter()
+            .map(|(_, path)| parse(path, includes, vars, lenient))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    debug!("Parsed {} source(s)", configs.len());
+
+    // Each option's value from the first source of the last layer setting it.
+    let mut overrides: Vec<(usize, ConfigOption)> = Vec::new();
+    for ((layer, _), config) in sources.iter().zip(&configs) {
+        for option in &config.options {
+            match overrides.iter_mut().find(|(_, o)| o.name == option.name) {
+                Some(entry) if entry.0 < *layer => *entry = (*layer, option.clone()),
+                Some(_) => {}
+                None => overrides.push((*layer, option.clone())),
+            }
+        }
+    }
+
+    let labels = sources.iter().map(|(_, p)| p.display().to_string());
+    let mut configs: Vec<(String, SysmonConfig)> = labels.zip(configs).collect();
+    if by_name {
+        let replaced = override_by_name(&mut configs)?;
+        info!(
+            "Replaced {} rule(s) by a later rule of the same name",
+            replaced
+        );
+    }
+    let mut merged = merge(configs, resolver)?;
+    for option in &mut merged.options {
+        if let Some((_, value)) = overrides.iter().find(|(_, o)| o.name == option.name) {
+            *option = value.clone();
+        }
+    }
+    Ok(merged)
+}
+
+fn parse(
+    path: &Path,
+    includes: include::Limits,
+    vars: &Vars,
+    lenient: bool,
+) -> Result<SysmonConfig, ConversionError> {
+    let bytes = std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+    let text = encoding::decode(&bytes)
+        .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", path.display(), e)))?;
+    let text = if lenient {
+        repair::repair_file(path, &text)
+    } else {
+        text
+    };
+    let text = include::expand(path, &text, includes)?;
+    let text = vars
+        .substitute(&text, Format::Xml)
+        .map_err(|e| ConversionError::ValidationError(format!("{}: {}", path.display(), e)))?;
+    SysmonConfig::from_xml_str(&text)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))
+}
+
+/// Folds parsed configurations together, in order. Each comes with the
+/// name conflicts report it by.
+pub fn merge(
+    configs: Vec<(String, SysmonConfig)>,
+    resolver: &mut Resolver,
+) -> Result<SysmonConfig, ConversionError> {
+    let mut merged = SysmonConfig {
+        schema_version: String::new(),
+        options: Vec::new(),
+        rule_groups: Vec::new(),
+    };
+    let mut highest: Option<Version> = None;
+    let mut index = Index::default();
+
+    for (source, config) in configs {
+        let version: Version = config
+            .schema_version
+            .parse()
+            .map_err(ConversionError::ValidationError)?;
+        if highest.is_none_or(|h| version > h) {
+            highest = Some(version);
+            merged.schema_version = config.schema_version.clone();
+        }
+
+        for option in config.options {
+            if !merged.options.iter().any(|o| o.name == option.name) {
+                merged.options.push(option);
+            }
+        }
+
+        let events = config
+            .rule_groups
+            .into_iter()
+            .flat_map(|group| {
+                let (name, relation) = (group.name, group.group_relation);
+                group
+                    .events
+                    .into_iter()
+                    .map(move |event| keep_relation(name.as_deref(), relation, event))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ConversionError::ValidationError(format!("{}: {}", source, e)))?;
+        for event in events {
+            let had_filters = !event.filters.is_empty();
+            let mut filters = Vec::with_capacity(event.filters.len());
+            for filter in event.filters {
+                let Some((at, kind)) =
+                    index.find_conflict(&merged, &event.event, event.onmatch, &filter)
+                else {
+                    filters.push(filter);
+                    continue;
+                };
+                let ours = &merged.rule_groups[at.0].events[at.1];
+                let resolution = resolver.resolve(&Conflict {
+                    kind,
+                    event: &event.event,
+                    ours: (ours.onmatch, &ours.filters[at.2]),
+                    theirs: (event.onmatch, &filter),
+                    source: &source,
+                })?;
+                if matches!(resolution, Resolution::Theirs | Resolution::Skip) {
+                    index.dropped.insert(at);
+                }
+                if matches!(resolution, Resolution::Theirs | Resolution::Both) {
+                    filters.push(filter);
+                }
+            }
+            // A filter emptied by conflicts is dropped: an empty exclude
+            // filter would log every event of its type.
+            if had_filters && filters.is_empty() {
+                continue;
+            }
+
+            let existing = merged
+                .rule_groups
+                .iter()
+                .enumerate()
+                .find_map(|(g, group)| {
+                    group
+                        .events
+                        .iter()
+                        .position(|e| e.event == event.event && e.onmatch == event.onmatch)
+                        .map(|e| (g, e))
+                });
+            let (g, e) = existing.unwrap_or_else(|| {
+                merged.rule_groups.push(RuleGroup {
+                    name: None,
+                    group_relation: Some(GroupRelation::Or),
+                    events: vec![EventFilter {
+                        event: event.event.clone(),
+                        onmatch: event.onmatch,
+                        filters: Vec::new(),
+                    }],
+                });
+                (merged.rule_groups.len() - 1, 0)
+            });
+            let merged_filters = &mut merged.rule_groups[g].events[e].filters;
+            for filter in filters {
+                index.add(&event.event, &filter, (g, e, merged_filters.len()));
+                merged_filters.push(filter);
+            }
+        }
+    }
+
+    index.remove_dropped(&mut merged);
+    Ok(merged)
+}
+
+/// `event` from a rule group with `relation`, ready to join a merged
+/// `groupRelation="or"` group. In an `and` group every condition must
+/// match, so its conditions become one `<Rule groupRelation="and">` named
+/// after the group rather than separate rules any one of which would do.
+fn keep_relation(
+    group: Option<&str>,
+    relation: Option<GroupRelation>,
+    mut event: EventFilter,
+) -> Result<EventFilter, String> {
+    if relation != Some(GroupRelation::And) || event.filters.len() <= 1 {
+        return Ok(event);
+    }
+    let fields = std::mem::take(&mut event.filters)
+        .into_iter()
+        .map(|filter| match filter {
+            Filter::Field(field) => Ok(field),
+            Filter::Rule(_) => Err(format!(
+                "{} combines a <Rule> with other conditions in a groupRelation=\"and\" group, \
+                 which cannot be merged into an \"or\" group",
+                event.event
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    event.filters.push(Filter::Rule(Rule {
+        name: group.map(str::to_string),
+        group_relation: GroupRelation::And,
+        fields,
+    }));
+    Ok(event)
+}
+
+/// Drops every named rule for which a later source has a rule with the
+/// same name under the same event type, so that the later rule replaces it
+/// instead of joining it, and returns how many were dropped. Event filters
+/// left empty are dropped too: an empty exclude would log everything.
+///
+/// Fails when a source holds more than one rule with a name a later source
+/// overrides: names such as sysmon-modular's technique tags are shared by
+/// many rules, and replacing them all is rarely what was meant.
+pub fn override_by_name(configs: &mut [(String, SysmonConfig)]) -> Result<usize, ConversionError> {
+    let mut last: HashMap<(String, String), usize> = HashMap::new();
+    let mut counts: BTreeMap<(usize, String, String), usize> = BTreeMap::new();
+    for (i, (_, config)) in configs.iter().enumerate() {
+        for event in config.events() {
+            for name in event.filters.iter().filter_map(name) {
+                if !name.is_empty() {
+                    last.insert((event.event.clone(), name.to_string()), i);
+                    *counts
+                        .entry((i, event.event.clone(), name.to_string()))
+                        .or_default() += 1;
+                }
+            }
+        }
+    }
+    for ((i, event, name), count) in &counts {
+        let key = (event.clone(), name.clone());
+        if *count > 1 && last.get(&key).is_some_and(|later| later > i) {
+            return Err(ConversionError::ValidationError(format!(
+                "{} has {} {} rules named {:?}; --override-by-name needs a name unique to one \
+                 rule, so rename the rule to override",
+                configs[*i].0, count, event, name
+            )));
+        }
+    }
+
+    let mut dropped = 0;
+    for (i, (source, config)) in configs.iter_mut().enumerate() {
+        for group in &mut config.rule_groups {
+            group.events.retain_mut(|event| {
+                let had_filters = !event.filters.is_empty();
+                event.filters.retain(|filter| {
+                    let Some(name) = name(filter) else {
+                        return true;
+                    };
+                    let key = (event.event.clone(), name.to_string());
+                    if last.get(&key).is_some_and(|&later| later > i) {
+                        debug!(
+                            "{}: {} rule {:?} replaced by a later source",
+                            source, key.0, name
+                        );
+                        dropped += 1;
+                        return false;
+                    }
+                    true
+                });
+                !had_filters || !event.filters.is_empty()
+            });
+        }
+        config.rule_groups.retain(|group| !group.events.is_empty());
+    }
+    Ok(dropped)
+}
+
+/// Where a merged rule is: rule group, event filter and filter.
+type At = (usize, usize, usize);
+
+/// The merged rules by the event, field and value of their first
+/// condition, so a conflict is found without scanning every rule. Rules a
+/// resolution drops stay in place until the merge is done, which keeps
+/// the positions recorded valid.
+#[derive(Default)]
+struct Index {
+    rules: HashMap<(String, String, String), Vec<At>>,
+    dropped: HashSet<At>,
+}
+
+impl Index {
+    fn key(event: &str, filter: &Filter) -> Option<(String, String, String)> {
+        let first = match filter {
+            Filter::Field(field) => field,
+            Filter::Rule(rule) => rule.fields.first()?,
+        };
+        Some((event.to_string(), first.field.clone(), first.value.clone()))
+    }
+
+    fn add(&mut self, event: &str, filter: &Filter, at: At) {
+        if let Some(key) = Self::key(event, filter) {
+            self.rules.entry(key).or_default().push(at);
+        }
+    }
+
+    /// The merged rule `filter` conflicts with, if any.
+    fn find_conflict(
+        &self,
+        merged: &SysmonConfig,
+        event: &str,
+        onmatch: OnMatch,
+        filter: &Filter,
+    ) -> Option<(At, ConflictKind)> {
+        let candidates = self.rules.get(&Self::key(event, filter)?)?;
+        for &at in candidates.iter().filter(|at| !self.dropped.contains(at)) {
+            let existing = &merged.rule_groups[at.0].events[at.1];
+            let other = &existing.filters[at.2];
+            if !same_test(filter, other) {
+                continue;
+            }
+            if existing.onmatch != onmatch {
+                return Some((at, ConflictKind::IncludeExclude));
+            }
+            if name(filter) != name(other) {
+                return Some((at, ConflictKind::Names));
+            }
+        }
+        None
+    }
+
+    /// Removes the dropped rules from `merged`, then the event filters they
+    /// emptied and the rule groups left without any.
+    fn remove_dropped(&self, merged: &mut SysmonConfig) {
+        if self.dropped.is_empty() {
+            return;
+        }
+        for (g, group) in merged.rule_groups.iter_mut().enumerate() {
+            let mut e = 0;
+            group.events.retain_mut(|event| {
+                let had_filters = !event.filters.is_empty();
+                let mut f = 0;
+                event.filters.retain(|_| {
+                    f += 1;
+                    !self.dropped.contains(&(g, e, f - 1))
+                });
+                e += 1;
+                !had_filters || !event.filters.is_empty()
+            });
+        }
+        merged.rule_groups.retain(|group| !group.events.is_empty());
+    }
+}
+
+/// Whether two rules test the same thing, whatever their names.
+fn same_test(a: &Filter, b: &Filter) -> bool {
+    let same = |a: &FieldCondition, b: &FieldCondition| {
+        a.field == b.field && a.condition == b.condition && a.value == b.value
+    };
+    match (a, b) {
+        (Filter::Field(a), Filter::Field(b)) => same(a, b),
+        (Filter::Rule(a), Filter::Rule(b)) => {
+            a.group_relation == b.group_relation
+                && a.fields.len() == b.fields.len()
+                && a.fields.iter().zip(&b.fields).all(|(a, b)| same(a, b))
+        }
+        _ => false,
+    }
+}
+
+fn name(filter: &Filter) -> Option<&str> {
+    match filter {
+        Filter::Field(field) => field.name.as_deref(),
+        Filter::Rule(rule) => rule.name.as_deref(),
+    }
+}
+
+fn describe(filter: &Filter) -> String {
+    let condition =
+        |f: &FieldCondition| format!("{} {} {:?}", f.field, f.condition.as_str(), f.value);
+    let text = match filter {
+        Filter::Field(field) => condition(field),
+        Filter::Rule(rule) => format!(
+            "Rule {}: {}",
+            rule.group_relation.as_str(),
+            rule.fields
+                .iter()
+                .map(condition)
+                .collect::<Vec<_>>()
+                .join("; ")
+        ),
+    };
+    match name(filter) {
+        Some(name) => format!("[{}] {}", name, text),
+        None => text,
+    }
+}
+
+/// Parses a `--template` file, expanding includes and placeholders as for
+/// a source.
+pub fn template(
+    path: &Path,
+    includes: include::Limits,
+    vars: &Vars,
+    lenient: bool,
+) -> Result<SysmonConfig, ConversionError> {
+    parse(path, includes, vars, lenient)
+}
+
+/// Puts the rules of `merged` into `template`. The result has the
+/// template's schema version and global options, followed by any option
+/// only the sources set, and the template's own rules ahead of the merged
+/// ones; rule groups the template leaves empty are dropped.
+pub fn apply_template(mut template: SysmonConfig, merged: SysmonConfig) -> SysmonConfig {
+    if let (Ok(base), Ok(sources)) = (
+        template.schema_version.parse::<Version>(),
+        merged.schema_version.parse::<Version>(),
+    ) {
+        if sources > base {
+            warn!(
+                "The sources use schema {} but the template declares {}: rules newer than {} will not validate",
+                sources, base, base
+            );
+        }
+    }
+    for option in merged.options {
+        if !template.options.iter().any(|o| o.name == option.name) {
+            template.options.push(option);
+        }
+    }
+    template
+        .rule_groups
+        .retain(|group| !group.events.is_empty());
+    template.rule_groups.extend(merged.rule_groups);
+    template
+}
+
+/// Moves the rules of rule groups that can share one into the first of
+/// them, and returns how many groups that emptied and removed. Two event
+/// filters can share a group when they have the same event type and
+/// `onmatch`, both have rules, and both sit in `groupRelation="or"` groups;
+/// a group whose event filters each hold a single rule counts as `or`,
+/// since the relation makes no difference there. `and` groups with several
+/// rules, and empty event filters (an empty exclude logs everything), are
+/// left as they are.
+pub fn coalesce(config: &mut SysmonConfig) -> usize {
+    let before = config.rule_groups.len();
+    let mut groups: Vec<RuleGroup> = Vec::with_capacity(before);
+    for mut group in std::mem::take(&mut config.rule_groups) {
+        if !is_or(&group) {
+            groups.push(group);
+            continue;
+        }
+        if group.group_relation == Some(GroupRelation::And) {
+            group.group_relation = Some(GroupRelation::Or);
+        }
+        let mut events: Vec<EventFilter> = Vec::with_capacity(group.events.len());
+        for event in group.events {
+            let target = groups
+                .iter_mut()
+                .filter(|g| is_or(g))
+                .flat_map(|g| g.events.iter_mut())
+                .chain(events.iter_mut())
+                .find(|e| {
+                    e.event == event.event && e.onmatch == event.onmatch && !e.filters.is_empty()
+                });
+            match target {
+                Some(target) if !event.filters.is_empty() => target.filters.extend(event.filters),
+                _ => events.push(event),
+            }
+        }
+        group.events = events;
+        if !group.events.is_empty() {
+            groups.push(group);
+        }
+    }
+    config.rule_groups = groups;
+    before - config.rule_groups.len()
+}
+
+/// Whether `group` combines its rules with `or`, explicitly or because
+/// each of its event filters has a single rule.
+fn is_or(group: &RuleGroup) -> bool {
+    group.group_relation != Some(GroupRelation::And)
+        || group.events.iter().all(|e| e.filters.len() <= 1)
+}
+
+/// Drops the event filters `platform` does not support, and rule groups
+/// left empty by that.
+pub fn retain_platform(config: &mut SysmonConfig, platform: Platform) {
+    let mut dropped = std::collections::BTreeSet::new();
+    for group in &mut config.rule_groups {
+        group.events.retain(|event| {
+            let keep = platform.supports(&event.event);
+            if !keep {
+                dropped.insert(event.event.clone());
+            }
+            keep
+        });
+    }
+    config.rule_groups.retain(|group| !group.events.is_empty());
+    if !dropped.is_empty() {
+        warn!(
+            "Dropped event types not available on {}: {}",
+            platform,
+            dropped.into_iter().collect::<Vec<_>>().join(", ")
+        );
+    }
+}
+
+/// The files to write for a merged config: `output` alone, or numbered
+/// parts next to it when the XML is over `max_bytes`.
+pub fn outputs(
+    config: SysmonConfig,
+    output: &Path,
+    max_bytes: Option<usize>,
+) -> Result<Vec<(PathBuf, String)>, ConversionError> {
+    let xml = config.to_xml_string()?;
+    let Some(max_bytes) = max_bytes.filter(|&max| xml.len() > max) else {
+        return Ok(vec![(output.to_path_buf(), xml)]);
+    };
+
+    let parts = split(config, max_bytes)?;
+    warn!(
+        "Merged config is {} KB, over the {} KB limit; split into {} configs by event type",
+        xml.len().div_ceil(1024),
+        max_bytes / 1024,
+        parts.len()
+    );
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| Ok((part_path(output, i + 1), part.to_xml_string()?)))
+        .collect()
+}
+
+/// Numbered parts of `output` that an earlier merge left and `outputs` does
+/// not write again, in name order: deployed with the new parts, they would
+/// bring back rules the merge no longer has.
+pub fn stale_parts<T>(output: &Path, outputs: &[(PathBuf, T)]) -> Vec<PathBuf> {
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut stale: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(output.with_file_name(entry.ok()?.file_name())))
+        .filter(|path| is_part_of(path, output) && !outputs.iter().any(|(p, _)| p == path))
+        .collect();
+    stale.sort();
+    stale
+}
+
+/// Splits `config` into standalone configs whose XML fits in `max_bytes`,
+/// keeping each event type's rule groups together.
+pub fn split(config: SysmonConfig, max_bytes: usize) -> Result<Vec<SysmonConfig>, ConversionError> {
+    let with_groups = |rule_groups: Vec<RuleGroup>| SysmonConfig {
+        schema_version: config.schema_version.clone(),
+        options: config.options.clone(),
+        rule_groups,
+    };
+    let base = with_groups(Vec::new()).to_xml_string()?.len();
+
+    let mut events: Vec<(String, Vec<RuleGroup>)> = Vec::new();
+    for group in &config.rule_groups {
+        let event = group
+            .events
+            .first()
+            .map(|e| e.event.clone())
+            .unwrap_or_default();
+        match events.iter_mut().find(|(name, _)| *name == event) {
+            Some((_, groups)) => groups.push(group.clone()),
+            None => events.push((event, vec![group.clone()])),
+        }
+    }
+
+    let mut sizes = Vec::with_capacity(events.len());
+    for (event, groups) in &events {
+        let size = with_groups(groups.clone()).to_xml_string()?.len();
+        if size > max_bytes {
+            return Err(ConversionError::ValidationError(format!(
+                "The {} rules alone take {} KB, over the {} KB output limit",
+                event,
+                size.div_ceil(1024),
+                max_bytes / 1024
+            )));
+        }
+        sizes.push(size - base);
+    }
+
+    let capacity = max_bytes.saturating_sub(base).max(1);
+    let fewest = sizes.iter().sum::<usize>().div_ceil(capacity).max(1);
+    for count in fewest..=events.len() {
+        let Some(assignment) = pack(&sizes, count, capacity) else {
+            continue;
+        };
+        let parts: Vec<SysmonConfig> = (0..count)
+            .map(|part| {
+                with_groups(
+                    events
+                        .iter()
+                        .zip(&assignment)
+                        .filter(|(_, &assigned)| assigned == part)
+                        .flat_map(|((_, groups), _)| groups.clone())
+                        .collect(),
+                )
+            })
+            .collect();
+        let mut fits = true;
+        for part in &parts {
+            fits &= part.to_xml_string()?.len() <= max_bytes;
+        }
+        if fits {
+            return Ok(parts);
+        }
+    }
+
+    // One event type per part always fits: each was checked above.
+    Ok(events
+        .into_iter()
+        .map(|(_, groups)| with_groups(groups))
+        .collect())
+}
+
+/// Assigns items to `count` bins, largest first into the emptiest bin.
+/// Returns each item's bin, or `None` if some bin would exceed `capacity`.
+fn pack(sizes: &[usize], count: usize, capacity: usize) -> Option<Vec<usize>> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+
+    let mut loads = vec![0; count];
+    let mut assignment = vec![0; sizes.len()];
+    for i in order {
+        let (bin, load) = loads
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, load)| *load)
+            .map(|(bin, &load)| (bin, load))?;
+        if load + sizes[i] > capacity {
+            return None;
+        }
+        loads[bin] += sizes[i];
+        assignment[i] = bin;
+    }
+    Some(assignment)
+}
+
+/// `merged.xml` -> `merged.<n>.xml`.
+fn part_path(output: &Path, n: usize) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match output.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, n),
+    };
+    output.with_file_name(name)
+}
+
+/// Whether `path` is one of the numbered parts written for `output`.
+fn is_part_of(path: &Path, output: &Path) -> bool {
+    if path.parent() != output.parent() {
+        return false;
+    }
+    let (Some(name), Some(stem)) = (path.file_name(), output.file_stem()) else {
+        return false;
+    };
+    let name = name.to_string_lossy();
+    let suffix = output
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    name.strip_prefix(&format!("{}.", stem.to_string_lossy()))
+        .and_then(|rest| rest.strip_suffix(&suffix))
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn both() -> Resolver {
+        Resolver::Fixed(Resolution::Both)
+    }
+
+    fn write_sources(dir: &Path) -> Vec<PathBuf> {
+        let files = [
+            (
+                "1_process.xml",
+                r#"<Sysmon schemaversion="4.50"><HashAlgorithms>sha1</HashAlgorithms><EventFiltering>
+                <RuleGroup groupRelation="or"><ProcessCreate onmatch="include">
+                <Image condition="end with">a.exe</Image></ProcessCreate></RuleGroup>
+                </EventFiltering></Sysmon>"#,
+            ),
+            (
+                "2_network.xml",
+                r#"<Sysmon schemaversion="4.90"><HashAlgorithms>sha256</HashAlgorithms><EventFiltering>
+                <RuleGroup groupRelation="or"><NetworkConnect onmatch="include">
+                <DestinationPort condition="is">443</DestinationPort></NetworkConnect></RuleGroup>
+                <RuleGroup groupRelation="or"><ProcessCreate onmatch="include">
+                <Image condition="end with">b.exe</Image></ProcessCreate></RuleGroup>
+                </EventFiltering></Sysmon>"#,
+            ),
+        ];
+        files
+            .iter()
+            .map(|(name, xml)| {
+                let path = dir.join(name);
+                fs::write(&path, xml).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn merge_files(sources: &[PathBuf], workers: Option<usize>) -> String {
+        merge_sources(sources, workers, 10, &Vars::default(), &mut both())
+            .unwrap()
+            .to_xml_string()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_merge_combines_events_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = write_sources(dir.path());
+        let merged = SysmonConfig::from_xml_str(&merge_files(&sources, Some(1))).unwrap();
+
+        assert_eq!(merged.schema_version, "4.90");
+        assert_eq!(merged.options.len(), 1);
+        assert_eq!(merged.options[0].value, "sha1");
+        let events: Vec<(&str, usize)> = merged
+            .events()
+            .map(|e| (e.event.as_str(), e.filters.len()))
+            .collect();
+        assert_eq!(events, vec![("ProcessCreate", 2), ("NetworkConnect", 1)]);
+    }
+
+    #[test]
+    fn test_merge_output_does_not_depend_on_workers() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = write_sources(dir.path());
+        assert_eq!(
+            merge_files(&sources, Some(1)),
+            merge_files(&sources, Some(4))
+        );
+    }
+
+    /// The merge of [`write_sources`] plus an `and` group, whatever the
+    /// number of workers.
+    const GOLDEN: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Sysmon schemaversion="4.90">
+  <HashAlgorithms>sha1</HashAlgorithms>
+  <EventFiltering>
+    <RuleGroup groupRelation="or">
+      <ProcessCreate onmatch="include">
+        <Image condition="end with">a.exe</Image>
+        <Image condition="end with">b.exe</Image>
+        <Rule name="encoded" groupRelation="and">
+          <Image condition="end with">powershell.exe</Image>
+          <CommandLine condition="contains">-enc</CommandLine>
+        </Rule>
+      </ProcessCreate>
+    </RuleGroup>
+    <RuleGroup groupRelation="or">
+      <NetworkConnect onmatch="include">
+        <DestinationPort condition="is">443</DestinationPort>
+      </NetworkConnect>
+    </RuleGroup>
+  </EventFiltering>
+</Sysmon>"#;
+
+    #[test]
+    fn test_merge_matches_golden_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sources = write_sources(dir.path());
+        let and = dir.path().join("3_and.xml");
+        fs::write(
+            &and,
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+            <RuleGroup name="encoded" groupRelation="and"><ProcessCreate onmatch="include">
+            <Image condition="end with">powershell.exe</Image>
+            <CommandLine condition="contains">-enc</CommandLine></ProcessCreate></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        sources.push(and);
+
+        let sequential = merge_files(&sources, Some(1));
+        assert_eq!(sequential, GOLDEN);
+        assert_eq!(merge_files(&sources, Some(8)), sequential);
+    }
+
+    #[test]
+    fn test_merge_layers_later_layers_override_options() {
+        let upstream = tempfile::tempdir().unwrap();
+        let sources = write_sources(upstream.path());
+        let local = tempfile::tempdir().unwrap();
+        let overrides = local.path().join("overrides.xml");
+        fs::write(
+            &overrides,
+            r#"<Sysmon schemaversion="4.90"><HashAlgorithms>md5</HashAlgorithms><EventFiltering>
+            <RuleGroup groupRelation="or"><ProcessCreate onmatch="include">
+            <Image condition="end with">c.exe</Image></ProcessCreate></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let layers = [sources, vec![overrides]];
+        let merged = merge_layers(
+            &layers,
+            Some(2),
+            include::Limits::new(10),
+            &Vars::default(),
+            false,
+            false,
+            &mut both(),
+        )
+        .unwrap();
+        assert_eq!(merged.options[0].value, "md5");
+        let process = merged
+            .events()
+            .find(|e| e.event == "ProcessCreate")
+            .unwrap();
+        assert_eq!(process.filters.len(), 3);
+    }
+
+    #[test]
+    fn test_lenient_merge_repairs_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("ampersand.xml");
+        fs::write(
+            &source,
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+            <ProcessCreate onmatch="include"><CommandLine condition="contains">a & b</CommandLine>
+            </ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        let layers = [vec![source]];
+        let merge = |lenient| {
+            merge_layers(
+                &layers,
+                Some(1),
+                include::Limits::new(10),
+                &Vars::default(),
+                false,
+                lenient,
+                &mut both(),
+            )
+        };
+
+        assert!(merge(false).is_err());
+        let merged = merge(true).unwrap();
+        assert_eq!(merged.events().next().unwrap().filters.len(), 1);
+    }
+
+    #[test]
+    fn test_override_by_name_replaces_earlier_rules() {
+        let config = |xml: &str| SysmonConfig::from_xml_str(xml).unwrap();
+        let mut configs = vec![
+            (
+                "upstream.xml".to_string(),
+                config(
+                    r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+                    <ProcessCreate onmatch="include">
+                    <Image name="powershell" condition="end with">\powershell.exe</Image>
+                    <Image name="cmd" condition="end with">\cmd.exe</Image>
+                    </ProcessCreate>
+                    <NetworkConnect onmatch="exclude">
+                    <Image name="powershell" condition="is">C:\x.exe</Image>
+                    </NetworkConnect>
+                    </RuleGroup></EventFiltering></Sysmon>"#,
+                ),
+            ),
+            (
+                "local.xml".to_string(),
+                config(
+                    r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+                    <ProcessCreate onmatch="include">
+                    <CommandLine name="powershell" condition="contains">-enc</CommandLine>
+                    </ProcessCreate>
+                    </RuleGroup></EventFiltering></Sysmon>"#,
+                ),
+            ),
+        ];
+        assert_eq!(override_by_name(&mut configs).unwrap(), 1);
+
+        let merged = merge(configs, &mut both()).unwrap();
+        let rules: Vec<(&str, &str)> = merged
+            .events()
+            .flat_map(|e| {
+                e.filters
+                    .iter()
+                    .map(move |f| (e.event.as_str(), name(f).unwrap()))
+            })
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                ("ProcessCreate", "cmd"),
+                ("ProcessCreate", "powershell"),
+                ("NetworkConnect", "powershell"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_override_by_name_refuses_shared_names() {
+        let config = |xml: &str| SysmonConfig::from_xml_str(xml).unwrap();
+        let upstream = config(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+            <ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059" condition="end with">\powershell.exe</Image>
+            <Image name="technique_id=T1059" condition="end with">\cmd.exe</Image>
+            </ProcessCreate>
+            </RuleGroup></EventFiltering></Sysmon>"#,
+        );
+        let local = config(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+            <ProcessCreate onmatch="include">
+            <CommandLine name="technique_id=T1059" condition="contains">-enc</CommandLine>
+            </ProcessCreate>
+            </RuleGroup></EventFiltering></Sysmon>"#,
+        );
+
+        // Shared names are fine as long as nothing overrides them.
+        let mut configs = vec![("upstream.xml".to_string(), upstream.clone())];
+        assert_eq!(override_by_name(&mut configs).unwrap(), 0);
+
+        let mut configs = vec![
+            ("upstream.xml".to_string(), upstream),
+            ("local.xml".to_string(), local),
+        ];
+        let error = override_by_name(&mut configs).unwrap_err().to_string();
+        assert!(
+            error.contains("upstream.xml has 2 ProcessCreate rules named"),
+            "{}",
+            error
+        );
+        assert_eq!(configs[0].1.events().next().unwrap().filters.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_output_does_not_depend_on_creation_order() {
+        let first = tempfile::tempdir().unwrap();
+        let written = write_sources(first.path());
+        let second = tempfile::tempdir().unwrap();
+        for path in written.iter().rev() {
+            fs::copy(path, second.path().join(path.file_name().unwrap())).unwrap();
+        }
+
+        let merged: Vec<String> = [first.path(), second.path()]
+            .iter()
+            .map(|dir| merge_files(&sources(dir, false, false, &dir.join("out.xml")), Some(4)))
+            .collect();
+        assert_eq!(merged[0], merged[1]);
+    }
+
+    #[test]
+    fn test_retain_platform_drops_windows_only_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = write_sources(dir.path());
+        let mut merged =
+            merge_sources(&sources, Some(1), 10, &Vars::default(), &mut both()).unwrap();
+        merged.rule_groups[1].events[0].event = "RegistryEvent".to_string();
+
+        retain_platform(&mut merged, Platform::Linux);
+        let events: Vec<&str> = merged.events().map(|e| e.event.as_str()).collect();
+        assert_eq!(events, vec!["ProcessCreate"]);
+    }
+
+    #[test]
+    fn test_split_keeps_event_types_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = write_sources(dir.path());
+        let merged = merge_sources(&sources, Some(1), 10, &Vars::default(), &mut both()).unwrap();
+        let whole = merged.to_xml_string().unwrap().len();
+
+        let output = dir.path().join("merged.xml");
+        let files = outputs(merged, &output, Some(whole - 1)).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, dir.path().join("merged.1.xml"));
+        for (_, xml) in &files {
+            assert!(xml.len() < whole);
+            let part = SysmonConfig::from_xml_str(xml).unwrap();
+            assert_eq!(part.schema_version, "4.90");
+            assert_eq!(part.options.len(), 1);
+            assert_eq!(part.events().count(), 1);
+        }
+
+        assert!(is_part_of(&files[1].0, &output));
+        assert!(!is_part_of(&dir.path().join("merged.old.xml"), &output));
+
+        // Parts beyond the new count are left over from a larger merge.
+        for n in 1..=3 {
+            fs::write(dir.path().join(format!("merged.{}.xml", n)), "").unwrap();
+        }
+        assert_eq!(
+            stale_parts(&output, &files),
+            [dir.path().join("merged.3.xml")]
+        );
+        let whole_file = [(output.clone(), String::new())];
+        assert_eq!(stale_parts(&output, &whole_file).len(), 3);
+        assert!(split(
+            merge_sources(&sources, Some(1), 10, &Vars::default(), &mut both()).unwrap(),
+            whole / 4
+        )
+        .is_err());
+    }
+
+    fn conflicting() -> Vec<(String, SysmonConfig)> {
+        let config = |onmatch: &str, name: &str| {
+            SysmonConfig::from_xml_str(&format!(
+                r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+                <ProcessCreate onmatch="{}"><Image name="{}" condition="is">a.exe</Image>
+                </ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#,
+                onmatch, name
+            ))
+            .unwrap()
+        };
+        vec![
+            ("1.xml".to_string(), config("include", "a")),
+            ("2.xml".to_string(), config("exclude", "a")),
+            ("3.xml".to_string(), config("include", "b")),
+        ]
+    }
+
+    #[test]
+    fn test_merge_resolves_conflicts() {
+        let rules = |resolution| {
+            let merged = merge(conflicting(), &mut Resolver::Fixed(resolution)).unwrap();
+            merged
+                .events()
+                .flat_map(|e| {
+                    e.filters
+                        .iter()
+                        .map(move |f| format!("{} {}", e.onmatch.as_str(), describe(f)))
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            rules(Resolution::Ours),
+            vec![r#"include [a] Image is "a.exe""#]
+        );
+        assert_eq!(
+            rules(Resolution::Theirs),
+            vec![r#"include [b] Image is "a.exe""#]
+        );
+        assert_eq!(
+            rules(Resolution::Skip),
+            vec![r#"include [b] Image is "a.exe""#]
+        );
+        assert_eq!(
+            rules(Resolution::Both),
+            vec![
+                r#"include [a] Image is "a.exe""#,
+                r#"include [b] Image is "a.exe""#,
+                r#"exclude [a] Image is "a.exe""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_finds_conflicts_under_any_event() {
+        let config = |xml: &str| {
+            SysmonConfig::from_xml_str(&format!(
+                r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">{}
+                </RuleGroup></EventFiltering></Sysmon>"#,
+                xml
+            ))
+            .unwrap()
+        };
+        let rules: String = (0..50)
+            .map(|i| format!(r#"<Image condition="is">{}.exe</Image>"#, i))
+            .collect();
+        let configs = vec![
+            (
+                "1.xml".to_string(),
+                config(&format!(
+                    r#"<ProcessCreate onmatch="include">{}</ProcessCreate>
+                    <NetworkConnect onmatch="include"><DestinationPort condition="is">22</DestinationPort>
+                    <Image condition="is">7.exe</Image></NetworkConnect>"#,
+                    rules
+                )),
+            ),
+            (
+                "2.xml".to_string(),
+                config(
+                    r#"<NetworkConnect onmatch="exclude"><Image condition="is">7.exe</Image>
+                    </NetworkConnect>"#,
+                ),
+            ),
+        ];
+
+        let merged = merge(configs, &mut Resolver::Fixed(Resolution::Skip)).unwrap();
+        let counts: Vec<(&str, usize)> = merged
+            .events()
+            .map(|e| (e.event.as_str(), e.filters.len()))
+            .collect();
+        assert_eq!(counts, [("ProcessCreate", 50), ("NetworkConnect", 1)]);
+    }
+
+    #[test]
+    fn test_ask_repeats_until_answered() {
+        let configs = conflicting();
+        let filter = &configs[0].1.rule_groups[0].events[0].filters[0];
+        let other = &configs[2].1.rule_groups[0].events[0].filters[0];
+        let conflict = Conflict {
+            kind: ConflictKind::Names,
+            event: "ProcessCreate",
+            ours: (OnMatch::Include, filter),
+            theirs: (OnMatch::Include, other),
+            source: "3.xml",
+        };
+
+        let mut output = Vec::new();
+        let answer = ask(&conflict, &mut "x\nT\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answer, (Resolution::Theirs, true));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(
+            r#"Merge conflict: ProcessCreate rule under two names: include [a] Image is "a.exe" vs include [b] Image is "a.exe" from 3.xml"#
+        ));
+        assert_eq!(output.matches("Keep [o]urs").count(), 2);
+
+        let answer = ask(&conflict, &mut "b\n".as_bytes(), &mut Vec::new()).unwrap();
+        assert_eq!(answer, (Resolution::Both, false));
+        assert!(ask(&conflict, &mut "".as_bytes(), &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_sources_follow_order_files_and_numeric_prefixes() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "baseconfig.xml",
+            "10_image_load/a.xml",
+            "2_network/a.xml",
+            "3_custom/a.xml",
+            "3_custom/b.xml",
+            "3_custom/c.xml",
+        ] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "<Sysmon/>").unwrap();
+        }
+        std::fs::write(
+            dir.path().join("3_custom/.order"),
+            "# reviewed first\nc.xml\n\na.xml\n",
+        )
+        .unwrap();
+
+        let output = dir.path().join("merged.xml");
+        let names = || -> Vec<String> {
+            sources(dir.path(), true, false, &output)
+                .iter()
+                .map(|p| {
+                    let relative = p.strip_prefix(dir.path()).unwrap();
+                    relative.to_string_lossy().replace('\\', "/")
+                })
+                .collect()
+        };
+        // Unprefixed names, baseconfig.xml among them, follow the numbered ones.
+        assert_eq!(
+            names(),
+            [
+                "2_network/a.xml",
+                "3_custom/c.xml",
+                "3_custom/a.xml",
+                "3_custom/b.xml",
+                "10_image_load/a.xml",
+                "baseconfig.xml",
+            ]
+        );
+
+        std::fs::write(dir.path().join(".order"), "baseconfig.xml\n").unwrap();
+        assert_eq!(
+            names(),
+            [
+                "baseconfig.xml",
+                "2_network/a.xml",
+                "3_custom/c.xml",
+                "3_custom/a.xml",
+                "3_custom/b.xml",
+                "10_image_load/a.xml",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_template_keeps_template_root_and_options() {
+        let template = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.50"><HashAlgorithms>*</HashAlgorithms><CheckRevocation/><EventFiltering><RuleGroup name="" groupRelation="or"></RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        let merged = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.50"><HashAlgorithms>md5</HashAlgorithms><DnsLookup>False</DnsLookup><EventFiltering><RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include"><Image condition="is">a.exe</Image></ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let result = apply_template(template, merged);
+        assert_eq!(result.schema_version, "4.50");
+        let options: Vec<(&str, &str)> = result
+            .options
+            .iter()
+            .map(|o| (o.name.as_str(), o.value.as_str()))
+            .collect();
+        assert_eq!(
+            options,
+            [
+                ("HashAlgorithms", "*"),
+                ("CheckRevocation", ""),
+                ("DnsLookup", "False")
+            ]
+        );
+        assert_eq!(result.rule_groups.len(), 1);
+        assert_eq!(result.rule_groups[0].events[0].event, "ProcessCreate");
+    }
+
+    #[test]
+    fn test_coalesce_combines_compatible_groups() {
+        let mut config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+            <RuleGroup name="first" groupRelation="or"><ProcessCreate onmatch="include"><Image condition="is">a.exe</Image></ProcessCreate></RuleGroup>
+            <RuleGroup name="" groupRelation="and"><ProcessCreate onmatch="include"><Image condition="is">b.exe</Image></ProcessCreate></RuleGroup>
+            <RuleGroup name="" groupRelation="and"><ProcessCreate onmatch="include"><Image condition="is">c.exe</Image><User condition="is">SYSTEM</User></ProcessCreate></RuleGroup>
+            <RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="exclude"><Image condition="is">d.exe</Image></ProcessCreate><DnsQuery onmatch="exclude"/></RuleGroup>
+            <RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include"><Image condition="is">e.exe</Image></ProcessCreate><DnsQuery onmatch="exclude"/></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        assert_eq!(coalesce(&mut config), 1);
+        let events: Vec<Vec<(&str, usize)>> = config
+            .rule_groups
+            .iter()
+            .map(|g| {
+                g.events
+                    .iter()
+                    .map(|e| (e.event.as_str(), e.filters.len()))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                vec![("ProcessCreate", 3)],
+                vec![("ProcessCreate", 2)],
+                vec![("ProcessCreate", 1), ("DnsQuery", 0)],
+                vec![("DnsQuery", 0)],
+            ]
+        );
+        assert_eq!(config.rule_groups[0].name.as_deref(), Some("first"));
+        assert_eq!(coalesce(&mut config), 0);
+    }
+
+    #[test]
+    fn test_merge_keeps_newer_event_types() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/events");
+        let sources = sources(&fixtures, false, false, &fixtures.join("merged.xml"));
+        let merged = merge_sources(&sources, None, 10, &Vars::default(), &mut both()).unwrap();
+
+        let events: Vec<(&str, usize)> = merged
+            .events()
+            .map(|e| (e.event.as_str(), e.filters.len()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("DnsQuery", 2),
+                ("FileDelete", 2),
+                ("ClipboardChange", 2),
+                ("ProcessTampering", 2),
+                ("FileDeleteDetected", 2),
+                ("FileBlockExecutable", 1),
+                ("FileBlockShredding", 2),
+                ("FileExecutableDetected", 2),
+            ]
+        );
+        assert_eq!(merged.options[0].name, "CaptureClipboard");
+        assert!(sysmon_cli::validation::validate(&merged).is_empty());
+    }
+}
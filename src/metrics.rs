@@ -0,0 +1,189 @@
+//! Counters for `serve`'s `/metrics` endpoint, in the Prometheus text
+//! exposition format.
+//!
+//! Only the processing endpoints are counted. A request that fails is
+//! counted under the kind of its error (the same names `--error-format
+//! json` uses), or `request` when it was rejected before any processing,
+//! e.g. for a missing body or an unsupported method.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+/// Upper bounds of the duration histogram's buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations at or under each of [`BUCKETS`].
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// One request's outcome.
+pub struct Observation {
+    pub endpoint: &'static str,
+    /// Configs processed: uploaded parts for a merge, otherwise one.
+    pub files: u64,
+    /// Request body bytes.
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// The kind of error the request failed with, if it failed.
+    pub failure: Option<&'static str>,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    files: BTreeMap<&'static str, u64>,
+    bytes: BTreeMap<&'static str, u64>,
+    failures: BTreeMap<&'static str, u64>,
+    durations: BTreeMap<&'static str, Histogram>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, observation: &Observation) {
+        let endpoint = observation.endpoint;
+        match observation.failure {
+            Some(kind) => *self.failures.entry(kind).or_default() += 1,
+            None => {
+                *self.files.entry(endpoint).or_default() += observation.files;
+                *self.bytes.entry(endpoint).or_default() += observation.bytes;
+            }
+        }
+
+        let seconds = observation.elapsed.as_secs_f64();
+        let histogram = self.durations.entry(endpoint).or_default();
+        for (bound, bucket) in BUCKETS.iter().zip(&mut histogram.buckets) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    /// The metrics in the Prometheus text format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        family(
+            &mut out,
+            "sysmon_helper_files_processed_total",
+            "counter",
+            "Configs processed successfully, by endpoint.",
+        );
+        for (endpoint, count) in &self.files {
+            let _ = writeln!(
+                out,
+                "sysmon_helper_files_processed_total{{endpoint=\"{}\"}} {}",
+                endpoint, count
+            );
+        }
+        family(
+            &mut out,
+            "sysmon_helper_bytes_processed_total",
+            "counter",
+            "Request bytes of configs processed successfully, by endpoint.",
+        );
+        for (endpoint, bytes) in &self.bytes {
+            let _ = writeln!(
+                out,
+                "sysmon_helper_bytes_processed_total{{endpoint=\"{}\"}} {}",
+                endpoint, bytes
+            );
+        }
+        family(
+            &mut out,
+            "sysmon_helper_failures_total",
+            "counter",
+            "Failed requests, by error kind.",
+        );
+        for (kind, count) in &self.failures {
+            let _ = writeln!(
+                out,
+                "sysmon_helper_failures_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+        family(
+            &mut out,
+            "sysmon_helper_request_duration_seconds",
+            "histogram",
+            "Time to handle a request, by endpoint.",
+        );
+        for (endpoint, histogram) in &self.durations {
+            let name = "sysmon_helper_request_duration_seconds";
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{endpoint=\"{}\",le=\"{}\"}} {}",
+                    name, endpoint, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}",
+                name, endpoint, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{endpoint=\"{}\"}} {}",
+                name, endpoint, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{endpoint=\"{}\"}} {}",
+                name, endpoint, histogram.count
+            );
+        }
+        out
+    }
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counts_files_failures_and_durations() {
+        let mut metrics = Metrics::default();
+        let observation = |endpoint, files, millis, failure| Observation {
+            endpoint,
+            files,
+            bytes: 100,
+            elapsed: Duration::from_millis(millis),
+            failure,
+        };
+        metrics.record(&observation("convert", 1, 3, None));
+        metrics.record(&observation("convert", 1, 40, None));
+        metrics.record(&observation("merge", 3, 200, None));
+        metrics.record(&observation("convert", 1, 2, Some("xml_parse")));
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE sysmon_helper_files_processed_total counter",
+            "sysmon_helper_files_processed_total{endpoint=\"convert\"} 2",
+            "sysmon_helper_files_processed_total{endpoint=\"merge\"} 3",
+            "sysmon_helper_bytes_processed_total{endpoint=\"convert\"} 200",
+            "sysmon_helper_failures_total{kind=\"xml_parse\"} 1",
+            "sysmon_helper_request_duration_seconds_bucket{endpoint=\"convert\",le=\"0.005\"} 2",
+            "sysmon_helper_request_duration_seconds_bucket{endpoint=\"convert\",le=\"0.05\"} 3",
+            "sysmon_helper_request_duration_seconds_bucket{endpoint=\"convert\",le=\"+Inf\"} 3",
+            "sysmon_helper_request_duration_seconds_count{endpoint=\"merge\"} 1",
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {:?} in\n{}",
+                line,
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! `--minify`: the smallest output that means the same thing.
+//!
+//! For XML, comments go, whitespace between tags goes, runs of whitespace
+//! inside tags collapse, and attributes that only restate Sysmon's defaults
+//! are dropped: an empty `name=""` and `condition="is"`, the condition a
+//! field test has when none is given. Text content and CDATA sections are
+//! kept exactly, since a rule's value may start or end with a space. For
+//! JSON, whitespace outside strings is removed.
+
+use sysmon_cli::format::Format;
+use sysmon_json::error::ConversionError;
+
+/// `text`, a document in `format`, minified.
+pub fn minify(text: &str, format: Format) -> Result<String, ConversionError> {
+    match format {
+        Format::Xml => xml(text),
+        Format::Json => Ok(json(text)),
+    }
+}
+
+fn xml(text: &str) -> Result<String, ConversionError> {
+    let tokens = tokens(text)?;
+    let mut out = String::with_capacity(text.len());
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            // Whitespace between a start tag and its end tag is the
+            // element's value rather than indentation.
+            Token::Text(text) => {
+                let value = is_tag(i.checked_sub(1).map(|p| &tokens[p]), TagKind::Start)
+                    && is_tag(tokens.get(i + 1), TagKind::End);
+                if value || !text.trim().is_empty() {
+                    out.push_str(text);
+                }
+            }
+            Token::Comment(_) => {}
+            Token::Cdata(markup) | Token::Declaration(markup) => out.push_str(markup),
+            Token::Tag(tag) => {
+                let mut tag = tag.clone();
+                tag.attributes.retain(|(key, value)| {
+                    let value = &value[1..value.len() - 1];
+                    !(*key == "name" && value.is_empty() || *key == "condition" && value == "is")
+                });
+                out.push_str(&tag.to_string());
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// A piece of an XML document, as [`tokens`] splits it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// Character data between markup, exactly as written.
+    Text(&'a str),
+    /// A CDATA section, including its delimiters.
+    Cdata(&'a str),
+    /// A comment, including its delimiters.
+    Comment(&'a str),
+    /// An XML declaration, processing instruction or DOCTYPE.
+    Declaration(&'a str),
+    Tag(Tag<'a>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+    Start,
+    End,
+    Empty,
+}
+
+/// A start, end or empty-element tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag<'a> {
+    pub name: &'a str,
+    /// Attribute names with their values, quotes included.
+    pub attributes: Vec<(&'a str, &'a str)>,
+    pub kind: TagKind,
+}
+
+impl std::fmt::Display for Tag<'_> {
+    /// The tag with single spaces between its name and attributes.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let slash = if self.kind == TagKind::End { "/" } else { "" };
+        write!(f, "<{}{}", slash, self.name)?;
+        for (key, value) in &self.attributes {
+            write!(f, " {}={}", key, value)?;
+        }
+        f.write_str(if self.kind == TagKind::Empty {
+            "/>"
+        } else {
+            ">"
+        })
+    }
+}
+
+/// Whether `token` is a tag of `kind`.
+pub fn is_tag(token: Option<&Token>, kind: TagKind) -> bool {
+    matches!(token, Some(Token::Tag(tag)) if tag.kind == kind)
+}
+
+/// Splits an XML document into text, markup and tags.
+pub fn tokens(text: &str) -> Result<Vec<Token<'_>>, ConversionError> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        rest = &rest[start..];
+        let (end, token) = if rest.starts_with("<!--") {
+            let end = closing(rest, "-->")?;
+            (end, Token::Comment(&rest[..end]))
+        } else if rest.starts_with("<![CDATA[") {
+            let end = closing(rest, "]]>")?;
+            (end, Token::Cdata(&rest[..end]))
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = closing(rest, ">")?;
+            (end, Token::Declaration(&rest[..end]))
+        } else {
+            let end = tag_end(rest)?;
+            (end, Token::Tag(tag(&rest[..end])))
+        };
+        tokens.push(token);
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    Ok(tokens)
+}
+
+/// The length of `text` up to and including the first `terminator`.
+fn closing(text: &str, terminator: &str) -> Result<usize, ConversionError> {
+    text.find(terminator)
+        .map(|at| at + terminator.len())
+        .ok_or_else(|| unterminated(text))
+}
+
+/// The length of the tag `text` starts with, skipping `>` inside quoted
+/// attribute values.
+fn tag_end(text: &str) -> Result<usize, ConversionError> {
+    let mut quote = None;
+    for (at, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Ok(at + 1),
+            _ => {}
+        }
+    }
+    Err(unterminated(text))
+}
+
+fn tag(text: &str) -> Tag<'_> {
+    let (kind, inner) = if let Some(inner) = text.strip_prefix("</") {
+        (TagKind::End, &inner[..inner.len() - 1])
+    } else if let Some(inner) = text.strip_suffix("/>") {
+        (TagKind::Empty, &inner[1..])
+    } else {
+        (TagKind::Start, &text[1..text.len() - 1])
+    };
+    let inner = inner.trim();
+    let (name, mut rest) = inner
+        .split_once(char::is_whitespace)
+        .map_or((inner, ""), |(name, rest)| (name, rest.trim_start()));
+
+    let mut attributes = Vec::new();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some(len) = value[1..].find(quote) else {
+            break;
+        };
+        attributes.push((key, &value[..len + 2]));
+        rest = value[len + 2..].trim_start();
+    }
+    Tag {
+        name,
+        attributes,
+        kind,
+    }
+}
+
+fn unterminated(text: &str) -> ConversionError {
+    let start: String = text.chars().take(40).collect();
+    ConversionError::InvalidFile(format!("Unterminated XML markup at {:?}", start))
+}
+
+fn json(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let (mut in_string, mut escaped) = (false, false);
+    for c in text.chars() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if !c.is_whitespace() {
+            in_string = c == '"';
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minify_xml_keeps_values_and_drops_defaults() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Sysmon schemaversion="4.90">
+  <!-- Process creation -->
+  <EventFiltering>
+    <RuleGroup name="" groupRelation="or">
+      <ProcessCreate onmatch="include">
+        <CommandLine   condition="contains" name="a > b"> -enc </CommandLine>
+        <Image condition="is">C:\x.exe</Image>
+        <User condition="is not"> </User>
+        <ParentImage condition='is'><![CDATA[  <y>  ]]></ParentImage>
+      </ProcessCreate>
+      <DnsQuery onmatch="exclude" />
+    </RuleGroup>
+  </EventFiltering>
+</Sysmon>
+"#;
+        assert_eq!(
+            minify(text, Format::Xml).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?><Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or"><ProcessCreate onmatch="include"><CommandLine condition="contains" name="a > b"> -enc </CommandLine><Image>C:\x.exe</Image><User condition="is not"> </User><ParentImage><![CDATA[  <y>  ]]></ParentImage></ProcessCreate><DnsQuery onmatch="exclude"/></RuleGroup></EventFiltering></Sysmon>"#
+        );
+        assert!(minify("<Sysmon><!-- open", Format::Xml).is_err());
+    }
+
+    #[test]
+    fn test_minify_json_keeps_strings() {
+        let text = "{\n  \"value\": \"a \\\" b\",\n  \"list\": [ 1, 2 ]\n}\n";
+        assert_eq!(
+            minify(text, Format::Json).unwrap(),
+            "{\"value\":\"a \\\" b\",\"list\":[1,2]}"
+        );
+    }
+}
@@ -0,0 +1,563 @@
+//! Strongly typed Sysmon configuration.
+//!
+//! `SysmonConfig` mirrors the structure of a Sysmon XML file: global options
+//! followed by rule groups, each holding event filters made of field
+//! conditions and `<Rule>` combinations. It derives serde's `Serialize` and
+//! `Deserialize` for JSON, and converts to and from the XML form through
+//! `xmltree`, so tools can edit configs as data instead of strings.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, EmitterConfig, ParserConfig, XMLNode};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SysmonConfig {
+    pub schema_version: String,
+    /// Top-level settings such as `HashAlgorithms` or `CheckRevocation`, in
+    /// document order. Flag elements like `<CheckRevocation/>` have an empty
+    /// value.
+    #[serde(default)]
+    pub options: Vec<ConfigOption>,
+    #[serde(default)]
+    pub rule_groups: Vec<RuleGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigOption {
+    pub name: String,
+    pub value: String,
+}
+
+/// A `<RuleGroup>`. Event filters placed directly under `<EventFiltering>`
+/// are read as a group with neither a name nor a relation, and written back
+/// the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleGroup {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub group_relation: Option<GroupRelation>,
+    #[serde(default)]
+    pub events: Vec<EventFilter>,
+}
+
+/// One event filter such as `<ProcessCreate onmatch="include">`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventFilter {
+    /// Element name of the event type, e.g. `ProcessCreate`.
+    pub event: String,
+    pub onmatch: OnMatch,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Filter {
+    Field(FieldCondition),
+    Rule(Rule),
+}
+
+/// A `<Rule>` combining several field conditions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub group_relation: GroupRelation,
+    #[serde(default)]
+    pub fields: Vec<FieldCondition>,
+}
+
+/// A single field test such as `<Image condition="end with">\cmd.exe</Image>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldCondition {
+    pub field: String,
+    pub condition: Condition,
+    pub value: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnMatch {
+    Include,
+    Exclude,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupRelation {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Condition {
+    #[serde(rename = "is")]
+    Is,
+    #[serde(rename = "is not")]
+    IsNot,
+    #[serde(rename = "is any")]
+    IsAny,
+    #[serde(rename = "contains")]
+    Contains,
+    #[serde(rename = "contains any")]
+    ContainsAny,
+    #[serde(rename = "contains all")]
+    ContainsAll,
+    #[serde(rename = "excludes")]
+    Excludes,
+    #[serde(rename = "excludes any")]
+    ExcludesAny,
+    #[serde(rename = "excludes all")]
+    ExcludesAll,
+    #[serde(rename = "begin with")]
+    BeginWith,
+    #[serde(rename = "not begin with")]
+    NotBeginWith,
+    #[serde(rename = "end with")]
+    EndWith,
+    #[serde(rename = "not end with")]
+    NotEndWith,
+    #[serde(rename = "less than")]
+    LessThan,
+    #[serde(rename = "more than")]
+    MoreThan,
+    #[serde(rename = "image")]
+    Image,
+}
+
+impl Condition {
+    pub const ALL: [Condition; 16] = [
+        Self::Is,
+        Self::IsNot,
+        Self::IsAny,
+        Self::Contains,
+        Self::ContainsAny,
+        Self::ContainsAll,
+        Self::Excludes,
+        Self::ExcludesAny,
+        Self::ExcludesAll,
+        Self::BeginWith,
+        Self::NotBeginWith,
+        Self::EndWith,
+        Self::NotEndWith,
+        Self::LessThan,
+        Self::MoreThan,
+        Self::Image,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Is => "is",
+            Self::IsNot => "is not",
+            Self::IsAny => "is any",
+            Self::Contains => "contains",
+            Self::ContainsAny => "contains any",
+            Self::ContainsAll => "contains all",
+            Self::Excludes => "excludes",
+            Self::ExcludesAny => "excludes any",
+            Self::ExcludesAll => "excludes all",
+            Self::BeginWith => "begin with",
+            Self::NotBeginWith => "not begin with",
+            Self::EndWith => "end with",
+            Self::NotEndWith => "not end with",
+            Self::LessThan => "less than",
+            Self::MoreThan => "more than",
+            Self::Image => "image",
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|c| c.as_str() == normalized)
+            .ok_or_else(|| format!("Unknown condition: {}", s))
+    }
+}
+
+impl FromStr for OnMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "include" => Ok(Self::Include),
+            "exclude" => Ok(Self::Exclude),
+            _ => Err(format!("Unknown onmatch value: {}", s)),
+        }
+    }
+}
+
+impl OnMatch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Include => "include",
+            Self::Exclude => "exclude",
+        }
+    }
+}
+
+impl FromStr for GroupRelation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "and" => Ok(Self::And),
+            "or" => Ok(Self::Or),
+            _ => Err(format!("Unknown groupRelation value: {}", s)),
+        }
+    }
+}
+
+impl GroupRelation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::And => "and",
+            Self::Or => "or",
+        }
+    }
+}
+
+impl SysmonConfig {
+    pub fn from_xml_str(xml: &str) -> Result<Self, ConversionError> {
+        // Untrimmed, so values keep their spaces (see `text_of`).
+        let config = ParserConfig::new().trim_whitespace(false);
+        let root = Element::parse_with_config(xml.as_bytes(), config)
+            .map_err(|e| ConversionError::ParserError(e.to_string()))?;
+        Self::from_element(&root)
+    }
+
+    pub fn to_xml_string(&self) -> Result<String, ConversionError> {
+        let mut buffer = Vec::new();
+        self.to_element()
+            .write_with_config(&mut buffer, EmitterConfig::new().perform_indent(true))
+            .map_err(|e| ConversionError::ParserError(e.to_string()))?;
+        String::from_utf8(buffer).map_err(|e| ConversionError::ParserError(e.to_string()))
+    }
+
+    pub fn from_json_str(json: &str) -> Result<Self, ConversionError> {
+        serde_json::from_str(json).map_err(|e| ConversionError::ParserError(e.to_string()))
+    }
+
+    pub fn to_json_string(&self) -> Result<String, ConversionError> {
+        serde_json::to_string_pretty(self).map_err(|e| ConversionError::ParserError(e.to_string()))
+    }
+
+    /// Reads a parsed `<Sysmon>` element.
+    pub fn from_element(root: &Element) -> Result<Self, ConversionError> {
+        if root.name != "Sysmon" {
+            return Err(invalid(format!(
+                "expected <Sysmon> root, found <{}>",
+                root.name
+            )));
+        }
+
+        let mut config = SysmonConfig {
+            schema_version: root
+                .attributes
+                .get("schemaversion")
+                .cloned()
+                .ok_or_else(|| invalid("<Sysmon> has no schemaversion attribute"))?,
+            options: Vec::new(),
+            rule_groups: Vec::new(),
+        };
+
+        for child in elements(root) {
+            if child.name != "EventFiltering" {
+                config.options.push(ConfigOption {
+                    name: child.name.clone(),
+                    value: text_of(child),
+                });
+                continue;
+            }
+
+            let mut bare = Vec::new();
+            for entry in elements(child) {
+                if entry.name == "RuleGroup" {
+                    config.rule_groups.push(RuleGroup {
+                        name: entry.attributes.get("name").cloned(),
+                        group_relation: entry
+                            .attributes
+                            .get("groupRelation")
+                            .map(|v| v.parse())
+                            .transpose()
+                            .map_err(invalid)?,
+                        events: elements(entry)
+                            .map(event_from_element)
+                            .collect::<Result<_, _>>()?,
+                    });
+                } else {
+                    bare.push(event_from_element(entry)?);
+                }
+            }
+            if !bare.is_empty() {
+                config.rule_groups.push(RuleGroup {
+                    name: None,
+                    group_relation: None,
+                    events: bare,
+                });
+            }
+        }
+        Ok(config)
+    }
+
+    /// Builds the `<Sysmon>` element for this configuration.
+    pub fn to_element(&self) -> Element {
+        let mut root = Element::new("Sysmon");
+        root.attributes
+            .insert("schemaversion".to_string(), self.schema_version.clone());
+        for option in &self.options {
+            let mut element = Element::new(&option.name);
+            if !option.value.is_empty() {
+                element.children.push(XMLNode::Text(option.value.clone()));
+            }
+            root.children.push(XMLNode::Element(element));
+        }
+
+        let mut filtering = Element::new("EventFiltering");
+        for group in &self.rule_groups {
+            let events = group.events.iter().map(event_to_element);
+            if group.name.is_none() && group.group_relation.is_none() {
+                filtering.children.extend(events.map(XMLNode::Element));
+                continue;
+            }
+            let mut element = Element::new("RuleGroup");
+            if let Some(name) = &group.name {
+                element.attributes.insert("name".to_string(), name.clone());
+            }
+            if let Some(relation) = group.group_relation {
+                element
+                    .attributes
+                    .insert("groupRelation".to_string(), relation.as_str().to_string());
+            }
+            element.children.extend(events.map(XMLNode::Element));
+            filtering.children.push(XMLNode::Element(element));
+        }
+        root.children.push(XMLNode::Element(filtering));
+        root
+    }
+
+    /// Every event filter in the configuration, across all rule groups.
+    pub fn events(&self) -> impl Iterator<Item = &EventFilter> {
+        self.rule_groups.iter().flat_map(|g| g.events.iter())
+    }
+}
+
+/// Converts a Sysmon XML document to the JSON form of [`SysmonConfig`], in
+/// memory. This is the JSON the wasm bindings and the C API produce. It is
+/// not the layout `sysmon_json::convert_file` writes, which the CLI,
+/// `progress::convert_files` and `asynchronous` use, and neither
+/// reads the other's output.
+pub fn convert_xml_str_to_json(xml: &str) -> Result<String, ConversionError> {
+    SysmonConfig::from_xml_str(xml)?.to_json_string()
+}
+
+/// Converts JSON from [`convert_xml_str_to_json`] back to Sysmon XML, in
+/// memory.
+pub fn convert_json_str_to_xml(json: &str) -> Result<String, ConversionError> {
+    SysmonConfig::from_json_str(json)?.to_xml_string()
+}
+
+fn event_from_element(element: &Element) -> Result<EventFilter, ConversionError> {
+    let onmatch = element
+        .attributes
+        .get("onmatch")
+        .ok_or_else(|| invalid(format!("<{}> has no onmatch attribute", element.name)))?
+        .parse()
+        .map_err(invalid)?;
+
+    let filters = elements(element)
+        .map(|child| {
+            if child.name != "Rule" {
+                return field_from_element(child).map(Filter::Field);
+            }
+            Ok(Filter::Rule(Rule {
+                name: child.attributes.get("name").cloned(),
+                group_relation: child
+                    .attributes
+                    .get("groupRelation")
+                    .map(|v| v.parse())
+                    .transpose()
+                    .map_err(invalid)?
+                    .unwrap_or(GroupRelation::Or),
+                fields: elements(child)
+                    .map(field_from_element)
+                    .collect::<Result<_, _>>()?,
+            }))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(EventFilter {
+        event: element.name.clone(),
+        onmatch,
+        filters,
+    })
+}
+
+fn field_from_element(element: &Element) -> Result<FieldCondition, ConversionError> {
+    Ok(FieldCondition {
+        field: element.name.clone(),
+        condition: element
+            .attributes
+            .get("condition")
+            .map(|c| c.parse())
+            .transpose()
+            .map_err(invalid)?
+            .unwrap_or(Condition::Is),
+        value: text_of(element),
+        name: element.attributes.get("name").cloned(),
+    })
+}
+
+fn event_to_element(event: &EventFilter) -> Element {
+    let mut element = Element::new(&event.event);
+    element
+        .attributes
+        .insert("onmatch".to_string(), event.onmatch.as_str().to_string());
+    for filter in &event.filters {
+        let child = match filter {
+            Filter::Field(field) => field_to_element(field),
+            Filter::Rule(rule) => {
+                let mut rule_element = Element::new("Rule");
+                if let Some(name) = &rule.name {
+                    rule_element
+                        .attributes
+                        .insert("name".to_string(), name.clone());
+                }
+                rule_element.attributes.insert(
+                    "groupRelation".to_string(),
+                    rule.group_relation.as_str().to_string(),
+                );
+                rule_element.children.extend(
+                    rule.fields
+                        .iter()
+                        .map(|f| XMLNode::Element(field_to_element(f))),
+                );
+                rule_element
+            }
+        };
+        element.children.push(XMLNode::Element(child));
+    }
+    element
+}
+
+fn field_to_element(field: &FieldCondition) -> Element {
+    let mut element = Element::new(&field.field);
+    if let Some(name) = &field.name {
+        element.attributes.insert("name".to_string(), name.clone());
+    }
+    element.attributes.insert(
+        "condition".to_string(),
+        field.condition.as_str().to_string(),
+    );
+    element.children.push(XMLNode::Text(field.value.clone()));
+    element
+}
+
+fn elements(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(|node| match node {
+        XMLNode::Element(child) => Some(child),
+        _ => None,
+    })
+}
+
+/// The text of `element`, verbatim: leading and trailing spaces can be
+/// part of a value (`contains "-e "`). Text that is only a line break and
+/// indentation, as in `<DnsQuery onmatch="exclude">\n</DnsQuery>`, is none.
+fn text_of(element: &Element) -> String {
+    match element.get_text() {
+        Some(text) if text.trim().is_empty() && text.contains('\n') => String::new(),
+        Some(text) => text.into_owned(),
+        None => String::new(),
+    }
+}
+
+fn invalid(message: impl Into<String>) -> ConversionError {
+    ConversionError::ValidationError(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+  <HashAlgorithms>sha256</HashAlgorithms>
+  <CheckRevocation/>
+  <EventFiltering>
+    <RuleGroup name="proc" groupRelation="or">
+      <ProcessCreate onmatch="include">
+        <Image condition="end with">\cmd.exe</Image>
+        <Rule name="encoded" groupRelation="and">
+          <Image condition="end with">\powershell.exe</Image>
+          <CommandLine condition="contains any">-enc;-e </CommandLine>
+        </Rule>
+      </ProcessCreate>
+    </RuleGroup>
+    <DnsQuery onmatch="exclude"/>
+  </EventFiltering>
+</Sysmon>"#;
+
+    #[test]
+    fn test_reads_typed_config() {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        assert_eq!(config.schema_version, "4.90");
+        assert_eq!(config.options[0].value, "sha256");
+        assert_eq!(config.options[1].value, "");
+        assert_eq!(config.rule_groups.len(), 2);
+
+        let process = &config.rule_groups[0].events[0];
+        assert_eq!(process.onmatch, OnMatch::Include);
+        let Filter::Rule(rule) = &process.filters[1] else {
+            panic!("expected a <Rule>");
+        };
+        assert_eq!(rule.group_relation, GroupRelation::And);
+        assert_eq!(rule.fields[1].condition, Condition::ContainsAny);
+        assert_eq!(rule.fields[1].value, "-enc;-e ");
+        assert_eq!(config.rule_groups[1].name, None);
+    }
+
+    #[test]
+    fn test_text_is_kept_verbatim() {
+        let config = SysmonConfig::from_xml_str(
+            "<Sysmon schemaversion=\"4.90\"><ArchiveDirectory>\n    </ArchiveDirectory>\
+             <EventFiltering><RuleGroup groupRelation=\"or\"><ProcessCreate onmatch=\"include\">\
+             <CommandLine condition=\"contains\"> -nop </CommandLine>\
+             </ProcessCreate></RuleGroup></EventFiltering></Sysmon>",
+        )
+        .unwrap();
+        assert_eq!(config.options[0].value, "");
+        let Filter::Field(field) = &config.rule_groups[0].events[0].filters[0] else {
+            panic!("expected a field condition");
+        };
+        assert_eq!(field.value, " -nop ");
+    }
+
+    #[test]
+    fn test_xml_round_trip() {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        let again = SysmonConfig::from_xml_str(&config.to_xml_string().unwrap()).unwrap();
+        assert_eq!(config, again);
+    }
+
+    #[test]
+    fn test_condition_names() {
+        for condition in Condition::ALL {
+            assert_eq!(condition.as_str().parse::<Condition>(), Ok(condition));
+        }
+        assert!("starts with".parse::<Condition>().is_err());
+    }
+}
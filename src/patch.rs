@@ -0,0 +1,311 @@
+//! `patch`: apply a reviewable file of rule edits to a config.
+//!
+//! A patch is a JSON array of operations, applied in order, with the verbs
+//! of RFC 6902 but Sysmon-shaped operands: `add` takes the same fields as
+//! `add-rule`, `remove` and `replace` the same selectors as `remove-rule`.
+//!
+//! ```json
+//! [
+//!   {"op": "add", "event": "ProcessCreate", "onmatch": "include", "field": "Image",
+//!    "condition": "end with", "value": "\\psexec.exe", "name": "technique_id=T1569.002"},
+//!   {"op": "remove", "name": "technique_id=T1003"},
+//!   {"op": "replace", "path": "//RuleGroup[@name=\"dns\"]", "field": "QueryName",
+//!    "value": ".microsoft.com", "set": {"condition": "end with", "value": "microsoft.com"}}
+//! ]
+//! ```
+//!
+//! Like a JSON Patch, the whole patch fails if any operation does: adding a
+//! rule that is already there, or removing or replacing rules that are not,
+//! usually means the patch was written against another version of the
+//! config.
+
+use crate::document;
+use crate::edit::{self, Changes, NewRule, Placement, Selector};
+use clap::Args;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+use xmltree::Element;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Add(NewRule),
+    Remove(Selector),
+    Replace(Replacement),
+}
+
+/// A `replace` operation: the [`Selector`] fields next to `set`. They are
+/// read through [`ReplacementFields`] rather than `#[serde(flatten)]`,
+/// which would let misspelled keys through unnoticed.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "ReplacementFields", into = "ReplacementFields")]
+pub struct Replacement {
+    pub select: Selector,
+    pub set: Changes,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ReplacementFields {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    set: Changes,
+}
+
+impl From<ReplacementFields> for Replacement {
+    fn from(fields: ReplacementFields) -> Self {
+        Replacement {
+            select: Selector {
+                name: fields.name,
+                field: fields.field,
+                value: fields.value,
+                event: fields.event,
+                query: fields.query,
+                path: fields.path,
+            },
+            set: fields.set,
+        }
+    }
+}
+
+impl From<Replacement> for ReplacementFields {
+    fn from(replacement: Replacement) -> Self {
+        let Selector {
+            name,
+            field,
+            value,
+            event,
+            query,
+            path,
+        } = replacement.select;
+        ReplacementFields {
+            name,
+            field,
+            value,
+            event,
+            query,
+            path,
+            set: replacement.set,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PatchArgs {
+    /// Configuration to patch (XML or JSON); rewritten in place unless --output is given
+    pub config: PathBuf,
+
+    /// Patch file: a JSON array of add, remove and replace operations
+    pub patch: PathBuf,
+
+    /// Print what the patch would change without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Write the patched config here instead of over the input
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn run(args: &PatchArgs) -> Result<(), ConversionError> {
+    let text = std::fs::read_to_string(&args.patch)
+        .map_err(|e| ConversionError::io_error(&args.patch, e))?;
+    let operations: Vec<Operation> = serde_json::from_str(&text)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", args.patch.display(), e)))?;
+
+    let mut root = document::load(&args.config)?;
+    let changes = apply(&mut root, &args.config, &operations)?;
+    if args.dry_run {
+        for change in &changes {
+            println!("{}", change);
+        }
+        return Ok(());
+    }
+
+    let output = args.output.as_ref().unwrap_or(&args.config);
+    document::save(output, &root)?;
+    for change in &changes {
+        info!("{}", change);
+    }
+    info!(
+        "Applied {} operation(s) from {} to {}",
+        operations.len(),
+        args.patch.display(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Applies `operations` to `root` in order and describes each change. On
+/// error `root` may be partly patched; callers discard it.
+pub fn apply(
+    root: &mut Element,
+    file: &Path,
+    operations: &[Operation],
+) -> Result<Vec<String>, ConversionError> {
+    let mut changes = Vec::new();
+    for (i, operation) in operations.iter().enumerate() {
+        let failed = |message: String| {
+            ConversionError::ValidationError(format!("Operation {}: {}", i + 1, message))
+        };
+        match operation {
+            Operation::Add(rule) => {
+                edit::check(root, rule).map_err(|e| failed(e.to_string()))?;
+                let placement = edit::add_rule(root, rule);
+                if placement == Placement::AlreadyPresent {
+                    return Err(failed(format!(
+                        "{} is already present",
+                        edit::describe_rule(rule)
+                    )));
+                }
+                changes.push(format!("add: {}", edit::describe_rule(rule)));
+            }
+            Operation::Remove(select) => {
+                let selected = select
+                    .select(root, file)
+                    .map_err(|e| failed(e.to_string()))?;
+                if selected.is_empty() {
+                    return Err(failed("remove matches no rule".into()));
+                }
+                for removal in edit::remove_rules(root, &selected, None) {
+                    changes.push(format!("remove: {}", removal.what));
+                }
+            }
+            Operation::Replace(replacement) => {
+                let set = &replacement.set;
+                if set.condition.is_none() && set.value.is_none() && set.name.is_none() {
+                    return Err(failed(
+                        "replace needs a condition, value or name to set".into(),
+                    ));
+                }
+                let selected = replacement
+                    .select
+                    .select(root, file)
+                    .map_err(|e| failed(e.to_string()))?;
+                if selected.is_empty() {
+                    return Err(failed("replace matches no rule".into()));
+                }
+                edit::check_replacement(root, &selected, set).map_err(|e| failed(e.to_string()))?;
+                for replaced in edit::replace_conditions(root, &selected, set) {
+                    changes.push(format!("replace: {}", replaced));
+                }
+            }
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sysmon_cli::model::{Condition, OnMatch};
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+<EventFiltering>
+<RuleGroup name="" groupRelation="or">
+<ProcessCreate onmatch="include">
+<Image name="technique_id=T1059.001" condition="end with">\powershell.exe</Image>
+</ProcessCreate>
+</RuleGroup>
+</EventFiltering>
+</Sysmon>"#;
+
+    #[test]
+    fn test_apply_runs_operations_in_order() {
+        let mut root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let operations = vec![
+            Operation::Add(NewRule {
+                event: "ProcessCreate".into(),
+                onmatch: OnMatch::Include,
+                field: "Image".into(),
+                condition: Condition::EndWith,
+                value: r"\psexec.exe".into(),
+                name: Some("technique_id=T1569.002".into()),
+                group: None,
+            }),
+            Operation::Replace(Replacement {
+                select: Selector {
+                    name: Some("technique_id=T1569.002".into()),
+                    ..Selector::default()
+                },
+                set: Changes {
+                    value: Some(r"\psexesvc.exe".into()),
+                    ..Changes::default()
+                },
+            }),
+            Operation::Remove(Selector {
+                name: Some("technique_id=T1059.001".into()),
+                ..Selector::default()
+            }),
+        ];
+
+        let changes = apply(&mut root, Path::new("c.xml"), &operations).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                r#"add: Image end with "\\psexec.exe" in ProcessCreate/include"#,
+                r#"replace: RuleGroup[1]/ProcessCreate/include: Image end with "\\psexec.exe" -> Image end with "\\psexesvc.exe""#,
+                r#"remove: RuleGroup[1]/ProcessCreate/include: Image end with "\\powershell.exe""#,
+            ]
+        );
+
+        let error = apply(&mut root, Path::new("c.xml"), &operations[2..]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Validation error: Operation 1: remove matches no rule"
+        );
+    }
+
+    #[test]
+    fn test_replace_is_checked_against_the_schema() {
+        let old = CONFIG.replace("4.90", "4.40");
+        let mut root = Element::parse(old.as_bytes()).unwrap();
+        let operations = vec![Operation::Replace(Replacement {
+            select: Selector {
+                name: Some("technique_id=T1059.001".into()),
+                ..Selector::default()
+            },
+            set: Changes {
+                condition: Some(Condition::NotEndWith),
+                ..Changes::default()
+            },
+        })];
+        let error = apply(&mut root, Path::new("c.xml"), &operations).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Validation error: Operation 1: Validation error: Condition \"not end with\" \
+             requires schema 4.50 but the config declares 4.40"
+        );
+    }
+
+    #[test]
+    fn test_operations_reject_unknown_keys() {
+        for patch in [
+            r#"[{"op": "add", "event": "ProcessCreate", "onmatch": "include", "field": "Image",
+                "value": "x", "nmae": "typo"}]"#,
+            r#"[{"op": "replace", "name": "x", "set": {"value": "y"}, "evnet": "DnsQuery"}]"#,
+            r#"[{"op": "remove", "name": "x", "scope": "all"}]"#,
+        ] {
+            let error = serde_json::from_str::<Vec<Operation>>(patch).unwrap_err();
+            assert!(error.to_string().contains("unknown field"), "{}", error);
+        }
+        let replace: Vec<Operation> =
+            serde_json::from_str(r#"[{"op": "replace", "name": "x", "set": {"value": "y"}}]"#)
+                .unwrap();
+        assert!(
+            matches!(&replace[0], Operation::Replace(r) if r.select.name.as_deref() == Some("x"))
+        );
+    }
+}
@@ -0,0 +1,508 @@
+//! Organization-specific lint rules from a policy file, for `lint --policy`.
+//!
+//! A policy is a TOML file of `[[rule]]` tables, or a YAML file (`.yml`,
+//! `.yaml`) with the same keys and a `rule` list. Each rule selects Sysmon
+//! rules (a field condition or a `<Rule>`) by event type, `onmatch` and
+//! tested field, then asserts something about them: that there are none
+//! (`forbid`), that their name matches a pattern (`name`), or that every
+//! value they test does (`value`). Patterns are regular expressions found
+//! anywhere in the text unless anchored. A rule that lists `profiles`
+//! only applies when `--profile` names one of them.
+//!
+//! `--policy` can be repeated to combine packs, say a corporate baseline
+//! and a compliance pack. Each pack is named by its `name` key or else its
+//! file name, and findings carry `pack/id` so it is clear which pack
+//! raised them.
+//!
+//! ```toml
+//! name = "corp-baseline"
+//!
+//! [[rule]]
+//! id = "technique-names"
+//! description = "Include rules must cite an ATT&CK technique"
+//! severity = "error"
+//! onmatch = "include"
+//! name = 'technique_id=T\d{4}'
+//!
+//! [[rule]]
+//! id = "no-registry-includes"
+//! profiles = ["workstation"]
+//! event = ["RegistryEvent"]
+//! onmatch = "include"
+//! forbid = true
+//! ```
+//!
+//! ```yaml
+//! name: corp-baseline
+//! rule:
+//!   - id: technique-names
+//!     severity: error
+//!     onmatch: include
+//!     name: 'technique_id=T\d{4}'
+//! ```
+
+use crate::yaml::{self, Yaml};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use sysmon_cli::model::{FieldCondition, Filter, OnMatch, SysmonConfig};
+use sysmon_cli::validation::{Issue, Severity};
+use sysmon_json::error::ConversionError;
+
+/// Rule id of every policy finding, with its description; the policy
+/// rule's own id starts the message.
+pub const RULES: &[(&str, &str)] = &[("policy", "Config breaks a rule of the --policy file")];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PolicyFile {
+    name: Option<String>,
+    rule: Vec<RuleSpec>,
+}
+
+/// A named set of rules, as read from one policy file.
+pub struct Pack {
+    pub name: String,
+    pub rules: Vec<RuleSpec>,
+}
+
+/// One `[[rule]]` table as written.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleSpec {
+    pub id: String,
+    pub description: Option<String>,
+    /// `error` or `warning` (the default).
+    pub severity: Option<String>,
+    pub profiles: Vec<String>,
+    /// Event types the rule applies to; all when empty.
+    pub event: Vec<String>,
+    pub onmatch: Option<String>,
+    /// Fields a Sysmon rule must test to be selected; any when empty.
+    pub field: Vec<String>,
+    pub name: Option<String>,
+    pub value: Option<String>,
+    pub forbid: bool,
+}
+
+struct PolicyRule {
+    pack: String,
+    spec: RuleSpec,
+    severity: Severity,
+    onmatch: Option<OnMatch>,
+    name: Option<Regex>,
+    value: Option<Regex>,
+}
+
+/// The rules of every pack that apply to the selected profile.
+pub struct Policy {
+    rules: Vec<PolicyRule>,
+}
+
+impl Policy {
+    pub fn load(paths: &[PathBuf], profile: Option<&str>) -> Result<Policy, ConversionError> {
+        let mut packs = Vec::new();
+        for path in paths {
+            let text =
+                std::fs::read_to_string(path).map_err(|e| ConversionError::io_error(path, e))?;
+            let file = if is_yaml(path) {
+                from_yaml(&text)
+            } else {
+                toml::from_str(&text).map_err(|e| e.to_string())
+            }
+            .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))?;
+            let name = file.name.unwrap_or_else(|| {
+                path.file_stem().map_or_else(
+                    || path.display().to_string(),
+                    |s| s.to_string_lossy().into(),
+                )
+            });
+            packs.push(Pack {
+                name,
+                rules: file.rule,
+            });
+        }
+        Policy::new(packs, profile).map_err(ConversionError::ValidationError)
+    }
+
+    pub fn new(packs: Vec<Pack>, profile: Option<&str>) -> Result<Policy, String> {
+        let mut names = HashSet::new();
+        let mut rules = Vec::new();
+        for pack in packs {
+            if !names.insert(pack.name.clone()) {
+                return Err(format!("two policy packs are named {}", pack.name));
+            }
+            let mut ids = HashSet::new();
+            for spec in pack.rules {
+                if !ids.insert(spec.id.clone()) {
+                    return Err(format!("{}: rule {} is defined twice", pack.name, spec.id));
+                }
+                let rule = compile(&pack.name, spec)?;
+                let applies = rule.spec.profiles.is_empty()
+                    || profile.is_some_and(|p| rule.spec.profiles.iter().any(|q| q == p));
+                if applies {
+                    rules.push(rule);
+                }
+            }
+        }
+        Ok(Policy { rules })
+    }
+
+    pub fn check(&self, config: &SysmonConfig) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        for (index, group) in config.rule_groups.iter().enumerate() {
+            for event in &group.events {
+                let location = format!("RuleGroup[{}]/{}", index + 1, event.event);
+                for filter in &event.filters {
+                    let (name, fields, location) = match filter {
+                        Filter::Field(field) => (
+                            field.name.as_deref(),
+                            std::slice::from_ref(field),
+                            format!("{}/{}", location, field.field),
+                        ),
+                        Filter::Rule(rule) => (
+                            rule.name.as_deref(),
+                            rule.fields.as_slice(),
+                            format!("{}/Rule", location),
+                        ),
+                    };
+                    for rule in &self.rules {
+                        let selected = (rule.spec.event.is_empty()
+                            || rule.spec.event.contains(&event.event))
+                            && rule.onmatch.is_none_or(|o| o == event.onmatch)
+                            && (rule.spec.field.is_empty()
+                                || fields.iter().any(|f| rule.spec.field.contains(&f.field)));
+                        if !selected {
+                            continue;
+                        }
+                        for problem in rule.problems(name, fields) {
+                            let id = format!("{}/{}", rule.pack, rule.spec.id);
+                            let message = match &rule.spec.description {
+                                Some(description) => {
+                                    format!("{}: {} ({})", id, description, problem)
+                                }
+                                None => format!("{}: {}", id, problem),
+                            };
+                            issues.push(Issue {
+                                rule: "policy",
+                                severity: rule.severity,
+                                location: location.clone(),
+                                message,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        issues
+    }
+}
+
+impl PolicyRule {
+    /// What a selected Sysmon rule does wrong, if anything.
+    fn problems(&self, name: Option<&str>, fields: &[FieldCondition]) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.spec.forbid {
+            problems.push("rule is forbidden".to_string());
+        }
+        if let Some(pattern) = &self.name {
+            match name {
+                Some(name) if pattern.is_match(name) => {}
+                Some(name) => problems.push(format!(
+                    "name {:?} does not match {}",
+                    name,
+                    pattern.as_str()
+                )),
+                None => problems.push(format!("rule has no name matching {}", pattern.as_str())),
+            }
+        }
+        if let Some(pattern) = &self.value {
+            for field in fields.iter().filter(|f| !pattern.is_match(&f.value)) {
+                problems.push(format!(
+                    "{} value {:?} does not match {}",
+                    field.field,
+                    field.value,
+                    pattern.as_str()
+                ));
+            }
+        }
+        problems
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("yml") || e.eq_ignore_ascii_case("yaml"))
+}
+
+/// Reads a pack written in YAML, with the keys of the TOML form.
+fn from_yaml(text: &str) -> Result<PolicyFile, String> {
+    let Yaml::Map(entries) = yaml::parse(text)? else {
+        return Err("expected a mapping with `name` and `rule`".to_string());
+    };
+    let mut file = PolicyFile::default();
+    for (key, value) in &entries {
+        match (key.as_str(), value) {
+            ("name", value) => file.name = scalar(key, value)?,
+            ("rule", Yaml::Null) => {}
+            ("rule", Yaml::List(rules)) => {
+                file.rule = rules.iter().map(rule_from_yaml).collect::<Result<_, _>>()?
+            }
+            ("rule", _) => return Err("`rule` must list rules".to_string()),
+            (other, _) => return Err(format!("unknown key `{}`", other)),
+        }
+    }
+    Ok(file)
+}
+
+fn rule_from_yaml(rule: &Yaml) -> Result<RuleSpec, String> {
+    let Yaml::Map(entries) = rule else {
+        return Err("each entry of `rule` must be a mapping".to_string());
+    };
+    let mut spec = RuleSpec::default();
+    for (key, value) in entries {
+        match key.as_str() {
+            "id" => spec.id = scalar(key, value)?.unwrap_or_default(),
+            "description" => spec.description = scalar(key, value)?,
+            "severity" => spec.severity = scalar(key, value)?,
+            "profiles" => spec.profiles = list(key, value)?,
+            "event" => spec.event = list(key, value)?,
+            "onmatch" => spec.onmatch = scalar(key, value)?,
+            "field" => spec.field = list(key, value)?,
+            "name" => spec.name = scalar(key, value)?,
+            "value" => spec.value = scalar(key, value)?,
+            "forbid" => {
+                spec.forbid = match scalar(key, value)?.as_deref() {
+                    None | Some("false") => false,
+                    Some("true") => true,
+                    Some(other) => {
+                        return Err(format!("`forbid` must be true or false, not {:?}", other))
+                    }
+                }
+            }
+            other => return Err(format!("unknown rule key `{}`", other)),
+        }
+    }
+    Ok(spec)
+}
+
+fn scalar(key: &str, value: &Yaml) -> Result<Option<String>, String> {
+    match value {
+        Yaml::Null => Ok(None),
+        Yaml::Scalar(s) => Ok(Some(s.clone())),
+        _ => Err(format!("`{}` must be a single value", key)),
+    }
+}
+
+/// A list of values; a single value is a list of one.
+fn list(key: &str, value: &Yaml) -> Result<Vec<String>, String> {
+    match value {
+        Yaml::Null => Ok(Vec::new()),
+        Yaml::Scalar(s) => Ok(vec![s.clone()]),
+        Yaml::List(items) => items
+            .iter()
+            .map(|i| i.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("`{}` must list values", key)),
+        Yaml::Map(_) => Err(format!("`{}` must list values", key)),
+    }
+}
+
+fn compile(pack: &str, spec: RuleSpec) -> Result<PolicyRule, String> {
+    if spec.id.is_empty() {
+        return Err(format!("{}: every rule needs an id", pack));
+    }
+    let context = |e: String| format!("{}: rule {}: {}", pack, spec.id, e);
+    let severity = match spec.severity.as_deref() {
+        None | Some("warning") => Severity::Warning,
+        Some("error") => Severity::Error,
+        Some(other) => {
+            return Err(context(format!(
+                "severity must be error or warning, not {:?}",
+                other
+            )))
+        }
+    };
+    let onmatch = spec
+        .onmatch
+        .as_deref()
+        .map(str::parse::<OnMatch>)
+        .transpose()
+        .map_err(context)?;
+    let pattern = |pattern: &Option<String>| {
+        pattern
+            .as_deref()
+            .map(|p| Regex::new(p).map_err(|e| context(format!("pattern {:?}: {}", p, e))))
+            .transpose()
+    };
+    let (name, value) = (pattern(&spec.name)?, pattern(&spec.value)?);
+    if !spec.forbid && name.is_none() && value.is_none() {
+        return Err(context(
+            "checks nothing; set forbid, name or value".to_string(),
+        ));
+    }
+    Ok(PolicyRule {
+        pack: pack.to_string(),
+        spec,
+        severity,
+        onmatch,
+        name,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90"><EventFiltering>
+        <RuleGroup groupRelation="or">
+        <ProcessCreate onmatch="include">
+        <Image name="technique_id=T1059.001" condition="end with">\powershell.exe</Image>
+        <Image name="PowerShell Core" condition="end with">\pwsh.exe</Image>
+        </ProcessCreate>
+        <RegistryEvent onmatch="include">
+        <TargetObject name="technique_id=T1547.001" condition="contains">\CurrentVersion\Run</TargetObject>
+        </RegistryEvent>
+        </RuleGroup>
+        </EventFiltering></Sysmon>"#;
+
+    fn packs() -> Vec<Pack> {
+        let baseline = Pack {
+            name: "corp-baseline".into(),
+            rules: vec![RuleSpec {
+                id: "technique-names".into(),
+                severity: Some("error".into()),
+                onmatch: Some("include".into()),
+                name: Some(r"^technique_id=T\d{4}".into()),
+                ..RuleSpec::default()
+            }],
+        };
+        let workstation = Pack {
+            name: "workstation".into(),
+            rules: vec![RuleSpec {
+                id: "no-registry-includes".into(),
+                description: Some("Registry includes are too noisy on workstations".into()),
+                profiles: vec!["workstation".into()],
+                event: vec!["RegistryEvent".into()],
+                forbid: true,
+                ..RuleSpec::default()
+            }],
+        };
+        vec![baseline, workstation]
+    }
+
+    fn messages(profile: Option<&str>) -> Vec<(String, Severity, String)> {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        Policy::new(packs(), profile)
+            .unwrap()
+            .check(&config)
+            .into_iter()
+            .map(|issue| (issue.location, issue.severity, issue.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_policy_checks_names_and_profiles_per_pack() {
+        let naming = (
+            "RuleGroup[1]/ProcessCreate/Image".to_string(),
+            Severity::Error,
+            r#"corp-baseline/technique-names: name "PowerShell Core" does not match ^technique_id=T\d{4}"#
+                .to_string(),
+        );
+        assert_eq!(messages(None), vec![naming.clone()]);
+        assert_eq!(
+            messages(Some("workstation")),
+            vec![
+                naming,
+                (
+                    "RuleGroup[1]/RegistryEvent/TargetObject".to_string(),
+                    Severity::Warning,
+                    "workstation/no-registry-includes: Registry includes are too noisy on workstations \
+                     (rule is forbidden)"
+                        .to_string(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_policy_rejects_bad_rules() {
+        let pack = |rules: Vec<RuleSpec>| Pack {
+            name: "pack".into(),
+            rules,
+        };
+        let check = |spec: RuleSpec| Policy::new(vec![pack(vec![spec])], None).err().unwrap();
+        assert_eq!(
+            check(RuleSpec {
+                id: "empty".into(),
+                ..RuleSpec::default()
+            }),
+            "pack: rule empty: checks nothing; set forbid, name or value"
+        );
+        assert!(check(RuleSpec {
+            id: "bad".into(),
+            onmatch: Some("both".into()),
+            forbid: true,
+            ..RuleSpec::default()
+        })
+        .starts_with("pack: rule bad: Unknown onmatch value"));
+        assert!(check(RuleSpec {
+            id: "regex".into(),
+            value: Some("(".into()),
+            ..RuleSpec::default()
+        })
+        .starts_with("pack: rule regex: pattern \"(\""));
+
+        let forbid = || RuleSpec {
+            id: "same".into(),
+            forbid: true,
+            ..RuleSpec::default()
+        };
+        let twice = Policy::new(vec![pack(vec![forbid(), forbid()])], None);
+        assert_eq!(twice.err().unwrap(), "pack: rule same is defined twice");
+        let packs = vec![pack(vec![forbid()]), pack(vec![forbid()])];
+        assert_eq!(
+            Policy::new(packs, None).err().unwrap(),
+            "two policy packs are named pack"
+        );
+    }
+
+    #[test]
+    fn test_yaml_packs_use_the_toml_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corp.yml");
+        std::fs::write(
+            &path,
+            "name: corp-baseline\nrule:\n  - id: technique-names\n    severity: error\n    \
+             onmatch: include\n    name: '^technique_id=T\\d{4}'\n  - id: no-registry-includes\n    \
+             profiles: [workstation]\n    event:\n      - RegistryEvent\n    forbid: true\n",
+        )
+        .unwrap();
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        let issues = Policy::load(&[path], Some("workstation"))
+            .unwrap()
+            .check(&config);
+        let messages: Vec<&str> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"corp-baseline/technique-names: name "PowerShell Core" does not match ^technique_id=T\d{4}"#,
+                "corp-baseline/no-registry-includes: rule is forbidden",
+            ]
+        );
+
+        assert_eq!(
+            from_yaml("rule:\n  - id: a\n    forbid: yes\n")
+                .err()
+                .unwrap(),
+            "`forbid` must be true or false, not \"yes\""
+        );
+        assert_eq!(
+            from_yaml("rules: []\n").err().unwrap(),
+            "unknown key `rules`"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! `posture`: what a config actually logs, one line per event type.
+//!
+//! Sysmon's filtering reads differently from how a config looks: an event
+//! type with any include filter logs only what matches one, even when it
+//! also has excludes; one with only exclude filters logs everything except
+//! what they match; and an empty include filter logs nothing at all while
+//! an empty exclude filter logs everything. This report spells that out:
+//!
+//! ```text
+//! ProcessCreate: include-list of 412 patterns (logs only matches)
+//! NetworkConnect: exclude-list (logs everything except 35 patterns)
+//! ```
+//!
+//! A pattern is one rule: a field condition or `<Rule>` in an `or` group,
+//! or the whole event filter in an `and` group, whose conditions must all
+//! match together. Event types the config does not mention are listed as
+//! not configured; Sysmon's own default applies to those.
+
+use crate::{io_guard, validate};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::path::PathBuf;
+use sysmon_cli::model::{GroupRelation, OnMatch, SysmonConfig};
+use sysmon_cli::schema::{self, Platform, Version};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PostureFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct PostureArgs {
+    /// Configuration to describe (XML or JSON)
+    pub config: PathBuf,
+
+    /// Platform whose event types are listed when not configured: windows or linux
+    #[arg(long, default_value = "windows")]
+    pub platform: Platform,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: PostureFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// How an event type is filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Posture {
+    /// Only events matching an include pattern are logged.
+    IncludeList,
+    /// Include patterns, with exclude patterns dropping some matches.
+    IncludeListWithExclusions,
+    /// Everything is logged except events matching an exclude pattern.
+    ExcludeList,
+    /// An empty include filter: nothing is logged.
+    Nothing,
+    /// An empty exclude filter: everything is logged.
+    Everything,
+    /// The config has no filter for the event type.
+    NotConfigured,
+}
+
+impl Posture {
+    /// The name it is serialized as.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Posture::IncludeList => "include_list",
+            Posture::IncludeListWithExclusions => "include_list_with_exclusions",
+            Posture::ExcludeList => "exclude_list",
+            Posture::Nothing => "nothing",
+            Posture::Everything => "everything",
+            Posture::NotConfigured => "not_configured",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EventPosture {
+    pub event: String,
+    pub posture: Posture,
+    /// Include patterns.
+    pub include: usize,
+    /// Exclude patterns.
+    pub exclude: usize,
+}
+
+impl EventPosture {
+    pub fn describe(&self) -> String {
+        let patterns = |n: usize| format!("{} pattern{}", n, if n == 1 { "" } else { "s" });
+        let summary = match self.posture {
+            Posture::IncludeList => {
+                format!(
+                    "include-list of {} (logs only matches)",
+                    patterns(self.include)
+                )
+            }
+            Posture::IncludeListWithExclusions => format!(
+                "include-list of {}, minus matches of {} excluded",
+                patterns(self.include),
+                patterns(self.exclude)
+            ),
+            Posture::ExcludeList => format!(
+                "exclude-list (logs everything except {})",
+                patterns(self.exclude)
+            ),
+            Posture::Nothing => "nothing logged (empty include filter)".to_string(),
+            Posture::Everything => "everything logged (empty exclude filter)".to_string(),
+            Posture::NotConfigured => "not configured (Sysmon's default applies)".to_string(),
+        };
+        format!("{}: {}", self.event, summary)
+    }
+}
+
+pub fn run(args: &PostureArgs) -> Result<(), ConversionError> {
+    let config = SysmonConfig::from_xml_str(&validate::load(&args.config)?.0)?;
+    let postures = posture(&config, args.platform);
+
+    let text = match args.format {
+        PostureFormat::Text => postures.iter().map(|p| p.describe() + "\n").collect(),
+        PostureFormat::Json => serde_json::to_string_pretty(&postures)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+/// The posture of every event type `config` filters, in order of first
+/// appearance, then of the other event types its schema version and
+/// `platform` know.
+pub fn posture(config: &SysmonConfig, platform: Platform) -> Vec<EventPosture> {
+    // Per event type, its include and exclude pattern counts, `None` when
+    // it has no filter of that kind.
+    let mut seen: Vec<(String, Option<usize>, Option<usize>)> = Vec::new();
+    for group in &config.rule_groups {
+        for event in &group.events {
+            let patterns = match group.group_relation {
+                Some(GroupRelation::And) => usize::from(!event.filters.is_empty()),
+                _ => event.filters.len(),
+            };
+            let index = match seen.iter().position(|s| s.0 == event.event) {
+                Some(index) => index,
+                None => {
+                    seen.push((event.event.clone(), None, None));
+                    seen.len() - 1
+                }
+            };
+            let count = match event.onmatch {
+                OnMatch::Include => &mut seen[index].1,
+                OnMatch::Exclude => &mut seen[index].2,
+            };
+            *count = Some(count.unwrap_or(0) + patterns);
+        }
+    }
+
+    let mut postures: Vec<EventPosture> = seen
+        .into_iter()
+        .map(|(event, include, exclude)| {
+            let posture = match (include, exclude.unwrap_or(0)) {
+                (Some(0), _) => Posture::Nothing,
+                (Some(_), 0) => Posture::IncludeList,
+                (Some(_), _) => Posture::IncludeListWithExclusions,
+                (None, 0) => Posture::Everything,
+                (None, _) => Posture::ExcludeList,
+            };
+            EventPosture {
+                event,
+                posture,
+                include: include.unwrap_or(0),
+                exclude: exclude.unwrap_or(0),
+            }
+        })
+        .collect();
+
+    let version: Option<Version> = config.schema_version.parse().ok();
+    let unconfigured: Vec<EventPosture> = schema::EVENT_TYPES
+        .iter()
+        .filter(|e| {
+            version.is_none_or(|v| e.min_schema <= v)
+                && platform.supports(e.name)
+                && !postures.iter().any(|p| p.event == e.name)
+        })
+        .map(|e| EventPosture {
+            event: e.name.to_string(),
+            posture: Posture::NotConfigured,
+            include: 0,
+            exclude: 0,
+        })
+        .collect();
+    postures.extend(unconfigured);
+    postures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.22"><EventFiltering>
+<RuleGroup groupRelation="or">
+<ProcessCreate onmatch="include">
+<Image condition="end with">\powershell.exe</Image>
+<Image condition="end with">\pwsh.exe</Image>
+</ProcessCreate>
+<NetworkConnect onmatch="exclude">
+<Image condition="is">C:\Windows\System32\svchost.exe</Image>
+</NetworkConnect>
+<DnsQuery onmatch="exclude"/>
+<PipeEvent onmatch="include"/>
+<PipeEvent onmatch="exclude"><PipeName condition="is">\x</PipeName></PipeEvent>
+</RuleGroup>
+<RuleGroup groupRelation="and">
+<ProcessCreate onmatch="exclude">
+<Image condition="end with">\pwsh.exe</Image>
+<User condition="is">NT AUTHORITY\SYSTEM</User>
+</ProcessCreate>
+</RuleGroup>
+</EventFiltering></Sysmon>"#;
+
+    #[test]
+    fn test_posture_follows_sysmon_filtering() {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        let postures = posture(&config, Platform::Windows);
+        let lines: Vec<String> = postures.iter().take(4).map(|p| p.describe()).collect();
+        assert_eq!(
+            lines,
+            [
+                "ProcessCreate: include-list of 2 patterns, minus matches of 1 pattern excluded",
+                "NetworkConnect: exclude-list (logs everything except 1 pattern)",
+                "DnsQuery: everything logged (empty exclude filter)",
+                "PipeEvent: nothing logged (empty include filter)",
+            ]
+        );
+        assert!(postures[4..]
+            .iter()
+            .all(|p| p.posture == Posture::NotConfigured));
+        assert!(postures.iter().any(|p| p.event == "ProcessTerminate"));
+        assert!(!postures.iter().any(|p| p.event == "FileDelete"));
+    }
+}
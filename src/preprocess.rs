@@ -0,0 +1,431 @@
+//! Named, individually selectable preprocessing steps.
+//!
+//! `sysmon_json`'s `preprocess_config` runs first, as the `sysmon-json`
+//! step. Every other step implements [`Preprocessor`] and runs afterwards,
+//! in order, on the preprocessed text just before conversion, for single
+//! files and for every file of a batch alike. Steps can be picked with
+//! `--preprocess-only`, and a TOML file passed with `--preprocess-rules`
+//! adds regex rewrite rules:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "lowercase-onmatch"
+//! description = "Normalize onmatch values"
+//! pattern = 'onmatch="Include"'
+//! replacement = 'onmatch="include"'
+//! ```
+
+use crate::format::Format;
+use crate::repair;
+use log::error;
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use sysmon_json::error::{ConversionError, PreprocessError};
+
+pub const LIBRARY_STEP: &str = "sysmon-json";
+
+/// A text transform that runs between preprocessing and conversion.
+///
+/// Implementations must be thread-safe: batch runs share one pipeline
+/// across all workers.
+pub trait Preprocessor: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// Rewrites `text`, a document in `format`.
+    fn process(&self, text: &str, format: Format) -> Result<String, ConversionError>;
+}
+
+struct EntityFix;
+
+impl Preprocessor for EntityFix {
+    fn name(&self) -> &str {
+        "entity-fix"
+    }
+
+    fn description(&self) -> &str {
+        "Escape bare '&' that does not start an entity (XML only)"
+    }
+
+    fn process(&self, text: &str, format: Format) -> Result<String, ConversionError> {
+        Ok(match format {
+            Format::Xml => repair::escape_ampersands(text, &mut Vec::new()),
+            Format::Json => text.to_string(),
+        })
+    }
+}
+
+struct Whitespace;
+
+impl Preprocessor for Whitespace {
+    fn name(&self) -> &str {
+        "whitespace"
+    }
+
+    fn description(&self) -> &str {
+        "Normalize line endings and trim trailing whitespace"
+    }
+
+    fn process(&self, text: &str, _format: Format) -> Result<String, ConversionError> {
+        Ok(normalize_whitespace(text))
+    }
+}
+
+struct StripComments;
+
+impl Preprocessor for StripComments {
+    fn name(&self) -> &str {
+        "strip-comments"
+    }
+
+    fn description(&self) -> &str {
+        "Remove XML comments (XML only)"
+    }
+
+    fn process(&self, text: &str, format: Format) -> Result<String, ConversionError> {
+        Ok(match format {
+            Format::Xml => strip_comments(text),
+            Format::Json => text.to_string(),
+        })
+    }
+}
+
+/// A user rule from `--preprocess-rules`.
+struct RewriteRule {
+    name: String,
+    description: String,
+    pattern: Regex,
+    replacement: String,
+}
+
+impl Preprocessor for RewriteRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn process(&self, text: &str, _format: Format) -> Result<String, ConversionError> {
+        Ok(self
+            .pattern
+            .replace_all(text, self.replacement.as_str())
+            .into_owned())
+    }
+}
+
+/// One entry of `--list-preprocessors`.
+#[derive(Clone)]
+pub struct Step {
+    /// Whether the step runs when `--preprocess-only` is not given.
+    pub default: bool,
+    stage: Option<Arc<dyn Preprocessor>>,
+}
+
+impl Step {
+    fn library() -> Self {
+        Self {
+            default: true,
+            stage: None,
+        }
+    }
+
+    fn stage(default: bool, stage: impl Preprocessor + 'static) -> Self {
+        Self {
+            default,
+            stage: Some(Arc::new(stage)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.stage.as_ref().map_or(LIBRARY_STEP, |s| s.name())
+    }
+
+    pub fn description(&self) -> &str {
+        self.stage
+            .as_ref()
+            .map_or("sysmon_json's own preprocess_config", |s| s.description())
+    }
+}
+
+/// The selected steps, ready to run.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    library: bool,
+    stages: Vec<Arc<dyn Preprocessor>>,
+}
+
+impl Pipeline {
+    /// Appends `stage` to the stages that run after the library step.
+    pub fn register(&mut self, stage: Arc<dyn Preprocessor>) {
+        self.stages.push(stage);
+    }
+
+    /// Whether `sysmon_json`'s `preprocess_config` should run first.
+    pub fn runs_library(&self) -> bool {
+        self.library
+    }
+
+    #[cfg(test)]
+    pub fn has_stages(&self) -> bool {
+        !self.stages.is_empty()
+    }
+
+    /// Runs every registered stage over `text`, in order.
+    pub fn apply(&self, text: &str, format: Format) -> Result<String, ConversionError> {
+        let mut text = text.to_string();
+        for stage in &self.stages {
+            text = stage.process(&text, format)?;
+        }
+        Ok(text)
+    }
+}
+
+#[derive(Deserialize)]
+struct RuleFile {
+    #[serde(default)]
+    rule: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+struct RuleSpec {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    pattern: String,
+    replacement: String,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Built-in steps in the order they run.
+pub fn builtin_steps() -> Vec<Step> {
+    vec![
+        Step::library(),
+        Step::stage(false, EntityFix),
+        Step::stage(false, Whitespace),
+        Step::stage(false, StripComments),
+    ]
+}
+
+/// Reads user rewrite rules from a TOML file.
+pub fn load_rules(path: &Path) -> Result<Vec<Step>, ConversionError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ConversionError::io_error(path, e))?;
+    let file: RuleFile = toml::from_str(&text)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))?;
+
+    file.rule
+        .into_iter()
+        .map(|spec| {
+            let pattern = Regex::new(&spec.pattern).map_err(|e| {
+                ConversionError::ValidationError(format!(
+                    "{}: rule '{}': {}",
+                    path.display(),
+                    spec.name,
+                    e
+                ))
+            })?;
+            let rule = RewriteRule {
+                description: spec
+                    .description
+                    .unwrap_or_else(|| format!("Rewrite /{}/", spec.pattern)),
+                name: spec.name,
+                pattern,
+                replacement: spec.replacement,
+            };
+            Ok(Step::stage(spec.enabled, rule))
+        })
+        .collect()
+}
+
+/// Every available step: built-ins followed by the rules in `rules`.
+pub fn available_steps(rules: Option<&Path>) -> Result<Vec<Step>, ConversionError> {
+    let mut steps = builtin_steps();
+    if let Some(path) = rules {
+        for rule in load_rules(path)? {
+            if steps.iter().any(|s| s.name() == rule.name()) {
+                return Err(ConversionError::ValidationError(format!(
+                    "{}: duplicate preprocessing step name '{}'",
+                    path.display(),
+                    rule.name()
+                )));
+            }
+            steps.push(rule);
+        }
+    }
+    Ok(steps)
+}
+
+/// Builds the pipeline from the named steps if `only` is given, otherwise
+/// from the defaults. Unknown names are an error.
+pub fn pipeline(
+    rules: Option<&Path>,
+    only: Option<&[String]>,
+) -> Result<Pipeline, ConversionError> {
+    let steps = available_steps(rules)?;
+    if let Some(only) = only {
+        if let Some(unknown) = only.iter().find(|n| !steps.iter().any(|s| s.name() == *n)) {
+            let names: Vec<&str> = steps.iter().map(Step::name).collect();
+            return Err(ConversionError::InvalidFile(format!(
+                "Unknown preprocessing step '{}' (available: {})",
+                unknown,
+                names.join(", ")
+            )));
+        }
+    }
+
+    let mut pipeline = Pipeline::default();
+    for step in steps {
+        let selected = match only {
+            Some(only) => only.iter().any(|n| n == step.name()),
+            None => step.default,
+        };
+        if !selected {
+            continue;
+        }
+        match step.stage {
+            Some(stage) => pipeline.register(stage),
+            None => pipeline.library = true,
+        }
+    }
+    Ok(pipeline)
+}
+
+/// Prints the available steps for `--list-preprocessors`.
+pub fn list(rules: Option<&Path>) -> Result<(), ConversionError> {
+    for step in available_steps(rules)? {
+        println!(
+            "{:<20} {:<8} {}",
+            step.name(),
+            if step.default { "default" } else { "" },
+            step.description()
+        );
+    }
+    Ok(())
+}
+
+/// Maps a `preprocess_config` failure to the CLI's error type, logging it.
+pub fn library_error(path: &Path, e: PreprocessError) -> ConversionError {
+    match e {
+        PreprocessError::IoError(e) => {
+            error!("IO error during preprocessing: {}", e);
+            ConversionError::io_error(path, e)
+        }
+        PreprocessError::XmlError(e) => {
+            error!("XML parsing error during preprocessing: {}", e);
+            ConversionError::XmlParse(e.into())
+        }
+        PreprocessError::ValidationError(e) => {
+            error!("Validation error during preprocessing: {}", e);
+            ConversionError::ValidationError(e.to_string())
+        }
+        PreprocessError::PathError(e) => {
+            error!("Path error during preprocessing: {}", e);
+            ConversionError::InvalidFile(e)
+        }
+        PreprocessError::ParserError(e) => {
+            error!("Parser error during preprocessing: {}", e);
+            ConversionError::ParserError(e.to_string())
+        }
+    }
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    let mut out: String = text
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<!--") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 4..].find("-->") {
+            Some(end) => &rest[start + 4 + end + 3..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline_defaults_to_library_only() {
+        let pipeline = pipeline(None, None).unwrap();
+        assert!(pipeline.runs_library());
+        assert!(!pipeline.has_stages());
+
+        let only = vec!["whitespace".to_string()];
+        let pipeline = super::pipeline(None, Some(&only)).unwrap();
+        assert!(!pipeline.runs_library());
+        assert!(pipeline.has_stages());
+
+        assert!(super::pipeline(None, Some(&["nope".to_string()])).is_err());
+    }
+
+    #[test]
+    fn test_text_steps() {
+        let only = vec!["strip-comments".to_string(), "whitespace".to_string()];
+        let pipeline = pipeline(None, Some(&only)).unwrap();
+        assert_eq!(
+            pipeline
+                .apply("<Sysmon>  \r\n<!-- note -->\t\r\n</Sysmon>\n", Format::Xml)
+                .unwrap(),
+            "<Sysmon>\n\n</Sysmon>\n"
+        );
+        assert_eq!(
+            pipeline
+                .apply("{\"a\": \"<!-- kept -->\"}", Format::Json)
+                .unwrap(),
+            "{\"a\": \"<!-- kept -->\"}"
+        );
+    }
+
+    #[test]
+    fn test_registered_stage_runs_last() {
+        struct Redact;
+
+        impl Preprocessor for Redact {
+            fn name(&self) -> &str {
+                "redact"
+            }
+
+            fn description(&self) -> &str {
+                "Replace the corporate domain"
+            }
+
+            fn process(&self, text: &str, _format: Format) -> Result<String, ConversionError> {
+                Ok(text.replace("corp.example", "REDACTED"))
+            }
+        }
+
+        let only = vec!["whitespace".to_string()];
+        let mut pipeline = pipeline(None, Some(&only)).unwrap();
+        pipeline.register(Arc::new(Redact));
+        assert_eq!(
+            pipeline
+                .apply("<User>CORP\\a@corp.example</User>  \n", Format::Xml)
+                .unwrap(),
+            "<User>CORP\\a@REDACTED</User>\n"
+        );
+    }
+}
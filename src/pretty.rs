@@ -0,0 +1,379 @@
+//! `--indent`, `--indent-char`, `--key-order` and `--attribute-per-line`:
+//! the layout of converted output.
+//!
+//! Without these options output keeps the layout `sysmon_json` gives it.
+//! With any of them, the document is re-indented: XML one element per
+//! line, with elements that hold only a value kept on one line, and JSON
+//! one member or array item per line. Comments, values and CDATA are kept
+//! as they are, and a comment inside a value stays where it is, so the
+//! text on either side of it is not split apart. `--key-order sorted`
+//! sorts JSON object keys and XML attributes by name, for linters and
+//! diffs that expect a fixed order.
+
+use crate::minify::{self, TagKind, Token};
+use clap::ValueEnum;
+use std::fmt::Write as _;
+use sysmon_cli::format::Format;
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IndentChar {
+    Space,
+    Tab,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyOrder {
+    /// Keep keys and attributes in the order they were written
+    Preserve,
+    /// Sort keys and attributes by name
+    Sorted,
+}
+
+/// How output is laid out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    /// Indent characters per level.
+    pub indent: usize,
+    pub indent_char: IndentChar,
+    pub key_order: KeyOrder,
+    /// Put each attribute of an XML element with several on its own line.
+    pub attribute_per_line: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            indent: 2,
+            indent_char: IndentChar::Space,
+            key_order: KeyOrder::Preserve,
+            attribute_per_line: false,
+        }
+    }
+}
+
+impl Style {
+    fn indent(&self, depth: usize) -> String {
+        let c = match self.indent_char {
+            IndentChar::Space => ' ',
+            IndentChar::Tab => '\t',
+        };
+        std::iter::repeat_n(c, self.indent * depth).collect()
+    }
+}
+
+/// `text`, a document in `format`, laid out in `style`.
+pub fn format(text: &str, format: Format, style: &Style) -> Result<String, ConversionError> {
+    let mut out = match format {
+        Format::Xml => xml(text, style)?,
+        Format::Json => {
+            let mut out = String::with_capacity(text.len());
+            write_json(&Json::parse(text)?, 0, style, &mut out);
+            out
+        }
+    };
+    out.push('\n');
+    Ok(out)
+}
+
+fn xml(text: &str, style: &Style) -> Result<String, ConversionError> {
+    let tokens = minify::tokens(text)?;
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let indent = style.indent(depth);
+        match &tokens[i] {
+            Token::Text(text) if text.trim().is_empty() => {}
+            Token::Text(text) => lines.push(indent + text.trim()),
+            Token::Cdata(markup) | Token::Comment(markup) | Token::Declaration(markup) => {
+                lines.push(indent + markup)
+            }
+            Token::Tag(tag) => match tag.kind {
+                TagKind::End => {
+                    depth = depth.saturating_sub(1);
+                    lines.push(style.indent(depth) + &tag.to_string());
+                }
+                TagKind::Empty => lines.push(indent + &self::tag(tag, depth, style)),
+                TagKind::Start => {
+                    let mut line = indent + &self::tag(tag, depth, style);
+                    match value_end(&tokens, i) {
+                        Some(end) => {
+                            for token in &tokens[i + 1..end] {
+                                if let Token::Text(value)
+                                | Token::Cdata(value)
+                                | Token::Comment(value) = token
+                                {
+                                    line.push_str(value);
+                                }
+                            }
+                            let _ = write!(line, "</{}>", tag.name);
+                            i = end;
+                        }
+                        None => depth += 1,
+                    }
+                    lines.push(line);
+                }
+            },
+        }
+        i += 1;
+    }
+    Ok(lines.join("\n"))
+}
+
+/// If the element whose start tag is `tokens[start]` holds only a value,
+/// possibly with comments in it, the index of its end tag.
+fn value_end(tokens: &[Token], start: usize) -> Option<usize> {
+    let end = tokens[start + 1..]
+        .iter()
+        .position(|t| !matches!(t, Token::Text(_) | Token::Cdata(_) | Token::Comment(_)))
+        .map(|n| start + 1 + n)?;
+    let inner = &tokens[start + 1..end];
+    // An element holding nothing but comments is laid out like any other.
+    let value = inner.iter().any(|t| match t {
+        Token::Text(text) => !text.trim().is_empty(),
+        Token::Cdata(_) => true,
+        _ => false,
+    });
+    let comment = inner.iter().any(|t| matches!(t, Token::Comment(_)));
+    (minify::is_tag(tokens.get(end), TagKind::End) && (value || !comment)).then_some(end)
+}
+
+/// A start or empty-element tag at `depth`, with its attributes in key
+/// order and, for `attribute_per_line`, each on its own line.
+fn tag(tag: &minify::Tag, depth: usize, style: &Style) -> String {
+    let mut tag = tag.clone();
+    if style.key_order == KeyOrder::Sorted {
+        tag.attributes.sort_by_key(|(key, _)| *key);
+    }
+    if !style.attribute_per_line || tag.attributes.len() < 2 {
+        return tag.to_string();
+    }
+    let mut out = format!("<{}", tag.name);
+    for (key, value) in &tag.attributes {
+        let _ = write!(out, "\n{}{}={}", style.indent(depth + 1), key, value);
+    }
+    out.push_str(if tag.kind == TagKind::Empty {
+        "/>"
+    } else {
+        ">"
+    });
+    out
+}
+
+/// A JSON value: objects and arrays parsed, everything else as written.
+#[derive(Debug)]
+enum Json<'a> {
+    Object(Vec<(&'a str, Json<'a>)>),
+    Array(Vec<Json<'a>>),
+    Scalar(&'a str),
+}
+
+impl<'a> Json<'a> {
+    fn parse(text: &'a str) -> Result<Json<'a>, ConversionError> {
+        let mut parser = Parser { text, at: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.at < text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.at..];
+        self.at += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.as_bytes().get(self.at).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ConversionError> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.at += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json<'a>, ConversionError> {
+        match self.peek() {
+            Some(b'{') => {
+                self.at += 1;
+                let mut members = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.at += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected a key"));
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value()?));
+                    match self.peek() {
+                        Some(b',') => self.at += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(b'}')?;
+                Ok(Json::Object(members))
+            }
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.at += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.at += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(b']')?;
+                Ok(Json::Array(items))
+            }
+            Some(b'"') => self.string().map(Json::Scalar),
+            Some(_) => {
+                let rest = &self.text[self.at..];
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || ",]}".contains(c))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(self.error("expected a value"));
+                }
+                self.at += len;
+                Ok(Json::Scalar(&rest[..len]))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// The string starting at the cursor, quotes and escapes included.
+    fn string(&mut self) -> Result<&'a str, ConversionError> {
+        let start = self.at;
+        let mut escaped = false;
+        for (i, c) in self.text[start + 1..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    self.at = start + 1 + i + 1;
+                    return Ok(&self.text[start..self.at]);
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn error(&self, message: &str) -> ConversionError {
+        ConversionError::ParserError(format!("JSON output at byte {}: {}", self.at, message))
+    }
+}
+
+fn write_json(value: &Json, depth: usize, style: &Style, out: &mut String) {
+    match value {
+        Json::Scalar(text) => out.push_str(text),
+        Json::Object(members) if members.is_empty() => out.push_str("{}"),
+        Json::Array(items) if items.is_empty() => out.push_str("[]"),
+        Json::Object(members) => {
+            let mut members: Vec<&(&str, Json)> = members.iter().collect();
+            if style.key_order == KeyOrder::Sorted {
+                members.sort_by_key(|(key, _)| *key);
+            }
+            out.push('{');
+            for (n, (key, value)) in members.into_iter().enumerate() {
+                out.push_str(if n == 0 { "\n" } else { ",\n" });
+                out.push_str(&style.indent(depth + 1));
+                out.push_str(key);
+                out.push_str(": ");
+                write_json(value, depth + 1, style, out);
+            }
+            out.push('\n');
+            out.push_str(&style.indent(depth));
+            out.push('}');
+        }
+        Json::Array(items) => {
+            out.push('[');
+            for (n, item) in items.iter().enumerate() {
+                out.push_str(if n == 0 { "\n" } else { ",\n" });
+                out.push_str(&style.indent(depth + 1));
+                write_json(item, depth + 1, style, out);
+            }
+            out.push('\n');
+            out.push_str(&style.indent(depth));
+            out.push(']');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_xml_with_tabs_sorted_attributes_per_line() {
+        let text = r#"<?xml version="1.0"?><Sysmon schemaversion="4.90"><!-- note --><EventFiltering><RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include"><Image name="b" condition="is"> x.exe</Image><User condition="is"/></ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#;
+        let style = Style {
+            indent: 1,
+            indent_char: IndentChar::Tab,
+            key_order: KeyOrder::Sorted,
+            attribute_per_line: true,
+        };
+        assert_eq!(
+            format(text, Format::Xml, &style).unwrap(),
+            "<?xml version=\"1.0\"?>\n\
+             <Sysmon schemaversion=\"4.90\">\n\
+             \t<!-- note -->\n\
+             \t<EventFiltering>\n\
+             \t\t<RuleGroup\n\t\t\tgroupRelation=\"or\"\n\t\t\tname=\"\">\n\
+             \t\t\t<ProcessCreate onmatch=\"include\">\n\
+             \t\t\t\t<Image\n\t\t\t\t\tcondition=\"is\"\n\t\t\t\t\tname=\"b\"> x.exe</Image>\n\
+             \t\t\t\t<User condition=\"is\"/>\n\
+             \t\t\t</ProcessCreate>\n\
+             \t\t</RuleGroup>\n\
+             \t</EventFiltering>\n\
+             </Sysmon>\n"
+        );
+    }
+
+    #[test]
+    fn test_format_xml_keeps_comments_inside_values() {
+        let text = r#"<Sysmon><ProcessCreate onmatch="exclude"><Image condition="is">C:\a<!-- old: b.exe -->.exe</Image><Rule><!-- empty --></Rule></ProcessCreate></Sysmon>"#;
+        assert_eq!(
+            format(text, Format::Xml, &Style::default()).unwrap(),
+            "<Sysmon>\n  <ProcessCreate onmatch=\"exclude\">\n    \
+             <Image condition=\"is\">C:\\a<!-- old: b.exe -->.exe</Image>\n    \
+             <Rule>\n      <!-- empty -->\n    </Rule>\n  </ProcessCreate>\n</Sysmon>\n"
+        );
+    }
+
+    #[test]
+    fn test_format_json_indents_and_sorts_keys() {
+        let text = r#"{"b": [1, {"y": "a \" }", "x": null}], "a": {}, "c": []}"#;
+        let style = Style {
+            indent: 4,
+            key_order: KeyOrder::Sorted,
+            ..Style::default()
+        };
+        assert_eq!(
+            format(text, Format::Json, &style).unwrap(),
+            "{\n    \"a\": {},\n    \"b\": [\n        1,\n        {\n            \"x\": null,\n            \"y\": \"a \\\" }\"\n        }\n    ],\n    \"c\": []\n}\n"
+        );
+        assert!(format("{\"a\": ", Format::Json, &style).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Progress reporting for batch conversions.
+//!
+//! A batch run calls a [`Progress`] implementation as it goes: once with the
+//! size of the job, around every file, and once with the final [`Summary`].
+//! The CLI draws an indicatif bar with [`Bars`]; programs embedding the
+//! library can implement the trait to feed their own UI or metrics, and pass
+//! it to [`convert_files`].
+//!
+//! Implementations are shared by worker threads, so they must be
+//! `Send + Sync`, and every call is made on the worker that is converting
+//! the file, between jobs. A slow reporter therefore slows the batch down:
+//! keep callbacks cheap and hand anything expensive (network, a UI thread)
+//! off through a channel rather than blocking in the callback.
+
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use sysmon_json::convert_file;
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Converted,
+    Failed,
+    Skipped,
+}
+
+/// One file leaving the batch.
+#[derive(Debug, Clone, Copy)]
+pub struct FileEvent<'a> {
+    pub path: &'a Path,
+    /// Size of the input file.
+    pub bytes: u64,
+    pub status: FileStatus,
+    pub elapsed: Duration,
+}
+
+/// Totals for a finished (or running) batch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub converted: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Input bytes of converted and failed files.
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub failures: Vec<PathBuf>,
+}
+
+impl Summary {
+    pub fn record(&mut self, event: &FileEvent) {
+        match event.status {
+            FileStatus::Converted => self.converted += 1,
+            FileStatus::Failed => {
+                self.failed += 1;
+                self.failures.push(event.path.to_path_buf());
+            }
+            FileStatus::Skipped => self.skipped += 1,
+        }
+        if event.status != FileStatus::Skipped {
+            self.bytes += event.bytes;
+        }
+    }
+
+    /// Files that were converted or failed.
+    pub fn files(&self) -> usize {
+        self.converted + self.failed
+    }
+
+    pub fn files_per_sec(&self) -> f64 {
+        per_sec(self.files() as f64, self.elapsed)
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        per_sec(self.bytes as f64, self.elapsed)
+    }
+
+    /// Plain-text summary table, one row per status, followed by throughput
+    /// and the files that failed.
+    pub fn table(&self) -> String {
+        let mut out = format!("{:<10} {:>7}\n", "Status", "Files");
+        for (label, count) in [
+            ("converted", self.converted),
+            ("failed", self.failed),
+            ("skipped", self.skipped),
+        ] {
+            out.push_str(&format!("{:<10} {:>7}\n", label, count));
+        }
+        out.push_str(&format!(
+            "{} in {} ({:.1} files/s, {}/s)\n",
+            HumanBytes(self.bytes),
+            HumanDuration(self.elapsed),
+            self.files_per_sec(),
+            HumanBytes(self.bytes_per_sec() as u64)
+        ));
+        for path in &self.failures {
+            out.push_str(&format!("failed: {}\n", path.display()));
+        }
+        out
+    }
+}
+
+fn per_sec(amount: f64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        amount / secs
+    } else {
+        0.0
+    }
+}
+
+/// Receives batch progress. Every method has an empty default, so an
+/// implementation only overrides what it needs.
+pub trait Progress: Send + Sync {
+    /// Called once before any file, with the number of files to convert and
+    /// their combined size.
+    fn on_start(&self, _files: usize, _bytes: u64) {}
+
+    fn on_file_start(&self, _path: &Path) {}
+
+    /// Called for every file leaving the batch, including skipped and
+    /// failed ones.
+    fn on_file_done(&self, _event: &FileEvent) {}
+
+    /// Called with the reason a file failed, before its
+    /// [`on_file_done`](Progress::on_file_done).
+    fn on_error(&self, _path: &Path, _error: &ConversionError) {}
+
+    fn on_finish(&self, _summary: &Summary) {}
+}
+
+/// Reports nothing.
+pub struct Silent;
+
+impl Progress for Silent {}
+
+/// Terminal progress bar with ETA, throughput and the current file, ending
+/// with [`Summary::table`] on stderr.
+pub struct Bars {
+    bar: ProgressBar,
+    started: Instant,
+    done: Mutex<Summary>,
+    total_files: Mutex<usize>,
+}
+
+impl Bars {
+    pub fn new() -> Self {
+        let bar = ProgressBar::new(0);
+        let style = ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:30} {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta}) \
+             {prefix} {wide_msg}",
+        )
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("=> ");
+        bar.set_style(style);
+        Bars {
+            bar,
+            started: Instant::now(),
+            done: Mutex::new(Summary::default()),
+            total_files: Mutex::new(0),
+        }
+    }
+}
+
+impl Default for Bars {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress for Bars {
+    fn on_start(&self, files: usize, bytes: u64) {
+        *self.total_files.lock().unwrap() = files;
+        self.bar.set_length(bytes);
+        self.bar.set_prefix(format!("0/{} files", files));
+        self.bar.enable_steady_tick(Duration::from_millis(200));
+    }
+
+    fn on_file_start(&self, path: &Path) {
+        self.bar.set_message(path.display().to_string());
+    }
+
+    fn on_file_done(&self, event: &FileEvent) {
+        let mut done = self.done.lock().unwrap();
+        done.record(event);
+        done.elapsed = self.started.elapsed();
+        if event.status == FileStatus::Skipped {
+            return;
+        }
+        self.bar.inc(event.bytes);
+        self.bar.set_prefix(format!(
+            "{}/{} files, {:.1} files/s",
+            done.files(),
+            *self.total_files.lock().unwrap(),
+            done.files_per_sec()
+        ));
+    }
+
+    fn on_error(&self, path: &Path, error: &ConversionError) {
+        self.bar
+            .println(format!("failed: {}: {}", path.display(), error));
+    }
+
+    fn on_finish(&self, summary: &Summary) {
+        self.bar.finish_and_clear();
+        eprint!("{}", summary.table());
+    }
+}
+
+/// Converts each `(input, output)` pair with `sysmon_json::convert_file`,
+/// one at a time, reporting to `progress`. Failures are counted in the
+/// summary rather than stopping the run.
+pub fn convert_files(jobs: &[(PathBuf, PathBuf)], progress: &dyn Progress) -> Summary {
+    let sizes: Vec<u64> = jobs
+        .iter()
+        .map(|(input, _)| std::fs::metadata(input).map(|m| m.len()).unwrap_or(0))
+        .collect();
+    progress.on_start(jobs.len(), sizes.iter().sum());
+
+    let started = Instant::now();
+    let mut summary = Summary::default();
+    for ((input, output), bytes) in jobs.iter().zip(sizes) {
+        progress.on_file_start(input);
+        let file_started = Instant::now();
+        let status = match convert_file(input.as_path(), output.as_path()) {
+            Ok(()) => FileStatus::Converted,
+            Err(e) => {
+                progress.on_error(input, &e);
+                FileStatus::Failed
+            }
+        };
+        let event = FileEvent {
+            path: input,
+            bytes,
+            status,
+            elapsed: file_started.elapsed(),
+        };
+        summary.record(&event);
+        progress.on_file_done(&event);
+    }
+    summary.elapsed = started.elapsed();
+    progress.on_finish(&summary);
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(path: &Path, bytes: u64, status: FileStatus) -> FileEvent<'_> {
+        FileEvent {
+            path,
+            bytes,
+            status,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_summary_totals_and_throughput() {
+        let mut summary = Summary::default();
+        summary.record(&event(Path::new("a.xml"), 300, FileStatus::Converted));
+        summary.record(&event(Path::new("b.xml"), 100, FileStatus::Failed));
+        summary.record(&event(Path::new("c.xml"), 50, FileStatus::Skipped));
+        summary.elapsed = Duration::from_secs(2);
+
+        assert_eq!((summary.converted, summary.failed, summary.skipped), (1, 1, 1));
+        assert_eq!(summary.bytes, 400);
+        assert_eq!(summary.failures, vec![PathBuf::from("b.xml")]);
+        assert_eq!(summary.files_per_sec(), 1.0);
+        assert_eq!(summary.bytes_per_sec(), 200.0);
+        assert!(summary.table().contains("failed: b.xml"));
+    }
+
+    #[test]
+    fn test_convert_files_reports_each_file() {
+        struct Count(Mutex<Vec<FileStatus>>, Mutex<Vec<PathBuf>>);
+        impl Progress for Count {
+            fn on_file_done(&self, event: &FileEvent) {
+                self.0.lock().unwrap().push(event.status);
+            }
+
+            fn on_error(&self, path: &Path, _error: &ConversionError) {
+                self.1.lock().unwrap().push(path.to_path_buf());
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.xml");
+        let count = Count(Mutex::new(Vec::new()), Mutex::new(Vec::new()));
+        let summary = convert_files(&[(missing.clone(), dir.path().join("out.json"))], &count);
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(*count.0.lock().unwrap(), vec![FileStatus::Failed]);
+        assert_eq!(*count.1.lock().unwrap(), vec![missing]);
+    }
+}
@@ -0,0 +1,260 @@
+//! Provenance manifests: what a merge or build was made from, and with what.
+//!
+//! A manifest records the tool version, the commit of each git work tree
+//! the inputs are in, the SHA-256 of every input (files they
+//! `<?include?>` among them) and of every output, and the options that
+//! shape the result, so an audit can tell exactly which sources a deployed
+//! config came from. A work tree counts as dirty when anything under the
+//! inputs differs from the commit, untracked files included.
+//!
+//! Placeholder values are recorded whatever their source: `--var`,
+//! `--vars-file`, a build matrix or the environment.
+
+use crate::merge::Resolution;
+use crate::{encoding, include, io_guard};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use sysmon_json::error::ConversionError;
+
+/// Who made an artifact and from which revisions.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Provenance {
+    pub tool: String,
+    pub tool_version: String,
+    /// One entry per work tree holding inputs, in input order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub git: Vec<GitRef>,
+}
+
+/// The commit a work tree is at.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct GitRef {
+    /// The top directory of the work tree.
+    pub work_tree: String,
+    pub commit: String,
+    /// `None` on a detached HEAD.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Whether anything under the inputs differs from the commit, including
+    /// untracked files.
+    pub dirty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FileHash {
+    pub path: String,
+    pub sha256: String,
+    pub bytes: usize,
+}
+
+/// The manifest `--manifest` writes for a merge.
+#[derive(Debug, Serialize)]
+pub struct MergeManifest {
+    #[serde(flatten)]
+    pub provenance: Provenance,
+    pub platform: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<Resolution>,
+    pub vars: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<FileHash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suppress: Option<FileHash>,
+    pub inputs: Vec<FileHash>,
+    pub outputs: Vec<FileHash>,
+}
+
+impl Provenance {
+    /// This tool, and the commits of the work trees `inputs` are in.
+    pub fn new(inputs: &[&Path]) -> Provenance {
+        Provenance {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            git: git_refs(inputs),
+        }
+    }
+}
+
+pub fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn hash(path: &Path, bytes: &[u8]) -> FileHash {
+    FileHash {
+        path: path.display().to_string(),
+        sha256: sha256(bytes),
+        bytes: bytes.len(),
+    }
+}
+
+pub fn hash_file(path: &Path) -> Result<FileHash, ConversionError> {
+    let bytes = std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+    Ok(hash(path, &bytes))
+}
+
+/// Hashes each of `paths` and every file it includes within `includes`,
+/// each file once.
+pub fn hash_inputs(
+    paths: &[PathBuf],
+    includes: include::Limits,
+) -> Result<Vec<FileHash>, ConversionError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut hashes = Vec::new();
+    for path in paths {
+        let bytes = std::fs::read(path).map_err(|e| ConversionError::io_error(path, e))?;
+        let text = encoding::decode(&bytes)
+            .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", path.display(), e)))?;
+        let (_, included) = include::expand_listing(path, &text, includes)?;
+        if !files.contains(path) {
+            files.push(path.clone());
+            hashes.push(hash(path, &bytes));
+        }
+        for file in included {
+            if !files.contains(&file) {
+                hashes.push(hash_file(&file)?);
+                files.push(file);
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+/// Runs git in `dir`; `None` when it fails or is not installed.
+fn git(dir: &Path, args: &[&OsStr]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The commit of each work tree holding one of `inputs` (files or
+/// directories), in input order; inputs outside a work tree are skipped.
+pub fn git_refs(inputs: &[&Path]) -> Vec<GitRef> {
+    let mut trees: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    for input in inputs {
+        let Ok(input) = std::fs::canonicalize(input) else {
+            continue;
+        };
+        let dir = if input.is_dir() {
+            input.as_path()
+        } else {
+            input.parent().unwrap_or(Path::new("."))
+        };
+        let Some(top) = git(dir, &["rev-parse".as_ref(), "--show-toplevel".as_ref()]) else {
+            continue;
+        };
+        match trees.iter_mut().find(|(t, _)| *t == top) {
+            Some((_, paths)) => paths.push(input),
+            None => trees.push((top, vec![input])),
+        }
+    }
+
+    trees
+        .into_iter()
+        .filter_map(|(top, paths)| {
+            let dir = Path::new(&top);
+            let commit = git(dir, &["rev-parse".as_ref(), "HEAD".as_ref()])?;
+            let branch = git(
+                dir,
+                &["symbolic-ref", "--quiet", "--short", "HEAD"].map(OsStr::new),
+            )
+            .filter(|b| !b.is_empty());
+            let mut status: Vec<&OsStr> = ["status", "--porcelain", "--untracked-files=all", "--"]
+                .map(OsStr::new)
+                .to_vec();
+            status.extend(paths.iter().map(|p| p.as_os_str()));
+            // Unknown rather than clean when git cannot tell.
+            let dirty = git(dir, &status).is_none_or(|status| !status.is_empty());
+            Some(GitRef {
+                work_tree: top,
+                commit,
+                branch,
+                dirty,
+            })
+        })
+        .collect()
+}
+
+/// Writes `manifest` as pretty JSON to `path`.
+pub fn write(path: &Path, manifest: &impl Serialize) -> Result<(), ConversionError> {
+    let json = serde_json::to_string_pretty(manifest)
+        .map(|json| json + "\n")
+        .map_err(|e| ConversionError::Other(e.to_string()))?;
+    io_guard::write(path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_ref_is_none_outside_a_work_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(git_refs(&[dir.path()]), []);
+        let file = dir.path().join("a.xml");
+        std::fs::write(&file, "<Sysmon/>").unwrap();
+        let hashed = hash_inputs(std::slice::from_ref(&file), include::Limits::new(10)).unwrap();
+        assert_eq!(hashed[0].path, file.display().to_string());
+        assert_eq!(hashed[0].bytes, 9);
+    }
+
+    #[test]
+    fn test_git_refs_cover_file_inputs_and_untracked_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+                .args(args)
+                .output()
+                .map(|o| o.status.success());
+            status.unwrap_or(false)
+        };
+        if !run(&["init", "-q"]) {
+            return; // git is not installed
+        }
+        let rules = dir.path().join("rules");
+        std::fs::create_dir(&rules).unwrap();
+        std::fs::write(rules.join("a.xml"), "<Sysmon/>").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+        assert!(run(&["add", "rules"]) && run(&["commit", "-qm", "rules"]));
+
+        // An untracked file elsewhere in the work tree is not an input.
+        let file = rules.join("a.xml");
+        let refs = git_refs(&[file.as_path(), rules.as_path()]);
+        assert_eq!(refs.len(), 1);
+        assert!(!refs[0].dirty);
+        assert_eq!(refs[0].commit.len(), 40);
+
+        std::fs::write(rules.join("b.xml"), "<Sysmon/>").unwrap();
+        assert!(git_refs(&[rules.as_path()])[0].dirty);
+    }
+
+    #[test]
+    fn test_hash_inputs_covers_included_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main.xml");
+        let shared = dir.path().join("shared.xml");
+        std::fs::write(&main, "<Sysmon><?include href=\"shared.xml\"?></Sysmon>").unwrap();
+        std::fs::write(&shared, "<DnsQuery onmatch=\"exclude\"/>").unwrap();
+        let hashed =
+            hash_inputs(&[main.clone(), shared.clone()], include::Limits::new(10)).unwrap();
+        let paths: Vec<&str> = hashed.iter().map(|h| h.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [main.display().to_string(), shared.display().to_string()]
+        );
+    }
+}
@@ -0,0 +1,606 @@
+//! `query`: find rules matching an expression, across one config or a tree
+//! of modular files.
+//!
+//! Every field condition is a candidate, including those inside `<Rule>`
+//! combinations. An expression compares its attributes:
+//!
+//! ```text
+//! event==ProcessCreate && field==CommandLine && value~="powershell"
+//! (onmatch==exclude || condition!=is) && !group~=test
+//! ```
+//!
+//! Keys are `event`, `onmatch`, `field`, `condition`, `value`, `name` (the
+//! condition's or its `<Rule>`'s name), `group` (the rule group's name) and
+//! `file`. `==` and `!=` compare whole values, `~=` tests for a substring;
+//! all three ignore ASCII case. Values are bare words or double-quoted
+//! strings with `\"` and `\\` escapes. `!` binds tighter than `&&`, which
+//! binds tighter than `||`.
+//!
+//! With `--xpath` the expression is an XPath instead (see [`crate::xpath`]
+//! for the supported subset), evaluated against each file's XML form, and
+//! the selected elements are printed as XML fragments or JSON.
+
+use crate::{document, io_guard, validate, walk, xpath};
+use clap::{Args, ValueEnum};
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use sysmon_cli::model::{EventFilter, FieldCondition, Filter, RuleGroup, SysmonConfig};
+use sysmon_json::error::ConversionError;
+use xmltree::Element;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum QueryFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct QueryArgs {
+    /// Config file, or a directory searched recursively for XML and JSON configs
+    pub path: PathBuf,
+
+    /// Expression such as 'event==ProcessCreate && value~="powershell"'
+    pub expression: String,
+
+    /// Treat the expression as an XPath, e.g.
+    /// '//RuleGroup[@groupRelation="or"]/ProcessCreate'
+    #[arg(long)]
+    pub xpath: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: QueryFormat,
+
+    /// Write the matches to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// A field condition that matched, with its context.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Match {
+    pub file: PathBuf,
+    /// Best-effort line of the condition; only known for XML input.
+    pub line: Option<usize>,
+    pub group: Option<String>,
+    pub event: String,
+    pub onmatch: String,
+    pub name: Option<String>,
+    pub field: String,
+    pub condition: String,
+    pub value: String,
+}
+
+/// Something an XPath selected: an element or an attribute or text value.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct XPathMatch {
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub element: Option<Fragment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// An XML element as JSON.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Fragment {
+    pub name: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Fragment>,
+}
+
+impl From<&Element> for Fragment {
+    fn from(element: &Element) -> Self {
+        Fragment {
+            name: element.name.clone(),
+            attributes: element
+                .attributes
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            text: element
+                .get_text()
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty()),
+            children: document::child_elements(element)
+                .map(Fragment::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Event,
+    OnMatch,
+    Field,
+    Condition,
+    Value,
+    Name,
+    Group,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Contains,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Expr {
+    Compare(Key, Op, String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+pub fn run(args: &QueryArgs) -> Result<(), ConversionError> {
+    let files = if args.path.is_dir() {
+        walk::files(&args.path, usize::MAX, false)
+            .into_iter()
+            .filter(|p| {
+                p.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+                    e.eq_ignore_ascii_case("xml") || e.eq_ignore_ascii_case("json")
+                })
+            })
+            .collect()
+    } else {
+        vec![args.path.clone()]
+    };
+
+    let report = if args.xpath {
+        xpath_report(args, &files)?
+    } else {
+        expression_report(args, &files)?
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, report),
+        None => {
+            print!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+fn expression_report(args: &QueryArgs, files: &[PathBuf]) -> Result<String, ConversionError> {
+    let expr = parse(&args.expression)?;
+    let mut matches = Vec::new();
+    for file in files {
+        let (text, is_xml) = validate::load(file)?;
+        let config = SysmonConfig::from_xml_str(&text)
+            .map_err(|e| ConversionError::ParserError(format!("{}: {}", file.display(), e)))?;
+        matches.extend(search(
+            &config,
+            file,
+            &expr,
+            is_xml.then_some(text.as_str()),
+        ));
+    }
+    info!(
+        "{} matching condition(s) in {} file(s)",
+        matches.len(),
+        files.len()
+    );
+
+    Ok(match args.format {
+        QueryFormat::Text => matches.iter().fold(String::new(), |mut out, m| {
+            let _ = writeln!(out, "{}", describe(m));
+            out
+        }),
+        QueryFormat::Json => serde_json::to_string_pretty(&matches)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    })
+}
+
+fn xpath_report(args: &QueryArgs, files: &[PathBuf]) -> Result<String, ConversionError> {
+    let path = xpath::parse(&args.expression)?;
+    let mut matches = Vec::new();
+    let mut text = String::new();
+    for file in files {
+        let root = Element::parse(validate::load(file)?.0.as_bytes())
+            .map_err(|e| ConversionError::ParserError(format!("{}: {}", file.display(), e)))?;
+        for node in path.select(&root) {
+            if files.len() > 1 {
+                let _ = writeln!(text, "<!-- {} -->", file.display());
+            }
+            let (element, value) = match node {
+                xpath::Node::Element(element) => {
+                    text.push_str(document::to_fragment_string(element)?.trim_end());
+                    (Some(Fragment::from(element)), None)
+                }
+                xpath::Node::Value(value) => {
+                    text.push_str(&value);
+                    (None, Some(value))
+                }
+            };
+            text.push('\n');
+            matches.push(XPathMatch {
+                file: file.clone(),
+                element,
+                value,
+            });
+        }
+    }
+    info!(
+        "{} node(s) selected in {} file(s)",
+        matches.len(),
+        files.len()
+    );
+
+    Ok(match args.format {
+        QueryFormat::Text => text,
+        QueryFormat::Json => serde_json::to_string_pretty(&matches)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    })
+}
+
+/// The conditions in `config` that satisfy `expr`. `text` is the XML the
+/// config was read from, used to find line numbers.
+pub fn search(config: &SysmonConfig, file: &Path, expr: &Expr, text: Option<&str>) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for (index, group) in config.rule_groups.iter().enumerate() {
+        for event in &group.events {
+            let fields = event.filters.iter().flat_map(|filter| match filter {
+                Filter::Field(field) => vec![(field, field.name.as_deref())],
+                Filter::Rule(rule) => rule
+                    .fields
+                    .iter()
+                    .map(|f| (f, f.name.as_deref().or(rule.name.as_deref())))
+                    .collect(),
+            });
+            let mut seen: Vec<&str> = Vec::new();
+            for (field, name) in fields {
+                seen.push(&field.field);
+                let candidate = candidate(file, group, event, field, name);
+                if !evaluate(expr, &candidate) {
+                    continue;
+                }
+                let nth = seen.iter().filter(|f| **f == field.field).count();
+                let location = format!(
+                    "RuleGroup[{}]/{}/{}[{}]",
+                    index + 1,
+                    event.event,
+                    field.field,
+                    nth
+                );
+                matches.push(Match {
+                    line: text.and_then(|text| validate::line_of(text, &location)),
+                    ..candidate
+                });
+            }
+        }
+    }
+    matches
+}
+
+/// The `Match` an expression is tested against for one field condition;
+/// `name` is the condition's name or its `<Rule>`'s.
+pub fn candidate(
+    file: &Path,
+    group: &RuleGroup,
+    event: &EventFilter,
+    field: &FieldCondition,
+    name: Option<&str>,
+) -> Match {
+    Match {
+        file: file.to_path_buf(),
+        line: None,
+        group: group.name.clone().filter(|n| !n.is_empty()),
+        event: event.event.clone(),
+        onmatch: event.onmatch.as_str().to_string(),
+        name: name.map(str::to_string),
+        field: field.field.clone(),
+        condition: field.condition.as_str().to_string(),
+        value: field.value.clone(),
+    }
+}
+
+pub fn evaluate(expr: &Expr, candidate: &Match) -> bool {
+    match expr {
+        Expr::Compare(key, op, wanted) => {
+            let file = candidate.file.display().to_string();
+            let actual = match key {
+                Key::Event => Some(candidate.event.as_str()),
+                Key::OnMatch => Some(candidate.onmatch.as_str()),
+                Key::Field => Some(candidate.field.as_str()),
+                Key::Condition => Some(candidate.condition.as_str()),
+                Key::Value => Some(candidate.value.as_str()),
+                Key::Name => candidate.name.as_deref(),
+                Key::Group => candidate.group.as_deref(),
+                Key::File => Some(file.as_str()),
+            }
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+            let wanted = wanted.to_ascii_lowercase();
+            match op {
+                Op::Eq => actual == wanted,
+                Op::Ne => actual != wanted,
+                Op::Contains => actual.contains(&wanted),
+            }
+        }
+        Expr::Not(inner) => !evaluate(inner, candidate),
+        Expr::And(a, b) => evaluate(a, candidate) && evaluate(b, candidate),
+        Expr::Or(a, b) => evaluate(a, candidate) || evaluate(b, candidate),
+    }
+}
+
+fn describe(m: &Match) -> String {
+    let file = match m.line {
+        Some(line) => format!("{}:{}", m.file.display(), line),
+        None => m.file.display().to_string(),
+    };
+    let name = m
+        .name
+        .as_ref()
+        .map(|n| format!("[{}] ", n))
+        .unwrap_or_default();
+    format!(
+        "{}: {}/{}: {}{} {} {:?}",
+        file, m.event, m.onmatch, name, m.field, m.condition, m.value
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+/// Parses a query expression.
+pub fn parse(input: &str) -> Result<Expr, ConversionError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: input.len(),
+    };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some((at, _)) => Err(invalid(*at, "expected && or ||")),
+    }
+}
+
+fn invalid(at: usize, message: impl std::fmt::Display) -> ConversionError {
+    ConversionError::ValidationError(format!("Invalid query at column {}: {}", at + 1, message))
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ConversionError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let token = match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('&', Some('&'))
+            | ('|', Some('|'))
+            | ('=', Some('='))
+            | ('!', Some('='))
+            | ('~', Some('=')) => {
+                chars.next();
+                match c {
+                    '&' => Token::And,
+                    '|' => Token::Or,
+                    '=' => Token::Op(Op::Eq),
+                    '!' => Token::Op(Op::Ne),
+                    _ => Token::Op(Op::Contains),
+                }
+            }
+            ('!', _) => Token::Not,
+            ('(', _) => Token::Open,
+            (')', _) => Token::Close,
+            ('"', _) => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => word.push(c),
+                            None => return Err(invalid(at, "unterminated string")),
+                        },
+                        Some((_, c)) => word.push(c),
+                        None => return Err(invalid(at, "unterminated string")),
+                    }
+                }
+                Token::Word(word)
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some((_, c)) = chars.peek() {
+                    if c.is_whitespace() || "&|=!~()\"".contains(*c) {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push((at, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Offset reported for errors at the end of the input.
+    end: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Expr, ConversionError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ConversionError> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ConversionError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::Open) {
+            let expr = self.or()?;
+            if !self.eat(&Token::Close) {
+                return Err(self.error("expected )"));
+            }
+            return Ok(expr);
+        }
+
+        let key = match self.peek() {
+            Some(Token::Word(word)) => match word.to_ascii_lowercase().as_str() {
+                "event" => Key::Event,
+                "onmatch" => Key::OnMatch,
+                "field" => Key::Field,
+                "condition" => Key::Condition,
+                "value" => Key::Value,
+                "name" => Key::Name,
+                "group" => Key::Group,
+                "file" => Key::File,
+                _ => {
+                    return Err(self.error(format!(
+                        "unknown key {:?}; use event, onmatch, field, condition, value, \
+                         name, group or file",
+                        word
+                    )))
+                }
+            },
+            _ => return Err(self.error("expected a key such as event or value")),
+        };
+        self.pos += 1;
+        let Some(Token::Op(op)) = self.peek() else {
+            return Err(self.error("expected ==, != or ~="));
+        };
+        let op = *op;
+        self.pos += 1;
+        let Some(Token::Word(value)) = self.peek() else {
+            return Err(self.error("expected a value"));
+        };
+        let value = value.clone();
+        self.pos += 1;
+        Ok(Expr::Compare(key, op, value))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.pos).is_some_and(|(_, t)| t == token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// An error at the current token, or at the end of the input.
+    fn error(&self, message: impl std::fmt::Display) -> ConversionError {
+        let at = self.tokens.get(self.pos).map_or(self.end, |(at, _)| *at);
+        invalid(at, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+<EventFiltering>
+<RuleGroup name="office" groupRelation="or">
+<ProcessCreate onmatch="include">
+<ParentImage condition="end with">\winword.exe</ParentImage>
+<Rule name="encoded" groupRelation="and">
+<Image condition="end with">\powershell.exe</Image>
+<CommandLine condition="contains">-enc</CommandLine>
+</Rule>
+<CommandLine condition="contains">PowerShell -nop</CommandLine>
+</ProcessCreate>
+</RuleGroup>
+<RuleGroup name="" groupRelation="or">
+<DnsQuery onmatch="exclude">
+<QueryName condition="end with">.microsoft.com</QueryName>
+</DnsQuery>
+</RuleGroup>
+</EventFiltering>
+</Sysmon>
+"#;
+
+    fn lines(query: &str) -> Vec<String> {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        search(
+            &config,
+            Path::new("c.xml"),
+            &parse(query).unwrap(),
+            Some(CONFIG),
+        )
+        .iter()
+        .map(describe)
+        .collect()
+    }
+
+    #[test]
+    fn test_search_matches_fields_in_and_out_of_rules() {
+        assert_eq!(
+            lines(r#"event==processcreate && field==CommandLine && value~="powershell""#),
+            vec![r#"c.xml:10: ProcessCreate/include: CommandLine contains "PowerShell -nop""#]
+        );
+        assert_eq!(
+            lines("name==encoded && !field==Image"),
+            vec![r#"c.xml:8: ProcessCreate/include: [encoded] CommandLine contains "-enc""#]
+        );
+        assert_eq!(
+            lines("(onmatch==exclude || group==office) && condition==\"end with\"").len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_parse_reports_the_column() {
+        let error = |query| parse(query).unwrap_err().to_string();
+        assert!(error("event==ProcessCreate &&").contains("expected a key"));
+        assert!(error("colour==red").contains("column 1: unknown key \"colour\""));
+        assert!(error("value==\"abc").contains("unterminated string"));
+        assert!(error("event ProcessCreate").contains("expected ==, != or ~="));
+    }
+
+    #[test]
+    fn test_fragment_keeps_attributes_text_and_children() {
+        let root = Element::parse(CONFIG.as_bytes()).unwrap();
+        let path = xpath::parse(r#"//Rule[@name="encoded"]"#).unwrap();
+        let xpath::Node::Element(rule) = path.select(&root)[0] else {
+            panic!("expected an element");
+        };
+        let fragment = Fragment::from(rule);
+        assert_eq!(fragment.attributes["groupRelation"], "and");
+        assert_eq!(fragment.text, None);
+        assert_eq!(fragment.children.len(), 2);
+        assert_eq!(fragment.children[1].name, "CommandLine");
+        assert_eq!(fragment.children[1].text.as_deref(), Some("-enc"));
+    }
+}
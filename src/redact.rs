@@ -0,0 +1,363 @@
+//! `redact`: a copy of a config that is safe to share outside the
+//! organization.
+//!
+//! Rule values, rule names and processing instructions such as
+//! `<?include href="..."?>` are searched for organization-specific
+//! details; other attributes (`condition`, `onmatch`, ...) are structure
+//! and left alone. Each distinct match is
+//! replaced with a numbered placeholder such as `HOST_1`, so rules that
+//! tested the same value still do after redaction. Built-in patterns cover
+//! private IPv4 addresses, the server of a `\\server\share` path and the
+//! user name in a `C:\Users\<name>` path; `--patterns` adds an
+//! organization's own, such as its internal domain:
+//!
+//! ```toml
+//! [[pattern]]
+//! name = "internal-hosts"
+//! regex = '(?i)\b[a-z0-9-]+\.corp\.example\.com\b'
+//! placeholder = "HOST"
+//!
+//! [[pattern]]
+//! name = "service-accounts"
+//! regex = '(?i)\bCORP\\(svc_[a-z0-9_]+)'
+//! placeholder = "USER"
+//! keep = ["svc_backup"]
+//! ```
+//!
+//! When a pattern has a capture group, only the first group is replaced.
+//! Matches listed in `keep` are left alone. `--mapping` writes which
+//! placeholder stands for which value, for the owner's reference only.
+//! Comments are not carried over into the redacted copy.
+
+use crate::{convert, document, io_guard};
+use clap::Args;
+use log::{info, warn};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+/// A private (RFC 1918) IPv4 address.
+pub const PRIVATE_IPV4: &str = r"\b(?:10(?:\.\d{1,3}){3}|172\.(?:1[6-9]|2\d|3[01])(?:\.\d{1,3}){2}|192\.168(?:\.\d{1,3}){2})\b";
+
+/// Built-in patterns as (name, regex, placeholder, matches to keep).
+const DEFAULTS: &[(&str, &str, &str, &[&str])] = &[
+    ("private-ipv4", PRIVATE_IPV4, "IP", &[]),
+    (
+        "unc-server",
+        r"\\\\([A-Za-z0-9][A-Za-z0-9.-]*)\\",
+        "HOST",
+        &["localhost", "127.0.0.1"],
+    ),
+    (
+        "profile-user",
+        r"(?i)\\Users\\([^\\]+)",
+        "USER",
+        &["Public", "Default", "Default User", "All Users"],
+    ),
+];
+
+#[derive(Args)]
+pub struct RedactArgs {
+    /// Configuration to redact (XML or JSON)
+    pub config: PathBuf,
+
+    /// TOML file of extra `[[pattern]]` tables with name, regex, placeholder and keep
+    #[arg(long, value_name = "FILE")]
+    pub patterns: Option<PathBuf>,
+
+    /// Use only the --patterns file, not the built-in patterns
+    #[arg(long, requires = "patterns")]
+    pub no_defaults: bool,
+
+    /// Write the placeholder for each redacted value to this file
+    #[arg(long, value_name = "FILE")]
+    pub mapping: Option<PathBuf>,
+
+    /// Write the redacted config here (JSON for a .json path) instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PatternFile {
+    pattern: Vec<PatternSpec>,
+}
+
+/// One `[[pattern]]` table as written.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PatternSpec {
+    name: String,
+    regex: String,
+    placeholder: String,
+    keep: Vec<String>,
+}
+
+pub struct Pattern {
+    name: String,
+    regex: Regex,
+    placeholder: String,
+    keep: Vec<String>,
+}
+
+impl Pattern {
+    pub fn new(
+        name: &str,
+        regex: &str,
+        placeholder: &str,
+        keep: &[&str],
+    ) -> Result<Pattern, String> {
+        if placeholder.is_empty() {
+            return Err(format!("pattern {}: placeholder is empty", name));
+        }
+        Ok(Pattern {
+            name: name.to_string(),
+            regex: Regex::new(regex).map_err(|e| format!("pattern {}: {}", name, e))?,
+            placeholder: placeholder.to_string(),
+            keep: keep.iter().map(|k| k.to_string()).collect(),
+        })
+    }
+
+    fn defaults() -> Vec<Pattern> {
+        DEFAULTS
+            .iter()
+            .map(|(name, regex, placeholder, keep)| {
+                Pattern::new(name, regex, placeholder, keep).expect("built-in patterns compile")
+            })
+            .collect()
+    }
+}
+
+/// Placeholders handed out so far, so a value gets the same one each time.
+#[derive(Default)]
+pub struct Redactor {
+    patterns: Vec<Pattern>,
+    assigned: HashMap<(String, String), String>,
+    next: HashMap<String, usize>,
+    /// Replacements made per pattern name.
+    pub counts: BTreeMap<String, usize>,
+}
+
+impl Redactor {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        Redactor {
+            patterns,
+            ..Redactor::default()
+        }
+    }
+
+    /// `text` with every match of every pattern replaced.
+    pub fn redact(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        for p in 0..self.patterns.len() {
+            let pattern = &self.patterns[p];
+            let spans: Vec<(usize, usize)> = pattern
+                .regex
+                .captures_iter(&text)
+                .filter_map(|c| c.get(1).or_else(|| c.get(0)))
+                .filter(|m| {
+                    !pattern
+                        .keep
+                        .iter()
+                        .any(|k| k.eq_ignore_ascii_case(m.as_str()))
+                })
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            let (name, placeholder) = (pattern.name.clone(), pattern.placeholder.clone());
+            for (start, end) in spans.into_iter().rev() {
+                let replacement = self.placeholder(&placeholder, &text[start..end]);
+                text.replace_range(start..end, &replacement);
+                *self.counts.entry(name.clone()).or_default() += 1;
+            }
+        }
+        text
+    }
+
+    fn placeholder(&mut self, placeholder: &str, value: &str) -> String {
+        let key = (placeholder.to_string(), value.to_string());
+        if let Some(assigned) = self.assigned.get(&key) {
+            return assigned.clone();
+        }
+        let n = self.next.entry(placeholder.to_string()).or_default();
+        *n += 1;
+        let assigned = format!("{}_{}", placeholder, n);
+        self.assigned.insert(key, assigned.clone());
+        assigned
+    }
+
+    /// `placeholder<TAB>value` lines, sorted by placeholder.
+    pub fn mapping(&self) -> String {
+        let mut rows: Vec<(&String, &String)> = self
+            .assigned
+            .iter()
+            .map(|((_, value), placeholder)| (placeholder, value))
+            .collect();
+        rows.sort_by(|a, b| natural(a.0).cmp(&natural(b.0)));
+        rows.iter()
+            .fold(String::new(), |mut out, (placeholder, value)| {
+                let _ = writeln!(out, "{}\t{}", placeholder, value);
+                out
+            })
+    }
+
+    /// Redacts every `name` attribute, text, CDATA section and processing
+    /// instruction under `element`.
+    pub fn redact_element(&mut self, element: &mut Element) {
+        if let Some(name) = element.attributes.get_mut("name") {
+            *name = self.redact(name);
+        }
+        for node in &mut element.children {
+            match node {
+                XMLNode::Element(child) => self.redact_element(child),
+                XMLNode::Text(text) | XMLNode::CData(text) => *text = self.redact(text),
+                XMLNode::ProcessingInstruction(_, Some(data)) => *data = self.redact(data),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `HOST_10` after `HOST_9`.
+fn natural(placeholder: &str) -> (&str, usize) {
+    match placeholder.rsplit_once('_') {
+        Some((prefix, n)) => (prefix, n.parse().unwrap_or(0)),
+        None => (placeholder, 0),
+    }
+}
+
+pub fn run(args: &RedactArgs) -> Result<(), ConversionError> {
+    let mut patterns = if args.no_defaults {
+        Vec::new()
+    } else {
+        Pattern::defaults()
+    };
+    if let Some(path) = &args.patterns {
+        let text = std::fs::read_to_string(path).map_err(|e| ConversionError::io_error(path, e))?;
+        let file: PatternFile = toml::from_str(&text)
+            .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))?;
+        for spec in file.pattern {
+            let keep: Vec<&str> = spec.keep.iter().map(String::as_str).collect();
+            patterns.push(
+                Pattern::new(&spec.name, &spec.regex, &spec.placeholder, &keep).map_err(|e| {
+                    ConversionError::ValidationError(format!("{}: {}", path.display(), e))
+                })?,
+            );
+        }
+    }
+
+    let mut root = document::load(&args.config)?;
+    let mut redactor = Redactor::new(patterns);
+    redactor.redact_element(&mut root);
+    for (name, count) in &redactor.counts {
+        info!("Redacted {} match(es) of {}", count, name);
+    }
+    if redactor.counts.is_empty() {
+        warn!("Nothing in {} matched a pattern", args.config.display());
+    }
+
+    if let Some(path) = &args.mapping {
+        io_guard::write(path, redactor.mapping())?;
+        warn!(
+            "{} holds the original values; do not share it",
+            path.display()
+        );
+    }
+    match &args.output {
+        Some(path) => document::save(path, &root),
+        None => {
+            let mut text = document::to_xml_string(&root)?;
+            if document::is_json(&args.config) {
+                text = convert::xml_str_to_json(&text)?;
+            }
+            println!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_replaces_values_consistently() {
+        let mut root = Element::parse(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+<NetworkConnect onmatch="exclude"><DestinationIp condition="is">10.1.2.3</DestinationIp><DestinationIp condition="is">8.8.8.8</DestinationIp></NetworkConnect>
+<ProcessCreate onmatch="include"><Image name="from 10.1.2.3" condition="begin with">\\fs01.corp.example.com\tools\</Image><Image condition="begin with">C:\Users\Public\</Image><Image condition="begin with">C:\Users\alice\</Image></ProcessCreate>
+</RuleGroup></EventFiltering></Sysmon>"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut patterns = Pattern::defaults();
+        patterns.push(
+            Pattern::new(
+                "internal",
+                r"(?i)\b[a-z0-9-]+\.corp\.example\.com\b",
+                "HOST",
+                &[],
+            )
+            .unwrap(),
+        );
+        let mut redactor = Redactor::new(patterns);
+        redactor.redact_element(&mut root);
+
+        let xml = document::to_xml_string(&root).unwrap();
+        for expected in [
+            ">IP_1</DestinationIp>",
+            ">8.8.8.8</DestinationIp>",
+            "name=\"from IP_1\"",
+            ">\\\\HOST_1\\tools\\</Image>",
+            ">C:\\Users\\Public\\</Image>",
+            ">C:\\Users\\USER_1\\</Image>",
+        ] {
+            assert!(xml.contains(expected), "missing {:?} in\n{}", expected, xml);
+        }
+        assert!(!xml.contains("alice") && !xml.contains("fs01"));
+        assert_eq!(
+            redactor.mapping(),
+            "HOST_1\tfs01.corp.example.com\nIP_1\t10.1.2.3\nUSER_1\talice\n"
+        );
+        assert_eq!(redactor.counts["private-ipv4"], 2);
+        assert!(Pattern::new("bad", "(", "X", &[]).is_err());
+    }
+
+    #[test]
+    fn test_redact_covers_instructions_but_not_structure() {
+        let mut root = Element::parse(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup name="corp" groupRelation="or">
+<?include href="\\fs01.corp.example.com\configs\common.xml"?>
+<ProcessCreate onmatch="include"><Image name="corp tools" condition="begin with">C:\corp\</Image></ProcessCreate>
+</RuleGroup></EventFiltering></Sysmon>"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let patterns = vec![
+            Pattern::new(
+                "internal",
+                r"(?i)\b[a-z0-9-]+\.corp\.example\.com\b",
+                "HOST",
+                &[],
+            )
+            .unwrap(),
+            Pattern::new("org", r"\b(?:corp|include|begin)\b", "ORG", &[]).unwrap(),
+        ];
+        let mut redactor = Redactor::new(patterns);
+        redactor.redact_element(&mut root);
+
+        let xml = document::to_xml_string(&root).unwrap();
+        for expected in [
+            "<?include href=\"\\\\HOST_1\\configs\\common.xml\"?>",
+            "<RuleGroup name=\"ORG_1\" groupRelation=\"or\">",
+            "name=\"ORG_1 tools\" condition=\"begin with\">C:\\ORG_1\\</Image>",
+        ] {
+            assert!(xml.contains(expected), "missing {:?} in\n{}", expected, xml);
+        }
+        assert!(!xml.contains("fs01"));
+    }
+}
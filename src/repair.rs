@@ -0,0 +1,246 @@
+//! Best-effort fixes for XML that is almost, but not quite, well-formed.
+//!
+//! Enabled with `--lenient`. Community configs often contain bare `&` in
+//! condition values, junk before the prolog, or the same attribute twice on
+//! one element. Each fix is recorded so the user can see what was changed.
+
+use log::{info, warn};
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Repair {
+    fn at(text: &str, offset: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: text[..offset].matches('\n').count() + 1,
+            message: message.into(),
+        }
+    }
+}
+
+/// Applies every repair to `text` and returns the fixed text along with a
+/// description of what was changed.
+pub fn repair_xml(text: &str) -> (String, Vec<Repair>) {
+    let mut repairs = Vec::new();
+    let text = strip_before_prolog(text, &mut repairs);
+    let text = escape_ampersands(&text, &mut repairs);
+    let text = dedupe_attributes(&text, &mut repairs);
+    (text, repairs)
+}
+
+/// [`repair_xml`] for the contents of `path`, warning about each fix.
+pub fn repair_file(path: &Path, text: &str) -> String {
+    let (repaired, repairs) = repair_xml(text);
+    for fix in &repairs {
+        warn!("{}:{}: {}", path.display(), fix.line, fix.message);
+    }
+    if !repairs.is_empty() {
+        info!(
+            "Repaired {} problem(s) in {}",
+            repairs.len(),
+            path.display()
+        );
+    }
+    repaired
+}
+
+fn strip_before_prolog(text: &str, repairs: &mut Vec<Repair>) -> String {
+    let start = text.find('<').unwrap_or(text.len());
+    let prefix = &text[..start];
+    let stray =
+        !prefix.trim().is_empty() || (!prefix.is_empty() && text[start..].starts_with("<?xml"));
+    if !stray {
+        return text.to_string();
+    }
+    repairs.push(Repair::at(
+        text,
+        0,
+        format!(
+            "removed {} stray character(s) before the prolog",
+            prefix.chars().count()
+        ),
+    ));
+    text[start..].to_string()
+}
+
+pub fn escape_ampersands(text: &str, repairs: &mut Vec<Repair>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if let Some(end) = unparsed_end(text, i) {
+            out.push_str(&text[i..end]);
+            i = end;
+            continue;
+        }
+        let ch = text[i..].chars().next().unwrap();
+        if ch == '&' && !starts_with_reference(&text[i + 1..]) {
+            out.push_str("&amp;");
+            repairs.push(Repair::at(text, i, "escaped bare '&'"));
+        } else {
+            out.push(ch);
+        }
+        i += ch.len_utf8();
+    }
+    out
+}
+
+/// True if `rest` (the text after a `&`) is a predefined entity or a
+/// character reference.
+fn starts_with_reference(rest: &str) -> bool {
+    let Some(end) = rest.find(';').filter(|&end| end > 0 && end <= 10) else {
+        return false;
+    };
+    let name = &rest[..end];
+    match name.strip_prefix('#') {
+        Some(number) => match number.strip_prefix(['x', 'X']) {
+            Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => matches!(name, "amp" | "lt" | "gt" | "quot" | "apos"),
+    }
+}
+
+fn dedupe_attributes(text: &str, repairs: &mut Vec<Repair>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while let Some(offset) = text[i..].find('<') {
+        let start = i + offset;
+        out.push_str(&text[i..start]);
+        i = match unparsed_end(text, start) {
+            Some(end) => {
+                out.push_str(&text[start..end]);
+                end
+            }
+            None => dedupe_tag(text, start, &mut out, repairs),
+        };
+    }
+    out.push_str(&text[i..]);
+    out
+}
+
+/// Copies the tag starting at `start` into `out`, dropping every repeat of
+/// an attribute after its first occurrence, and returns the index just past
+/// the tag. Anything that does not look like `name="value"` is copied as is.
+fn dedupe_tag(text: &str, start: usize, out: &mut String, repairs: &mut Vec<Repair>) -> usize {
+    let bytes = text.as_bytes();
+    let skip_space = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut i = start + 1;
+    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'>' | b'/') {
+        i += 1;
+    }
+    out.push_str(&text[start..i]);
+
+    let mut seen = HashSet::new();
+    loop {
+        let attr_start = i;
+        let name_start = skip_space(i);
+        let mut name_end = name_start;
+        while name_end < bytes.len()
+            && !bytes[name_end].is_ascii_whitespace()
+            && !matches!(bytes[name_end], b'=' | b'>' | b'/')
+        {
+            name_end += 1;
+        }
+        let equals = skip_space(name_end);
+        let quote_at = skip_space(equals + 1);
+        let quote = bytes.get(quote_at).copied();
+        if name_start == name_end
+            || bytes.get(equals) != Some(&b'=')
+            || !matches!(quote, Some(b'"' | b'\''))
+        {
+            break;
+        }
+        let Some(close) = text[quote_at + 1..].find(quote.unwrap() as char) else {
+            break;
+        };
+        let end = quote_at + 1 + close + 1;
+
+        let name = &text[name_start..name_end];
+        if seen.insert(name) {
+            out.push_str(&text[attr_start..end]);
+        } else {
+            repairs.push(Repair::at(
+                text,
+                name_start,
+                format!("removed duplicate attribute '{}'", name),
+            ));
+        }
+        i = end;
+    }
+
+    let end = text[i..].find('>').map(|k| i + k + 1).unwrap_or(text.len());
+    out.push_str(&text[i..end]);
+    end
+}
+
+/// If a comment, CDATA section or processing instruction starts at `at`,
+/// returns the index just past its end. Their content is never repaired.
+fn unparsed_end(text: &str, at: usize) -> Option<usize> {
+    let rest = &text[at..];
+    [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")]
+        .iter()
+        .find(|(open, _)| rest.starts_with(open))
+        .map(|(open, close)| {
+            rest[open.len()..]
+                .find(close)
+                .map(|i| at + open.len() + i + close.len())
+                .unwrap_or(text.len())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escapes_bare_ampersands_only() {
+        let (fixed, repairs) = repair_xml(
+            "<Sysmon>\n<Image condition=\"is\">C:\\R&D\\tool.exe &amp; &#38; &#x26;</Image>\n<!-- a & b --></Sysmon>",
+        );
+        assert_eq!(
+            fixed,
+            "<Sysmon>\n<Image condition=\"is\">C:\\R&amp;D\\tool.exe &amp; &#38; &#x26;</Image>\n<!-- a & b --></Sysmon>"
+        );
+        assert_eq!(
+            repairs,
+            vec![Repair {
+                line: 2,
+                message: "escaped bare '&'".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_strips_junk_before_prolog() {
+        let (fixed, repairs) = repair_xml("\u{feff}junk\n<?xml version=\"1.0\"?><Sysmon/>");
+        assert_eq!(fixed, "<?xml version=\"1.0\"?><Sysmon/>");
+        assert_eq!(repairs.len(), 1);
+
+        let (fixed, repairs) = repair_xml("\n<Sysmon/>");
+        assert_eq!(fixed, "\n<Sysmon/>");
+        assert!(repairs.is_empty());
+    }
+
+    #[test]
+    fn test_removes_duplicate_attributes() {
+        let (fixed, repairs) = repair_xml(
+            "<RuleGroup name=\"a\" groupRelation=\"or\" name='b'><ProcessCreate onmatch=\"include\"/></RuleGroup>",
+        );
+        assert_eq!(
+            fixed,
+            "<RuleGroup name=\"a\" groupRelation=\"or\"><ProcessCreate onmatch=\"include\"/></RuleGroup>"
+        );
+        assert_eq!(repairs[0].message, "removed duplicate attribute 'name'");
+    }
+}
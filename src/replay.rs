@@ -0,0 +1,189 @@
+//! Replaying events against a config: would Sysmon log them?
+//!
+//! Sysmon decides per event type. An event matching any exclude rule is
+//! dropped; otherwise, if the event type has include filters, it is logged
+//! only when an include rule matches, and if it has none it is logged. The
+//! conditions directly under an event filter combine with their rule
+//! group's `groupRelation`, a `<Rule>` combines its own with its own, and
+//! an empty filter matches nothing. Comparisons ignore case, and `less
+//! than` and `more than` compare integers, as Sysmon's do; a value that is
+//! not one matches neither.
+//!
+//! Event types a config has no filter for are logged when Sysmon logs them
+//! by default (see [`schema::LOGGED_BY_DEFAULT`]); events Sysmon always
+//! writes, such as its own service and configuration changes, are always
+//! logged.
+
+use sysmon_cli::model::{
+    Condition, EventFilter, FieldCondition, Filter, GroupRelation, OnMatch, RuleGroup, SysmonConfig,
+};
+use sysmon_cli::schema;
+
+/// Whether `config` logs an event with ID `id` and the given fields.
+pub fn is_logged<'a>(
+    config: &SysmonConfig,
+    id: u32,
+    fields: impl Fn(&str) -> Option<&'a str>,
+) -> bool {
+    let Some(event) = schema::event_type_for_id(id) else {
+        return true;
+    };
+    let mut configured = false;
+    let mut included = None;
+    for group in &config.rule_groups {
+        for filter in group.events.iter().filter(|f| f.event == event.name) {
+            configured = true;
+            let matched = matches(group, filter, &fields);
+            match filter.onmatch {
+                OnMatch::Exclude if matched => return false,
+                OnMatch::Exclude => {}
+                OnMatch::Include => *included.get_or_insert(false) |= matched,
+            }
+        }
+    }
+    included.unwrap_or(configured || schema::LOGGED_BY_DEFAULT.contains(&event.name))
+}
+
+/// Whether the rules of `filter`, in `group`, match the event.
+fn matches<'a>(
+    group: &RuleGroup,
+    filter: &EventFilter,
+    fields: &impl Fn(&str) -> Option<&'a str>,
+) -> bool {
+    let rule = |rule: &Filter| match rule {
+        Filter::Field(condition) => holds(condition, fields),
+        Filter::Rule(rule) => match rule.group_relation {
+            GroupRelation::And => rule.fields.iter().all(|c| holds(c, fields)),
+            GroupRelation::Or => rule.fields.iter().any(|c| holds(c, fields)),
+        },
+    };
+    if filter.filters.is_empty() {
+        return false;
+    }
+    match group.group_relation {
+        Some(GroupRelation::And) => filter.filters.iter().all(rule),
+        _ => filter.filters.iter().any(rule),
+    }
+}
+
+/// Whether the event's value of the condition's field satisfies it.
+fn holds<'a>(condition: &FieldCondition, fields: &impl Fn(&str) -> Option<&'a str>) -> bool {
+    let actual = fields(&condition.field).unwrap_or_default().to_lowercase();
+    let expected = condition.value.to_lowercase();
+    let values = || expected.split(';').filter(|v| !v.is_empty());
+    match condition.condition {
+        Condition::Is => actual == expected,
+        Condition::IsNot => actual != expected,
+        Condition::IsAny => values().any(|v| actual == v),
+        Condition::Contains => actual.contains(&expected),
+        Condition::ContainsAny => values().any(|v| actual.contains(v)),
+        Condition::ContainsAll => values().all(|v| actual.contains(v)),
+        Condition::Excludes => !actual.contains(&expected),
+        Condition::ExcludesAny => values().any(|v| !actual.contains(v)),
+        Condition::ExcludesAll => values().all(|v| !actual.contains(v)),
+        Condition::BeginWith => actual.starts_with(&expected),
+        Condition::NotBeginWith => !actual.starts_with(&expected),
+        Condition::EndWith => actual.ends_with(&expected),
+        Condition::NotEndWith => !actual.ends_with(&expected),
+        Condition::LessThan | Condition::MoreThan => {
+            let (Ok(actual), Ok(expected)) =
+                (actual.trim().parse::<i64>(), expected.trim().parse())
+            else {
+                return false;
+            };
+            if condition.condition == Condition::LessThan {
+                actual < expected
+            } else {
+                actual > expected
+            }
+        }
+        // An image name alone matches the file name of a full path.
+        Condition::Image => {
+            actual == expected
+                || (!expected.contains('\\') && actual.rsplit('\\').next() == Some(&expected))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_logged_follows_sysmon_filtering() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+<RuleGroup name="" groupRelation="or">
+<ProcessCreate onmatch="include">
+<Image condition="end with">\powershell.exe</Image>
+<Rule groupRelation="and"><Image condition="image">rundll32.exe</Image><CommandLine condition="contains any">javascript:;.dll,#</CommandLine></Rule>
+</ProcessCreate>
+<ProcessCreate onmatch="exclude"><ParentImage condition="is">C:\Windows\System32\svchost.exe</ParentImage></ProcessCreate>
+<NetworkConnect onmatch="exclude"><DestinationPort condition="is any">80;443</DestinationPort></NetworkConnect>
+<ImageLoad onmatch="include"/>
+<NetworkConnect onmatch="exclude"><SourcePort condition="less than">1024</SourcePort></NetworkConnect>
+</RuleGroup>
+<RuleGroup name="" groupRelation="and">
+<DnsQuery onmatch="exclude"><Image condition="end with">\chrome.exe</Image><QueryName condition="end with">.google.com</QueryName></DnsQuery>
+</RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let logged = |id: u32, fields: &[(&str, &str)]| {
+            is_logged(&config, id, |name: &str| {
+                fields.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+            })
+        };
+        let powershell = [(
+            "Image",
+            r"C:\Windows\System32\WindowsPowerShell\v1.0\PowerShell.exe",
+        )];
+        assert!(logged(1, &powershell));
+        assert!(!logged(1, &[("Image", r"C:\Windows\System32\cmd.exe")]));
+        assert!(logged(
+            1,
+            &[
+                ("Image", r"C:\Windows\System32\rundll32.exe"),
+                ("CommandLine", "rundll32 x.dll,#1")
+            ]
+        ));
+        assert!(!logged(
+            1,
+            &[
+                ("Image", r"C:\Windows\System32\rundll32.exe"),
+                ("CommandLine", "rundll32")
+            ]
+        ));
+        assert!(!logged(
+            1,
+            &[
+                powershell[0],
+                ("ParentImage", r"c:\windows\system32\svchost.exe")
+            ]
+        ));
+        assert!(!logged(3, &[("DestinationPort", "443")]));
+        assert!(logged(3, &[("DestinationPort", "445")]));
+        // Compared as numbers: "135" sorts after "1024" as text.
+        assert!(!logged(
+            3,
+            &[("DestinationPort", "445"), ("SourcePort", "135")]
+        ));
+        assert!(logged(
+            3,
+            &[("DestinationPort", "445"), ("SourcePort", "49152")]
+        ));
+        assert!(!logged(7, &[]));
+        assert!(!logged(
+            22,
+            &[("Image", r"C:\chrome.exe"), ("QueryName", "www.google.com")]
+        ));
+        assert!(logged(
+            22,
+            &[("Image", r"C:\chrome.exe"), ("QueryName", "example.com")]
+        ));
+        // Unfiltered event types follow Sysmon's defaults.
+        assert!(logged(5, &[]));
+        assert!(!logged(11, &[]));
+        assert!(logged(16, &[]));
+    }
+}
@@ -0,0 +1,546 @@
+//! `decode-rules`: recover a configuration from the binary rule blob Sysmon
+//! keeps in the registry.
+//!
+//! The driver stores its active rules in the `Rules` value (`REG_BINARY`)
+//! of `HKLM\SYSTEM\CurrentControlSet\Services\SysmonDrv\Parameters`; the key
+//! is named after the driver, so it differs when Sysmon was installed under
+//! another name. The input is either a `.reg` export of that key or the raw
+//! value bytes, as carved from an offline hive.
+//!
+//! The format is undocumented. It is read here as community reverse
+//! engineering describes it, all fields little-endian:
+//!
+//! - header: schema minor and major version (`u16` each), event count and
+//!   offset of the first event (`u32` each)
+//! - event: event ID, `onmatch` (0 exclude, 1 include), offset of the next
+//!   event and filter count, followed by the filters
+//! - filter: field index, condition, offset of the next filter and data
+//!   length, followed by the value as NUL-terminated UTF-16
+//!
+//! Condition codes 0 to 8 are the conditions early Sysmon releases had;
+//! the ones added since follow, in the order `sysmon -s` lists them. Every
+//! offset and count is checked against the blob before it is used, so a
+//! truncated or corrupt blob is reported rather than read past its end.
+//!
+//! The blob holds event IDs and field indexes, not names, so decoding needs
+//! the schema of the Sysmon release that wrote it: the output of `sysmon -s`
+//! from the same version. Rule names and `<Rule>` combinations are not
+//! recovered. A `.reg` export also carries `HashingAlgorithm`, which is
+//! turned back into `<HashAlgorithms>`.
+
+use crate::{convert, encoding, io_guard};
+use clap::Args;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use sysmon_cli::format::Format;
+use sysmon_cli::model::{
+    Condition, ConfigOption, EventFilter, FieldCondition, Filter, GroupRelation, OnMatch,
+    RuleGroup, SysmonConfig,
+};
+use sysmon_cli::schema::Version;
+use sysmon_json::error::ConversionError;
+use xmltree::Element;
+
+/// `HashingAlgorithm` bits and the names `<HashAlgorithms>` uses for them.
+const HASH_ALGORITHMS: &[(u32, &str)] = &[(1, "sha1"), (2, "md5"), (4, "sha256"), (8, "imphash")];
+
+/// Condition codes as stored in the blob.
+const CONDITIONS: &[Condition] = &[
+    Condition::Is,
+    Condition::IsNot,
+    Condition::Contains,
+    Condition::Excludes,
+    Condition::BeginWith,
+    Condition::EndWith,
+    Condition::LessThan,
+    Condition::MoreThan,
+    Condition::Image,
+    Condition::IsAny,
+    Condition::ContainsAny,
+    Condition::ContainsAll,
+    Condition::ExcludesAny,
+    Condition::ExcludesAll,
+    Condition::NotBeginWith,
+    Condition::NotEndWith,
+];
+
+/// Bytes an event or filter record takes before its value.
+const RECORD: u64 = 16;
+
+#[derive(Args)]
+pub struct DecodeRulesArgs {
+    /// `.reg` export of the SysmonDrv Parameters key, or the raw `Rules` value
+    #[arg(short, long)]
+    pub input: PathBuf,
+
+    /// Output of `sysmon -s` from the Sysmon release that wrote the rules
+    #[arg(long)]
+    pub schema: PathBuf,
+
+    /// Output file; `.json` writes JSON, anything else XML (default: XML on stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn run(args: &DecodeRulesArgs) -> Result<(), ConversionError> {
+    let bytes =
+        std::fs::read(&args.input).map_err(|e| ConversionError::io_error(&args.input, e))?;
+    let schema_text = std::fs::read(&args.schema)
+        .map_err(|e| ConversionError::io_error(&args.schema, e))
+        .and_then(|bytes| {
+            encoding::decode(&bytes).map_err(|e| {
+                ConversionError::InvalidFile(format!("{}: {}", args.schema.display(), e))
+            })
+        })?;
+    let schema = Schema::parse(&schema_text)?;
+
+    let config = decode_input(&bytes, &schema)?;
+    info!(
+        "Decoded {} event filter(s) from {}",
+        config.events().count(),
+        args.input.display()
+    );
+    write_config(&config, args.output.as_deref())
+}
+
+/// Decodes a `.reg` export or a raw `Rules` value.
+pub fn decode_input(bytes: &[u8], schema: &Schema) -> Result<SysmonConfig, ConversionError> {
+    match RegExport::parse(bytes)? {
+        Some(export) => decode(&export.rules, schema, export.hashing),
+        None => decode(bytes, schema, None),
+    }
+}
+
+/// Writes `config` to `output`, as JSON for a `.json` path and XML
+/// otherwise, or prints it as XML.
+pub fn write_config(config: &SysmonConfig, output: Option<&Path>) -> Result<(), ConversionError> {
+    let xml = config.to_xml_string()?;
+    match output {
+        Some(path) => {
+            let text = match Format::from_extension(path) {
+                Some(Format::Json) => convert::xml_str_to_json(&xml)?,
+                _ => xml,
+            };
+            io_guard::write(path, text)?;
+            info!("Wrote {}", path.display());
+        }
+        None => print!("{}", xml),
+    }
+    Ok(())
+}
+
+/// Event types and their fields, in the order the blob indexes them.
+#[derive(Debug, Default)]
+pub struct Schema {
+    version: Option<String>,
+    events: BTreeMap<u32, (String, Vec<String>)>,
+}
+
+impl Schema {
+    /// Reads `sysmon -s` output. The banner Sysmon prints before the
+    /// `<manifest>` is skipped.
+    pub fn parse(text: &str) -> Result<Self, ConversionError> {
+        let start = text.find("<manifest").ok_or_else(|| {
+            ConversionError::InvalidFile(
+                "Schema has no <manifest> (expected `sysmon -s` output)".to_string(),
+            )
+        })?;
+        let root = Element::parse(&text.as_bytes()[start..])
+            .map_err(|e| ConversionError::ParserError(format!("Schema: {}", e)))?;
+
+        let mut schema = Schema {
+            version: root.attributes.get("schemaversion").cloned(),
+            events: BTreeMap::new(),
+        };
+        collect_events(&root, &mut schema.events);
+        if schema.events.is_empty() {
+            return Err(ConversionError::InvalidFile(
+                "Schema defines no filterable events".to_string(),
+            ));
+        }
+        Ok(schema)
+    }
+}
+
+fn collect_events(element: &Element, events: &mut BTreeMap<u32, (String, Vec<String>)>) {
+    for child in element.children.iter().filter_map(|n| n.as_element()) {
+        if child.name == "event" {
+            let id = child.attributes.get("value").and_then(|v| v.parse().ok());
+            if let (Some(id), Some(rule)) = (id, child.attributes.get("rulename")) {
+                let fields = child
+                    .children
+                    .iter()
+                    .filter_map(|n| n.as_element())
+                    .filter(|d| d.name == "data")
+                    .filter_map(|d| d.attributes.get("name").cloned())
+                    .collect();
+                events.insert(id, (rule.clone(), fields));
+            }
+        } else {
+            collect_events(child, events);
+        }
+    }
+}
+
+/// The values of interest in a `.reg` export of the Parameters key.
+#[derive(Debug, PartialEq, Eq)]
+struct RegExport {
+    rules: Vec<u8>,
+    hashing: Option<u32>,
+}
+
+impl RegExport {
+    /// Returns `None` when `bytes` is not a `.reg` export.
+    fn parse(bytes: &[u8]) -> Result<Option<Self>, ConversionError> {
+        let Ok(text) = encoding::decode(bytes) else {
+            return Ok(None);
+        };
+        let text = text.trim_start_matches('\u{feff}');
+        if !text.starts_with("Windows Registry Editor") && !text.starts_with("REGEDIT4") {
+            return Ok(None);
+        }
+
+        let mut rules = None;
+        let mut hashing = None;
+        for line in text.replace("\\\r\n", "").replace("\\\n", "").lines() {
+            let Some((name, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+            match name.trim_matches('"') {
+                "Rules" => {
+                    let hex = value.strip_prefix("hex:").ok_or_else(|| {
+                        ConversionError::InvalidFile("Rules is not a binary value".to_string())
+                    })?;
+                    rules = Some(parse_hex(hex)?);
+                }
+                "HashingAlgorithm" => {
+                    hashing = value
+                        .strip_prefix("dword:")
+                        .and_then(|v| u32::from_str_radix(v.trim(), 16).ok());
+                }
+                _ => {}
+            }
+        }
+        let rules = rules.ok_or_else(|| {
+            ConversionError::InvalidFile("The registry export has no Rules value".to_string())
+        })?;
+        Ok(Some(RegExport { rules, hashing }))
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, ConversionError> {
+    hex.split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            u8::from_str_radix(b, 16)
+                .map_err(|_| ConversionError::InvalidFile(format!("Bad byte in Rules: {:?}", b)))
+        })
+        .collect()
+}
+
+/// Decodes a rule blob into a configuration.
+pub fn decode(
+    blob: &[u8],
+    schema: &Schema,
+    hashing: Option<u32>,
+) -> Result<SysmonConfig, ConversionError> {
+    let reader = Reader(blob);
+    let version = Version::new(reader.u16(2)? as u32, reader.u16(0)? as u32).to_string();
+    if schema.version.as_ref().is_some_and(|v| *v != version) {
+        warn!(
+            "Rules were written for schema {} but the schema given is {}; field names may be wrong",
+            version,
+            schema.version.as_deref().unwrap_or_default()
+        );
+    }
+
+    let mut config = SysmonConfig {
+        schema_version: version,
+        options: Vec::new(),
+        rule_groups: Vec::new(),
+    };
+    if let Some(bits) = hashing {
+        let names: Vec<&str> = HASH_ALGORITHMS
+            .iter()
+            .filter(|(bit, _)| bits & bit != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if !names.is_empty() {
+            config.options.push(ConfigOption {
+                name: "HashAlgorithms".to_string(),
+                value: names.join(","),
+            });
+        }
+    }
+
+    // Records never overlap, so a blob this size holds at most this many;
+    // counts past it, or offsets that loop back, are corrupt.
+    let mut budget = blob.len() as u64 / RECORD;
+    let mut take = |offset: u64, what: &str, count: u64| {
+        if count > budget {
+            return Err(corrupt(
+                offset,
+                format!("{} {}(s) cannot fit in {} bytes", count, what, blob.len()),
+            ));
+        }
+        budget -= count;
+        Ok(())
+    };
+
+    let count = reader.u32(4)?;
+    take(4, "event", count.into())?;
+    let mut offset = u64::from(reader.u32(8)?);
+    for _ in 0..count {
+        let id = reader.u32(offset)?;
+        let onmatch = match reader.u32(offset + 4)? {
+            0 => OnMatch::Exclude,
+            1 => OnMatch::Include,
+            other => return Err(corrupt(offset + 4, format!("onmatch {}", other))),
+        };
+        let next = reader.u32(offset + 8)?;
+        let filter_count = reader.u32(offset + 12)?;
+        take(offset + 12, "filter", filter_count.into())?;
+        let (event, fields) = schema
+            .events
+            .get(&id)
+            .ok_or_else(|| corrupt(offset, format!("event ID {} not in the schema", id)))?;
+
+        let mut filters = Vec::new();
+        let mut filter = offset + RECORD;
+        for _ in 0..filter_count {
+            let index = reader.u32(filter)?;
+            let code = reader.u32(filter + 4)?;
+            let next_filter = reader.u32(filter + 8)?;
+            let length = reader.u32(filter + 12)?;
+            let field = fields.get(index as usize).ok_or_else(|| {
+                corrupt(filter, format!("{} has no field number {}", event, index))
+            })?;
+            let condition = CONDITIONS
+                .get(code as usize)
+                .ok_or_else(|| corrupt(filter + 4, format!("condition code {}", code)))?;
+            filters.push(Filter::Field(FieldCondition {
+                field: field.clone(),
+                condition: *condition,
+                value: utf16(reader.bytes(filter + RECORD, length.into())?),
+                name: None,
+            }));
+            filter = next_filter.into();
+        }
+
+        config.rule_groups.push(RuleGroup {
+            name: None,
+            group_relation: Some(GroupRelation::Or),
+            events: vec![EventFilter {
+                event: event.clone(),
+                onmatch,
+                filters,
+            }],
+        });
+        offset = next.into();
+    }
+    Ok(config)
+}
+
+/// Reads little-endian values at `u64` offsets, which cannot overflow when
+/// a `u32` offset from the blob is added to.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes(&self, offset: u64, length: u64) -> Result<&[u8], ConversionError> {
+        let end = offset.saturating_add(length);
+        usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(end).ok())
+            .and_then(|(start, end)| self.0.get(start..end))
+            .ok_or_else(|| corrupt(offset, "read past the end".to_string()))
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16, ConversionError> {
+        let bytes = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32, ConversionError> {
+        let bytes = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn corrupt(offset: u64, detail: String) -> ConversionError {
+    ConversionError::InvalidFile(format!(
+        "Unreadable rule blob at offset {:#x}: {}",
+        offset, detail
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"System Monitor v15.15 - System activity monitor
+
+<manifest schemaversion="4.90" binaryversion="15.15">
+  <events>
+    <event name="SYSMONEVENT_CREATE_PROCESS" value="1" rulename="ProcessCreate">
+      <data name="RuleName"/><data name="UtcTime"/><data name="ProcessGuid"/>
+      <data name="ProcessId"/><data name="Image"/>
+    </event>
+    <event name="SYSMONEVENT_DNS_QUERY" value="22" rulename="DnsQuery">
+      <data name="RuleName"/><data name="UtcTime"/><data name="ProcessGuid"/>
+      <data name="ProcessId"/><data name="QueryName"/>
+    </event>
+  </events>
+</manifest>"#;
+
+    /// `(id, onmatch, [(field, condition, value)])`
+    type Event<'a> = (u32, u32, &'a [(u32, u32, &'a str)]);
+
+    fn encode(events: &[Event]) -> Vec<u8> {
+        let mut blob = vec![90, 0, 4, 0];
+        blob.extend((events.len() as u32).to_le_bytes());
+        blob.extend(12u32.to_le_bytes());
+        for (i, (id, onmatch, filters)) in events.iter().enumerate() {
+            let mut body = Vec::new();
+            let start = blob.len() + 16;
+            for (j, (field, condition, value)) in filters.iter().enumerate() {
+                let data: Vec<u8> = value
+                    .encode_utf16()
+                    .chain([0])
+                    .flat_map(u16::to_le_bytes)
+                    .collect();
+                let next = start + body.len() + 16 + data.len();
+                let next = if j + 1 < filters.len() { next } else { 0 };
+                body.extend(field.to_le_bytes());
+                body.extend(condition.to_le_bytes());
+                body.extend((next as u32).to_le_bytes());
+                body.extend((data.len() as u32).to_le_bytes());
+                body.extend(data);
+            }
+            let next = if i + 1 < events.len() {
+                start + body.len()
+            } else {
+                0
+            };
+            blob.extend(id.to_le_bytes());
+            blob.extend(onmatch.to_le_bytes());
+            blob.extend((next as u32).to_le_bytes());
+            blob.extend((filters.len() as u32).to_le_bytes());
+            blob.extend(body);
+        }
+        blob
+    }
+
+    #[test]
+    fn test_decode_blob() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let blob = encode(&[
+            (1, 1, &[(4, 5, "\\cmd.exe"), (4, 2, "powershell")]),
+            (22, 0, &[(4, 0, "example.com")]),
+        ]);
+        let config = decode(&blob, &schema, Some(5)).unwrap();
+
+        assert_eq!(config.schema_version, "4.90");
+        assert_eq!(config.options[0].value, "sha1,sha256");
+        let events: Vec<_> = config.events().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "ProcessCreate");
+        assert_eq!(events[0].onmatch, OnMatch::Include);
+        assert_eq!(
+            events[0].filters[1],
+            Filter::Field(FieldCondition {
+                field: "Image".to_string(),
+                condition: Condition::Contains,
+                value: "powershell".to_string(),
+                name: None,
+            })
+        );
+        assert_eq!(events[1].event, "DnsQuery");
+        assert_eq!(events[1].onmatch, OnMatch::Exclude);
+
+        assert!(decode(&blob[..blob.len() - 4], &schema, None).is_err());
+        assert!(decode(&encode(&[(99, 1, &[])]), &schema, None).is_err());
+    }
+
+    #[test]
+    fn test_decode_newer_conditions() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let blob = encode(&[(22, 0, &[(4, 10, "a.com;b.com"), (4, 15, ".local")])]);
+        let config = decode(&blob, &schema, None).unwrap();
+        let conditions: Vec<_> = config
+            .events()
+            .next()
+            .unwrap()
+            .filters
+            .iter()
+            .map(|f| match f {
+                Filter::Field(f) => f.condition,
+                Filter::Rule(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(conditions, [Condition::ContainsAny, Condition::NotEndWith]);
+        assert_eq!(CONDITIONS.len(), Condition::ALL.len());
+        assert!(Condition::ALL.iter().all(|c| CONDITIONS.contains(c)));
+    }
+
+    #[test]
+    fn test_decode_rejects_truncated_and_corrupt_blobs() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        let blob = encode(&[
+            (1, 1, &[(4, 5, "\\cmd.exe"), (4, 2, "powershell")]),
+            (22, 0, &[(4, 0, "example.com")]),
+        ]);
+        for end in 0..blob.len() - 1 {
+            assert!(decode(&blob[..end], &schema, None).is_err(), "{}", end);
+        }
+
+        let patched = |patches: &[(usize, u32)]| {
+            let mut blob = blob.clone();
+            for (at, value) in patches {
+                blob[*at..*at + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            decode(&blob, &schema, None).unwrap_err().to_string()
+        };
+        // Event count, first event offset, filter count and data length.
+        for patch in [
+            (4, u32::MAX),
+            (8, u32::MAX - 2),
+            (24, 1 << 30),
+            (40, u32::MAX),
+        ] {
+            let error = patched(&[patch]);
+            assert!(error.contains("Unreadable rule blob"), "{}", error);
+        }
+        // Events that point back at the first one, as often as the blob
+        // could hold events.
+        let error = patched(&[(4, 9), (20, 12)]);
+        assert!(error.contains("cannot fit"), "{}", error);
+    }
+
+    #[test]
+    fn test_parse_reg_export() {
+        let reg = "Windows Registry Editor Version 5.00\r\n\r\n\
+            [HKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Services\\SysmonDrv\\Parameters]\r\n\
+            \"HashingAlgorithm\"=dword:00000004\r\n\
+            \"Rules\"=hex:5a,00,04,00,\\\r\n  00,00,00,00,0c,00,00,00\r\n";
+        assert_eq!(
+            RegExport::parse(reg.as_bytes()).unwrap(),
+            Some(RegExport {
+                rules: vec![0x5a, 0, 4, 0, 0, 0, 0, 0, 0x0c, 0, 0, 0],
+                hashing: Some(4),
+            })
+        );
+        assert_eq!(RegExport::parse(&[0x5a, 0, 4, 0]).unwrap(), None);
+    }
+}
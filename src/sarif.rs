@@ -0,0 +1,180 @@
+//! SARIF 2.1.0 output for `validate` and `lint`, for code-scanning services
+//! such as GitHub and Azure DevOps.
+
+use crate::validate::Finding;
+use crate::{policy, secrets, xsd};
+use serde::Serialize;
+use std::path::Path;
+use sysmon_cli::validation::Severity;
+use sysmon_cli::{lint, validation};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+#[derive(Debug, Serialize)]
+pub struct Log {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Run {
+    pub tool: Tool,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub information_uri: &'static str,
+    pub rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportingDescriptor {
+    pub id: &'static str,
+    pub short_description: Message,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: &'static str,
+    pub rule_index: Option<usize>,
+    pub level: &'static str,
+    pub message: Message,
+    pub locations: Vec<Location>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtifactLocation {
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: usize,
+}
+
+pub fn to_sarif(findings: &[Finding]) -> Log {
+    let rules: Vec<(&'static str, &'static str)> = validation::RULES
+        .iter()
+        .chain(lint::RULES)
+        .chain(policy::RULES)
+        .chain(secrets::RULES)
+        .chain(xsd::RULES)
+        .copied()
+        .collect();
+
+    let results = findings
+        .iter()
+        .map(|finding| {
+            let issue = &finding.issue;
+            SarifResult {
+                rule_id: issue.rule,
+                rule_index: rules.iter().position(|(id, _)| *id == issue.rule),
+                level: match issue.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                },
+                message: Message {
+                    text: format!("{}: {}", issue.location, issue.message),
+                },
+                locations: vec![Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation {
+                            uri: uri(&finding.file),
+                        },
+                        region: finding.line.map(|start_line| Region { start_line }),
+                    },
+                }],
+            }
+        })
+        .collect();
+
+    Log {
+        schema: SCHEMA,
+        version: "2.1.0",
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    information_uri: "https://github.com/whit3rabbit/sysmon-helper-cli",
+                    rules: rules
+                        .iter()
+                        .map(|&(id, description)| ReportingDescriptor {
+                            id,
+                            short_description: Message {
+                                text: description.to_string(),
+                            },
+                        })
+                        .collect(),
+                },
+            },
+            results,
+        }],
+    }
+}
+
+/// Paths with forward slashes, as code-scanning UIs expect.
+fn uri(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use sysmon_cli::validation::Issue;
+
+    #[test]
+    fn test_sarif_results_reference_rules() {
+        let findings = vec![Finding {
+            file: PathBuf::from("configs\\base.xml"),
+            line: Some(12),
+            issue: Issue::warning("catch-all-exclude", "RuleGroup[1]/DnsQuery", "Empty"),
+        }];
+        let sarif = to_sarif(&findings);
+        let run = &sarif.runs[0];
+        let result = &run.results[0];
+        assert_eq!(result.rule_id, "catch-all-exclude");
+        assert_eq!(result.level, "warning");
+
+        let location = &result.locations[0].physical_location;
+        assert_eq!(location.artifact_location.uri, "configs/base.xml");
+        assert_eq!(location.region.as_ref().map(|r| r.start_line), Some(12));
+
+        let rule = &run.tool.driver.rules[result.rule_index.unwrap()];
+        assert_eq!(rule.id, "catch-all-exclude");
+    }
+}
@@ -0,0 +1,718 @@
+//! Knowledge about Sysmon releases, schema versions and the event types each
+//! schema understands.
+
+use crate::model::Condition;
+use std::fmt;
+use std::str::FromStr;
+
+/// A `major.minor` version as used by both Sysmon binaries (`15.15`) and
+/// configuration schemas (`4.90`).
+///
+/// Minor components are compared as two-digit decimals, so `4.5` and `4.50`
+/// are equal and `4.22` sorts before `4.3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim().trim_start_matches(['v', 'V']);
+        let mut parts = trimmed.split('.');
+        let major = parts
+            .next()
+            .and_then(|p| p.parse::<u32>().ok())
+            .ok_or_else(|| format!("Invalid version: {}", s))?;
+        let minor = match parts.next() {
+            None | Some("") => 0,
+            Some(p) if p.len() <= 2 && p.chars().all(|c| c.is_ascii_digit()) => {
+                let value: u32 = p.parse().map_err(|_| format!("Invalid version: {}", s))?;
+                if p.len() == 1 {
+                    value * 10
+                } else {
+                    value
+                }
+            }
+            Some(_) => return Err(format!("Invalid version: {}", s)),
+        };
+        Ok(Self { major, minor })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+/// A filterable Sysmon event type as it appears inside `<EventFiltering>`.
+#[derive(Debug)]
+pub struct EventType {
+    /// Element name used in configurations, e.g. `ProcessCreate`.
+    pub name: &'static str,
+    /// Event IDs the element filters; `RegistryEvent`, for one, covers 12-14.
+    pub ids: &'static [u32],
+    /// First schema version that accepts the element.
+    pub min_schema: Version,
+    /// Fields rules can test, as of the newest schema.
+    pub fields: &'static [&'static str],
+}
+
+impl EventType {
+    pub fn has_field(&self, field: &str) -> bool {
+        self.fields.contains(&field)
+    }
+}
+
+const fn event(
+    name: &'static str,
+    ids: &'static [u32],
+    min_schema: Version,
+    fields: &'static [&'static str],
+) -> EventType {
+    EventType {
+        name,
+        ids,
+        min_schema,
+        fields,
+    }
+}
+
+pub const EVENT_TYPES: &[EventType] = &[
+    event(
+        "ProcessCreate",
+        &[1],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "FileVersion",
+            "Description",
+            "Product",
+            "Company",
+            "OriginalFileName",
+            "CommandLine",
+            "CurrentDirectory",
+            "User",
+            "LogonGuid",
+            "LogonId",
+            "TerminalSessionId",
+            "IntegrityLevel",
+            "Hashes",
+            "ParentProcessGuid",
+            "ParentProcessId",
+            "ParentImage",
+            "ParentCommandLine",
+            "ParentUser",
+        ],
+    ),
+    event(
+        "FileCreateTime",
+        &[2],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "TargetFilename",
+            "CreationUtcTime",
+            "PreviousCreationUtcTime",
+            "User",
+        ],
+    ),
+    event(
+        "NetworkConnect",
+        &[3],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "User",
+            "Protocol",
+            "Initiated",
+            "SourceIsIpv6",
+            "SourceIp",
+            "SourceHostname",
+            "SourcePort",
+            "SourcePortName",
+            "DestinationIsIpv6",
+            "DestinationIp",
+            "DestinationHostname",
+            "DestinationPort",
+            "DestinationPortName",
+        ],
+    ),
+    event(
+        "ProcessTerminate",
+        &[5],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "User",
+        ],
+    ),
+    event(
+        "DriverLoad",
+        &[6],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ImageLoaded",
+            "Hashes",
+            "Signed",
+            "Signature",
+            "SignatureStatus",
+        ],
+    ),
+    event(
+        "ImageLoad",
+        &[7],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "ImageLoaded",
+            "FileVersion",
+            "Description",
+            "Product",
+            "Company",
+            "OriginalFileName",
+            "Hashes",
+            "Signed",
+            "Signature",
+            "SignatureStatus",
+            "User",
+        ],
+    ),
+    event(
+        "CreateRemoteThread",
+        &[8],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "SourceProcessGuid",
+            "SourceProcessId",
+            "SourceImage",
+            "TargetProcessGuid",
+            "TargetProcessId",
+            "TargetImage",
+            "NewThreadId",
+            "StartAddress",
+            "StartModule",
+            "StartFunction",
+            "SourceUser",
+            "TargetUser",
+        ],
+    ),
+    event(
+        "RawAccessRead",
+        &[9],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "Device",
+            "User",
+        ],
+    ),
+    event(
+        "ProcessAccess",
+        &[10],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "SourceProcessGUID",
+            "SourceProcessId",
+            "SourceThreadId",
+            "SourceImage",
+            "TargetProcessGUID",
+            "TargetProcessId",
+            "TargetImage",
+            "GrantedAccess",
+            "CallTrace",
+            "SourceUser",
+            "TargetUser",
+        ],
+    ),
+    event(
+        "FileCreate",
+        &[11],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "TargetFilename",
+            "CreationUtcTime",
+            "User",
+        ],
+    ),
+    event(
+        "RegistryEvent",
+        &[12, 13, 14],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "EventType",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "TargetObject",
+            "Details",
+            "NewName",
+            "User",
+        ],
+    ),
+    event(
+        "FileCreateStreamHash",
+        &[15],
+        Version::new(3, 0),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "TargetFilename",
+            "CreationUtcTime",
+            "Hash",
+            "Contents",
+            "User",
+        ],
+    ),
+    event(
+        "PipeEvent",
+        &[17, 18],
+        Version::new(3, 30),
+        &[
+            "RuleName",
+            "EventType",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "PipeName",
+            "Image",
+            "User",
+        ],
+    ),
+    event(
+        "WmiEvent",
+        &[19, 20, 21],
+        Version::new(3, 40),
+        &[
+            "RuleName",
+            "EventType",
+            "UtcTime",
+            "Operation",
+            "User",
+            "EventNamespace",
+            "Name",
+            "Query",
+            "Type",
+            "Destination",
+            "Consumer",
+            "Filter",
+        ],
+    ),
+    event(
+        "DnsQuery",
+        &[22],
+        Version::new(4, 21),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "QueryName",
+            "QueryStatus",
+            "QueryResults",
+            "Image",
+            "User",
+        ],
+    ),
+    event(
+        "FileDelete",
+        &[23],
+        Version::new(4, 30),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "User",
+            "Image",
+            "TargetFilename",
+            "Hashes",
+            "IsExecutable",
+            "Archived",
+        ],
+    ),
+    event(
+        "ClipboardChange",
+        &[24],
+        Version::new(4, 40),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "Session",
+            "ClientInfo",
+            "Hashes",
+            "Archived",
+            "User",
+        ],
+    ),
+    event(
+        "ProcessTampering",
+        &[25],
+        Version::new(4, 50),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "Image",
+            "Type",
+            "User",
+        ],
+    ),
+    event(
+        "FileDeleteDetected",
+        &[26],
+        Version::new(4, 60),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "User",
+            "Image",
+            "TargetFilename",
+            "Hashes",
+            "IsExecutable",
+        ],
+    ),
+    event(
+        "FileBlockExecutable",
+        &[27],
+        Version::new(4, 82),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "User",
+            "Image",
+            "TargetFilename",
+            "Hashes",
+        ],
+    ),
+    event(
+        "FileBlockShredding",
+        &[28],
+        Version::new(4, 83),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "User",
+            "Image",
+            "TargetFilename",
+            "Hashes",
+            "IsExecutable",
+        ],
+    ),
+    event(
+        "FileExecutableDetected",
+        &[29],
+        Version::new(4, 90),
+        &[
+            "RuleName",
+            "UtcTime",
+            "ProcessGuid",
+            "ProcessId",
+            "User",
+            "Image",
+            "TargetFilename",
+            "Hashes",
+        ],
+    ),
+];
+
+/// Conditions added after schema 3.0, with the first schema that accepts
+/// them.
+pub const NEWER_CONDITIONS: &[(Condition, Version)] = &[
+    (Condition::IsAny, Version::new(4, 22)),
+    (Condition::ContainsAny, Version::new(4, 22)),
+    (Condition::ContainsAll, Version::new(4, 22)),
+    (Condition::ExcludesAny, Version::new(4, 22)),
+    (Condition::ExcludesAll, Version::new(4, 22)),
+    (Condition::NotBeginWith, Version::new(4, 50)),
+    (Condition::NotEndWith, Version::new(4, 50)),
+];
+
+/// First schema version that accepts `condition`.
+pub fn condition_min_schema(condition: Condition) -> Version {
+    NEWER_CONDITIONS
+        .iter()
+        .find(|(c, _)| *c == condition)
+        .map_or(Version::new(3, 0), |(_, version)| *version)
+}
+
+/// Fields added to an event type after the type itself, with the first
+/// schema that accepts them.
+pub const NEWER_FIELDS: &[(&str, &str, Version)] = &[
+    ("ProcessCreate", "OriginalFileName", Version::new(4, 21)),
+    ("ImageLoad", "OriginalFileName", Version::new(4, 21)),
+];
+
+/// First schema version that accepts `field` on `event`.
+pub fn field_min_schema(event: &EventType, field: &str) -> Version {
+    NEWER_FIELDS
+        .iter()
+        .find(|(name, f, _)| *name == event.name && *f == field)
+        .map_or(event.min_schema, |(_, _, version)| *version)
+}
+
+/// Values `<HashAlgorithms>` accepts, besides `*` for all of them. Sysmon
+/// hashes with SHA1 when the option is absent.
+pub const HASH_ALGORITHMS: &[&str] = &["MD5", "SHA1", "SHA256", "IMPHASH"];
+
+/// Event types that copy their file or text to `ArchiveDirectory`.
+pub const ARCHIVING_EVENT_TYPES: &[&str] = &["FileDelete", "ClipboardChange"];
+
+/// Event types Sysmon logs when a config has no filter for them; the
+/// others are off until a config includes something.
+pub const LOGGED_BY_DEFAULT: &[&str] = &[
+    "ProcessCreate",
+    "FileCreateTime",
+    "ProcessTerminate",
+    "DriverLoad",
+];
+
+/// Top-level settings Sysmon accepts next to `<EventFiltering>`.
+pub const SETTINGS: &[&str] = &[
+    "ArchiveDirectory",
+    "CaptureClipboard",
+    "CheckRevocation",
+    "CopyOnDeleteExtensions",
+    "CopyOnDeletePE",
+    "CopyOnDeleteProcesses",
+    "CopyOnDeleteSIDs",
+    "DnsLookup",
+    "DriverName",
+    "FieldSizes",
+    "HashAlgorithms",
+];
+
+/// Event types Sysmon for Linux reports; everything else is Windows-only.
+pub const LINUX_EVENT_TYPES: &[&str] = &[
+    "ProcessCreate",
+    "NetworkConnect",
+    "ProcessTerminate",
+    "RawAccessRead",
+    "ProcessAccess",
+    "FileCreate",
+    "FileDelete",
+];
+
+/// The operating system a configuration is written for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+    #[default]
+    Windows,
+    Linux,
+}
+
+impl Platform {
+    /// Whether Sysmon on this platform reports `event`.
+    pub fn supports(self, event: &str) -> bool {
+        match self {
+            Platform::Windows => true,
+            Platform::Linux => LINUX_EVENT_TYPES.contains(&event),
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "windows" => Ok(Platform::Windows),
+            "linux" => Ok(Platform::Linux),
+            _ => Err(format!(
+                "Unknown platform {} (expected windows or linux)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Windows => "windows",
+            Platform::Linux => "linux",
+        })
+    }
+}
+
+/// Sysmon releases paired with the newest schema version they accept,
+/// ordered oldest first.
+pub const RELEASES: &[(Version, Version)] = &[
+    (Version::new(6, 0), Version::new(3, 30)),
+    (Version::new(6, 10), Version::new(3, 40)),
+    (Version::new(8, 0), Version::new(4, 0)),
+    (Version::new(9, 0), Version::new(4, 20)),
+    (Version::new(10, 0), Version::new(4, 21)),
+    (Version::new(10, 40), Version::new(4, 22)),
+    (Version::new(11, 0), Version::new(4, 30)),
+    (Version::new(12, 0), Version::new(4, 40)),
+    (Version::new(13, 0), Version::new(4, 50)),
+    (Version::new(13, 10), Version::new(4, 60)),
+    (Version::new(13, 30), Version::new(4, 81)),
+    (Version::new(14, 0), Version::new(4, 82)),
+    (Version::new(14, 10), Version::new(4, 83)),
+    (Version::new(15, 0), Version::new(4, 90)),
+];
+
+pub fn event_type(name: &str) -> Option<&'static EventType> {
+    EVENT_TYPES.iter().find(|event| event.name == name)
+}
+
+/// The event type that filters event ID `id`.
+pub fn event_type_for_id(id: u32) -> Option<&'static EventType> {
+    EVENT_TYPES.iter().find(|event| event.ids.contains(&id))
+}
+
+/// Returns the newest schema version a given Sysmon release accepts, or
+/// `None` if the release predates every entry in [`RELEASES`].
+pub fn max_schema_for(sysmon: Version) -> Option<Version> {
+    RELEASES
+        .iter()
+        .rev()
+        .find(|(release, _)| *release <= sysmon)
+        .map(|(_, schema)| *schema)
+}
+
+/// Returns the oldest Sysmon release that accepts `schema`, or `None` if no
+/// release in [`RELEASES`] does.
+pub fn min_sysmon_for(schema: Version) -> Option<Version> {
+    RELEASES
+        .iter()
+        .find(|(_, accepted)| *accepted >= schema)
+        .map(|(release, _)| *release)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_parsing_normalizes_minor() {
+        assert_eq!("4.5".parse::<Version>().unwrap(), Version::new(4, 50));
+        assert_eq!("4.50".parse::<Version>().unwrap(), Version::new(4, 50));
+        assert_eq!(
+            "v15.15.0.0".parse::<Version>().unwrap(),
+            Version::new(15, 15)
+        );
+        assert!("4.22".parse::<Version>().unwrap() < "4.3".parse::<Version>().unwrap());
+        assert!("four".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn test_max_schema_for_release() {
+        assert_eq!(
+            max_schema_for(Version::new(13, 34)),
+            Some(Version::new(4, 81))
+        );
+        assert_eq!(
+            max_schema_for(Version::new(15, 15)),
+            Some(Version::new(4, 90))
+        );
+        assert_eq!(max_schema_for(Version::new(5, 2)), None);
+    }
+
+    #[test]
+    fn test_newer_event_types_by_id() {
+        let names: Vec<&str> = (22..=29)
+            .map(|id| event_type_for_id(id).unwrap().name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "DnsQuery",
+                "FileDelete",
+                "ClipboardChange",
+                "ProcessTampering",
+                "FileDeleteDetected",
+                "FileBlockExecutable",
+                "FileBlockShredding",
+                "FileExecutableDetected",
+            ]
+        );
+        assert!(event_type("ProcessTampering").unwrap().has_field("Type"));
+        assert!(!event_type("ClipboardChange")
+            .unwrap()
+            .has_field("TargetFilename"));
+    }
+
+    #[test]
+    fn test_condition_min_schema() {
+        assert_eq!(condition_min_schema(Condition::Is), Version::new(3, 0));
+        assert_eq!(
+            condition_min_schema(Condition::ContainsAll),
+            Version::new(4, 22)
+        );
+        assert_eq!(
+            min_sysmon_for(condition_min_schema(Condition::NotEndWith)),
+            Some(Version::new(13, 0))
+        );
+    }
+
+    #[test]
+    fn test_platform_support() {
+        assert!(Platform::Windows.supports("RegistryEvent"));
+        assert!(!Platform::Linux.supports("RegistryEvent"));
+        assert!(Platform::Linux.supports("ProcessCreate"));
+        assert_eq!("Linux".parse::<Platform>(), Ok(Platform::Linux));
+        assert!("macos".parse::<Platform>().is_err());
+    }
+}
@@ -0,0 +1,369 @@
+//! `serve`: a small HTTP service for editors and other internal tools.
+//!
+//! Every endpoint takes the configuration in the request body and answers
+//! with JSON:
+//!
+//! - `POST /convert[?from=xml|json]` converts to the other format
+//! - `POST /validate` checks event types against the declared schema
+//! - `POST /lint` reports likely mistakes in a valid config
+//! - `POST /merge` merges the XML parts of a `multipart/form-data` upload
+//!
+//! `GET /metrics` reports files processed, failures by error kind, bytes
+//! processed and request durations in the Prometheus text format (see
+//! [`metrics`](crate::metrics)), for alerting on a conversion pipeline.
+//!
+//! Requests are handled one at a time; the service is meant for a single
+//! editing UI on the same host, not for public exposure.
+
+use crate::metrics::{Metrics, Observation};
+use crate::{convert, encoding, error_report, merge};
+use clap::Args;
+use log::{error, info};
+use serde::Serialize;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::time::Instant;
+use sysmon_cli::format::{self, Format};
+use sysmon_cli::lint;
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::validation::{self, Issue};
+use sysmon_json::error::ConversionError;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080", env = "SYSMON_HELPER_LISTEN")]
+    pub listen: SocketAddr,
+
+    /// Largest accepted request body in MB
+    #[arg(long, default_value = "10", env = "SYSMON_HELPER_MAX_BODY_MB")]
+    pub max_body_mb: u64,
+}
+
+/// A reply: HTTP status plus a JSON body, and what `/metrics` counts.
+struct Reply {
+    status: u16,
+    body: String,
+    /// Configs processed.
+    files: u64,
+    /// Request body bytes read.
+    bytes: u64,
+    /// The kind of error processing failed with.
+    failure: Option<&'static str>,
+}
+
+impl Reply {
+    fn json(value: &impl Serialize) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => Reply {
+                status: 200,
+                body,
+                files: 1,
+                bytes: 0,
+                failure: None,
+            },
+            Err(e) => Reply::error(500, e.to_string()),
+        }
+    }
+
+    /// 422: the request was read but its config could not be processed.
+    fn failed(e: &ConversionError) -> Self {
+        Reply {
+            failure: Some(error_report::kind(e)),
+            ..Reply::error(422, e.to_string())
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        #[derive(Serialize)]
+        struct ErrorBody {
+            error: String,
+        }
+        let body = serde_json::to_string(&ErrorBody {
+            error: message.into(),
+        })
+        .unwrap_or_else(|_| "{}".to_string());
+        Reply {
+            status,
+            body,
+            files: 0,
+            bytes: 0,
+            failure: (status >= 400).then_some("request"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Converted {
+    /// `xml` or `json`
+    format: &'static str,
+    output: String,
+}
+
+#[derive(Serialize)]
+struct Checked {
+    valid: bool,
+    issues: Vec<Issue>,
+}
+
+#[derive(Serialize)]
+struct Merged {
+    files: usize,
+    output: String,
+}
+
+pub fn run(args: &ServeArgs) -> Result<(), ConversionError> {
+    let server = Server::http(args.listen).map_err(|e| {
+        ConversionError::InvalidFile(format!("Cannot listen on {}: {}", args.listen, e))
+    })?;
+    info!("Listening on http://{}", args.listen);
+
+    let max_body = args.max_body_mb * 1024 * 1024;
+    let mut metrics = Metrics::default();
+    for mut request in server.incoming_requests() {
+        let url = request.url().to_string();
+        let path = url.split_once('?').map_or(url.as_str(), |(path, _)| path);
+        let response = if path == "/metrics" && *request.method() == Method::Get {
+            Response::from_string(metrics.render()).with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+                    .expect("static header is valid"),
+            )
+        } else {
+            let started = Instant::now();
+            let reply = handle(&mut request, max_body);
+            if let Some(endpoint) = ENDPOINTS.iter().find(|e| path == format!("/{}", e)) {
+                metrics.record(&Observation {
+                    endpoint,
+                    files: reply.files,
+                    bytes: reply.bytes,
+                    elapsed: started.elapsed(),
+                    failure: reply.failure,
+                });
+            }
+            info!("{} {} -> {}", request.method(), url, reply.status);
+            Response::from_string(reply.body)
+                .with_status_code(reply.status)
+                .with_header(json_header())
+        };
+        if let Err(e) = request.respond(response) {
+            error!("Failed to send response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// The processing endpoints, as `/metrics` labels them.
+const ENDPOINTS: [&str; 4] = ["convert", "validate", "lint", "merge"];
+
+fn handle(request: &mut Request, max_body: u64) -> Reply {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    if !matches!(path, "/convert" | "/validate" | "/lint" | "/merge") {
+        return Reply::error(404, format!("No such endpoint: {}", path));
+    }
+    if *request.method() != Method::Post {
+        return Reply::error(405, "Use POST");
+    }
+
+    let mut body = Vec::new();
+    let read = request
+        .as_reader()
+        .take(max_body + 1)
+        .read_to_end(&mut body);
+    if let Err(e) = read {
+        return Reply::error(400, format!("Failed to read request body: {}", e));
+    }
+    if body.len() as u64 > max_body {
+        return Reply::error(413, "Request body too large");
+    }
+
+    let bytes = body.len() as u64;
+    let reply = match path {
+        "/merge" => {
+            let content_type = header(request, "Content-Type").unwrap_or_default();
+            merge(&content_type, &body)
+        }
+        _ => match String::from_utf8(body) {
+            Ok(text) => match path {
+                "/convert" => convert(&text, query),
+                "/validate" => check(&text, validation::validate),
+                _ => check(&text, lint::lint),
+            },
+            Err(_) => Reply::error(400, "Request body is not valid UTF-8"),
+        },
+    };
+    Reply { bytes, ..reply }
+}
+
+fn convert(text: &str, query: &str) -> Reply {
+    let from = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("from="))
+        .map(|value| match value {
+            "xml" => Ok(Format::Xml),
+            "json" => Ok(Format::Json),
+            other => Err(format!("Unknown format: {}", other)),
+        })
+        .transpose()
+        .map(|from| from.or_else(|| format::sniff(text.as_bytes())));
+
+    match from {
+        Ok(Some(from)) => match convert::convert_str(text, from) {
+            Ok(output) => Reply::json(&Converted {
+                format: from.target().extension(),
+                output,
+            }),
+            Err(e) => Reply::failed(&e),
+        },
+        Ok(None) => Reply::error(400, "Cannot detect the input format; pass ?from=xml|json"),
+        Err(e) => Reply::error(400, e),
+    }
+}
+
+fn check(text: &str, rules: fn(&SysmonConfig) -> Vec<Issue>) -> Reply {
+    match SysmonConfig::from_xml_str(text) {
+        Ok(config) => {
+            let issues = rules(&config);
+            Reply::json(&Checked {
+                valid: issues.is_empty(),
+                issues,
+            })
+        }
+        Err(e) => Reply::failed(&e),
+    }
+}
+
+fn merge(content_type: &str, body: &[u8]) -> Reply {
+    let Some(boundary) = content_type
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))
+    else {
+        return Reply::error(400, "Expected a multipart/form-data upload");
+    };
+    let parts = match parse_multipart(body, boundary.trim_matches('"')) {
+        Ok(parts) => parts,
+        Err(e) => return Reply::error(400, e),
+    };
+
+    match merge_parts(&parts) {
+        Ok(output) => Reply {
+            files: parts.len() as u64,
+            ..Reply::json(&Merged {
+                files: parts.len(),
+                output,
+            })
+        },
+        Err(e) => Reply::failed(&e),
+    }
+}
+
+fn merge_parts(parts: &[Vec<u8>]) -> Result<String, ConversionError> {
+    let configs = parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let text =
+                encoding::decode(part).map_err(|e| ConversionError::InvalidFile(e.to_string()))?;
+            Ok((
+                format!("part {}", i + 1),
+                SysmonConfig::from_xml_str(&text)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, ConversionError>>()?;
+    let mut resolver = merge::Resolver::Fixed(merge::Resolution::Both);
+    merge::merge(configs, &mut resolver)?.to_xml_string()
+}
+
+/// Splits a `multipart/form-data` body into the contents of its parts.
+fn parse_multipart(body: &[u8], boundary: &str) -> Result<Vec<Vec<u8>>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut sections = split(body, &delimiter).into_iter();
+    sections.next(); // preamble
+
+    let mut parts = Vec::new();
+    for section in sections {
+        if section.starts_with(b"--") {
+            return if parts.is_empty() {
+                Err("Upload contains no files".to_string())
+            } else {
+                Ok(parts)
+            };
+        }
+        let section = section.strip_prefix(b"\r\n").unwrap_or(section);
+        let Some(header_end) = find(section, b"\r\n\r\n") else {
+            return Err("Malformed multipart part".to_string());
+        };
+        let content = &section[header_end + 4..];
+        let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+        parts.push(content.to_vec());
+    }
+    Err("Multipart body is missing its closing boundary".to_string())
+}
+
+fn split<'a>(haystack: &'a [u8], needle: &[u8]) -> Vec<&'a [u8]> {
+    let mut pieces = Vec::new();
+    let mut rest = haystack;
+    while let Some(at) = find(rest, needle) {
+        pieces.push(&rest[..at]);
+        rest = &rest[at + needle.len()..];
+    }
+    pieces.push(rest);
+    pieces
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn header(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str().to_string())
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multipart() {
+        let body = b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"; filename=\"a.xml\"\r\n\r\n<Sysmon/>\r\n--XyZ\r\nContent-Disposition: form-data; name=\"b\"\r\nContent-Type: text/xml\r\n\r\n<Sysmon schemaversion=\"4.90\"/>\r\n--XyZ--\r\n";
+        let parts = parse_multipart(body, "XyZ").unwrap();
+        assert_eq!(
+            parts,
+            vec![
+                b"<Sysmon/>".to_vec(),
+                b"<Sysmon schemaversion=\"4.90\"/>".to_vec()
+            ]
+        );
+
+        assert!(parse_multipart(b"--XyZ--\r\n", "XyZ").is_err());
+        assert!(parse_multipart(b"--XyZ\r\nno headers", "XyZ").is_err());
+    }
+
+    #[test]
+    fn test_convert_rejects_unknown_format() {
+        let reply = convert("<Sysmon/>", "from=yaml");
+        assert_eq!(reply.status, 400);
+        assert_eq!(reply.failure, Some("request"));
+    }
+
+    #[test]
+    fn test_failed_reply_carries_error_kind() {
+        let reply = check("<Sysmon", validation::validate);
+        assert_eq!(reply.status, 422);
+        assert_eq!(reply.files, 0);
+        assert!(reply.failure.is_some_and(|kind| kind != "request"));
+    }
+}
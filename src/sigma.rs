@@ -0,0 +1,389 @@
+//! Sigma rules (<https://sigmahq.io>), as far as Sysmon is concerned.
+//!
+//! A rule names the log it applies to in its `logsource` and describes
+//! what it looks for in `detection`: named selections, each a map of
+//! `Field|modifier: value(s)` tests that must all hold, or a list of such
+//! maps of which one must, combined by a `condition`. For Sysmon the log
+//! source is a `category` such as `process_creation`, which
+//! [`SigmaRule::event_type`] maps to the event type that logs it, and the
+//! field names are Sysmon's own.
+//!
+//! Rules are YAML, read with [`crate::yaml`], which covers the constructs
+//! rules are written with.
+
+use crate::walk;
+use crate::yaml::{self, Yaml};
+use std::path::{Path, PathBuf};
+use sysmon_cli::schema::{self, Platform};
+use sysmon_json::error::ConversionError;
+
+/// Sigma log source categories and the Sysmon event type that logs each.
+pub const CATEGORIES: &[(&str, &str)] = &[
+    ("process_creation", "ProcessCreate"),
+    ("file_change", "FileCreateTime"),
+    ("network_connection", "NetworkConnect"),
+    ("process_termination", "ProcessTerminate"),
+    ("driver_load", "DriverLoad"),
+    ("image_load", "ImageLoad"),
+    ("create_remote_thread", "CreateRemoteThread"),
+    ("raw_access_thread", "RawAccessRead"),
+    ("process_access", "ProcessAccess"),
+    ("file_event", "FileCreate"),
+    ("registry_add", "RegistryEvent"),
+    ("registry_delete", "RegistryEvent"),
+    ("registry_set", "RegistryEvent"),
+    ("registry_rename", "RegistryEvent"),
+    ("registry_event", "RegistryEvent"),
+    ("create_stream_hash", "FileCreateStreamHash"),
+    ("pipe_created", "PipeEvent"),
+    ("wmi_event", "WmiEvent"),
+    ("dns_query", "DnsQuery"),
+    ("file_delete", "FileDelete"),
+    ("clipboard_capture", "ClipboardChange"),
+    ("process_tampering", "ProcessTampering"),
+    ("file_delete_detected", "FileDeleteDetected"),
+    ("file_block_executable", "FileBlockExecutable"),
+    ("file_block_shredding", "FileBlockShredding"),
+    ("file_executable_detected", "FileExecutableDetected"),
+];
+
+/// One `Field|modifier: value(s)` test of a selection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMatch {
+    /// `None` for a keyword search, which matches anywhere in the event.
+    pub field: Option<String>,
+    /// Modifiers in order, such as `contains` and `all`.
+    pub modifiers: Vec<String>,
+    /// `None` for `null`, which matches an empty or missing field.
+    pub values: Vec<Option<String>>,
+}
+
+/// How a Sigma value constrains a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Test {
+    Equals,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+impl FieldMatch {
+    pub fn has_modifier(&self, modifier: &str) -> bool {
+        self.modifiers
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(modifier))
+    }
+
+    /// The test each value makes, with a leading or trailing `*` wildcard
+    /// read as `endswith` or `startswith`. `None` when a test cannot be
+    /// expressed that way: a `null` value, a modifier such as `re` or
+    /// `base64`, or a wildcard inside the value.
+    pub fn tests(&self) -> Option<Vec<(Test, String)>> {
+        let mut kind = Test::Equals;
+        for modifier in &self.modifiers {
+            kind = match modifier.to_ascii_lowercase().as_str() {
+                "contains" => Test::Contains,
+                "startswith" => Test::StartsWith,
+                "endswith" => Test::EndsWith,
+                "all" => kind,
+                _ => return None,
+            };
+        }
+        let tests: Option<Vec<(Test, String)>> = self
+            .values
+            .iter()
+            .map(|value| {
+                let value = value.as_deref()?;
+                let (leading, trailing) = (value.starts_with('*'), value.ends_with('*'));
+                let kind = match (kind, leading, trailing) {
+                    (Test::Equals, true, true) => Test::Contains,
+                    (Test::Equals, true, false) => Test::EndsWith,
+                    (Test::Equals, false, true) => Test::StartsWith,
+                    (kind, _, _) => kind,
+                };
+                let value = match kind == Test::Equals {
+                    true => value,
+                    false => value.trim_matches('*'),
+                };
+                (!value.is_empty() && !value.contains(['*', '?']))
+                    .then(|| (kind, value.to_string()))
+            })
+            .collect();
+        tests.filter(|t| !t.is_empty())
+    }
+}
+
+/// A named selection: any one of its alternatives matching is enough,
+/// and an alternative matches when all of its tests do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub name: String,
+    pub alternatives: Vec<Vec<FieldMatch>>,
+}
+
+impl Selection {
+    /// Whether the selection is, by Sigma convention, one the condition
+    /// negates (`selection and not filter_main`).
+    pub fn is_filter(&self) -> bool {
+        self.name.to_ascii_lowercase().starts_with("filter")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigmaRule {
+    pub title: String,
+    pub id: Option<String>,
+    pub level: Option<String>,
+    pub tags: Vec<String>,
+    pub product: Option<String>,
+    pub category: Option<String>,
+    pub service: Option<String>,
+    pub selections: Vec<Selection>,
+    /// The `condition` expressions, usually one.
+    pub conditions: Vec<String>,
+}
+
+impl SigmaRule {
+    /// The Sysmon event type that logs what the rule looks at: by its
+    /// category, or by the `EventID` a `service: sysmon` rule selects.
+    pub fn event_type(&self) -> Option<&'static str> {
+        let product = self.product.as_deref().unwrap_or("windows");
+        if !matches!(product, "windows" | "linux") {
+            return None;
+        }
+        if let Some(category) = &self.category {
+            return CATEGORIES
+                .iter()
+                .find(|(c, _)| c == category)
+                .map(|(_, event)| *event);
+        }
+        if self.service.as_deref() != Some("sysmon") {
+            return None;
+        }
+        self.selections
+            .iter()
+            .flat_map(|s| s.alternatives.iter().flatten())
+            .filter(|m| m.field.as_deref() == Some("EventID"))
+            .flat_map(|m| m.values.iter().flatten())
+            .find_map(|id| schema::event_type_for_id(id.parse().ok()?))
+            .map(|event| event.name)
+    }
+
+    /// The platform whose Sysmon the rule reads: Linux for `product: linux`,
+    /// Windows otherwise.
+    pub fn platform(&self) -> Platform {
+        match self.product.as_deref() {
+            Some("linux") => Platform::Linux,
+            _ => Platform::Windows,
+        }
+    }
+
+    /// The selections the condition looks for, as opposed to filters.
+    pub fn positive_selections(&self) -> impl Iterator<Item = &Selection> {
+        self.selections.iter().filter(|s| !s.is_filter())
+    }
+}
+
+/// Reads every rule in the Sigma file at `path`. A file can hold several
+/// `---`-separated documents; those without a `detection` are skipped.
+pub fn load(path: &Path) -> Result<Vec<SigmaRule>, ConversionError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ConversionError::io_error(path, e))?;
+    parse_rules(&text)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))
+}
+
+/// `path` if it is a file, or the `.yml` and `.yaml` files under it if it
+/// is a directory.
+pub fn files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    walk::files(path, usize::MAX, false)
+        .into_iter()
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("yml") || e.eq_ignore_ascii_case("yaml"))
+        })
+        .collect()
+}
+
+pub fn parse_rules(text: &str) -> Result<Vec<SigmaRule>, String> {
+    let mut rules = Vec::new();
+    let mut document = Vec::new();
+    let mut first_line = 1;
+    for (index, line) in text.lines().chain(["---"]).enumerate() {
+        if line.trim_end() != "---" {
+            document.push(line);
+            continue;
+        }
+        let yaml = yaml::parse_document(&document, first_line)?;
+        if yaml.get("detection").is_some() {
+            rules.push(rule(&yaml)?);
+        }
+        document.clear();
+        first_line = index + 2;
+    }
+    Ok(rules)
+}
+
+fn rule(yaml: &Yaml) -> Result<SigmaRule, String> {
+    let text = |key: &str| yaml.get(key).and_then(Yaml::as_str).map(str::to_string);
+    let logsource = |key: &str| {
+        yaml.get("logsource")
+            .and_then(|l| l.get(key))
+            .and_then(Yaml::as_str)
+            .map(str::to_ascii_lowercase)
+    };
+    let Some(Yaml::Map(detection)) = yaml.get("detection") else {
+        return Err("detection is not a mapping".to_string());
+    };
+    let mut selections = Vec::new();
+    let mut conditions = Vec::new();
+    for (name, value) in detection {
+        match (name.as_str(), value) {
+            ("condition", Yaml::Scalar(condition)) => conditions.push(condition.clone()),
+            ("condition", Yaml::List(list)) => {
+                conditions.extend(list.iter().filter_map(Yaml::as_str).map(str::to_string))
+            }
+            ("timeframe", _) => {}
+            _ => selections.push(Selection {
+                name: name.clone(),
+                alternatives: alternatives(name, value)?,
+            }),
+        }
+    }
+    Ok(SigmaRule {
+        title: text("title").unwrap_or_default(),
+        id: text("id"),
+        level: text("level"),
+        tags: match yaml.get("tags") {
+            Some(Yaml::List(tags)) => tags
+                .iter()
+                .filter_map(Yaml::as_str)
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        },
+        product: logsource("product"),
+        category: logsource("category"),
+        service: logsource("service"),
+        selections,
+        conditions,
+    })
+}
+
+fn alternatives(name: &str, value: &Yaml) -> Result<Vec<Vec<FieldMatch>>, String> {
+    match value {
+        Yaml::Map(fields) => Ok(vec![fields
+            .iter()
+            .map(|(key, values)| field_match(key, values))
+            .collect()]),
+        Yaml::List(items) if items.iter().all(|i| matches!(i, Yaml::Map(_))) => items
+            .iter()
+            .map(|i| Ok(alternatives(name, i)?.remove(0)))
+            .collect(),
+        Yaml::List(_) | Yaml::Scalar(_) => Ok(vec![vec![FieldMatch {
+            field: None,
+            modifiers: Vec::new(),
+            values: values(value),
+        }]]),
+        Yaml::Null => Err(format!("selection {} is empty", name)),
+    }
+}
+
+fn field_match(key: &str, value: &Yaml) -> FieldMatch {
+    let mut parts = key.split('|');
+    let field = parts.next().unwrap_or_default().trim().to_string();
+    FieldMatch {
+        field: Some(field),
+        modifiers: parts.map(|m| m.trim().to_string()).collect(),
+        values: values(value),
+    }
+}
+
+fn values(value: &Yaml) -> Vec<Option<String>> {
+    match value {
+        Yaml::Null => vec![None],
+        Yaml::Scalar(s) => vec![Some(s.clone())],
+        Yaml::List(items) => items.iter().flat_map(values).collect(),
+        Yaml::Map(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULE: &str = r#"title: Encoded PowerShell # trailing comment
+id: 0b1c2d3e
+description: |
+    Spots encoded commands
+    in the command line
+tags:
+    - attack.execution
+    - attack.t1059.001
+logsource:
+    category: process_creation
+    product: windows
+detection:
+    selection_img:
+        - Image|endswith: '\powershell.exe'
+        - OriginalFileName: 'PowerShell.EXE'
+    selection_cli:
+        CommandLine|contains:
+            - ' -enc '
+            - ' -ec '
+    filter_main:
+        ParentImage: [ 'C:\Program Files\Agent\agent.exe', "C:\\Tools\\x.exe" ]
+    condition: all of selection_* and not filter_main
+level: high
+---
+title: Not a detection
+"#;
+
+    #[test]
+    fn test_parse_rules_reads_selections_and_logsource() {
+        let rules = parse_rules(RULE).unwrap();
+        assert_eq!(rules.len(), 1);
+        let rule = &rules[0];
+        assert_eq!(rule.title, "Encoded PowerShell");
+        assert_eq!(rule.tags, ["attack.execution", "attack.t1059.001"]);
+        assert_eq!(rule.event_type(), Some("ProcessCreate"));
+        assert_eq!(rule.conditions, ["all of selection_* and not filter_main"]);
+
+        let names: Vec<&str> = rule
+            .positive_selections()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, ["selection_img", "selection_cli"]);
+        let image = &rule.selections[0].alternatives;
+        assert_eq!(image.len(), 2);
+        assert_eq!(
+            image[0][0],
+            FieldMatch {
+                field: Some("Image".into()),
+                modifiers: vec!["endswith".into()],
+                values: vec![Some(r"\powershell.exe".into())],
+            }
+        );
+        assert_eq!(rule.selections[1].alternatives[0][0].values.len(), 2);
+        assert_eq!(
+            rule.selections[2].alternatives[0][0].values,
+            [
+                Some(r"C:\Program Files\Agent\agent.exe".into()),
+                Some(r"C:\Tools\x.exe".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_yaml_rejects_unsupported_syntax() {
+        assert!(parse_rules("detection:\n  sel: &a x\n").is_err());
+        assert!(parse_rules("a:\n  - x\n  b: 2\n").is_err());
+        let sysmon = "logsource:\n  product: windows\n  service: sysmon\ndetection:\n  sel:\n    EventID: 8\n  condition: sel\n";
+        assert_eq!(
+            parse_rules(sysmon).unwrap()[0].event_type(),
+            Some("CreateRemoteThread")
+        );
+    }
+}
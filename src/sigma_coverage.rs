@@ -0,0 +1,523 @@
+//! `sigma-coverage`: which Sigma detections a config leaves blind.
+//!
+//! Each Sigma rule for a Sysmon log source is checked against what the
+//! config logs of its event type (see [`crate::posture`]):
+//!
+//! - **logged**: the event type logs everything, or everything but
+//!   exclusions that do not touch the rule, or an include rule is sure to
+//!   log what the rule looks for;
+//! - **partial**: the event type is an include-list and no include rule is
+//!   sure to log it, or exclusions hide some of the rule's selections;
+//! - **blind**: the event type logs nothing, exclude rules drop every event
+//!   the rule's selections match, or each selection tests a field Sysmon
+//!   does not record for the event type;
+//! - **not configured**: the config has no filter for the event type.
+//!
+//! A rule "is sure to" match when every event a Sigma selection matches
+//! also satisfies it: `Image end with \powershell.exe` covers
+//! `Image|endswith: '\powershell.exe'` and `Image: C:\...\powershell.exe`.
+//! Selections named `filter...` are taken to be negated, as Sigma's
+//! conventions have it; `re`, `base64` and other modifiers, and wildcards
+//! other than a leading or trailing `*`, are not analyzed, so such tests
+//! never count as covered.
+
+use crate::posture::{self, Posture};
+use crate::sigma::{self, FieldMatch, SigmaRule, Test};
+use crate::{io_guard, validate};
+use clap::{Args, ValueEnum};
+use log::warn;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::{Condition, FieldCondition, Filter, GroupRelation, OnMatch, SysmonConfig};
+use sysmon_cli::schema;
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SigmaCoverageFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct SigmaCoverageArgs {
+    /// Configuration to check (XML or JSON)
+    pub config: PathBuf,
+
+    /// Sigma rule file, or a directory searched recursively for .yml and .yaml rules
+    pub rules: PathBuf,
+
+    /// Also list the rules the config logs (the text report lists only the others)
+    #[arg(long)]
+    pub all: bool,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: SigmaCoverageFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Blind,
+    Partial,
+    NotConfigured,
+    Logged,
+}
+
+impl Visibility {
+    fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Blind => "blind",
+            Visibility::Partial => "partial",
+            Visibility::NotConfigured => "not configured",
+            Visibility::Logged => "logged",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleCoverage {
+    pub file: String,
+    pub title: String,
+    pub event: String,
+    pub visibility: Visibility,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SigmaCoverage {
+    /// Rules for Sysmon event types, worst first.
+    pub rules: Vec<RuleCoverage>,
+    /// Rules for other log sources.
+    pub not_applicable: usize,
+    /// Files that could not be read as Sigma rules.
+    pub unreadable: usize,
+}
+
+pub fn run(args: &SigmaCoverageArgs) -> Result<(), ConversionError> {
+    let config = SysmonConfig::from_xml_str(&validate::load(&args.config)?.0)?;
+    let files = sigma::files(&args.rules);
+
+    let mut report = SigmaCoverage::default();
+    for file in &files {
+        let rules = match sigma::load(file) {
+            Ok(rules) => rules,
+            Err(e) => {
+                warn!("Skipping {}", e);
+                report.unreadable += 1;
+                continue;
+            }
+        };
+        let name = file.strip_prefix(&args.rules).unwrap_or(file);
+        let name = match name.as_os_str().is_empty() {
+            true => file.display().to_string(),
+            false => name.display().to_string(),
+        };
+        for rule in &rules {
+            match check(&config, rule) {
+                Some((event, visibility, reason)) => report.rules.push(RuleCoverage {
+                    file: name.clone(),
+                    title: rule.title.clone(),
+                    event: event.to_string(),
+                    visibility,
+                    reason,
+                }),
+                None => report.not_applicable += 1,
+            }
+        }
+    }
+    report.rules.sort_by_key(|r| r.visibility);
+
+    let text = match args.format {
+        SigmaCoverageFormat::Text => render(&report, args.all),
+        SigmaCoverageFormat::Json => serde_json::to_string_pretty(&report)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn render(report: &SigmaCoverage, all: bool) -> String {
+    let mut out = String::new();
+    for rule in &report.rules {
+        if rule.visibility == Visibility::Logged && !all {
+            continue;
+        }
+        let _ = writeln!(
+            out,
+            "{}: {}: {:?} ({}): {}",
+            rule.file,
+            rule.visibility.as_str(),
+            rule.title,
+            rule.event,
+            rule.reason
+        );
+    }
+    let count = |v: Visibility| report.rules.iter().filter(|r| r.visibility == v).count();
+    let _ = writeln!(
+        out,
+        "{} Sigma rule(s) for Sysmon events: {} logged, {} partial, {} blind, {} not configured; \
+         {} for other log sources",
+        report.rules.len(),
+        count(Visibility::Logged),
+        count(Visibility::Partial),
+        count(Visibility::Blind),
+        count(Visibility::NotConfigured),
+        report.not_applicable
+    );
+    if report.unreadable > 0 {
+        let _ = writeln!(out, "{} file(s) could not be read", report.unreadable);
+    }
+    out
+}
+
+/// The event type `rule` needs, how well `config` logs it, and why; `None`
+/// for a rule that is not about Sysmon events.
+pub fn check(
+    config: &SysmonConfig,
+    rule: &SigmaRule,
+) -> Option<(&'static str, Visibility, String)> {
+    let event = rule.event_type()?;
+    let event_type = schema::event_type(event)?;
+    let platform = rule.platform();
+    if !platform.supports(event) {
+        return Some((
+            event,
+            Visibility::Blind,
+            format!("Sysmon for {} does not report {} events", platform, event),
+        ));
+    }
+    let posture = posture::posture(config, platform)
+        .into_iter()
+        .find(|p| p.event == event)
+        .map_or(Posture::NotConfigured, |p| p.posture);
+
+    let selections: Vec<&sigma::Selection> = rule.positive_selections().collect();
+    let missing: Vec<&str> = selections
+        .iter()
+        .flat_map(|s| s.alternatives.iter().flatten())
+        .filter_map(|m| m.field.as_deref())
+        .filter(|f| !event_type.has_field(f) && *f != "EventID")
+        .collect();
+    let impossible = |alternative: &Vec<FieldMatch>| {
+        alternative
+            .iter()
+            .any(|m| m.field.as_deref().is_some_and(|f| missing.contains(&f)))
+    };
+    if !selections.is_empty()
+        && selections
+            .iter()
+            .all(|s| s.alternatives.iter().all(impossible))
+    {
+        let mut missing = missing;
+        missing.sort_unstable();
+        missing.dedup();
+        return Some((
+            event,
+            Visibility::Blind,
+            format!("{} events have no {} field", event, missing.join(", ")),
+        ));
+    }
+
+    let result = match posture {
+        Posture::Nothing => (
+            Visibility::Blind,
+            format!("{} logs nothing: its include filter is empty", event),
+        ),
+        Posture::NotConfigured => (
+            Visibility::NotConfigured,
+            format!("no {} filter; Sysmon's default applies", event),
+        ),
+        Posture::Everything => (Visibility::Logged, format!("{} logs everything", event)),
+        Posture::ExcludeList | Posture::IncludeList | Posture::IncludeListWithExclusions => {
+            let excludes = patterns(config, event, OnMatch::Exclude);
+            let hidden: Vec<(&str, &Pattern)> = selections
+                .iter()
+                .filter_map(|s| {
+                    let pattern = s
+                        .alternatives
+                        .iter()
+                        .map(|a| excludes.iter().find(|p| p.contains(a)))
+                        .collect::<Option<Vec<_>>>()?;
+                    Some((s.name.as_str(), pattern[0]))
+                })
+                .collect();
+            let includes = patterns(config, event, OnMatch::Include);
+            let included = selections
+                .iter()
+                .flat_map(|s| &s.alternatives)
+                .find_map(|a| includes.iter().find(|p| p.contains(a)));
+            match (hidden.len(), posture, included) {
+                (n, _, _) if n > 0 && n == selections.len() => (
+                    Visibility::Blind,
+                    format!("excluded by {}", hidden[0].1.describe()),
+                ),
+                (n, _, _) if n > 0 => (
+                    Visibility::Partial,
+                    format!("{} excluded by {}", hidden[0].0, hidden[0].1.describe()),
+                ),
+                (_, Posture::ExcludeList, _) => (
+                    Visibility::Logged,
+                    format!("no {} exclusion covers it", event),
+                ),
+                (_, _, Some(pattern)) => (
+                    Visibility::Logged,
+                    format!("included by {}", pattern.describe()),
+                ),
+                (_, _, None) => (
+                    Visibility::Partial,
+                    format!(
+                        "{} is an include-list and no include rule is sure to match it",
+                        event
+                    ),
+                ),
+            }
+        }
+    };
+    Some((event, result.0, result.1))
+}
+
+/// Conditions that must all hold for an event to match, and the rule group
+/// they are in.
+struct Pattern<'a> {
+    group: usize,
+    conditions: Vec<&'a FieldCondition>,
+}
+
+impl Pattern<'_> {
+    /// Whether every event the Sigma `alternative` matches also matches
+    /// this pattern.
+    fn contains(&self, alternative: &[FieldMatch]) -> bool {
+        self.conditions.iter().all(|condition| {
+            alternative.iter().any(|m| {
+                m.field
+                    .as_deref()
+                    .is_some_and(|f| f.eq_ignore_ascii_case(&condition.field))
+                    && covers(condition, m)
+            })
+        })
+    }
+
+    fn describe(&self) -> String {
+        let conditions: Vec<String> = self
+            .conditions
+            .iter()
+            .map(|c| format!("{} {} {:?}", c.field, c.condition.as_str(), c.value))
+            .collect();
+        format!("{} in RuleGroup[{}]", conditions.join(" and "), self.group)
+    }
+}
+
+/// The include or exclude patterns of `event`, counted the way `posture`
+/// counts them.
+fn patterns<'a>(config: &'a SysmonConfig, event: &str, onmatch: OnMatch) -> Vec<Pattern<'a>> {
+    let mut patterns = Vec::new();
+    for (index, group) in config.rule_groups.iter().enumerate() {
+        let group_number = index + 1;
+        for filter in &group.events {
+            if filter.event != event || filter.onmatch != onmatch {
+                continue;
+            }
+            if group.group_relation == Some(GroupRelation::And) {
+                let conditions = filter
+                    .filters
+                    .iter()
+                    .flat_map(|f| match f {
+                        Filter::Field(c) => vec![c],
+                        Filter::Rule(r) => r.fields.iter().collect(),
+                    })
+                    .collect();
+                patterns.push(Pattern {
+                    group: group_number,
+                    conditions,
+                });
+                continue;
+            }
+            for f in &filter.filters {
+                match f {
+                    Filter::Field(c) => patterns.push(Pattern {
+                        group: group_number,
+                        conditions: vec![c],
+                    }),
+                    Filter::Rule(r) if r.group_relation == GroupRelation::And => {
+                        patterns.push(Pattern {
+                            group: group_number,
+                            conditions: r.fields.iter().collect(),
+                        })
+                    }
+                    Filter::Rule(r) => patterns.extend(r.fields.iter().map(|c| Pattern {
+                        group: group_number,
+                        conditions: vec![c],
+                    })),
+                }
+            }
+        }
+    }
+    patterns.retain(|p| !p.conditions.is_empty());
+    patterns
+}
+
+/// Whether every value the Sigma test `m` accepts satisfies `condition`.
+fn covers(condition: &FieldCondition, m: &FieldMatch) -> bool {
+    let Some(tests) = m.tests() else {
+        return false;
+    };
+    let covered =
+        |(kind, value): &(Test, String)| covers_value(condition, *kind, &value.to_lowercase());
+    if m.has_modifier("all") {
+        tests.iter().any(covered)
+    } else {
+        tests.iter().all(covered)
+    }
+}
+
+fn covers_value(condition: &FieldCondition, kind: Test, value: &str) -> bool {
+    let wanted = condition.value.to_lowercase();
+    let listed: Vec<&str> = wanted.split(';').map(str::trim).collect();
+    let starts = matches!(kind, Test::Equals | Test::StartsWith);
+    let ends = matches!(kind, Test::Equals | Test::EndsWith);
+    match condition.condition {
+        Condition::Is => kind == Test::Equals && value == wanted,
+        Condition::IsAny => kind == Test::Equals && listed.contains(&value),
+        Condition::Contains => value.contains(wanted.as_str()),
+        Condition::ContainsAny => listed.iter().any(|w| value.contains(w)),
+        Condition::ContainsAll => listed.iter().all(|w| value.contains(w)),
+        Condition::BeginWith => starts && value.starts_with(wanted.as_str()),
+        Condition::EndWith => ends && value.ends_with(wanted.as_str()),
+        // A bare image name matches the file name of a path, so a suffix
+        // only covers it when the suffix starts at a path separator.
+        Condition::Image => {
+            let file_name = !wanted.contains(['\\', '/'])
+                && ['\\', '/']
+                    .iter()
+                    .any(|sep| value.ends_with(&format!("{}{}", sep, wanted)));
+            match kind {
+                Test::Equals => value == wanted || file_name,
+                Test::EndsWith => file_name,
+                _ => false,
+            }
+        }
+        Condition::LessThan | Condition::MoreThan => {
+            let (Ok(value), Ok(wanted)) = (value.parse::<u64>(), wanted.parse::<u64>()) else {
+                return false;
+            };
+            kind == Test::Equals
+                && match condition.condition {
+                    Condition::LessThan => value < wanted,
+                    _ => value > wanted,
+                }
+        }
+        Condition::IsNot
+        | Condition::Excludes
+        | Condition::ExcludesAny
+        | Condition::ExcludesAll
+        | Condition::NotBeginWith
+        | Condition::NotEndWith => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(category: &str, detection: &str) -> SigmaRule {
+        let text = format!(
+            "title: Test\nlogsource:\n  category: {}\n  product: windows\ndetection:\n{}  condition: selection\n",
+            category, detection
+        );
+        sigma::parse_rules(&text).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_check_finds_blind_partial_and_logged_rules() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+<RuleGroup groupRelation="or">
+<ProcessCreate onmatch="exclude"><Image condition="end with">\powershell.exe</Image></ProcessCreate>
+<NetworkConnect onmatch="include"><Image condition="image">rundll32.exe</Image></NetworkConnect>
+<ImageLoad onmatch="include"/>
+</RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        let visibility = |rule: &SigmaRule| {
+            let (_, visibility, reason) = check(&config, rule).unwrap();
+            (visibility, reason)
+        };
+
+        let powershell = rule(
+            "process_creation",
+            "  selection:\n    Image|endswith: '\\powershell.exe'\n    CommandLine|contains: ' -enc '\n",
+        );
+        assert_eq!(
+            visibility(&powershell),
+            (
+                Visibility::Blind,
+                r#"excluded by Image end with "\\powershell.exe" in RuleGroup[1]"#.to_string()
+            )
+        );
+        let cmd = rule(
+            "process_creation",
+            "  selection:\n    Image|endswith: '\\cmd.exe'\n",
+        );
+        assert_eq!(visibility(&cmd).0, Visibility::Logged);
+
+        let rundll = rule(
+            "network_connection",
+            "  selection:\n    Image: 'C:\\Windows\\System32\\rundll32.exe'\n",
+        );
+        assert_eq!(visibility(&rundll).0, Visibility::Logged);
+        let other = rule(
+            "network_connection",
+            "  selection:\n    Image|endswith: '\\mshta.exe'\n",
+        );
+        assert_eq!(visibility(&other).0, Visibility::Partial);
+        // `endswith: rundll32.exe` also matches xrundll32.exe, which the
+        // `image` condition does not.
+        let suffix = rule(
+            "network_connection",
+            "  selection:\n    Image|endswith: 'rundll32.exe'\n",
+        );
+        assert_eq!(visibility(&suffix).0, Visibility::Partial);
+
+        let dll = rule(
+            "image_load",
+            "  selection:\n    ImageLoaded|endswith: '\\x.dll'\n",
+        );
+        assert_eq!(visibility(&dll).0, Visibility::Blind);
+        let fields = rule("process_creation", "  selection:\n    ServiceName: x\n");
+        assert_eq!(
+            visibility(&fields),
+            (
+                Visibility::Blind,
+                "ProcessCreate events have no ServiceName field".to_string()
+            )
+        );
+        let dns = rule(
+            "dns_query",
+            "  selection:\n    QueryName|endswith: '.onion'\n",
+        );
+        assert_eq!(visibility(&dns).0, Visibility::NotConfigured);
+        assert!(check(&config, &rule("antivirus", "  selection:\n    x: y\n")).is_none());
+
+        let mut linux = dll;
+        linux.product = Some("linux".to_string());
+        assert_eq!(
+            visibility(&linux),
+            (
+                Visibility::Blind,
+                "Sysmon for linux does not report ImageLoad events".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! `--modified-since`: limit a batch to recently changed inputs.
+//!
+//! The cutoff is either a duration back from now (`90m`, `36h`, `7d`, `2w`)
+//! or a UTC date or time (`2024-05-01`, `2024-05-01T08:30:00Z`). Run from a
+//! nightly job with `--modified-since 1d`, a batch converts only what
+//! changed since the last run without keeping any state between runs, as
+//! `--incremental` does.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct ModifiedSince {
+    cutoff: SystemTime,
+    text: String,
+}
+
+impl std::str::FromStr for ModifiedSince {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let cutoff = match (duration(text), timestamp(text)) {
+            (Some(ago), _) => SystemTime::now().checked_sub(ago).unwrap_or(UNIX_EPOCH),
+            (None, Some(seconds)) => UNIX_EPOCH + Duration::from_secs(seconds),
+            (None, None) => {
+                return Err(format!(
+                    "Invalid --modified-since {:?}: expected a duration such as 36h or 7d, \
+                     or a UTC time such as 2024-05-01 or 2024-05-01T08:30:00Z",
+                    text
+                ))
+            }
+        };
+        Ok(ModifiedSince {
+            cutoff,
+            text: text.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for ModifiedSince {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl ModifiedSince {
+    /// Whether `path` was modified at or after the cutoff. A file whose
+    /// modification time cannot be read is kept, so that converting it
+    /// reports the problem.
+    pub fn includes(&self, path: &Path) -> bool {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map_or(true, |modified| modified >= self.cutoff)
+    }
+}
+
+/// `36h`: a whole number followed by s, m, h, d or w.
+fn duration(text: &str) -> Option<Duration> {
+    let unit = text.chars().last()?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3_600,
+        'd' => 86_400,
+        'w' => 7 * 86_400,
+        _ => return None,
+    };
+    let count: u64 = text[..text.len() - 1].parse().ok()?;
+    count.checked_mul(seconds).map(Duration::from_secs)
+}
+
+/// Seconds since the Unix epoch of `YYYY-MM-DD`, optionally followed by
+/// `THH:MM[:SS]` (or a space instead of `T`) and a trailing `Z`.
+fn timestamp(text: &str) -> Option<u64> {
+    let text = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = number(parts.next()?, 4)?;
+    let month: i64 = number(parts.next()?, 2)?;
+    let day: i64 = number(parts.next()?, 2)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let parts: Vec<&str> = time.split(':').collect();
+        if !(2..=3).contains(&parts.len()) {
+            return None;
+        }
+        let hour = number(parts[0], 2)?;
+        let minute = number(parts[1], 2)?;
+        let second = parts.get(2).map_or(Some(0), |s| number(s, 2))?;
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        seconds = hour * 3_600 + minute * 60 + second;
+    }
+
+    // Days-from-civil (Howard Hinnant's algorithm).
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    u64::try_from(days * 86_400 + seconds).ok()
+}
+
+fn number(text: &str, digits: usize) -> Option<i64> {
+    if text.len() != digits || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_durations_and_timestamps() {
+        assert_eq!(duration("36h"), Some(Duration::from_secs(36 * 3_600)));
+        assert_eq!(duration("2w"), Some(Duration::from_secs(14 * 86_400)));
+        assert_eq!(duration("h"), None);
+        assert_eq!(duration("7 d"), None);
+
+        assert_eq!(timestamp("1970-01-01"), Some(0));
+        assert_eq!(timestamp("2024-05-01"), Some(1_714_521_600));
+        assert_eq!(timestamp("2024-02-29T08:30:15Z"), Some(1_709_195_415));
+        assert_eq!(timestamp("2024-02-29 08:30"), Some(1_709_195_400));
+        assert_eq!(timestamp("2024-13-01"), None);
+        assert_eq!(timestamp("24-05-01"), None);
+        assert!("yesterday".parse::<ModifiedSince>().is_err());
+    }
+
+    #[test]
+    fn test_includes_files_modified_after_the_cutoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("include_process_create.xml");
+        std::fs::write(&file, "<Sysmon/>").unwrap();
+
+        let since = |text: &str| text.parse::<ModifiedSince>().unwrap();
+        assert!(since("1h").includes(&file));
+        assert!(since("2000-01-01").includes(&file));
+        assert!(!since("2999-01-01").includes(&file));
+    }
+}
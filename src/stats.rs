@@ -0,0 +1,320 @@
+//! `stats`: summary counts for a configuration.
+//!
+//! Rules are counted per event type and `onmatch`; a `<Rule>` counts once,
+//! while the field and operator tallies count every condition inside it.
+//! Technique coverage comes from the ATT&CK IDs cited in rule names, the
+//! same ones `explain` reports. Comparing the output before and after a
+//! merge is a quick check that nothing went missing.
+
+use crate::{explain, io_guard, validate};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::{FieldCondition, Filter, OnMatch, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// Configuration to summarize (XML or JSON)
+    pub config: PathBuf,
+
+    /// Number of fields and techniques to list
+    #[arg(long, default_value = "10")]
+    pub top: usize,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: StatsFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EventStats {
+    pub event: String,
+    pub include: usize,
+    pub exclude: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Count {
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub schema_version: String,
+    /// Size of the config file in bytes.
+    pub bytes: u64,
+    pub rule_groups: usize,
+    pub rules: usize,
+    pub conditions: usize,
+    pub include: usize,
+    pub exclude: usize,
+    /// In order of first appearance.
+    pub events: Vec<EventStats>,
+    /// Most used first.
+    pub fields: Vec<Count>,
+    /// Every operator used, most used first.
+    pub operators: Vec<Count>,
+    /// Distinct ATT&CK technique IDs cited.
+    pub techniques: usize,
+    /// Rules whose name cites at least one technique.
+    pub rules_with_techniques: usize,
+    /// Techniques cited by the most rules first.
+    pub top_techniques: Vec<Count>,
+}
+
+pub fn run(args: &StatsArgs) -> Result<(), ConversionError> {
+    let config = SysmonConfig::from_xml_str(&validate::load(&args.config)?.0)?;
+    let bytes = std::fs::metadata(&args.config)
+        .map_err(|e| ConversionError::io_error(&args.config, e))?
+        .len();
+    let mut stats = stats(&config, bytes);
+    stats.fields.truncate(args.top);
+    stats.top_techniques.truncate(args.top);
+
+    let text = match args.format {
+        StatsFormat::Text => render(&stats),
+        StatsFormat::Json => serde_json::to_string_pretty(&stats)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+pub fn stats(config: &SysmonConfig, bytes: u64) -> Stats {
+    let mut events: Vec<EventStats> = Vec::new();
+    let mut fields = BTreeMap::new();
+    let mut operators = BTreeMap::new();
+    let mut techniques: BTreeMap<String, usize> = BTreeMap::new();
+    let mut rules_with_techniques = 0;
+    let mut conditions = 0;
+
+    for event in config.events() {
+        let index = match events.iter().position(|e| e.event == event.event) {
+            Some(index) => index,
+            None => {
+                events.push(EventStats {
+                    event: event.event.clone(),
+                    include: 0,
+                    exclude: 0,
+                });
+                events.len() - 1
+            }
+        };
+        match event.onmatch {
+            OnMatch::Include => events[index].include += event.filters.len(),
+            OnMatch::Exclude => events[index].exclude += event.filters.len(),
+        }
+
+        for filter in &event.filters {
+            let (name, tested): (_, Vec<&FieldCondition>) = match filter {
+                Filter::Field(field) => (field.name.as_deref(), vec![field]),
+                Filter::Rule(rule) => (rule.name.as_deref(), rule.fields.iter().collect()),
+            };
+            for field in tested {
+                conditions += 1;
+                *fields.entry(field.field.clone()).or_insert(0) += 1;
+                *operators.entry(field.condition.as_str()).or_insert(0) += 1;
+            }
+
+            let cited: BTreeSet<String> = name
+                .map(explain::techniques)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|t| t.split_whitespace().next().map(str::to_string))
+                .collect();
+            if !cited.is_empty() {
+                rules_with_techniques += 1;
+            }
+            for id in cited {
+                *techniques.entry(id).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let include = events.iter().map(|e| e.include).sum();
+    let exclude = events.iter().map(|e| e.exclude).sum();
+    Stats {
+        schema_version: config.schema_version.clone(),
+        bytes,
+        rule_groups: config.rule_groups.len(),
+        rules: include + exclude,
+        conditions,
+        include,
+        exclude,
+        events,
+        fields: ranked(fields),
+        operators: ranked(operators),
+        techniques: techniques.len(),
+        rules_with_techniques,
+        top_techniques: ranked(techniques),
+    }
+}
+
+/// Counts sorted most first; ties keep name order.
+fn ranked<K: ToString>(counts: BTreeMap<K, usize>) -> Vec<Count> {
+    let mut ranked: Vec<Count> = counts
+        .into_iter()
+        .map(|(name, count)| Count {
+            name: name.to_string(),
+            count,
+        })
+        .collect();
+    ranked.sort_by_key(|c| std::cmp::Reverse(c.count));
+    ranked
+}
+
+fn percent(part: usize, whole: usize) -> usize {
+    (part * 100 + whole / 2).checked_div(whole).unwrap_or(0)
+}
+
+fn render(stats: &Stats) -> String {
+    let mut out = format!(
+        "Schema {}, {} bytes, {} rule group(s), {} rule(s), {} condition(s)\n",
+        stats.schema_version, stats.bytes, stats.rule_groups, stats.rules, stats.conditions
+    );
+
+    let width = stats
+        .events
+        .iter()
+        .map(|e| e.event.len())
+        .chain(["Event type".len()])
+        .max()
+        .unwrap_or(0);
+    let _ = writeln!(out, "\n{:width$}  Include  Exclude", "Event type");
+    for event in &stats.events {
+        let _ = writeln!(
+            out,
+            "{:width$}  {:>7}  {:>7}",
+            event.event, event.include, event.exclude
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{:width$}  {:>7}  {:>7}  ({}% include)",
+        "Total",
+        stats.include,
+        stats.exclude,
+        percent(stats.include, stats.rules)
+    );
+
+    let list = |counts: &[Count]| {
+        counts
+            .iter()
+            .map(|c| format!("{} {}", c.name, c.count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let _ = writeln!(out, "\nTop fields: {}", list(&stats.fields));
+    let _ = writeln!(out, "Operators: {}", list(&stats.operators));
+    let _ = write!(
+        out,
+        "ATT&CK: {} technique(s) cited by {} of {} rule(s) ({}%)",
+        stats.techniques,
+        stats.rules_with_techniques,
+        stats.rules,
+        percent(stats.rules_with_techniques, stats.rules)
+    );
+    if stats.top_techniques.is_empty() {
+        out.push('\n');
+    } else {
+        let _ = writeln!(out, "; most cited: {}", list(&stats.top_techniques));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90"><EventFiltering>
+<RuleGroup groupRelation="or">
+<ProcessCreate onmatch="include">
+<Image name="technique_id=T1059.001,technique_name=PowerShell" condition="end with">\powershell.exe</Image>
+<Image name="technique_id=T1059.001" condition="end with">\pwsh.exe</Image>
+<Rule name="technique_id=T1218.011" groupRelation="and">
+<Image condition="end with">\rundll32.exe</Image>
+<CommandLine condition="contains">javascript:</CommandLine>
+</Rule>
+</ProcessCreate>
+<ProcessCreate onmatch="exclude">
+<Image condition="is">C:\Windows\System32\svchost.exe</Image>
+</ProcessCreate>
+</RuleGroup>
+<RuleGroup groupRelation="or">
+<DnsQuery onmatch="exclude">
+<QueryName condition="end with">.microsoft.com</QueryName>
+</DnsQuery>
+</RuleGroup>
+</EventFiltering></Sysmon>"#;
+
+    #[test]
+    fn test_stats_counts_rules_fields_and_techniques() {
+        let stats = stats(&SysmonConfig::from_xml_str(CONFIG).unwrap(), 1234);
+        assert_eq!((stats.rules, stats.conditions), (5, 6));
+        assert_eq!((stats.include, stats.exclude), (3, 2));
+        assert_eq!(
+            stats.events,
+            vec![
+                EventStats {
+                    event: "ProcessCreate".into(),
+                    include: 3,
+                    exclude: 1
+                },
+                EventStats {
+                    event: "DnsQuery".into(),
+                    include: 0,
+                    exclude: 1
+                },
+            ]
+        );
+        assert_eq!(
+            stats.fields[0],
+            Count {
+                name: "Image".into(),
+                count: 4
+            }
+        );
+        assert_eq!(stats.operators[0].name, "end with");
+        assert_eq!(stats.operators[0].count, 4);
+        assert_eq!((stats.techniques, stats.rules_with_techniques), (2, 3));
+        assert_eq!(
+            stats.top_techniques[0],
+            Count {
+                name: "T1059.001".into(),
+                count: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_render_lists_event_types_and_totals() {
+        let text = render(&stats(&SysmonConfig::from_xml_str(CONFIG).unwrap(), 1234));
+        assert!(text
+            .starts_with("Schema 4.90, 1234 bytes, 2 rule group(s), 5 rule(s), 6 condition(s)\n"));
+        assert!(text.contains("\nProcessCreate        3        1\n"));
+        assert!(text.contains("\nTotal                3        2  (60% include)\n"));
+        assert!(text.contains(
+            "ATT&CK: 2 technique(s) cited by 3 of 5 rule(s) (60%); most cited: T1059.001 2, T1218.011 1\n"
+        ));
+    }
+}
@@ -0,0 +1,255 @@
+//! `--suppress`: a tuning file of rules to drop from a merged config.
+//!
+//! Suppressions are kept apart from the upstream files a merge reads, so
+//! those can be synced as they are while local exceptions for noisy rules
+//! live in one place. The file has one entry per line; blank lines and `#`
+//! comments are ignored:
+//!
+//! ```text
+//! # Too noisy on build agents
+//! id: T1036
+//! name: technique_id=T1059.001,technique_name=PowerShell
+//! field: Image = C:\Program Files\Agent\agent.exe
+//! NetworkConnect/field: DestinationPort = 443
+//! ```
+//!
+//! `id:` matches rules whose name has that `technique_id=` (or is the ID),
+//! `name:` matches the whole rule name, and `field:` matches conditions on
+//! that field with exactly that value. An event type before the kind, as
+//! in the last line, limits the entry to that event type. A `<Rule>` is
+//! dropped whole when any of its conditions matches, since dropping one
+//! condition would loosen it.
+//!
+//! An include filter left empty is kept, since an empty include logs
+//! nothing, which is what suppressing all its rules asks for. An exclude
+//! filter left empty is dropped, as an empty exclude would log everything;
+//! suppressing an exclude rule always logs more, so each one is warned
+//! about.
+
+use log::{debug, warn};
+use std::path::Path;
+use sysmon_cli::model::{FieldCondition, Filter, OnMatch, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Id(String),
+    Name(String),
+    Field { field: String, value: String },
+}
+
+/// One line of a tuning file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suppression {
+    /// Line number in the file, for the report.
+    pub line: usize,
+    event: Option<String>,
+    target: Target,
+}
+
+/// How many rules each suppression dropped, in file order.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub dropped: Vec<(Suppression, usize)>,
+}
+
+impl Report {
+    pub fn total(&self) -> usize {
+        self.dropped.iter().map(|(_, n)| n).sum()
+    }
+}
+
+impl std::fmt::Display for Suppression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(event) = &self.event {
+            write!(f, "{}/", event)?;
+        }
+        match &self.target {
+            Target::Id(id) => write!(f, "id: {}", id),
+            Target::Name(name) => write!(f, "name: {}", name),
+            Target::Field { field, value } => write!(f, "field: {} = {}", field, value),
+        }
+    }
+}
+
+impl Suppression {
+    fn matches_condition(&self, condition: &FieldCondition) -> bool {
+        match &self.target {
+            Target::Field { field, value } => {
+                condition.field == *field && condition.value == *value
+            }
+            Target::Id(_) | Target::Name(_) => condition
+                .name
+                .as_deref()
+                .is_some_and(|n| self.matches_name(n)),
+        }
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        match &self.target {
+            Target::Id(id) => {
+                name == id
+                    || name
+                        .split(',')
+                        .filter_map(|part| part.trim().split_once('='))
+                        .any(|(key, value)| key.trim() == "technique_id" && value.trim() == id)
+            }
+            Target::Name(wanted) => name == wanted,
+            Target::Field { .. } => false,
+        }
+    }
+
+    fn matches(&self, event: &str, filter: &Filter) -> bool {
+        if self.event.as_deref().is_some_and(|e| e != event) {
+            return false;
+        }
+        match filter {
+            Filter::Field(condition) => self.matches_condition(condition),
+            Filter::Rule(rule) => {
+                rule.name.as_deref().is_some_and(|n| self.matches_name(n))
+                    || rule.fields.iter().any(|c| self.matches_condition(c))
+            }
+        }
+    }
+}
+
+/// Reads the tuning file at `path`.
+pub fn load(path: &Path) -> Result<Vec<Suppression>, ConversionError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ConversionError::io_error(path, e))?;
+    parse(&text).map_err(|e| ConversionError::ValidationError(format!("{}: {}", path.display(), e)))
+}
+
+fn parse(text: &str) -> Result<Vec<Suppression>, String> {
+    let mut suppressions = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: &str| format!("line {}: {}: `{}`", index + 1, message, line);
+        let Some((kind, value)) = line.split_once(':') else {
+            return Err(invalid("expected `id:`, `name:` or `field:`"));
+        };
+        let (event, kind) = match kind.trim().split_once('/') {
+            Some((event, kind)) => (Some(event.trim().to_string()), kind.trim()),
+            None => (None, kind.trim()),
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(invalid("missing value"));
+        }
+        let target = match kind {
+            "id" => Target::Id(value.to_string()),
+            "name" => Target::Name(value.to_string()),
+            "field" => match value.split_once('=') {
+                Some((field, value)) if !field.trim().is_empty() => Target::Field {
+                    field: field.trim().to_string(),
+                    value: value.trim().to_string(),
+                },
+                _ => return Err(invalid("expected `field: <Field> = <value>`")),
+            },
+            _ => return Err(invalid("expected `id:`, `name:` or `field:`")),
+        };
+        suppressions.push(Suppression {
+            line: index + 1,
+            event,
+            target,
+        });
+    }
+    Ok(suppressions)
+}
+
+/// Drops every rule of `config` a suppression matches. Each rule is
+/// counted against the first suppression that matches it.
+pub fn apply(config: &mut SysmonConfig, suppressions: &[Suppression]) -> Report {
+    let mut counts = vec![0; suppressions.len()];
+    for group in &mut config.rule_groups {
+        group.events.retain_mut(|event| {
+            let had_filters = !event.filters.is_empty();
+            event.filters.retain(|filter| {
+                let Some(i) = suppressions
+                    .iter()
+                    .position(|s| s.matches(&event.event, filter))
+                else {
+                    return true;
+                };
+                if event.onmatch == OnMatch::Exclude {
+                    warn!(
+                        "{} suppresses a {} exclude rule, so more events are logged",
+                        suppressions[i], event.event
+                    );
+                } else {
+                    debug!("{} rule suppressed by {}", event.event, suppressions[i]);
+                }
+                counts[i] += 1;
+                false
+            });
+            event.onmatch == OnMatch::Include || !had_filters || !event.filters.is_empty()
+        });
+    }
+    config.rule_groups.retain(|group| !group.events.is_empty());
+
+    let report = Report {
+        dropped: suppressions.iter().cloned().zip(counts).collect(),
+    };
+    for (suppression, _) in report.dropped.iter().filter(|(_, n)| *n == 0) {
+        warn!(
+            "Suppression on line {} ({}) matches no rule",
+            suppression.line, suppression
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_drops_matching_rules_and_empty_excludes() {
+        let mut config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+<ProcessCreate onmatch="include">
+<Image name="technique_id=T1036,technique_name=Masquerading" condition="end with">\svch0st.exe</Image>
+<Image condition="end with">\psexec.exe</Image>
+<Rule groupRelation="and"><Image condition="is">C:\agent.exe</Image><User condition="is">SYSTEM</User></Rule>
+</ProcessCreate>
+<NetworkConnect onmatch="exclude"><DestinationPort condition="is">443</DestinationPort></NetworkConnect>
+<DnsQuery onmatch="exclude"><Image condition="is">C:\agent.exe</Image></DnsQuery>
+<FileCreate onmatch="include"><Image condition="is">C:\agent.exe</Image></FileCreate>
+</RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        let suppressions = parse(
+            "# noisy\nid: T1036\nProcessCreate/field: Image = C:\\agent.exe\n\
+             NetworkConnect/field: DestinationPort = 443\nname: unused\n\
+             FileCreate/field: Image = C:\\agent.exe\n",
+        )
+        .unwrap();
+
+        let report = apply(&mut config, &suppressions);
+        let counts: Vec<usize> = report.dropped.iter().map(|(_, n)| *n).collect();
+        assert_eq!(counts, [1, 1, 1, 0, 1]);
+        assert_eq!(report.total(), 4);
+        let events: Vec<(&str, usize)> = config
+            .events()
+            .map(|e| (e.event.as_str(), e.filters.len()))
+            .collect();
+        // The emptied include still logs no FileCreate events.
+        assert_eq!(
+            events,
+            [("ProcessCreate", 1), ("DnsQuery", 1), ("FileCreate", 0)]
+        );
+        assert_eq!(
+            suppressions[1].to_string(),
+            "ProcessCreate/field: Image = C:\\agent.exe"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_entries() {
+        assert!(parse("rule: x").unwrap_err().starts_with("line 1:"));
+        assert!(parse("\nfield: Image").unwrap_err().starts_with("line 2:"));
+        assert!(parse("name:").is_err());
+    }
+}
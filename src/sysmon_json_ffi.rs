@@ -0,0 +1,181 @@
+//! C ABI for linking the converter into native tooling.
+//!
+//! Enabled with the `ffi` feature; build the shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//! The matching header is `include/sysmon_cli.h`,
+//! generated with `cbindgen --config cbindgen.toml --output include/sysmon_cli.h`.
+//!
+//! Every function takes NUL-terminated UTF-8 and returns a newly allocated
+//! string that the caller must release with [`sysmon_string_free`]. On
+//! failure the functions return NULL and [`sysmon_last_error`] describes the
+//! problem. The JSON comes from the same serializers as the wasm bindings'
+//! ([`convert_xml_str_to_json`], [`validation::validate_str_to_json`]):
+//! the typed model's JSON, not the `sysmon_json` layout the CLI writes.
+
+use crate::validation;
+use crate::{convert_json_str_to_xml, convert_xml_str_to_json};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Converts a Sysmon XML document to JSON.
+///
+/// # Safety
+///
+/// `xml` must be NULL or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sysmon_xml_to_json(xml: *const c_char) -> *mut c_char {
+    call(xml, |text| {
+        convert_xml_str_to_json(text).map_err(Into::into)
+    })
+}
+
+/// Converts JSON produced by [`sysmon_xml_to_json`] back to Sysmon XML.
+///
+/// # Safety
+///
+/// `json` must be NULL or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sysmon_json_to_xml(json: *const c_char) -> *mut c_char {
+    call(json, |text| {
+        convert_json_str_to_xml(text).map_err(Into::into)
+    })
+}
+
+/// Validates a Sysmon XML document and returns a JSON array of
+/// `{"location", "message"}` issues; an empty array means no problems.
+///
+/// # Safety
+///
+/// `xml` must be NULL or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sysmon_validate(xml: *const c_char) -> *mut c_char {
+    call(xml, |text| {
+        validation::validate_str_to_json(text).map_err(Into::into)
+    })
+}
+
+/// Releases a string returned by this library. NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must be NULL or a pointer returned by one of the functions above
+/// that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn sysmon_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Returns the message for the last failure on this thread, or NULL. The
+/// pointer is owned by the library and valid until the next call on the
+/// same thread; do not free it.
+#[no_mangle]
+pub extern "C" fn sysmon_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(std::ptr::null(), |s| s.as_ptr()))
+}
+
+type FfiResult = Result<String, Box<dyn std::error::Error>>;
+
+unsafe fn call(input: *const c_char, f: impl FnOnce(&str) -> FfiResult) -> *mut c_char {
+    let result = if input.is_null() {
+        Err("input is NULL".into())
+    } else {
+        match CStr::from_ptr(input).to_str() {
+            Ok(text) => panic::catch_unwind(AssertUnwindSafe(|| f(text)))
+                .unwrap_or_else(|_| Err("internal error while processing input".into())),
+            Err(e) => Err(format!("input is not valid UTF-8: {e}").into()),
+        }
+    };
+
+    let output = result.and_then(|text| CString::new(text).map_err(Into::into));
+    match output {
+        Ok(text) => {
+            set_last_error(None);
+            text.into_raw()
+        }
+        Err(e) => {
+            set_last_error(Some(e.to_string()));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn set_last_error(message: Option<String>) {
+    let message = message.map(|m| CString::new(m.replace('\0', " ")).unwrap_or_default());
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn take(s: *mut c_char) -> String {
+        let text = CStr::from_ptr(s).to_str().unwrap().to_string();
+        sysmon_string_free(s);
+        text
+    }
+
+    #[test]
+    fn test_round_trip_and_errors() {
+        let xml = CString::new(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="exclude"><Image condition="is">C:\a.exe</Image></ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        unsafe {
+            let json = sysmon_xml_to_json(xml.as_ptr());
+            assert!(!json.is_null());
+            let back = sysmon_json_to_xml(json);
+            sysmon_string_free(json);
+            assert!(take(back).contains("ProcessCreate"));
+            assert_eq!(take(sysmon_validate(xml.as_ptr())), "[]");
+
+            assert!(sysmon_xml_to_json(std::ptr::null()).is_null());
+            let error = CStr::from_ptr(sysmon_last_error()).to_str().unwrap();
+            assert_eq!(error, "input is NULL");
+        }
+    }
+
+    #[test]
+    fn test_json_shape_matches_the_typed_model() {
+        let xml = r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="exclude"><Image condition="is">C:\a.exe</Image></ProcessCreate></RuleGroup></EventFiltering></Sysmon>"#;
+        let input = CString::new(xml).unwrap();
+        let json = unsafe { take(sysmon_xml_to_json(input.as_ptr())) };
+
+        assert_eq!(json, convert_xml_str_to_json(xml).unwrap());
+        assert_eq!(
+            json,
+            r#"{
+  "schema_version": "4.90",
+  "options": [],
+  "rule_groups": [
+    {
+      "name": "",
+      "group_relation": "or",
+      "events": [
+        {
+          "event": "ProcessCreate",
+          "onmatch": "exclude",
+          "filters": [
+            {
+              "type": "field",
+              "field": "Image",
+              "condition": "is",
+              "value": "C:\\a.exe",
+              "name": null
+            }
+          ]
+        }
+      ]
+    }
+  ]
+}"#
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! `--output-template`: where each batch output is written.
+//!
+//! The template is a path relative to the output directory with these
+//! placeholders:
+//!
+//! - `{relpath}`: the input's directory relative to the input root (empty
+//!   at the top level)
+//! - `{stem}`: the input file name without its extension
+//! - `{ext}` / `{format}`: the output format, `json` or `xml`
+//! - `{schema}`: the config's `schemaversion`
+//! - `{event}`: the config's event type, `mixed` if it filters several and
+//!   `none` if it has no filters
+//!
+//! `{schema}` and `{event}` read the input; when it cannot be parsed they
+//! become `unknown` and the conversion itself reports the problem. Empty
+//! path segments are dropped, so `{relpath}/{stem}.{ext}` also works at the
+//! top level.
+
+use crate::convert;
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use sysmon_cli::format::Format;
+use sysmon_cli::model::SysmonConfig;
+use sysmon_json::error::ConversionError;
+
+const PLACEHOLDERS: &[&str] = &["relpath", "stem", "ext", "format", "schema", "event"];
+
+#[derive(Debug, Clone)]
+pub struct OutputTemplate(String);
+
+impl std::str::FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed '{{' in output template: {}", template))?;
+            let name = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "Unknown placeholder {{{}}} in output template (expected one of: {})",
+                    name,
+                    PLACEHOLDERS.join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if Path::new(template).is_absolute() {
+            return Err("The output template must be a relative path".to_string());
+        }
+        Ok(OutputTemplate(template.to_string()))
+    }
+}
+
+impl OutputTemplate {
+    /// The output path for `input`, relative to the output directory.
+    pub fn render(
+        &self,
+        input_dir: &Path,
+        input: &Path,
+        output_format: Format,
+    ) -> Result<PathBuf, ConversionError> {
+        let relative = input.strip_prefix(input_dir).unwrap_or(input);
+        let relpath = relative
+            .parent()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        let stem = input
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut rendered = self
+            .0
+            .replace("{relpath}", &relpath)
+            .replace("{stem}", &stem)
+            .replace("{ext}", output_format.extension())
+            .replace("{format}", output_format.extension());
+        if rendered.contains("{schema}") || rendered.contains("{event}") {
+            let (schema, event) = describe(input);
+            rendered = rendered
+                .replace("{schema}", &schema)
+                .replace("{event}", &event);
+        }
+
+        let path: PathBuf = rendered
+            .split(['/', '\\'])
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        if path.as_os_str().is_empty()
+            || path
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(ConversionError::InvalidFile(format!(
+                "Output template gives {:?} for {}, which leaves the output directory",
+                rendered,
+                input.display()
+            )));
+        }
+        Ok(path)
+    }
+}
+
+/// `schemaversion` and event type of a config, for `{schema}` and `{event}`.
+fn describe(input: &Path) -> (String, String) {
+    let unknown = || ("unknown".to_string(), "unknown".to_string());
+    let Ok(text) = std::fs::read_to_string(input) else {
+        return unknown();
+    };
+    let xml = match Format::from_extension(input) {
+        Some(Format::Json) => match convert::json_str_to_xml(&text) {
+            Ok(xml) => xml,
+            Err(_) => return unknown(),
+        },
+        _ => text,
+    };
+    let Ok(config) = SysmonConfig::from_xml_str(&xml) else {
+        return unknown();
+    };
+
+    let events: BTreeSet<&str> = config.events().map(|e| e.event.as_str()).collect();
+    let event = match events.len() {
+        0 => "none".to_string(),
+        1 => events.into_iter().next().unwrap_or_default().to_string(),
+        _ => "mixed".to_string(),
+    };
+    (config.schema_version.clone(), event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_placeholders() {
+        let template: OutputTemplate = "{format}/{relpath}/{stem}.{ext}".parse().unwrap();
+        let render = |input: &str| {
+            template
+                .render(Path::new("in"), Path::new(input), Format::Json)
+                .unwrap()
+        };
+        assert_eq!(
+            render("in/1_process/include.xml"),
+            PathBuf::from("json/1_process/include.json")
+        );
+        assert_eq!(render("in/top.xml"), PathBuf::from("json/top.json"));
+    }
+
+    #[test]
+    fn test_render_reads_schema_and_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("dns.xml");
+        std::fs::write(
+            &input,
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup groupRelation="or">
+            <DnsQuery onmatch="exclude"><QueryName condition="is">x</QueryName></DnsQuery>
+            </RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        let template: OutputTemplate = "{event}/{stem}_v{schema}.{ext}".parse().unwrap();
+        assert_eq!(
+            template.render(dir.path(), &input, Format::Json).unwrap(),
+            PathBuf::from("DnsQuery/dns_v4.90.json")
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_templates() {
+        assert!("{name}.json".parse::<OutputTemplate>().is_err());
+        assert!("{stem.json".parse::<OutputTemplate>().is_err());
+        let template: OutputTemplate = "../{stem}.{ext}".parse().unwrap();
+        assert!(template
+            .render(Path::new("in"), Path::new("in/a.xml"), Format::Json)
+            .is_err());
+    }
+}
@@ -0,0 +1,546 @@
+//! `tui`: browse a config in the terminal.
+//!
+//! The tree lists event types, then the RuleGroups and `onmatch` blocks that
+//! filter them, then their rules, so a merged config of thousands of rules
+//! folds down to one screen of event types. `/` searches rule values, fields
+//! and names as fuzzy subsequences and lists the best matches first; Enter
+//! on a match shows it in the tree. ATT&CK technique tags from rule names
+//! are shown next to each rule.
+
+use crate::{explain, validate};
+use clap::Args;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use sysmon_cli::model::{FieldCondition, Filter, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+const PAGE: isize = 10;
+
+#[derive(Args)]
+pub struct TuiArgs {
+    /// Configuration to browse (XML or JSON)
+    pub config: PathBuf,
+}
+
+pub fn run(args: &TuiArgs) -> Result<(), ConversionError> {
+    let config = SysmonConfig::from_xml_str(&validate::load(&args.config)?.0)?;
+    let mut app = App::new(Tree::build(&config), args.config.display().to_string());
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result.map_err(|e| ConversionError::Other(format!("Terminal error: {}", e)))
+}
+
+/// One line of the tree.
+#[derive(Debug)]
+struct Node {
+    depth: usize,
+    parent: Option<usize>,
+    has_children: bool,
+    label: String,
+    /// What a search matches; empty for event types and groups.
+    text: String,
+    name: Option<String>,
+    /// ATT&CK techniques cited in the rule's name.
+    tags: Vec<String>,
+}
+
+/// The config as a tree, flattened in display order.
+#[derive(Debug)]
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn build(config: &SysmonConfig) -> Tree {
+        let mut types: Vec<&str> = Vec::new();
+        for event in config.events() {
+            if !types.contains(&event.event.as_str()) {
+                types.push(&event.event);
+            }
+        }
+
+        let mut tree = Tree { nodes: Vec::new() };
+        for event_type in types {
+            let top = tree.push(None, String::new(), String::new(), None);
+            let mut rules = 0;
+            for (g, group) in config.rule_groups.iter().enumerate() {
+                for event in group.events.iter().filter(|e| e.event == event_type) {
+                    let name = match group.name.as_deref() {
+                        Some(name) if !name.is_empty() => format!(" \"{}\"", name),
+                        _ => String::new(),
+                    };
+                    let label = format!("RuleGroup[{}]{} {}", g + 1, name, event.onmatch.as_str());
+                    let parent = tree.push(Some(top), label, String::new(), None);
+                    rules += event.filters.len();
+                    for filter in &event.filters {
+                        match filter {
+                            Filter::Field(field) => {
+                                tree.push_condition(parent, field, field.name.as_deref());
+                            }
+                            Filter::Rule(rule) => {
+                                let label = format!("Rule {}", rule.group_relation.as_str());
+                                let text = rule
+                                    .fields
+                                    .iter()
+                                    .map(search_text)
+                                    .collect::<Vec<_>>()
+                                    .join(" ");
+                                let combined =
+                                    tree.push(Some(parent), label, text, rule.name.as_deref());
+                                for field in &rule.fields {
+                                    tree.push_condition(combined, field, None);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            tree.nodes[top].label = format!("{} ({} rules)", event_type, rules);
+        }
+        tree
+    }
+
+    fn push(
+        &mut self,
+        parent: Option<usize>,
+        label: String,
+        text: String,
+        name: Option<&str>,
+    ) -> usize {
+        let depth = match parent {
+            Some(parent) => {
+                self.nodes[parent].has_children = true;
+                self.nodes[parent].depth + 1
+            }
+            None => 0,
+        };
+        let name = name.filter(|n| !n.is_empty());
+        self.nodes.push(Node {
+            depth,
+            parent,
+            has_children: false,
+            label,
+            text,
+            tags: name.map(explain::techniques).unwrap_or_default(),
+            name: name.map(str::to_string),
+        });
+        self.nodes.len() - 1
+    }
+
+    fn push_condition(&mut self, parent: usize, field: &FieldCondition, name: Option<&str>) {
+        let label = format!(
+            "{} {} \"{}\"",
+            field.field,
+            field.condition.as_str(),
+            field.value
+        );
+        let text = match name {
+            Some(name) => format!("{} {}", search_text(field), name),
+            None => search_text(field),
+        };
+        self.push(Some(parent), label, text, name);
+    }
+
+    /// The labels from the top of the tree down to node `i`.
+    fn location(&self, i: usize) -> String {
+        let mut labels = vec![self.nodes[i].label.as_str()];
+        let mut at = self.nodes[i].parent;
+        while let Some(parent) = at {
+            labels.push(&self.nodes[parent].label);
+            at = self.nodes[parent].parent;
+        }
+        labels.reverse();
+        labels.join(" › ")
+    }
+}
+
+fn search_text(field: &FieldCondition) -> String {
+    format!("{} {}", field.value, field.field)
+}
+
+/// Scores `text` against `pattern` as a case-insensitive subsequence,
+/// favouring consecutive characters and word starts over gaps; `None` if
+/// the characters of `pattern` do not all appear in order.
+fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut from = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in pattern.chars().flat_map(char::to_lowercase) {
+        let found = from + text[from..].iter().position(|&c| c == wanted)?;
+        score += match previous {
+            Some(previous) if found == previous + 1 => 5,
+            _ if found == 0 || !text[found - 1].is_alphanumeric() => 3,
+            Some(previous) => 1 - (found - previous - 1).min(5) as i64,
+            None => 1,
+        };
+        previous = Some(found);
+        from = found + 1;
+    }
+    Some(score)
+}
+
+struct App {
+    tree: Tree,
+    title: String,
+    collapsed: HashSet<usize>,
+    query: String,
+    typing: bool,
+    /// The nodes listed: the unfolded tree, or search matches best first.
+    rows: Vec<usize>,
+    state: ListState,
+}
+
+impl App {
+    fn new(tree: Tree, title: String) -> App {
+        let collapsed = (0..tree.nodes.len())
+            .filter(|&i| tree.nodes[i].depth == 0 && tree.nodes[i].has_children)
+            .collect();
+        let mut app = App {
+            tree,
+            title,
+            collapsed,
+            query: String::new(),
+            typing: false,
+            rows: Vec::new(),
+            state: ListState::default(),
+        };
+        app.refresh();
+        app
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.state
+            .selected()
+            .and_then(|row| self.rows.get(row).copied())
+    }
+
+    /// Recomputes the rows, keeping the selected node if it is still listed.
+    fn refresh(&mut self) {
+        let keep = self.selected();
+        self.rows = if self.query.is_empty() {
+            let mut rows = Vec::new();
+            let mut folded_at: Option<usize> = None;
+            for (i, node) in self.tree.nodes.iter().enumerate() {
+                if folded_at.is_some_and(|depth| node.depth > depth) {
+                    continue;
+                }
+                folded_at = self.collapsed.contains(&i).then_some(node.depth);
+                rows.push(i);
+            }
+            rows
+        } else {
+            let mut scored: Vec<(i64, usize)> = (self.tree.nodes.iter().enumerate())
+                .filter(|(_, node)| !node.text.is_empty())
+                .filter_map(|(i, node)| Some((fuzzy_score(&self.query, &node.text)?, i)))
+                .collect();
+            // Ties go to the shorter text, the closer match.
+            let nodes = &self.tree.nodes;
+            scored.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), nodes[i].text.len()));
+            scored.into_iter().map(|(_, i)| i).collect()
+        };
+        let row = keep.and_then(|node| self.rows.iter().position(|&r| r == node));
+        self.state
+            .select(row.or((!self.rows.is_empty()).then_some(0)));
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let last = self.rows.len() as isize - 1;
+        let row = self.state.selected().unwrap_or(0) as isize;
+        self.state
+            .select(Some((row + delta).clamp(0, last) as usize));
+    }
+
+    fn select_node(&mut self, node: usize) {
+        if let Some(row) = self.rows.iter().position(|&r| r == node) {
+            self.state.select(Some(row));
+        }
+    }
+
+    /// Leaves search and shows `node` in the tree, unfolding its ancestors.
+    fn reveal(&mut self, node: usize) {
+        let mut at = self.tree.nodes[node].parent;
+        while let Some(parent) = at {
+            self.collapsed.remove(&parent);
+            at = self.tree.nodes[parent].parent;
+        }
+        self.query.clear();
+        self.refresh();
+        self.select_node(node);
+    }
+
+    /// Handles a key press; false quits.
+    fn handle(&mut self, key: KeyEvent) -> bool {
+        if self.typing {
+            match key.code {
+                KeyCode::Esc => {
+                    self.typing = false;
+                    self.query.clear();
+                    self.refresh();
+                }
+                KeyCode::Enter => self.typing = false,
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.refresh();
+                }
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.state.select(Some(0));
+                    self.refresh();
+                }
+                KeyCode::Up => self.move_by(-1),
+                KeyCode::Down => self.move_by(1),
+                _ => {}
+            }
+            return true;
+        }
+
+        let selected = self.selected();
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if self.query.is_empty() => return false,
+            KeyCode::Esc => {
+                self.query.clear();
+                self.refresh();
+            }
+            KeyCode::Char('/') => self.typing = true,
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-PAGE),
+            KeyCode::PageDown => self.move_by(PAGE),
+            KeyCode::Home | KeyCode::Char('g') => self.move_by(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_by(isize::MAX / 2),
+            _ if !self.query.is_empty() => {
+                if let (KeyCode::Enter | KeyCode::Char(' '), Some(node)) = (key.code, selected) {
+                    self.reveal(node);
+                }
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                if let Some(node) = selected {
+                    self.collapsed.remove(&node);
+                    self.refresh();
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                if let Some(node) = selected {
+                    let has_children = self.tree.nodes[node].has_children;
+                    if has_children && !self.collapsed.contains(&node) {
+                        self.collapsed.insert(node);
+                        self.refresh();
+                    } else if let Some(parent) = self.tree.nodes[node].parent {
+                        self.select_node(parent);
+                    }
+                }
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                if let Some(node) = selected.filter(|&n| self.tree.nodes[n].has_children) {
+                    if !self.collapsed.remove(&node) {
+                        self.collapsed.insert(node);
+                    }
+                    self.refresh();
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, details_area, status_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(6),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let items: Vec<ListItem> = self.rows.iter().map(|&i| self.item(i)).collect();
+        let title = if self.query.is_empty() {
+            format!(" {} ", self.title)
+        } else {
+            format!(" {} match(es) for \"{}\" ", self.rows.len(), self.query)
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.state);
+
+        let details = Paragraph::new(self.details())
+            .block(Block::bordered().title(" Details "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(details, details_area);
+
+        let status = if self.typing {
+            format!("/{}_", self.query)
+        } else if self.query.is_empty() {
+            "↑↓ move  ←→ fold  enter toggle  / search  q quit".to_string()
+        } else {
+            "↑↓ move  enter show in tree  / edit search  esc clear  q quit".to_string()
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+    }
+
+    fn item(&self, i: usize) -> ListItem<'static> {
+        let node = &self.tree.nodes[i];
+        let mut spans = Vec::new();
+        if self.query.is_empty() {
+            let marker = match (node.has_children, self.collapsed.contains(&i)) {
+                (false, _) => "  ",
+                (true, true) => "▸ ",
+                (true, false) => "▾ ",
+            };
+            spans.push(Span::raw(format!("{}{}", "  ".repeat(node.depth), marker)));
+        } else if let Some(parent) = node.parent {
+            let dim = Style::new().fg(Color::DarkGray);
+            spans.push(Span::styled(
+                format!("{} › ", self.tree.location(parent)),
+                dim,
+            ));
+        }
+        spans.push(Span::raw(node.label.clone()));
+        for tag in &node.tags {
+            spans.push(Span::styled(
+                format!("  {}", tag),
+                Style::new().fg(Color::Cyan),
+            ));
+        }
+        ListItem::new(Line::from(spans))
+    }
+
+    fn details(&self) -> Vec<Line<'static>> {
+        let Some(node) = self.selected() else {
+            return vec![Line::raw("No rules match")];
+        };
+        let bold = Style::new().add_modifier(Modifier::BOLD);
+        let mut lines = vec![Line::styled(self.tree.location(node), bold)];
+        let node = &self.tree.nodes[node];
+        if let Some(name) = &node.name {
+            lines.push(Line::raw(format!("Name: {}", name)));
+        }
+        if !node.tags.is_empty() {
+            lines.push(Line::raw(format!("ATT&CK: {}", node.tags.join(", "))));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+<EventFiltering>
+<RuleGroup name="" groupRelation="or">
+<ProcessCreate onmatch="include">
+<Image name="technique_id=T1059.001,technique_name=PowerShell" condition="end with">\powershell.exe</Image>
+<Rule groupRelation="and">
+<Image condition="end with">\rundll32.exe</Image>
+<CommandLine condition="contains">javascript:</CommandLine>
+</Rule>
+</ProcessCreate>
+</RuleGroup>
+<RuleGroup name="dns" groupRelation="or">
+<DnsQuery onmatch="exclude">
+<QueryName condition="end with">.microsoft.com</QueryName>
+</DnsQuery>
+</RuleGroup>
+</EventFiltering>
+</Sysmon>"#;
+
+    fn app() -> App {
+        let config = SysmonConfig::from_xml_str(CONFIG).unwrap();
+        App::new(Tree::build(&config), "c.xml".into())
+    }
+
+    fn labels(app: &App) -> Vec<&str> {
+        app.rows
+            .iter()
+            .map(|&i| app.tree.nodes[i].label.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_tree_folds_and_unfolds() {
+        let mut app = app();
+        assert_eq!(
+            labels(&app),
+            vec!["ProcessCreate (2 rules)", "DnsQuery (1 rules)"]
+        );
+
+        app.handle(KeyCode::Right.into());
+        app.handle(KeyCode::Down.into());
+        app.handle(KeyCode::Right.into());
+        assert_eq!(
+            labels(&app),
+            vec![
+                "ProcessCreate (2 rules)",
+                "RuleGroup[1] include",
+                r#"Image end with "\powershell.exe""#,
+                "Rule and",
+                r#"Image end with "\rundll32.exe""#,
+                r#"CommandLine contains "javascript:""#,
+                "DnsQuery (1 rules)",
+            ]
+        );
+        let powershell = app.rows[2];
+        assert_eq!(
+            app.tree.nodes[powershell].tags,
+            vec!["T1059.001 (PowerShell)"]
+        );
+
+        app.handle(KeyCode::Left.into());
+        assert_eq!(labels(&app).len(), 3);
+        assert!(!app.handle(KeyCode::Char('q').into()));
+    }
+
+    #[test]
+    fn test_search_ranks_matches_and_reveals_them() {
+        let mut app = app();
+        app.handle(KeyCode::Char('/').into());
+        for c in "rundll".chars() {
+            app.handle(KeyCode::Char(c).into());
+        }
+        app.handle(KeyCode::Enter.into());
+        assert_eq!(
+            labels(&app),
+            vec![r#"Image end with "\rundll32.exe""#, "Rule and"]
+        );
+
+        app.handle(KeyCode::Enter.into());
+        assert!(app.query.is_empty());
+        assert_eq!(
+            app.tree.location(app.selected().unwrap()),
+            r#"ProcessCreate (2 rules) › RuleGroup[1] include › Rule and › Image end with "\rundll32.exe""#
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_score_prefers_runs_and_word_starts() {
+        assert!(fuzzy_score("pwsh", "powershell.exe").is_some());
+        assert_eq!(fuzzy_score("xyz", "powershell.exe"), None);
+        let run = fuzzy_score("shell", "powershell.exe").unwrap();
+        let gaps = fuzzy_score("shell", "sh-e-l-l").unwrap();
+        assert!(run > gaps);
+    }
+}
@@ -0,0 +1,103 @@
+//! Per-user defaults for command-line options.
+//!
+//! Read from `--config <path>`, or else from `config.toml` in the
+//! `sysmon-helper` directory under `$XDG_CONFIG_HOME` (falling back to
+//! `~/.config`, or `%APPDATA%` on Windows). Values only replace built-in
+//! defaults: anything given on the command line wins.
+//!
+//! ```toml
+//! max_size = 50
+//! workers = 8
+//! ignore = ["*.bak", "archive/*"]
+//! backup = true
+//! output_encoding = "utf-8-bom"
+//! ```
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    pub max_size: Option<u64>,
+    pub max_depth: Option<u32>,
+    pub max_memory_mb: Option<u64>,
+    pub max_output_kb: Option<u64>,
+    pub platform: Option<String>,
+    pub strategy: Option<String>,
+    pub timeout_secs: Option<u64>,
+    pub workers: Option<usize>,
+    pub ignore: Option<Vec<String>>,
+    pub recursive: Option<bool>,
+    pub follow_symlinks: Option<bool>,
+    pub backup: Option<bool>,
+    pub verify: Option<bool>,
+    pub incremental: Option<bool>,
+    pub silent: Option<bool>,
+    pub output_template: Option<String>,
+    pub on_collision: Option<String>,
+    pub output_encoding: Option<String>,
+    pub preprocess_rules: Option<PathBuf>,
+    pub log_format: Option<String>,
+    pub error_format: Option<String>,
+}
+
+/// Loads the config file. An explicit path must exist; the default
+/// location is optional.
+pub fn load(explicit: Option<&Path>) -> Result<Option<(PathBuf, UserConfig)>, ConversionError> {
+    let path = match explicit {
+        Some(path) => path.to_path_buf(),
+        None => match default_path() {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(None),
+        },
+    };
+
+    let text = std::fs::read_to_string(&path).map_err(|e| ConversionError::io_error(&path, e))?;
+    let config = parse(&text)
+        .map_err(|e| ConversionError::ParserError(format!("{}: {}", path.display(), e)))?;
+    Ok(Some((path, config)))
+}
+
+fn parse(text: &str) -> Result<UserConfig, toml::de::Error> {
+    toml::from_str(text)
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                std::env::var_os("APPDATA").map(PathBuf::from)
+            } else {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+            }
+        })?;
+    Some(base.join("sysmon-helper").join("config.toml"))
+}
+
+/// Parses an enum-valued setting the same way clap parses the flag.
+pub fn parse_enum<T: ValueEnum>(path: &Path, key: &str, value: &str) -> Result<T, ConversionError> {
+    T::from_str(value, true).map_err(|e| {
+        ConversionError::ValidationError(format!("{}: {}: {}", path.display(), key, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse("max_size = 50\nignore = [\"*.bak\"]\nbackup = true\n").unwrap();
+        assert_eq!(config.max_size, Some(50));
+        assert_eq!(config.ignore, Some(vec!["*.bak".to_string()]));
+        assert_eq!(config.backup, Some(true));
+        assert_eq!(config.workers, None);
+
+        assert!(parse("max_sise = 50\n").is_err());
+    }
+}
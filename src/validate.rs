@@ -0,0 +1,545 @@
+//! `validate` and `lint`: report problems in Sysmon configurations.
+//!
+//! `validate` runs the structural checks from `sysmon_cli::validation`;
+//! `lint` adds the warnings from `sysmon_cli::lint`. Both accept XML or JSON
+//! (optionally gzipped) and report as text, JSON or SARIF. Errors make the
+//! command fail; warnings do not. `--xsd` adds validation against an XML
+//! Schema, see [`crate::xsd`], `lint --policy` adds an organization's own
+//! rules, see [`crate::policy`], and `lint --secrets` flags values that
+//! should not be published, see [`crate::secrets`].
+
+use crate::{
+    batch, compression, convert, encoding, error_report, file_list, format, io_guard, policy,
+    sarif, secrets, walk, xsd,
+};
+use clap::{Args, ValueEnum};
+use log::info;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use sysmon_cli::lint;
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::schema::Platform;
+use sysmon_cli::validation::{self, Issue, Severity};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Sarif,
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    /// Configuration files to check
+    #[arg(required_unless_present_any = ["files_from", "batch"])]
+    pub files: Vec<PathBuf>,
+
+    /// Also check every XML and JSON config under this directory, in parallel, and end the
+    /// report with a summary
+    #[arg(long, value_name = "DIR")]
+    pub batch: Option<PathBuf>,
+
+    /// Threads for --batch [default: one per CPU]
+    #[arg(long, requires = "batch")]
+    pub workers: Option<usize>,
+
+    /// Skip files under --batch matching this pattern, as conversion's --ignore does (can be
+    /// specified multiple times)
+    #[arg(long, value_name = "PATTERN", requires = "batch")]
+    pub ignore: Vec<String>,
+
+    /// Also check the files listed in this file (one per line, `-` for stdin); entries that
+    /// do not exist or are not XML or JSON are ignored
+    #[arg(long, value_name = "PATH")]
+    pub files_from: Option<PathBuf>,
+
+    /// Report format
+    #[arg(
+        long,
+        value_enum,
+        default_value = "text",
+        env = "SYSMON_HELPER_REPORT_FORMAT"
+    )]
+    pub format: ReportFormat,
+
+    /// Platform the configs are for: windows or linux (Sysmon for Linux)
+    #[arg(long, default_value = "windows", env = "SYSMON_HELPER_PLATFORM")]
+    pub platform: Platform,
+
+    /// Also validate against an XML Schema: an XSD file, or `embedded` for the schema
+    /// generated for each config's schemaversion
+    #[arg(long, value_name = "PATH|embedded")]
+    pub xsd: Option<String>,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    #[command(flatten)]
+    pub report: ReportArgs,
+}
+
+#[derive(Args)]
+pub struct LintArgs {
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// Also check the organization-specific rules in this TOML or YAML policy pack; repeat
+    /// to combine packs
+    #[arg(long, value_name = "PATH", env = "SYSMON_HELPER_POLICY")]
+    pub policy: Vec<PathBuf>,
+
+    /// Apply the policy rules for this profile, e.g. workstation or server
+    #[arg(long, requires = "policy")]
+    pub profile: Option<String>,
+
+    /// Also flag values and comments that look like credentials, API keys, private IP
+    /// addresses or email addresses
+    #[arg(long, env = "SYSMON_HELPER_SECRETS")]
+    pub secrets: bool,
+}
+
+/// What [`check_file`] runs besides the structural checks.
+#[derive(Default)]
+struct Checks<'a> {
+    lint: bool,
+    policy: Option<&'a policy::Policy>,
+    xsd: Option<&'a xsd::Source>,
+    secrets: bool,
+}
+
+/// An issue in a particular file.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub file: PathBuf,
+    /// Best-effort line of the issue's element; only known for XML input.
+    pub line: Option<usize>,
+    #[serde(flatten)]
+    pub issue: Issue,
+}
+
+pub fn run_validate(args: &ValidateArgs) -> Result<(), ConversionError> {
+    report(&args.report, None)
+}
+
+pub fn run_lint(args: &LintArgs) -> Result<(), ConversionError> {
+    let policy = if args.policy.is_empty() {
+        None
+    } else {
+        Some(policy::Policy::load(&args.policy, args.profile.as_deref())?)
+    };
+    let checks = Checks {
+        lint: true,
+        policy: policy.as_ref(),
+        secrets: args.secrets,
+        ..Checks::default()
+    };
+    report(&args.report, Some(checks))
+}
+
+/// Runs the structural checks, and with `lint` the `lint` command's too.
+fn report(args: &ReportArgs, lint: Option<Checks>) -> Result<(), ConversionError> {
+    let is_config =
+        |path: &PathBuf| format::Format::from_extension(&compression::strip_gz(path)).is_some();
+    let mut files = args.files.clone();
+    if let Some(source) = &args.files_from {
+        files.extend(
+            file_list::read(source)?
+                .into_iter()
+                .filter(|path| path.is_file())
+                .filter(is_config),
+        );
+    }
+    if let Some(dir) = &args.batch {
+        if !dir.is_dir() {
+            return Err(ConversionError::InvalidFile(format!(
+                "{} is not a directory",
+                dir.display()
+            )));
+        }
+        files.extend(
+            walk::files(dir, usize::MAX, false)
+                .into_iter()
+                .filter(is_config)
+                .filter(|path| {
+                    let relative = path.strip_prefix(dir).unwrap_or(path);
+                    !args.ignore.iter().any(|p| batch::is_ignored(p, relative))
+                }),
+        );
+    }
+    let files = dedupe(files);
+
+    let xsd = args.xsd.as_deref().map(xsd::Source::load).transpose()?;
+    let checks = Checks {
+        xsd: xsd.as_ref(),
+        ..lint.unwrap_or_default()
+    };
+    let findings: Vec<Finding> = if args.batch.is_some() {
+        check_files(&files, args.platform, &checks, args.workers)
+    } else {
+        files
+            .iter()
+            .flat_map(|file| check_file(file, args.platform, &checks))
+            .collect()
+    };
+    let summary = args
+        .batch
+        .is_some()
+        .then(|| Summary::new(files.len(), &findings));
+
+    let rendered = match (args.format, &summary) {
+        (ReportFormat::Text, _) => {
+            let mut text: String = findings
+                .iter()
+                .map(|f| format!("{}\n", describe(f)))
+                .collect();
+            if let Some(summary) = &summary {
+                text.push_str(&summary.describe());
+            }
+            text
+        }
+        (ReportFormat::Json, Some(summary)) => {
+            to_json(&serde_json::to_string_pretty(&BatchReport {
+                summary,
+                findings: &findings,
+            }))?
+        }
+        (ReportFormat::Json, None) => to_json(&serde_json::to_string_pretty(&findings))?,
+        (ReportFormat::Sarif, _) => {
+            to_json(&serde_json::to_string_pretty(&sarif::to_sarif(&findings)))?
+        }
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, rendered)?,
+        None => print!("{}", rendered),
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|f| f.issue.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+    info!(
+        "{} file(s) checked: {} error(s), {} warning(s)",
+        files.len(),
+        errors,
+        warnings
+    );
+    if errors > 0 {
+        return Err(ConversionError::ValidationError(format!(
+            "{} error(s) found",
+            errors
+        )));
+    }
+    Ok(())
+}
+
+/// `files` without the repeats of a file named more than once, by its
+/// canonical path, keeping the first.
+fn dedupe(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = std::collections::HashSet::new();
+    files
+        .into_iter()
+        .filter(|path| seen.insert(std::fs::canonicalize(path).unwrap_or_else(|_| path.clone())))
+        .collect()
+}
+
+/// Totals for a `--batch` report.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub files: usize,
+    pub files_with_errors: usize,
+    pub files_with_warnings: usize,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl Summary {
+    fn new(files: usize, findings: &[Finding]) -> Summary {
+        let files_with = |severity: Severity| {
+            let mut paths: Vec<&Path> = findings
+                .iter()
+                .filter(|f| f.issue.severity == severity)
+                .map(|f| f.file.as_path())
+                .collect();
+            paths.dedup();
+            paths.len()
+        };
+        let errors = findings
+            .iter()
+            .filter(|f| f.issue.severity == Severity::Error)
+            .count();
+        Summary {
+            files,
+            files_with_errors: files_with(Severity::Error),
+            files_with_warnings: files_with(Severity::Warning),
+            errors,
+            warnings: findings.len() - errors,
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "\n{} file(s) checked: {} valid, {} with errors, {} with warnings; {} error(s), {} warning(s)\n",
+            self.files,
+            self.files - self.files_with_errors,
+            self.files_with_errors,
+            self.files_with_warnings,
+            self.errors,
+            self.warnings
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct BatchReport<'a> {
+    summary: &'a Summary,
+    findings: &'a [Finding],
+}
+
+/// [`check_file`] for many files at once, on `workers` threads; findings
+/// keep the order of `files`.
+fn check_files(
+    files: &[PathBuf],
+    platform: Platform,
+    checks: &Checks,
+    workers: Option<usize>,
+) -> Vec<Finding> {
+    let results = Mutex::new(Vec::with_capacity(files.len()));
+    batch::in_parallel(files, workers, |index, file| {
+        let findings = check_file(file, platform, checks);
+        results.lock().unwrap().push((index, findings));
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().flat_map(|(_, f)| f).collect()
+}
+
+fn check_file(path: &Path, platform: Platform, checks: &Checks) -> Vec<Finding> {
+    let finding = |line, issue| Finding {
+        file: path.to_path_buf(),
+        line,
+        issue,
+    };
+
+    let (text, is_xml) = match load(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            return vec![finding(
+                None,
+                Issue::error("parse-error", "Sysmon", e.to_string()),
+            )]
+        }
+    };
+    let mut issues = match SysmonConfig::from_xml_str(&text) {
+        Ok(config) => {
+            let mut issues = validation::validate_for(&config, platform);
+            if checks.lint {
+                issues.extend(lint::lint(&config));
+            }
+            if let Some(policy) = checks.policy {
+                issues.extend(policy.check(&config));
+            }
+            issues
+        }
+        // Bad attributes are reported one by one, at their elements.
+        Err(e) => {
+            let issues = validation::attribute_issues(&text);
+            if issues.is_empty() {
+                let message = e.to_string();
+                let line = error_report::position(&message)
+                    .filter(|_| is_xml)
+                    .map(|(line, _)| line);
+                return vec![finding(
+                    line,
+                    Issue::error("parse-error", "Sysmon", message),
+                )];
+            }
+            issues
+        }
+    };
+    if let Some(xsd) = checks.xsd {
+        issues.extend(xsd.issues(&text));
+    }
+    let mut findings: Vec<Finding> = issues
+        .into_iter()
+        .map(|issue| {
+            let line = if is_xml {
+                line_of(&text, &issue.location)
+            } else {
+                None
+            };
+            finding(line, issue)
+        })
+        .collect();
+    if checks.secrets {
+        for (line, issue) in secrets::scan(&text) {
+            findings.push(finding(is_xml.then_some(line), issue));
+        }
+    }
+    findings
+}
+
+/// Reads a config as XML text. The flag is false when the file was JSON and
+/// line numbers therefore do not refer to it.
+pub fn load(path: &Path) -> Result<(String, bool), ConversionError> {
+    parse(path, &compression::read(path)?)
+}
+
+/// [`load`] for contents read elsewhere; `path` names them in errors and
+/// hints at their format.
+pub fn parse(path: &Path, bytes: &[u8]) -> Result<(String, bool), ConversionError> {
+    let text = encoding::decode(bytes)
+        .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", path.display(), e)))?;
+    match format::detect(&compression::strip_gz(path), text.as_bytes())? {
+        format::Format::Xml => Ok((text, true)),
+        format::Format::Json => Ok((convert::json_str_to_xml(&text)?, false)),
+    }
+}
+
+fn describe(finding: &Finding) -> String {
+    let issue = &finding.issue;
+    let severity = match issue.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let file = match finding.line {
+        Some(line) => format!("{}:{}", finding.file.display(), line),
+        None => finding.file.display().to_string(),
+    };
+    format!(
+        "{}: {}[{}] {}: {}",
+        file, severity, issue.rule, issue.location, issue.message
+    )
+}
+
+fn to_json(result: &Result<String, serde_json::Error>) -> Result<String, ConversionError> {
+    match result {
+        Ok(text) => Ok(format!("{}\n", text)),
+        Err(e) => Err(ConversionError::Other(e.to_string())),
+    }
+}
+
+/// Best-effort line number for a location such as
+/// `RuleGroup[2]/DnsQuery/QueryName`: each segment is looked up after the
+/// previous one. Bare event filters are reported under a `RuleGroup` that
+/// does not exist in the text, so a missing `RuleGroup` is skipped.
+pub fn line_of(text: &str, location: &str) -> Option<usize> {
+    let mut pos = 0;
+    for segment in location.split('/') {
+        let (name, nth) = match segment.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            Some((name, nth)) => (name, nth.parse().ok()?),
+            None => (segment, 1),
+        };
+        match find_element(&text[pos..], name, nth) {
+            Some(at) => pos += at,
+            None if name == "RuleGroup" => {}
+            None => return None,
+        }
+    }
+    Some(text[..pos].matches('\n').count() + 1)
+}
+
+/// Byte offset of the `nth` (1-based) start tag named `name`.
+fn find_element(text: &str, name: &str, nth: usize) -> Option<usize> {
+    let open = format!("<{}", name);
+    text.match_indices(&open)
+        .filter(|(at, _)| {
+            text[at + open.len()..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_whitespace() || c == '>' || c == '/')
+        })
+        .nth(nth.checked_sub(1)?)
+        .map(|(at, _)| at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_of_follows_location_segments() {
+        let text = "<Sysmon schemaversion=\"4.90\">\n<EventFiltering>\n<RuleGroup name=\"a\">\n<DnsQuery onmatch=\"exclude\"/>\n</RuleGroup>\n<RuleGroup name=\"b\">\n<DnsQuery onmatch=\"include\">\n<QueryName condition=\"is\">x</QueryName>\n</DnsQuery>\n</RuleGroup>\n</EventFiltering>\n</Sysmon>\n";
+        assert_eq!(line_of(text, "Sysmon"), Some(1));
+        assert_eq!(line_of(text, "RuleGroup[2]"), Some(6));
+        assert_eq!(line_of(text, "RuleGroup[2]/DnsQuery/QueryName"), Some(8));
+        assert_eq!(line_of(text, "RuleGroup[3]/DnsQuery"), Some(4));
+        assert_eq!(line_of(text, "RuleGroup[1]/ProcessCreate"), None);
+    }
+
+    #[test]
+    fn test_check_file_reports_lint_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.xml");
+        std::fs::write(
+            &file,
+            "<Sysmon schemaversion=\"4.90\">\n<EventFiltering>\n<RuleGroup name=\"\" groupRelation=\"or\">\n<NetworkConnect onmatch=\"exclude\"/>\n</RuleGroup>\n</EventFiltering>\n</Sysmon>\n",
+        )
+        .unwrap();
+
+        assert!(check_file(&file, Platform::Windows, &Checks::default()).is_empty());
+        let lint = Checks {
+            lint: true,
+            ..Checks::default()
+        };
+        let findings = check_file(&file, Platform::Windows, &lint);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].issue.rule, "catch-all-exclude");
+        assert_eq!(findings[0].line, Some(4));
+    }
+
+    #[test]
+    fn test_dedupe_drops_repeats_of_the_same_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("rules")).unwrap();
+        let (a, b) = (dir.path().join("a.xml"), dir.path().join("rules/b.xml"));
+        std::fs::write(&a, "").unwrap();
+        std::fs::write(&b, "").unwrap();
+
+        let again = dir.path().join("rules/../a.xml");
+        assert_eq!(dedupe(vec![b.clone(), a.clone(), again, b.clone()]), [b, a]);
+    }
+
+    #[test]
+    fn test_check_files_keeps_order_and_summarizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = Vec::new();
+        for (name, text) in [
+            ("a.xml", "<Sysmon schemaversion=\"4.90\"><EventFiltering/></Sysmon>"),
+            ("b.xml", "<Sysmon"),
+            ("c.xml", "<Sysmon schemaversion=\"4.90\"><EventFiltering><RuleGroup name=\"\" groupRelation=\"or\"><NetworkConnect onmatch=\"exclude\"/></RuleGroup></EventFiltering></Sysmon>"),
+            ("d.xml", "not xml"),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            files.push(path);
+        }
+        let lint = Checks {
+            lint: true,
+            ..Checks::default()
+        };
+
+        let findings = check_files(&files, Platform::Windows, &lint, Some(3));
+        let names: Vec<_> = findings
+            .iter()
+            .map(|f| f.file.file_name().unwrap())
+            .collect();
+        assert_eq!(names, ["b.xml", "c.xml", "d.xml"]);
+        assert_eq!(
+            Summary::new(files.len(), &findings),
+            Summary {
+                files: 4,
+                files_with_errors: 2,
+                files_with_warnings: 1,
+                errors: 2,
+                warnings: 1,
+            }
+        );
+    }
+}
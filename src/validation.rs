@@ -0,0 +1,701 @@
+//! Structural checks on a typed configuration.
+//!
+//! Parsing into [`SysmonConfig`] already rejects unknown conditions,
+//! `onmatch` values and relations, but stops at the first one;
+//! [`attribute_issues`] reports each bad `condition`, `onmatch` and
+//! `groupRelation` at its element instead. The other checks cover what the types cannot:
+//! the schema version itself, event types the declared schema does not
+//! support, fields an event type does not have and, for Sysmon for Linux,
+//! event types that only exist on Windows. Global options are checked for
+//! values Sysmon rejects, and for combinations with the rules that cannot
+//! work, such as matching an IMPHASH that `HashAlgorithms` never computes.
+
+use crate::model::{
+    Condition, ConfigOption, FieldCondition, Filter, GroupRelation, OnMatch, SysmonConfig,
+};
+use crate::schema::{self, Platform, Version};
+use serde::Serialize;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+/// Rule ids reported by [`validate`], with a one-line description each.
+pub const RULES: &[(&str, &str)] = &[
+    (
+        "parse-error",
+        "The document is not a readable Sysmon configuration",
+    ),
+    ("schema-version", "schemaversion is missing or malformed"),
+    (
+        "onmatch",
+        "Event filter onmatch is missing or not include/exclude",
+    ),
+    ("group-relation", "groupRelation is not and/or"),
+    ("unknown-condition", "Condition is not one Sysmon supports"),
+    (
+        "unknown-event",
+        "Event type is not part of any Sysmon schema",
+    ),
+    (
+        "unsupported-event",
+        "Event type is newer than the declared schemaversion",
+    ),
+    ("unknown-field", "Event type has no field of this name"),
+    (
+        "unsupported-condition",
+        "Condition is newer than the declared schemaversion",
+    ),
+    (
+        "platform-event",
+        "Event type is not available on the target platform",
+    ),
+    ("option-value", "Global option has a value Sysmon rejects"),
+    (
+        "option-conflict",
+        "Global option has no effect, or keeps rules from matching",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    /// Stable id of the check that raised this, e.g. `unknown-event`.
+    pub rule: &'static str,
+    pub severity: Severity,
+    /// Where the problem is, e.g. `RuleGroup[2]/DnsQuery`.
+    pub location: String,
+    pub message: String,
+}
+
+impl Issue {
+    pub fn error(
+        rule: &'static str,
+        location: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Issue {
+            rule,
+            severity: Severity::Error,
+            location: location.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(
+        rule: &'static str,
+        location: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Issue {
+            severity: Severity::Warning,
+            ..Issue::error(rule, location, message)
+        }
+    }
+}
+
+/// Validates a configuration for Sysmon on Windows.
+pub fn validate(config: &SysmonConfig) -> Vec<Issue> {
+    validate_for(config, Platform::Windows)
+}
+
+pub fn validate_for(config: &SysmonConfig, platform: Platform) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let declared = match config.schema_version.parse::<Version>() {
+        Ok(version) => Some(version),
+        Err(e) => {
+            issues.push(Issue::error("schema-version", "Sysmon", e));
+            None
+        }
+    };
+
+    for (index, group) in config.rule_groups.iter().enumerate() {
+        for event in &group.events {
+            let location = format!("RuleGroup[{}]/{}", index + 1, event.event);
+            let Some(kind) = schema::event_type(&event.event) else {
+                issues.push(Issue::error(
+                    "unknown-event",
+                    location,
+                    format!("Unknown event type {}", event.event),
+                ));
+                continue;
+            };
+            if declared.is_some_and(|v| v < kind.min_schema) {
+                issues.push(Issue::error(
+                    "unsupported-event",
+                    &location,
+                    format!(
+                        "{} requires schema {} but the config declares {}",
+                        kind.name, kind.min_schema, config.schema_version
+                    ),
+                ));
+            } else if !platform.supports(kind.name) {
+                issues.push(Issue::error(
+                    "platform-event",
+                    &location,
+                    format!("{} is not available on {}", kind.name, platform),
+                ));
+            }
+
+            let fields = event.filters.iter().flat_map(|filter| match filter {
+                Filter::Field(field) => std::slice::from_ref(field),
+                Filter::Rule(rule) => rule.fields.as_slice(),
+            });
+            for field in fields {
+                let field_location = format!("{}/{}", location, field.field);
+                if !kind.has_field(&field.field) {
+                    issues.push(Issue::error(
+                        "unknown-field",
+                        &field_location,
+                        format!("{} has no field {}", kind.name, field.field),
+                    ));
+                }
+                let needed = schema::condition_min_schema(field.condition);
+                if declared.is_some_and(|v| v < needed) {
+                    let release = schema::min_sysmon_for(needed)
+                        .map(|r| format!(" (Sysmon {})", r))
+                        .unwrap_or_default();
+                    issues.push(Issue::error(
+                        "unsupported-condition",
+                        field_location,
+                        format!(
+                            "Condition \"{}\" requires schema {}{} but the config declares {}",
+                            field.condition, needed, release, config.schema_version
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    validate_options(config, &mut issues);
+    issues
+}
+
+fn validate_options(config: &SysmonConfig, issues: &mut Vec<Issue>) {
+    for option in &config.options {
+        let location = format!("Sysmon/{}", option.name);
+        let value = option.value.trim();
+        let problem = match option.name.as_str() {
+            "HashAlgorithms" => hash_algorithms(value)
+                .err()
+                .map(|bad| format!("Unknown hash algorithm {:?}", bad)),
+            "ArchiveDirectory" if !is_directory_name(value) => Some(format!(
+                "ArchiveDirectory must be a directory name such as Sysmon, not {:?}",
+                value
+            )),
+            "CheckRevocation" if !value.is_empty() && !is_bool(value) => Some(format!(
+                "CheckRevocation takes no value or true/false, not {:?}",
+                value
+            )),
+            "DnsLookup" if !is_bool(value) => {
+                Some(format!("DnsLookup must be true or false, not {:?}", value))
+            }
+            "CopyOnDeleteExtensions" => extensions_problem(value),
+            _ => None,
+        };
+        if let Some(message) = problem {
+            issues.push(Issue::error("option-value", location, message));
+        }
+    }
+
+    let option =
+        |name: &str| -> Option<&ConfigOption> { config.options.iter().find(|o| o.name == name) };
+    let has_event = |name: &str| config.events().any(|e| e.event == name);
+
+    let selected = match option("HashAlgorithms") {
+        Some(option) => hash_algorithms(option.value.trim()).unwrap_or_default(),
+        None => vec!["SHA1".to_string()],
+    };
+    if selected.len() > 1 && selected.iter().any(|a| a == "*") {
+        issues.push(Issue::warning(
+            "option-conflict",
+            "Sysmon/HashAlgorithms",
+            "* already selects every algorithm; the others are redundant",
+        ));
+    }
+    if !selected.is_empty() && !selected.iter().any(|a| a == "*") {
+        for (_, field) in conditions(config) {
+            if !matches!(field.field.as_str(), "Hashes" | "Hash") {
+                continue;
+            }
+            if let Some(algorithm) = tested_algorithm(field) {
+                if !selected.contains(&algorithm) {
+                    issues.push(Issue::warning(
+                        "option-conflict",
+                        "Sysmon/HashAlgorithms",
+                        format!(
+                            "A {} rule tests {} but HashAlgorithms is {}",
+                            field.field,
+                            algorithm,
+                            selected.join(",")
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    let archives = schema::ARCHIVING_EVENT_TYPES.iter().any(|e| has_event(e));
+    if option("ArchiveDirectory").is_some() && !archives {
+        issues.push(Issue::warning(
+            "option-conflict",
+            "Sysmon/ArchiveDirectory",
+            "ArchiveDirectory is set but no FileDelete or ClipboardChange filter archives anything",
+        ));
+    }
+    if option("CopyOnDeleteExtensions").is_some() && !has_event("FileDelete") {
+        issues.push(Issue::warning(
+            "option-conflict",
+            "Sysmon/CopyOnDeleteExtensions",
+            "CopyOnDeleteExtensions is set but there is no FileDelete filter",
+        ));
+    }
+
+    let no_lookup =
+        option("DnsLookup").is_some_and(|o| o.value.trim().eq_ignore_ascii_case("false"));
+    let hostname_rules = conditions(config).any(|(event, field)| {
+        event == "NetworkConnect"
+            && matches!(
+                field.field.as_str(),
+                "SourceHostname" | "DestinationHostname"
+            )
+    });
+    if no_lookup && hostname_rules {
+        issues.push(Issue::warning(
+            "option-conflict",
+            "Sysmon/DnsLookup",
+            "DnsLookup is false, so NetworkConnect hostname rules never match",
+        ));
+    }
+}
+
+/// Every field condition with its event type, including those in `<Rule>`s.
+fn conditions(config: &SysmonConfig) -> impl Iterator<Item = (&str, &FieldCondition)> {
+    config.events().flat_map(|event| {
+        event.filters.iter().flat_map(move |filter| {
+            let fields = match filter {
+                Filter::Field(field) => std::slice::from_ref(field),
+                Filter::Rule(rule) => rule.fields.as_slice(),
+            };
+            fields
+                .iter()
+                .map(move |field| (event.event.as_str(), field))
+        })
+    })
+}
+
+/// Upper-cased algorithms of a `HashAlgorithms` value, or the first
+/// unknown one.
+fn hash_algorithms(value: &str) -> Result<Vec<String>, String> {
+    let algorithms: Vec<String> = value
+        .split(',')
+        .map(|a| a.trim().to_ascii_uppercase())
+        .collect();
+    match algorithms
+        .iter()
+        .find(|a| *a != "*" && !schema::HASH_ALGORITHMS.contains(&a.as_str()))
+    {
+        Some(bad) => Err(bad.clone()),
+        None => Ok(algorithms),
+    }
+}
+
+/// The algorithm a `Hashes` condition such as `IMPHASH=1A2B...` tests.
+fn tested_algorithm(field: &FieldCondition) -> Option<String> {
+    let (algorithm, _) = field.value.split_once('=')?;
+    let algorithm = algorithm.trim().to_ascii_uppercase();
+    schema::HASH_ALGORITHMS
+        .contains(&algorithm.as_str())
+        .then_some(algorithm)
+}
+
+fn is_bool(value: &str) -> bool {
+    value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+}
+
+/// Sysmon creates `ArchiveDirectory` at the root of each volume, so it is a
+/// single directory name rather than a path.
+fn is_directory_name(value: &str) -> bool {
+    !value.is_empty()
+        && value != "."
+        && value != ".."
+        && !value.contains(['\\', '/', ':', '*', '?', '"', '<', '>', '|'])
+}
+
+fn extensions_problem(value: &str) -> Option<String> {
+    if value.is_empty() {
+        return Some("CopyOnDeleteExtensions lists no extensions".to_string());
+    }
+    value
+        .split(',')
+        .map(str::trim)
+        .find(|ext| {
+            ext.trim_start_matches('.').is_empty() || ext.contains(['\\', '/', '*', '?', ' '])
+        })
+        .map(|ext| format!("Invalid extension {:?} in CopyOnDeleteExtensions", ext))
+}
+
+/// Parses `xml` and validates it. Input that does not parse yields its
+/// [`attribute_issues`] or, when there are none, a single issue for the
+/// whole document.
+pub fn validate_str(xml: &str) -> Vec<Issue> {
+    match SysmonConfig::from_xml_str(xml) {
+        Ok(config) => validate(&config),
+        Err(e) => {
+            let issues = attribute_issues(xml);
+            if issues.is_empty() {
+                vec![Issue::error("parse-error", "Sysmon", e.to_string())]
+            } else {
+                issues
+            }
+        }
+    }
+}
+
+/// [`validate_str`] as a JSON array of issues, the form the wasm and C
+/// bindings return.
+pub fn validate_str_to_json(xml: &str) -> Result<String, ConversionError> {
+    serde_json::to_string(&validate_str(xml)).map_err(|e| ConversionError::Other(e.to_string()))
+}
+
+/// Checks every `condition`, `onmatch` and `groupRelation` in well-formed XML, with the
+/// locations [`validate`] would use for the elements.
+pub fn attribute_issues(xml: &str) -> Vec<Issue> {
+    let Ok(root) = Element::parse(xml.as_bytes()) else {
+        return Vec::new();
+    };
+    let mut issues = Vec::new();
+    let mut groups = 0;
+    for filtering in children(&root).filter(|e| e.name == "EventFiltering") {
+        // Bare event filters are numbered as one group after the others.
+        let bare = groups
+            + children(filtering)
+                .filter(|e| e.name == "RuleGroup")
+                .count()
+            + 1;
+        let mut has_bare = false;
+        for entry in children(filtering) {
+            if entry.name != "RuleGroup" {
+                has_bare = true;
+                check_event(
+                    entry,
+                    &format!("RuleGroup[{}]/{}", bare, entry.name),
+                    &mut issues,
+                );
+                continue;
+            }
+            groups += 1;
+            let location = format!("RuleGroup[{}]", groups);
+            check_relation(entry, &location, &mut issues);
+            for event in children(entry) {
+                check_event(event, &format!("{}/{}", location, event.name), &mut issues);
+            }
+        }
+        if has_bare {
+            groups += 1;
+        }
+    }
+    issues
+}
+
+fn children(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(|node| match node {
+        XMLNode::Element(child) => Some(child),
+        _ => None,
+    })
+}
+
+fn check_event(event: &Element, location: &str, issues: &mut Vec<Issue>) {
+    match event.attributes.get("onmatch") {
+        None => issues.push(Issue::error(
+            "onmatch",
+            location,
+            format!("{} has no onmatch; use include or exclude", event.name),
+        )),
+        Some(value) if value.parse::<OnMatch>().is_err() => issues.push(Issue::error(
+            "onmatch",
+            location,
+            format!("onmatch=\"{}\" must be include or exclude", value),
+        )),
+        Some(_) => {}
+    }
+    for child in children(event) {
+        if child.name != "Rule" {
+            check_condition(child, location, issues);
+            continue;
+        }
+        check_relation(child, &format!("{}/Rule", location), issues);
+        for field in children(child) {
+            check_condition(field, location, issues);
+        }
+    }
+}
+
+/// Conditions other tools accept but Sysmon has no equivalent for: it only
+/// matches literal text, so patterns have to be rewritten.
+const PATTERN_CONDITIONS: &[&str] = &["regex", "regexp", "matches", "match", "like", "wildcard"];
+
+fn check_condition(field: &Element, location: &str, issues: &mut Vec<Issue>) {
+    let Some(value) = field.attributes.get("condition") else {
+        return;
+    };
+    if value.parse::<Condition>().is_ok() {
+        return;
+    }
+    let hint = if PATTERN_CONDITIONS.contains(&value.trim().to_ascii_lowercase().as_str()) {
+        "; Sysmon has no pattern matching, use contains, begin with, end with or their \
+         any/all forms"
+    } else {
+        ""
+    };
+    issues.push(Issue::error(
+        "unknown-condition",
+        format!("{}/{}", location, field.name),
+        format!("Unknown condition \"{}\"{}", value, hint),
+    ));
+}
+
+fn check_relation(element: &Element, location: &str, issues: &mut Vec<Issue>) {
+    if let Some(value) = element.attributes.get("groupRelation") {
+        if value.parse::<GroupRelation>().is_err() {
+            issues.push(Issue::error(
+                "group-relation",
+                location,
+                format!("groupRelation=\"{}\" must be and or or", value),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_unsupported_and_unknown_events() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.22"><EventFiltering>
+                <RuleGroup name="" groupRelation="or"><DnsQuery onmatch="exclude"/></RuleGroup>
+                <RuleGroup name="" groupRelation="or"><FileDelete onmatch="include"/><Bogus onmatch="include"/></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let issues = validate(&config);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].location, "RuleGroup[2]/FileDelete");
+        assert_eq!(issues[0].rule, "unsupported-event");
+        assert_eq!(issues[1].message, "Unknown event type Bogus");
+    }
+
+    #[test]
+    fn test_linux_rejects_windows_only_events() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.81"><EventFiltering>
+                <RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include"/></RuleGroup>
+                <RuleGroup name="" groupRelation="or"><RegistryEvent onmatch="include"/></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        assert!(validate(&config).is_empty());
+        let issues = validate_for(&config, Platform::Linux);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "platform-event");
+        assert_eq!(issues[0].message, "RegistryEvent is not available on linux");
+    }
+
+    #[test]
+    fn test_reports_fields_the_event_does_not_have() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+                <RuleGroup name="" groupRelation="or"><ProcessTampering onmatch="include">
+                    <Type condition="is">Image is replaced</Type>
+                    <Rule groupRelation="and">
+                        <Image condition="end with">\explorer.exe</Image>
+                        <TargetFilename condition="contains">x</TargetFilename>
+                    </Rule>
+                </ProcessTampering></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let issues = validate(&config);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].rule, "unknown-field");
+        assert_eq!(
+            issues[0].location,
+            "RuleGroup[1]/ProcessTampering/TargetFilename"
+        );
+        assert_eq!(
+            issues[0].message,
+            "ProcessTampering has no field TargetFilename"
+        );
+    }
+
+    #[test]
+    fn test_reports_malformed_global_options() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90">
+                <HashAlgorithms>sha256,crc32</HashAlgorithms>
+                <ArchiveDirectory>C:\Sysmon</ArchiveDirectory>
+                <CheckRevocation/>
+                <DnsLookup>no</DnsLookup>
+                <CopyOnDeleteExtensions>exe,,dll</CopyOnDeleteExtensions>
+                <EventFiltering>
+                <RuleGroup name="" groupRelation="or"><FileDelete onmatch="include"/></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let issues = validate(&config);
+        let found: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|i| (i.rule, i.location.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("option-value", "Sysmon/HashAlgorithms"),
+                ("option-value", "Sysmon/ArchiveDirectory"),
+                ("option-value", "Sysmon/DnsLookup"),
+                ("option-value", "Sysmon/CopyOnDeleteExtensions"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_conflicting_global_options() {
+        let config = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90">
+                <HashAlgorithms>sha256</HashAlgorithms>
+                <ArchiveDirectory>Sysmon</ArchiveDirectory>
+                <DnsLookup>False</DnsLookup>
+                <EventFiltering>
+                <RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include">
+                    <Hashes condition="contains">IMPHASH=0123456789ABCDEF</Hashes>
+                </ProcessCreate></RuleGroup>
+                <RuleGroup name="" groupRelation="or"><NetworkConnect onmatch="exclude">
+                    <DestinationHostname condition="end with">.example.com</DestinationHostname>
+                </NetworkConnect></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+
+        let issues = validate(&config);
+        assert!(issues.iter().all(|i| i.severity == Severity::Warning));
+        let messages: Vec<&str> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "A Hashes rule tests IMPHASH but HashAlgorithms is SHA256",
+                "ArchiveDirectory is set but no FileDelete or ClipboardChange filter archives anything",
+                "DnsLookup is false, so NetworkConnect hostname rules never match",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_each_bad_attribute_at_its_element() {
+        let issues = validate_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+                <RuleGroup name="" groupRelation="xor"><ProcessCreate onmatch="include"/></RuleGroup>
+                <RuleGroup name="" groupRelation="or">
+                    <DnsQuery onmatch="exlcude">
+                        <Rule groupRelation="nand"><QueryName condition="is">a</QueryName></Rule>
+                    </DnsQuery>
+                </RuleGroup>
+                <NetworkConnect/>
+            </EventFiltering></Sysmon>"#,
+        );
+
+        let found: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|i| (i.rule, i.location.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("group-relation", "RuleGroup[1]"),
+                ("onmatch", "RuleGroup[2]/DnsQuery"),
+                ("group-relation", "RuleGroup[2]/DnsQuery/Rule"),
+                ("onmatch", "RuleGroup[3]/NetworkConnect"),
+            ]
+        );
+        assert_eq!(
+            issues[1].message,
+            "onmatch=\"exlcude\" must be include or exclude"
+        );
+        assert_eq!(validate_str("<Sysmon>")[0].rule, "parse-error");
+    }
+
+    #[test]
+    fn test_reports_conditions_newer_than_the_schema() {
+        let config = |schema: &str| {
+            SysmonConfig::from_xml_str(&format!(
+                r#"<Sysmon schemaversion="{}"><EventFiltering>
+                    <RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include">
+                        <Image condition="end with">\cmd.exe</Image>
+                        <CommandLine condition="contains any">-enc;-e </CommandLine>
+                        <ParentImage condition="not end with">\explorer.exe</ParentImage>
+                    </ProcessCreate></RuleGroup>
+                </EventFiltering></Sysmon>"#,
+                schema
+            ))
+            .unwrap()
+        };
+
+        let issues = validate(&config("4.21"));
+        let found: Vec<&str> = issues.iter().map(|i| i.location.as_str()).collect();
+        assert_eq!(
+            found,
+            vec![
+                "RuleGroup[1]/ProcessCreate/CommandLine",
+                "RuleGroup[1]/ProcessCreate/ParentImage",
+            ]
+        );
+        assert_eq!(
+            issues[0].message,
+            "Condition \"contains any\" requires schema 4.22 (Sysmon 10.40) but the config declares 4.21"
+        );
+        assert_eq!(validate(&config("4.30")).len(), 1);
+        assert!(validate(&config("4.50")).is_empty());
+    }
+
+    #[test]
+    fn test_reports_unknown_conditions_at_their_fields() {
+        let issues = validate_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+                <RuleGroup name="" groupRelation="or"><ProcessCreate onmatch="include">
+                    <Image condition="ends with">\cmd.exe</Image>
+                    <Rule groupRelation="and">
+                        <CommandLine condition="regex">-e(nc)?\s</CommandLine>
+                    </Rule>
+                </ProcessCreate></RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        );
+
+        let found: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|i| (i.location.as_str(), i.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "RuleGroup[1]/ProcessCreate/Image",
+                    "Unknown condition \"ends with\""
+                ),
+                (
+                    "RuleGroup[1]/ProcessCreate/CommandLine",
+                    "Unknown condition \"regex\"; Sysmon has no pattern matching, use contains, \
+                     begin with, end with or their any/all forms"
+                ),
+            ]
+        );
+    }
+}
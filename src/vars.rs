@@ -0,0 +1,235 @@
+//! `${VAR}` placeholders, resolved when a config is converted or merged.
+//!
+//! Sites whose configs differ in a handful of values (a domain, a proxy
+//! address) can share one template:
+//!
+//! ```xml
+//! <DestinationHostname condition="end with">${CORP_DOMAIN}</DestinationHostname>
+//! ```
+//!
+//! A name is looked up in `--var KEY=VALUE` first, then in the TOML file
+//! given with `--vars-file` (`CORP_DOMAIN = "corp.example.com"`), then in
+//! the environment. Values are escaped for the document they land in, so
+//! `&` becomes `&amp;` in XML. A placeholder that resolves nowhere is an
+//! error rather than being left in the output; `$${VAR}` writes a literal
+//! `${VAR}`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use sysmon_cli::format::Format;
+use sysmon_cli::preprocess::Preprocessor;
+use sysmon_json::error::ConversionError;
+
+/// Where placeholder values come from.
+#[derive(Debug, Clone, Default)]
+pub struct Vars {
+    values: BTreeMap<String, String>,
+    /// Values read from the environment so far, shared between clones.
+    from_env: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl Vars {
+    /// Values from `--var` pairs, which win over those in `file`.
+    pub fn load(pairs: &[String], file: Option<&Path>) -> Result<Vars, ConversionError> {
+        let mut values = match file {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| ConversionError::io_error(path, e))?;
+                toml::from_str::<BTreeMap<String, String>>(&text).map_err(|e| {
+                    ConversionError::ParserError(format!("{}: {}", path.display(), e))
+                })?
+            }
+            None => BTreeMap::new(),
+        };
+        for pair in pairs {
+            let (name, value) = parse_pair(pair).map_err(ConversionError::ValidationError)?;
+            values.insert(name.to_string(), value.to_string());
+        }
+        Ok(Vars {
+            values,
+            ..Vars::default()
+        })
+    }
+
+    /// The values given with `--var` and in the vars file, and those
+    /// placeholders have been resolved to from the environment so far: what
+    /// a manifest records.
+    pub fn values(&self) -> BTreeMap<String, String> {
+        let mut values = self.from_env.lock().unwrap().clone();
+        values.extend(self.values.clone());
+        values
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.values.get(name) {
+            return Some(value.clone());
+        }
+        let value = std::env::var(name).ok()?;
+        self.from_env
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), value.clone());
+        Some(value)
+    }
+
+    /// `text` with every placeholder replaced by its value, escaped for
+    /// `format`; an error names the placeholders that did not resolve.
+    pub fn substitute(&self, text: &str, format: Format) -> Result<String, String> {
+        let mut out = String::with_capacity(text.len());
+        let mut unresolved: Vec<&str> = Vec::new();
+        let mut rest = text;
+        while let Some(at) = rest.find("${") {
+            let Some(name) = placeholder(&rest[at + 2..]) else {
+                out.push_str(&rest[..at + 2]);
+                rest = &rest[at + 2..];
+                continue;
+            };
+            let end = at + 2 + name.len() + 1;
+            if rest[..at].ends_with('$') {
+                out.push_str(&rest[..at - 1]);
+                out.push_str(&rest[at..end]);
+            } else {
+                out.push_str(&rest[..at]);
+                match self.lookup(name) {
+                    Some(value) => out.push_str(&escape(&value, format)),
+                    None if !unresolved.contains(&name) => unresolved.push(name),
+                    None => {}
+                }
+            }
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+
+        if !unresolved.is_empty() {
+            let names: Vec<String> = unresolved.iter().map(|n| format!("${{{}}}", n)).collect();
+            return Err(format!(
+                "Unresolved placeholder(s) {}: set them with --var, --vars-file or the environment",
+                names.join(", ")
+            ));
+        }
+        Ok(out)
+    }
+}
+
+impl Preprocessor for Vars {
+    fn name(&self) -> &str {
+        "vars"
+    }
+
+    fn description(&self) -> &str {
+        "Substitute ${VAR} placeholders from --var, --vars-file and the environment"
+    }
+
+    fn process(&self, text: &str, format: Format) -> Result<String, ConversionError> {
+        self.substitute(text, format)
+            .map_err(ConversionError::ValidationError)
+    }
+}
+
+/// Parses a `--var KEY=VALUE` argument.
+pub fn parse_pair(pair: &str) -> Result<(&str, &str), String> {
+    match pair.split_once('=') {
+        Some((name, value)) if is_name(name) => Ok((name, value)),
+        _ => Err(format!(
+            "Invalid --var {:?}: expected KEY=VALUE with a name of letters, digits and _",
+            pair
+        )),
+    }
+}
+
+/// The name of the placeholder `text` starts with (after `${`), if it is
+/// one: a name and a closing brace.
+fn placeholder(text: &str) -> Option<&str> {
+    let (name, _) = text.split_once('}')?;
+    is_name(name).then_some(name)
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn escape(value: &str, format: Format) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match (format, c) {
+            (Format::Xml, '&') => out.push_str("&amp;"),
+            (Format::Xml, '<') => out.push_str("&lt;"),
+            (Format::Xml, '>') => out.push_str("&gt;"),
+            (Format::Xml, '"') => out.push_str("&quot;"),
+            (Format::Xml, '\'') => out.push_str("&apos;"),
+            (Format::Json, '"') => out.push_str("\\\""),
+            (Format::Json, '\\') => out.push_str("\\\\"),
+            (Format::Json, c) if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            (_, c) => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[&str]) -> Vars {
+        let pairs: Vec<String> = pairs.iter().map(|p| p.to_string()).collect();
+        Vars::load(&pairs, None).unwrap()
+    }
+
+    #[test]
+    fn test_substitute_escapes_values_for_the_format() {
+        let vars = vars(&["CORP_DOMAIN=corp.example.com", "TEAM=R&D \"blue\""]);
+        assert_eq!(
+            vars.substitute(
+                "<QueryName condition=\"end with\">${CORP_DOMAIN}</QueryName><!-- ${TEAM} $${KEEP} ${ not} -->",
+                Format::Xml
+            )
+            .unwrap(),
+            "<QueryName condition=\"end with\">corp.example.com</QueryName><!-- R&amp;D &quot;blue&quot; ${KEEP} ${ not} -->"
+        );
+        assert_eq!(
+            vars.substitute(r#"{"value": "${TEAM}"}"#, Format::Json)
+                .unwrap(),
+            r#"{"value": "R&D \"blue\""}"#
+        );
+    }
+
+    #[test]
+    fn test_substitute_reports_every_unresolved_placeholder() {
+        let err = vars(&[])
+            .substitute("${SYSMON_HELPER_TEST_UNSET_A} ${SYSMON_HELPER_TEST_UNSET_B} ${SYSMON_HELPER_TEST_UNSET_A}", Format::Xml)
+            .unwrap_err();
+        assert!(
+            err.contains("${SYSMON_HELPER_TEST_UNSET_A}, ${SYSMON_HELPER_TEST_UNSET_B}:"),
+            "{}",
+            err
+        );
+
+        assert!(parse_pair("PROXY_IP=10.0.0.1").is_ok());
+        assert!(parse_pair("1X=y").is_err());
+        assert!(parse_pair("novalue").is_err());
+    }
+
+    #[test]
+    fn test_values_include_those_read_from_the_environment() {
+        let path = std::env::var("PATH").unwrap();
+        let vars = vars(&["TEAM=blue"]);
+        let shared = vars.clone();
+        assert_eq!(vars.values().len(), 1);
+        assert_eq!(
+            shared.substitute("${TEAM} ${PATH}", Format::Xml).unwrap(),
+            format!("blue {}", path)
+        );
+        assert_eq!(
+            vars.values(),
+            BTreeMap::from([
+                ("PATH".to_string(), path),
+                ("TEAM".to_string(), "blue".to_string())
+            ])
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! `visualize`: draw a config's structure as a graph.
+//!
+//! RuleGroups point to the event filters they hold and those to their
+//! rules, with include filters and rules in green and exclude ones in red.
+//! The graph comes out as Graphviz DOT (`dot -Tsvg`) or as a Mermaid
+//! flowchart for embedding in Markdown. `--summary` replaces each filter's
+//! rules with their count, which keeps large merged configs readable.
+
+use crate::{io_guard, validate};
+use clap::{Args, ValueEnum};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use sysmon_cli::model::{FieldCondition, Filter, OnMatch, SysmonConfig};
+use sysmon_json::error::ConversionError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+#[derive(Args)]
+pub struct VisualizeArgs {
+    /// Configuration to draw (XML or JSON)
+    pub config: PathBuf,
+
+    /// Graph format
+    #[arg(long, value_enum, default_value = "dot")]
+    pub format: GraphFormat,
+
+    /// Show the number of rules in each event filter instead of the rules
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Write the graph to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+pub fn run(args: &VisualizeArgs) -> Result<(), ConversionError> {
+    let config = SysmonConfig::from_xml_str(&validate::load(&args.config)?.0)?;
+    let graph = Graph::build(&config, args.summary);
+    let text = match args.format {
+        GraphFormat::Dot => graph.to_dot(),
+        GraphFormat::Mermaid => graph.to_mermaid(),
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Group,
+    Event(OnMatch),
+    Rule(OnMatch),
+}
+
+struct Node {
+    id: String,
+    kind: Kind,
+    lines: Vec<String>,
+}
+
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<(String, String)>,
+}
+
+impl Graph {
+    fn build(config: &SysmonConfig, summary: bool) -> Graph {
+        let mut graph = Graph {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        for (g, group) in config.rule_groups.iter().enumerate() {
+            let group_id = format!("g{}", g + 1);
+            let mut lines = vec![format!("RuleGroup[{}]", g + 1)];
+            lines.extend(group.name.clone().filter(|n| !n.is_empty()));
+            if let Some(relation) = group.group_relation {
+                lines.push(format!("groupRelation={}", relation.as_str()));
+            }
+            graph.node(None, &group_id, Kind::Group, lines);
+
+            for (e, event) in group.events.iter().enumerate() {
+                let event_id = format!("{}e{}", group_id, e + 1);
+                let lines = vec![
+                    event.event.clone(),
+                    format!("onmatch={}", event.onmatch.as_str()),
+                ];
+                graph.node(
+                    Some(&group_id),
+                    &event_id,
+                    Kind::Event(event.onmatch),
+                    lines,
+                );
+                if summary {
+                    let id = format!("{}n", event_id);
+                    let lines = vec![format!("{} rule(s)", event.filters.len())];
+                    graph.node(Some(&event_id), &id, Kind::Rule(event.onmatch), lines);
+                    continue;
+                }
+                for (r, filter) in event.filters.iter().enumerate() {
+                    let id = format!("{}r{}", event_id, r + 1);
+                    let lines = match filter {
+                        Filter::Field(field) => {
+                            let mut lines: Vec<String> = field.name.iter().cloned().collect();
+                            lines.push(condition(field));
+                            lines
+                        }
+                        Filter::Rule(rule) => {
+                            let mut lines: Vec<String> = rule.name.iter().cloned().collect();
+                            lines.push(format!("Rule {}", rule.group_relation.as_str()));
+                            lines.extend(rule.fields.iter().map(condition));
+                            lines
+                        }
+                    };
+                    graph.node(Some(&event_id), &id, Kind::Rule(event.onmatch), lines);
+                }
+            }
+        }
+        graph
+    }
+
+    fn node(&mut self, parent: Option<&str>, id: &str, kind: Kind, lines: Vec<String>) {
+        if let Some(parent) = parent {
+            self.edges.push((parent.to_string(), id.to_string()));
+        }
+        self.nodes.push(Node {
+            id: id.to_string(),
+            kind,
+            lines,
+        });
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph sysmon {\n    rankdir=LR;\n");
+        out.push_str("    node [fontname=\"Helvetica\", fontsize=10];\n");
+        for node in &self.nodes {
+            let label = node
+                .lines
+                .iter()
+                .map(|line| line.replace('\\', "\\\\").replace('"', "\\\""))
+                .collect::<Vec<_>>()
+                .join("\\n");
+            let style = match node.kind {
+                Kind::Group => "shape=folder".to_string(),
+                Kind::Event(onmatch) => {
+                    format!("shape=box, style=\"filled,bold\", {}", dot_colors(onmatch))
+                }
+                Kind::Rule(onmatch) => format!("shape=note, style=filled, {}", dot_colors(onmatch)),
+            };
+            let _ = writeln!(out, "    {} [label=\"{}\", {}];", node.id, label, style);
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(out, "    {} -> {};", from, to);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        out.push_str("    classDef include fill:#d4edda,stroke:#2e7d32\n");
+        out.push_str("    classDef exclude fill:#f8d7da,stroke:#c62828\n");
+        for node in &self.nodes {
+            let label = node
+                .lines
+                .iter()
+                .map(|line| mermaid_escape(line))
+                .collect::<Vec<_>>()
+                .join("<br>");
+            let class = match node.kind {
+                Kind::Group => String::new(),
+                Kind::Event(onmatch) | Kind::Rule(onmatch) => format!(":::{}", onmatch.as_str()),
+            };
+            let _ = writeln!(out, "    {}[\"{}\"]{}", node.id, label, class);
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(out, "    {} --> {}", from, to);
+        }
+        out
+    }
+}
+
+fn dot_colors(onmatch: OnMatch) -> &'static str {
+    match onmatch {
+        OnMatch::Include => "color=\"#2e7d32\", fillcolor=\"#d4edda\"",
+        OnMatch::Exclude => "color=\"#c62828\", fillcolor=\"#f8d7da\"",
+    }
+}
+
+/// Mermaid labels are HTML: entities stand in for the characters that
+/// would end the label or start a tag.
+fn mermaid_escape(text: &str) -> String {
+    text.replace('&', "#amp;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+fn condition(field: &FieldCondition) -> String {
+    format!(
+        "{} {} \"{}\"",
+        field.field,
+        field.condition.as_str(),
+        field.value
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SysmonConfig {
+        SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering>
+            <RuleGroup name="process" groupRelation="or">
+            <ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001" condition="end with">\powershell.exe</Image>
+            </ProcessCreate>
+            <ProcessCreate onmatch="exclude">
+            <Rule groupRelation="and">
+            <Image condition="is">C:\Windows\System32\svchost.exe</Image>
+            <ParentImage condition="is">C:\Windows\System32\services.exe</ParentImage>
+            </Rule>
+            </ProcessCreate>
+            </RuleGroup>
+            </EventFiltering></Sysmon>"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dot_links_groups_events_and_rules() {
+        let dot = Graph::build(&config(), false).to_dot();
+        assert!(dot.starts_with("digraph sysmon {\n"));
+        assert!(
+            dot.contains(r#"g1 [label="RuleGroup[1]\nprocess\ngroupRelation=or", shape=folder];"#)
+        );
+        assert!(dot.contains(r#"g1e1r1 [label="technique_id=T1059.001\nImage end with \"\\powershell.exe\"", shape=note"#));
+        assert!(dot.contains(r##"g1e2 [label="ProcessCreate\nonmatch=exclude", shape=box, style="filled,bold", color="#c62828""##));
+        assert!(dot.contains("g1 -> g1e2;\n"));
+        assert!(dot.contains("g1e2 -> g1e2r1;\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_mermaid_escapes_labels_and_tags_onmatch() {
+        let mermaid = Graph::build(&config(), false).to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains(
+            r#"g1e1r1["technique_id=T1059.001<br>Image end with #quot;\powershell.exe#quot;"]:::include"#
+        ));
+        assert!(mermaid.contains(":::exclude\n"));
+        assert!(mermaid.contains("g1e1 --> g1e1r1\n"));
+    }
+
+    #[test]
+    fn test_summary_counts_rules() {
+        let dot = Graph::build(&config(), true).to_dot();
+        assert!(dot.contains(r#"g1e2n [label="1 rule(s)""#));
+        assert!(!dot.contains("g1e1r1"));
+    }
+}
@@ -0,0 +1,352 @@
+//! `estimate-volume`: events per day a candidate config would log, from
+//! Sysmon event logs sampled on real hosts.
+//!
+//! Every Sysmon event in the sample `.evtx` files (see [`crate::evtx`]) is
+//! replayed against the candidate config and, when given, the current one
+//! (see [`crate::replay`]). The counts are scaled from the time the samples
+//! span, from the first event to the last or `--hours`, to a day, and by
+//! `--hosts` for a fleet. Without `--current` the candidate is compared
+//! with the samples as captured.
+//!
+//! Samples can only show what the config they were captured under logged:
+//! events it filtered out are not there to replay, so a candidate that logs
+//! more than that config is underestimated. Capture with a config that logs
+//! everything for the truest numbers.
+
+use crate::evtx::{self, Event};
+use crate::{io_guard, replay, validate, walk};
+use clap::{Args, ValueEnum};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use sysmon_cli::model::SysmonConfig;
+use sysmon_cli::schema;
+use sysmon_json::error::ConversionError;
+
+/// Events Sysmon writes whatever its config, which no event type names.
+const UNFILTERED_EVENTS: &[(u32, &str)] = &[
+    (4, "ServiceStateChange"),
+    (16, "ServiceConfigurationChange"),
+    (255, "Error"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VolumeFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+pub struct EstimateVolumeArgs {
+    /// Candidate configuration (XML or JSON)
+    #[arg(short, long)]
+    pub config: PathBuf,
+
+    /// Sysmon event log (.evtx), or a directory searched recursively for them
+    #[arg(long)]
+    pub evtx: PathBuf,
+
+    /// Configuration deployed now, to compare with [default: the samples as captured]
+    #[arg(long)]
+    pub current: Option<PathBuf>,
+
+    /// Hours of activity the samples cover [default: from their first event to their last]
+    #[arg(long)]
+    pub hours: Option<f64>,
+
+    /// Number of hosts to project for
+    #[arg(long, default_value = "1")]
+    pub hosts: u32,
+
+    /// Report format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: VolumeFormat,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EventVolume {
+    pub id: u32,
+    pub event: String,
+    /// Events in the samples.
+    pub sampled: usize,
+    pub current_per_day: f64,
+    pub candidate_per_day: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VolumeEstimate {
+    pub sampled: usize,
+    pub hours: f64,
+    pub hosts: u32,
+    /// Whether `current_per_day` is for a current config rather than the
+    /// samples as captured.
+    pub has_current: bool,
+    /// By event ID.
+    pub events: Vec<EventVolume>,
+    pub current_per_day: f64,
+    pub candidate_per_day: f64,
+}
+
+pub fn run(args: &EstimateVolumeArgs) -> Result<(), ConversionError> {
+    let load = |path: &Path| SysmonConfig::from_xml_str(&validate::load(path)?.0);
+    let candidate = load(&args.config)?;
+    let current = args.current.as_deref().map(load).transpose()?;
+
+    let mut events = Vec::new();
+    let mut unreadable = 0;
+    for file in files(&args.evtx) {
+        let log = evtx::read(&file)?;
+        unreadable += log.unreadable;
+        events.extend(
+            log.events
+                .into_iter()
+                .filter(|e| e.provider == evtx::SYSMON_PROVIDER),
+        );
+    }
+    if unreadable > 0 {
+        warn!(
+            "Skipped {} event record(s) that could not be read",
+            unreadable
+        );
+    }
+    if events.is_empty() {
+        return Err(ConversionError::ValidationError(format!(
+            "No Sysmon events in {}",
+            args.evtx.display()
+        )));
+    }
+    let hours = match args.hours {
+        Some(hours) if hours > 0.0 => hours,
+        Some(_) => {
+            return Err(ConversionError::ValidationError(
+                "--hours must be more than 0".to_string(),
+            ))
+        }
+        None => span_hours(&events).ok_or_else(|| {
+            ConversionError::ValidationError(
+                "The samples span no time; pass --hours to say how long they cover".to_string(),
+            )
+        })?,
+    };
+    info!(
+        "Replaying {} Sysmon event(s) covering {:.1} hour(s)",
+        events.len(),
+        hours
+    );
+
+    let estimate = estimate(&events, current.as_ref(), &candidate, hours, args.hosts);
+    let text = match args.format {
+        VolumeFormat::Text => render(&estimate),
+        VolumeFormat::Json => serde_json::to_string_pretty(&estimate)
+            .map(|json| json + "\n")
+            .map_err(|e| ConversionError::Other(e.to_string()))?,
+    };
+    match &args.output {
+        Some(path) => io_guard::write(path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+/// `path` if it is a file, or the `.evtx` files under it.
+fn files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    walk::files(path, usize::MAX, false)
+        .into_iter()
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("evtx"))
+        })
+        .collect()
+}
+
+/// Hours from the first event to the last; `None` when that is no time.
+fn span_hours(events: &[Event]) -> Option<f64> {
+    let first = events.iter().map(|e| e.time).min()?;
+    let last = events.iter().map(|e| e.time).max()?;
+    (last > first).then(|| (last - first) as f64 / 3600.0)
+}
+
+/// Projects the events each config logs to a day on `hosts` hosts.
+pub fn estimate(
+    events: &[Event],
+    current: Option<&SysmonConfig>,
+    candidate: &SysmonConfig,
+    hours: f64,
+    hosts: u32,
+) -> VolumeEstimate {
+    // Per event ID: sampled, logged by the current config, by the candidate.
+    let mut counts: BTreeMap<u32, (usize, usize, usize)> = BTreeMap::new();
+    for event in events {
+        let fields = |name: &str| event.get(name);
+        let count = counts.entry(event.id).or_default();
+        count.0 += 1;
+        count.1 += usize::from(current.is_none_or(|c| replay::is_logged(c, event.id, fields)));
+        count.2 += usize::from(replay::is_logged(candidate, event.id, fields));
+    }
+
+    let per_day = |count: usize| count as f64 * 24.0 / hours * f64::from(hosts);
+    let events: Vec<EventVolume> = counts
+        .into_iter()
+        .map(|(id, (sampled, current, candidate))| EventVolume {
+            id,
+            event: event_name(id),
+            sampled,
+            current_per_day: per_day(current),
+            candidate_per_day: per_day(candidate),
+        })
+        .collect();
+    VolumeEstimate {
+        sampled: events.iter().map(|e| e.sampled).sum(),
+        hours,
+        hosts,
+        has_current: current.is_some(),
+        current_per_day: events.iter().map(|e| e.current_per_day).sum(),
+        candidate_per_day: events.iter().map(|e| e.candidate_per_day).sum(),
+        events,
+    }
+}
+
+fn event_name(id: u32) -> String {
+    schema::event_type_for_id(id)
+        .map(|e| e.name)
+        .or_else(|| {
+            UNFILTERED_EVENTS
+                .iter()
+                .find(|(i, _)| *i == id)
+                .map(|(_, name)| *name)
+        })
+        .map_or_else(|| format!("Event {}", id), str::to_string)
+}
+
+fn render(estimate: &VolumeEstimate) -> String {
+    let mut out = format!(
+        "{} Sysmon event(s) sampled over {:.1} hour(s), projected to events/day on {} host(s):\n\n",
+        estimate.sampled, estimate.hours, estimate.hosts
+    );
+    let baseline = if estimate.has_current {
+        "Current"
+    } else {
+        "Captured"
+    };
+    let _ = writeln!(
+        out,
+        "  {:>3}  {:<24} {:>12} {:>12}  Change",
+        "ID", "Event", baseline, "Candidate"
+    );
+    let mut row = |id: &str, event: &str, current: f64, candidate: f64| {
+        let _ = writeln!(
+            out,
+            "  {:>3}  {:<24} {:>12.0} {:>12.0}  {}",
+            id,
+            event,
+            current,
+            candidate,
+            change(current, candidate)
+        );
+    };
+    for event in &estimate.events {
+        row(
+            &event.id.to_string(),
+            &event.event,
+            event.current_per_day,
+            event.candidate_per_day,
+        );
+    }
+    row(
+        "",
+        "Total",
+        estimate.current_per_day,
+        estimate.candidate_per_day,
+    );
+    out
+}
+
+/// `+12.5%`, `-100.0%`, `new` when nothing was logged before, or `-`.
+fn change(current: f64, candidate: f64) -> String {
+    match (current > 0.0, candidate > 0.0) {
+        (true, _) => format!("{:+.1}%", (candidate - current) / current * 100.0),
+        (false, true) => "new".to_string(),
+        (false, false) => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: u32, time: i64, data: &[(&str, &str)]) -> Event {
+        Event {
+            record_id: 0,
+            time,
+            provider: evtx::SYSMON_PROVIDER.to_string(),
+            id,
+            data: data
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_estimate_projects_logged_events_per_day() {
+        let current = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup name="" groupRelation="or">
+<NetworkConnect onmatch="exclude"/>
+</RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        let candidate = SysmonConfig::from_xml_str(
+            r#"<Sysmon schemaversion="4.90"><EventFiltering><RuleGroup name="" groupRelation="or">
+<NetworkConnect onmatch="exclude"><DestinationPort condition="is">443</DestinationPort></NetworkConnect>
+</RuleGroup></EventFiltering></Sysmon>"#,
+        )
+        .unwrap();
+        let events = [
+            event(3, 0, &[("DestinationPort", "443")]),
+            event(3, 1800, &[("DestinationPort", "443")]),
+            event(3, 3600, &[("DestinationPort", "445")]),
+            event(16, 3600, &[]),
+        ];
+
+        let hours = span_hours(&events).unwrap();
+        assert_eq!(hours, 1.0);
+        let estimate = estimate(&events, Some(&current), &candidate, hours, 10);
+        assert_eq!(
+            estimate.events,
+            [
+                EventVolume {
+                    id: 3,
+                    event: "NetworkConnect".to_string(),
+                    sampled: 3,
+                    current_per_day: 720.0,
+                    candidate_per_day: 240.0,
+                },
+                EventVolume {
+                    id: 16,
+                    event: "ServiceConfigurationChange".to_string(),
+                    sampled: 1,
+                    current_per_day: 240.0,
+                    candidate_per_day: 240.0,
+                },
+            ]
+        );
+        let text = render(&estimate);
+        assert!(
+            text.contains("    3  NetworkConnect                    720          240  -66.7%\n")
+        );
+        assert!(
+            text.contains("       Total                             960          480  -50.0%\n")
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Directory walks for batch conversion and merging.
+//!
+//! Symbolic links are skipped unless `--follow-symlinks` is given. When they
+//! are followed, a file reachable through several paths (a shared module
+//! symlinked into more than one directory, say) is returned once, under the
+//! first of its paths in sorted order, and links that point back at one of
+//! their own ancestors are reported instead of being walked forever.
+
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Regular files under `root`, at most `max_depth` levels down, sorted.
+pub fn files(root: &Path, max_depth: usize, follow_symlinks: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root)
+        .max_depth(max_depth)
+        .follow_links(follow_symlinks)
+    {
+        match entry {
+            Ok(entry) if entry.file_type().is_file() => files.push(entry.into_path()),
+            Ok(entry) if entry.path_is_symlink() => {
+                debug!(
+                    "Skipping symlink {} (use --follow-symlinks to include it)",
+                    entry.path().display()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => match (e.path(), e.loop_ancestor()) {
+                (Some(path), Some(ancestor)) => warn!(
+                    "Skipping symlink loop at {}: it points back to {}",
+                    path.display(),
+                    ancestor.display()
+                ),
+                _ => warn!("Skipping unreadable entry: {}", e),
+            },
+        }
+    }
+    files.sort();
+
+    if follow_symlinks {
+        dedupe(files)
+    } else {
+        files
+    }
+}
+
+/// Drops paths that resolve to a file already listed.
+fn dedupe(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut unique = Vec::new();
+    for path in files {
+        let target = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        match seen.get(&target) {
+            Some(first) => info!(
+                "Skipping {}: same file as {}",
+                path.display(),
+                first.display()
+            ),
+            None => {
+                seen.insert(target, path.clone());
+                unique.push(path);
+            }
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dedupe_keeps_first_path_to_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = dir.path().join("a_shared.xml");
+        std::fs::write(&shared, "<Sysmon/>").unwrap();
+        let link = dir.path().join("b_link.xml");
+        std::os::unix::fs::symlink(&shared, &link).unwrap();
+        let other = dir.path().join("c_other.xml");
+        std::fs::write(&other, "<Sysmon/>").unwrap();
+
+        assert_eq!(
+            dedupe(vec![shared.clone(), link, other.clone()]),
+            vec![shared, other]
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! WebAssembly bindings for browser-based tooling.
+//!
+//! Enabled with the `wasm` feature on `wasm32` targets. Build the module
+//! with `--no-default-features --features wasm --crate-type cdylib` and
+//! run `wasm-bindgen --target web` over the result. Everything here is
+//! string-in, string-out and never touches a filesystem. The functions
+//! share their serializers with the C ABI ([`convert_xml_str_to_json`],
+//! [`validation::validate_str_to_json`]), so both produce the typed
+//! model's JSON, not the `sysmon_json` layout the CLI writes.
+
+use crate::validation;
+use crate::{convert_json_str_to_xml, convert_xml_str_to_json};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(js_name = xmlToJson)]
+pub fn xml_to_json(xml: &str) -> Result<String, JsError> {
+    convert_xml_str_to_json(xml).map_err(to_js)
+}
+
+#[wasm_bindgen(js_name = jsonToXml)]
+pub fn json_to_xml(json: &str) -> Result<String, JsError> {
+    convert_json_str_to_xml(json).map_err(to_js)
+}
+
+/// Validates XML and returns a JSON array of `{location, message}` issues.
+#[wasm_bindgen]
+pub fn validate(xml: &str) -> Result<String, JsError> {
+    validation::validate_str_to_json(xml).map_err(to_js)
+}
+
+fn to_js(e: impl std::fmt::Display) -> JsError {
+    JsError::new(&e.to_string())
+}
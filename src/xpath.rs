@@ -0,0 +1,458 @@
+//! The XPath subset behind `query --xpath`.
+//!
+//! Supported: absolute and relative location paths with `/` and `//`, name
+//! tests and `*`, `.`, and predicates `[n]`, `[last()]`, `[@attr]`,
+//! `[@attr="v"]`, `[@attr!="v"]`, `[child="v"]`, `[text()="v"]` and their
+//! `contains(..., "v")` forms. The last step may also be `@attr` or
+//! `text()` to select strings instead of elements. Axes other than child
+//! and descendant, and functions other than those above, are rejected
+//! rather than guessed at.
+
+use crate::document::child_elements;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Path {
+    absolute: bool,
+    steps: Vec<Step>,
+    select: Option<Select>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Step {
+    /// Preceded by `//`: search all descendants of the context.
+    descendants: bool,
+    /// Element name, or `None` for `*`; `.` is a step with `self_only`.
+    name: Option<String>,
+    self_only: bool,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Predicate {
+    Position(usize),
+    Last,
+    Has(Operand),
+    Compare(Operand, Test, String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Operand {
+    Attribute(String),
+    Child(String),
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Test {
+    Eq,
+    Ne,
+    Contains,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Select {
+    Attribute(String),
+    Text,
+}
+
+/// What a path selects.
+#[derive(Debug, PartialEq)]
+pub enum Node<'a> {
+    Element(&'a Element),
+    Value(String),
+}
+
+pub fn parse(input: &str) -> Result<Path, ConversionError> {
+    let mut parser = Parser { input, pos: 0 };
+    parser.path()
+}
+
+impl Path {
+    /// Evaluates the path against a document whose root element is `root`.
+    /// Relative paths start at the root element, absolute ones above it.
+    pub fn select<'a>(&self, root: &'a Element) -> Vec<Node<'a>> {
+        let mut context: Vec<&'a Element> = vec![root];
+        let mut steps = self.steps.iter();
+
+        // An absolute path starts above the root element, so its first step
+        // tests the root itself and, after `//`, everything below it.
+        if self.absolute {
+            if let Some(step) = steps.next() {
+                let roots = [root].into_iter().filter(|e| step.matches(e)).collect();
+                let mut first = filter(step, roots);
+                if step.descendants {
+                    for element in apply(step, &context) {
+                        if !first.iter().any(|seen| std::ptr::eq(*seen, element)) {
+                            first.push(element);
+                        }
+                    }
+                }
+                context = first;
+            }
+        }
+        for step in steps {
+            context = apply(step, &context);
+        }
+
+        match &self.select {
+            None => context.into_iter().map(Node::Element).collect(),
+            Some(Select::Attribute(name)) => context
+                .into_iter()
+                .filter_map(|e| e.attributes.get(name).cloned())
+                .map(Node::Value)
+                .collect(),
+            Some(Select::Text) => context
+                .into_iter()
+                .map(text_of)
+                .filter(|text| !text.is_empty())
+                .map(Node::Value)
+                .collect(),
+        }
+    }
+}
+
+impl Step {
+    fn matches(&self, element: &Element) -> bool {
+        self.name.as_ref().is_none_or(|name| *name == element.name)
+    }
+}
+
+fn apply<'a>(step: &Step, context: &[&'a Element]) -> Vec<&'a Element> {
+    let mut parents = Vec::new();
+    for element in context {
+        if step.descendants {
+            descendants_or_self(element, &mut parents);
+        } else {
+            parents.push(element);
+        }
+    }
+
+    let mut result: Vec<&'a Element> = Vec::new();
+    for parent in parents {
+        let candidates = if step.self_only {
+            vec![parent]
+        } else {
+            child_elements(parent).filter(|e| step.matches(e)).collect()
+        };
+        for element in filter(step, candidates) {
+            if !result.iter().any(|seen| std::ptr::eq(*seen, element)) {
+                result.push(element);
+            }
+        }
+    }
+    result
+}
+
+/// Applies the step's predicates, in order, to one parent's candidates.
+fn filter<'a>(step: &Step, mut nodes: Vec<&'a Element>) -> Vec<&'a Element> {
+    for predicate in &step.predicates {
+        let count = nodes.len();
+        nodes = nodes
+            .into_iter()
+            .enumerate()
+            .filter(|(i, element)| match predicate {
+                Predicate::Position(n) => i + 1 == *n,
+                Predicate::Last => i + 1 == count,
+                Predicate::Has(operand) => !values(element, operand).is_empty(),
+                Predicate::Compare(operand, test, wanted) => {
+                    values(element, operand).iter().any(|value| match test {
+                        Test::Eq => value == wanted,
+                        Test::Ne => value != wanted,
+                        Test::Contains => value.contains(wanted.as_str()),
+                    })
+                }
+            })
+            .map(|(_, element)| element)
+            .collect();
+    }
+    nodes
+}
+
+fn values(element: &Element, operand: &Operand) -> Vec<String> {
+    match operand {
+        Operand::Attribute(name) => element.attributes.get(name).cloned().into_iter().collect(),
+        Operand::Child(name) => child_elements(element)
+            .filter(|e| e.name == *name)
+            .map(text_of)
+            .collect(),
+        Operand::Text => vec![text_of(element)],
+    }
+}
+
+fn descendants_or_self<'a>(element: &'a Element, out: &mut Vec<&'a Element>) {
+    out.push(element);
+    for child in child_elements(element) {
+        descendants_or_self(child, out);
+    }
+}
+
+fn text_of(element: &Element) -> String {
+    element
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            XMLNode::Text(text) | XMLNode::CData(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn path(&mut self) -> Result<Path, ConversionError> {
+        let absolute = self.input.trim_start().starts_with('/');
+        let mut steps = Vec::new();
+        let mut select = None;
+        let mut descendants = false;
+        if !absolute {
+            self.skip_space();
+            steps.push(self.step(false)?);
+        }
+        loop {
+            self.skip_space();
+            if self.pos == self.input.len() {
+                break;
+            }
+            if select.is_some() {
+                return Err(self.error("@attr and text() must be the last step"));
+            }
+            if self.eat("//") {
+                descendants = true;
+            } else if self.eat("/") {
+                descendants = false;
+            } else {
+                return Err(self.error("expected / or //"));
+            }
+            self.skip_space();
+            if self.eat("@") {
+                select = Some(Select::Attribute(self.name()?));
+            } else if self.eat("text()") {
+                select = Some(Select::Text);
+            } else {
+                steps.push(self.step(descendants)?);
+                continue;
+            }
+            if descendants {
+                // `//@attr` means the attribute on any descendant.
+                steps.push(Step {
+                    descendants: true,
+                    name: None,
+                    self_only: true,
+                    predicates: Vec::new(),
+                });
+            }
+        }
+        if steps.is_empty() && select.is_none() {
+            return Err(self.error("expected a location step"));
+        }
+        Ok(Path {
+            absolute,
+            steps,
+            select,
+        })
+    }
+
+    fn step(&mut self, descendants: bool) -> Result<Step, ConversionError> {
+        let (name, self_only) = if self.eat("*") {
+            (None, false)
+        } else if self.eat("..") {
+            return Err(self.error("the parent step .. is not supported"));
+        } else if self.eat(".") {
+            (None, true)
+        } else {
+            let name = self.name()?;
+            if self.input[self.pos..].starts_with("::") {
+                return Err(self.error(format!("the {} axis is not supported", name)));
+            }
+            (Some(name), false)
+        };
+        let mut predicates = Vec::new();
+        self.skip_space();
+        while self.eat("[") {
+            predicates.push(self.predicate()?);
+            self.skip_space();
+            if !self.eat("]") {
+                return Err(self.error("expected ]"));
+            }
+            self.skip_space();
+        }
+        Ok(Step {
+            descendants,
+            name,
+            self_only,
+            predicates,
+        })
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, ConversionError> {
+        self.skip_space();
+        let digits = self.input[self.pos..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .count();
+        if digits > 0 {
+            let n = self.input[self.pos..self.pos + digits]
+                .parse()
+                .map_err(|_| self.error("position out of range"))?;
+            self.pos += digits;
+            return match n {
+                0 => Err(self.error("positions start at 1")),
+                n => Ok(Predicate::Position(n)),
+            };
+        }
+        if self.eat("last()") {
+            return Ok(Predicate::Last);
+        }
+        if self.eat("contains(") {
+            let operand = self.operand()?;
+            self.skip_space();
+            if !self.eat(",") {
+                return Err(self.error("expected ,"));
+            }
+            let value = self.literal()?;
+            self.skip_space();
+            if !self.eat(")") {
+                return Err(self.error("expected )"));
+            }
+            return Ok(Predicate::Compare(operand, Test::Contains, value));
+        }
+
+        let operand = self.operand()?;
+        self.skip_space();
+        let test = if self.eat("!=") {
+            Test::Ne
+        } else if self.eat("=") {
+            Test::Eq
+        } else {
+            return Ok(Predicate::Has(operand));
+        };
+        Ok(Predicate::Compare(operand, test, self.literal()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, ConversionError> {
+        self.skip_space();
+        if self.eat("@") {
+            Ok(Operand::Attribute(self.name()?))
+        } else if self.eat("text()") {
+            Ok(Operand::Text)
+        } else {
+            Ok(Operand::Child(self.name()?))
+        }
+    }
+
+    fn literal(&mut self) -> Result<String, ConversionError> {
+        self.skip_space();
+        let quote = match self.input[self.pos..].chars().next() {
+            Some(q @ ('"' | '\'')) => q,
+            _ => return Err(self.error("expected a quoted string")),
+        };
+        let rest = &self.input[self.pos + 1..];
+        let end = rest
+            .find(quote)
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += end + 2;
+        Ok(rest[..end].to_string())
+    }
+
+    fn name(&mut self) -> Result<String, ConversionError> {
+        let len = self.input[self.pos..]
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+            .unwrap_or(self.input.len() - self.pos);
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        let name = self.input[self.pos..self.pos + len].to_string();
+        self.pos += len;
+        Ok(name)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.input[self.pos..].starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn skip_space(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> ConversionError {
+        ConversionError::ValidationError(format!(
+            "Invalid XPath at column {}: {}",
+            self.pos + 1,
+            message
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"<Sysmon schemaversion="4.90">
+        <HashAlgorithms>sha256</HashAlgorithms>
+        <EventFiltering>
+            <RuleGroup name="a" groupRelation="or">
+                <ProcessCreate onmatch="include">
+                    <Image condition="end with">\cmd.exe</Image>
+                    <Image condition="end with">\powershell.exe</Image>
+                </ProcessCreate>
+            </RuleGroup>
+            <RuleGroup name="b" groupRelation="and">
+                <ProcessCreate onmatch="exclude"/>
+            </RuleGroup>
+        </EventFiltering>
+    </Sysmon>"#;
+
+    fn select(path: &str) -> Vec<String> {
+        let root = Element::parse(CONFIG.as_bytes()).unwrap();
+        parse(path)
+            .unwrap()
+            .select(&root)
+            .into_iter()
+            .map(|node| match node {
+                Node::Element(e) => format!("<{}>", e.name),
+                Node::Value(value) => value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_select_elements_and_values() {
+        assert_eq!(
+            select(r#"//RuleGroup[@groupRelation="or"]/ProcessCreate/@onmatch"#),
+            vec!["include"]
+        );
+        assert_eq!(select("/Sysmon/HashAlgorithms/text()"), vec!["sha256"]);
+        assert_eq!(select("//Image[last()]/text()"), vec![r"\powershell.exe"]);
+        assert_eq!(
+            select(r#"//ProcessCreate[Image="\cmd.exe"]"#),
+            vec!["<ProcessCreate>"]
+        );
+        assert_eq!(select("//RuleGroup[2]/@name"), vec!["b"]);
+        assert_eq!(select("EventFiltering/*/@name"), vec!["a", "b"]);
+        assert_eq!(
+            select(r#"//*[contains(text(), "shell")]/@condition"#),
+            vec!["end with"]
+        );
+        assert!(select("/Bogus").is_empty());
+    }
+
+    #[test]
+    fn test_parse_rejects_unsupported_syntax() {
+        let error = |path| parse(path).unwrap_err().to_string();
+        assert!(error("//RuleGroup/..").contains("parent step"));
+        assert!(error("//ancestor::RuleGroup").contains("ancestor axis"));
+        assert!(error("//RuleGroup[@name=a]").contains("expected a quoted string"));
+        assert!(error("//RuleGroup[1").contains("column 14: expected ]"));
+    }
+}
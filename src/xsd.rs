@@ -0,0 +1,797 @@
+//! XML Schema validation for `validate --xsd`.
+//!
+//! Covers the part of XSD that describes config files: global and local
+//! element declarations, named and anonymous types, `sequence`, `choice`
+//! and `all` with occurrence bounds, attributes, simple-content and
+//! complex-content extensions, and restrictions with enumerations and
+//! patterns. Compositors are checked for which elements may appear and how
+//! often, not for their order, which Sysmon does not care about. Other
+//! constructs, such as unions and lists, accept any value.
+//!
+//! Microsoft does not publish an XSD for Sysmon configs, so `--xsd
+//! embedded` generates one per schema version from the event types, fields
+//! and conditions in `sysmon_cli::schema`. It checks the same facts as the
+//! structural validator, but by an independent route.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use sysmon_cli::model::Condition;
+use sysmon_cli::schema::{self, Version};
+use sysmon_cli::validation::Issue;
+use sysmon_json::error::ConversionError;
+use xmltree::{Element, XMLNode};
+
+/// Rule id reported by [`Schema::validate`], with its description.
+pub const RULES: &[(&str, &str)] = &[("xsd", "Config does not conform to the XML Schema")];
+
+/// Where `--xsd` gets its schema.
+pub enum Source {
+    /// Generated for each config's `schemaversion`.
+    Embedded,
+    File(Schema),
+}
+
+impl Source {
+    /// `embedded`, or the path of an XSD file.
+    pub fn load(spec: &str) -> Result<Source, ConversionError> {
+        if spec == "embedded" {
+            return Ok(Source::Embedded);
+        }
+        let path = Path::new(spec);
+        let text = std::fs::read_to_string(path).map_err(|e| ConversionError::io_error(path, e))?;
+        let schema = Schema::parse(&text)
+            .map_err(|e| ConversionError::InvalidFile(format!("{}: {}", path.display(), e)))?;
+        Ok(Source::File(schema))
+    }
+
+    pub fn issues(&self, text: &str) -> Vec<Issue> {
+        match self {
+            Source::File(schema) => schema.validate(text),
+            Source::Embedded => {
+                let version = Element::parse(text.as_bytes())
+                    .ok()
+                    .and_then(|root| root.attributes.get("schemaversion")?.parse().ok())
+                    .unwrap_or_else(latest);
+                Schema::parse(&embedded(version))
+                    .expect("the embedded schema parses")
+                    .validate(text)
+            }
+        }
+    }
+}
+
+fn latest() -> Version {
+    schema::RELEASES
+        .iter()
+        .map(|(_, schema)| *schema)
+        .max()
+        .unwrap_or(Version::new(4, 90))
+}
+
+#[derive(Debug, Clone)]
+enum TypeRef {
+    /// A named or built-in type, without its namespace prefix.
+    Named(String),
+    Inline(Box<Type>),
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Simple(Simple),
+    Complex(Complex),
+}
+
+#[derive(Debug, Clone)]
+struct Simple {
+    base: String,
+    values: Vec<String>,
+    /// Source text and the pattern anchored at both ends, as XSD implies.
+    patterns: Vec<(String, Regex)>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Complex {
+    /// Complex-content base whose children and attributes this type extends.
+    base: Option<String>,
+    /// Simple-content type of the element's text.
+    content: Option<String>,
+    mixed: bool,
+    children: Vec<Particle>,
+    any_children: bool,
+    attributes: Vec<Attribute>,
+    any_attribute: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Particle {
+    name: String,
+    /// `None` for a `ref` to a global element.
+    kind: Option<TypeRef>,
+    min: usize,
+    /// `None` when unbounded.
+    max: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Attribute {
+    name: String,
+    kind: TypeRef,
+    required: bool,
+}
+
+/// A parsed XSD.
+pub struct Schema {
+    elements: HashMap<String, TypeRef>,
+    types: HashMap<String, Type>,
+}
+
+impl Schema {
+    pub fn parse(text: &str) -> Result<Schema, String> {
+        let root = Element::parse(text.as_bytes()).map_err(|e| e.to_string())?;
+        if tag(&root) != "schema" {
+            return Err(format!("not an XML Schema: the root is <{}>", root.name));
+        }
+        let mut schema = Schema {
+            elements: HashMap::new(),
+            types: HashMap::new(),
+        };
+        for child in children(&root) {
+            let Some(name) = child.attributes.get("name") else {
+                continue;
+            };
+            match tag(child) {
+                "element" => {
+                    schema.elements.insert(name.clone(), element_type(child)?);
+                }
+                "complexType" | "simpleType" => {
+                    schema.types.insert(name.clone(), parse_type(child)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Issues for a config's XML text. Text that is not well-formed gives
+    /// none; the structural checks already report it.
+    pub fn validate(&self, text: &str) -> Vec<Issue> {
+        let Ok(root) = Element::parse(text.as_bytes()) else {
+            return Vec::new();
+        };
+        let mut issues = Vec::new();
+        match self.elements.get(&root.name) {
+            Some(kind) => self.check(&root, kind, &root.name, &mut issues),
+            None => issues.push(Issue::error(
+                "xsd",
+                &root.name,
+                format!("<{}> is not declared in the schema", root.name),
+            )),
+        }
+        issues
+    }
+
+    fn resolve<'a>(&'a self, kind: &'a TypeRef) -> Option<&'a Type> {
+        match kind {
+            TypeRef::Named(name) => self.types.get(name),
+            TypeRef::Inline(kind) => Some(kind),
+        }
+    }
+
+    fn check(&self, element: &Element, kind: &TypeRef, location: &str, issues: &mut Vec<Issue>) {
+        let error = |message: String| Issue::error("xsd", location, message);
+        match self.resolve(kind) {
+            Some(Type::Complex(complex)) => {
+                let complex = self.extended(complex, 0);
+                self.check_complex(element, &complex, location, issues);
+            }
+            _ if matches!(kind, TypeRef::Named(name) if name == "anyType") => {}
+            _ => {
+                if children(element).next().is_some() {
+                    issues.push(error(format!("<{}> may only contain text", element.name)));
+                }
+                if let Some(name) = element
+                    .attributes
+                    .keys()
+                    .find(|name| !is_schema_attribute(name))
+                {
+                    issues.push(error(format!(
+                        "attribute {} is not allowed on <{}>",
+                        name, element.name
+                    )));
+                }
+                if let Err(message) = self.check_value(kind, &text(element), 0) {
+                    issues.push(error(format!("<{}>: {}", element.name, message)));
+                }
+            }
+        }
+    }
+
+    fn check_complex(
+        &self,
+        element: &Element,
+        complex: &Complex,
+        location: &str,
+        issues: &mut Vec<Issue>,
+    ) {
+        let error = |message: String| Issue::error("xsd", location, message);
+        let name = &element.name;
+
+        for (attribute, value) in element.attributes.iter() {
+            if is_schema_attribute(attribute) {
+                continue;
+            }
+            match complex.attributes.iter().find(|a| a.name == *attribute) {
+                Some(declared) => {
+                    if let Err(message) = self.check_value(&declared.kind, value, 0) {
+                        issues.push(error(format!("attribute {}: {}", attribute, message)));
+                    }
+                }
+                None if complex.any_attribute => {}
+                None => issues.push(error(format!(
+                    "attribute {} is not allowed on <{}>",
+                    attribute, name
+                ))),
+            }
+        }
+        for declared in complex.attributes.iter().filter(|a| a.required) {
+            if !element.attributes.contains_key(&declared.name) {
+                issues.push(error(format!(
+                    "<{}> is missing required attribute {}",
+                    name, declared.name
+                )));
+            }
+        }
+
+        let mut totals: HashMap<&str, usize> = HashMap::new();
+        for child in children(element) {
+            *totals.entry(&child.name).or_default() += 1;
+        }
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        let mut counts = vec![0; complex.children.len()];
+        for child in children(element) {
+            let nth = seen.entry(&child.name).or_default();
+            *nth += 1;
+            let child_location = if totals[child.name.as_str()] > 1 {
+                format!("{}/{}[{}]", location, child.name, nth)
+            } else {
+                format!("{}/{}", location, child.name)
+            };
+            match complex.children.iter().position(|p| p.name == child.name) {
+                Some(i) => {
+                    counts[i] += 1;
+                    let any = TypeRef::Named("anyType".to_string());
+                    let kind = match &complex.children[i].kind {
+                        Some(kind) => kind,
+                        None => self.elements.get(&child.name).unwrap_or(&any),
+                    };
+                    self.check(child, kind, &child_location, issues);
+                }
+                None if complex.any_children => {}
+                None => issues.push(Issue::error(
+                    "xsd",
+                    child_location,
+                    format!("<{}> is not allowed in <{}>", child.name, name),
+                )),
+            }
+        }
+        for (particle, count) in complex.children.iter().zip(counts) {
+            if count < particle.min {
+                issues.push(error(format!(
+                    "<{}> requires at least {} <{}>",
+                    name, particle.min, particle.name
+                )));
+            }
+            if let Some(max) = particle.max.filter(|max| count > *max) {
+                issues.push(error(format!(
+                    "<{}> allows at most {} <{}>, found {}",
+                    name, max, particle.name, count
+                )));
+            }
+        }
+
+        let text = text(element);
+        if text.trim().is_empty() {
+            return;
+        }
+        match &complex.content {
+            Some(content) => {
+                let kind = TypeRef::Named(content.clone());
+                if let Err(message) = self.check_value(&kind, &text, 0) {
+                    issues.push(error(format!("<{}>: {}", name, message)));
+                }
+            }
+            None if complex.mixed => {}
+            None => issues.push(error(format!("<{}> may not contain text", name))),
+        }
+    }
+
+    /// `complex` with the children and attributes of its complex-content
+    /// base chain added.
+    fn extended(&self, complex: &Complex, depth: usize) -> Complex {
+        let mut complex = complex.clone();
+        let base = match complex.base.as_ref().map(|b| self.types.get(b)) {
+            Some(Some(Type::Complex(base))) if depth < 16 => self.extended(base, depth + 1),
+            _ => return complex,
+        };
+        let mut children = base.children;
+        children.append(&mut complex.children);
+        complex.children = children;
+        complex.attributes.extend(base.attributes);
+        complex.any_children |= base.any_children;
+        complex.any_attribute |= base.any_attribute;
+        complex.content = complex.content.or(base.content);
+        complex
+    }
+
+    fn check_value(&self, kind: &TypeRef, value: &str, depth: usize) -> Result<(), String> {
+        match self.resolve(kind) {
+            Some(Type::Simple(simple)) if depth < 16 => {
+                self.check_value(&TypeRef::Named(simple.base.clone()), value, depth + 1)?;
+                if !simple.values.is_empty() && !simple.values.iter().any(|v| v == value) {
+                    return Err(format!(
+                        "{:?} is not one of {}",
+                        value,
+                        simple.values.join(", ")
+                    ));
+                }
+                match simple
+                    .patterns
+                    .iter()
+                    .find(|(_, regex)| !regex.is_match(value))
+                {
+                    Some((pattern, _)) => Err(format!(
+                        "{:?} does not match the pattern {}",
+                        value, pattern
+                    )),
+                    None => Ok(()),
+                }
+            }
+            Some(_) => Ok(()),
+            None => match kind {
+                TypeRef::Named(name) => builtin(name, value),
+                TypeRef::Inline(_) => Ok(()),
+            },
+        }
+    }
+}
+
+/// Checks the lexical form of the built-in types a config is likely to
+/// use; the rest accept anything.
+fn builtin(name: &str, value: &str) -> Result<(), String> {
+    let trimmed = value.trim();
+    let valid = match name {
+        "boolean" => matches!(trimmed, "true" | "false" | "1" | "0"),
+        "integer" | "int" | "long" | "short" | "byte" => trimmed.parse::<i64>().is_ok(),
+        "nonNegativeInteger" | "unsignedLong" | "unsignedInt" | "unsignedShort"
+        | "unsignedByte" => trimmed.parse::<u64>().is_ok(),
+        "positiveInteger" => trimmed.parse::<u64>().is_ok_and(|n| n > 0),
+        "decimal" | "double" | "float" => trimmed.parse::<f64>().is_ok(),
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("{:?} is not a valid {}", value, name))
+    }
+}
+
+/// Namespace declarations and `xsi:` hints, which no schema declares.
+fn is_schema_attribute(name: &str) -> bool {
+    let local = name.rsplit(':').next().unwrap_or(name);
+    name.starts_with("xmlns") || local == "schemaLocation" || local == "noNamespaceSchemaLocation"
+}
+
+fn children(element: &Element) -> impl Iterator<Item = &Element> {
+    element.children.iter().filter_map(XMLNode::as_element)
+}
+
+fn text(element: &Element) -> String {
+    element
+        .get_text()
+        .map(|text| text.into_owned())
+        .unwrap_or_default()
+}
+
+/// An XSD element's name without its namespace prefix.
+fn tag(element: &Element) -> &str {
+    element.name.rsplit(':').next().unwrap_or(&element.name)
+}
+
+fn local(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_string()
+}
+
+fn element_type(element: &Element) -> Result<TypeRef, String> {
+    if let Some(kind) = element.attributes.get("type") {
+        return Ok(TypeRef::Named(local(kind)));
+    }
+    match children(element).find(|c| tag(c) == "complexType" || tag(c) == "simpleType") {
+        Some(inline) => Ok(TypeRef::Inline(Box::new(parse_type(inline)?))),
+        None => Ok(TypeRef::Named("anyType".to_string())),
+    }
+}
+
+fn parse_type(element: &Element) -> Result<Type, String> {
+    if tag(element) == "simpleType" {
+        let mut simple = Simple {
+            base: "string".to_string(),
+            values: Vec::new(),
+            patterns: Vec::new(),
+        };
+        if let Some(restriction) = children(element).find(|c| tag(c) == "restriction") {
+            if let Some(base) = restriction.attributes.get("base") {
+                simple.base = local(base);
+            }
+            for facet in children(restriction) {
+                let Some(value) = facet.attributes.get("value") else {
+                    continue;
+                };
+                match tag(facet) {
+                    "enumeration" => simple.values.push(value.clone()),
+                    "pattern" => {
+                        let regex = Regex::new(&format!("^(?:{})$", value))
+                            .map_err(|e| format!("pattern {}: {}", value, e))?;
+                        simple.patterns.push((value.clone(), regex));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        return Ok(Type::Simple(simple));
+    }
+
+    let mut complex = Complex {
+        mixed: element.attributes.get("mixed").is_some_and(|m| m == "true"),
+        ..Complex::default()
+    };
+    parse_content(element, &mut complex)?;
+    Ok(Type::Complex(complex))
+}
+
+fn parse_content(element: &Element, complex: &mut Complex) -> Result<(), String> {
+    for child in children(element) {
+        match tag(child) {
+            "sequence" | "choice" | "all" => particles(child, complex, false, Some(1))?,
+            "attribute" => {
+                let Some(name) = child.attributes.get("name").or(child.attributes.get("ref"))
+                else {
+                    continue;
+                };
+                complex.attributes.push(Attribute {
+                    name: local(name),
+                    kind: element_type(child)?,
+                    required: child.attributes.get("use").is_some_and(|u| u == "required"),
+                });
+            }
+            "anyAttribute" => complex.any_attribute = true,
+            "simpleContent" | "complexContent" => {
+                for derivation in children(child) {
+                    let base = derivation.attributes.get("base").map(|b| local(b));
+                    if tag(child) == "simpleContent" {
+                        complex.content = base;
+                    } else {
+                        complex.base = base;
+                    }
+                    parse_content(derivation, complex)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Flattens a compositor into the elements it allows. Elements under a
+/// `choice` or an optional compositor become optional, and repeating
+/// compositors multiply their elements' upper bounds.
+fn particles(
+    compositor: &Element,
+    complex: &mut Complex,
+    optional: bool,
+    repeat: Option<usize>,
+) -> Result<(), String> {
+    let (min, max) = occurs(compositor)?;
+    let optional = optional || min == 0 || tag(compositor) == "choice";
+    let repeat = repeat.zip(max).map(|(a, b)| a * b);
+    for child in children(compositor) {
+        match tag(child) {
+            "element" => {
+                let (min, max) = occurs(child)?;
+                let (name, kind) = match child.attributes.get("ref") {
+                    Some(reference) => (local(reference), None),
+                    None => match child.attributes.get("name") {
+                        Some(name) => (name.clone(), Some(element_type(child)?)),
+                        None => continue,
+                    },
+                };
+                complex.children.push(Particle {
+                    name,
+                    kind,
+                    min: if optional { 0 } else { min },
+                    max: repeat.zip(max).map(|(a, b)| a * b),
+                });
+            }
+            "sequence" | "choice" | "all" => particles(child, complex, optional, repeat)?,
+            "any" => complex.any_children = true,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `minOccurs` and `maxOccurs`, with `None` for unbounded.
+fn occurs(element: &Element) -> Result<(usize, Option<usize>), String> {
+    let number = |name: &str, value: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| format!("<{}>: bad {} {:?}", element.name, name, value))
+    };
+    let min = match element.attributes.get("minOccurs") {
+        Some(value) => number("minOccurs", value)?,
+        None => 1,
+    };
+    let max = match element.attributes.get("maxOccurs").map(String::as_str) {
+        Some("unbounded") => None,
+        Some(value) => Some(number("maxOccurs", value)?),
+        None => Some(1),
+    };
+    Ok((min, max))
+}
+
+/// The XSD for configs declaring schema `version`: the event types and
+/// conditions that schema accepts, with every field an event type has in
+/// the newest schema.
+pub fn embedded(version: Version) -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!-- Sysmon configuration schema {}, generated by sysmon_cli -->\n\
+         <xs:schema xmlns:xs=\"http://www.w3.org/2001/XMLSchema\">\n",
+        version
+    );
+    let enumeration = |out: &mut String, name: &str, values: &[&str]| {
+        let _ = writeln!(
+            out,
+            "  <xs:simpleType name=\"{}\">\n    <xs:restriction base=\"xs:string\">",
+            name
+        );
+        for value in values {
+            let _ = writeln!(out, "      <xs:enumeration value=\"{}\"/>", value);
+        }
+        out.push_str("    </xs:restriction>\n  </xs:simpleType>\n");
+    };
+    enumeration(&mut out, "OnMatch", &["include", "exclude"]);
+    enumeration(&mut out, "GroupRelation", &["and", "or"]);
+    // A flag such as `<CheckRevocation/>`, optionally set to true or false.
+    out.push_str(
+        "  <xs:simpleType name=\"Flag\">\n    <xs:restriction base=\"xs:string\">\n      \
+         <xs:pattern value=\"(true|false)?\"/>\n    </xs:restriction>\n  </xs:simpleType>\n",
+    );
+    let conditions: Vec<&str> = Condition::ALL
+        .into_iter()
+        .filter(|c| schema::condition_min_schema(*c) <= version)
+        .map(Condition::as_str)
+        .collect();
+    enumeration(&mut out, "Condition", &conditions);
+    out.push_str(
+        "  <xs:complexType name=\"Field\">\n    <xs:simpleContent>\n      \
+         <xs:extension base=\"xs:string\">\n        \
+         <xs:attribute name=\"name\" type=\"xs:string\"/>\n        \
+         <xs:attribute name=\"condition\" type=\"Condition\"/>\n      \
+         </xs:extension>\n    </xs:simpleContent>\n  </xs:complexType>\n",
+    );
+
+    let events: Vec<_> = schema::EVENT_TYPES
+        .iter()
+        .filter(|event| event.min_schema <= version)
+        .collect();
+    let choice = |out: &mut String, elements: &[(String, String)]| {
+        out.push_str("    <xs:choice minOccurs=\"0\" maxOccurs=\"unbounded\">\n");
+        for (name, kind) in elements {
+            let _ = writeln!(
+                out,
+                "      <xs:element name=\"{}\" type=\"{}\"/>",
+                name, kind
+            );
+        }
+        out.push_str("    </xs:choice>\n");
+    };
+    for event in &events {
+        let fields: Vec<(String, String)> = event
+            .fields
+            .iter()
+            .map(|field| (field.to_string(), "Field".to_string()))
+            .collect();
+        let _ = writeln!(out, "  <xs:complexType name=\"{}Rule\">", event.name);
+        choice(&mut out, &fields);
+        out.push_str(
+            "    <xs:attribute name=\"name\" type=\"xs:string\"/>\n    \
+             <xs:attribute name=\"groupRelation\" type=\"GroupRelation\"/>\n  \
+             </xs:complexType>\n",
+        );
+        let mut elements = fields;
+        elements.push(("Rule".to_string(), format!("{}Rule", event.name)));
+        let _ = writeln!(out, "  <xs:complexType name=\"{}\">", event.name);
+        choice(&mut out, &elements);
+        out.push_str(
+            "    <xs:attribute name=\"onmatch\" type=\"OnMatch\" use=\"required\"/>\n  \
+             </xs:complexType>\n",
+        );
+    }
+
+    let mut filters: Vec<(String, String)> = events
+        .iter()
+        .map(|event| (event.name.to_string(), event.name.to_string()))
+        .collect();
+    out.push_str("  <xs:complexType name=\"RuleGroup\">\n");
+    choice(&mut out, &filters);
+    out.push_str(
+        "    <xs:attribute name=\"name\" type=\"xs:string\"/>\n    \
+         <xs:attribute name=\"groupRelation\" type=\"GroupRelation\"/>\n  \
+         </xs:complexType>\n",
+    );
+    filters.push(("RuleGroup".to_string(), "RuleGroup".to_string()));
+    out.push_str("  <xs:complexType name=\"EventFiltering\">\n");
+    choice(&mut out, &filters);
+    out.push_str("  </xs:complexType>\n");
+
+    out.push_str("  <xs:element name=\"Sysmon\">\n    <xs:complexType>\n      <xs:all>\n");
+    for setting in schema::SETTINGS {
+        let kind = match *setting {
+            "DnsLookup" => "xs:boolean",
+            "CheckRevocation" => "Flag",
+            _ => "xs:string",
+        };
+        let _ = writeln!(
+            out,
+            "        <xs:element name=\"{}\" type=\"{}\" minOccurs=\"0\"/>",
+            setting, kind
+        );
+    }
+    out.push_str(
+        "        <xs:element name=\"EventFiltering\" type=\"EventFiltering\" minOccurs=\"0\"/>\n      \
+         </xs:all>\n      \
+         <xs:attribute name=\"schemaversion\" type=\"xs:decimal\" use=\"required\"/>\n    \
+         </xs:complexType>\n  </xs:element>\n</xs:schema>\n",
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(issues: Vec<Issue>) -> Vec<(String, String)> {
+        issues
+            .into_iter()
+            .map(|issue| (issue.location, issue.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_embedded_schema_accepts_valid_configs() {
+        let text = r#"<Sysmon schemaversion="4.90">
+            <HashAlgorithms>sha256</HashAlgorithms>
+            <DnsLookup>false</DnsLookup>
+            <CheckRevocation/>
+            <EventFiltering>
+            <RuleGroup name="" groupRelation="or">
+            <ProcessCreate onmatch="include">
+            <Image name="technique_id=T1059.001" condition="end with">\powershell.exe</Image>
+            <Rule groupRelation="and">
+            <Image condition="end with">\rundll32.exe</Image>
+            <CommandLine condition="contains any">javascript:;vbscript:</CommandLine>
+            </Rule>
+            </ProcessCreate>
+            </RuleGroup>
+            <DnsQuery onmatch="exclude"><QueryName condition="not end with">.local</QueryName></DnsQuery>
+            </EventFiltering>
+            </Sysmon>"#;
+        assert!(messages(Source::Embedded.issues(text)).is_empty());
+    }
+
+    #[test]
+    fn test_embedded_schema_follows_schema_version() {
+        let text = r#"<Sysmon schemaversion="4.22">
+            <DnsLookup>no</DnsLookup>
+            <CheckRevocation>yes</CheckRevocation>
+            <EventFiltering>
+            <ProcessCreate onmatch="inclde">
+            <Image condition="not end with">\a.exe</Image>
+            <Imagee condition="is">b.exe</Imagee>
+            </ProcessCreate>
+            <FileDelete onmatch="include"/>
+            </EventFiltering>
+            </Sysmon>"#;
+        assert_eq!(
+            messages(Source::Embedded.issues(text)),
+            vec![
+                (
+                    "Sysmon/DnsLookup".into(),
+                    "<DnsLookup>: \"no\" is not a valid boolean".into()
+                ),
+                (
+                    "Sysmon/CheckRevocation".into(),
+                    "<CheckRevocation>: \"yes\" does not match the pattern (true|false)?".into()
+                ),
+                (
+                    "Sysmon/EventFiltering/ProcessCreate".into(),
+                    "attribute onmatch: \"inclde\" is not one of include, exclude".into()
+                ),
+                (
+                    "Sysmon/EventFiltering/ProcessCreate/Image".into(),
+                    "attribute condition: \"not end with\" is not one of is, is not, is any, \
+                     contains, contains any, contains all, excludes, excludes any, excludes all, \
+                     begin with, end with, less than, more than, image"
+                        .into()
+                ),
+                (
+                    "Sysmon/EventFiltering/ProcessCreate/Imagee".into(),
+                    "<Imagee> is not allowed in <ProcessCreate>".into()
+                ),
+                (
+                    "Sysmon/EventFiltering/FileDelete".into(),
+                    "<FileDelete> is not allowed in <EventFiltering>".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_schema_file_occurrences_and_attributes() {
+        let schema = Schema::parse(
+            r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+            <xs:element name="Sysmon">
+              <xs:complexType>
+                <xs:sequence>
+                  <xs:element ref="HashAlgorithms"/>
+                  <xs:element name="EventFiltering" minOccurs="0" maxOccurs="1">
+                    <xs:complexType><xs:sequence><xs:any/></xs:sequence></xs:complexType>
+                  </xs:element>
+                </xs:sequence>
+                <xs:attribute name="schemaversion" use="required">
+                  <xs:simpleType>
+                    <xs:restriction base="xs:string">
+                      <xs:enumeration value="4.90"/>
+                    </xs:restriction>
+                  </xs:simpleType>
+                </xs:attribute>
+              </xs:complexType>
+            </xs:element>
+            <xs:element name="HashAlgorithms" type="xs:string"/>
+            </xs:schema>"#,
+        )
+        .unwrap();
+
+        assert!(schema
+            .validate(r#"<Sysmon schemaversion="4.90"><HashAlgorithms>md5</HashAlgorithms><EventFiltering><Anything/></EventFiltering></Sysmon>"#)
+            .is_empty());
+        assert_eq!(
+            messages(
+                schema.validate(
+                    r#"<Sysmon version="4.90"><EventFiltering/><EventFiltering/></Sysmon>"#
+                )
+            ),
+            vec![
+                (
+                    "Sysmon".into(),
+                    "attribute version is not allowed on <Sysmon>".into()
+                ),
+                (
+                    "Sysmon".into(),
+                    "<Sysmon> is missing required attribute schemaversion".into()
+                ),
+                (
+                    "Sysmon".into(),
+                    "<Sysmon> requires at least 1 <HashAlgorithms>".into()
+                ),
+                (
+                    "Sysmon".into(),
+                    "<Sysmon> allows at most 1 <EventFiltering>, found 2".into()
+                ),
+            ]
+        );
+        assert!(Schema::parse("<Sysmon/>").is_err());
+    }
+}
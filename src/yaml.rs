@@ -0,0 +1,281 @@
+//! The subset of YAML this tool reads, without a YAML library: block
+//! mappings and sequences, one-line flow sequences (`[a, b]`), plain and
+//! quoted scalars, `|` and `>` block scalars, and comments. A file using
+//! anchors, tags or flow mappings is reported as unreadable.
+
+/// A YAML value. Numbers and booleans are kept as the text they were
+/// written as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Yaml {
+    Null,
+    Scalar(String),
+    List(Vec<Yaml>),
+    Map(Vec<(String, Yaml)>),
+}
+
+impl Yaml {
+    pub fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Yaml::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a file holding one YAML document.
+pub fn parse(text: &str) -> Result<Yaml, String> {
+    let lines: Vec<&str> = text.lines().skip_while(|l| l.trim_end() == "---").collect();
+    let first_line = text.lines().count() - lines.len() + 1;
+    parse_document(&lines, first_line)
+}
+
+/// A line of a YAML document without its indentation and comment.
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+struct Parser<'a> {
+    lines: Vec<Line<'a>>,
+    pos: usize,
+}
+
+/// Parses one YAML document, whose first line is `first_line` of its file.
+pub fn parse_document(document: &[&str], first_line: usize) -> Result<Yaml, String> {
+    let lines = document
+        .iter()
+        .enumerate()
+        .filter_map(|(i, raw)| {
+            let text = strip_comment(raw).trim_end();
+            let content = text.trim_start();
+            (!content.is_empty()).then(|| Line {
+                number: first_line + i,
+                indent: text.len() - content.len(),
+                text: content,
+            })
+        })
+        .collect();
+    let mut parser = Parser { lines, pos: 0 };
+    let Some(first) = parser.lines.first() else {
+        return Ok(Yaml::Null);
+    };
+    let yaml = parser.block(first.indent)?;
+    match parser.lines.get(parser.pos) {
+        Some(line) => Err(format!("line {}: unexpected indentation", line.number)),
+        None => Ok(yaml),
+    }
+}
+
+/// `line` up to a `#` comment outside quotes.
+fn strip_comment(line: &str) -> &str {
+    let (mut single, mut double, mut previous) = (false, false, ' ');
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' if !double => single = !single,
+            '"' if !single && previous != '\\' => double = !double,
+            '#' if !single && !double && previous.is_whitespace() => return &line[..i],
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// The key and the rest of a `key: value` line.
+fn split_key(text: &str) -> Option<(String, &str)> {
+    for quote in ['\'', '"'] {
+        if let Some(rest) = text.strip_prefix(quote) {
+            let end = rest.find(quote)?;
+            let after = rest[end + 1..].strip_prefix(':')?;
+            return Some((rest[..end].to_string(), after.trim()));
+        }
+    }
+    let colon = text
+        .match_indices(':')
+        .map(|(i, _)| i)
+        .find(|&i| text[i + 1..].is_empty() || text[i + 1..].starts_with(' '))?;
+    Some((text[..colon].trim().to_string(), text[colon + 1..].trim()))
+}
+
+impl Parser<'_> {
+    fn block(&mut self, indent: usize) -> Result<Yaml, String> {
+        if is_item(self.lines[self.pos].text) {
+            self.sequence(indent)
+        } else {
+            self.mapping(indent)
+        }
+    }
+
+    /// The value after `key:` or `-` on the current line, which is
+    /// `rest`, with the block under it when `rest` is empty.
+    fn value(&mut self, indent: usize, rest: &str) -> Result<Yaml, String> {
+        let number = self.lines[self.pos].number;
+        self.pos += 1;
+        if rest.is_empty() {
+            return match self.lines.get(self.pos) {
+                Some(next) if next.indent > indent => self.block(next.indent),
+                Some(next) if next.indent == indent && is_item(next.text) => self.sequence(indent),
+                _ => Ok(Yaml::Null),
+            };
+        }
+        if rest.starts_with('|') || rest.starts_with('>') {
+            let separator = if rest.starts_with('|') { "\n" } else { " " };
+            let mut lines = Vec::new();
+            while let Some(line) = self.lines.get(self.pos).filter(|l| l.indent > indent) {
+                lines.push(line.text);
+                self.pos += 1;
+            }
+            return Ok(Yaml::Scalar(lines.join(separator)));
+        }
+        let mut value = flow(rest).map_err(|e| format!("line {}: {}", number, e))?;
+        // A plain scalar continued on more indented lines.
+        while let Some(line) = self.lines.get(self.pos).filter(|l| l.indent > indent) {
+            match &mut value {
+                Yaml::Scalar(s) => {
+                    s.push(' ');
+                    s.push_str(line.text);
+                }
+                _ => return Err(format!("line {}: unexpected indentation", line.number)),
+            }
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Yaml, String> {
+        let mut entries = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.indent != indent || is_item(line.text) {
+                break;
+            }
+            let Some((key, rest)) = split_key(line.text) else {
+                return Err(format!("line {}: expected `key: value`", line.number));
+            };
+            let value = self.value(indent, rest)?;
+            entries.push((key, value));
+        }
+        Ok(Yaml::Map(entries))
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Yaml, String> {
+        let mut items = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.indent != indent || !is_item(line.text) {
+                break;
+            }
+            let rest = line.text[1..].trim_start();
+            if !rest.starts_with('[') && split_key(rest).is_some() {
+                // `- key: value` starts a mapping indented to `key`.
+                let item_indent = indent + line.text.len() - rest.len();
+                self.lines[self.pos] = Line {
+                    number: line.number,
+                    indent: item_indent,
+                    text: rest,
+                };
+                items.push(self.mapping(item_indent)?);
+            } else {
+                items.push(self.value(indent, rest)?);
+            }
+        }
+        Ok(Yaml::List(items))
+    }
+}
+
+/// A value written on one line: a flow sequence or a scalar.
+fn flow(text: &str) -> Result<Yaml, String> {
+    if let Some(inner) = text.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or("flow sequences must close on the same line")?;
+        let mut items = Vec::new();
+        let (mut start, mut single, mut double) = (0, false, false);
+        for (i, c) in inner.char_indices().chain([(inner.len(), ',')]) {
+            match c {
+                '\'' if !double => single = !single,
+                '"' if !single => double = !double,
+                ',' if !single && !double => {
+                    let item = inner[start..i.min(inner.len())].trim();
+                    if !item.is_empty() {
+                        items.push(scalar(item)?);
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        return Ok(Yaml::List(items));
+    }
+    scalar(text)
+}
+
+fn scalar(text: &str) -> Result<Yaml, String> {
+    if let Some(inner) = text.strip_prefix('\'') {
+        let inner = inner
+            .strip_suffix('\'')
+            .ok_or("unterminated quoted string")?;
+        return Ok(Yaml::Scalar(inner.replace("''", "'")));
+    }
+    if let Some(inner) = text.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or("unterminated quoted string")?;
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            out.push(match (c, c == '\\') {
+                (_, true) => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(other) => other,
+                    None => '\\',
+                },
+                (c, false) => c,
+            });
+        }
+        return Ok(Yaml::Scalar(out));
+    }
+    if text.starts_with(['{', '&', '*', '!']) {
+        return Err(format!(
+            "flow mappings, anchors and tags are not supported: `{}`",
+            text
+        ));
+    }
+    Ok(match text {
+        "null" | "~" => Yaml::Null,
+        _ => Yaml::Scalar(text.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reads_nested_blocks() {
+        let yaml = parse("---\nname: 'a: b' # note\nitems:\n  - x\n  - k: [1, \"2\"]\n").unwrap();
+        assert_eq!(yaml.get("name").and_then(Yaml::as_str), Some("a: b"));
+        assert_eq!(
+            yaml.get("items"),
+            Some(&Yaml::List(vec![
+                Yaml::Scalar("x".into()),
+                Yaml::Map(vec![(
+                    "k".into(),
+                    Yaml::List(vec![Yaml::Scalar("1".into()), Yaml::Scalar("2".into())])
+                )]),
+            ]))
+        );
+        assert!(parse("a: {b: 1}\n").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod event_types_tests {
+    use std::path::PathBuf;
+    use sysmon_cli::model::{Filter, SysmonConfig};
+    use sysmon_cli::schema;
+    use sysmon_cli::validation::validate;
+    use sysmon_json::convert_file;
+    use tempfile::tempdir;
+
+    /// One fixture per newer event type, named `<event id>_<event>.xml`.
+    const FIXTURES: &[(&str, &str)] = &[
+        ("22_dns_query.xml", "DnsQuery"),
+        ("23_file_delete.xml", "FileDelete"),
+        ("24_clipboard_change.xml", "ClipboardChange"),
+        ("25_process_tampering.xml", "ProcessTampering"),
+        ("26_file_delete_detected.xml", "FileDeleteDetected"),
+        ("27_file_block_executable.xml", "FileBlockExecutable"),
+        ("28_file_block_shredding.xml", "FileBlockShredding"),
+        ("29_file_executable_detected.xml", "FileExecutableDetected"),
+    ];
+
+    fn get_fixture_path() -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests");
+        path.push("fixtures");
+        path.push("events");
+        path
+    }
+
+    fn load(path: &PathBuf) -> SysmonConfig {
+        let xml = std::fs::read_to_string(path).unwrap();
+        SysmonConfig::from_xml_str(&xml).unwrap()
+    }
+
+    fn fields(config: &SysmonConfig) -> Vec<String> {
+        config
+            .events()
+            .flat_map(|event| &event.filters)
+            .flat_map(|filter| match filter {
+                Filter::Field(field) => vec![field.field.clone()],
+                Filter::Rule(rule) => rule.fields.iter().map(|f| f.field.clone()).collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fixtures_use_event_specific_fields() {
+        for (file, event) in FIXTURES {
+            let config = load(&get_fixture_path().join(file));
+            let kind = schema::event_type(event).unwrap();
+            let id: u32 = file.split('_').next().unwrap().parse().unwrap();
+            assert!(kind.ids.contains(&id), "{} is not event {}", event, id);
+
+            let events: Vec<&str> = config.events().map(|e| e.event.as_str()).collect();
+            assert_eq!(events, vec![*event]);
+            for field in fields(&config) {
+                assert!(kind.has_field(&field), "{} has no field {}", event, field);
+            }
+
+            let issues = validate(&config);
+            assert!(issues.is_empty(), "{}: {:?}", file, issues);
+        }
+    }
+
+    #[test]
+    fn test_fixtures_round_trip_through_json() {
+        let temp_dir = tempdir().unwrap();
+        for (file, _) in FIXTURES {
+            let xml_path = get_fixture_path().join(file);
+            let json_path = temp_dir.path().join(file).with_extension("json");
+            let back_path = temp_dir.path().join(file);
+
+            convert_file(&xml_path, &json_path).unwrap();
+            convert_file(&json_path, &back_path).unwrap();
+            assert_eq!(load(&back_path), load(&xml_path), "{} changed", file);
+        }
+    }
+}